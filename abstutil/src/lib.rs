@@ -174,6 +174,13 @@ pub fn path_all_saves(map_name: &str, edits_name: &str, run_name: &str) -> Strin
     ))
 }
 
+pub fn path_replay(map_name: &str, edits_name: &str, run_name: &str) -> String {
+    path(format!(
+        "player/replays/{}/{}_{}.bin",
+        map_name, edits_name, run_name
+    ))
+}
+
 // Input data (For developers to build maps, not needed at runtime)
 
 pub fn path_popdat() -> String {
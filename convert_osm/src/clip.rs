@@ -4,7 +4,6 @@ use map_model::raw::{OriginalRoad, RawMap};
 use map_model::{osm, IntersectionType};
 use std::collections::BTreeMap;
 
-// TODO This needs to update turn restrictions too
 pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
     timer.start("clipping map to boundary");
 
@@ -31,6 +30,9 @@ pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
     // When we split an intersection out of bounds into two, one of them gets a new ID. Remember
     // that here.
     let mut extra_borders: BTreeMap<osm::NodeID, osm::NodeID> = BTreeMap::new();
+    // Trimming a road changes its ID. Remember the old to new ID, so turn restrictions can be
+    // fixed later.
+    let mut renamed: BTreeMap<OriginalRoad, OriginalRoad> = BTreeMap::new();
 
     // First pass: Clip roads beginning out of bounds
     let road_ids: Vec<OriginalRoad> = map.roads.keys().cloned().collect();
@@ -75,14 +77,15 @@ pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
             panic!("{} interacts with border strangely", id);
         }
         i.point = mut_r.center_points[0];
-        map.roads.insert(
-            OriginalRoad {
-                osm_way_id: id.osm_way_id,
-                i1: move_i,
-                i2: id.i2,
-            },
-            mut_r,
-        );
+        let new_id = OriginalRoad {
+            osm_way_id: id.osm_way_id,
+            i1: move_i,
+            i2: id.i2,
+        };
+        if new_id != id {
+            renamed.insert(id, new_id);
+        }
+        map.roads.insert(new_id, mut_r);
     }
 
     // Second pass: clip roads ending out of bounds
@@ -130,16 +133,19 @@ pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
             panic!("{} interacts with border strangely", id);
         }
         i.point = *mut_r.center_points.last().unwrap();
-        map.roads.insert(
-            OriginalRoad {
-                osm_way_id: id.osm_way_id,
-                i1: id.i1,
-                i2: move_i,
-            },
-            mut_r,
-        );
+        let new_id = OriginalRoad {
+            osm_way_id: id.osm_way_id,
+            i1: id.i1,
+            i2: move_i,
+        };
+        if new_id != id {
+            renamed.insert(id, new_id);
+        }
+        map.roads.insert(new_id, mut_r);
     }
 
+    fix_turn_restrictions(map, &renamed, timer);
+
     retain_btreemap(&mut map.buildings, |_, b| {
         b.polygon
             .points()
@@ -234,3 +240,143 @@ pub fn clip_map(map: &mut RawMap, timer: &mut Timer) {
 
     timer.stop("clipping map to boundary");
 }
+
+// Clipping deletes some roads and renames others. Rewrite turn restrictions to use the new IDs, and
+// drop the ones that reference deleted roads or roads that are no longer connected.
+fn fix_turn_restrictions(
+    map: &mut RawMap,
+    renamed: &BTreeMap<OriginalRoad, OriginalRoad>,
+    timer: &mut Timer,
+) {
+    let lookup = |mut id: OriginalRoad| -> Option<OriginalRoad> {
+        // A road clipped at both ends is renamed twice
+        while let Some(new_id) = renamed.get(&id) {
+            id = *new_id;
+        }
+        if map.roads.contains_key(&id) {
+            Some(id)
+        } else {
+            None
+        }
+    };
+    let connected = |r1: &OriginalRoad, r2: &OriginalRoad| {
+        r1.i1 == r2.i1 || r1.i1 == r2.i2 || r1.i2 == r2.i1 || r1.i2 == r2.i2
+    };
+
+    let mut lost = Vec::new();
+    let mut fixed = Vec::new();
+    for (from, r) in &map.roads {
+        let mut simple = Vec::new();
+        for (rt, to) in &r.turn_restrictions {
+            match lookup(*to) {
+                Some(to) if connected(from, &to) => {
+                    simple.push((*rt, to));
+                }
+                _ => {
+                    lost.push(format!("{:?} from {} to {}", rt, from, to));
+                }
+            }
+        }
+
+        let mut complicated = Vec::new();
+        for (via, to) in &r.complicated_turn_restrictions {
+            let mut chain = vec![*from];
+            for id in via.iter().chain(std::iter::once(to)) {
+                if let Some(id) = lookup(*id) {
+                    chain.push(id);
+                } else {
+                    break;
+                }
+            }
+            if chain.len() == via.len() + 2
+                && chain.windows(2).all(|pair| connected(&pair[0], &pair[1]))
+            {
+                let to = chain.pop().unwrap();
+                complicated.push((chain.split_off(1), to));
+            } else {
+                lost.push(format!("BanTurns from {} to {} via {:?}", from, to, via));
+            }
+        }
+
+        fixed.push((*from, simple, complicated));
+    }
+    for (id, simple, complicated) in fixed {
+        let r = map.roads.get_mut(&id).unwrap();
+        r.turn_restrictions = simple;
+        r.complicated_turn_restrictions = complicated;
+    }
+
+    if !lost.is_empty() {
+        timer.warn(format!(
+            "Clipping lost {} turn restrictions from roads that remain in the map:",
+            lost.len()
+        ));
+        for x in lost {
+            timer.warn(format!("  {}", x));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geom::Pt2D;
+    use map_model::raw::RestrictionType;
+
+    #[test]
+    fn test_fix_turn_restrictions() {
+        // Cut the bottom two intersections of the right column out of a 3x3 grid. The road
+        // between them leaves the map completely, and the road leading down into them is trimmed
+        // and renamed, because the intersection it ended at still has another road.
+        let mut map = RawMap::synthetic_grid(3, 3);
+        map.boundary_polygon = Ring::must_new(vec![
+            Pt2D::new(0.0, 0.0),
+            Pt2D::new(600.0, 0.0),
+            Pt2D::new(600.0, 200.0),
+            Pt2D::new(400.0, 200.0),
+            Pt2D::new(400.0, 600.0),
+            Pt2D::new(0.0, 600.0),
+            Pt2D::new(0.0, 0.0),
+        ])
+        .to_polygon();
+
+        let trimmed = OriginalRoad::new(5, (3, 6));
+        let gone = OriginalRoad::new(10, (6, 9));
+        map.roads
+            .get_mut(&OriginalRoad::new(3, (2, 3)))
+            .unwrap()
+            .turn_restrictions
+            .push((RestrictionType::BanTurns, trimmed));
+        map.roads
+            .get_mut(&OriginalRoad::new(1, (1, 2)))
+            .unwrap()
+            .complicated_turn_restrictions
+            .push((vec![OriginalRoad::new(3, (2, 3))], trimmed));
+        map.roads
+            .get_mut(&OriginalRoad::new(8, (5, 6)))
+            .unwrap()
+            .complicated_turn_restrictions
+            .push((vec![gone], OriginalRoad::new(12, (8, 9))));
+
+        clip_map(&mut map, &mut Timer::throwaway());
+
+        assert!(!map.roads.contains_key(&gone));
+        assert!(!map.roads.contains_key(&trimmed));
+        let renamed = OriginalRoad::new(5, (3, -1));
+        assert!(map.roads.contains_key(&renamed));
+
+        // Restrictions involving the trimmed road just follow the new name
+        assert_eq!(
+            map.roads[&OriginalRoad::new(3, (2, 3))].turn_restrictions,
+            vec![(RestrictionType::BanTurns, renamed)]
+        );
+        assert_eq!(
+            map.roads[&OriginalRoad::new(1, (1, 2))].complicated_turn_restrictions,
+            vec![(vec![OriginalRoad::new(3, (2, 3))], renamed)]
+        );
+        // But one going through the road that's gone can't be kept
+        assert!(map.roads[&OriginalRoad::new(8, (5, 6))]
+            .complicated_turn_restrictions
+            .is_empty());
+    }
+}
@@ -0,0 +1,785 @@
+use abstutil::slurp_file;
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use flate2::read::ZlibDecoder;
+use geom::{Distance, LonLat};
+use std::error::Error;
+use std::io::Read;
+
+// A digital elevation model: a grid of samples covering a rectangle in lon/lat space. Two formats
+// are supported:
+//
+// - SRTM .hgt tiles (https://dwtkns.com/srtm30m/ or USGS), either 1 or 3 arc-second. The tile's
+//   southwest corner comes from the filename, like N47W122.hgt.
+// - GeoTIFF rasters with a single band, in a geographic (lon/lat) coordinate system. Projected
+//   rasters can be converted with `gdalwarp -t_srs EPSG:4326 in.tif out.tif`.
+pub struct Elevation {
+    // The center of the northwest-most sample
+    west: f64,
+    north: f64,
+    // Degrees between samples
+    dx: f64,
+    dy: f64,
+    width: usize,
+    height: usize,
+    // Meters, in row-major order from the north. NaN means no data.
+    data: Vec<f32>,
+}
+
+impl Elevation {
+    pub fn load(path: &str) -> Result<Elevation, Box<dyn Error>> {
+        println!("Reading elevation data from {}", path);
+        let lower = path.to_lowercase();
+        let result = if lower.ends_with(".hgt") {
+            load_hgt(path)
+        } else if lower.ends_with(".tif") || lower.ends_with(".tiff") {
+            load_geotiff(path)
+        } else {
+            Err("only .hgt and .tif files are supported".into())
+        };
+        result.map_err(|err| format!("Can't read elevation data from {}: {}", path, err).into())
+    }
+
+    // Bilinearly interpolates between the 4 nearest samples. None if the point is out of bounds
+    // or any of the samples are missing.
+    pub fn get(&self, pt: LonLat) -> Option<Distance> {
+        let fx = (pt.x() - self.west) / self.dx;
+        let fy = (self.north - pt.y()) / self.dy;
+        if fx < 0.0 || fy < 0.0 || fx > (self.width - 1) as f64 || fy > (self.height - 1) as f64 {
+            return None;
+        }
+        let (x0, y0) = (fx.floor() as usize, fy.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (fx - x0 as f64, fy - y0 as f64);
+
+        let sample = |x: usize, y: usize| f64::from(self.data[x + y * self.width]);
+        let (nw, ne, sw, se) = (
+            sample(x0, y0),
+            sample(x1, y0),
+            sample(x0, y1),
+            sample(x1, y1),
+        );
+        if nw.is_nan() || ne.is_nan() || sw.is_nan() || se.is_nan() {
+            return None;
+        }
+        let north = nw + (ne - nw) * tx;
+        let south = sw + (se - sw) * tx;
+        Some(Distance::meters(north + (south - north) * ty))
+    }
+}
+
+fn load_hgt(path: &str) -> Result<Elevation, Box<dyn Error>> {
+    let name = abstutil::basename(path).to_uppercase();
+    let bad_name = || "the file isn't named like N47W122.hgt";
+    if name.len() != 7 {
+        return Err(bad_name().into());
+    }
+    let lat = name[1..3].parse::<f64>().map_err(|_| bad_name())?
+        * match &name[0..1] {
+            "N" => 1.0,
+            "S" => -1.0,
+            _ => {
+                return Err(bad_name().into());
+            }
+        };
+    let lon = name[4..7].parse::<f64>().map_err(|_| bad_name())?
+        * match &name[3..4] {
+            "E" => 1.0,
+            "W" => -1.0,
+            _ => {
+                return Err(bad_name().into());
+            }
+        };
+
+    let bytes = slurp_file(path)?;
+    // Square grids of big-endian i16s. 1201 samples per side for 3 arc-seconds, 3601 for 1.
+    let dim = ((bytes.len() / 2) as f64).sqrt() as usize;
+    if dim < 2 || dim * dim * 2 != bytes.len() {
+        return Err(format!("weird size of {} bytes", bytes.len()).into());
+    }
+    let data = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            let x = BigEndian::read_i16(pair);
+            // Voids in the data
+            if x == -32768 {
+                std::f32::NAN
+            } else {
+                f32::from(x)
+            }
+        })
+        .collect();
+
+    // Samples on the edges overlap with the neighboring tiles.
+    Ok(Elevation {
+        west: lon,
+        north: lat + 1.0,
+        dx: 1.0 / (dim - 1) as f64,
+        dy: 1.0 / (dim - 1) as f64,
+        width: dim,
+        height: dim,
+        data,
+    })
+}
+
+// Just enough of https://www.awaresystems.be/imaging/tiff/specification/TIFF6.pdf and
+// http://docs.opengeospatial.org/is/19-008r4/19-008r4.html to handle the DEMs people typically
+// download: one band, stored in strips or tiles, uncompressed or deflated.
+fn load_geotiff(path: &str) -> Result<Elevation, Box<dyn Error>> {
+    let bytes = slurp_file(path)?;
+    let tiff = Tiff::new(&bytes)?;
+    let ifd = tiff.u32_at(4)? as usize;
+    let num_entries = tiff.u16_at(ifd)? as usize;
+    let mut entries = Vec::new();
+    for idx in 0..num_entries {
+        entries.push(ifd + 2 + 12 * idx);
+    }
+    let find = |tag: u16| -> Result<Option<usize>, Box<dyn Error>> {
+        for entry in &entries {
+            if tiff.u16_at(*entry)? == tag {
+                return Ok(Some(*entry));
+            }
+        }
+        Ok(None)
+    };
+    let required = |tag: u16| -> Result<Vec<f64>, Box<dyn Error>> {
+        match find(tag)? {
+            Some(entry) => tiff.values(entry),
+            None => Err(format!("missing TIFF tag {}", tag).into()),
+        }
+    };
+    let optional = |tag: u16, default: f64| -> Result<f64, Box<dyn Error>> {
+        match find(tag)? {
+            Some(entry) => Ok(tiff.values(entry)?[0]),
+            None => Ok(default),
+        }
+    };
+
+    let width = required(256)?[0] as usize;
+    let height = required(257)?[0] as usize;
+    let bits = required(258)?[0] as usize;
+    let compression = optional(259, 1.0)? as usize;
+    let samples_per_pixel = optional(277, 1.0)? as usize;
+    let predictor = optional(317, 1.0)? as usize;
+    // 1 is unsigned int, 2 is signed int, 3 is float
+    let sample_format = optional(339, 1.0)? as usize;
+    if width < 2 || height < 2 {
+        return Err("the raster is too small".into());
+    }
+    if samples_per_pixel != 1 {
+        return Err(format!("{} bands; only 1 is supported", samples_per_pixel).into());
+    }
+    if compression != 1 && compression != 8 && compression != 32946 {
+        return Err(format!("unsupported compression {}", compression).into());
+    }
+    if predictor != 1 && !(predictor == 2 && sample_format != 3) {
+        return Err(format!("unsupported predictor {}", predictor).into());
+    }
+    match (sample_format, bits) {
+        (1, 8) | (1, 16) | (2, 16) | (1, 32) | (2, 32) | (3, 32) | (3, 64) => {}
+        _ => {
+            return Err(format!(
+                "unsupported {}-bit samples of format {}",
+                bits, sample_format
+            )
+            .into());
+        }
+    }
+
+    // Each chunk is (byte offset, byte count, first column, first row, chunk width, chunk height)
+    let mut chunks = Vec::new();
+    if find(324)?.is_some() {
+        let tile_width = required(322)?[0] as usize;
+        let tile_height = required(323)?[0] as usize;
+        let tiles_across = (width + tile_width - 1) / tile_width;
+        for (idx, (offset, count)) in required(324)?.into_iter().zip(required(325)?).enumerate() {
+            chunks.push((
+                offset as usize,
+                count as usize,
+                (idx % tiles_across) * tile_width,
+                (idx / tiles_across) * tile_height,
+                tile_width,
+                tile_height,
+            ));
+        }
+    } else {
+        let rows_per_strip = (optional(278, height as f64)? as usize).min(height);
+        for (idx, (offset, count)) in required(273)?.into_iter().zip(required(279)?).enumerate() {
+            chunks.push((
+                offset as usize,
+                count as usize,
+                0,
+                idx * rows_per_strip,
+                width,
+                // The last strip may be shorter
+                rows_per_strip.min(height.saturating_sub(idx * rows_per_strip)),
+            ));
+        }
+    }
+
+    let nodata = match find(42113)? {
+        Some(entry) => tiff.ascii(entry)?.trim().parse::<f64>().ok(),
+        None => None,
+    };
+    let mut data = vec![std::f32::NAN; width * height];
+    let bytes_per_sample = bits / 8;
+    let mask = if bits == 64 {
+        std::u64::MAX
+    } else {
+        (1 << bits) - 1
+    };
+    for (offset, count, x0, y0, chunk_width, chunk_height) in chunks {
+        let raw = tiff.slice(offset, count)?;
+        let decompressed;
+        let buf = if compression == 1 {
+            raw
+        } else {
+            let mut out = Vec::new();
+            ZlibDecoder::new(raw).read_to_end(&mut out)?;
+            decompressed = out;
+            &decompressed[..]
+        };
+        if buf.len() < chunk_width * chunk_height * bytes_per_sample {
+            return Err("truncated chunk".into());
+        }
+
+        for row in 0..chunk_height {
+            let mut last: u64 = 0;
+            for col in 0..chunk_width {
+                let idx = (row * chunk_width + col) * bytes_per_sample;
+                let mut raw = tiff.sample_bits(&buf[idx..idx + bytes_per_sample]);
+                if predictor == 2 {
+                    // Horizontal differencing
+                    raw = raw.wrapping_add(last) & mask;
+                    last = raw;
+                }
+                let (x, y) = (x0 + col, y0 + row);
+                if x >= width || y >= height {
+                    continue;
+                }
+                let value = match (sample_format, bits) {
+                    (1, _) => raw as f64,
+                    (2, 16) => f64::from(raw as u16 as i16),
+                    (2, 32) => f64::from(raw as u32 as i32),
+                    (3, 32) => f64::from(f32::from_bits(raw as u32)),
+                    (3, 64) => f64::from_bits(raw),
+                    _ => unreachable!(),
+                };
+                if Some(value) != nodata && value.is_finite() {
+                    data[x + y * width] = value as f32;
+                }
+            }
+        }
+    }
+
+    // Where is the raster?
+    let scale = required(33550)?;
+    let tiepoint = required(33922)?;
+    if scale.len() < 2 || tiepoint.len() < 6 {
+        return Err("malformed georeference".into());
+    }
+    let (dx, dy) = (scale[0], scale[1]);
+    // The tiepoint maps raster (I, J) to (X, Y)
+    let mut west = tiepoint[3] - tiepoint[0] * dx;
+    let mut north = tiepoint[4] + tiepoint[1] * dy;
+
+    let mut geographic = None;
+    let mut pixel_is_area = true;
+    if find(34735)?.is_some() {
+        let keys = required(34735)?;
+        for key in keys[4..].chunks_exact(4) {
+            // Only look at values stored directly in the directory
+            if key[1] != 0.0 {
+                continue;
+            }
+            match key[0] as usize {
+                // GTModelTypeGeoKey
+                1024 => {
+                    geographic = Some(key[3] as usize == 2);
+                }
+                // GTRasterTypeGeoKey
+                1025 => {
+                    pixel_is_area = key[3] as usize == 1;
+                }
+                _ => {}
+            }
+        }
+    }
+    if geographic == Some(false) {
+        return Err(
+            "it uses a projected coordinate system; reproject it to EPSG:4326 first".into(),
+        );
+    }
+    // Otherwise the tiepoint is the corner of the first pixel, not its center.
+    if pixel_is_area {
+        west += dx / 2.0;
+        north -= dy / 2.0;
+    }
+
+    Ok(Elevation {
+        west,
+        north,
+        dx,
+        dy,
+        width,
+        height,
+        data,
+    })
+}
+
+struct Tiff<'a> {
+    bytes: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Tiff<'a>, Box<dyn Error>> {
+        let little_endian = match bytes.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => {
+                return Err("not a TIFF file".into());
+            }
+        };
+        let tiff = Tiff {
+            bytes,
+            little_endian,
+        };
+        match tiff.u16_at(2)? {
+            42 => Ok(tiff),
+            43 => Err("BigTIFF isn't supported".into()),
+            _ => Err("not a TIFF file".into()),
+        }
+    }
+
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        self.bytes
+            .get(offset..offset + len)
+            .ok_or_else(|| "truncated TIFF file".into())
+    }
+
+    fn u16_at(&self, offset: usize) -> Result<u16, Box<dyn Error>> {
+        let buf = self.slice(offset, 2)?;
+        Ok(if self.little_endian {
+            LittleEndian::read_u16(buf)
+        } else {
+            BigEndian::read_u16(buf)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Result<u32, Box<dyn Error>> {
+        let buf = self.slice(offset, 4)?;
+        Ok(if self.little_endian {
+            LittleEndian::read_u32(buf)
+        } else {
+            BigEndian::read_u32(buf)
+        })
+    }
+
+    // Reads a 1, 2, 4, or 8 byte sample as raw bits.
+    fn sample_bits(&self, buf: &[u8]) -> u64 {
+        match (buf.len(), self.little_endian) {
+            (1, _) => u64::from(buf[0]),
+            (2, true) => u64::from(LittleEndian::read_u16(buf)),
+            (2, false) => u64::from(BigEndian::read_u16(buf)),
+            (4, true) => u64::from(LittleEndian::read_u32(buf)),
+            (4, false) => u64::from(BigEndian::read_u32(buf)),
+            (8, true) => LittleEndian::read_u64(buf),
+            (8, false) => BigEndian::read_u64(buf),
+            _ => unreachable!(),
+        }
+    }
+
+    // Where the values of an IFD entry live, and how many bytes each one is
+    fn locate(&self, entry: usize) -> Result<(usize, usize, usize), Box<dyn Error>> {
+        let field_type = self.u16_at(entry + 2)?;
+        let count = self.u32_at(entry + 4)? as usize;
+        let size = match field_type {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => {
+                return Err(format!("unsupported TIFF field type {}", field_type).into());
+            }
+        };
+        let offset = if size * count <= 4 {
+            entry + 8
+        } else {
+            self.u32_at(entry + 8)? as usize
+        };
+        Ok((offset, count, size))
+    }
+
+    fn values(&self, entry: usize) -> Result<Vec<f64>, Box<dyn Error>> {
+        let field_type = self.u16_at(entry + 2)?;
+        let (offset, count, size) = self.locate(entry)?;
+        let buf = self.slice(offset, count * size)?;
+        let mut result = Vec::new();
+        for idx in 0..count {
+            let raw = self.sample_bits(&buf[idx * size..(idx + 1) * size]);
+            result.push(match field_type {
+                1 | 3 | 4 => raw as f64,
+                6 => f64::from(raw as u8 as i8),
+                8 => f64::from(raw as u16 as i16),
+                9 => f64::from(raw as u32 as i32),
+                11 => f64::from(f32::from_bits(raw as u32)),
+                12 => f64::from_bits(raw),
+                _ => {
+                    return Err(format!("TIFF field type {} isn't numeric", field_type).into());
+                }
+            });
+        }
+        Ok(result)
+    }
+
+    fn ascii(&self, entry: usize) -> Result<String, Box<dyn Error>> {
+        let (offset, count, _) = self.locate(entry)?;
+        let buf = self.slice(offset, count)?;
+        Ok(String::from_utf8_lossy(buf)
+            .trim_end_matches('\0')
+            .to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    // Writes a fixture to its own directory, since .hgt files need a particular name.
+    fn write_fixture(test: &str, filename: &str, bytes: &[u8]) -> String {
+        let dir = std::env::temp_dir().join(format!("convert_osm_elevation_{}", test));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(filename);
+        std::fs::write(&path, bytes).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn hgt(samples: &[i16]) -> Vec<u8> {
+        let mut bytes = vec![0; samples.len() * 2];
+        BigEndian::write_i16_into(samples, &mut bytes);
+        bytes
+    }
+
+    fn pt(lon: f64, lat: f64) -> LonLat {
+        LonLat::new(lon, lat)
+    }
+
+    #[test]
+    fn test_hgt() {
+        // A 3x3 grid covering N47-48, W122-121, so samples are half a degree apart. The southeast
+        // quadrant has a void.
+        let path = write_fixture(
+            "hgt",
+            "N47W122.hgt",
+            &hgt(&[100, 110, 120, 200, 210, 220, 300, -32768, 320]),
+        );
+        let elevation = Elevation::load(&path).unwrap();
+
+        assert_eq!(
+            elevation.get(pt(-122.0, 48.0)),
+            Some(Distance::meters(100.0))
+        );
+        assert_eq!(
+            elevation.get(pt(-121.0, 48.0)),
+            Some(Distance::meters(120.0))
+        );
+        assert_eq!(
+            elevation.get(pt(-122.0, 47.5)),
+            Some(Distance::meters(200.0))
+        );
+        // Halfway between 100, 110, 200, and 210
+        assert_eq!(
+            elevation.get(pt(-121.75, 47.75)),
+            Some(Distance::meters(155.0))
+        );
+        assert_eq!(elevation.get(pt(-121.25, 47.25)), None);
+        assert_eq!(elevation.get(pt(-122.1, 47.5)), None);
+        assert_eq!(elevation.get(pt(-121.5, 48.1)), None);
+    }
+
+    #[test]
+    fn test_hgt_southern_and_eastern_hemispheres() {
+        let path = write_fixture("hgt_se", "s34e151.hgt", &hgt(&[1, 2, 3, 4]));
+        let elevation = Elevation::load(&path).unwrap();
+        assert_eq!(elevation.get(pt(151.0, -33.0)), Some(Distance::meters(1.0)));
+        assert_eq!(elevation.get(pt(152.0, -34.0)), Some(Distance::meters(4.0)));
+    }
+
+    #[test]
+    fn test_bad_hgt() {
+        let path = write_fixture("bad_hgt", "seattle.hgt", &hgt(&[1, 2, 3, 4]));
+        assert!(Elevation::load(&path).is_err());
+
+        let path = write_fixture("bad_hgt", "N47W122.hgt", &hgt(&[1, 2, 3]));
+        assert_eq!(
+            load_error(&path),
+            format!(
+                "Can't read elevation data from {}: weird size of 6 bytes",
+                path
+            )
+        );
+
+        let path = write_fixture("bad_hgt", "N47W122.dem", &hgt(&[1, 2, 3, 4]));
+        assert!(Elevation::load(&path).is_err());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let path = write_fixture("missing", "N47W122.hgt", &hgt(&[1, 2, 3, 4]));
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            load_error(&path).starts_with(&format!("Can't read elevation data from {}: ", path))
+        );
+    }
+
+    fn load_error(path: &str) -> String {
+        match Elevation::load(path) {
+            Ok(_) => panic!("{} loaded fine", path),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    // (tag, field type, count, encoded values)
+    type Entry = (u16, u16, usize, Vec<u8>);
+
+    fn shorts(little_endian: bool, tag: u16, values: &[u16]) -> Entry {
+        let mut bytes = vec![0; values.len() * 2];
+        if little_endian {
+            LittleEndian::write_u16_into(values, &mut bytes);
+        } else {
+            BigEndian::write_u16_into(values, &mut bytes);
+        }
+        (tag, 3, values.len(), bytes)
+    }
+
+    fn long(little_endian: bool, tag: u16, value: u32) -> Entry {
+        let mut bytes = vec![0; 4];
+        if little_endian {
+            LittleEndian::write_u32(&mut bytes, value);
+        } else {
+            BigEndian::write_u32(&mut bytes, value);
+        }
+        (tag, 4, 1, bytes)
+    }
+
+    fn doubles(little_endian: bool, tag: u16, values: &[f64]) -> Entry {
+        let mut bytes = vec![0; values.len() * 8];
+        if little_endian {
+            LittleEndian::write_f64_into(values, &mut bytes);
+        } else {
+            BigEndian::write_f64_into(values, &mut bytes);
+        }
+        (tag, 12, values.len(), bytes)
+    }
+
+    // A single strip holding all of the pixels. The entries shouldn't include the strip offsets
+    // or byte counts.
+    fn tiff(little_endian: bool, strip: &[u8], mut entries: Vec<Entry>) -> Vec<u8> {
+        // The strip goes right after the header, then the IFD, then any values too big to fit
+        // in their entry.
+        entries.push(long(little_endian, 273, 8));
+        entries.push(long(little_endian, 279, strip.len() as u32));
+        entries.sort_by_key(|(tag, _, _, _)| *tag);
+        let ifd = 8 + strip.len();
+        let mut extra_offset = ifd + 2 + 12 * entries.len() + 4;
+
+        let u16_bytes = |x: u16| {
+            let mut buf = vec![0; 2];
+            if little_endian {
+                LittleEndian::write_u16(&mut buf, x);
+            } else {
+                BigEndian::write_u16(&mut buf, x);
+            }
+            buf
+        };
+        let u32_bytes = |x: u32| {
+            let mut buf = vec![0; 4];
+            if little_endian {
+                LittleEndian::write_u32(&mut buf, x);
+            } else {
+                BigEndian::write_u32(&mut buf, x);
+            }
+            buf
+        };
+
+        let mut out = Vec::new();
+        out.extend_from_slice(if little_endian { b"II" } else { b"MM" });
+        out.extend(u16_bytes(42));
+        out.extend(u32_bytes(ifd as u32));
+        out.extend_from_slice(strip);
+        out.extend(u16_bytes(entries.len() as u16));
+        let mut extra = Vec::new();
+        for (tag, field_type, count, mut bytes) in entries {
+            out.extend(u16_bytes(tag));
+            out.extend(u16_bytes(field_type));
+            out.extend(u32_bytes(count as u32));
+            if bytes.len() <= 4 {
+                bytes.resize(4, 0);
+                out.extend(bytes);
+            } else {
+                out.extend(u32_bytes(extra_offset as u32));
+                extra_offset += bytes.len();
+                extra.extend(bytes);
+            }
+        }
+        // No more IFDs
+        out.extend(u32_bytes(0));
+        out.extend(extra);
+        out
+    }
+
+    // GeoKeyDirectory header, then GTModelTypeGeoKey, then optionally GTRasterTypeGeoKey
+    fn geokeys(little_endian: bool, geographic: bool, pixel_is_point: bool) -> Entry {
+        let mut keys = vec![1, 1, 0, 1, 1024, 0, 1, if geographic { 2 } else { 1 }];
+        if pixel_is_point {
+            keys[3] = 2;
+            keys.extend_from_slice(&[1025, 0, 1, 2]);
+        }
+        shorts(little_endian, 34735, &keys)
+    }
+
+    #[test]
+    fn test_geotiff_signed_with_nodata() {
+        // 3x2 signed 16-bit samples a quarter degree apart, with the tiepoint on the center of
+        // the northwest sample
+        let le = true;
+        let mut strip = vec![0; 12];
+        LittleEndian::write_i16_into(&[10, 20, -9999, 30, 40, 50], &mut strip);
+        let nodata = b"-9999\0".to_vec();
+        let path = write_fixture(
+            "geotiff_signed",
+            "dem.tif",
+            &tiff(
+                le,
+                &strip,
+                vec![
+                    shorts(le, 256, &[3]),
+                    shorts(le, 257, &[2]),
+                    shorts(le, 258, &[16]),
+                    shorts(le, 339, &[2]),
+                    doubles(le, 33550, &[0.25, 0.25, 0.0]),
+                    doubles(le, 33922, &[0.0, 0.0, 0.0, -122.0, 48.0, 0.0]),
+                    geokeys(le, true, true),
+                    (42113, 2, nodata.len(), nodata),
+                ],
+            ),
+        );
+        let elevation = Elevation::load(&path).unwrap();
+
+        assert_eq!(
+            elevation.get(pt(-122.0, 48.0)),
+            Some(Distance::meters(10.0))
+        );
+        assert_eq!(
+            elevation.get(pt(-121.5, 47.75)),
+            Some(Distance::meters(50.0))
+        );
+        assert_eq!(
+            elevation.get(pt(-121.875, 47.875)),
+            Some(Distance::meters(25.0))
+        );
+        // Touches the nodata sample
+        assert_eq!(elevation.get(pt(-121.625, 47.875)), None);
+        assert_eq!(elevation.get(pt(-122.125, 47.875)), None);
+    }
+
+    #[test]
+    fn test_geotiff_deflate_with_predictor() {
+        // Big-endian unsigned 16-bit samples, deflated after horizontal differencing. Without a
+        // GTRasterTypeGeoKey, pixels are areas, so the tiepoint is the corner of the first one.
+        let le = false;
+        let rows: Vec<Vec<u16>> = vec![vec![100, 105, 95], vec![200, 190, 210]];
+        let mut differenced = Vec::new();
+        for row in rows {
+            let mut last = 0_u16;
+            for x in row {
+                differenced.push(x.wrapping_sub(last));
+                last = x;
+            }
+        }
+        let mut raw = vec![0; differenced.len() * 2];
+        BigEndian::write_u16_into(&differenced, &mut raw);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let strip = encoder.finish().unwrap();
+
+        let path = write_fixture(
+            "geotiff_deflate",
+            "dem.TIFF",
+            &tiff(
+                le,
+                &strip,
+                vec![
+                    shorts(le, 256, &[3]),
+                    shorts(le, 257, &[2]),
+                    shorts(le, 258, &[16]),
+                    shorts(le, 259, &[8]),
+                    shorts(le, 317, &[2]),
+                    doubles(le, 33550, &[0.25, 0.25, 0.0]),
+                    doubles(le, 33922, &[0.0, 0.0, 0.0, -122.0, 48.0, 0.0]),
+                    geokeys(le, true, false),
+                ],
+            ),
+        );
+        let elevation = Elevation::load(&path).unwrap();
+
+        assert_eq!(
+            elevation.get(pt(-121.875, 47.875)),
+            Some(Distance::meters(100.0))
+        );
+        assert_eq!(
+            elevation.get(pt(-121.375, 47.625)),
+            Some(Distance::meters(210.0))
+        );
+        assert_eq!(
+            elevation.get(pt(-121.75, 47.75)),
+            Some(Distance::meters(148.75))
+        );
+        assert_eq!(elevation.get(pt(-122.0, 48.0)), None);
+    }
+
+    #[test]
+    fn test_bad_geotiff() {
+        let le = true;
+        let strip = vec![0; 8];
+        let fields = |geographic: bool, bits: u16| {
+            vec![
+                shorts(le, 256, &[2]),
+                shorts(le, 257, &[2]),
+                shorts(le, 258, &[bits]),
+                doubles(le, 33550, &[0.25, 0.25, 0.0]),
+                doubles(le, 33922, &[0.0, 0.0, 0.0, -122.0, 48.0, 0.0]),
+                geokeys(le, geographic, false),
+            ]
+        };
+
+        // Sanity check the fixture first
+        let path = write_fixture("bad_geotiff", "ok.tif", &tiff(le, &strip, fields(true, 16)));
+        assert!(Elevation::load(&path).is_ok());
+
+        let path = write_fixture(
+            "bad_geotiff",
+            "projected.tif",
+            &tiff(le, &strip, fields(false, 16)),
+        );
+        assert!(Elevation::load(&path).is_err());
+
+        let path = write_fixture(
+            "bad_geotiff",
+            "12bit.tif",
+            &tiff(le, &strip, fields(true, 12)),
+        );
+        assert!(Elevation::load(&path).is_err());
+
+        let mut truncated = tiff(le, &strip, fields(true, 16));
+        truncated.truncate(40);
+        let path = write_fixture("bad_geotiff", "truncated.tif", &truncated);
+        assert!(Elevation::load(&path).is_err());
+
+        let path = write_fixture("bad_geotiff", "not_a_tiff.tif", b"GIF89a");
+        assert!(Elevation::load(&path).is_err());
+    }
+}
@@ -2,9 +2,11 @@ use crate::reader::{Document, Relation};
 use crate::transit;
 use crate::Options;
 use abstutil::{retain_btreemap, Tags, Timer};
-use geom::{HashablePt2D, PolyLine, Polygon, Pt2D, Ring};
+use geom::{HashablePt2D, LonLat, PolyLine, Polygon, Pt2D, Ring};
 use kml::{ExtraShape, ExtraShapes};
-use map_model::raw::{RawArea, RawBuilding, RawMap, RawParkingLot, RawRoad, RestrictionType};
+use map_model::raw::{
+    CrossingType, RawArea, RawBuilding, RawMap, RawParkingLot, RawRoad, RestrictionType,
+};
 use map_model::{osm, AreaType, NamePerLanguage};
 use osm::{NodeID, OsmID, RelationID, WayID};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
@@ -17,14 +19,43 @@ pub struct OsmExtract {
     pub osm_node_ids: HashMap<HashablePt2D, NodeID>,
     // (ID, restriction type, from way ID, via node ID, to way ID)
     pub simple_turn_restrictions: Vec<(RestrictionType, WayID, NodeID, WayID)>,
-    // (relation ID, from way ID, via way ID, to way ID)
-    pub complicated_turn_restrictions: Vec<(RelationID, WayID, WayID, WayID)>,
+    // (relation ID, from way ID, via way IDs in order, to way ID)
+    pub complicated_turn_restrictions: Vec<(RelationID, WayID, Vec<WayID>, WayID)>,
     // (location, name, amenity type)
     pub amenities: Vec<(Pt2D, NamePerLanguage, String)>,
+    // Separately mapped sidewalks (footway=sidewalk)
+    pub sidewalks: Vec<Vec<Pt2D>>,
+    // From highway=crossing nodes and footway=crossing ways
+    pub crossings: Vec<(Pt2D, CrossingType)>,
+    // amenity=charging_station nodes, with the number of vehicles that can charge at once
+    pub charging_stations: Vec<(Pt2D, usize)>,
 }
 
 pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmExtract {
-    let mut doc = crate::reader::read(&opts.osm_input, &map.gps_bounds, timer).unwrap();
+    let clip = opts
+        .clip
+        .as_ref()
+        .map(|path| LonLat::read_osmosis_polygon(path.to_string()).unwrap());
+    // Clipping a big .pbf is slow, so keep what's inside each map's boundary next to the input,
+    // like osmconvert's output used to be.
+    let cache = opts.clip.as_ref().map(|path| {
+        format!(
+            "{}/{}.bin",
+            std::path::Path::new(&opts.osm_input)
+                .parent()
+                .and_then(|dir| dir.to_str())
+                .unwrap_or("."),
+            abstutil::basename(path)
+        )
+    });
+    let mut doc = crate::reader::read(
+        &opts.osm_input,
+        &map.gps_bounds,
+        clip.as_ref(),
+        cache,
+        timer,
+    )
+    .unwrap();
     if opts.clip.is_none() {
         // Use the boundary from .osm.
         map.gps_bounds = doc.gps_bounds.clone();
@@ -38,6 +69,9 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
         simple_turn_restrictions: Vec::new(),
         complicated_turn_restrictions: Vec::new(),
         amenities: Vec::new(),
+        sidewalks: Vec::new(),
+        crossings: Vec::new(),
+        charging_stations: Vec::new(),
     };
 
     timer.start_iter("processing OSM nodes", doc.nodes.len());
@@ -50,6 +84,10 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
             out.traffic_signals
                 .insert(node.pt.to_hashable(), !backwards);
         }
+        if node.tags.is(osm::HIGHWAY, "crossing") {
+            out.crossings
+                .push((node.pt, CrossingType::from_osm(&node.tags)));
+        }
         if let Some(amenity) = node.tags.get("amenity") {
             out.amenities.push((
                 node.pt,
@@ -57,6 +95,15 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
                 amenity.clone(),
             ));
         }
+        if node.tags.is("amenity", "charging_station") {
+            out.charging_stations.push((
+                node.pt,
+                node.tags
+                    .get("capacity")
+                    .and_then(|x| x.parse::<usize>().ok())
+                    .unwrap_or(1),
+            ));
+        }
         if let Some(shop) = node.tags.get("shop") {
             out.amenities.push((
                 node.pt,
@@ -80,13 +127,6 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
         way.tags.insert(osm::OSM_WAY_ID, id.0.to_string());
 
         if is_road(&mut way.tags, opts) {
-            // TODO Hardcoding these overrides. OSM is correct, these don't have
-            // sidewalks; there's a crosswalk mapped. But until we can snap sidewalks properly, do
-            // this to prevent the sidewalks from being disconnected.
-            if id == WayID(332060260) || id == WayID(332060236) {
-                way.tags.insert(osm::SIDEWALK, "right");
-            }
-
             out.roads.push((
                 id,
                 RawRoad {
@@ -94,6 +134,8 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
                     osm_tags: way.tags.clone(),
                     turn_restrictions: Vec::new(),
                     complicated_turn_restrictions: Vec::new(),
+                    elevation_samples: Vec::new(),
+                    crossings: Vec::new(),
                 },
             ));
             continue;
@@ -109,6 +151,16 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
             .tags
             .is_any(osm::HIGHWAY, vec!["cycleway", "footway", "path"])
         {
+            if way.tags.is("footway", "sidewalk") {
+                out.sidewalks.push(way.pts.clone());
+            } else if way.tags.is("footway", "crossing") {
+                // The crossing usually shares a node with the road in the middle of the way. If
+                // that node is tagged too, this is deduplicated later.
+                if let Ok(pl) = PolyLine::new(way.pts.clone()) {
+                    out.crossings
+                        .push((pl.middle(), CrossingType::from_osm(&way.tags)));
+                }
+            }
             extra_footways.shapes.push(ExtraShape {
                 points: map.gps_bounds.convert_back(&way.pts),
                 attributes: way.tags.inner().clone(),
@@ -206,7 +258,7 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
         } else if rel.tags.is("type", "restriction") {
             let mut from_way_id: Option<WayID> = None;
             let mut via_node_id: Option<NodeID> = None;
-            let mut via_way_id: Option<WayID> = None;
+            let mut via_way_ids: Vec<WayID> = Vec::new();
             let mut to_way_id: Option<WayID> = None;
             for (role, member) in &rel.members {
                 match member {
@@ -216,7 +268,7 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
                         } else if role == "to" {
                             to_way_id = Some(*w);
                         } else if role == "via" {
-                            via_way_id = Some(*w);
+                            via_way_ids.push(*w);
                         }
                     }
                     OsmID::Node(n) => {
@@ -232,16 +284,17 @@ pub fn extract_osm(map: &mut RawMap, opts: &Options, timer: &mut Timer) -> OsmEx
                     if let (Some(from), Some(via), Some(to)) = (from_way_id, via_node_id, to_way_id)
                     {
                         out.simple_turn_restrictions.push((rt, from, via, to));
-                    } else if let (Some(from), Some(via), Some(to)) =
-                        (from_way_id, via_way_id, to_way_id)
-                    {
-                        if rt == RestrictionType::BanTurns {
-                            out.complicated_turn_restrictions.push((id, from, via, to));
+                    } else if let (Some(from), Some(to)) = (from_way_id, to_way_id) {
+                        if via_way_ids.is_empty() {
+                            // Nothing to go on
+                        } else if rt == RestrictionType::BanTurns {
+                            out.complicated_turn_restrictions
+                                .push((id, from, via_way_ids, to));
                         } else {
                             timer.warn(format!(
-                                "Weird complicated turn restriction \"{}\" from {} to {} via {}: \
-                                 {}",
-                                restriction, from, to, via, id
+                                "Weird complicated turn restriction \"{}\" from {} to {} via \
+                                 {:?}: {}",
+                                restriction, from, to, via_way_ids, id
                             ));
                         }
                     }
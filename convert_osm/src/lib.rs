@@ -1,15 +1,23 @@
 mod clip;
+mod elevation;
 mod extract;
 mod parking;
+mod pbf;
 mod reader;
+mod sidewalks;
 mod split_ways;
-mod srtm;
 mod transit;
 
+pub use parking::apply_parking;
+
 use abstutil::Timer;
-use geom::{Distance, FindClosest, GPSBounds, LonLat, Pt2D, Ring};
+use geom::{Bounds, Distance, FindClosest, GPSBounds, Line, LonLat, Pt2D, Ring};
 use map_model::raw::RawMap;
 use map_model::{osm, MapConfig, NamePerLanguage};
+use serde::{Deserialize, Serialize};
+
+// How often to sample elevation data along roads. Most DEMs are around 30m resolution.
+const ELEVATION_SAMPLE_DIST: Distance = Distance::const_meters(10.0);
 
 pub struct Options {
     pub osm_input: String,
@@ -23,8 +31,7 @@ pub struct Options {
     pub onstreet_parking: OnstreetParking,
     pub public_offstreet_parking: PublicOffstreetParking,
     pub private_offstreet_parking: PrivateOffstreetParking,
-    // If provided, pull elevation data from this SRTM file. The SRTM parser is incorrect, so the
-    // results will be nonsense.
+    // If provided, pull elevation data from this SRTM .hgt or GeoTIFF file.
     pub elevation: Option<String>,
     // OSM railway=rail will be included as light rail if so. Cosmetic only.
     pub include_railroads: bool,
@@ -32,12 +39,16 @@ pub struct Options {
 
 // What roads will have on-street parking lanes? Data from
 // https://wiki.openstreetmap.org/wiki/Key:parking:lane is always used if available.
+#[derive(Clone, Serialize, Deserialize)]
 pub enum OnstreetParking {
     // If not tagged, there won't be parking.
     JustOSM,
     // If OSM data is missing, then try to match data from
     // http://data-seattlecitygis.opendata.arcgis.com/datasets/blockface. This is Seattle specific.
     Blockface(String),
+    // If OSM data is missing, then match lines from any GIS source to the closest side of a road.
+    // Prices and time limits also apply where OSM already has parking lanes.
+    GIS(ParkingShapes),
     // If OSM data is missing, then infer parking lanes on some percentage of
     // "highway=residential" roads.
     SomeAdditionalWhereNoData {
@@ -47,16 +58,40 @@ pub enum OnstreetParking {
 }
 
 // How many spots are available in public parking garages?
+#[derive(Clone, Serialize, Deserialize)]
 pub enum PublicOffstreetParking {
     None,
     // Pull data from
     // https://data-seattlecitygis.opendata.arcgis.com/datasets/public-garages-or-parking-lots, a
     // Seattle-specific data source.
     GIS(String),
+    // Points from any GIS source. A point inside a building makes it a public garage; a point
+    // inside a parking lot sets the lot's capacity, price, and time limit.
+    GenericGIS(ParkingShapes),
+}
+
+// Parking data from some city's GIS portal, already converted to ExtraShapes; see kml_downloads in
+// the importer config. The rest of the fields name attributes of each shape to use.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ParkingShapes {
+    pub path: String,
+    // The number of spots. For lines, 0 means no parking, and if this is missing, every line has
+    // parking. Points without a capacity are skipped.
+    pub capacity_attribute: Option<String>,
+    // Dollars per hour
+    pub price_attribute: Option<String>,
+    // In minutes
+    pub max_stay_attribute: Option<String>,
+    // When the price and time limit apply, in opening_hours syntax like "Mo-Sa 08:00-20:00". If
+    // missing, they always apply.
+    pub hours_attribute: Option<String>,
+    // For garages
+    pub name_attribute: Option<String>,
 }
 
 // If a building doesn't have anything from public_offstreet_parking and isn't tagged as a garage
 // in OSM, how many private spots should it have?
+#[derive(Clone, Serialize, Deserialize)]
 pub enum PrivateOffstreetParking {
     FixedPerBldg(usize),
     // TODO Based on the number of residents?
@@ -71,7 +106,10 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
         map.gps_bounds = gps_bounds;
     }
 
-    let extract = extract::extract_osm(&mut map, &opts, timer);
+    let mut extract = extract::extract_osm(&mut map, &opts, timer);
+    let sidewalks = std::mem::take(&mut extract.sidewalks);
+    let crossings = std::mem::take(&mut extract.crossings);
+    let charging_stations = std::mem::take(&mut extract.charging_stations);
     let (amenities, pt_to_road) = split_ways::split_up_roads(&mut map, extract, timer);
     clip::clip_map(&mut map, timer);
 
@@ -79,6 +117,9 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     // doing the parking hint matching.
     abstutil::retain_btreemap(&mut map.roads, |r, _| r.i1 != r.i2);
 
+    sidewalks::snap_separate_sidewalks(&mut map, sidewalks, timer);
+    sidewalks::snap_crossings(&mut map, crossings, timer);
+
     let all_routes = map.bus_routes.drain(..).collect::<Vec<_>>();
     let mut routes = Vec::new();
     for route in all_routes {
@@ -97,6 +138,7 @@ pub fn convert(opts: Options, timer: &mut abstutil::Timer) -> RawMap {
     use_amenities(&mut map, amenities, timer);
 
     parking::apply_parking(&mut map, &opts, timer);
+    use_charging_stations(&mut map, charging_stations, timer);
 
     if let Some(ref path) = opts.elevation {
         use_elevation(&mut map, path, timer);
@@ -128,16 +170,177 @@ fn use_amenities(
     }
 }
 
+// Where a charging station might be
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum ChargingSite {
+    // Index into parking_lots. Lots sort first, so they win over a building they overlap.
+    Lot(usize),
+    Building(osm::OsmID),
+}
+
+// Charging stations are usually mapped as points. Attach them to the parking lot or building
+// they're in, using the tag OSM uses for chargers in a parking lot.
+fn use_charging_stations(map: &mut RawMap, stations: Vec<(Pt2D, usize)>, timer: &mut Timer) {
+    // A station in the middle of a big lot can be far from every point on its outline, so check
+    // lots by their bounding box instead. There aren't many lots.
+    let lot_bounds: Vec<Bounds> = map
+        .parking_lots
+        .iter()
+        .map(|lot| lot.polygon.get_bounds())
+        .collect();
+    let mut closest: FindClosest<ChargingSite> = FindClosest::new(&map.gps_bounds.to_bounds());
+    for (id, b) in &map.buildings {
+        closest.add(ChargingSite::Building(*id), b.polygon.points());
+    }
+
+    timer.start_iter("match charging stations", stations.len());
+    for (pt, capacity) in stations {
+        timer.next();
+        let site = lot_bounds
+            .iter()
+            .enumerate()
+            .filter(|(idx, bounds)| {
+                bounds.contains(pt) && map.parking_lots[*idx].polygon.contains_pt(pt)
+            })
+            .map(|(idx, _)| ChargingSite::Lot(idx))
+            .chain(
+                closest
+                    .all_close_pts(pt, Distance::meters(50.0))
+                    .into_iter()
+                    .map(|(site, _, _)| site)
+                    .filter(|site| match site {
+                        ChargingSite::Lot(_) => false,
+                        ChargingSite::Building(id) => map.buildings[id].polygon.contains_pt(pt),
+                    }),
+            )
+            .min();
+        let tags = match site {
+            Some(ChargingSite::Lot(idx)) => &mut map.parking_lots[idx].osm_tags,
+            Some(ChargingSite::Building(id)) => &mut map.buildings.get_mut(&id).unwrap().osm_tags,
+            None => {
+                continue;
+            }
+        };
+        let existing = tags
+            .get("capacity:charging")
+            .and_then(|x| x.parse::<usize>().ok())
+            .unwrap_or(0);
+        tags.insert("capacity:charging", (existing + capacity).to_string());
+    }
+}
+
 fn use_elevation(map: &mut RawMap, path: &str, timer: &mut Timer) {
-    timer.start("apply elevation data to intersections");
-    let elevation = srtm::Elevation::load(path).unwrap();
+    timer.start("apply elevation data");
+    let elevation = match elevation::Elevation::load(path) {
+        Ok(elevation) => elevation,
+        Err(err) => panic!(
+            "{}. Fix the file, or leave elevation out of the config.",
+            err
+        ),
+    };
+    let mut missing = 0;
     for i in map.intersections.values_mut() {
-        // TODO Not sure why, but I've seen nodes from South Carolina wind up in the updated
-        // Seattle extract. And I think there's a bug with clipping, because they survive to this
-        // point. O_O
-        if map.boundary_polygon.contains_pt(i.point) {
-            i.elevation = elevation.get(i.point.to_gps(&map.gps_bounds));
+        if let Some(e) = elevation.get(i.point.to_gps(&map.gps_bounds)) {
+            i.elevation = e;
+        } else {
+            missing += 1;
+        }
+    }
+    if missing > 0 {
+        timer.warn(format!(
+            "{} intersections are outside of the elevation data",
+            missing
+        ));
+    }
+
+    // The ends of roads are trimmed back later, so the intersection's elevation is used there.
+    for r in map.roads.values_mut() {
+        r.elevation_samples.clear();
+        for pair in r.center_points.windows(2) {
+            if let Some(line) = Line::new(pair[0], pair[1]) {
+                let mut dist = Distance::ZERO;
+                while dist < line.length() {
+                    let pt = line.must_dist_along(dist);
+                    if let Some(e) = elevation.get(pt.to_gps(&map.gps_bounds)) {
+                        r.elevation_samples.push((pt, e));
+                    }
+                    dist += ELEVATION_SAMPLE_DIST;
+                }
+            }
+        }
+    }
+    timer.stop("apply elevation data");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abstutil::Tags;
+    use geom::Polygon;
+    use map_model::raw::RawParkingLot;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_charging_stations() {
+        // One road along y=100, with a building centered at (200, 120)
+        let mut map = RawMap::synthetic_grid(2, 1);
+        let bldg = osm::OsmID::Way(osm::WayID(1001));
+        // A parking lot across the street, with a little kiosk inside it
+        map.parking_lots.push(RawParkingLot {
+            osm_id: osm::OsmID::Way(osm::WayID(4000)),
+            polygon: Polygon::rectangle_centered(
+                Pt2D::new(200.0, 70.0),
+                Distance::meters(40.0),
+                Distance::meters(20.0),
+            ),
+            osm_tags: Tags::new(BTreeMap::new()),
+        });
+        // A huge lot further along, far bigger than the search radius for buildings
+        map.parking_lots.push(RawParkingLot {
+            osm_id: osm::OsmID::Way(osm::WayID(4002)),
+            polygon: Polygon::rectangle_centered(
+                Pt2D::new(400.0, 250.0),
+                Distance::meters(300.0),
+                Distance::meters(250.0),
+            ),
+            osm_tags: Tags::new(BTreeMap::new()),
+        });
+        let kiosk = osm::OsmID::Way(osm::WayID(4001));
+        let mut kiosk_bldg = map.buildings[&bldg].clone();
+        kiosk_bldg.polygon = Polygon::rectangle_centered(
+            Pt2D::new(210.0, 70.0),
+            Distance::meters(4.0),
+            Distance::meters(4.0),
+        );
+        map.buildings.insert(kiosk, kiosk_bldg);
+
+        use_charging_stations(
+            &mut map,
+            vec![
+                // Two stations in the same building add up
+                (Pt2D::new(200.0, 120.0), 2),
+                (Pt2D::new(203.0, 118.0), 1),
+                // In the lot
+                (Pt2D::new(190.0, 70.0), 4),
+                // In the kiosk, which is in the lot; the lot wins
+                (Pt2D::new(210.0, 70.0), 1),
+                // In the middle of the street, near everything but not inside anything
+                (Pt2D::new(200.0, 100.0), 10),
+                // Over 100m from any edge of the huge lot
+                (Pt2D::new(400.0, 250.0), 6),
+            ],
+            &mut Timer::throwaway(),
+        );
+
+        assert!(map.buildings[&bldg].osm_tags.is("capacity:charging", "3"));
+        assert!(map.parking_lots[0].osm_tags.is("capacity:charging", "5"));
+        assert!(map.parking_lots[1].osm_tags.is("capacity:charging", "6"));
+        assert!(!map.buildings[&kiosk]
+            .osm_tags
+            .contains_key("capacity:charging"));
+        // Nothing else picked up the one in the street
+        for b in map.buildings.values() {
+            assert!(!b.osm_tags.is("capacity:charging", "10"));
         }
     }
-    timer.stop("apply elevation data to intersections");
 }
@@ -1,7 +1,9 @@
-use crate::{OnstreetParking, Options, PrivateOffstreetParking, PublicOffstreetParking};
-use abstutil::Timer;
+use crate::{
+    OnstreetParking, Options, ParkingShapes, PrivateOffstreetParking, PublicOffstreetParking,
+};
+use abstutil::{Tags, Timer};
 use geom::{Distance, FindClosest, PolyLine, Pt2D};
-use kml::ExtraShapes;
+use kml::{ExtraShape, ExtraShapes};
 use map_model::osm;
 use map_model::raw::{OriginalRoad, RawMap};
 
@@ -14,6 +16,9 @@ pub fn apply_parking(map: &mut RawMap, opts: &Options, timer: &mut Timer) {
         OnstreetParking::Blockface(ref path) => {
             use_parking_hints(map, path.clone(), timer);
         }
+        OnstreetParking::GIS(ref source) => {
+            use_parking_lines(map, source, timer);
+        }
         OnstreetParking::SomeAdditionalWhereNoData { pct } => {
             let pct = pct as i64;
             for (id, r) in map.roads.iter_mut() {
@@ -42,6 +47,9 @@ pub fn apply_parking(map: &mut RawMap, opts: &Options, timer: &mut Timer) {
         PublicOffstreetParking::GIS(ref path) => {
             use_offstreet_parking(map, path.clone(), timer);
         }
+        PublicOffstreetParking::GenericGIS(ref source) => {
+            use_parking_points(map, source, timer);
+        }
     }
     apply_private_offstreet_parking(map, &opts.private_offstreet_parking);
 }
@@ -49,41 +57,10 @@ pub fn apply_parking(map: &mut RawMap, opts: &Options, timer: &mut Timer) {
 fn use_parking_hints(map: &mut RawMap, path: String, timer: &mut Timer) {
     timer.start("apply parking hints");
     let shapes: ExtraShapes = abstutil::read_binary(path, timer);
-
-    // Match shapes with the nearest road + direction (true for forwards)
-    let mut closest: FindClosest<(OriginalRoad, bool)> =
-        FindClosest::new(&map.gps_bounds.to_bounds());
-    for (id, r) in &map.roads {
-        if r.is_light_rail() || r.is_footway() {
-            continue;
-        }
-        let center = PolyLine::must_new(r.center_points.clone());
-        closest.add(
-            (*id, true),
-            center.must_shift_right(DIRECTED_ROAD_THICKNESS).points(),
-        );
-        closest.add(
-            (*id, false),
-            center.must_shift_left(DIRECTED_ROAD_THICKNESS).points(),
-        );
-    }
+    let closest = road_sides(map);
 
     for s in shapes.shapes.into_iter() {
-        let pts = map.gps_bounds.convert(&s.points);
-        if pts.len() <= 1 {
-            continue;
-        }
-        // The blockface line endpoints will be close to other roads, so match based on the
-        // middle of the blockface.
-        // TODO Long blockfaces sometimes cover two roads. Should maybe find ALL matches within
-        // the threshold distance?
-        let middle = if let Ok(pl) = PolyLine::new(pts) {
-            pl.middle()
-        } else {
-            // Weird blockface with duplicate points. Shrug.
-            continue;
-        };
-        if let Some(((r, fwds), _)) = closest.closest_pt(middle, DIRECTED_ROAD_THICKNESS * 5.0) {
+        if let Some((r, fwds)) = match_line(map, &closest, &s) {
             let tags = &mut map.roads.get_mut(&r).unwrap().osm_tags;
 
             // Skip if the road already has this mapped.
@@ -105,35 +82,161 @@ fn use_parking_hints(map: &mut RawMap, path: String, timer: &mut Timer) {
                 continue;
             }
 
-            if let Some(both) = tags.remove(osm::PARKING_BOTH) {
-                tags.insert(osm::PARKING_LEFT, both.clone());
-                tags.insert(osm::PARKING_RIGHT, both);
-            }
+            set_parking_lane(tags, fwds, has_parking);
+        }
+    }
+    timer.stop("apply parking hints");
+}
 
-            tags.insert(
-                if fwds {
-                    osm::PARKING_RIGHT
-                } else {
-                    osm::PARKING_LEFT
-                },
-                if has_parking {
-                    "parallel"
-                } else {
-                    "no_parking"
-                },
-            );
+// Like use_parking_hints, but for any source of lines
+fn use_parking_lines(map: &mut RawMap, source: &ParkingShapes, timer: &mut Timer) {
+    timer.start("apply parking lines");
+    let shapes: ExtraShapes = abstutil::read_binary(source.path.clone(), timer);
+    let closest = road_sides(map);
 
-            // Maybe fold back into "both"
-            if tags.contains_key(osm::PARKING_LEFT)
-                && tags.get(osm::PARKING_LEFT) == tags.get(osm::PARKING_RIGHT)
-            {
-                let value = tags.remove(osm::PARKING_LEFT).unwrap();
-                tags.remove(osm::PARKING_RIGHT).unwrap();
-                tags.insert(osm::PARKING_BOTH, value);
+    let mut matches = 0;
+    for s in shapes.shapes.into_iter() {
+        if let Some((r, fwds)) = match_line(map, &closest, &s) {
+            matches += 1;
+            let tags = &mut map.roads.get_mut(&r).unwrap().osm_tags;
+            let has_parking = match get_number(&s, &source.capacity_attribute) {
+                Some(capacity) => capacity > 0.0,
+                None => true,
+            };
+
+            if tags.contains_key(osm::INFERRED_PARKING) {
+                if has_parking && tags.is_any(osm::HIGHWAY, vec!["motorway", "motorway_link"]) {
+                    timer.warn(format!(
+                        "{} says there's parking along motorway {}, ignoring",
+                        source.path, r
+                    ));
+                    continue;
+                }
+                set_parking_lane(tags, fwds, has_parking);
+            }
+            if has_parking {
+                let prefix = if fwds {
+                    "parking:right:"
+                } else {
+                    "parking:left:"
+                };
+                set_parking_rules(tags, prefix, &s, source);
             }
         }
     }
-    timer.stop("apply parking hints");
+    timer.note(format!(
+        "Matched {} parking lines from {} to roads",
+        abstutil::prettyprint_usize(matches),
+        source.path
+    ));
+    timer.stop("apply parking lines");
+}
+
+// Match shapes with the nearest road + direction (true for forwards)
+fn road_sides(map: &RawMap) -> FindClosest<(OriginalRoad, bool)> {
+    let mut closest: FindClosest<(OriginalRoad, bool)> =
+        FindClosest::new(&map.gps_bounds.to_bounds());
+    for (id, r) in &map.roads {
+        if r.is_light_rail() || r.is_footway() {
+            continue;
+        }
+        let center = PolyLine::must_new(r.center_points.clone());
+        closest.add(
+            (*id, true),
+            center.must_shift_right(DIRECTED_ROAD_THICKNESS).points(),
+        );
+        closest.add(
+            (*id, false),
+            center.must_shift_left(DIRECTED_ROAD_THICKNESS).points(),
+        );
+    }
+    closest
+}
+
+fn match_line(
+    map: &RawMap,
+    closest: &FindClosest<(OriginalRoad, bool)>,
+    s: &ExtraShape,
+) -> Option<(OriginalRoad, bool)> {
+    let pts = map.gps_bounds.convert(&s.points);
+    if pts.len() <= 1 {
+        return None;
+    }
+    // The blockface line endpoints will be close to other roads, so match based on the middle of
+    // the blockface.
+    // TODO Long blockfaces sometimes cover two roads. Should maybe find ALL matches within the
+    // threshold distance?
+    // If the line has duplicate points, just skip it.
+    let middle = PolyLine::new(pts).ok()?.middle();
+    closest
+        .closest_pt(middle, DIRECTED_ROAD_THICKNESS * 5.0)
+        .map(|(pair, _)| pair)
+}
+
+fn set_parking_lane(tags: &mut Tags, fwds: bool, has_parking: bool) {
+    if let Some(both) = tags.remove(osm::PARKING_BOTH) {
+        tags.insert(osm::PARKING_LEFT, both.clone());
+        tags.insert(osm::PARKING_RIGHT, both);
+    }
+
+    tags.insert(
+        if fwds {
+            osm::PARKING_RIGHT
+        } else {
+            osm::PARKING_LEFT
+        },
+        if has_parking {
+            "parallel"
+        } else {
+            "no_parking"
+        },
+    );
+
+    // Maybe fold back into "both"
+    if tags.contains_key(osm::PARKING_LEFT)
+        && tags.get(osm::PARKING_LEFT) == tags.get(osm::PARKING_RIGHT)
+    {
+        let value = tags.remove(osm::PARKING_LEFT).unwrap();
+        tags.remove(osm::PARKING_RIGHT).unwrap();
+        tags.insert(osm::PARKING_BOTH, value);
+    }
+}
+
+// Translate the price and time limit into the tags that map_model understands. Anything already
+// in OSM wins.
+fn set_parking_rules(tags: &mut Tags, prefix: &str, s: &ExtraShape, source: &ParkingShapes) {
+    let charge = format!("{}charge", prefix);
+    let maxstay = format!("{}maxstay", prefix);
+    if tags.contains_key(&charge) || tags.contains_key(&maxstay) {
+        return;
+    }
+    if let Some(dollars) = get_number(s, &source.price_attribute) {
+        tags.insert(charge, format!("{:.2}/hour", dollars));
+    }
+    if let Some(mins) = get_number(s, &source.max_stay_attribute) {
+        if mins > 0.0 {
+            tags.insert(maxstay, format!("{} minutes", mins));
+        }
+    }
+    if let Some(hours) = source
+        .hours_attribute
+        .as_ref()
+        .and_then(|key| s.attributes.get(key))
+    {
+        tags.insert(
+            format!("{}fee:conditional", prefix),
+            format!("yes @ ({})", hours),
+        );
+    }
+}
+
+fn get_number(s: &ExtraShape, attribute: &Option<String>) -> Option<f64> {
+    s.attributes
+        .get(attribute.as_ref()?)?
+        .trim()
+        .trim_start_matches('$')
+        .parse::<f64>()
+        .ok()
 }
 
 fn use_offstreet_parking(map: &mut RawMap, path: String, timer: &mut Timer) {
@@ -146,7 +249,7 @@ fn use_offstreet_parking(map: &mut RawMap, path: String, timer: &mut Timer) {
     }
 
     // TODO Another function just to use ?. Try blocks would rock.
-    let mut handle_shape: Box<dyn FnMut(kml::ExtraShape) -> Option<()>> = Box::new(|s| {
+    let mut handle_shape: Box<dyn FnMut(ExtraShape) -> Option<()>> = Box::new(|s| {
         assert_eq!(s.points.len(), 1);
         let pt = Pt2D::from_gps(s.points[0], &map.gps_bounds);
         let (id, _) = closest.closest_pt(pt, Distance::meters(50.0))?;
@@ -184,6 +287,82 @@ fn use_offstreet_parking(map: &mut RawMap, path: String, timer: &mut Timer) {
     timer.stop("match offstreet parking points");
 }
 
+// Like use_offstreet_parking, but for any source of points. Points can also land in parking lots.
+fn use_parking_points(map: &mut RawMap, source: &ParkingShapes, timer: &mut Timer) {
+    timer.start("match parking points");
+    let shapes: ExtraShapes = abstutil::read_binary(source.path.clone(), timer);
+
+    let mut closest_bldg: FindClosest<osm::OsmID> = FindClosest::new(&map.gps_bounds.to_bounds());
+    for (id, b) in &map.buildings {
+        closest_bldg.add(*id, b.polygon.points());
+    }
+    let mut closest_lot: FindClosest<usize> = FindClosest::new(&map.gps_bounds.to_bounds());
+    for (idx, lot) in map.parking_lots.iter().enumerate() {
+        closest_lot.add(idx, lot.polygon.points());
+    }
+
+    let mut garages = 0;
+    let mut lots = 0;
+    for s in shapes.shapes.into_iter() {
+        if s.points.len() != 1 {
+            continue;
+        }
+        let capacity = match get_number(&s, &source.capacity_attribute) {
+            // Why's it listed?
+            Some(n) if n >= 1.0 => n as usize,
+            _ => continue,
+        };
+        let pt = Pt2D::from_gps(s.points[0], &map.gps_bounds);
+
+        if let Some(idx) = closest_lot
+            .all_close_pts(pt, Distance::meters(50.0))
+            .into_iter()
+            .map(|(idx, _, _)| idx)
+            .find(|idx| map.parking_lots[*idx].polygon.contains_pt(pt))
+        {
+            let tags = &mut map.parking_lots[idx].osm_tags;
+            tags.insert("capacity", capacity.to_string());
+            set_parking_rules(tags, "", &s, source);
+            lots += 1;
+            continue;
+        }
+
+        if let Some(id) = closest_bldg
+            .all_close_pts(pt, Distance::meters(50.0))
+            .into_iter()
+            .map(|(id, _, _)| id)
+            .find(|id| map.buildings[id].polygon.contains_pt(pt))
+        {
+            let name = source
+                .name_attribute
+                .as_ref()
+                .and_then(|key| s.attributes.get(key))
+                .cloned()
+                .unwrap_or_else(|| "public parking".to_string());
+            let bldg = map.buildings.get_mut(&id).unwrap();
+            if let Some(old_name) = bldg.public_garage_name.take() {
+                timer.warn(format!(
+                    "Two parking points apply to {}: {} @ {}, and {} @ {}",
+                    id, bldg.num_parking_spots, old_name, capacity, name
+                ));
+                bldg.public_garage_name = Some(format!("{} and {}", old_name, name));
+                bldg.num_parking_spots += capacity;
+            } else {
+                bldg.public_garage_name = Some(name);
+                bldg.num_parking_spots = capacity;
+            }
+            garages += 1;
+        }
+    }
+    timer.note(format!(
+        "Matched {} parking points from {} to garages and {} to parking lots",
+        abstutil::prettyprint_usize(garages),
+        source.path,
+        abstutil::prettyprint_usize(lots)
+    ));
+    timer.stop("match parking points");
+}
+
 fn apply_private_offstreet_parking(map: &mut RawMap, policy: &PrivateOffstreetParking) {
     match policy {
         PrivateOffstreetParking::FixedPerBldg(n) => {
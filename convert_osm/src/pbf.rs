@@ -0,0 +1,1340 @@
+use crate::reader::{skip_tag, Document, Node, Relation, Way};
+use abstutil::{prettyprint_usize, Tags, Timer};
+use byteorder::{BigEndian, ReadBytesExt};
+use flate2::read::ZlibDecoder;
+use geom::{GPSBounds, LonLat, Pt2D};
+use map_model::osm::{NodeID, OsmID, RelationID, WayID};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read};
+
+// Streams an .osm.pbf file (https://wiki.openstreetmap.org/wiki/PBF_Format) one block at a time,
+// so the whole file never has to be in memory. If a clipping polygon is provided, a first pass
+// finds everything inside of it, matching `osmconvert -B=clip.poly --complete-ways`: ways with
+// any node inside the polygon are kept whole, and relations are kept if any of their members
+// are. The second pass only builds the objects that survive.
+//
+// Clipping a whole state or country takes a while, so what's kept can be cached and reused until
+// the .pbf or the polygon changes.
+//
+// Just enough protobuf decoding is hand-rolled here to read the OSM schema; nothing else uses
+// protobuf.
+
+// Limits from the spec
+const MAX_BLOB_HEADER_SIZE: usize = 64 * 1024;
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+pub fn read(
+    path: &str,
+    input_gps_bounds: &GPSBounds,
+    clip: Option<&Vec<LonLat>>,
+    cache: Option<String>,
+    timer: &mut Timer,
+) -> Result<Document, Box<dyn Error>> {
+    let mut gps_bounds = input_gps_bounds.clone();
+    if gps_bounds == GPSBounds::new() {
+        if let Some(b) = read_header_bbox(path)? {
+            gps_bounds = b;
+        }
+    }
+
+    let extract = match clip {
+        Some(pts) => {
+            let clip = Clip {
+                bounds: GPSBounds::from(pts.clone()),
+                pts: pts.clone(),
+            };
+            let cached = cache
+                .as_ref()
+                .and_then(|c| read_cache(c, path, &clip, timer));
+            match cached {
+                Some(extract) => extract,
+                None => {
+                    let extract = clip_extract(path, clip, timer)?;
+                    if let Some(cache) = cache {
+                        abstutil::write_binary(cache, &extract);
+                    }
+                    extract
+                }
+            }
+        }
+        None => {
+            timer.start(format!("read {}", path));
+            let extract = read_extract(path, None, Vec::new())?;
+            timer.stop(format!("read {}", path));
+            extract
+        }
+    };
+
+    if gps_bounds == GPSBounds::new() {
+        if extract.clip.is_empty() {
+            timer.warn(
+                "No clipping polygon provided and the .pbf is missing a bbox, so figuring out \
+                 the bounds manually."
+                    .to_string(),
+            );
+        }
+        for (gps, _) in extract.nodes.values() {
+            gps_bounds.update(*gps);
+        }
+    }
+
+    let mut doc = Document {
+        gps_bounds,
+        nodes: BTreeMap::new(),
+        ways: BTreeMap::new(),
+        relations: extract.relations,
+    };
+    for (id, (gps, tags)) in extract.nodes {
+        doc.nodes.insert(
+            id,
+            Node {
+                pt: Pt2D::from_gps(gps, &doc.gps_bounds),
+                tags,
+            },
+        );
+    }
+    for (id, (nodes, tags)) in extract.ways {
+        let pts = nodes.iter().map(|n| doc.nodes[n].pt).collect();
+        doc.ways.insert(id, Way { nodes, pts, tags });
+    }
+    timer.note(format!(
+        "Found {} nodes, {} ways, {} relations",
+        prettyprint_usize(doc.nodes.len()),
+        prettyprint_usize(doc.ways.len()),
+        prettyprint_usize(doc.relations.len())
+    ));
+
+    Ok(doc)
+}
+
+// Everything kept from a .pbf, before coordinates are projected. This is what gets cached.
+#[derive(Serialize, Deserialize)]
+struct Extract {
+    // What this was clipped to; empty if nothing
+    clip: Vec<LonLat>,
+    nodes: BTreeMap<NodeID, (LonLat, Tags)>,
+    ways: BTreeMap<WayID, (Vec<NodeID>, Tags)>,
+    relations: BTreeMap<RelationID, Relation>,
+}
+
+struct Keep {
+    nodes: HashSet<NodeID>,
+    ways: HashSet<WayID>,
+    relations: HashSet<RelationID>,
+}
+
+// The first pass only remembers IDs, and the second only builds what's kept.
+fn clip_extract(path: &str, clip: Clip, timer: &mut Timer) -> Result<Extract, Box<dyn Error>> {
+    timer.start(format!("scan {}", path));
+    let mut inside_nodes = HashSet::new();
+    let mut keep = Keep {
+        nodes: HashSet::new(),
+        ways: HashSet::new(),
+        relations: HashSet::new(),
+    };
+    read_elements(path, &mut |elem| {
+        match elem {
+            Element::Node { id, gps, .. } => {
+                if clip.contains(gps) {
+                    inside_nodes.insert(id);
+                }
+            }
+            Element::Way { id, nodes, .. } => {
+                if nodes.iter().any(|n| inside_nodes.contains(n)) {
+                    keep.ways.insert(id);
+                    keep.nodes.extend(nodes);
+                }
+            }
+            Element::Relation { id, members, .. } => {
+                if members.iter().any(|(_, member)| match member {
+                    OsmID::Node(n) => inside_nodes.contains(n) || keep.nodes.contains(n),
+                    OsmID::Way(w) => keep.ways.contains(w),
+                    OsmID::Relation(r) => keep.relations.contains(r),
+                }) {
+                    keep.relations.insert(id);
+                }
+            }
+        }
+        Ok(())
+    })?;
+    // Nodes inside the polygon but not part of any way (like amenities) are kept too.
+    keep.nodes.extend(inside_nodes);
+    timer.stop(format!("scan {}", path));
+
+    timer.start(format!("read {}", path));
+    let extract = read_extract(path, Some(&keep), clip.pts)?;
+    timer.stop(format!("read {}", path));
+    Ok(extract)
+}
+
+// If there's nothing to keep, everything is.
+fn read_extract(
+    path: &str,
+    keep: Option<&Keep>,
+    clip: Vec<LonLat>,
+) -> Result<Extract, Box<dyn Error>> {
+    let mut extract = Extract {
+        clip,
+        nodes: BTreeMap::new(),
+        ways: BTreeMap::new(),
+        relations: BTreeMap::new(),
+    };
+    read_elements(path, &mut |elem| {
+        match elem {
+            Element::Node { id, gps, tags } => {
+                if !keep.map(|k| k.nodes.contains(&id)).unwrap_or(true) {
+                    return Ok(());
+                }
+                if extract.nodes.contains_key(&id) {
+                    return Err(format!("Duplicate {}, your .pbf is corrupt", id).into());
+                }
+                extract.nodes.insert(id, (gps, make_tags(tags)));
+            }
+            Element::Way { id, nodes, tags } => {
+                if !keep.map(|k| k.ways.contains(&id)).unwrap_or(true) {
+                    return Ok(());
+                }
+                if extract.ways.contains_key(&id) {
+                    return Err(format!("Duplicate {}, your .pbf is corrupt", id).into());
+                }
+                // Just skip missing nodes
+                let nodes: Vec<NodeID> = nodes
+                    .into_iter()
+                    .filter(|n| extract.nodes.contains_key(n))
+                    .collect();
+                if !nodes.is_empty() {
+                    extract.ways.insert(id, (nodes, make_tags(tags)));
+                }
+            }
+            Element::Relation { id, members, tags } => {
+                if !keep.map(|k| k.relations.contains(&id)).unwrap_or(true) {
+                    return Ok(());
+                }
+                if extract.relations.contains_key(&id) {
+                    return Err(format!("Duplicate {}, your .pbf is corrupt", id).into());
+                }
+                let members = members
+                    .into_iter()
+                    .filter(|(_, member)| match member {
+                        OsmID::Node(n) => extract.nodes.contains_key(n),
+                        OsmID::Way(w) => extract.ways.contains_key(w),
+                        OsmID::Relation(r) => extract.relations.contains_key(r),
+                    })
+                    .map(|(role, member)| (role.to_string(), member))
+                    .collect();
+                extract.relations.insert(
+                    id,
+                    Relation {
+                        tags: make_tags(tags),
+                        members,
+                    },
+                );
+            }
+        }
+        Ok(())
+    })?;
+    Ok(extract)
+}
+
+// Only use the cache if it was clipped to the same polygon after the .pbf last changed.
+fn read_cache(cache: &str, path: &str, clip: &Clip, timer: &mut Timer) -> Option<Extract> {
+    let modified = |p: &str| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+    if modified(cache)? < modified(path)? {
+        return None;
+    }
+    let extract: Extract = abstutil::maybe_read_binary(cache.to_string(), timer).ok()?;
+    if extract.clip != clip.pts {
+        return None;
+    }
+    Some(extract)
+}
+
+fn make_tags(tags: Vec<(&str, &str)>) -> Tags {
+    let mut result = Tags::new(BTreeMap::new());
+    for (k, v) in tags {
+        if !skip_tag(k) {
+            result.insert(k, v);
+        }
+    }
+    result
+}
+
+struct Clip {
+    pts: Vec<LonLat>,
+    bounds: GPSBounds,
+}
+
+impl Clip {
+    // Ray casting; the polygon isn't necessarily convex.
+    fn contains(&self, pt: LonLat) -> bool {
+        if !self.bounds.contains(pt) {
+            return false;
+        }
+        let (x, y) = (pt.x(), pt.y());
+        let mut inside = false;
+        let mut j = self.pts.len() - 1;
+        for i in 0..self.pts.len() {
+            let (xi, yi) = (self.pts[i].x(), self.pts[i].y());
+            let (xj, yj) = (self.pts[j].x(), self.pts[j].y());
+            if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+}
+
+enum Element<'a> {
+    Node {
+        id: NodeID,
+        gps: LonLat,
+        tags: Vec<(&'a str, &'a str)>,
+    },
+    Way {
+        id: WayID,
+        nodes: Vec<NodeID>,
+        tags: Vec<(&'a str, &'a str)>,
+    },
+    Relation {
+        id: RelationID,
+        // Role, member
+        members: Vec<(&'a str, OsmID)>,
+        tags: Vec<(&'a str, &'a str)>,
+    },
+}
+
+// Calls the handler on every element, in file order.
+fn read_elements(
+    path: &str,
+    handler: &mut dyn FnMut(Element) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut file = BufReader::new(File::open(path)?);
+    while let Some((blob_type, data)) = read_blob(&mut file)? {
+        if blob_type == "OSMData" {
+            read_primitive_block(&data, handler)?;
+        }
+    }
+    Ok(())
+}
+
+// Only looks at the first blob.
+fn read_header_bbox(path: &str) -> Result<Option<GPSBounds>, Box<dyn Error>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let (blob_type, data) = match read_blob(&mut file)? {
+        Some(pair) => pair,
+        None => {
+            return Err(format!("{} is empty", path).into());
+        }
+    };
+    if blob_type != "OSMHeader" {
+        return Err(format!("{} doesn't start with an OSMHeader", path).into());
+    }
+
+    let mut bbox = None;
+    let mut msg = Message::new(&data);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => {
+                // left, right, top, bottom in nanodegrees
+                let mut edges = [0; 4];
+                let mut inner = Message::new(value.bytes()?);
+                while let Some((field, value)) = inner.next_field()? {
+                    if (1..=4).contains(&field) {
+                        edges[field as usize - 1] = zigzag(value.varint()?);
+                    }
+                }
+                let mut b = GPSBounds::new();
+                b.update(LonLat::new(1e-9 * edges[0] as f64, 1e-9 * edges[3] as f64));
+                b.update(LonLat::new(1e-9 * edges[1] as f64, 1e-9 * edges[2] as f64));
+                bbox = Some(b);
+            }
+            4 => {
+                let feature = std::str::from_utf8(value.bytes()?)?;
+                if feature != "OsmSchema-V0.6" && feature != "DenseNodes" {
+                    return Err(format!("{} requires unsupported feature {}", path, feature).into());
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(bbox)
+}
+
+// Returns the blob type and the decompressed contents, or None at the end of the file.
+fn read_blob(file: &mut BufReader<File>) -> Result<Option<(String, Vec<u8>)>, Box<dyn Error>> {
+    let header_size = match file.read_u32::<BigEndian>() {
+        Ok(x) => x as usize,
+        Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
+            return Ok(None);
+        }
+        Err(err) => {
+            return Err(err.into());
+        }
+    };
+    if header_size > MAX_BLOB_HEADER_SIZE {
+        return Err(format!("BlobHeader is {} bytes, your .pbf is corrupt", header_size).into());
+    }
+    let mut buf = vec![0; header_size];
+    file.read_exact(&mut buf)?;
+
+    let mut blob_type = String::new();
+    let mut data_size = 0;
+    let mut msg = Message::new(&buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => {
+                blob_type = std::str::from_utf8(value.bytes()?)?.to_string();
+            }
+            3 => {
+                data_size = value.varint()? as usize;
+            }
+            _ => {}
+        }
+    }
+    if data_size > MAX_BLOB_SIZE {
+        return Err(format!("Blob is {} bytes, your .pbf is corrupt", data_size).into());
+    }
+    let mut buf = vec![0; data_size];
+    file.read_exact(&mut buf)?;
+
+    let mut raw_size = 0;
+    let mut data = None;
+    let mut msg = Message::new(&buf);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => {
+                data = Some(value.bytes()?.to_vec());
+            }
+            2 => {
+                raw_size = value.varint()? as usize;
+            }
+            3 => {
+                let mut out = Vec::with_capacity(raw_size.min(MAX_BLOB_SIZE));
+                ZlibDecoder::new(value.bytes()?).read_to_end(&mut out)?;
+                data = Some(out);
+            }
+            4 | 5 | 6 | 7 => {
+                return Err(format!("{} blob uses unsupported compression", blob_type).into());
+            }
+            _ => {}
+        }
+    }
+    match data {
+        Some(data) => Ok(Some((blob_type, data))),
+        None => Err(format!("{} blob has no data", blob_type).into()),
+    }
+}
+
+fn read_primitive_block(
+    data: &[u8],
+    handler: &mut dyn FnMut(Element) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    // The coordinate settings come after the groups in the encoding, so find everything first.
+    let mut strings = Vec::new();
+    let mut groups = Vec::new();
+    let mut granularity: i64 = 100;
+    let mut lat_offset: i64 = 0;
+    let mut lon_offset: i64 = 0;
+    let mut msg = Message::new(data);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => {
+                let mut table = Message::new(value.bytes()?);
+                while let Some((field, value)) = table.next_field()? {
+                    if field == 1 {
+                        strings.push(std::str::from_utf8(value.bytes()?)?);
+                    }
+                }
+            }
+            2 => {
+                groups.push(value.bytes()?);
+            }
+            17 => {
+                granularity = value.varint()? as i64;
+            }
+            19 => {
+                lat_offset = value.varint()? as i64;
+            }
+            20 => {
+                lon_offset = value.varint()? as i64;
+            }
+            _ => {}
+        }
+    }
+    let gps = |lon: i64, lat: i64| {
+        LonLat::new(
+            1e-9 * (lon_offset + granularity * lon) as f64,
+            1e-9 * (lat_offset + granularity * lat) as f64,
+        )
+    };
+
+    for group in groups {
+        let mut msg = Message::new(group);
+        while let Some((field, value)) = msg.next_field()? {
+            match field {
+                1 => {
+                    let (mut id, mut lon, mut lat) = (0, 0, 0);
+                    let (mut keys, mut vals) = (Vec::new(), Vec::new());
+                    let mut node = Message::new(value.bytes()?);
+                    while let Some((field, value)) = node.next_field()? {
+                        match field {
+                            1 => {
+                                id = zigzag(value.varint()?);
+                            }
+                            2 => {
+                                keys.extend(value.packed()?);
+                            }
+                            3 => {
+                                vals.extend(value.packed()?);
+                            }
+                            8 => {
+                                lat = zigzag(value.varint()?);
+                            }
+                            9 => {
+                                lon = zigzag(value.varint()?);
+                            }
+                            _ => {}
+                        }
+                    }
+                    handler(Element::Node {
+                        id: NodeID(id),
+                        gps: gps(lon, lat),
+                        tags: lookup_tags(&strings, &keys, &vals)?,
+                    })?;
+                }
+                2 => {
+                    read_dense_nodes(value.bytes()?, &strings, &gps, handler)?;
+                }
+                3 => {
+                    let mut id = 0;
+                    let (mut keys, mut vals, mut refs) = (Vec::new(), Vec::new(), Vec::new());
+                    let mut way = Message::new(value.bytes()?);
+                    while let Some((field, value)) = way.next_field()? {
+                        match field {
+                            1 => {
+                                id = value.varint()? as i64;
+                            }
+                            2 => {
+                                keys.extend(value.packed()?);
+                            }
+                            3 => {
+                                vals.extend(value.packed()?);
+                            }
+                            8 => {
+                                refs.extend(value.packed()?);
+                            }
+                            _ => {}
+                        }
+                    }
+                    handler(Element::Way {
+                        id: WayID(id),
+                        nodes: delta_decode(&refs).into_iter().map(NodeID).collect(),
+                        tags: lookup_tags(&strings, &keys, &vals)?,
+                    })?;
+                }
+                4 => {
+                    let mut id = 0;
+                    let (mut keys, mut vals) = (Vec::new(), Vec::new());
+                    let (mut roles, mut member_ids, mut types) =
+                        (Vec::new(), Vec::new(), Vec::new());
+                    let mut relation = Message::new(value.bytes()?);
+                    while let Some((field, value)) = relation.next_field()? {
+                        match field {
+                            1 => {
+                                id = value.varint()? as i64;
+                            }
+                            2 => {
+                                keys.extend(value.packed()?);
+                            }
+                            3 => {
+                                vals.extend(value.packed()?);
+                            }
+                            8 => {
+                                roles.extend(value.packed()?);
+                            }
+                            9 => {
+                                member_ids.extend(value.packed()?);
+                            }
+                            10 => {
+                                types.extend(value.packed()?);
+                            }
+                            _ => {}
+                        }
+                    }
+                    if roles.len() != member_ids.len() || roles.len() != types.len() {
+                        return Err(format!(
+                            "r{} has mismatched members, your .pbf is corrupt",
+                            id
+                        )
+                        .into());
+                    }
+                    let mut members = Vec::new();
+                    for ((role, member), member_type) in
+                        roles.into_iter().zip(delta_decode(&member_ids)).zip(types)
+                    {
+                        let member = match member_type {
+                            0 => OsmID::Node(NodeID(member)),
+                            1 => OsmID::Way(WayID(member)),
+                            2 => OsmID::Relation(RelationID(member)),
+                            _ => continue,
+                        };
+                        members.push((lookup_string(&strings, role)?, member));
+                    }
+                    handler(Element::Relation {
+                        id: RelationID(id),
+                        members,
+                        tags: lookup_tags(&strings, &keys, &vals)?,
+                    })?;
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_dense_nodes(
+    data: &[u8],
+    strings: &[&str],
+    gps: &dyn Fn(i64, i64) -> LonLat,
+    handler: &mut dyn FnMut(Element) -> Result<(), Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    let (mut ids, mut lats, mut lons, mut keys_vals) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let mut msg = Message::new(data);
+    while let Some((field, value)) = msg.next_field()? {
+        match field {
+            1 => {
+                ids.extend(value.packed()?);
+            }
+            8 => {
+                lats.extend(value.packed()?);
+            }
+            9 => {
+                lons.extend(value.packed()?);
+            }
+            10 => {
+                keys_vals.extend(value.packed()?);
+            }
+            _ => {}
+        }
+    }
+    if ids.len() != lats.len() || ids.len() != lons.len() {
+        return Err("DenseNodes has mismatched lengths, your .pbf is corrupt".into());
+    }
+
+    // Tags for each node are listed as key, value, key, value, ..., 0. If no node has tags, the
+    // list is just empty.
+    let mut keys_vals = keys_vals.into_iter();
+    for ((id, lat), lon) in delta_decode(&ids)
+        .into_iter()
+        .zip(delta_decode(&lats))
+        .zip(delta_decode(&lons))
+    {
+        let mut tags = Vec::new();
+        while let Some(k) = keys_vals.next() {
+            if k == 0 {
+                break;
+            }
+            let v = keys_vals
+                .next()
+                .ok_or("DenseNodes has a key without a value, your .pbf is corrupt")?;
+            tags.push((lookup_string(strings, k)?, lookup_string(strings, v)?));
+        }
+        handler(Element::Node {
+            id: NodeID(id),
+            gps: gps(lon, lat),
+            tags,
+        })?;
+    }
+    Ok(())
+}
+
+fn lookup_string<'a>(strings: &[&'a str], idx: u64) -> Result<&'a str, Box<dyn Error>> {
+    strings
+        .get(idx as usize)
+        .cloned()
+        .ok_or_else(|| format!("String {} is missing, your .pbf is corrupt", idx).into())
+}
+
+fn lookup_tags<'a>(
+    strings: &[&'a str],
+    keys: &[u64],
+    vals: &[u64],
+) -> Result<Vec<(&'a str, &'a str)>, Box<dyn Error>> {
+    if keys.len() != vals.len() {
+        return Err("Mismatched tag keys and values, your .pbf is corrupt".into());
+    }
+    let mut tags = Vec::new();
+    for (k, v) in keys.iter().zip(vals.iter()) {
+        tags.push((lookup_string(strings, *k)?, lookup_string(strings, *v)?));
+    }
+    Ok(tags)
+}
+
+// Packed sint64s are stored as differences from the previous value.
+fn delta_decode(values: &[u64]) -> Vec<i64> {
+    let mut result = Vec::with_capacity(values.len());
+    let mut last = 0;
+    for x in values {
+        last += zigzag(*x);
+        result.push(last);
+    }
+    result
+}
+
+fn zigzag(x: u64) -> i64 {
+    ((x >> 1) as i64) ^ -((x & 1) as i64)
+}
+
+// A minimal protobuf wire format reader. Fields are returned in encoding order.
+struct Message<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    // Fixed-width 32 and 64 bit values aren't used by the OSM schema; they're just skipped.
+    Fixed,
+}
+
+impl<'a> Message<'a> {
+    fn new(buf: &'a [u8]) -> Message<'a> {
+        Message { buf, pos: 0 }
+    }
+
+    fn next_field(&mut self) -> Result<Option<(u64, Value<'a>)>, Box<dyn Error>> {
+        if self.pos == self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 0x7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.skip(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                let start = self.pos;
+                self.skip(len)?;
+                Value::Bytes(&self.buf[start..self.pos])
+            }
+            5 => {
+                self.skip(4)?;
+                Value::Fixed
+            }
+            wire_type => {
+                return Err(format!("Unsupported protobuf wire type {}", wire_type).into());
+            }
+        };
+        Ok(Some((key >> 3, value)))
+    }
+
+    fn varint(&mut self) -> Result<u64, Box<dyn Error>> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *self
+                .buf
+                .get(self.pos)
+                .ok_or("Truncated protobuf varint, your .pbf is corrupt")?;
+            self.pos += 1;
+            if shift < 64 {
+                result |= ((byte & 0x7f) as u64) << shift;
+            }
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), Box<dyn Error>> {
+        if self.pos + len > self.buf.len() {
+            return Err("Truncated protobuf message, your .pbf is corrupt".into());
+        }
+        self.pos += len;
+        Ok(())
+    }
+}
+
+impl<'a> Value<'a> {
+    fn varint(&self) -> Result<u64, Box<dyn Error>> {
+        match self {
+            Value::Varint(x) => Ok(*x),
+            _ => Err("Expected a protobuf varint, your .pbf is corrupt".into()),
+        }
+    }
+
+    fn bytes(&self) -> Result<&'a [u8], Box<dyn Error>> {
+        match self {
+            Value::Bytes(x) => Ok(x),
+            _ => Err("Expected protobuf bytes, your .pbf is corrupt".into()),
+        }
+    }
+
+    // Repeated varints are usually packed, but a writer is allowed to emit them one at a time.
+    fn packed(&self) -> Result<Vec<u64>, Box<dyn Error>> {
+        match self {
+            Value::Varint(x) => Ok(vec![*x]),
+            Value::Bytes(buf) => {
+                let mut msg = Message::new(buf);
+                let mut result = Vec::new();
+                while msg.pos < buf.len() {
+                    result.push(msg.varint()?);
+                }
+                Ok(result)
+            }
+            Value::Fixed => Err("Expected packed protobuf varints, your .pbf is corrupt".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use geom::Distance;
+    use std::io::Write;
+
+    // Just enough of a writer to build small fixtures. Coordinates use the default granularity of
+    // 100 nanodegrees.
+    struct Fixture {
+        bbox: Option<(f64, f64, f64, f64)>,
+        // ID, lon, lat, tags
+        nodes: Vec<(i64, f64, f64, Vec<(&'static str, &'static str)>)>,
+        dense_nodes: Vec<(i64, f64, f64, Vec<(&'static str, &'static str)>)>,
+        ways: Vec<(i64, Vec<i64>, Vec<(&'static str, &'static str)>)>,
+        // ID, (role, member), tags
+        relations: Vec<(
+            i64,
+            Vec<(&'static str, OsmID)>,
+            Vec<(&'static str, &'static str)>,
+        )>,
+    }
+
+    fn varint(out: &mut Vec<u8>, mut x: u64) {
+        while x >= 0x80 {
+            out.push((x as u8) | 0x80);
+            x >>= 7;
+        }
+        out.push(x as u8);
+    }
+
+    fn put_varint(out: &mut Vec<u8>, field: u64, x: u64) {
+        varint(out, field << 3);
+        varint(out, x);
+    }
+
+    fn put_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+        varint(out, (field << 3) | 2);
+        varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    fn put_packed(out: &mut Vec<u8>, field: u64, values: &[u64]) {
+        let mut buf = Vec::new();
+        for x in values {
+            varint(&mut buf, *x);
+        }
+        put_bytes(out, field, &buf);
+    }
+
+    fn encode_zigzag(x: i64) -> u64 {
+        ((x << 1) ^ (x >> 63)) as u64
+    }
+
+    fn delta_encode(values: &[i64]) -> Vec<u64> {
+        let mut last = 0;
+        values
+            .iter()
+            .map(|x| {
+                let delta = x - last;
+                last = *x;
+                encode_zigzag(delta)
+            })
+            .collect()
+    }
+
+    // In units of the granularity
+    fn nanodegrees(x: f64) -> i64 {
+        (x * 1e7).round() as i64
+    }
+
+    // What the reader should turn a node's coordinates into
+    fn node_gps(lon: f64, lat: f64) -> LonLat {
+        LonLat::new(
+            1e-9 * (100 * nanodegrees(lon)) as f64,
+            1e-9 * (100 * nanodegrees(lat)) as f64,
+        )
+    }
+
+    // What the reader should turn the bbox in the header into
+    fn bbox_gps(lon: f64, lat: f64) -> LonLat {
+        LonLat::new(
+            1e-9 * (lon * 1e9).round() as i64 as f64,
+            1e-9 * (lat * 1e9).round() as i64 as f64,
+        )
+    }
+
+    fn put_blob(out: &mut Vec<u8>, blob_type: &str, data: &[u8], compress: bool) {
+        let mut blob = Vec::new();
+        if compress {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).unwrap();
+            put_varint(&mut blob, 2, data.len() as u64);
+            put_bytes(&mut blob, 3, &encoder.finish().unwrap());
+        } else {
+            put_bytes(&mut blob, 1, data);
+        }
+
+        let mut header = Vec::new();
+        put_bytes(&mut header, 1, blob_type.as_bytes());
+        put_varint(&mut header, 3, blob.len() as u64);
+
+        out.extend_from_slice(&(header.len() as u32).to_be_bytes());
+        out.extend(header);
+        out.extend(blob);
+    }
+
+    fn intern(strings: &mut Vec<String>, s: &str) -> u64 {
+        if let Some(idx) = strings.iter().position(|x| x == s) {
+            return idx as u64;
+        }
+        strings.push(s.to_string());
+        (strings.len() - 1) as u64
+    }
+
+    fn tag_indices(strings: &mut Vec<String>, tags: &Vec<(&str, &str)>) -> (Vec<u64>, Vec<u64>) {
+        tags.iter()
+            .map(|(k, v)| (intern(strings, k), intern(strings, v)))
+            .unzip()
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            Fixture {
+                bbox: None,
+                nodes: Vec::new(),
+                dense_nodes: Vec::new(),
+                ways: Vec::new(),
+                relations: Vec::new(),
+            }
+        }
+
+        fn write(&self, name: &str) -> String {
+            // The first entry is always empty, since 0 delimits tags in dense nodes
+            let mut strings = vec![String::new()];
+
+            let mut group = Vec::new();
+            for (id, lon, lat, tags) in &self.nodes {
+                let (keys, vals) = tag_indices(&mut strings, tags);
+                let mut node = Vec::new();
+                put_varint(&mut node, 1, encode_zigzag(*id));
+                put_packed(&mut node, 2, &keys);
+                put_packed(&mut node, 3, &vals);
+                put_varint(&mut node, 8, encode_zigzag(nanodegrees(*lat)));
+                put_varint(&mut node, 9, encode_zigzag(nanodegrees(*lon)));
+                put_bytes(&mut group, 1, &node);
+            }
+            if !self.dense_nodes.is_empty() {
+                let mut keys_vals = Vec::new();
+                for (_, _, _, tags) in &self.dense_nodes {
+                    let (keys, vals) = tag_indices(&mut strings, tags);
+                    for (k, v) in keys.into_iter().zip(vals) {
+                        keys_vals.push(k);
+                        keys_vals.push(v);
+                    }
+                    keys_vals.push(0);
+                }
+                let ids: Vec<i64> = self.dense_nodes.iter().map(|n| n.0).collect();
+                let lons: Vec<i64> = self.dense_nodes.iter().map(|n| nanodegrees(n.1)).collect();
+                let lats: Vec<i64> = self.dense_nodes.iter().map(|n| nanodegrees(n.2)).collect();
+                let mut dense = Vec::new();
+                put_packed(&mut dense, 1, &delta_encode(&ids));
+                put_packed(&mut dense, 8, &delta_encode(&lats));
+                put_packed(&mut dense, 9, &delta_encode(&lons));
+                put_packed(&mut dense, 10, &keys_vals);
+                put_bytes(&mut group, 2, &dense);
+            }
+            for (id, refs, tags) in &self.ways {
+                let (keys, vals) = tag_indices(&mut strings, tags);
+                let mut way = Vec::new();
+                put_varint(&mut way, 1, *id as u64);
+                put_packed(&mut way, 2, &keys);
+                put_packed(&mut way, 3, &vals);
+                put_packed(&mut way, 8, &delta_encode(refs));
+                put_bytes(&mut group, 3, &way);
+            }
+            for (id, members, tags) in &self.relations {
+                let (keys, vals) = tag_indices(&mut strings, tags);
+                let mut roles = Vec::new();
+                let mut ids = Vec::new();
+                let mut types = Vec::new();
+                for (role, member) in members {
+                    roles.push(intern(&mut strings, role));
+                    let (member_id, member_type) = match member {
+                        OsmID::Node(n) => (n.0, 0),
+                        OsmID::Way(w) => (w.0, 1),
+                        OsmID::Relation(r) => (r.0, 2),
+                    };
+                    ids.push(member_id);
+                    types.push(member_type);
+                }
+                let mut relation = Vec::new();
+                put_varint(&mut relation, 1, *id as u64);
+                put_packed(&mut relation, 2, &keys);
+                put_packed(&mut relation, 3, &vals);
+                put_packed(&mut relation, 8, &roles);
+                put_packed(&mut relation, 9, &delta_encode(&ids));
+                put_packed(&mut relation, 10, &types);
+                put_bytes(&mut group, 4, &relation);
+            }
+
+            let mut header = Vec::new();
+            if let Some((left, right, top, bottom)) = self.bbox {
+                let mut bbox = Vec::new();
+                for (field, x) in vec![(1, left), (2, right), (3, top), (4, bottom)] {
+                    put_varint(&mut bbox, field, encode_zigzag((x * 1e9).round() as i64));
+                }
+                put_bytes(&mut header, 1, &bbox);
+            }
+            put_bytes(&mut header, 4, b"OsmSchema-V0.6");
+            put_bytes(&mut header, 4, b"DenseNodes");
+
+            let mut table = Vec::new();
+            for s in &strings {
+                put_bytes(&mut table, 1, s.as_bytes());
+            }
+            let mut block = Vec::new();
+            put_bytes(&mut block, 1, &table);
+            put_bytes(&mut block, 2, &group);
+
+            let mut file = Vec::new();
+            put_blob(&mut file, "OSMHeader", &header, false);
+            put_blob(&mut file, "OSMData", &block, true);
+
+            let path = std::env::temp_dir().join(format!("convert_osm_{}.osm.pbf", name));
+            std::fs::write(&path, file).unwrap();
+            path.to_str().unwrap().to_string()
+        }
+    }
+
+    // A short street with a traffic signal on it, a building, and a route relation
+    fn street() -> Fixture {
+        let mut f = Fixture::new();
+        f.nodes = vec![
+            (1, -122.30, 47.60, vec![]),
+            (2, -122.29, 47.60, vec![("highway", "traffic_signals")]),
+        ];
+        f.dense_nodes = vec![
+            (3, -122.28, 47.60, vec![]),
+            (
+                4,
+                -122.28,
+                47.61,
+                vec![("amenity", "cafe"), ("tiger:cfcc", "A41")],
+            ),
+            (5, -122.27, 47.61, vec![]),
+        ];
+        f.ways = vec![
+            (
+                10,
+                vec![1, 2, 3],
+                vec![("highway", "residential"), ("name", "Pine")],
+            ),
+            (11, vec![3, 4, 5, 3], vec![("building", "yes")]),
+        ];
+        f.relations = vec![(
+            20,
+            vec![
+                ("", OsmID::Way(WayID(10))),
+                ("stop", OsmID::Node(NodeID(2))),
+            ],
+            vec![("type", "route")],
+        )];
+        f
+    }
+
+    #[test]
+    fn test_read_everything() {
+        let mut f = street();
+        f.bbox = Some((-122.31, -122.26, 47.62, 47.59));
+        let path = f.write("everything");
+        let doc = read(
+            &path,
+            &GPSBounds::new(),
+            None,
+            None,
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+
+        // The bounds come from the header
+        assert_eq!(
+            doc.gps_bounds,
+            GPSBounds::from(vec![bbox_gps(-122.31, 47.59), bbox_gps(-122.26, 47.62)])
+        );
+
+        assert_eq!(doc.nodes.len(), 5);
+        assert!(doc.nodes[&NodeID(2)].tags.is("highway", "traffic_signals"));
+        // Tags from dense nodes are decoded, minus the ones nobody uses
+        assert!(doc.nodes[&NodeID(4)].tags.is("amenity", "cafe"));
+        assert!(!doc.nodes[&NodeID(4)].tags.contains_key("tiger:cfcc"));
+        assert!(doc.nodes[&NodeID(5)].tags.inner().is_empty());
+
+        let way = &doc.ways[&WayID(10)];
+        assert_eq!(way.nodes, vec![NodeID(1), NodeID(2), NodeID(3)]);
+        assert_eq!(
+            way.pts,
+            vec![
+                doc.nodes[&NodeID(1)].pt,
+                doc.nodes[&NodeID(2)].pt,
+                doc.nodes[&NodeID(3)].pt
+            ]
+        );
+        assert!(way.tags.is("name", "Pine"));
+        assert_eq!(doc.ways[&WayID(11)].nodes.len(), 4);
+
+        let relation = &doc.relations[&RelationID(20)];
+        assert!(relation.tags.is("type", "route"));
+        assert_eq!(
+            relation.members,
+            vec![
+                ("".to_string(), OsmID::Way(WayID(10))),
+                ("stop".to_string(), OsmID::Node(NodeID(2)))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_bounds_without_bbox() {
+        let path = street().write("no_bbox");
+        let doc = read(
+            &path,
+            &GPSBounds::new(),
+            None,
+            None,
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+        assert_eq!(
+            doc.gps_bounds,
+            GPSBounds::from(vec![node_gps(-122.30, 47.60), node_gps(-122.27, 47.61)])
+        );
+    }
+
+    #[test]
+    fn test_clip() {
+        let mut f = street();
+        // Nowhere near anything else
+        f.dense_nodes
+            .push((6, -122.20, 47.70, vec![("amenity", "bench")]));
+        f.dense_nodes.push((7, -122.19, 47.70, vec![]));
+        f.ways.push((12, vec![6, 7], vec![("highway", "footway")]));
+        f.relations.push((
+            21,
+            vec![("", OsmID::Way(WayID(12)))],
+            vec![("type", "route")],
+        ));
+        f.relations.push((
+            22,
+            vec![
+                ("", OsmID::Relation(RelationID(20))),
+                ("", OsmID::Node(NodeID(6))),
+            ],
+            vec![("type", "superroute")],
+        ));
+        let path = f.write("clip");
+
+        // Only covers the first two nodes of the street
+        let clip = vec![
+            LonLat::new(-122.305, 47.595),
+            LonLat::new(-122.285, 47.595),
+            LonLat::new(-122.285, 47.605),
+            LonLat::new(-122.305, 47.605),
+            LonLat::new(-122.305, 47.595),
+        ];
+        let doc = read(
+            &path,
+            &GPSBounds::new(),
+            Some(&clip),
+            None,
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+
+        // The street is kept whole, even though node 3 is outside. The building and the footway
+        // are entirely outside.
+        assert_eq!(
+            doc.nodes.keys().cloned().collect::<Vec<_>>(),
+            vec![NodeID(1), NodeID(2), NodeID(3)]
+        );
+        assert_eq!(
+            doc.ways.keys().cloned().collect::<Vec<_>>(),
+            vec![WayID(10)]
+        );
+        assert_eq!(doc.ways[&WayID(10)].pts.len(), 3);
+
+        // The superroute survives because the route does, but loses the member that's gone
+        assert_eq!(
+            doc.relations.keys().cloned().collect::<Vec<_>>(),
+            vec![RelationID(20), RelationID(22)]
+        );
+        assert_eq!(
+            doc.relations[&RelationID(22)].members,
+            vec![("".to_string(), OsmID::Relation(RelationID(20)))]
+        );
+
+        // Without a bbox, the bounds cover what was kept
+        assert_eq!(
+            doc.gps_bounds,
+            GPSBounds::from(vec![node_gps(-122.30, 47.60), node_gps(-122.28, 47.60)])
+        );
+    }
+
+    // Only covers the first two nodes of the street
+    fn clip_polygon() -> Vec<LonLat> {
+        vec![
+            LonLat::new(-122.305, 47.595),
+            LonLat::new(-122.285, 47.595),
+            LonLat::new(-122.285, 47.605),
+            LonLat::new(-122.305, 47.605),
+            LonLat::new(-122.305, 47.595),
+        ]
+    }
+
+    #[test]
+    fn test_clip_matches_xml() {
+        let clip = clip_polygon();
+        let gps_bounds = GPSBounds::from(clip.clone());
+        let pbf = read(
+            &street().write("clip_vs_xml"),
+            &gps_bounds,
+            Some(&clip),
+            None,
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+
+        // What osmconvert -B=clip.poly --complete-ways used to produce
+        let path = std::env::temp_dir().join("convert_osm_clip_vs_xml.osm");
+        std::fs::write(
+            &path,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<osm version="0.6">
+  <node id="1" lat="47.60" lon="-122.30"/>
+  <node id="2" lat="47.60" lon="-122.29">
+    <tag k="highway" v="traffic_signals"/>
+  </node>
+  <node id="3" lat="47.60" lon="-122.28"/>
+  <way id="10">
+    <nd ref="1"/>
+    <nd ref="2"/>
+    <nd ref="3"/>
+    <tag k="highway" v="residential"/>
+    <tag k="name" v="Pine"/>
+  </way>
+  <relation id="20">
+    <member type="way" ref="10" role=""/>
+    <member type="node" ref="2" role="stop"/>
+    <tag k="type" v="route"/>
+  </relation>
+</osm>"#,
+        )
+        .unwrap();
+        let xml = crate::reader::read(
+            path.to_str().unwrap(),
+            &gps_bounds,
+            None,
+            None,
+            &mut Timer::throwaway(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            pbf.nodes.keys().collect::<Vec<_>>(),
+            xml.nodes.keys().collect::<Vec<_>>()
+        );
+        for (id, node) in &pbf.nodes {
+            assert!(node.pt.approx_eq(xml.nodes[id].pt, Distance::meters(0.01)));
+            assert_eq!(node.tags, xml.nodes[id].tags);
+        }
+        assert_eq!(
+            pbf.ways.keys().collect::<Vec<_>>(),
+            xml.ways.keys().collect::<Vec<_>>()
+        );
+        for (id, way) in &pbf.ways {
+            assert_eq!(way.nodes, xml.ways[id].nodes);
+            assert_eq!(way.tags, xml.ways[id].tags);
+        }
+        assert_eq!(
+            pbf.relations.keys().collect::<Vec<_>>(),
+            xml.relations.keys().collect::<Vec<_>>()
+        );
+        for (id, relation) in &pbf.relations {
+            assert_eq!(relation.members, xml.relations[id].members);
+            assert_eq!(relation.tags, xml.relations[id].tags);
+        }
+    }
+
+    #[test]
+    fn test_cache() {
+        let path = street().write("cache");
+        let cache = std::env::temp_dir()
+            .join("convert_osm_cache.bin")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let _ = std::fs::remove_file(&cache);
+        let read_clipped = |clip: &Vec<LonLat>| {
+            read(
+                &path,
+                &GPSBounds::from(clip_polygon()),
+                Some(clip),
+                Some(cache.clone()),
+                &mut Timer::throwaway(),
+            )
+            .unwrap()
+        };
+
+        let clip = clip_polygon();
+        assert_eq!(read_clipped(&clip).nodes.len(), 3);
+        assert!(abstutil::file_exists(cache.clone()));
+        // Reused
+        assert_eq!(read_clipped(&clip).nodes.len(), 3);
+
+        // A different polygon doesn't use the old extract. This one also covers the building.
+        let bigger = vec![
+            LonLat::new(-122.305, 47.595),
+            LonLat::new(-122.265, 47.595),
+            LonLat::new(-122.265, 47.615),
+            LonLat::new(-122.305, 47.615),
+            LonLat::new(-122.305, 47.595),
+        ];
+        assert_eq!(read_clipped(&bigger).nodes.len(), 5);
+    }
+
+    #[test]
+    fn test_corrupt() {
+        let mut f = street();
+        f.nodes.push((1, -122.30, 47.60, vec![]));
+        let path = f.write("duplicate");
+        assert!(read(
+            &path,
+            &GPSBounds::new(),
+            None,
+            None,
+            &mut Timer::throwaway()
+        )
+        .is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use abstutil::{prettyprint_usize, slurp_file, Tags, Timer};
 use geom::{GPSBounds, LonLat, Pt2D};
 use map_model::osm::{NodeID, OsmID, RelationID, WayID};
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 use std::error::Error;
 
@@ -32,17 +33,26 @@ pub struct Way {
     pub tags: Tags,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Relation {
     pub tags: Tags,
     // Role, member
     pub members: Vec<(String, OsmID)>,
 }
 
+// The clipping polygon is only applied while reading .pbf files; .osm input should already be
+// clipped. If a cache path is given, what's inside the polygon is saved there and reused.
 pub fn read(
     path: &str,
     input_gps_bounds: &GPSBounds,
+    clip: Option<&Vec<LonLat>>,
+    cache: Option<String>,
     timer: &mut Timer,
 ) -> Result<Document, Box<dyn Error>> {
+    if path.ends_with(".pbf") {
+        return crate::pbf::read(path, input_gps_bounds, clip, cache, timer);
+    }
+
     timer.start(format!("read {}", path));
     let bytes = slurp_file(path)?;
     let raw_string = std::str::from_utf8(&bytes)?;
@@ -184,8 +194,7 @@ fn read_tags(obj: roxmltree::Node) -> Tags {
     for child in obj.children() {
         if child.tag_name().name() == "tag" {
             let key = child.attribute("k").unwrap();
-            // Filter out really useless data
-            if key.starts_with("tiger:") || key.starts_with("old_name:") {
+            if skip_tag(key) {
                 continue;
             }
             tags.insert(key, child.attribute("v").unwrap());
@@ -194,6 +203,11 @@ fn read_tags(obj: roxmltree::Node) -> Tags {
     tags
 }
 
+// Filter out really useless data
+pub(crate) fn skip_tag(key: &str) -> bool {
+    key.starts_with("tiger:") || key.starts_with("old_name:")
+}
+
 fn scrape_bounds(doc: &roxmltree::Document) -> GPSBounds {
     let mut b = GPSBounds::new();
     for obj in doc.descendants() {
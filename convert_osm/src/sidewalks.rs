@@ -0,0 +1,260 @@
+use abstutil::Timer;
+use geom::{Distance, FindClosest, PolyLine, Pt2D};
+use map_model::osm;
+use map_model::raw::{CrossingType, OriginalRoad, RawMap};
+use std::collections::BTreeMap;
+
+// Just used for matching sidewalks to different sides of a road.
+const DIRECTED_ROAD_THICKNESS: Distance = Distance::const_meters(2.5);
+// Separate sidewalks on wide roads can be drawn quite far from the center-line.
+const MAX_SIDEWALK_DIST: Distance = Distance::const_meters(15.0);
+const SIDEWALK_SAMPLE_EVERY: Distance = Distance::const_meters(5.0);
+// A side of the road has a sidewalk if separately mapped sidewalks run along at least this much of
+// it. Sidewalks wrap around corners and get cut off at driveways, so don't demand everything.
+const MIN_SIDEWALK_COVERAGE: f64 = 0.5;
+// highway=crossing nodes are on the road itself, and footway=crossing ways are matched by their
+// middle, which should be close.
+const MAX_CROSSING_DIST: Distance = Distance::const_meters(5.0);
+// The same crossing is often mapped as both a node and a way.
+const DUPLICATE_CROSSING_DIST: Distance = Distance::const_meters(3.0);
+
+// Figure out which sides of a road have sidewalks, using footway=sidewalk ways mapped separately.
+// Only roads tagged sidewalk=separate or with inferred sidewalks are changed.
+pub fn snap_separate_sidewalks(map: &mut RawMap, sidewalks: Vec<Vec<Pt2D>>, timer: &mut Timer) {
+    timer.start("snap separate sidewalks");
+
+    // Match sidewalks with the nearest road + side (true for right)
+    let mut closest: FindClosest<(OriginalRoad, bool)> =
+        FindClosest::new(&map.gps_bounds.to_bounds());
+    let mut sides: BTreeMap<(OriginalRoad, bool), PolyLine> = BTreeMap::new();
+    for (id, r) in &map.roads {
+        if r.is_light_rail() || r.is_footway() {
+            continue;
+        }
+        let center = match PolyLine::new(r.center_points.clone()) {
+            Ok(pl) => pl,
+            Err(_) => continue,
+        };
+        if let (Ok(right), Ok(left)) = (
+            center.shift_right(DIRECTED_ROAD_THICKNESS),
+            center.shift_left(DIRECTED_ROAD_THICKNESS),
+        ) {
+            closest.add((*id, true), right.points());
+            closest.add((*id, false), left.points());
+            sides.insert((*id, true), right);
+            sides.insert((*id, false), left);
+        }
+    }
+
+    // Sample points along each sidewalk, and add up how much of each side they cover.
+    let mut coverage: BTreeMap<(OriginalRoad, bool), Distance> = BTreeMap::new();
+    for pts in sidewalks {
+        let pl = match PolyLine::deduping_new(pts) {
+            Ok(pl) => pl,
+            Err(_) => continue,
+        };
+        let mut dist = SIDEWALK_SAMPLE_EVERY / 2.0;
+        while dist < pl.length() {
+            let (pt, angle) = pl.must_dist_along(dist);
+            dist += SIDEWALK_SAMPLE_EVERY;
+
+            if let Some((side, snapped)) = closest.closest_pt(pt, MAX_SIDEWALK_DIST) {
+                // Near intersections, the closest road might be a perpendicular one. Only count
+                // the sidewalk if it runs alongside the road.
+                if let Some((_, road_angle)) = sides[&side].dist_along_of_point(snapped) {
+                    if angle.approx_eq(road_angle, 30.0)
+                        || angle.approx_eq(road_angle.opposite(), 30.0)
+                    {
+                        *coverage.entry(side).or_insert(Distance::ZERO) += SIDEWALK_SAMPLE_EVERY;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut changed = 0;
+    for ((id, right), pl) in &sides {
+        // Handle both sides at once
+        if !right {
+            continue;
+        }
+        let covered = |side: bool| {
+            coverage
+                .get(&(*id, side))
+                .map(|dist| *dist >= pl.length() * MIN_SIDEWALK_COVERAGE)
+                .unwrap_or(false)
+        };
+        let (has_right, has_left) = (covered(true), covered(false));
+
+        let tags = &mut map.roads.get_mut(id).unwrap().osm_tags;
+        let separate = tags.is(osm::SIDEWALK, "separate")
+            || tags.is("sidewalk:both", "separate")
+            || tags.is("sidewalk:left", "separate")
+            || tags.is("sidewalk:right", "separate");
+        if !separate && !tags.contains_key(osm::INFERRED_SIDEWALKS) {
+            continue;
+        }
+        // If nothing matched, the sidewalks were probably clipped out or are too far away. Leave
+        // sidewalk=separate and inferred guesses alone.
+        let value = match (has_left, has_right) {
+            (true, true) => "both",
+            (true, false) => "left",
+            (false, true) => "right",
+            (false, false) => {
+                continue;
+            }
+        };
+        tags.insert(osm::SIDEWALK, value);
+        tags.remove(osm::INFERRED_SIDEWALKS);
+        changed += 1;
+    }
+    timer.note(format!(
+        "Used separately mapped sidewalks for {} roads",
+        abstutil::prettyprint_usize(changed)
+    ));
+
+    timer.stop("snap separate sidewalks");
+}
+
+// Attach highway=crossing nodes and footway=crossing ways to the road they cross.
+pub fn snap_crossings(map: &mut RawMap, crossings: Vec<(Pt2D, CrossingType)>, timer: &mut Timer) {
+    timer.start("snap crossings");
+
+    let mut closest: FindClosest<OriginalRoad> = FindClosest::new(&map.gps_bounds.to_bounds());
+    for (id, r) in &map.roads {
+        if r.is_light_rail() || r.is_footway() {
+            continue;
+        }
+        closest.add(*id, &r.center_points);
+    }
+
+    let mut matched = 0;
+    for (pt, crossing_type) in crossings {
+        if let Some((id, snapped)) = closest.closest_pt(pt, MAX_CROSSING_DIST) {
+            let road = map.roads.get_mut(&id).unwrap();
+            // Nodes come first, and they have more specific tags, so keep those.
+            if road
+                .crossings
+                .iter()
+                .any(|(other, _)| other.dist_to(snapped) < DUPLICATE_CROSSING_DIST)
+            {
+                continue;
+            }
+            road.crossings.push((snapped, crossing_type));
+            matched += 1;
+        }
+    }
+    timer.note(format!(
+        "Matched {} crossings to roads",
+        abstutil::prettyprint_usize(matched)
+    ));
+
+    timer.stop("snap crossings");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use map_model::Map;
+
+    fn line(pts: &[(f64, f64)]) -> Vec<Pt2D> {
+        pts.iter().map(|(x, y)| Pt2D::new(*x, *y)).collect()
+    }
+
+    #[test]
+    fn test_snap_separate_sidewalks() {
+        // In a 2x2 grid, the top road runs east, so its right side is south (+y). The left road
+        // runs south, so its right side is west (-x).
+        let mut map = RawMap::synthetic_grid(2, 2);
+        let top = OriginalRoad::new(1, (1, 2));
+        let left = OriginalRoad::new(2, (1, 3));
+        let bottom = OriginalRoad::new(4, (3, 4));
+        map.roads
+            .get_mut(&top)
+            .unwrap()
+            .osm_tags
+            .insert(osm::SIDEWALK, "separate");
+        {
+            let tags = &mut map.roads.get_mut(&left).unwrap().osm_tags;
+            tags.remove(osm::SIDEWALK);
+            tags.insert(osm::INFERRED_SIDEWALKS, "true");
+        }
+
+        snap_separate_sidewalks(
+            &mut map,
+            vec![
+                line(&[(110.0, 108.0), (290.0, 108.0)]),
+                line(&[(92.0, 110.0), (92.0, 290.0)]),
+                line(&[(108.0, 110.0), (108.0, 290.0)]),
+                // Not tagged separate, so this doesn't matter
+                line(&[(110.0, 292.0), (290.0, 292.0)]),
+                // Too far from anything
+                line(&[(110.0, 200.0), (290.0, 200.0)]),
+            ],
+            &mut Timer::throwaway(),
+        );
+
+        let tags = &map.roads[&top].osm_tags;
+        assert_eq!(tags.get(osm::SIDEWALK), Some(&"right".to_string()));
+        let tags = &map.roads[&left].osm_tags;
+        assert_eq!(tags.get(osm::SIDEWALK), Some(&"both".to_string()));
+        assert!(!tags.contains_key(osm::INFERRED_SIDEWALKS));
+        let tags = &map.roads[&bottom].osm_tags;
+        assert_eq!(tags.get(osm::SIDEWALK), Some(&"both".to_string()));
+    }
+
+    #[test]
+    fn test_sidewalk_too_short() {
+        // A sidewalk covering less than half of the road isn't enough to change anything.
+        let mut map = RawMap::synthetic_grid(2, 2);
+        let top = OriginalRoad::new(1, (1, 2));
+        map.roads
+            .get_mut(&top)
+            .unwrap()
+            .osm_tags
+            .insert(osm::SIDEWALK, "separate");
+
+        snap_separate_sidewalks(
+            &mut map,
+            vec![line(&[(110.0, 108.0), (160.0, 108.0)])],
+            &mut Timer::throwaway(),
+        );
+        assert_eq!(
+            map.roads[&top].osm_tags.get(osm::SIDEWALK),
+            Some(&"separate".to_string())
+        );
+    }
+
+    #[test]
+    fn test_snap_mid_block_crossing() {
+        let mut raw = RawMap::synthetic_grid(2, 2);
+        let top = OriginalRoad::new(1, (1, 2));
+        snap_crossings(
+            &mut raw,
+            vec![
+                (Pt2D::new(200.0, 103.0), CrossingType::Marked),
+                // The same crossing, also mapped as a way
+                (Pt2D::new(201.0, 98.0), CrossingType::Unmarked),
+                // Too far from any road
+                (Pt2D::new(200.0, 150.0), CrossingType::Signalized),
+            ],
+            &mut Timer::throwaway(),
+        );
+        let crossings = &raw.roads[&top].crossings;
+        assert_eq!(crossings.len(), 1);
+        assert!(crossings[0].0.dist_to(Pt2D::new(200.0, 100.0)) < Distance::meters(0.1));
+        assert_eq!(crossings[0].1, CrossingType::Marked);
+        assert!(raw
+            .roads
+            .iter()
+            .all(|(id, r)| *id == top || r.crossings.is_empty()));
+
+        let map = Map::create_from_raw(raw, false, &mut Timer::throwaway());
+        let road = map.get_r(map.find_r_by_osm_id(top).unwrap());
+        let crossings = road.mid_block_crossings();
+        assert_eq!(crossings.len(), 1);
+        assert_eq!(crossings[0].1, CrossingType::Marked);
+        let half = road.center_pts.length() / 2.0;
+        assert!((crossings[0].0 - half).abs() < Distance::meters(1.0));
+    }
+}
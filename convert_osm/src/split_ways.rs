@@ -3,6 +3,7 @@ use abstutil::{Counter, Timer};
 use geom::{Distance, HashablePt2D, Pt2D};
 use map_model::raw::{OriginalRoad, RawIntersection, RawMap};
 use map_model::{osm, IntersectionType, NamePerLanguage};
+use osm::{RelationID, WayID};
 use std::collections::HashMap;
 
 // Returns amenities and a mapping of all points to split road. (Some internal points on roads are
@@ -118,55 +119,7 @@ pub fn split_up_roads(
             .push((rt, to));
     }
 
-    // Resolve complicated turn restrictions (via a way). TODO Only handle via ways immediately
-    // connected to both roads, for now
-    let mut complicated_restrictions = Vec::new();
-    for (rel_osm, from_osm, via_osm, to_osm) in input.complicated_turn_restrictions {
-        let via_candidates: Vec<OriginalRoad> = map
-            .roads
-            .keys()
-            .filter(|r| r.osm_way_id == via_osm)
-            .cloned()
-            .collect();
-        if via_candidates.len() != 1 {
-            timer.warn(format!(
-                "Couldn't resolve turn restriction from way {} to way {} via way {}. Candidate \
-                 roads for via: {:?}. See {}",
-                from_osm, to_osm, via_osm, via_candidates, rel_osm
-            ));
-            continue;
-        }
-        let via = via_candidates[0];
-
-        let maybe_from = map
-            .roads_per_intersection(via.i1)
-            .into_iter()
-            .chain(map.roads_per_intersection(via.i2).into_iter())
-            .find(|r| r.osm_way_id == from_osm);
-        let maybe_to = map
-            .roads_per_intersection(via.i1)
-            .into_iter()
-            .chain(map.roads_per_intersection(via.i2).into_iter())
-            .find(|r| r.osm_way_id == to_osm);
-        match (maybe_from, maybe_to) {
-            (Some(from), Some(to)) => {
-                complicated_restrictions.push((from, via, to));
-            }
-            _ => {
-                timer.warn(format!(
-                    "Couldn't resolve turn restriction from {} to {} via {:?}",
-                    from_osm, to_osm, via
-                ));
-            }
-        }
-    }
-    for (from, via, to) in complicated_restrictions {
-        map.roads
-            .get_mut(&from)
-            .unwrap()
-            .complicated_turn_restrictions
-            .push((via, to));
-    }
+    resolve_complicated_turn_restrictions(map, input.complicated_turn_restrictions, timer);
 
     timer.start("match traffic signals to intersections");
     // Handle traffic signals tagged on incoming ways and not at intersections
@@ -214,3 +167,185 @@ fn dedupe_angles(pts: Vec<Pt2D>) -> Vec<Pt2D> {
     }
     result
 }
+
+// Resolve complicated turn restrictions (via one or more ways), dropping the ones that don't match
+// up with the split roads.
+fn resolve_complicated_turn_restrictions(
+    map: &mut RawMap,
+    input: Vec<(RelationID, WayID, Vec<WayID>, WayID)>,
+    timer: &mut Timer,
+) {
+    let mut complicated_restrictions = Vec::new();
+    for (rel_osm, from_osm, via_osm, to_osm) in input {
+        if let Some((from, via, to)) = resolve_via_ways(map, from_osm, &via_osm, to_osm) {
+            complicated_restrictions.push((from, via, to));
+        } else {
+            timer.warn(format!(
+                "Couldn't resolve turn restriction from way {} to way {} via ways {:?}. See {}",
+                from_osm, to_osm, via_osm, rel_osm
+            ));
+        }
+    }
+    for (from, via, to) in complicated_restrictions {
+        map.roads
+            .get_mut(&from)
+            .unwrap()
+            .complicated_turn_restrictions
+            .push((via, to));
+    }
+}
+
+// The via ways might've been split into several roads. Find a chain of roads starting at a piece
+// of the from way, going through pieces of each via way in order, and ending at a piece of the to
+// way.
+fn resolve_via_ways(
+    map: &RawMap,
+    from: WayID,
+    via: &[WayID],
+    to: WayID,
+) -> Option<(OriginalRoad, Vec<OriginalRoad>, OriginalRoad)> {
+    for start in map.roads.keys().filter(|r| r.osm_way_id == from) {
+        for i in vec![start.i1, start.i2] {
+            let mut chain = Vec::new();
+            if let Some(end) = extend_via_chain(map, i, via, 0, to, &mut chain) {
+                return Some((*start, chain, end));
+            }
+        }
+    }
+    None
+}
+
+// We're at intersection i, and have matched some of the pieces of via[idx] (or none, if chain
+// doesn't have any of them yet).
+fn extend_via_chain(
+    map: &RawMap,
+    i: osm::NodeID,
+    via: &[WayID],
+    idx: usize,
+    to: WayID,
+    chain: &mut Vec<OriginalRoad>,
+) -> Option<OriginalRoad> {
+    let started_current = chain
+        .last()
+        .map(|r| r.osm_way_id == via[idx])
+        .unwrap_or(false);
+    for r in map.roads_per_intersection(i) {
+        if chain.contains(&r) {
+            continue;
+        }
+        let next_i = if r.i1 == i { r.i2 } else { r.i1 };
+        // Keep following the current via way, or move on to the next one
+        if r.osm_way_id == via[idx] {
+            chain.push(r);
+            if let Some(end) = extend_via_chain(map, next_i, via, idx, to, chain) {
+                return Some(end);
+            }
+            chain.pop();
+        } else if started_current && idx + 1 < via.len() && r.osm_way_id == via[idx + 1] {
+            chain.push(r);
+            if let Some(end) = extend_via_chain(map, next_i, via, idx + 1, to, chain) {
+                return Some(end);
+            }
+            chain.pop();
+        } else if started_current && idx + 1 == via.len() && r.osm_way_id == to {
+            return Some(r);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A straight line of 6 intersections. Way 1 leads into way 20, which was split into two
+    // roads, then way 30 and finally way 5.
+    fn setup() -> RawMap {
+        let mut map = RawMap::synthetic_grid(6, 1);
+        for (old, new) in vec![(2, 20), (3, 20), (4, 30)] {
+            let id = *map
+                .roads
+                .keys()
+                .find(|r| r.osm_way_id == WayID(old))
+                .unwrap();
+            let r = map.roads.remove(&id).unwrap();
+            map.roads.insert(
+                OriginalRoad {
+                    osm_way_id: WayID(new),
+                    i1: id.i1,
+                    i2: id.i2,
+                },
+                r,
+            );
+        }
+        map
+    }
+
+    #[test]
+    fn test_via_way_split_into_pieces() {
+        let map = setup();
+        assert_eq!(
+            resolve_via_ways(&map, WayID(1), &[WayID(20), WayID(30)], WayID(5)),
+            Some((
+                OriginalRoad::new(1, (1, 2)),
+                vec![
+                    OriginalRoad::new(20, (2, 3)),
+                    OriginalRoad::new(20, (3, 4)),
+                    OriginalRoad::new(30, (4, 5)),
+                ],
+                OriginalRoad::new(5, (5, 6)),
+            ))
+        );
+        // Skipping part of the chain doesn't work
+        assert_eq!(
+            resolve_via_ways(&map, WayID(1), &[WayID(30)], WayID(5)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unresolved_restriction_dropped() {
+        let mut map = setup();
+        resolve_complicated_turn_restrictions(
+            &mut map,
+            vec![
+                (
+                    RelationID(1),
+                    WayID(1),
+                    vec![WayID(20), WayID(30)],
+                    WayID(5),
+                ),
+                // The via ways are out of order
+                (
+                    RelationID(2),
+                    WayID(1),
+                    vec![WayID(30), WayID(20)],
+                    WayID(5),
+                ),
+                // There's no way 99
+                (RelationID(3), WayID(1), vec![WayID(20)], WayID(99)),
+            ],
+            &mut Timer::throwaway(),
+        );
+
+        assert_eq!(
+            map.roads[&OriginalRoad::new(1, (1, 2))].complicated_turn_restrictions,
+            vec![(
+                vec![
+                    OriginalRoad::new(20, (2, 3)),
+                    OriginalRoad::new(20, (3, 4)),
+                    OriginalRoad::new(30, (4, 5)),
+                ],
+                OriginalRoad::new(5, (5, 6)),
+            )]
+        );
+        // Nothing else was added anywhere
+        assert_eq!(
+            map.roads
+                .values()
+                .map(|r| r.complicated_turn_restrictions.len())
+                .sum::<usize>(),
+            1
+        );
+    }
+}
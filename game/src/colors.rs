@@ -95,6 +95,7 @@ pub struct ColorScheme {
     pub unzoomed_bike: Color,
     pub unzoomed_bus: Color,
     pub unzoomed_pedestrian: Color,
+    pub unzoomed_ride_hail: Color,
 
     // Agents
     agent_colors: Vec<Color>,
@@ -196,6 +197,7 @@ impl ColorScheme {
             unzoomed_bike: hex("#5D9630"),
             unzoomed_bus: hex("#12409D"),
             unzoomed_pedestrian: hex("#DF8C3D"),
+            unzoomed_ride_hail: hex("#8E3B9E"),
 
             // Agents
             agent_colors: vec![
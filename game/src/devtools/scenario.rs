@@ -1,12 +1,13 @@
 use crate::app::App;
 use crate::common::{ColorDiscrete, CommonState};
 use crate::devtools::destinations::PopularDestinations;
-use crate::game::{State, Transition};
+use crate::game::{PopupMsg, State, Transition};
 use abstutil::prettyprint_usize;
-use sim::Scenario;
+use geom::{Duration, Time};
+use sim::{DepartureProfile, Scenario};
 use widgetry::{
-    hotkey, Btn, Color, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel,
-    Text, VerticalAlignment, Widget,
+    hotkey, Btn, Choice, Color, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Key, Line,
+    Outcome, Panel, Spinner, Text, TextExt, VerticalAlignment, Widget,
 };
 
 pub struct ScenarioManager {
@@ -56,6 +57,7 @@ impl ScenarioManager {
                         .align_right(),
                 ]),
                 Btn::text_fg("popular destinations").build_def(ctx, hotkey(Key::D)),
+                Btn::text_fg("change departure times").build_def(ctx, hotkey(Key::T)),
                 Text::from_multiline(vec![
                     Line(format!(
                         "{} people",
@@ -94,6 +96,9 @@ impl State for ScenarioManager {
                 "popular destinations" => {
                     return Transition::Push(PopularDestinations::new(ctx, app, &self.scenario));
                 }
+                "change departure times" => {
+                    return Transition::Push(ChangeDepartures::new(ctx, self.scenario.clone()));
+                }
                 _ => unreachable!(),
             },
             _ => {}
@@ -117,3 +122,137 @@ impl State for ScenarioManager {
         CommonState::draw_osd(g, app);
     }
 }
+
+// Re-time everybody's day, so the first trip of each person follows a departure profile.
+struct ChangeDepartures {
+    panel: Panel,
+    scenario: Scenario,
+}
+
+impl ChangeDepartures {
+    fn new(ctx: &mut EventCtx, scenario: Scenario) -> Box<dyn State> {
+        Box::new(ChangeDepartures {
+            panel: Panel::new(Widget::col(vec![
+                Line("Change departure times").small_heading().draw(ctx),
+                Widget::row(vec![
+                    "Distribution:".draw_text(ctx),
+                    Widget::dropdown(
+                        ctx,
+                        "profile",
+                        "normal",
+                        vec![
+                            Choice::new("uniform", "uniform"),
+                            Choice::new("normal", "normal"),
+                            Choice::new("triangular", "triangular"),
+                            Choice::new("hourly", "hourly"),
+                        ],
+                    ),
+                ]),
+                Widget::row(vec![
+                    "Earliest departure (hour):".draw_text(ctx).centered_vert(),
+                    Spinner::new(ctx, (0, 23), 6).named("start"),
+                ]),
+                Widget::row(vec![
+                    "Peak departure (hour):".draw_text(ctx).centered_vert(),
+                    Spinner::new(ctx, (0, 24), 8).named("peak"),
+                ]),
+                Widget::row(vec![
+                    "Latest departure (hour):".draw_text(ctx).centered_vert(),
+                    Spinner::new(ctx, (1, 24), 11).named("stop"),
+                ]),
+                Widget::row(vec![
+                    "Standard deviation (minutes, normal only):"
+                        .draw_text(ctx)
+                        .centered_vert(),
+                    Spinner::new(ctx, (1, 240), 45).named("std_dev"),
+                ]),
+                Widget::row(vec![
+                    "Departures per hour from midnight (comma-separated, hourly only):"
+                        .draw_text(ctx)
+                        .centered_vert(),
+                    Widget::text_entry(ctx, "0,0,0,0,0,1,3,5,4,2,1,1".to_string(), true)
+                        .named("hourly"),
+                ]),
+                Widget::row(vec![
+                    Btn::text_bg2("Apply").build_def(ctx, hotkey(Key::Enter)),
+                    Btn::text_bg2("Discard changes").build_def(ctx, hotkey(Key::Escape)),
+                ])
+                .centered(),
+            ]))
+            .build(ctx),
+            scenario,
+        })
+    }
+
+    fn profile(&self) -> Result<DepartureProfile, String> {
+        let hour = |name| Time::START_OF_DAY + Duration::hours(self.panel.spinner(name) as usize);
+        let (start, peak, stop) = (hour("start"), hour("peak"), hour("stop"));
+        let profile = match self.panel.dropdown_value::<&str>("profile") {
+            "uniform" => DepartureProfile::uniform(start, stop),
+            "normal" => DepartureProfile::Normal {
+                mean: peak,
+                std_dev: Duration::minutes(self.panel.spinner("std_dev") as usize),
+                start,
+                stop,
+            },
+            "triangular" => DepartureProfile::Triangular { start, peak, stop },
+            "hourly" => {
+                let mut weights = Vec::new();
+                for x in self.panel.text_box("hourly").split(',') {
+                    match x.trim().parse::<f64>() {
+                        Ok(w) => weights.push(w),
+                        Err(_) => return Err(format!("{} isn't a number", x.trim())),
+                    }
+                }
+                DepartureProfile::Hourly(weights)
+            }
+            _ => unreachable!(),
+        };
+        profile.validate()?;
+        Ok(profile)
+    }
+}
+
+impl State for ChangeDepartures {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "Discard changes" => Transition::Pop,
+                "Apply" => {
+                    let profile = match self.profile() {
+                        Ok(p) => p,
+                        Err(err) => {
+                            return Transition::Push(PopupMsg::new(ctx, "Error", vec![err]));
+                        }
+                    };
+                    let mut scenario = self.scenario.clone();
+                    scenario.reschedule(
+                        &profile,
+                        &mut app.primary.current_flags.sim_flags.make_rng(),
+                    );
+                    scenario.scenario_name = format!("{} (rescheduled)", scenario.scenario_name);
+                    scenario.save();
+                    Transition::Multi(vec![
+                        Transition::Pop,
+                        Transition::Replace(Box::new(ScenarioManager::new(scenario, ctx, app))),
+                        Transition::Push(PopupMsg::new(
+                            ctx,
+                            "Rescheduled",
+                            vec![format!(
+                                "Everybody now starts their day {}",
+                                profile.describe()
+                            )],
+                        )),
+                    ])
+                }
+                _ => unreachable!(),
+            },
+            _ => Transition::Keep,
+        }
+    }
+
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        State::grey_out_map(g, app);
+        self.panel.draw(g);
+    }
+}
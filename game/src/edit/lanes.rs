@@ -4,14 +4,19 @@ use crate::edit::zones::ZoneEditor;
 use crate::edit::{
     apply_map_edits, can_edit_lane, change_speed_limit, maybe_edit_intersection, try_change_lt,
 };
-use crate::game::{State, Transition};
+use crate::game::{PopupMsg, State, Transition};
 use crate::helpers::ID;
 use crate::render::Renderable;
 use crate::sandbox::GameplayMode;
-use map_model::{EditCmd, LaneID, LaneType, Map};
+use enumset::EnumSet;
+use geom::Distance;
+use map_model::raw::CrossingType;
+use map_model::{
+    ConditionalRestriction, EditCmd, LaneID, LaneType, Map, PathConstraints, TimeWindow,
+};
 use widgetry::{
-    hotkey, Btn, Color, EventCtx, GfxCtx, HorizontalAlignment, Key, Outcome, Panel, RewriteColor,
-    TextExt, VerticalAlignment, Widget,
+    hotkey, Btn, Checkbox, Color, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel,
+    RewriteColor, TextExt, VerticalAlignment, Widget,
 };
 
 pub struct LaneEditor {
@@ -73,7 +78,7 @@ impl LaneEditor {
         }
 
         let parent = app.primary.map.get_parent(l);
-        let col = vec![
+        let mut col = vec![
             format!(
                 "Convert this lane of {} to what type?",
                 parent.get_name(app.opts.language.as_ref())
@@ -83,8 +88,15 @@ impl LaneEditor {
             Widget::custom_row(row).centered(),
             change_speed_limit(ctx, parent.speed_limit),
             Btn::text_fg("Change access restrictions").build_def(ctx, hotkey(Key::A)),
-            Btn::text_bg2("Finish").build_def(ctx, hotkey(Key::Escape)),
         ];
+        if !app.primary.map.get_l(l).is_walkable() {
+            col.push(Btn::text_fg("Change time restrictions").build_def(ctx, hotkey(Key::R)));
+        } else if parent.mid_block_crossings().is_empty() {
+            col.push(Btn::text_fg("Add a mid-block crosswalk").build_def(ctx, hotkey(Key::W)));
+        } else {
+            col.push(Btn::text_fg("Remove mid-block crosswalks").build_def(ctx, hotkey(Key::W)));
+        }
+        col.push(Btn::text_bg2("Finish").build_def(ctx, hotkey(Key::Escape)));
 
         let panel = Panel::new(Widget::col(col))
             .aligned(HorizontalAlignment::Center, VerticalAlignment::Top)
@@ -134,6 +146,9 @@ impl State for LaneEditor {
                         app.primary.map.get_l(self.l).parent,
                     ));
                 }
+                "Change time restrictions" => {
+                    return Transition::Push(TimeRestrictionEditor::new(ctx, app, self.l));
+                }
                 "Finish" => {
                     return Transition::Pop;
                 }
@@ -141,6 +156,10 @@ impl State for LaneEditor {
                     let map = &mut app.primary.map;
                     let result = match x {
                         "reverse lane direction" => Ok(reverse_lane(map, self.l)),
+                        "Add a mid-block crosswalk" => Ok(add_mid_block_crosswalk(map, self.l)),
+                        "Remove mid-block crosswalks" => {
+                            Ok(remove_mid_block_crosswalks(map, self.l))
+                        }
                         "convert to a driving lane" => {
                             try_change_lt(ctx, map, self.l, LaneType::Driving)
                         }
@@ -207,6 +226,130 @@ impl State for LaneEditor {
     }
 }
 
+// Restrict a lane during some times of day, like a peak-hour bus lane or a parking lane that's
+// cleared during rush hour.
+struct TimeRestrictionEditor {
+    l: LaneID,
+    panel: Panel,
+}
+
+impl TimeRestrictionEditor {
+    fn new(ctx: &mut EventCtx, app: &App, l: LaneID) -> Box<dyn State> {
+        let lane = app.primary.map.get_l(l);
+        let (allow, windows) = match lane.conditional {
+            Some(ref c) => (c.allow, c.describe_windows()),
+            None => (EnumSet::new(), String::new()),
+        };
+        let mut col = vec![
+            Line("Time restrictions").small_heading().draw(ctx),
+            "During these times (like 07:00-09:00, 16:00-18:00), leave blank for none:"
+                .draw_text(ctx),
+            Widget::text_entry(ctx, windows, true).named("windows"),
+        ];
+        if lane.is_parking() {
+            col.push(Checkbox::checkbox(
+                ctx,
+                "parking allowed",
+                None,
+                allow.contains(PathConstraints::Car),
+            ));
+        } else {
+            col.push("Only allow:".draw_text(ctx));
+            col.push(Widget::custom_row(
+                vec![
+                    ("cars", PathConstraints::Car),
+                    ("bikes", PathConstraints::Bike),
+                    ("buses", PathConstraints::Bus),
+                    ("trucks", PathConstraints::Truck),
+                ]
+                .into_iter()
+                .map(|(label, c)| {
+                    Checkbox::checkbox(ctx, label, None, allow.contains(c)).margin_right(24)
+                })
+                .collect(),
+            ));
+        }
+        col.push(
+            Widget::custom_row(vec![
+                Btn::text_fg("Apply").build_def(ctx, hotkey(Key::Enter)),
+                Btn::text_fg("Cancel").build_def(ctx, hotkey(Key::Escape)),
+            ])
+            .evenly_spaced(),
+        );
+
+        Box::new(TimeRestrictionEditor {
+            l,
+            panel: Panel::new(Widget::col(col))
+                .aligned(HorizontalAlignment::Center, VerticalAlignment::Center)
+                .build(ctx),
+        })
+    }
+}
+
+impl State for TimeRestrictionEditor {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "Apply" => {
+                    let windows = match TimeWindow::parse_list(&self.panel.text_box("windows")) {
+                        Some(windows) => windows,
+                        None => {
+                            return Transition::Push(PopupMsg::new(
+                                ctx,
+                                "Error",
+                                vec!["Times should look like 07:00-09:00, 16:00-18:00"],
+                            ));
+                        }
+                    };
+                    let mut allow = EnumSet::new();
+                    if app.primary.map.get_l(self.l).is_parking() {
+                        if self.panel.is_checked("parking allowed") {
+                            allow.insert(PathConstraints::Car);
+                        }
+                    } else {
+                        for (label, c) in vec![
+                            ("cars", PathConstraints::Car),
+                            ("bikes", PathConstraints::Bike),
+                            ("buses", PathConstraints::Bus),
+                            ("trucks", PathConstraints::Truck),
+                        ] {
+                            if self.panel.is_checked(label) {
+                                allow.insert(c);
+                            }
+                        }
+                    }
+                    let conditional = if windows.is_empty() {
+                        None
+                    } else {
+                        Some(ConditionalRestriction { allow, windows })
+                    };
+
+                    let map = &app.primary.map;
+                    let r = map.get_parent(self.l);
+                    let idx = r.offset(self.l);
+                    let mut edits = map.get_edits().clone();
+                    edits.commands.push(map.edit_road_cmd(r.id, |new| {
+                        new.conditional_ltr[idx] = conditional.clone();
+                    }));
+                    apply_map_edits(ctx, app, edits);
+                    return Transition::Pop;
+                }
+                "Cancel" => {
+                    return Transition::Pop;
+                }
+                _ => unreachable!(),
+            },
+            _ => {}
+        }
+
+        Transition::Keep
+    }
+
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+    }
+}
+
 // Allow doing this anywhere. Players can create really wacky roads with many direction changes,
 // but it's not really useful to limit creativity. ;)
 fn reverse_lane(map: &Map, l: LaneID) -> EditCmd {
@@ -216,3 +359,22 @@ fn reverse_lane(map: &Map, l: LaneID) -> EditCmd {
         new.lanes_ltr[idx].1 = new.lanes_ltr[idx].1.opposite();
     })
 }
+
+// Halfway along the block, where it's furthest from the crosswalks at either end
+fn add_mid_block_crosswalk(map: &Map, l: LaneID) -> EditCmd {
+    let r = map.get_parent(l);
+    let dist = r.center_pts.length() / 2.0;
+    map.edit_road_cmd(r.id, |new| {
+        new.crossings.push((dist, CrossingType::Marked));
+        new.crossings.sort_by_key(|(dist, _)| *dist);
+    })
+}
+
+fn remove_mid_block_crosswalks(map: &Map, l: LaneID) -> EditCmd {
+    let r = map.get_parent(l);
+    let len = r.center_pts.length();
+    map.edit_road_cmd(r.id, |new| {
+        new.crossings
+            .retain(|(dist, _)| *dist == Distance::ZERO || *dist == len);
+    })
+}
@@ -13,6 +13,9 @@ use widgetry::{
     Widget,
 };
 
+// NACTO recommends at least 3 seconds
+const LEADING_PED_INTERVAL: Duration = Duration::const_seconds(3.0);
+
 pub struct ChangeDuration {
     panel: Panel,
     idx: usize,
@@ -119,6 +122,7 @@ pub fn edit_entire_signal(
 
     let use_template = "use template";
     let all_walk = "add an all-walk stage at the end";
+    let lpi = "give pedestrians a head start before vehicles";
     let stop_sign = "convert to stop signs";
     let close = "close intersection for construction";
     let reset = "reset to default";
@@ -126,6 +130,7 @@ pub fn edit_entire_signal(
     let mut choices = vec![use_template];
     if has_sidewalks {
         choices.push(all_walk);
+        choices.push(lpi);
     }
     // TODO Conflating stop signs and construction here
     if mode.can_edit_stop_signs() {
@@ -171,6 +176,23 @@ pub fn edit_entire_signal(
                     }
                 })),
             ]),
+            x if x == lpi => Transition::Multi(vec![
+                Transition::Pop,
+                Transition::ModifyState(Box::new(move |state, ctx, app| {
+                    let mut new_signal = app.primary.map.get_traffic_signal(i).clone();
+                    // Work backwards, since this inserts stages
+                    let mut changed = false;
+                    for idx in (0..new_signal.stages.len()).rev() {
+                        changed |= new_signal.add_leading_ped_interval(idx, LEADING_PED_INTERVAL);
+                    }
+                    if changed {
+                        let editor = state.downcast_mut::<TrafficSignalEditor>().unwrap();
+                        editor.add_new_edit(ctx, app, 0, |ts| {
+                            *ts = new_signal.clone();
+                        });
+                    }
+                })),
+            ]),
             x if x == stop_sign => {
                 original.apply(app);
 
@@ -41,6 +41,8 @@ pub struct TrafficSignalEditor {
     // Before synchronizing the number of stages
     original: BundleEdits,
     warn_changed: bool,
+    // Why the last edit was refused
+    rejected_edit: Option<String>,
 
     fade_irrelevant: Drawable,
 }
@@ -99,6 +101,7 @@ impl TrafficSignalEditor {
             command_stack: Vec::new(),
             redo_stack: Vec::new(),
             warn_changed,
+            rejected_edit: None,
             original,
             fade_irrelevant: GeomBatch::from(vec![(app.cs.fade_map_dark, fade_area)]).upload(ctx),
         };
@@ -125,6 +128,8 @@ impl TrafficSignalEditor {
         self.draw_current = self.recalc_draw_current(ctx, app);
     }
 
+    // Refuses edits that leave a crosswalk green for too short. Signals that were already like
+    // that can still be changed in other ways.
     fn add_new_edit<F: Fn(&mut ControlTrafficSignal)>(
         &mut self,
         ctx: &mut EventCtx,
@@ -132,12 +137,19 @@ impl TrafficSignalEditor {
         idx: usize,
         fxn: F,
     ) {
-        let mut bundle = BundleEdits::get_current(app, &self.members);
-        self.command_stack.push(bundle.clone());
-        self.redo_stack.clear();
-        for ts in &mut bundle.signals {
+        let orig = BundleEdits::get_current(app, &self.members);
+        let mut bundle = orig.clone();
+        for (old, ts) in orig.signals.iter().zip(bundle.signals.iter_mut()) {
             fxn(ts);
+            if let Err(err) = ts.check_crosswalk_timing() {
+                if old.check_crosswalk_timing().is_ok() {
+                    self.rejected_edit = Some(err);
+                    return;
+                }
+            }
         }
+        self.command_stack.push(orig);
+        self.redo_stack.clear();
         bundle.apply(app);
 
         self.top_panel = make_top_panel(ctx, app, true, false);
@@ -206,6 +218,9 @@ impl State for TrafficSignalEditor {
                 vec!["Some signals were modified to match the number and duration of stages"],
             ));
         }
+        if let Some(err) = self.rejected_edit.take() {
+            return Transition::Push(PopupMsg::new(ctx, "Error: stage too short", vec![err]));
+        }
 
         ctx.canvas_movement();
 
@@ -11,8 +11,8 @@ use maplit::btreeset;
 use sim::TripMode;
 use std::collections::BTreeSet;
 use widgetry::{
-    hotkey, Btn, Color, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Key, Line, Outcome, Panel,
-    Spinner, Text, TextExt, VerticalAlignment, Widget,
+    hotkey, Btn, Checkbox, Color, Drawable, EventCtx, GfxCtx, HorizontalAlignment, Key, Line,
+    Outcome, Panel, Spinner, Text, TextExt, VerticalAlignment, Widget,
 };
 
 pub struct ZoneEditor {
@@ -34,12 +34,17 @@ impl ZoneEditor {
             // Starting a new zone
             btreeset! { start.id }
         };
-        let allow_through_traffic = start
+        let mut allow_through_traffic: BTreeSet<TripMode> = start
             .access_restrictions
             .allow_through_traffic
             .into_iter()
+            .filter(|c| *c != PathConstraints::Truck)
             .map(|c| TripMode::from_constraints(c))
             .collect();
+        // Ride-hailing vehicles follow the same rules as cars
+        if allow_through_traffic.contains(&TripMode::Drive) {
+            allow_through_traffic.insert(TripMode::RideHail);
+        }
         let cap_vehicles_per_hour = start.access_restrictions.cap_vehicles_per_hour;
 
         let (unzoomed, zoomed, legend) = draw_zone(ctx, app, &members);
@@ -55,6 +60,15 @@ impl ZoneEditor {
                 legend,
                 make_instructions(ctx, &allow_through_traffic),
                 checkbox_per_mode(ctx, app, &allow_through_traffic),
+                Checkbox::checkbox(
+                    ctx,
+                    "trucks passing through",
+                    None,
+                    start
+                        .access_restrictions
+                        .allow_through_traffic
+                        .contains(PathConstraints::Truck),
+                ),
                 Widget::row(vec![
                     "Limit the number of vehicles passing through per hour (0 = unlimited):"
                         .draw_text(ctx),
@@ -103,6 +117,10 @@ impl State for ZoneEditor {
                     // The original allow_through_traffic always includes this, and there's no way
                     // to exclude it, so stay consistent.
                     allow_through_traffic.insert(PathConstraints::Train);
+                    // Trucks aren't a trip mode, so they get their own checkbox
+                    if self.panel.is_checked("trucks passing through") {
+                        allow_through_traffic.insert(PathConstraints::Truck);
+                    }
                     let new_access_restrictions = AccessRestrictions {
                         allow_through_traffic,
                         cap_vehicles_per_hour: {
@@ -25,7 +25,7 @@ impl ID {
         match id {
             AgentID::Car(id) => ID::Car(id),
             AgentID::Pedestrian(id) => ID::Pedestrian(id),
-            AgentID::BusPassenger(_, bus) => ID::Car(bus),
+            AgentID::BusPassenger(_, car) | AgentID::RideHailPassenger(_, car) => ID::Car(car),
         }
     }
 
@@ -127,6 +127,7 @@ pub fn color_for_mode(app: &App, m: TripMode) -> Color {
         TripMode::Bike => app.cs.unzoomed_bike,
         TripMode::Transit => app.cs.unzoomed_bus,
         TripMode::Drive => app.cs.unzoomed_car,
+        TripMode::RideHail => app.cs.unzoomed_ride_hail,
     }
 }
 
@@ -136,13 +137,13 @@ pub fn color_for_agent_type(app: &App, a: AgentType) -> Color {
         AgentType::Bike => app.cs.unzoomed_bike,
         AgentType::Bus | AgentType::Train => app.cs.unzoomed_bus,
         AgentType::TransitRider => app.cs.bus_lane,
-        AgentType::Car => app.cs.unzoomed_car,
+        AgentType::Car | AgentType::RideHailPassenger => app.cs.unzoomed_car,
     }
 }
 
 pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
     match tpt {
-        TripPhaseType::Driving => app.cs.unzoomed_car,
+        TripPhaseType::Driving | TripPhaseType::RidingAlong(_) => app.cs.unzoomed_car,
         TripPhaseType::Walking => app.cs.unzoomed_pedestrian,
         TripPhaseType::Biking => app.cs.bike_lane,
         TripPhaseType::Parking => app.cs.parking_trip,
@@ -151,6 +152,8 @@ pub fn color_for_trip_phase(app: &App, tpt: TripPhaseType) -> Color {
         TripPhaseType::Aborted | TripPhaseType::Finished => unreachable!(),
         TripPhaseType::DelayedStart => Color::YELLOW,
         TripPhaseType::Remote => Color::PINK,
+        TripPhaseType::WaitingForRide => app.cs.unzoomed_ride_hail.alpha(0.5),
+        TripPhaseType::RidingFleetVehicle(_) => app.cs.unzoomed_ride_hail,
     }
 }
 
@@ -1,9 +1,10 @@
 use crate::app::App;
-use crate::info::{header_btns, make_table, make_tabs, Details, Tab};
+use crate::info::{header_btns, make_table, make_tabs, parking_lot, Details, Tab};
 use crate::render::DrawPedestrian;
+use abstutil::prettyprint_usize;
 use geom::{Angle, Circle, Distance, Speed, Time};
 use map_model::{BuildingID, LaneID, OffstreetParking, Traversable, SIDEWALK_THICKNESS};
-use sim::{DrawPedestrianInput, PedestrianID, PersonID, TripMode, TripResult};
+use sim::{ChargingStation, DrawPedestrianInput, PedestrianID, PersonID, TripMode, TripResult};
 use std::collections::BTreeMap;
 use widgetry::{Btn, Color, EventCtx, Line, Text, TextExt, Widget};
 
@@ -21,6 +22,19 @@ pub fn info(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BuildingID
         kv.push(("OSM ID", format!("{}", b.orig_id.inner())));
     }
 
+    if b.bldg_type.num_residents() > 0 {
+        kv.push((
+            "Residents",
+            format!("~{}", prettyprint_usize(b.bldg_type.num_residents())),
+        ));
+    }
+    if b.bldg_type.num_jobs() > 0 {
+        kv.push((
+            "Jobs",
+            format!("~{}", prettyprint_usize(b.bldg_type.num_jobs())),
+        ));
+    }
+
     let num_spots = b.num_parking_spots();
     if num_spots > 0 {
         let free = app.primary.sim.get_free_offstreet_spots(b.id).len();
@@ -38,6 +52,9 @@ pub fn info(ctx: &mut EventCtx, app: &App, details: &mut Details, id: BuildingID
     } else {
         kv.push(("Parking", "None".to_string()));
     }
+    if let Some(charging) = parking_lot::describe_charging(app, ChargingStation::Building(id)) {
+        kv.push(("Charging", charging));
+    }
 
     rows.extend(make_table(ctx, kv.into_iter()));
 
@@ -2,6 +2,7 @@ use crate::app::App;
 use crate::info::{header_btns, make_table, make_tabs, throughput, DataOptions, Details, Tab};
 use abstutil::prettyprint_usize;
 use map_model::{LaneID, PathConstraints};
+use sim::IncidentLocation;
 use std::collections::HashSet;
 use widgetry::{Btn, EventCtx, Line, LinePlot, PlotOptions, Series, Text, TextExt, Widget};
 
@@ -16,7 +17,7 @@ pub fn info(ctx: &EventCtx, app: &App, details: &mut Details, id: LaneID) -> Vec
     if !l.is_walkable() {
         kv.push(("Type", l.lane_type.describe().to_string()));
     }
-    if r.is_private() {
+    if r.is_private() || r.bans_through_trucks() {
         let mut ban = Vec::new();
         for p in PathConstraints::all() {
             if !r.access_restrictions.allow_through_traffic.contains(p) {
@@ -38,6 +39,45 @@ pub fn info(ctx: &EventCtx, app: &App, details: &mut Details, id: LaneID) -> Vec
         }
     }
 
+    if let Some(ref c) = l.conditional {
+        let rule = if l.is_parking() {
+            if c.allow.contains(PathConstraints::Car) {
+                "Parking allowed".to_string()
+            } else {
+                "No parking".to_string()
+            }
+        } else if c.allow.is_empty() {
+            "Closed".to_string()
+        } else {
+            format!(
+                "Only {}",
+                c.allow
+                    .iter()
+                    .map(|p| format!("{:?}", p).to_ascii_lowercase())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        };
+        let status = if c.is_active(app.primary.sim.time()) {
+            "now"
+        } else {
+            "not now"
+        };
+        kv.push((
+            "Time restriction",
+            format!("{} during {} ({})", rule, c.describe_windows(), status),
+        ));
+    }
+
+    for (incident, active, _) in app.primary.sim.get_incidents() {
+        if let IncidentLocation::Lanes(ref lanes) = incident.location {
+            if lanes.contains(&id) {
+                let status = if active { "now" } else { "not now" };
+                kv.push(("Incident", format!("{} ({})", incident.describe(), status)));
+            }
+        }
+    }
+
     if l.is_parking() {
         kv.push((
             "Parking",
@@ -47,12 +87,35 @@ pub fn info(ctx: &EventCtx, app: &App, details: &mut Details, id: LaneID) -> Vec
                 l.number_parking_spots()
             ),
         ));
+        kv.push((
+            "Price",
+            l.parking_rules
+                .as_ref()
+                .map(|rules| rules.describe())
+                .unwrap_or_else(|| "free".to_string()),
+        ));
     } else {
         kv.push(("Speed limit", r.speed_limit.to_string()));
     }
 
     kv.push(("Length", l.length().describe_rounded()));
 
+    if l.is_walkable() {
+        let crossings = r.mid_block_crossings();
+        if !crossings.is_empty() {
+            kv.push((
+                "Mid-block crossings",
+                crossings
+                    .into_iter()
+                    .map(|(dist, ct)| {
+                        format!("{:?} at {}", ct, dist.describe_rounded()).to_ascii_lowercase()
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ));
+        }
+    }
+
     rows.extend(make_table(ctx, kv.into_iter()));
 
     if l.is_parking() {
@@ -114,10 +177,7 @@ pub fn debug(ctx: &EventCtx, app: &App, details: &mut Details, id: LaneID) -> Ve
         ));
     }
 
-    if let Some(types) = l
-        .get_turn_restrictions(r)
-        .map(|types| types.collect::<Vec<_>>())
-    {
+    if let Some(ref types) = l.turn_restrictions {
         kv.push(("Turn restrictions".to_string(), format!("{:?}", types)));
     }
     for (restriction, to) in &r.turn_restrictions {
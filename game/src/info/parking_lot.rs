@@ -1,7 +1,9 @@
 use crate::app::App;
 use crate::info::{header_btns, make_tabs, Details, Tab};
 use abstutil::prettyprint_usize;
+use geom::Duration;
 use map_model::ParkingLotID;
+use sim::ChargingStation;
 use std::collections::HashSet;
 use widgetry::{Btn, EventCtx, Line, LinePlot, PlotOptions, Series, TextExt, Widget};
 
@@ -18,6 +20,12 @@ pub fn info(ctx: &mut EventCtx, app: &App, details: &mut Details, id: ParkingLot
         )
         .draw_text(ctx),
     );
+    if let Some(ref rules) = pl.parking_rules {
+        rows.push(format!("Price: {}", rules.describe()).draw_text(ctx));
+    }
+    if let Some(charging) = describe_charging(app, ChargingStation::Lot(id)) {
+        rows.push(charging.draw_text(ctx));
+    }
 
     let mut series = vec![Series {
         label: format!("After \"{}\"", app.primary.map.get_edits().edits_name),
@@ -58,6 +66,34 @@ pub fn info(ctx: &mut EventCtx, app: &App, details: &mut Details, id: ParkingLot
     rows
 }
 
+pub fn describe_charging(app: &App, station: ChargingStation) -> Option<String> {
+    let (chargers, charging, waiting) = app.primary.sim.get_charging_station(station)?;
+    let mut sessions = 0;
+    let mut total_wait = Duration::ZERO;
+    let mut energy = 0.0;
+    for (_, s, wait, kwh) in &app.primary.sim.get_analytics().charging_sessions {
+        if *s == station {
+            sessions += 1;
+            total_wait += *wait;
+            energy += *kwh;
+        }
+    }
+    let mut result = format!(
+        "{} EV chargers: {} in use, {} waiting",
+        chargers, charging, waiting
+    );
+    if sessions > 0 {
+        result = format!(
+            "{}. {} cars charged {:.0} kWh today, waiting {} on average",
+            result,
+            prettyprint_usize(sessions),
+            energy,
+            total_wait / (sessions as f64)
+        );
+    }
+    Some(result)
+}
+
 fn header(ctx: &EventCtx, details: &mut Details, id: ParkingLotID, tab: Tab) -> Vec<Widget> {
     vec![
         Widget::row(vec![
@@ -137,7 +137,7 @@ pub fn trips(
                     match trip.mode {
                         TripMode::Walk => "system/assets/meters/pedestrian.svg",
                         TripMode::Bike => "system/assets/meters/bike.svg",
-                        TripMode::Drive => "system/assets/meters/car.svg",
+                        TripMode::Drive | TripMode::RideHail => "system/assets/meters/car.svg",
                         TripMode::Transit => "system/assets/meters/bus.svg",
                     },
                 )
@@ -325,6 +325,12 @@ pub fn bio(
             } else {
                 rows.push(format!("Owner of {} (off-map)", v.id).draw_text(ctx));
             }
+            if let Some((charge, capacity)) = app.primary.sim.get_battery(v.id) {
+                rows.push(
+                    format!("Electric, with {:.0} / {:.0} kWh charged", charge, capacity)
+                        .draw_text(ctx),
+                );
+            }
         }
     }
     if has_bike {
@@ -483,12 +489,17 @@ pub fn parked_car(
 
     // TODO how long idle, prev trips, next trips, etc
 
-    let p = app.primary.sim.get_owner_of_car(id).unwrap();
-    rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
-    details.hyperlinks.insert(
-        format!("Owned by {}", p),
-        Tab::PersonTrips(p, BTreeMap::new()),
-    );
+    if let Some(p) = app.primary.sim.get_owner_of_car(id) {
+        rows.push(Btn::text_bg2(format!("Owned by {}", p)).build_def(ctx, None));
+        details.hyperlinks.insert(
+            format!("Owned by {}", p),
+            Tab::PersonTrips(p, BTreeMap::new()),
+        );
+    } else if id.1 == VehicleType::Car {
+        rows.push("Part of the ride-hailing fleet".draw_text(ctx));
+    } else {
+        rows.push(format!("Making deliveries in a {}", id.1).draw_text(ctx));
+    }
 
     if let Some(p) = app.primary.sim.lookup_parked_car(id) {
         match p.spot {
@@ -545,11 +556,18 @@ fn header(
                     AgentID::Car(c) => match c.1 {
                         VehicleType::Car => ("driving", Some("system/assets/meters/car.svg")),
                         VehicleType::Bike => ("biking", Some("system/assets/meters/bike.svg")),
-                        VehicleType::Bus | VehicleType::Train => unreachable!(),
+                        VehicleType::Bus
+                        | VehicleType::Train
+                        | VehicleType::Van
+                        | VehicleType::Truck => unreachable!(),
                     },
                     AgentID::BusPassenger(_, _) => {
                         ("riding a bus", Some("system/assets/meters/bus.svg"))
                     }
+                    AgentID::RideHailPassenger(_, _) => (
+                        "riding in a ride-hail",
+                        Some("system/assets/meters/car.svg"),
+                    ),
                 }
             } else {
                 // TODO Really should clean up the TripModeChange issue
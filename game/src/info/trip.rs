@@ -298,6 +298,19 @@ pub fn finished(
                 .force_width_pct(ctx, col_width),
             waiting.to_string().draw_text(ctx),
         ]));
+
+        let analytics = if open_trips[&id].show_after {
+            app.primary.sim.get_analytics()
+        } else {
+            app.prebaked()
+        };
+        if let Some(emissions) = analytics.trip_emissions.get(&id) {
+            col.push(Widget::custom_row(vec![
+                Widget::custom_row(vec![Line("Emissions").secondary().draw(ctx)])
+                    .force_width_pct(ctx, col_width),
+                emissions.describe().draw_text(ctx),
+            ]));
+        }
     }
 
     col.push(make_timeline(
@@ -517,11 +530,13 @@ fn make_timeline(
             GeomBatch::load_svg(
                 ctx.prerender,
                 match p.phase_type {
-                    TripPhaseType::Driving => "system/assets/timeline/driving.svg",
+                    TripPhaseType::Driving
+                    | TripPhaseType::RidingAlong(_)
+                    | TripPhaseType::RidingFleetVehicle(_) => "system/assets/timeline/driving.svg",
                     TripPhaseType::Walking => "system/assets/timeline/walking.svg",
                     TripPhaseType::Biking => "system/assets/timeline/biking.svg",
                     TripPhaseType::Parking => "system/assets/timeline/parking.svg",
-                    TripPhaseType::WaitingForBus(_, _) => {
+                    TripPhaseType::WaitingForBus(_, _) | TripPhaseType::WaitingForRide => {
                         "system/assets/timeline/waiting_for_bus.svg"
                     }
                     TripPhaseType::RidingBus(_, _, _) => "system/assets/timeline/riding_bus.svg",
@@ -7,6 +7,9 @@ use widgetry::{
     Panel, Text, TextExt, VerticalAlignment, Widget,
 };
 
+// Anything this steep or more gets the worst color
+const STEEPEST_GRADE: f64 = 0.15;
+
 pub struct Elevation {
     unzoomed: Drawable,
     zoomed: Drawable,
@@ -37,15 +40,20 @@ impl Elevation {
     pub fn new(ctx: &mut EventCtx, app: &App) -> Elevation {
         let mut colorer = ColorNetwork::new(app);
 
+        // Color each road by its steepest stretch.
         let mut max = 0.0_f64;
         for r in app.primary.map.all_roads() {
-            let pct = r.percent_grade(&app.primary.map).abs();
+            let pct = r
+                .grades()
+                .into_iter()
+                .map(|(_, _, grade)| grade.abs())
+                .fold(0.0, f64::max);
             max = max.max(pct);
 
-            let color = app.cs.good_to_bad_red.eval(
-                // TODO Rescale based on a reasonable steepest grade, once the data doesn't suck
-                pct.max(0.0).min(1.0),
-            );
+            let color = app
+                .cs
+                .good_to_bad_red
+                .eval((pct / STEEPEST_GRADE).max(0.0).min(1.0));
             colorer.add_r(r.id, color);
         }
 
@@ -54,34 +62,37 @@ impl Elevation {
         // TODO Draw V's, not arrows.
         // TODO Or try gradient colors.
         for r in app.primary.map.all_roads() {
-            let mut pl = r.center_pts.clone();
-            let e1 = app.primary.map.get_i(r.src_i).elevation;
-            let e2 = app.primary.map.get_i(r.dst_i).elevation;
-            if (e1 - e2).abs() / pl.length() < 0.01 {
-                // Don't bother with ~flat roads
-                continue;
-            }
-            if e1 > e2 {
-                pl = pl.reversed();
-            }
+            for (dist1, dist2, grade) in r.grades() {
+                if grade.abs() < 0.01 {
+                    // Don't bother with ~flat stretches
+                    continue;
+                }
+                let mut pl = match r.center_pts.maybe_exact_slice(dist1, dist2) {
+                    Ok(pl) => pl,
+                    Err(_) => continue,
+                };
+                if grade < 0.0 {
+                    pl = pl.reversed();
+                }
 
-            let arrow_len = Distance::meters(5.0);
-            let btwn = Distance::meters(10.0);
-            let thickness = Distance::meters(1.0);
-            let len = pl.length();
+                let arrow_len = Distance::meters(5.0);
+                let btwn = Distance::meters(10.0);
+                let thickness = Distance::meters(1.0);
+                let len = pl.length();
 
-            let mut dist = arrow_len;
-            while dist + arrow_len <= len {
-                let (pt, angle) = pl.must_dist_along(dist);
-                batch.push(
-                    Color::BLACK,
-                    PolyLine::must_new(vec![
-                        pt.project_away(arrow_len / 2.0, angle.opposite()),
-                        pt.project_away(arrow_len / 2.0, angle),
-                    ])
-                    .make_arrow(thickness, ArrowCap::Triangle),
-                );
-                dist += btwn;
+                let mut dist = arrow_len;
+                while dist + arrow_len <= len {
+                    let (pt, angle) = pl.must_dist_along(dist);
+                    batch.push(
+                        Color::BLACK,
+                        PolyLine::must_new(vec![
+                            pt.project_away(arrow_len / 2.0, angle.opposite()),
+                            pt.project_away(arrow_len / 2.0, angle),
+                        ])
+                        .make_arrow(thickness, ArrowCap::Triangle),
+                    );
+                    dist += btwn;
+                }
             }
         }
         colorer.unzoomed.append(batch);
@@ -94,12 +105,15 @@ impl Elevation {
                     .build(ctx, "close", hotkey(Key::Escape))
                     .align_right(),
             ]),
-            Text::from_multiline(vec![
-                Line(format!("Steepest road: {:.0}% grade", max * 100.0)),
-                Line("Note: elevation data is currently wrong!").secondary(),
-            ])
-            .draw(ctx),
-            ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["flat", "steep"]),
+            Text::from(Line(format!("Steepest road: {:.0}% grade", max * 100.0))).draw(ctx),
+            ColorLegend::gradient(
+                ctx,
+                &app.cs.good_to_bad_red,
+                vec![
+                    "flat".to_string(),
+                    format!("{:.0}%+", STEEPEST_GRADE * 100.0),
+                ],
+            ),
         ]))
         .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
         .build(ctx);
@@ -5,6 +5,7 @@ mod parking;
 mod population;
 pub mod traffic;
 pub mod transit;
+mod weather;
 
 use crate::app::App;
 use crate::common::HeatmapOptions;
@@ -101,6 +102,7 @@ impl PickLayer {
                     btn("delay", Key::D),
                     btn("throughput", Key::T),
                     btn("traffic jams", Key::J),
+                    btn("emissions", Key::M),
                 ]),
                 Widget::col(vec![
                     "Map".draw_text(ctx),
@@ -130,6 +132,9 @@ impl PickLayer {
         if app.primary.sim.get_pandemic_model().is_some() {
             col.push(btn("pandemic model", Key::Y));
         }
+        if !app.primary.sim.get_weather().is_clear() {
+            col.push(btn("weather", Key::W));
+        }
 
         Box::new(PickLayer {
             panel: Panel::new(Widget::col(col))
@@ -162,6 +167,13 @@ impl State for PickLayer {
                 "elevation" => {
                     app.layer = Some(Box::new(elevation::Elevation::new(ctx, app)));
                 }
+                "emissions" => {
+                    app.layer = Some(Box::new(traffic::Pollution::new(
+                        ctx,
+                        app,
+                        traffic::Pollutant::CO2,
+                    )));
+                }
                 "map edits" => {
                     app.layer = Some(Box::new(map::Static::edits(ctx, app)));
                 }
@@ -209,6 +221,9 @@ impl State for PickLayer {
                         ctx, app, false, true, true,
                     )));
                 }
+                "weather" => {
+                    app.layer = Some(Box::new(weather::Weather::new(ctx, app)));
+                }
                 _ => unreachable!(),
             },
             _ => {
@@ -5,10 +5,11 @@ use abstutil::Counter;
 use geom::{Distance, Duration, Polygon, Time};
 use map_model::{IntersectionID, Map, Traversable};
 use maplit::btreeset;
+use sim::Emissions;
 use std::collections::BTreeSet;
 use widgetry::{
-    hotkey, Btn, Checkbox, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key,
-    Line, Outcome, Panel, Text, TextExt, VerticalAlignment, Widget,
+    hotkey, Btn, Checkbox, Choice, Color, Drawable, EventCtx, GeomBatch, GfxCtx,
+    HorizontalAlignment, Key, Line, Outcome, Panel, Text, TextExt, VerticalAlignment, Widget,
 };
 
 pub struct Backpressure {
@@ -453,6 +454,135 @@ impl Delay {
     }
 }
 
+pub struct Pollution {
+    time: Time,
+    pollutant: Pollutant,
+    unzoomed: Drawable,
+    zoomed: Drawable,
+    panel: Panel,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Pollutant {
+    CO2,
+    NOx,
+    Energy,
+}
+
+impl Pollutant {
+    // Only used for ranking, so pick units that don't round small amounts to zero
+    fn amount(self, e: &Emissions) -> usize {
+        match self {
+            Pollutant::CO2 => e.co2.round() as usize,
+            // Milligrams
+            Pollutant::NOx => (e.nox * 1000.0).round() as usize,
+            // Kilojoules
+            Pollutant::Energy => (e.energy * 1000.0).round() as usize,
+        }
+    }
+}
+
+impl Layer for Pollution {
+    fn name(&self) -> Option<&'static str> {
+        Some("emissions")
+    }
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        app: &mut App,
+        minimap: &Panel,
+    ) -> Option<LayerOutcome> {
+        if app.primary.sim.time() != self.time {
+            *self = Pollution::new(ctx, app, self.pollutant);
+        }
+
+        self.panel.align_above(ctx, minimap);
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "close" => {
+                    return Some(LayerOutcome::Close);
+                }
+                _ => unreachable!(),
+            },
+            Outcome::Changed => {
+                *self = Pollution::new(ctx, app, self.panel.dropdown_value("pollutant"));
+                self.panel.align_above(ctx, minimap);
+            }
+            _ => {}
+        }
+        None
+    }
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        self.panel.draw(g);
+        if g.canvas.cam_zoom < app.opts.min_zoom_for_detail {
+            g.redraw(&self.unzoomed);
+        } else {
+            g.redraw(&self.zoomed);
+        }
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.unzoomed);
+    }
+}
+
+impl Pollution {
+    pub fn new(ctx: &mut EventCtx, app: &App, pollutant: Pollutant) -> Pollution {
+        let stats = app.primary.sim.get_analytics();
+        let mut per_road = Counter::new();
+        for (r, e) in &stats.road_emissions {
+            per_road.add(*r, pollutant.amount(e));
+        }
+        let mut per_intersection = Counter::new();
+        for (i, e) in &stats.intersection_emissions {
+            per_intersection.add(*i, pollutant.amount(e));
+        }
+
+        let mut colorer = ColorNetwork::new(app);
+        colorer.ranked_roads(per_road, &app.cs.good_to_bad_red);
+        colorer.ranked_intersections(per_intersection, &app.cs.good_to_bad_red);
+        let (unzoomed, zoomed) = colorer.build(ctx);
+
+        let panel = Panel::new(Widget::col(vec![
+            Widget::row(vec![
+                Widget::draw_svg(ctx, "system/assets/tools/layers.svg"),
+                "Emissions".draw_text(ctx),
+                Btn::plaintext("X")
+                    .build(ctx, "close", hotkey(Key::Escape))
+                    .align_right(),
+            ]),
+            Text::from(
+                Line("This estimates what vehicles have emitted since midnight").secondary(),
+            )
+            .wrap_to_pct(ctx, 15)
+            .draw(ctx),
+            Widget::row(vec![
+                "Show:".draw_text(ctx),
+                Widget::dropdown(
+                    ctx,
+                    "pollutant",
+                    pollutant,
+                    vec![
+                        Choice::new("CO2", Pollutant::CO2),
+                        Choice::new("NOx", Pollutant::NOx),
+                        Choice::new("energy", Pollutant::Energy),
+                    ],
+                ),
+            ]),
+            ColorLegend::gradient(ctx, &app.cs.good_to_bad_red, vec!["lowest", "highest"]),
+        ]))
+        .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
+        .build(ctx);
+
+        Pollution {
+            time: app.primary.sim.time(),
+            pollutant,
+            unzoomed,
+            zoomed,
+            panel,
+        }
+    }
+}
+
 pub struct TrafficJams {
     time: Time,
     unzoomed: Drawable,
@@ -0,0 +1,97 @@
+use crate::app::App;
+use crate::layer::{Layer, LayerOutcome};
+use geom::Time;
+use sim::{Precipitation, VehicleType};
+use widgetry::{
+    hotkey, Btn, Color, Drawable, EventCtx, GeomBatch, GfxCtx, HorizontalAlignment, Key, Line,
+    Panel, Text, TextExt, VerticalAlignment, Widget,
+};
+
+pub struct Weather {
+    time: Time,
+    draw: Drawable,
+    panel: Panel,
+}
+
+impl Layer for Weather {
+    fn name(&self) -> Option<&'static str> {
+        Some("weather")
+    }
+    fn event(
+        &mut self,
+        ctx: &mut EventCtx,
+        app: &mut App,
+        minimap: &Panel,
+    ) -> Option<LayerOutcome> {
+        if app.primary.sim.time() != self.time {
+            *self = Weather::new(ctx, app);
+        }
+
+        Layer::simple_event(ctx, minimap, &mut self.panel)
+    }
+    fn draw(&self, g: &mut GfxCtx, _: &App) {
+        self.panel.draw(g);
+        g.redraw(&self.draw);
+    }
+    fn draw_minimap(&self, g: &mut GfxCtx) {
+        g.redraw(&self.draw);
+    }
+}
+
+impl Weather {
+    pub fn new(ctx: &mut EventCtx, app: &App) -> Weather {
+        let now = app.primary.sim.time();
+        let weather = app.primary.sim.get_weather();
+
+        // Tint the whole map
+        let mut batch = GeomBatch::new();
+        let boundary = app.primary.map.get_boundary_polygon();
+        match weather.precipitation {
+            Precipitation::Dry => {}
+            Precipitation::Rain => {
+                batch.push(Color::hex("#5C7A99").alpha(0.3), boundary.clone());
+            }
+            Precipitation::Snow => {
+                batch.push(Color::WHITE.alpha(0.4), boundary.clone());
+            }
+        }
+        if weather.is_dark(now) {
+            batch.push(Color::hex("#0B1A33").alpha(0.4), boundary.clone());
+        }
+
+        let mut txt = Text::new();
+        txt.add(Line(format!("Right now: {}", weather.describe(now))));
+        txt.add(Line(format!(
+            "Cars go {:.0}% and bikes {:.0}% of their usual speed",
+            weather.vehicle_speed_factor(VehicleType::Car, now) * 100.0,
+            weather.vehicle_speed_factor(VehicleType::Bike, now) * 100.0
+        )));
+        txt.add(Line(format!(
+            "People walk at {:.0}% of their usual speed",
+            weather.walking_speed_factor() * 100.0
+        )));
+        txt.add(Line(format!(
+            "Vehicles take {:.0}% as long to get through intersections",
+            weather.headway_factor(now) * 100.0
+        )));
+
+        let panel = Panel::new(Widget::col(vec![
+            Widget::row(vec![
+                Widget::draw_svg(ctx, "system/assets/tools/layers.svg"),
+                "Weather".draw_text(ctx),
+                Btn::plaintext("X")
+                    .build(ctx, "close", hotkey(Key::Escape))
+                    .align_right(),
+            ]),
+            txt.draw(ctx),
+        ]))
+        .aligned(HorizontalAlignment::Right, VerticalAlignment::Center)
+        .build(ctx);
+
+        Weather {
+            time: now,
+            draw: ctx.upload(batch),
+            panel,
+        }
+    }
+}
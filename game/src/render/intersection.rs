@@ -6,6 +6,7 @@ use crate::render::{
     draw_signal_stage, DrawOptions, Renderable, CROSSWALK_LINE_THICKNESS, OUTLINE_THICKNESS,
 };
 use geom::{Angle, ArrowCap, Distance, Line, PolyLine, Polygon, Pt2D, Ring, Time, EPSILON_DIST};
+use map_model::raw::CrossingType;
 use map_model::{
     Direction, DrivingSide, Intersection, IntersectionID, IntersectionType, Map, Road,
     RoadWithStopSign, Turn, TurnType, SIDEWALK_THICKNESS,
@@ -311,6 +312,10 @@ pub fn make_crosswalk(batch: &mut GeomBatch, turn: &Turn, map: &Map, cs: &ColorS
     if make_rainbow_crosswalk(batch, turn, map) {
         return;
     }
+    // Unmarked crossings are just where people cross; there's nothing to draw.
+    if map.get_parent(turn.id.src).crossing_at(turn.id.parent) == Some(CrossingType::Unmarked) {
+        return;
+    }
 
     // This size also looks better for shoulders
     let width = SIDEWALK_THICKNESS;
@@ -2,7 +2,8 @@ use crate::app::App;
 use crate::helpers::ID;
 use crate::render::{DrawOptions, Renderable};
 use geom::{Distance, Polygon, Pt2D};
-use map_model::{Map, Road, RoadID};
+use map_model::raw::CrossingType;
+use map_model::{Map, MidBlockCrossing, Road, RoadID, SIDEWALK_THICKNESS};
 use std::cell::RefCell;
 use widgetry::{Drawable, GeomBatch, GfxCtx, Line, Text};
 
@@ -65,6 +66,34 @@ impl Renderable for DrawRoad {
                     );
                 }
             }
+
+            // Zebra stripes for mid-block crosswalks
+            for crossing in MidBlockCrossing::all_on(r, &app.primary.map) {
+                if crossing.crossing_type == Some(CrossingType::Unmarked) {
+                    continue;
+                }
+                let line = crossing.geom(&app.primary.map);
+                let mut dist = SIDEWALK_THICKNESS;
+                while dist < line.length() - SIDEWALK_THICKNESS {
+                    if let Some(pt) = line.dist_along(dist) {
+                        batch.push(
+                            app.cs.general_road_marking,
+                            geom::Line::must_new(
+                                pt.project_away(
+                                    Distance::meters(1.5),
+                                    line.angle().rotate_degs(90.0),
+                                ),
+                                pt.project_away(
+                                    Distance::meters(1.5),
+                                    line.angle().rotate_degs(-90.0),
+                                ),
+                            )
+                            .make_polygons(Distance::meters(0.5)),
+                        );
+                    }
+                    dist += Distance::meters(1.0);
+                }
+            }
             *draw_center_line = Some(g.prerender.upload(batch));
         }
         g.redraw(draw_center_line.as_ref().unwrap());
@@ -5,8 +5,10 @@ use crate::sandbox::dashboards::trip_table::{make_table, preview_trip};
 use crate::sandbox::dashboards::DashTab;
 use crate::sandbox::SandboxMode;
 use abstutil::prettyprint_usize;
-use geom::Duration;
+use geom::{Distance, Duration};
+use map_model::{describe_cents, OVERSTAY_FINE_CENTS};
 use sim::{TripEndpoint, TripID, TripPhaseType};
+use std::collections::BTreeMap;
 use widgetry::{
     Btn, Checkbox, EventCtx, Filler, GfxCtx, Line, Outcome, Panel, Text, TextExt, Widget,
 };
@@ -49,6 +51,8 @@ enum SortBy {
     ParkingDuration,
     WalkingDuration,
     PercentOverhead,
+    ParkingCost,
+    Cruising,
 }
 
 impl ParkingOverhead {
@@ -97,6 +101,14 @@ impl State for ParkingOverhead {
                     self.opts.change(SortBy::PercentOverhead);
                     self.recalc(ctx, app);
                 }
+                "Parking cost" => {
+                    self.opts.change(SortBy::ParkingCost);
+                    self.recalc(ctx, app);
+                }
+                "Cruising distance" => {
+                    self.opts.change(SortBy::Cruising);
+                    self.recalc(ctx, app);
+                }
                 "previous trips" => {
                     self.opts.skip -= ROWS;
                     self.recalc(ctx, app);
@@ -156,10 +168,28 @@ struct Entry {
     parking_duration: Duration,
     walking_duration: Duration,
     percent_overhead: usize,
+    // In cents, and whether the car overstayed the time limit
+    parking_cost: usize,
+    overstayed: bool,
+    // Extra distance driven looking for a spot
+    cruising: Distance,
 }
 
 fn make(ctx: &mut EventCtx, app: &App, opts: &Options) -> Panel {
     // Gather raw data
+    let mut fees: BTreeMap<TripID, (usize, bool)> = BTreeMap::new();
+    for (_, trip, cents, overstayed) in &app.primary.sim.get_analytics().parking_fees {
+        let entry = fees.entry(*trip).or_insert((0, false));
+        entry.0 += *cents;
+        entry.1 |= *overstayed;
+    }
+    let mut cruising: BTreeMap<TripID, Distance> = BTreeMap::new();
+    for (_, trip, _, dist) in &app.primary.sim.get_analytics().parking_cruising {
+        *cruising.entry(*trip).or_insert(Distance::ZERO) += *dist;
+    }
+    let mut total_fees = 0;
+    let mut num_overstays = 0;
+
     let mut data = Vec::new();
     for (id, phases) in app.primary.sim.get_analytics().get_all_trip_phases() {
         let trip = app.primary.sim.trip_info(id);
@@ -204,6 +234,11 @@ fn make(ctx: &mut EventCtx, app: &App, opts: &Options) -> Panel {
             continue;
         }
 
+        let (parking_cost, overstayed) = fees.get(&id).cloned().unwrap_or((0, false));
+        total_fees += parking_cost;
+        if overstayed {
+            num_overstays += 1;
+        }
         data.push(Entry {
             trip: id,
             total_duration,
@@ -211,6 +246,9 @@ fn make(ctx: &mut EventCtx, app: &App, opts: &Options) -> Panel {
             parking_duration,
             walking_duration,
             percent_overhead: (100.0 * (1.0 - (driving_duration / total_duration))) as usize,
+            parking_cost,
+            overstayed,
+            cruising: cruising.get(&id).cloned().unwrap_or(Distance::ZERO),
         });
     }
 
@@ -221,6 +259,8 @@ fn make(ctx: &mut EventCtx, app: &App, opts: &Options) -> Panel {
         SortBy::ParkingDuration => data.sort_by_key(|x| x.parking_duration),
         SortBy::WalkingDuration => data.sort_by_key(|x| x.walking_duration),
         SortBy::PercentOverhead => data.sort_by_key(|x| x.percent_overhead),
+        SortBy::ParkingCost => data.sort_by_key(|x| x.parking_cost),
+        SortBy::Cruising => data.sort_by_key(|x| x.cruising),
     }
     if opts.descending {
         data.reverse();
@@ -239,6 +279,13 @@ fn make(ctx: &mut EventCtx, app: &App, opts: &Options) -> Panel {
                 Text::from(Line(x.parking_duration.to_string())).render_ctx(ctx),
                 Text::from(Line(x.walking_duration.to_string())).render_ctx(ctx),
                 Text::from(Line(format!("{}%", x.percent_overhead))).render_ctx(ctx),
+                Text::from(Line(if x.overstayed {
+                    format!("{} (overstayed)", describe_cents(x.parking_cost))
+                } else {
+                    describe_cents(x.parking_cost)
+                }))
+                .render_ctx(ctx),
+                Text::from(Line(x.cruising.to_string())).render_ctx(ctx),
             ],
         ));
     }
@@ -262,6 +309,8 @@ fn make(ctx: &mut EventCtx, app: &App, opts: &Options) -> Panel {
         btn(SortBy::ParkingDuration, "Parking duration"),
         btn(SortBy::WalkingDuration, "Walking duration"),
         btn(SortBy::PercentOverhead, "Percent overhead"),
+        btn(SortBy::ParkingCost, "Parking cost"),
+        btn(SortBy::Cruising, "Cruising distance"),
     ];
 
     let mut col = vec![DashTab::ParkingOverhead.picker(ctx, app)];
@@ -285,6 +334,14 @@ fn make(ctx: &mut EventCtx, app: &App, opts: &Options) -> Panel {
                      overhead,",
                 ),
                 Line("since the time spent driving off-map isn't shown here."),
+                Line(""),
+                Line(format!(
+                    "Parking cost {} in total, and {} trips overstayed a time limit, paying a \
+                     {} fine. Trips pay once their car leaves the spot.",
+                    describe_cents(total_fees),
+                    prettyprint_usize(num_overstays),
+                    describe_cents(OVERSTAY_FINE_CENTS)
+                )),
             ])
             .draw(ctx),
             Filler::square_width(ctx, 0.15).named("preview"),
@@ -206,6 +206,14 @@ impl EditScenarioModifiers {
             Spinner::new(ctx, (1, 100), 1).named("cancel_pct"),
             Btn::text_bg2("Cancel all trips for some percent of people").build_def(ctx, None),
         ]));
+        rows.push(Widget::row(vec![
+            Spinner::new(ctx, (0, 5), 1).named("max_cars"),
+            Btn::text_bg2("Limit cars per household").build_def(ctx, None),
+        ]));
+        rows.push(Widget::row(vec![
+            Spinner::new(ctx, (1, 1000), 100).named("num_tours"),
+            Btn::text_bg2("Add delivery tours").build_def(ctx, None),
+        ]));
         rows.push(
             Widget::row(vec![
                 Btn::text_bg2("Apply").build_def(ctx, hotkey(Key::Enter)),
@@ -298,6 +306,26 @@ impl State for EditScenarioModifiers {
                         self.modifiers.clone(),
                     ));
                 }
+                "Limit cars per household" => {
+                    self.modifiers.push(ScenarioModifier::LimitCarsPerHousehold(
+                        self.panel.spinner("max_cars") as usize,
+                    ));
+                    return Transition::Replace(EditScenarioModifiers::new(
+                        ctx,
+                        self.scenario_name.clone(),
+                        self.modifiers.clone(),
+                    ));
+                }
+                "Add delivery tours" => {
+                    self.modifiers.push(ScenarioModifier::AddDeliveryTours(
+                        self.panel.spinner("num_tours") as usize,
+                    ));
+                    return Transition::Replace(EditScenarioModifiers::new(
+                        ctx,
+                        self.scenario_name.clone(),
+                        self.modifiers.clone(),
+                    ));
+                }
                 x => {
                     if let Some(x) = x.strip_prefix("delete modifier ") {
                         self.modifiers.remove(x.parse::<usize>().unwrap() - 1);
@@ -14,8 +14,9 @@ use geom::{ArrowCap, Distance, Duration, PolyLine, Pt2D, Time};
 use map_model::raw::OriginalRoad;
 use map_model::{osm, BuildingID, DirectedRoadID, Direction, Map, Position};
 use sim::{
-    AgentID, Analytics, BorderSpawnOverTime, CarID, DrivingGoal, IndividTrip, OriginDestination,
-    PersonID, PersonSpec, Scenario, ScenarioGenerator, SpawnOverTime, SpawnTrip, VehicleType,
+    AgentID, Analytics, BorderSpawnOverTime, CarID, DepartureProfile, DrivingGoal, IndividTrip,
+    OriginDestination, PersonID, PersonSpec, Scenario, ScenarioGenerator, SpawnOverTime, SpawnTrip,
+    VehicleType,
 };
 use std::collections::BTreeSet;
 use widgetry::{
@@ -643,8 +644,10 @@ fn make_bike_lane_scenario(map: &Map) -> ScenarioGenerator {
         num_cars: 10,
         num_bikes: 10,
         percent_use_transit: 0.0,
-        start_time: Time::START_OF_DAY,
-        stop_time: Time::START_OF_DAY + Duration::seconds(10.0),
+        departure: DepartureProfile::uniform(
+            Time::START_OF_DAY,
+            Time::START_OF_DAY + Duration::seconds(10.0),
+        ),
         start_from_border: DirectedRoadID {
             id: map
                 .find_r_by_osm_id(OriginalRoad::new(263665925, (2499826475, 53096959)))
@@ -1160,8 +1163,10 @@ impl TutorialState {
                         only_seed_buses: Some(BTreeSet::new()),
                         spawn_over_time: vec![SpawnOverTime {
                             num_agents: 1000,
-                            start_time: Time::START_OF_DAY,
-                            stop_time: Time::START_OF_DAY + Duration::hours(3),
+                            departure: DepartureProfile::uniform(
+                                Time::START_OF_DAY,
+                                Time::START_OF_DAY + Duration::hours(3),
+                            ),
                             goal: OriginDestination::Anywhere,
                             percent_driving: 1.0,
                             percent_biking: 0.0,
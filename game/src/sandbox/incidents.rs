@@ -0,0 +1,144 @@
+use crate::app::App;
+use crate::game::{State, Transition};
+use geom::Duration;
+use map_model::LaneID;
+use sim::{Incident, IncidentLocation, IncidentReport};
+use widgetry::{
+    hotkey, Btn, Checkbox, EventCtx, GfxCtx, Key, Line, Outcome, Panel, Spinner, Text, TextExt,
+    Widget,
+};
+
+// Block or slow down a lane later in the simulation, and see how earlier incidents played out.
+pub struct IncidentScheduler {
+    lane: LaneID,
+    panel: Panel,
+}
+
+impl IncidentScheduler {
+    pub fn new(ctx: &mut EventCtx, app: &App, lane: LaneID) -> Box<dyn State> {
+        let mut col = vec![
+            Widget::row(vec![
+                Line(format!("Schedule an incident on {}", lane))
+                    .small_heading()
+                    .draw(ctx),
+                Btn::plaintext("X")
+                    .build(ctx, "close", hotkey(Key::Escape))
+                    .align_right(),
+            ]),
+            Widget::row(vec![
+                "Starts in (minutes)".draw_text(ctx).centered_vert(),
+                Spinner::new(ctx, (0, 240), 0).named("start"),
+            ]),
+            Widget::row(vec![
+                "Lasts (minutes)".draw_text(ctx).centered_vert(),
+                Spinner::new(ctx, (1, 480), 30).named("duration"),
+            ]),
+            Widget::row(vec![
+                "Capacity left (%)".draw_text(ctx).centered_vert(),
+                Spinner::new(ctx, (0, 90), 0).named("capacity"),
+            ]),
+            Checkbox::switch(ctx, "block every lane going this way", None, false),
+            Btn::text_bg2("Schedule").build_def(ctx, hotkey(Key::Enter)),
+        ];
+
+        let incidents = app.primary.sim.get_incidents();
+        if !incidents.is_empty() {
+            col.push(Widget::horiz_separator(ctx, 0.25));
+            col.push(Line("All incidents").small_heading().draw(ctx));
+            for (id, (incident, active, report)) in incidents.into_iter().enumerate() {
+                col.push(describe(app, id, incident, active, report).draw(ctx));
+            }
+        }
+
+        Box::new(IncidentScheduler {
+            lane,
+            panel: Panel::new(Widget::col(col)).build(ctx),
+        })
+    }
+}
+
+impl State for IncidentScheduler {
+    fn event(&mut self, ctx: &mut EventCtx, app: &mut App) -> Transition {
+        match self.panel.event(ctx) {
+            Outcome::Clicked(x) => match x.as_ref() {
+                "close" => {
+                    return Transition::Pop;
+                }
+                "Schedule" => {
+                    let map = &app.primary.map;
+                    let lanes = if self.panel.is_checked("block every lane going this way") {
+                        let road = map.get_parent(self.lane);
+                        let dir = road.dir(self.lane);
+                        road.lanes_ltr()
+                            .into_iter()
+                            .filter(|(_, d, lt)| *d == dir && lt.is_for_moving_vehicles())
+                            .map(|(l, _, _)| l)
+                            .collect()
+                    } else {
+                        vec![self.lane]
+                    };
+                    let start = app.primary.sim.time()
+                        + Duration::minutes(self.panel.spinner("start") as usize);
+                    app.primary.sim.schedule_incident(Incident {
+                        start,
+                        duration: Duration::minutes(self.panel.spinner("duration") as usize),
+                        location: IncidentLocation::Lanes(lanes),
+                        capacity: (self.panel.spinner("capacity") as f64) / 100.0,
+                    });
+                    return Transition::Pop;
+                }
+                _ => unreachable!(),
+            },
+            _ => {}
+        }
+
+        if self.panel.clicked_outside(ctx) {
+            return Transition::Pop;
+        }
+
+        Transition::Keep
+    }
+
+    fn draw(&self, g: &mut GfxCtx, app: &App) {
+        State::grey_out_map(g, app);
+        self.panel.draw(g);
+    }
+}
+
+fn describe(
+    app: &App,
+    id: usize,
+    incident: &Incident,
+    active: bool,
+    report: &IncidentReport,
+) -> Text {
+    let mut txt = Text::from(Line(format!(
+        "Incident #{}{}",
+        id + 1,
+        if active { " (happening now)" } else { "" }
+    )));
+    txt.add(Line(incident.describe()).secondary());
+    txt.add(Line(format!(
+        "{} held up for {} in total",
+        report.agents_delayed, report.delay
+    )));
+    txt.add(Line(format!(
+        "{} drivers rerouted, {} couldn't avoid it",
+        report.rerouted, report.stuck
+    )));
+
+    // Knock-on effects show up when comparing to the same day without the incident.
+    let now = app.primary.sim.time();
+    if app.has_prebaked().is_some() && now > incident.start {
+        let (trips, extra) = app.primary.sim.get_analytics().incident_delay(
+            now,
+            incident.start,
+            &app.prebaked().finished_trips,
+        );
+        txt.add(Line(format!(
+            "Compared to the baseline, {} trips took {} longer in total",
+            trips, extra
+        )));
+    }
+    txt
+}
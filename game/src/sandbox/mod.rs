@@ -1,5 +1,6 @@
 mod dashboards;
 pub mod gameplay;
+mod incidents;
 mod misc_tools;
 mod speed;
 mod time_warp;
@@ -433,6 +434,9 @@ impl ContextualActions for Actions {
                     if can_edit_lane(&self.gameplay, l, app) {
                         actions.push((Key::E, "edit lane".to_string()));
                     }
+                    if !app.primary.map.get_turns_to_lane(l).is_empty() {
+                        actions.push((Key::I, "schedule an incident".to_string()));
+                    }
                 }
                 ID::Building(_) => {
                     if app.opts.dev {
@@ -481,6 +485,9 @@ impl ContextualActions for Actions {
                 Transition::Push(EditMode::new(ctx, app, self.gameplay.clone())),
                 Transition::Push(LaneEditor::new(ctx, app, l, self.gameplay.clone())),
             ]),
+            (ID::Lane(l), "schedule an incident") => {
+                Transition::Push(incidents::IncidentScheduler::new(ctx, app, l))
+            }
             (ID::Building(b), "explore isochrone from here") => {
                 Transition::Push(IsochroneViewer::new(ctx, app, b))
             }
@@ -16,10 +16,10 @@ use map_model::{
     CompressedMovementID, ControlTrafficSignal, EditCmd, EditIntersection, IntersectionID, Map,
     MovementID, PermanentMapEdits,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use sim::{
-    AlertHandler, ExternalPerson, GetDrawAgents, PersonID, Scenario, Sim, SimFlags, SimOptions,
-    TripID, TripMode, VehicleType,
+    AlertHandler, ExternalPerson, GetDrawAgents, Incident, IncidentReport, PersonID, Scenario, Sim,
+    SimFlags, SimOptions, TripID, TripMode, VehicleType,
 };
 use std::collections::{BTreeMap, HashMap};
 use std::convert::TryFrom;
@@ -118,6 +118,10 @@ fn handle_command(
                 Ok(format!("it's now {}", t))
             }
         }
+        "/sim/save-replay" => match sim.save_replay_log() {
+            Some(path) => Ok(format!("replay log written to {}", path)),
+            None => Err("the sim isn't recording; load it with --record_replay".into()),
+        },
         "/sim/new-person" => {
             let input: ExternalPerson = abstutil::from_json(body)?;
             for trip in &input.trips {
@@ -219,6 +223,38 @@ fn handle_command(
             }
             Ok(abstutil::to_json(&thruput))
         }
+        // Incidents
+        "/incidents/add" => {
+            let incident: Incident = abstutil::from_json(body)?;
+            let id = sim.schedule_incident(incident);
+            Ok(format!("incident {} scheduled", id))
+        }
+        "/incidents/get" => Ok(abstutil::to_json(&Incidents {
+            incidents: sim
+                .get_incidents()
+                .into_iter()
+                .map(|(incident, active, report)| IncidentStatus {
+                    incident: incident.clone(),
+                    active,
+                    report: report.clone(),
+                })
+                .collect(),
+        })),
+        "/incidents/get-network-delay" => {
+            // The body is the output of /data/get-finished-trips from a run without the incident.
+            let id = params["id"].parse::<usize>()?;
+            let baseline: FinishedTrips = abstutil::from_json(body)?;
+            let incidents = sim.get_incidents();
+            if id >= incidents.len() {
+                return Err(format!("there's no incident {}", id).into());
+            }
+            let (trips, extra_time) = sim.get_analytics().incident_delay(
+                sim.time(),
+                incidents[id].0.start,
+                &baseline.trips,
+            );
+            Ok(abstutil::to_json(&NetworkDelay { trips, extra_time }))
+        }
         // Querying data
         "/data/get-finished-trips" => Ok(abstutil::to_json(&FinishedTrips {
             trips: sim.get_analytics().finished_trips.clone(),
@@ -249,7 +285,7 @@ fn handle_command(
 
 // TODO I think specifying the API with protobufs or similar will be a better idea.
 
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 struct FinishedTrips {
     // TODO Hack: No TripMode means aborted
     // Finish time, ID, mode (or None as aborted), trip duration
@@ -268,6 +304,28 @@ struct Throughput {
     per_direction: BTreeMap<MovementID, usize>,
 }
 
+#[derive(Serialize)]
+struct Incidents {
+    // Indexed by ID
+    incidents: Vec<IncidentStatus>,
+}
+
+#[derive(Serialize)]
+struct IncidentStatus {
+    incident: Incident,
+    // Happening right now?
+    active: bool,
+    report: IncidentReport,
+}
+
+#[derive(Serialize)]
+struct NetworkDelay {
+    // How many trips finished in both runs after the incident started
+    trips: usize,
+    // How much longer they took in total. Negative means faster.
+    extra_time: Duration,
+}
+
 #[derive(Serialize)]
 struct AgentPositions {
     agents: Vec<AgentPosition>,
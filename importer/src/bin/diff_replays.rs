@@ -0,0 +1,21 @@
+use abstutil::{CmdArgs, Timer};
+use sim::ReplayLog;
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let first: ReplayLog = abstutil::read_binary(args.required_free(), &mut Timer::throwaway());
+    let second: ReplayLog = abstutil::read_binary(args.required_free(), &mut Timer::throwaway());
+    args.done();
+
+    match first.find_divergence(&second) {
+        Some(lines) => {
+            for line in lines {
+                println!("{}", line);
+            }
+            std::process::exit(1);
+        }
+        None => {
+            println!("Both logs match for {} commands", first.hashes.len());
+        }
+    }
+}
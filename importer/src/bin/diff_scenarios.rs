@@ -0,0 +1,22 @@
+use abstutil::{CmdArgs, Timer};
+use map_model::Map;
+use sim::{Scenario, ScenarioDiff};
+
+fn main() {
+    let mut args = CmdArgs::new();
+    let before: Scenario = abstutil::read_binary(args.required_free(), &mut Timer::throwaway());
+    let after: Scenario = abstutil::read_binary(args.required_free(), &mut Timer::throwaway());
+    args.done();
+
+    let map = Map::new(
+        abstutil::path_map(&before.map_name),
+        &mut Timer::throwaway(),
+    );
+    let diff = ScenarioDiff::new(&before, &after, &map);
+    for line in diff.describe() {
+        println!("{}", line);
+    }
+    if !diff.is_empty() {
+        std::process::exit(1);
+    }
+}
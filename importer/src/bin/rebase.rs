@@ -0,0 +1,61 @@
+use abstutil::{CmdArgs, Timer};
+use map_model::{Map, MapEdits, MapMatcher, PermanentMapEdits};
+use sim::Scenario;
+
+// Moves saved edits and scenarios from an old import of a map to a newer one. IDs change every
+// import, and OSM ways get split or redrawn, so this matches everything up and lists whatever
+// couldn't be carried over. The rebased files overwrite the originals for the new map.
+//
+// rebase --old_map=old_copy.bin [--new_map=data/system/maps/foo.bin] edits.json scenario.bin ...
+fn main() {
+    let mut args = CmdArgs::new();
+    let old_map = args.required("--old_map");
+    let new_map = args.optional("--new_map");
+    let mut inputs = vec![args.required_free()];
+    while let Some(path) = args.optional_free() {
+        inputs.push(path);
+    }
+    args.done();
+
+    let mut timer = Timer::new("rebase edits and scenarios");
+    let old_map = Map::new(old_map, &mut timer);
+    let new_map = Map::new(
+        new_map.unwrap_or_else(|| abstutil::path_map(old_map.get_name())),
+        &mut timer,
+    );
+    let matcher = MapMatcher::new(&old_map, &new_map);
+
+    for path in inputs {
+        let problems = if path.ends_with(".json") {
+            let edits = match MapEdits::load(&old_map, path.clone(), &mut timer) {
+                Ok(edits) => edits,
+                Err(err) => panic!("{} doesn't work with the old map: {}", path, err),
+            };
+            let (perma, problems) =
+                PermanentMapEdits::to_permanent(&edits, &old_map).rebase(&matcher);
+            let output = abstutil::path_edits(new_map.get_name(), &perma.edits_name);
+            abstutil::write_json(output.clone(), &perma);
+            println!("Rebased {} to {}", path, output);
+            problems
+        } else {
+            let scenario: Scenario = abstutil::read_binary(path.clone(), &mut timer);
+            let (scenario, problems) = scenario.rebase(&matcher);
+            scenario.save();
+            println!(
+                "Rebased {} to {}",
+                path,
+                abstutil::path_scenario(&scenario.map_name, &scenario.scenario_name)
+            );
+            problems
+        };
+
+        if problems.is_empty() {
+            println!("Everything was carried over");
+        } else {
+            println!("{} problems:", problems.len());
+            for problem in problems {
+                println!("- {}", problem);
+            }
+        }
+    }
+}
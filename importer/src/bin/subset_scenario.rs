@@ -0,0 +1,73 @@
+use abstutil::{CmdArgs, Timer};
+use geom::{LonLat, Ring, Time};
+use map_model::Map;
+use sim::{Scenario, ScenarioFilter, TripMode};
+
+// Combines scenarios and keeps only some of their trips, for building small test fixtures.
+//
+// subset_scenario input.bin [more_inputs.bin ...] --output=out.bin --name=fixture
+//   [--departure=7:00:00,9:00:00] [--area=polygon.poly] [--modes=walk,bike]
+fn main() {
+    let mut args = CmdArgs::new();
+    let output = args.required("--output");
+    let name = args.optional("--name");
+    let departure = args.optional("--departure");
+    let area = args.optional("--area");
+    let modes = args.optional("--modes");
+    let mut inputs = vec![args.required_free()];
+    while let Some(path) = args.optional_free() {
+        inputs.push(path);
+    }
+    args.done();
+
+    let mut timer = Timer::new("subset scenario");
+    let mut scenario: Scenario = abstutil::read_binary(inputs.remove(0), &mut timer);
+    for path in inputs {
+        scenario = scenario.merge(abstutil::read_binary(path, &mut timer));
+    }
+    let map = Map::new(abstutil::path_map(&scenario.map_name), &mut timer);
+
+    let filter = ScenarioFilter {
+        departure: departure.map(|x| {
+            let parts: Vec<&str> = x.split(',').collect();
+            if parts.len() != 2 {
+                panic!("--departure={} should be start,end", x);
+            }
+            (
+                Time::parse(parts[0]).unwrap(),
+                Time::parse(parts[1]).unwrap(),
+            )
+        }),
+        area: area.map(|path| {
+            let pts = LonLat::read_osmosis_polygon(path).unwrap();
+            Ring::must_new(map.get_gps_bounds().convert(&pts)).to_polygon()
+        }),
+        modes: modes.map(|x| {
+            x.split(',')
+                .map(|mode| match mode {
+                    "walk" => TripMode::Walk,
+                    "bike" => TripMode::Bike,
+                    "transit" => TripMode::Transit,
+                    "drive" => TripMode::Drive,
+                    "ridehail" => TripMode::RideHail,
+                    _ => panic!("Unknown mode {}", mode),
+                })
+                .collect()
+        }),
+    };
+    let before = scenario.people.len();
+    let (mut scenario, removed) = scenario.subset(&filter, &map);
+    if let Some(name) = name {
+        scenario.scenario_name = name;
+    }
+    for (_, err) in &removed {
+        println!("{}", err);
+    }
+    println!(
+        "Kept {} of {} people. {} more had nonsense schedules after removing trips.",
+        abstutil::prettyprint_usize(scenario.people.len()),
+        abstutil::prettyprint_usize(before),
+        abstutil::prettyprint_usize(removed.len())
+    );
+    abstutil::write_binary(output, &scenario);
+}
@@ -0,0 +1,315 @@
+use crate::configuration::ImporterConfiguration;
+use crate::utils::{download, download_kml};
+use abstutil::Timer;
+use convert_osm::{
+    OnstreetParking, ParkingShapes, PrivateOffstreetParking, PublicOffstreetParking,
+};
+use map_model::{Map, MapConfig};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+// Describes how to import every map in one city. This is read from data/input/$city/config.json,
+// so adding a new city doesn't need any code changes. All paths are relative to data/. Maps are
+// defined by the clipping polygons in data/input/$city/polygons/.
+#[derive(Deserialize)]
+pub struct GenericCityImporter {
+    // A .osm or .osm.pbf file covering the whole city
+    pub osm_path: String,
+    // If osm_path doesn't exist, download it from here. Leave this out to import offline from a
+    // local file.
+    pub osm_url: Option<String>,
+    // Other files to download before importing, if they don't exist already
+    #[serde(default)]
+    pub downloads: Vec<Download>,
+    #[serde(default)]
+    pub kml_downloads: Vec<KmlDownload>,
+
+    pub map_config: MapConfig,
+    pub onstreet_parking: OnstreetParking,
+    pub public_offstreet_parking: PublicOffstreetParking,
+    pub private_offstreet_parking: PrivateOffstreetParking,
+    // Overrides private_offstreet_parking for some maps
+    #[serde(default)]
+    pub private_offstreet_parking_per_map: BTreeMap<String, PrivateOffstreetParking>,
+    // An SRTM .hgt or GeoTIFF file
+    pub elevation: Option<String>,
+    pub include_railroads: bool,
+
+    // How to fill in the number of residents per building after building each map
+    pub population: Option<PopulationSource>,
+    // A directory with GTFS files, used for bus schedules
+    pub gtfs: Option<String>,
+    // What to produce with --scenario
+    #[serde(default)]
+    pub scenarios: Vec<ScenarioSource>,
+}
+
+#[derive(Deserialize)]
+pub struct Download {
+    pub url: String,
+    // A file, or a directory ending in / to unzip into
+    pub output: String,
+}
+
+// Downloads a .kml or .geojson file, clips it, and converts it to ExtraShapes.
+#[derive(Deserialize)]
+pub struct KmlDownload {
+    pub url: String,
+    // A .bin file
+    pub output: String,
+    // An osmosis polygon to clip shapes to
+    pub bounds: String,
+    // If false, keep shapes partly out-of-bounds
+    pub require_all_pts_in_bounds: bool,
+}
+
+#[derive(Deserialize)]
+pub enum PopulationSource {
+    // ExtraShapes covering the city, like census tracts, each with the number of residents living
+    // there. The estimates for each building in an area are scaled to match. Jobs work the same
+    // way, if the areas have them.
+    Areas {
+        shapes: String,
+        // Uniquely identifies each area
+        id_attribute: String,
+        residents_attribute: String,
+        jobs_attribute: Option<String>,
+        // If the numbers are in a separate CSV file, first copy them into residents_attribute and
+        // jobs_attribute.
+        csv: Option<PopulationCSV>,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct PopulationCSV {
+    pub path: String,
+    pub delimiter: char,
+    // Matches id_attribute
+    pub id_column: String,
+    pub residents_column: String,
+    pub jobs_column: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub enum ScenarioSource {
+    // Based on PSRC's Soundcast model. Only works for Seattle, and needs the scenarios feature.
+    // --scenario_everyone also uses this.
+    Soundcast {
+        // For these maps, make the private parking per building match the scenario
+        adjust_private_parking: Vec<String>,
+    },
+    // People commuting between homes and workplaces, based on building types
+    HomeToWork {
+        scenario_name: String,
+        rng_seed: u8,
+    },
+}
+
+impl GenericCityImporter {
+    pub fn load(city: &str) -> Result<GenericCityImporter, String> {
+        let path = abstutil::path(format!("input/{}/config.json", city));
+        abstutil::maybe_read_json(path.clone(), &mut Timer::throwaway()).map_err(|err| {
+            format!(
+                "Can't import {}; {} is missing or broken: {}",
+                city, path, err
+            )
+        })
+    }
+
+    pub fn osm_to_raw(
+        &self,
+        city: &str,
+        name: &str,
+        timer: &mut Timer,
+        config: &ImporterConfiguration,
+    ) {
+        self.download_inputs(config, timer);
+
+        let map = convert_osm::convert(
+            self.convert_osm_options(
+                abstutil::path(&self.osm_path),
+                city,
+                name,
+                Some(abstutil::path(format!(
+                    "input/{}/polygons/{}.poly",
+                    city, name
+                ))),
+            ),
+            timer,
+        );
+        let output = abstutil::path(format!("input/raw_maps/{}.bin", name));
+        // When re-importing, describe what changed in OSM, so saved edits and scenarios can be
+        // checked or moved over with the rebase tool.
+        if abstutil::file_exists(&output) {
+            if let Ok(old) = abstutil::maybe_read_binary(output.clone(), timer) {
+                let changes = crate::raw_diff::describe_changes(&old, &map);
+                let report = abstutil::path(format!("input/raw_maps/{}_changes.txt", name));
+                timer.note(format!(
+                    "{} roads and intersections changed since the last import of {}. Details in \
+                     {}",
+                    abstutil::prettyprint_usize(changes.len()),
+                    name,
+                    report
+                ));
+                std::fs::write(report, changes.join("\n")).unwrap();
+            }
+        }
+        abstutil::write_binary(output, &map);
+    }
+
+    pub fn convert_osm_options(
+        &self,
+        osm_input: String,
+        city: &str,
+        name: &str,
+        clip: Option<String>,
+    ) -> convert_osm::Options {
+        convert_osm::Options {
+            osm_input,
+            city_name: city.to_string(),
+            name: name.to_string(),
+
+            clip,
+            map_config: self.map_config.clone(),
+
+            onstreet_parking: match self.onstreet_parking {
+                OnstreetParking::Blockface(ref path) => {
+                    OnstreetParking::Blockface(abstutil::path(path))
+                }
+                OnstreetParking::GIS(ref source) => OnstreetParking::GIS(ParkingShapes {
+                    path: abstutil::path(&source.path),
+                    ..source.clone()
+                }),
+                ref x => x.clone(),
+            },
+            public_offstreet_parking: match self.public_offstreet_parking {
+                PublicOffstreetParking::GIS(ref path) => {
+                    PublicOffstreetParking::GIS(abstutil::path(path))
+                }
+                PublicOffstreetParking::GenericGIS(ref source) => {
+                    PublicOffstreetParking::GenericGIS(ParkingShapes {
+                        path: abstutil::path(&source.path),
+                        ..source.clone()
+                    })
+                }
+                ref x => x.clone(),
+            },
+            private_offstreet_parking: self
+                .private_offstreet_parking_per_map
+                .get(name)
+                .unwrap_or(&self.private_offstreet_parking)
+                .clone(),
+            elevation: self.elevation.as_ref().map(abstutil::path),
+            include_railroads: self.include_railroads,
+        }
+    }
+
+    // Steps to run after turning a RawMap into a Map
+    pub fn after_raw_to_map(&self, map: &mut Map, timer: &mut Timer) {
+        if let Some(PopulationSource::Areas {
+            ref shapes,
+            ref id_attribute,
+            ref residents_attribute,
+            ref jobs_attribute,
+            ..
+        }) = self.population
+        {
+            timer.start(format!("distribute population for {}", map.get_name()));
+            crate::population::distribute_population(
+                map,
+                shapes,
+                id_attribute,
+                residents_attribute,
+                jobs_attribute.as_ref(),
+                timer,
+            );
+            timer.stop(format!("distribute population for {}", map.get_name()));
+        }
+
+        if let Some(ref dir) = self.gtfs {
+            timer.start(format!("add GTFS schedules for {}", map.get_name()));
+            crate::gtfs::add_gtfs_schedules(map, dir);
+            timer.stop(format!("add GTFS schedules for {}", map.get_name()));
+        }
+    }
+
+    pub fn uses_soundcast(&self) -> bool {
+        self.scenarios
+            .iter()
+            .any(|s| matches!(s, ScenarioSource::Soundcast { .. }))
+    }
+
+    fn download_inputs(&self, config: &ImporterConfiguration, timer: &mut Timer) {
+        if let Some(ref url) = self.osm_url {
+            download(config, &self.osm_path, url);
+        } else if !abstutil::file_exists(abstutil::path(&self.osm_path)) {
+            panic!(
+                "{} doesn't exist, and there's no osm_url to download it from",
+                abstutil::path(&self.osm_path)
+            );
+        }
+        for dl in &self.downloads {
+            download(config, &dl.output, &dl.url);
+        }
+        for dl in &self.kml_downloads {
+            let bounds = geom::GPSBounds::from(
+                geom::LonLat::read_osmosis_polygon(abstutil::path(&dl.bounds)).unwrap(),
+            );
+            download_kml(
+                &dl.output,
+                &dl.url,
+                &bounds,
+                dl.require_all_pts_in_bounds,
+                timer,
+            );
+        }
+
+        if let Some(PopulationSource::Areas {
+            ref shapes,
+            ref id_attribute,
+            ref residents_attribute,
+            ref jobs_attribute,
+            csv: Some(ref csv),
+        }) = self.population
+        {
+            // Always do this, it's idempotent and fast
+            crate::population::correlate_population(
+                shapes,
+                id_attribute,
+                residents_attribute,
+                jobs_attribute.as_ref(),
+                csv,
+                timer,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_every_config() {
+        let mut cities = 0;
+        for dir in abstutil::list_dir(std::path::Path::new(&abstutil::path("input"))) {
+            if !abstutil::file_exists(format!("{}/config.json", dir)) {
+                continue;
+            }
+            if let Err(err) = GenericCityImporter::load(&abstutil::basename(&dir)) {
+                panic!("{}", err);
+            }
+            cities += 1;
+        }
+        assert!(cities > 0);
+    }
+
+    #[test]
+    fn test_missing_config() {
+        let err = match GenericCityImporter::load("atlantis") {
+            Ok(_) => panic!("atlantis doesn't have a config"),
+            Err(err) => err,
+        };
+        assert!(err.contains("input/atlantis/config.json"));
+    }
+}
@@ -1,17 +1,20 @@
-mod berlin;
 mod configuration;
 mod dependencies;
-mod krakow;
-mod london;
+mod generic;
+mod gtfs;
+mod population;
+mod raw_diff;
+#[cfg(feature = "scenarios")]
 mod seattle;
 #[cfg(feature = "scenarios")]
 mod soundcast;
-mod tel_aviv;
 mod utils;
-mod xian;
 
 use configuration::{load_configuration, ImporterConfiguration};
 use dependencies::are_dependencies_callable;
+use generic::{GenericCityImporter, ScenarioSource};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
 
 // TODO Might be cleaner to express as a dependency graph?
 
@@ -29,17 +32,19 @@ struct Job {
     oneshot: Option<String>,
     oneshot_clip: Option<String>,
     oneshot_drive_on_left: bool,
+    oneshot_config: Option<String>,
 }
 
 fn main() {
     let mut args = abstutil::CmdArgs::new();
     let job = Job {
         city: args.optional("--city").unwrap_or("seattle".to_string()),
-        // Download all raw input files, then convert OSM to the intermediate RawMap.
+        // Download all raw input files, then convert OSM to the intermediate RawMap. Everything
+        // about the city comes from data/input/$city/config.json.
         osm_to_raw: args.enabled("--raw"),
         // Convert the RawMap to the final Map format.
         raw_to_map: args.enabled("--map"),
-        // Download trip demand data, then produce the typical weekday scenario.
+        // Produce the scenarios listed in the city's config.
         scenario: args.enabled("--scenario"),
         // Produce a variation of the weekday scenario including off-map trips.
         scenario_everyone: args.enabled("--scenario_everyone"),
@@ -55,6 +60,9 @@ fn main() {
         oneshot: args.optional("--oneshot"),
         oneshot_clip: args.optional("--oneshot_clip"),
         oneshot_drive_on_left: args.enabled("--oneshot_drive_on_left"),
+        // Use the settings from some city's config.json (but not its input files) for the oneshot
+        // import.
+        oneshot_config: args.optional("--oneshot_config"),
     };
     args.done();
     if !job.osm_to_raw
@@ -86,6 +94,7 @@ fn main() {
             path,
             job.oneshot_clip,
             !job.oneshot_drive_on_left,
+            job.oneshot_config,
             !job.skip_ch,
         );
         return;
@@ -101,61 +110,44 @@ fn main() {
 
     let mut timer = abstutil::Timer::new("import map data");
 
-    let (maybe_popdat, maybe_huge_map) = if job.scenario || job.scenario_everyone {
-        assert_eq!(job.city, "seattle");
-
-        #[cfg(feature = "scenarios")]
-        {
-            let (popdat, huge_map) = seattle::ensure_popdat_exists(&mut timer, &config);
-            (Some(popdat), Some(huge_map))
-        }
-
-        #[cfg(not(feature = "scenarios"))]
-        {
-            panic!(
-                "Can't do --scenario or --scenario_everyone without the scenarios feature \
-                 compiled in"
-            );
-            // Nonsense to make the type-checker work
-            (Some(true), Some(true))
+    let city = match GenericCityImporter::load(&job.city) {
+        Ok(city) => city,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
         }
-    } else {
-        (None, None)
     };
 
+    let (maybe_popdat, maybe_huge_map) =
+        if (job.scenario || job.scenario_everyone) && city.uses_soundcast() {
+            #[cfg(feature = "scenarios")]
+            {
+                let (popdat, huge_map) = seattle::ensure_popdat_exists(&mut timer, &config, &city);
+                (Some(popdat), Some(huge_map))
+            }
+
+            #[cfg(not(feature = "scenarios"))]
+            {
+                panic!(
+                    "Can't do --scenario or --scenario_everyone for {} without the scenarios \
+                     feature compiled in",
+                    job.city
+                );
+                // Nonsense to make the type-checker work
+                (Some(true), Some(true))
+            }
+        } else {
+            (None, None)
+        };
+
     for name in names {
         if job.osm_to_raw {
-            match job.city.as_ref() {
-                "berlin" => berlin::osm_to_raw(&name, &mut timer, &config),
-                "krakow" => krakow::osm_to_raw(&name, &mut timer, &config),
-                "london" => london::osm_to_raw(&name, &mut timer, &config),
-                "seattle" => seattle::osm_to_raw(&name, &mut timer, &config),
-                "tel_aviv" => tel_aviv::osm_to_raw(&name, &mut timer, &config),
-                "xian" => xian::osm_to_raw(&name, &mut timer, &config),
-                x => panic!("Unknown city {}", x),
-            }
+            city.osm_to_raw(&job.city, &name, &mut timer, &config);
         }
 
         let mut maybe_map = if job.raw_to_map {
             let mut map = utils::raw_to_map(&name, !job.skip_ch, &mut timer);
-
-            // Another strange step in the pipeline.
-            if name == "berlin_center" {
-                timer.start(format!(
-                    "distribute residents from planning areas for {}",
-                    name
-                ));
-                berlin::distribute_residents(&mut map, &mut timer);
-                timer.stop(format!(
-                    "distribute residents from planning areas for {}",
-                    name
-                ));
-            } else if job.city == "seattle" {
-                timer.start(format!("add GTFS schedules for {}", name));
-                seattle::add_gtfs_schedules(&mut map);
-                timer.stop(format!("add GTFS schedules for {}", name));
-            }
-
+            city.after_raw_to_map(&mut map, &mut timer);
             Some(map)
         } else if job.scenario || job.scenario_everyone {
             Some(map_model::Map::new(abstutil::path_map(&name), &mut timer))
@@ -163,28 +155,53 @@ fn main() {
             None
         };
 
-        #[cfg(feature = "scenarios")]
         if job.scenario {
-            timer.start(format!("scenario for {}", name));
-            let scenario = soundcast::make_weekday_scenario(
-                maybe_map.as_ref().unwrap(),
-                maybe_popdat.as_ref().unwrap(),
-                maybe_huge_map.as_ref().unwrap(),
-                &mut timer,
-            );
-            scenario.save();
-            timer.stop(format!("scenario for {}", name));
-
-            // This is a strange ordering.
-            if name == "downtown" || name == "south_seattle" {
-                timer.start(format!("adjust parking for {}", name));
-                seattle::adjust_private_parking(maybe_map.as_mut().unwrap(), &scenario);
-                timer.stop(format!("adjust parking for {}", name));
+            for source in &city.scenarios {
+                match source {
+                    #[cfg(feature = "scenarios")]
+                    ScenarioSource::Soundcast {
+                        adjust_private_parking,
+                    } => {
+                        timer.start(format!("scenario for {}", name));
+                        let scenario = soundcast::make_weekday_scenario(
+                            maybe_map.as_ref().unwrap(),
+                            maybe_popdat.as_ref().unwrap(),
+                            maybe_huge_map.as_ref().unwrap(),
+                            &mut timer,
+                        );
+                        scenario.save();
+                        timer.stop(format!("scenario for {}", name));
+
+                        // This is a strange ordering.
+                        if adjust_private_parking.contains(&name) {
+                            timer.start(format!("adjust parking for {}", name));
+                            seattle::adjust_private_parking(maybe_map.as_mut().unwrap(), &scenario);
+                            timer.stop(format!("adjust parking for {}", name));
+                        }
+                    }
+                    #[cfg(not(feature = "scenarios"))]
+                    ScenarioSource::Soundcast { .. } => unreachable!(),
+                    ScenarioSource::HomeToWork {
+                        scenario_name,
+                        rng_seed,
+                    } => {
+                        timer.start(format!("{} scenario for {}", scenario_name, name));
+                        let mut rng = XorShiftRng::from_seed([*rng_seed; 16]);
+                        let mut scenario = sim::ScenarioGenerator::proletariat_robot(
+                            maybe_map.as_ref().unwrap(),
+                            &mut rng,
+                            &mut timer,
+                        );
+                        scenario.scenario_name = scenario_name.clone();
+                        scenario.save();
+                        timer.stop(format!("{} scenario for {}", scenario_name, name));
+                    }
+                }
             }
         }
 
         #[cfg(feature = "scenarios")]
-        if job.scenario_everyone {
+        if job.scenario_everyone && city.uses_soundcast() {
             timer.start(format!("scenario_everyone for {}", name));
             soundcast::make_weekday_scenario_with_everyone(
                 maybe_map.as_ref().unwrap(),
@@ -197,11 +214,20 @@ fn main() {
     }
 }
 
-fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, build_ch: bool) {
+fn oneshot(
+    osm_path: String,
+    clip: Option<String>,
+    drive_on_right: bool,
+    config: Option<String>,
+    build_ch: bool,
+) {
     let mut timer = abstutil::Timer::new("oneshot");
     println!("- Running convert_osm on {}", osm_path);
     let name = abstutil::basename(&osm_path);
-    let raw = convert_osm::convert(
+    let opts = if let Some(path) = config {
+        let city: GenericCityImporter = abstutil::read_json(path, &mut timer);
+        city.convert_osm_options(osm_path, "oneshot", &name, clip)
+    } else {
         convert_osm::Options {
             osm_input: osm_path,
             city_name: "oneshot".to_string(),
@@ -222,9 +248,9 @@ fn oneshot(osm_path: String, clip: Option<String>, drive_on_right: bool, build_c
             private_offstreet_parking: convert_osm::PrivateOffstreetParking::FixedPerBldg(1),
             elevation: None,
             include_railroads: true,
-        },
-        &mut timer,
-    );
+        }
+    };
+    let raw = convert_osm::convert(opts, &mut timer);
     let map = map_model::Map::create_from_raw(raw, build_ch, &mut timer);
     timer.start("save map");
     map.save();
@@ -0,0 +1,201 @@
+use crate::generic::PopulationCSV;
+use abstutil::{prettyprint_usize, Timer};
+use geom::{Polygon, Ring};
+use kml::ExtraShapes;
+use map_model::{BuildingID, BuildingType, Map};
+use std::fs::File;
+
+// Modify the filtered KML of areas with the number of residents and jobs from a different dataset.
+pub fn correlate_population(
+    shapes_path: &str,
+    id_attribute: &str,
+    residents_attribute: &str,
+    jobs_attribute: Option<&String>,
+    source: &PopulationCSV,
+    timer: &mut Timer,
+) {
+    let shapes_path = abstutil::path(shapes_path);
+    let mut shapes = abstutil::read_binary::<ExtraShapes>(shapes_path.clone(), timer);
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(source.delimiter as u8)
+        .from_reader(File::open(abstutil::path(&source.path)).unwrap());
+    let headers = reader.headers().unwrap().clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .unwrap_or_else(|| panic!("{} has no column {}", source.path, name))
+    };
+    let id_column = column(&source.id_column);
+    let mut copy_columns = vec![(
+        column(&source.residents_column),
+        residents_attribute.to_string(),
+    )];
+    match (&source.jobs_column, jobs_attribute) {
+        (Some(name), Some(attribute)) => {
+            copy_columns.push((column(name), attribute.to_string()));
+        }
+        (None, None) => {}
+        _ => panic!("jobs_column and jobs_attribute must both be set"),
+    }
+
+    for rec in reader.records() {
+        let rec = rec.unwrap();
+        for shape in &mut shapes.shapes {
+            if shape.attributes.get(id_attribute).map(|x| x.as_str()) == rec.get(id_column) {
+                for (col, attribute) in &copy_columns {
+                    shape
+                        .attributes
+                        .insert(attribute.clone(), rec[*col].to_string());
+                }
+                break;
+            }
+        }
+    }
+    abstutil::write_binary(shapes_path, &shapes);
+}
+
+// Scale the estimated residents (and jobs, if the areas have them) of each building, so the total
+// in each area matches.
+pub fn distribute_population(
+    map: &mut Map,
+    shapes_path: &str,
+    id_attribute: &str,
+    residents_attribute: &str,
+    jobs_attribute: Option<&String>,
+    timer: &mut Timer,
+) {
+    for shape in abstutil::read_binary::<ExtraShapes>(abstutil::path(shapes_path), timer).shapes {
+        let pts = map.get_gps_bounds().convert(&shape.points);
+        if pts
+            .iter()
+            .all(|pt| !map.get_boundary_polygon().contains_pt(*pt))
+        {
+            continue;
+        }
+        let region = Ring::must_new(pts).to_polygon();
+        let bldgs: Vec<BuildingID> = map
+            .all_buildings()
+            .iter()
+            .filter(|b| region.contains_pt(b.label_center))
+            .map(|b| b.id)
+            .collect();
+
+        // If the region is partly out-of-bounds, then scale down the totals linearly based on area
+        // of the overlapping part of the polygon.
+        let pct_overlap = Polygon::union_all(region.intersection(map.get_boundary_polygon()))
+            .area()
+            / region.area();
+        let name = &shape.attributes[id_attribute];
+
+        if let Some(n) = shape.attributes.get(residents_attribute) {
+            let num_residents = (pct_overlap * n.parse::<f64>().unwrap()) as usize;
+            let homes: Vec<BuildingID> = bldgs
+                .iter()
+                .cloned()
+                .filter(|b| map.get_b(*b).bldg_type.has_residents())
+                .collect();
+            timer.note(format!(
+                "Distributing {} residents in {} to {} buildings. {}% of this area overlapped \
+                 with the map, scaled residents accordingly.",
+                prettyprint_usize(num_residents),
+                name,
+                prettyprint_usize(homes.len()),
+                (pct_overlap * 100.0) as usize
+            ));
+            let counts = apportion(
+                num_residents,
+                homes
+                    .iter()
+                    .map(|b| map.get_b(*b).bldg_type.num_residents())
+                    .collect(),
+            );
+            for (b, n) in homes.into_iter().zip(counts) {
+                let bldg_type = match map.get_b(b).bldg_type {
+                    BuildingType::Residential(_) => BuildingType::Residential(n),
+                    BuildingType::ResidentialCommercial(_, jobs) => {
+                        BuildingType::ResidentialCommercial(n, jobs)
+                    }
+                    _ => unreachable!(),
+                };
+                map.hack_override_bldg_type(b, bldg_type);
+            }
+        } else {
+            timer.warn(format!("Area {} has no {}", name, residents_attribute));
+        }
+
+        if let Some(attribute) = jobs_attribute {
+            if let Some(n) = shape.attributes.get(attribute) {
+                let num_jobs = (pct_overlap * n.parse::<f64>().unwrap()) as usize;
+                let workplaces: Vec<BuildingID> = bldgs
+                    .iter()
+                    .cloned()
+                    .filter(|b| map.get_b(*b).bldg_type.num_jobs() > 0)
+                    .collect();
+                timer.note(format!(
+                    "Distributing {} jobs in {} to {} buildings",
+                    prettyprint_usize(num_jobs),
+                    name,
+                    prettyprint_usize(workplaces.len())
+                ));
+                let counts = apportion(
+                    num_jobs,
+                    workplaces
+                        .iter()
+                        .map(|b| map.get_b(*b).bldg_type.num_jobs())
+                        .collect(),
+                );
+                for (b, n) in workplaces.into_iter().zip(counts) {
+                    let bldg_type = match map.get_b(b).bldg_type {
+                        BuildingType::Commercial(_) => BuildingType::Commercial(n),
+                        BuildingType::ResidentialCommercial(residents, _) => {
+                            BuildingType::ResidentialCommercial(residents, n)
+                        }
+                        _ => unreachable!(),
+                    };
+                    map.hack_override_bldg_type(b, bldg_type);
+                }
+            } else {
+                timer.warn(format!("Area {} has no {}", name, attribute));
+            }
+        }
+    }
+
+    map.save();
+}
+
+// Split a total proportionally to some weights, handing out what's left after rounding down to the
+// largest remainders, so the result adds up exactly. If all the weights are 0, split evenly.
+fn apportion(total: usize, weights: Vec<usize>) -> Vec<usize> {
+    if weights.is_empty() {
+        return Vec::new();
+    }
+    let sum: usize = weights.iter().sum();
+    let shares: Vec<f64> = weights
+        .iter()
+        .map(|w| {
+            if sum == 0 {
+                (total as f64) / (weights.len() as f64)
+            } else {
+                (total as f64) * (*w as f64) / (sum as f64)
+            }
+        })
+        .collect();
+    let mut result: Vec<usize> = shares.iter().map(|x| x.floor() as usize).collect();
+    let mut leftover = total.saturating_sub(result.iter().sum());
+
+    let mut by_remainder: Vec<usize> = (0..shares.len()).collect();
+    by_remainder.sort_by(|a, b| {
+        (shares[*b] - shares[*b].floor())
+            .partial_cmp(&(shares[*a] - shares[*a].floor()))
+            .unwrap()
+    });
+    for idx in by_remainder {
+        if leftover == 0 {
+            break;
+        }
+        result[idx] += 1;
+        leftover -= 1;
+    }
+    result
+}
@@ -0,0 +1,101 @@
+use geom::{Distance, Pt2D};
+use map_model::osm;
+use map_model::raw::{OriginalRoad, RawMap};
+use std::collections::{BTreeMap, BTreeSet};
+
+// Smaller movements are usually just someone nudging nodes around
+const MOVED_THRESHOLD: Distance = Distance::const_meters(1.0);
+
+// Describes what changed between two imports of the same map, one line per road or intersection.
+// Road and intersection IDs in the final Map get reassigned every import, so this is phrased in
+// terms of OSM IDs.
+pub fn describe_changes(old: &RawMap, new: &RawMap) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    // When a way gets split or its endpoints change, the OriginalRoad changes, but the way ID
+    // usually survives.
+    let mut new_pieces: BTreeMap<osm::WayID, Vec<OriginalRoad>> = BTreeMap::new();
+    for id in new.roads.keys() {
+        if !old.roads.contains_key(id) {
+            new_pieces
+                .entry(id.osm_way_id)
+                .or_insert_with(Vec::new)
+                .push(*id);
+        }
+    }
+    let old_ways: BTreeSet<osm::WayID> = old.roads.keys().map(|id| id.osm_way_id).collect();
+
+    for (id, old_r) in &old.roads {
+        let new_r = if let Some(r) = new.roads.get(id) {
+            r
+        } else {
+            if let Some(pieces) = new_pieces.get(&id.osm_way_id) {
+                changes.push(format!(
+                    "{} became {}",
+                    id,
+                    pieces
+                        .iter()
+                        .map(|r| r.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            } else {
+                changes.push(format!("{} was removed", id));
+            }
+            continue;
+        };
+        if moved(&old_r.center_points, &new_r.center_points) {
+            changes.push(format!("{} changed shape", id));
+        }
+        let old_tags = old_r.osm_tags.inner();
+        let new_tags = new_r.osm_tags.inner();
+        for (k, v) in old_tags {
+            match new_tags.get(k) {
+                Some(v2) if v == v2 => {}
+                Some(v2) => changes.push(format!("{} changed {} from {} to {}", id, k, v, v2)),
+                None => changes.push(format!("{} lost {}={}", id, k, v)),
+            }
+        }
+        for (k, v) in new_tags {
+            if !old_tags.contains_key(k) {
+                changes.push(format!("{} gained {}={}", id, k, v));
+            }
+        }
+    }
+    for id in new.roads.keys() {
+        if !old_ways.contains(&id.osm_way_id) {
+            changes.push(format!("{} was added", id));
+        }
+    }
+
+    for (id, old_i) in &old.intersections {
+        if let Some(new_i) = new.intersections.get(id) {
+            if old_i.point.dist_to(new_i.point) > MOVED_THRESHOLD {
+                changes.push(format!("{} moved", id));
+            }
+            if old_i.intersection_type != new_i.intersection_type {
+                changes.push(format!(
+                    "{} changed from {:?} to {:?}",
+                    id, old_i.intersection_type, new_i.intersection_type
+                ));
+            }
+        } else {
+            changes.push(format!("{} was removed", id));
+        }
+    }
+    for id in new.intersections.keys() {
+        if !old.intersections.contains_key(id) {
+            changes.push(format!("{} was added", id));
+        }
+    }
+
+    changes
+}
+
+fn moved(old: &[Pt2D], new: &[Pt2D]) -> bool {
+    old.len() != new.len()
+        || old
+            .iter()
+            .zip(new.iter())
+            .any(|(pt1, pt2)| pt1.dist_to(*pt2) > MOVED_THRESHOLD)
+}
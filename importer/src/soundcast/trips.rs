@@ -224,12 +224,14 @@ fn clip_trips(map: &Map, popdat: &PopDat, huge_map: &Map, timer: &mut Timer) ->
                     TripMode::Walk | TripMode::Transit => {
                         (&incoming_borders_walking, &outgoing_borders_walking)
                     }
-                    TripMode::Drive => (&incoming_borders_driving, &outgoing_borders_driving),
+                    TripMode::Drive | TripMode::RideHail => {
+                        (&incoming_borders_driving, &outgoing_borders_driving)
+                    }
                     TripMode::Bike => (&incoming_borders_biking, &outgoing_borders_biking),
                 },
                 match orig.mode {
                     TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                    TripMode::Drive => PathConstraints::Car,
+                    TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
                     TripMode::Bike => PathConstraints::Bike,
                 },
                 maybe_huge_map.as_ref(),
@@ -314,13 +316,17 @@ pub fn make_weekday_scenario(
         }
     }
 
-    Scenario {
+    let mut scenario = Scenario {
         scenario_name: "weekday".to_string(),
         map_name: map.get_name().to_string(),
         people,
+        households: Vec::new(),
+        deliveries: Vec::new(),
         only_seed_buses: None,
     }
-    .remove_weird_schedules(map)
+    .remove_weird_schedules(map);
+    scenario.group_into_households(map);
+    scenario
 }
 
 pub fn make_weekday_scenario_with_everyone(
@@ -385,6 +391,8 @@ pub fn make_weekday_scenario_with_everyone(
         scenario_name: "everyone_weekday".to_string(),
         map_name: map.get_name().to_string(),
         people,
+        households: Vec::new(),
+        deliveries: Vec::new(),
         only_seed_buses: None,
     }
     .remove_weird_schedules(map)
@@ -1,5 +1,7 @@
 use crate::configuration::ImporterConfiguration;
 use abstutil::Timer;
+use geojson::{GeoJson, Value};
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::process::Command;
 
@@ -48,8 +50,8 @@ pub fn download(config: &ImporterConfiguration, output: &str, url: &str) {
     }
 }
 
-// If the output file doesn't already exist, downloads the URL into that location. Clips .kml
-// files and converts to a .bin.
+// If the output file doesn't already exist, downloads the URL into that location. Clips .kml or
+// .geojson files and converts to a .bin.
 pub fn download_kml(
     output: &str,
     url: &str,
@@ -57,7 +59,13 @@ pub fn download_kml(
     require_all_pts_in_bounds: bool,
     timer: &mut Timer,
 ) {
-    assert!(url.ends_with(".kml"));
+    let ext = if url.ends_with(".kml") {
+        ".kml"
+    } else if url.ends_with(".geojson") {
+        ".geojson"
+    } else {
+        panic!("{} isn't a .kml or .geojson file", url);
+    };
     let output = abstutil::path(output);
     if Path::new(&output).exists() {
         println!("- {} already exists", output);
@@ -68,8 +76,8 @@ pub fn download_kml(
         .expect("Creating parent dir failed");
 
     let tmp = "tmp_output";
-    if Path::new(&output.replace(".bin", ".kml")).exists() {
-        std::fs::copy(output.replace(".bin", ".kml"), tmp).unwrap();
+    if Path::new(&output.replace(".bin", ext)).exists() {
+        std::fs::copy(output.replace(".bin", ext), tmp).unwrap();
     } else {
         println!("- Missing {}, so downloading {}", output, url);
         run(Command::new("curl")
@@ -80,38 +88,102 @@ pub fn download_kml(
             .arg(url));
     }
 
-    println!("- Extracting KML data");
-
-    let shapes = kml::load(tmp, bounds, require_all_pts_in_bounds, timer).unwrap();
+    let shapes = if ext == ".kml" {
+        println!("- Extracting KML data");
+        kml::load(tmp, bounds, require_all_pts_in_bounds, timer).unwrap()
+    } else {
+        println!("- Extracting GeoJSON data");
+        load_geojson(tmp, bounds, require_all_pts_in_bounds).unwrap()
+    };
     abstutil::write_binary(output.clone(), &shapes);
     // Keep the intermediate file; otherwise we inadvertently grab new upstream data when
     // changing some binary formats
-    std::fs::rename(tmp, output.replace(".bin", ".kml")).unwrap();
+    std::fs::rename(tmp, output.replace(".bin", ext)).unwrap();
 }
 
-// Uses osmconvert to clip the input .osm (or .pbf) against a polygon and produce some output.
-// Skips if the output exists.
-pub fn osmconvert(
-    input: &str,
-    clipping_polygon: String,
-    output: String,
-    config: &ImporterConfiguration,
-) {
-    let input = abstutil::path(input);
-    let clipping_polygon = abstutil::path(clipping_polygon);
-    let output = abstutil::path(output);
+// Like kml::load. Every point, line, and polygon becomes a shape, with the feature's properties as
+// attributes. Multi-part geometry becomes one shape per part, and polygons just keep their outer
+// ring.
+fn load_geojson(
+    path: &str,
+    gps_bounds: &geom::GPSBounds,
+    require_all_pts_in_bounds: bool,
+) -> Result<kml::ExtraShapes, Box<dyn std::error::Error>> {
+    let features = match std::fs::read_to_string(path)?.parse::<GeoJson>()? {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::Geometry(_) => return Err(format!("{} has no features", path).into()),
+    };
 
-    if Path::new(&output).exists() {
-        println!("- {} already exists", output);
-        return;
+    let mut shapes = Vec::new();
+    let mut skipped_count = 0;
+    for feature in features {
+        let mut attributes = BTreeMap::new();
+        for (key, value) in feature.properties.unwrap_or_default() {
+            match value {
+                serde_json::Value::Null => {}
+                serde_json::Value::String(x) => {
+                    attributes.insert(key, x);
+                }
+                x => {
+                    attributes.insert(key, x.to_string());
+                }
+            }
+        }
+        let mut parts = Vec::new();
+        if let Some(geometry) = feature.geometry {
+            flatten_geojson(geometry.value, &mut parts);
+        }
+        for part in parts {
+            let pts: Vec<geom::LonLat> = part
+                .into_iter()
+                .map(|pos| geom::LonLat::new(pos[0], pos[1]))
+                .collect();
+            let num_ok = pts.iter().filter(|pt| gps_bounds.contains(**pt)).count();
+            if num_ok > 0 && (num_ok == pts.len() || !require_all_pts_in_bounds) {
+                shapes.push(kml::ExtraShape {
+                    points: pts,
+                    attributes: attributes.clone(),
+                });
+            } else {
+                skipped_count += 1;
+            }
+        }
     }
-    println!("- Clipping {} to {}", input, clipping_polygon);
 
-    run(Command::new(&config.osmconvert)
-        .arg(input)
-        .arg(format!("-B={}", clipping_polygon))
-        .arg("--complete-ways")
-        .arg(format!("-o={}", output)));
+    println!(
+        "Got {} shapes from {} and skipped {} shapes",
+        abstutil::prettyprint_usize(shapes.len()),
+        path,
+        abstutil::prettyprint_usize(skipped_count)
+    );
+    Ok(kml::ExtraShapes { shapes })
+}
+
+fn flatten_geojson(value: Value, parts: &mut Vec<Vec<Vec<f64>>>) {
+    match value {
+        Value::Point(pt) => parts.push(vec![pt]),
+        Value::MultiPoint(pts) => parts.extend(pts.into_iter().map(|pt| vec![pt])),
+        Value::LineString(line) => parts.push(line),
+        Value::MultiLineString(lines) => parts.extend(lines),
+        Value::Polygon(mut rings) => {
+            if !rings.is_empty() {
+                parts.push(rings.remove(0));
+            }
+        }
+        Value::MultiPolygon(polygons) => {
+            for mut rings in polygons {
+                if !rings.is_empty() {
+                    parts.push(rings.remove(0));
+                }
+            }
+        }
+        Value::GeometryCollection(geometries) => {
+            for geometry in geometries {
+                flatten_geojson(geometry.value, parts);
+            }
+        }
+    }
 }
 
 // Runs a command, asserts success. STDOUT and STDERR aren't touched.
@@ -151,3 +223,103 @@ pub fn raw_to_map(name: &str, build_ch: bool, timer: &mut Timer) -> map_model::M
 
     map
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use convert_osm::{
+        OnstreetParking, Options, ParkingShapes, PrivateOffstreetParking, PublicOffstreetParking,
+    };
+    use geom::{Distance, Polygon, Pt2D};
+    use map_model::raw::{OriginalRoad, RawMap, RawParkingLot};
+    use map_model::{osm, DrivingSide, MapConfig};
+
+    // Parking lines and points drawn around a 2x2 grid
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/test_data/parking.geojson");
+
+    #[test]
+    fn test_match_gis_parking() {
+        let mut map = RawMap::synthetic_grid(2, 2);
+        // Only replace parking lanes when OSM doesn't have them
+        map.roads
+            .get_mut(&OriginalRoad::new(2, (1, 3)))
+            .unwrap()
+            .osm_tags
+            .insert(osm::INFERRED_PARKING, "true");
+        // A lot in the middle of the block
+        map.parking_lots.push(RawParkingLot {
+            osm_id: osm::OsmID::Way(osm::WayID(5000)),
+            polygon: Polygon::rectangle_centered(
+                Pt2D::new(200.0, 200.0),
+                Distance::meters(60.0),
+                Distance::meters(40.0),
+            ),
+            osm_tags: abstutil::Tags::new(BTreeMap::new()),
+        });
+
+        // Multi-part lines get split up, and anything out of bounds is dropped
+        let shapes = load_geojson(FIXTURE, &map.gps_bounds, false).unwrap();
+        assert_eq!(shapes.shapes.len(), 8);
+        let path = std::env::temp_dir()
+            .join("test_match_gis_parking.bin")
+            .to_str()
+            .unwrap()
+            .to_string();
+        abstutil::write_binary(path.clone(), &shapes);
+
+        let source = ParkingShapes {
+            path,
+            capacity_attribute: Some("spots".to_string()),
+            price_attribute: Some("rate".to_string()),
+            max_stay_attribute: Some("limit".to_string()),
+            hours_attribute: Some("hours".to_string()),
+            name_attribute: Some("name".to_string()),
+        };
+        let opts = Options {
+            osm_input: String::new(),
+            city_name: "test".to_string(),
+            name: "grid".to_string(),
+            clip: None,
+            map_config: MapConfig {
+                driving_side: DrivingSide::Right,
+                bikes_can_use_bus_lanes: true,
+            },
+            onstreet_parking: OnstreetParking::GIS(source.clone()),
+            public_offstreet_parking: PublicOffstreetParking::GenericGIS(source),
+            private_offstreet_parking: PrivateOffstreetParking::FixedPerBldg(0),
+            elevation: None,
+            include_railroads: false,
+        };
+        convert_osm::apply_parking(&mut map, &opts, &mut Timer::throwaway());
+
+        let tags = |way, i1, i2| &map.roads[&OriginalRoad::new(way, (i1, i2))].osm_tags;
+        // South of the first road, which points east, so on its right
+        let r1 = tags(1, 1, 2);
+        assert!(r1.is("parking:right:charge", "2.50/hour"));
+        assert!(r1.is("parking:right:maxstay", "120 minutes"));
+        assert!(r1.is("parking:right:fee:conditional", "yes @ (Mo-Sa 08:00-20:00)"));
+        assert!(!r1.contains_key("parking:left:charge"));
+        // East of the second road, which points south, so on its left. No spots there.
+        let r2 = tags(2, 1, 3);
+        assert!(r2.is(osm::PARKING_LEFT, "no_parking"));
+        assert!(r2.is(osm::PARKING_RIGHT, "parallel"));
+        assert!(!r2.contains_key("parking:left:charge"));
+        // Both parts of one feature, each next to a different road
+        assert!(tags(3, 2, 4).is("parking:right:charge", "3.00/hour"));
+        assert!(tags(4, 3, 4).is("parking:left:charge", "3.00/hour"));
+
+        let lot = &map.parking_lots[0].osm_tags;
+        assert!(lot.is("capacity", "25"));
+        assert!(lot.is("charge", "1.00/hour"));
+
+        // Only one point lands in a building
+        for (id, b) in &map.buildings {
+            if *id == osm::OsmID::Way(osm::WayID(1004)) {
+                assert_eq!(b.public_garage_name, Some("Garage".to_string()));
+                assert_eq!(b.num_parking_spots, 40);
+            } else {
+                assert_eq!(b.public_garage_name, None);
+            }
+        }
+    }
+}
@@ -294,6 +294,8 @@ impl Model {
                 osm_tags,
                 turn_restrictions: Vec::new(),
                 complicated_turn_restrictions: Vec::new(),
+                elevation_samples: Vec::new(),
+                crossings: Vec::new(),
             },
         );
         self.road_added(id, ctx);
@@ -42,6 +42,20 @@ pub fn upgrade(mut value: Value, map: &Map) -> Result<PermanentMapEdits, String>
             .unwrap()
             .insert("version".to_string(), Value::Number(2.into()));
     }
+    if value["version"] == Value::Number(2.into()) {
+        fix_conditional_restrictions(&mut value, map)?;
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(3.into()));
+    }
+    if value["version"] == Value::Number(3.into()) {
+        fix_mid_block_crossings(&mut value, map)?;
+        value
+            .as_object_mut()
+            .unwrap()
+            .insert("version".to_string(), Value::Number(4.into()));
+    }
 
     abstutil::from_json(&value.to_string().into_bytes()).map_err(|x| x.to_string())
 }
@@ -193,6 +207,57 @@ fn fix_old_lane_cmds(value: &mut Value, map: &Map) -> Result<(), String> {
     Ok(())
 }
 
+// Time-dependent restrictions per lane were added to EditRoad. Before, they didn't exist in the
+// map at all, so fill in whatever OSM has now.
+fn fix_conditional_restrictions(value: &mut Value, map: &Map) -> Result<(), String> {
+    for cmd in value.as_object_mut().unwrap()["commands"]
+        .as_array_mut()
+        .unwrap()
+    {
+        let obj = if let Some(obj) = cmd.get_mut("ChangeRoad") {
+            obj
+        } else {
+            continue;
+        };
+        let osm_id: OriginalRoad = serde_json::from_value(obj["r"].clone()).unwrap();
+        let r = map.get_r(map.find_r_by_osm_id(osm_id)?);
+        let orig = EditRoad::get_orig_from_osm(r, map.get_config().driving_side);
+        let conditional_ltr = serde_json::to_value(orig.conditional_ltr).unwrap();
+        for key in vec!["old", "new"] {
+            obj[key]
+                .as_object_mut()
+                .unwrap()
+                .insert("conditional_ltr".to_string(), conditional_ltr.clone());
+        }
+    }
+    Ok(())
+}
+
+// Crossings were added to EditRoad, so they can be added and removed. Before, only the ones from
+// OSM existed.
+fn fix_mid_block_crossings(value: &mut Value, map: &Map) -> Result<(), String> {
+    for cmd in value.as_object_mut().unwrap()["commands"]
+        .as_array_mut()
+        .unwrap()
+    {
+        let obj = if let Some(obj) = cmd.get_mut("ChangeRoad") {
+            obj
+        } else {
+            continue;
+        };
+        let osm_id: OriginalRoad = serde_json::from_value(obj["r"].clone()).unwrap();
+        let r = map.get_r(map.find_r_by_osm_id(osm_id)?);
+        let crossings = serde_json::to_value(&r.orig_crossings).unwrap();
+        for key in vec!["old", "new"] {
+            obj[key]
+                .as_object_mut()
+                .unwrap()
+                .insert("crossings".to_string(), crossings.clone());
+        }
+    }
+    Ok(())
+}
+
 // These're old structs used in fix_old_lane_cmds.
 #[derive(Debug, Deserialize)]
 struct OriginalLane {
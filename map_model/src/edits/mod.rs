@@ -2,13 +2,14 @@ mod compat;
 mod perma;
 
 use crate::make::initial::lane_specs::get_lane_specs_ltr;
+use crate::raw::CrossingType;
 use crate::{
-    connectivity, AccessRestrictions, BusRouteID, ControlStopSign, ControlTrafficSignal, Direction,
-    DrivingSide, IntersectionID, IntersectionType, LaneID, LaneType, Map, PathConstraints,
-    Pathfinder, Road, RoadID, TurnID, Zone,
+    connectivity, AccessRestrictions, BusRouteID, ConditionalRestriction, ControlStopSign,
+    ControlTrafficSignal, Direction, DrivingSide, IntersectionID, IntersectionType, LaneID,
+    LaneType, Map, PathConstraints, Pathfinder, Road, RoadID, TurnID, Zone,
 };
 use abstutil::{retain_btreemap, retain_btreeset, Timer};
-use geom::{Speed, Time};
+use geom::{Distance, Speed, Time};
 pub use perma::PermanentMapEdits;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
@@ -43,17 +44,21 @@ pub struct EditRoad {
     pub lanes_ltr: Vec<(LaneType, Direction)>,
     pub speed_limit: Speed,
     pub access_restrictions: AccessRestrictions,
+    // Per lane, matching lanes_ltr
+    pub conditional_ltr: Vec<Option<ConditionalRestriction>>,
+    // Distance along the road's center line, like Road::crossings
+    pub crossings: Vec<(Distance, CrossingType)>,
 }
 
 impl EditRoad {
     pub fn get_orig_from_osm(r: &Road, driving_side: DrivingSide) -> EditRoad {
+        let specs = get_lane_specs_ltr(&r.osm_tags, driving_side);
         EditRoad {
-            lanes_ltr: get_lane_specs_ltr(&r.osm_tags, driving_side)
-                .into_iter()
-                .map(|spec| (spec.lt, spec.dir))
-                .collect(),
+            lanes_ltr: specs.iter().map(|spec| (spec.lt, spec.dir)).collect(),
             speed_limit: r.speed_limit_from_osm(),
             access_restrictions: r.access_restrictions_from_osm(),
+            conditional_ltr: specs.into_iter().map(|spec| spec.conditional).collect(),
+            crossings: r.orig_crossings.clone(),
         }
     }
 }
@@ -192,12 +197,16 @@ impl MapEdits {
             // What exactly changed?
             if r.speed_limit != orig.speed_limit
                 || r.access_restrictions != orig.access_restrictions
+                || r.crossings != orig.crossings
             {
                 roads.insert(r.id);
             } else {
                 let lanes_ltr = r.lanes_ltr();
                 for (idx, (lt, dir)) in orig.lanes_ltr.into_iter().enumerate() {
-                    if lanes_ltr[idx].1 != dir || lanes_ltr[idx].2 != lt {
+                    if lanes_ltr[idx].1 != dir
+                        || lanes_ltr[idx].2 != lt
+                        || map.get_l(lanes_ltr[idx].0).conditional != orig.conditional_ltr[idx]
+                    {
                         lanes.insert(lanes_ltr[idx].0);
                     }
                 }
@@ -248,14 +257,27 @@ impl EditCmd {
                     return;
                 }
 
+                // Turn lanes and parking rules from OSM only still make sense for lanes that haven't
+                // been changed.
+                let orig_specs =
+                    get_lane_specs_ltr(&map.roads[r.0].osm_tags, map.config.driving_side);
+
                 let road = &mut map.roads[r.0];
                 road.speed_limit = new.speed_limit;
                 road.access_restrictions = new.access_restrictions.clone();
+                road.crossings = new.crossings.clone();
                 assert_eq!(road.lanes_ltr.len(), new.lanes_ltr.len());
                 for (idx, (lt, dir)) in new.lanes_ltr.clone().into_iter().enumerate() {
                     let lane = &mut map.lanes[(road.lanes_ltr[idx].0).0];
                     road.lanes_ltr[idx].2 = lt;
                     lane.lane_type = lt;
+                    let orig_spec = orig_specs
+                        .get(idx)
+                        .filter(|spec| spec.lt == lt && spec.dir == dir);
+                    lane.turn_restrictions =
+                        orig_spec.and_then(|spec| spec.turn_restrictions.clone());
+                    lane.parking_rules = orig_spec.and_then(|spec| spec.parking_rules.clone());
+                    lane.conditional = new.conditional_ltr[idx].clone();
 
                     // Direction change?
                     if road.lanes_ltr[idx].1 != dir {
@@ -417,6 +439,12 @@ impl Map {
                 .collect(),
             speed_limit: r.speed_limit,
             access_restrictions: r.access_restrictions.clone(),
+            conditional_ltr: r
+                .lanes_ltr()
+                .into_iter()
+                .map(|(l, _, _)| self.get_l(l).conditional.clone())
+                .collect(),
+            crossings: r.crossings.clone(),
         }
     }
 
@@ -559,6 +587,7 @@ impl Map {
         let mut pathfinder = std::mem::replace(&mut self.pathfinder, Pathfinder::Dijkstra);
         pathfinder.apply_edits(self, timer);
         self.pathfinder = pathfinder;
+        self.dijkstra_graphs.clear();
 
         // Also recompute blackholes. This is cheap enough to do from scratch.
         timer.start("recompute blackholes");
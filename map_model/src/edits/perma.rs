@@ -1,8 +1,8 @@
 use crate::edits::{EditCmd, EditIntersection, EditRoad, MapEdits};
 use crate::raw::OriginalRoad;
-use crate::{osm, ControlStopSign, IntersectionID, Map};
+use crate::{osm, ControlStopSign, IntersectionID, Map, MapMatcher, RoadID};
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::Time;
+use geom::{Distance, Time};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 
@@ -83,7 +83,7 @@ impl PermanentMapEdits {
             map_name: map.get_name().to_string(),
             edits_name: edits.edits_name.clone(),
             // Increase this every time there's a schema change
-            version: 2,
+            version: 4,
             proposal_description: edits.proposal_description.clone(),
             proposal_link: edits.proposal_link.clone(),
             commands: edits.commands.iter().map(|cmd| cmd.to_perma(map)).collect(),
@@ -144,6 +144,109 @@ impl PermanentMapEdits {
         edits.update_derived(map);
         Ok(edits)
     }
+
+    // Moves edits to a newer import of the same map, using IDs in the new map. Commands that can't
+    // be carried over are dropped and described in the returned list.
+    pub fn rebase(self, matcher: &MapMatcher) -> (PermanentMapEdits, Vec<String>) {
+        let map = matcher.new_map();
+        let mut problems = Vec::new();
+        let mut commands = Vec::new();
+        // Which old road last edited each new road, to notice when edits get merged together
+        let mut edited_from: BTreeMap<RoadID, OriginalRoad> = BTreeMap::new();
+
+        for cmd in self.commands {
+            match cmd {
+                PermanentEditCmd::ChangeRoad { r, new, .. } => {
+                    let pieces = match matcher.orig_road(r) {
+                        Ok(pieces) => pieces,
+                        Err(err) => {
+                            problems.push(format!("Dropping edit to {}: {}", r, err));
+                            continue;
+                        }
+                    };
+                    for (id, reversed) in pieces {
+                        let road = map.get_r(id);
+                        let mut new = if reversed {
+                            new.clone().reversed(road.center_pts.length())
+                        } else {
+                            new.clone()
+                        };
+                        // The road may have been split since
+                        new.crossings
+                            .retain(|(dist, _)| *dist <= road.center_pts.length());
+                        if road.lanes_ltr().len() != new.lanes_ltr.len() {
+                            problems.push(format!(
+                                "Dropping edit to {}: {} has {} lanes now, but {} in the edits",
+                                r,
+                                road.orig_id,
+                                road.lanes_ltr().len(),
+                                new.lanes_ltr.len()
+                            ));
+                            continue;
+                        }
+                        if let Some(other) = edited_from.insert(id, r) {
+                            if other != r {
+                                problems.push(format!(
+                                    "{} and {} were merged into {}; the edit to {} wins",
+                                    other, r, road.orig_id, r
+                                ));
+                            }
+                        }
+                        commands.push(PermanentEditCmd::ChangeRoad {
+                            r: road.orig_id,
+                            new,
+                            old: map.get_r_edit(id),
+                        });
+                    }
+                }
+                PermanentEditCmd::ChangeIntersection { i, new, .. } => {
+                    match matcher
+                        .orig_intersection(i)
+                        .and_then(|id| Ok((id, new.rebase(id, matcher)?)))
+                    {
+                        Ok((id, new)) => {
+                            commands.push(PermanentEditCmd::ChangeIntersection {
+                                i: map.get_i(id).orig_id,
+                                new,
+                                old: map.get_i_edit(id).to_permanent(map),
+                            });
+                        }
+                        Err(err) => {
+                            problems.push(format!("Dropping edit to {}: {}", i, err));
+                        }
+                    }
+                }
+                PermanentEditCmd::ChangeRouteSchedule {
+                    osm_rel_id, new, ..
+                } => {
+                    if let Some(id) = map.find_br(osm_rel_id) {
+                        commands.push(PermanentEditCmd::ChangeRouteSchedule {
+                            osm_rel_id,
+                            old: map.get_br(id).spawn_times.clone(),
+                            new,
+                        });
+                    } else {
+                        problems.push(format!(
+                            "Dropping schedule change for {}: the route doesn't exist anymore",
+                            osm_rel_id
+                        ));
+                    }
+                }
+            }
+        }
+
+        (
+            PermanentMapEdits {
+                map_name: map.get_name().to_string(),
+                edits_name: self.edits_name,
+                version: self.version,
+                commands,
+                proposal_description: self.proposal_description,
+                proposal_link: self.proposal_link,
+            },
+            problems,
+        )
+    }
 }
 
 impl EditIntersection {
@@ -196,4 +299,78 @@ impl PermanentEditIntersection {
             PermanentEditIntersection::Closed => Ok(EditIntersection::Closed),
         }
     }
+
+    fn rebase(self, i: IntersectionID, matcher: &MapMatcher) -> Result<Self, String> {
+        let map = matcher.new_map();
+        // If the road got split, only the piece touching this intersection matters
+        let piece = |r: OriginalRoad| -> Result<(RoadID, bool), String> {
+            matcher
+                .orig_road(r)?
+                .into_iter()
+                .find(|(id, _)| map.get_r(*id).src_i == i || map.get_r(*id).dst_i == i)
+                .ok_or(format!(
+                    "{} doesn't touch {} anymore",
+                    r,
+                    map.get_i(i).orig_id
+                ))
+        };
+
+        match self {
+            PermanentEditIntersection::StopSign { must_stop } => {
+                let mut translated = BTreeMap::new();
+                for (r, stop) in must_stop {
+                    translated.insert(map.get_r(piece(r)?.0).orig_id, stop);
+                }
+                Ok(PermanentEditIntersection::StopSign {
+                    must_stop: translated,
+                })
+            }
+            PermanentEditIntersection::TrafficSignal(mut ts) => {
+                let translate = |dr: &seattle_traffic_signals::DirectedRoad| -> Result<_, String> {
+                    let (id, reversed) = piece(OriginalRoad::new(
+                        dr.osm_way_id,
+                        (dr.osm_node1, dr.osm_node2),
+                    ))?;
+                    let orig = map.get_r(id).orig_id;
+                    Ok(seattle_traffic_signals::DirectedRoad {
+                        osm_way_id: orig.osm_way_id.0,
+                        osm_node1: orig.i1.0,
+                        osm_node2: orig.i2.0,
+                        is_forwards: dr.is_forwards != reversed,
+                    })
+                };
+                let node = map.get_i(i).orig_id.0;
+                ts.intersection_osm_node_id = node;
+                for phase in &mut ts.phases {
+                    for turn in phase
+                        .protected_turns
+                        .iter_mut()
+                        .chain(phase.permitted_turns.iter_mut())
+                    {
+                        turn.from = translate(&turn.from)?;
+                        turn.to = translate(&turn.to)?;
+                        turn.intersection_osm_node_id = node;
+                    }
+                }
+                Ok(PermanentEditIntersection::TrafficSignal(ts))
+            }
+            PermanentEditIntersection::Closed => Ok(PermanentEditIntersection::Closed),
+        }
+    }
+}
+
+impl EditRoad {
+    // The same edit, for a road of this length pointing the other way
+    fn reversed(mut self, len: Distance) -> EditRoad {
+        self.lanes_ltr.reverse();
+        for (_, dir) in &mut self.lanes_ltr {
+            *dir = dir.opposite();
+        }
+        self.conditional_ltr.reverse();
+        for (dist, _) in &mut self.crossings {
+            *dist = (len - *dist).max(Distance::ZERO);
+        }
+        self.crossings.reverse();
+        self
+    }
 }
@@ -7,6 +7,7 @@ mod objects;
 pub mod osm;
 mod pathfind;
 pub mod raw;
+mod rebase;
 mod traversable;
 
 pub use crate::city::City;
@@ -21,7 +22,8 @@ pub use crate::objects::building::{
 pub use crate::objects::bus_stop::{BusRoute, BusRouteID, BusStop, BusStopID};
 pub use crate::objects::intersection::{Intersection, IntersectionID, IntersectionType};
 pub use crate::objects::lane::{
-    Lane, LaneID, LaneType, PARKING_LOT_SPOT_LENGTH, PARKING_SPOT_LENGTH,
+    describe_cents, ConditionalRestriction, Lane, LaneID, LaneType, ParkingRules, TimeWindow,
+    OVERSTAY_FINE_CENTS, PARKING_LOT_SPOT_LENGTH, PARKING_SPOT_LENGTH,
 };
 pub use crate::objects::parking_lot::{ParkingLot, ParkingLotID};
 pub use crate::objects::road::{DirectedRoadID, Direction, Road, RoadID};
@@ -32,8 +34,9 @@ pub use crate::objects::turn::{
 };
 pub use crate::objects::zone::{AccessRestrictions, Zone};
 pub use crate::pathfind::uber_turns::{IntersectionCluster, UberTurn, UberTurnGroup};
-use crate::pathfind::Pathfinder;
-pub use crate::pathfind::{Path, PathConstraints, PathRequest, PathStep};
+use crate::pathfind::{GraphCache, Pathfinder};
+pub use crate::pathfind::{MidBlockCrossing, Path, PathConstraints, PathRequest, PathStep};
+pub use crate::rebase::MapMatcher;
 pub use crate::traversable::{Position, Traversable};
 use abstutil::{deserialize_btreemap, serialize_btreemap};
 use geom::{Bounds, Distance, GPSBounds, Polygon};
@@ -76,6 +79,8 @@ pub struct Map {
 
     pathfinder: Pathfinder,
     pathfinder_dirty: bool,
+    #[serde(skip_serializing, skip_deserializing)]
+    dijkstra_graphs: GraphCache,
     // Not the source of truth, just cached.
     zones: Vec<Zone>,
 
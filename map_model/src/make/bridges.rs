@@ -28,3 +28,18 @@ pub fn find_bridges(roads: &mut Vec<Road>, bounds: &Bounds, timer: &mut Timer) {
         }
     }
 }
+
+// Bridges and tunnels don't follow the terrain underneath them, so just interpolate between their
+// endpoints.
+// TODO An intersection in the middle of a bridge still gets the elevation of the ground below.
+pub fn flatten_elevation(roads: &mut Vec<Road>) {
+    for r in roads {
+        if (r.osm_tags.contains_key("bridge") && !r.osm_tags.is("bridge", "no"))
+            || (r.osm_tags.contains_key("tunnel") && !r.osm_tags.is("tunnel", "no"))
+        {
+            let first = r.elevation_profile[0];
+            let last = *r.elevation_profile.last().unwrap();
+            r.elevation_profile = vec![first, last];
+        }
+    }
+}
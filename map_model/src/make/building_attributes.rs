@@ -0,0 +1,203 @@
+use crate::{BuildingType, NamePerLanguage};
+use abstutil::Tags;
+use std::collections::BTreeSet;
+
+// Rough guesses at how many people live and work in a building, from its footprint and tags. Cities
+// with census data can scale these later; see importer/src/population.rs.
+
+const METERS_PER_LEVEL: f64 = 3.0;
+// Floor area per resident, including hallways, stairs, and walls
+const SQ_METERS_PER_RESIDENT: f64 = 40.0;
+// A house is one household, no matter how big
+const MAX_RESIDENTS_PER_HOUSE: usize = 8;
+// Each shop, cafe, etc mapped in a building that's otherwise residential
+const JOBS_PER_AMENITY: usize = 5;
+
+pub fn classify_bldg(
+    tags: &Tags,
+    amenities: &BTreeSet<(NamePerLanguage, String)>,
+    area_sq_meters: f64,
+) -> BuildingType {
+    // used: top values from https://taginfo.openstreetmap.org/keys/building#values (>100k uses)
+
+    // These are (name, amenity type) pairs, produced by get_bldg_amenities in
+    // convert_osm/src/extract.rs.
+    let amenity_jobs = amenities.len() * JOBS_PER_AMENITY;
+
+    if tags.is("ruins", "yes") {
+        if amenity_jobs > 0 {
+            return BuildingType::Commercial(amenity_jobs);
+        }
+        return BuildingType::Empty;
+    }
+
+    let floor_area = area_sq_meters * num_levels(tags);
+    if let Some(sq_meters_per_job) = sq_meters_per_job(tags) {
+        let jobs = (floor_area / sq_meters_per_job) as usize;
+        return BuildingType::Commercial(jobs.max(amenity_jobs).max(1));
+    }
+    if tags.is_any(
+        "building",
+        vec![
+            "construction",
+            "garage",
+            "garages",
+            "shed",
+            "roof",
+            "greenhouse",
+            "farm_auxiliary",
+            "barn",
+            "service",
+            "parking",
+        ],
+    ) {
+        return BuildingType::Empty;
+    }
+
+    let residents = if tags.is_any(
+        "building",
+        vec![
+            "house",
+            "detached",
+            "semidetached_house",
+            "farm",
+            "hut",
+            "static_caravan",
+            "cabin",
+        ],
+    ) {
+        ((floor_area / SQ_METERS_PER_RESIDENT) as usize).min(MAX_RESIDENTS_PER_HOUSE)
+    } else if amenity_jobs > 0 && !tags.is_any("building", vec!["apartments", "residential"]) {
+        // Something like building=yes with shops. Assume the ground floor is commercial.
+        let upper_area = floor_area - area_sq_meters;
+        if upper_area < SQ_METERS_PER_RESIDENT {
+            return BuildingType::Commercial(amenity_jobs);
+        }
+        (upper_area / SQ_METERS_PER_RESIDENT) as usize
+    } else {
+        (floor_area / SQ_METERS_PER_RESIDENT) as usize
+    }
+    .max(1);
+
+    if amenity_jobs > 0 {
+        BuildingType::ResidentialCommercial(residents, amenity_jobs)
+    } else {
+        BuildingType::Residential(residents)
+    }
+}
+
+// None means the building isn't a workplace
+fn sq_meters_per_job(tags: &Tags) -> Option<f64> {
+    if tags.is_any(
+        "building",
+        vec!["office", "commercial", "civic", "public", "government"],
+    ) {
+        Some(25.0)
+    } else if tags.is_any("building", vec!["hospital"]) || tags.is("amenity", "hospital") {
+        Some(40.0)
+    } else if tags.is_any(
+        "building",
+        vec!["retail", "supermarket", "kiosk", "school", "university"],
+    ) {
+        Some(50.0)
+    } else if tags.is_any(
+        "building",
+        vec!["industrial", "warehouse", "manufacture", "hangar"],
+    ) {
+        Some(100.0)
+    } else if tags.is_any(
+        "building",
+        vec![
+            "church",
+            "cathedral",
+            "chapel",
+            "mosque",
+            "synagogue",
+            "temple",
+        ],
+    ) {
+        Some(200.0)
+    } else {
+        None
+    }
+}
+
+// Including some of the attic, if it's tagged
+fn num_levels(tags: &Tags) -> f64 {
+    let parse = |key: &str| tags.get(key).and_then(|x| x.parse::<f64>().ok());
+    if let Some(levels) = parse("building:levels") {
+        return levels.max(1.0) + 0.5 * parse("roof:levels").unwrap_or(0.0);
+    }
+    for key in &["height", "building:height"] {
+        if let Some(meters) = tags.get(key).and_then(|x| parse_height(x)) {
+            return (meters / METERS_PER_LEVEL).round().max(1.0);
+        }
+    }
+    // Untagged apartment blocks are usually a few stories. Assume everything else has one.
+    if tags.is_any("building", vec!["apartments", "residential"]) {
+        3.0
+    } else {
+        1.0
+    }
+}
+
+// Handles "12", "12 m", and "40'"
+fn parse_height(value: &str) -> Option<f64> {
+    if let Some(feet) = value.strip_suffix('\'') {
+        return feet.trim().parse::<f64>().ok().map(|x| x * 0.3048);
+    }
+    value.trim_end_matches('m').trim().parse::<f64>().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(kv: Vec<&str>) -> Tags {
+        let mut tags = Tags::new(std::collections::BTreeMap::new());
+        for pair in kv {
+            let parts = pair.split('=').collect::<Vec<_>>();
+            tags.insert(parts[0], parts[1]);
+        }
+        tags
+    }
+
+    #[test]
+    fn test_classify_bldg() {
+        let mut ok = true;
+        for (input, area, num_amenities, expected_residents, expected_jobs) in vec![
+            (vec!["building=house"], 100.0, 0, 2, 0),
+            (vec!["building=house", "building:levels=2"], 400.0, 0, 8, 0),
+            (vec!["building=apartments"], 400.0, 0, 30, 0),
+            (vec!["building=apartments", "height=30 m"], 400.0, 0, 100, 0),
+            (vec!["building=yes", "building:levels=4"], 200.0, 2, 15, 10),
+            (vec!["building=yes"], 200.0, 1, 0, 5),
+            (
+                vec!["building=office", "building:levels=10"],
+                1000.0,
+                0,
+                0,
+                400,
+            ),
+            (vec!["building=warehouse"], 5000.0, 0, 0, 50),
+            (vec!["building=garage"], 20.0, 0, 0, 0),
+        ] {
+            let amenities = (0..num_amenities)
+                .map(|idx| (NamePerLanguage::unnamed(), format!("shop {}", idx)))
+                .collect();
+            let bldg_type = classify_bldg(&tags(input.clone()), &amenities, area);
+            if bldg_type.num_residents() != expected_residents
+                || bldg_type.num_jobs() != expected_jobs
+            {
+                println!("For input: {:?} with area {}", input, area);
+                println!("Got:");
+                println!("  {:?}", bldg_type);
+                println!("Expected:");
+                println!("  {} residents, {} jobs", expected_residents, expected_jobs);
+                println!("");
+                ok = false;
+            }
+        }
+        assert!(ok);
+    }
+}
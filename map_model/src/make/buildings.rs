@@ -1,13 +1,10 @@
+use crate::make::building_attributes::classify_bldg;
 use crate::make::match_points_to_lanes;
 use crate::raw::RawBuilding;
-use crate::{
-    osm, Building, BuildingID, BuildingType, LaneID, Map, NamePerLanguage, OffstreetParking,
-};
+use crate::{osm, Building, BuildingID, LaneID, Map, NamePerLanguage, OffstreetParking};
 use abstutil::{Tags, Timer};
 use geom::{Distance, HashablePt2D, Line, Polygon};
-use rand::{Rng, SeedableRng};
-use rand_xorshift::XorShiftRng;
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::collections::{BTreeMap, HashSet};
 
 pub fn make_all_buildings(
     input: &BTreeMap<osm::OsmID, RawBuilding>,
@@ -57,7 +54,6 @@ pub fn make_all_buildings(
             };
 
             let id = BuildingID(results.len());
-            let mut rng = XorShiftRng::seed_from_u64(orig_id.inner() as u64);
             results.push(Building {
                 id,
                 polygon: b.polygon.clone(),
@@ -66,7 +62,7 @@ pub fn make_all_buildings(
                 orig_id,
                 label_center: b.polygon.polylabel(),
                 amenities: b.amenities.clone(),
-                bldg_type: classify_bldg(&b.osm_tags, &b.amenities, b.polygon.area(), &mut rng),
+                bldg_type: classify_bldg(&b.osm_tags, &b.amenities, b.polygon.area()),
                 parking: if let Some(n) = b.public_garage_name.clone() {
                     OffstreetParking::PublicGarage(n, b.num_parking_spots)
                 } else {
@@ -75,6 +71,7 @@ pub fn make_all_buildings(
                         b.osm_tags.is("building", "parking") || b.osm_tags.is("amenity", "parking"),
                     )
                 },
+                charging_points: charging_points(&b.osm_tags),
 
                 sidewalk_pos: *sidewalk_pos,
                 driveway_geom: sidewalk_line.to_polyline(),
@@ -91,6 +88,27 @@ pub fn make_all_buildings(
     results
 }
 
+// From capacity:charging, which convert_osm also fills in from charging station points inside the
+// area, or the capacity of something tagged amenity=charging_station.
+pub fn charging_points(tags: &Tags) -> usize {
+    if tags.is("capacity:charging", "yes") {
+        return 1;
+    }
+    if let Some(n) = tags
+        .get("capacity:charging")
+        .and_then(|x| x.parse::<usize>().ok())
+    {
+        return n;
+    }
+    if tags.is("amenity", "charging_station") {
+        return tags
+            .get("capacity")
+            .and_then(|x| x.parse::<usize>().ok())
+            .unwrap_or(1);
+    }
+    0
+}
+
 // Adjust the path to start on the building's border, not center
 fn trim_path(poly: &Polygon, path: Line) -> Line {
     for bldg_line in poly.points().windows(2) {
@@ -113,88 +131,3 @@ fn get_address(tags: &Tags, sidewalk: LaneID, map: &Map) -> String {
         _ => format!("??? {}", map.get_parent(sidewalk).get_name(None)),
     }
 }
-
-fn classify_bldg(
-    tags: &Tags,
-    amenities: &BTreeSet<(NamePerLanguage, String)>,
-    area_sq_meters: f64,
-    rng: &mut XorShiftRng,
-) -> BuildingType {
-    // used: top values from https://taginfo.openstreetmap.org/keys/building#values (>100k uses)
-
-    let mut commercial = false;
-    let workers;
-
-    // These are (name, amenity type) pairs, produced by get_bldg_amenities in
-    // convert_osm/src/osm_reader.rs.
-    if !amenities.is_empty() {
-        commercial = true;
-    }
-
-    if tags.is("ruins", "yes") {
-        if commercial {
-            return BuildingType::Commercial;
-        }
-        return BuildingType::Empty;
-    }
-
-    if tags.is_any(
-        "building",
-        vec![
-            "office",
-            "industrial",
-            "commercial",
-            "retail",
-            "warehouse",
-            "civic",
-            "public",
-        ],
-    ) {
-        return BuildingType::Commercial;
-    } else if tags.is_any(
-        "building",
-        vec!["school", "university", "construction", "church"],
-    ) {
-        // TODO: special handling in future
-        return BuildingType::Empty;
-    } else if tags.is_any(
-        "building",
-        vec![
-            "garage",
-            "garages",
-            "shed",
-            "roof",
-            "greenhouse",
-            "farm_auxiliary",
-            "barn",
-            "service",
-        ],
-    ) {
-        return BuildingType::Empty;
-    } else if tags.is_any(
-        "building",
-        vec!["house", "detached", "semidetached_house", "farm"],
-    ) {
-        workers = rng.gen_range(0, 3);
-    } else if tags.is_any("building", vec!["hut", "static_caravan", "cabin"]) {
-        workers = rng.gen_range(0, 2);
-    } else if tags.is_any("building", vec!["apartments", "terrace", "residential"]) {
-        let levels = tags
-            .get("building:levels")
-            .and_then(|x| x.parse::<usize>().ok())
-            .unwrap_or(1);
-        // TODO is it worth using height or building:height as an alternative if not tagged?
-        // 1 person per 10 square meters
-        let residents = (levels as f64 * area_sq_meters / 10.0) as usize;
-        workers = (residents / 3) as usize;
-    } else {
-        workers = rng.gen_range(0, 2);
-    }
-    if commercial {
-        if workers > 0 {
-            return BuildingType::ResidentialCommercial(workers);
-        }
-        return BuildingType::Commercial;
-    }
-    return BuildingType::Residential(workers);
-}
@@ -1,9 +1,11 @@
 use crate::{
-    osm, Direction, DrivingSide, LaneType, NORMAL_LANE_THICKNESS, SHOULDER_THICKNESS,
-    SIDEWALK_THICKNESS,
+    osm, ConditionalRestriction, Direction, DrivingSide, LaneType, ParkingRules, PathConstraints,
+    TimeWindow, TurnType, NORMAL_LANE_THICKNESS, SHOULDER_THICKNESS, SIDEWALK_THICKNESS,
 };
 use abstutil::Tags;
-use geom::Distance;
+use enumset::EnumSet;
+use geom::{Distance, Duration};
+use std::collections::BTreeSet;
 use std::iter;
 
 #[derive(PartialEq)]
@@ -11,6 +13,9 @@ pub struct LaneSpec {
     pub lt: LaneType,
     pub dir: Direction,
     pub width: Distance,
+    pub turn_restrictions: Option<BTreeSet<TurnType>>,
+    pub conditional: Option<ConditionalRestriction>,
+    pub parking_rules: Option<ParkingRules>,
 }
 
 fn fwd(lt: LaneType) -> LaneSpec {
@@ -22,6 +27,9 @@ fn fwd(lt: LaneType) -> LaneSpec {
             LaneType::Shoulder => SHOULDER_THICKNESS,
             _ => NORMAL_LANE_THICKNESS,
         },
+        turn_restrictions: None,
+        conditional: None,
+        parking_rules: None,
     }
 }
 
@@ -34,6 +42,9 @@ fn back(lt: LaneType) -> LaneSpec {
             LaneType::Shoulder => SHOULDER_THICKNESS,
             _ => NORMAL_LANE_THICKNESS,
         },
+        turn_restrictions: None,
+        conditional: None,
+        parking_rules: None,
     }
 }
 
@@ -96,14 +107,6 @@ pub fn get_lane_specs_ltr(tags: &Tags, driving_side: DrivingSide) -> Vec<LaneSpe
         if tags.is("access", "no") && (tags.is("bus", "yes") || tags.is("psv", "yes")) {
             // Sup West Seattle
             LaneType::Bus
-        } else if tags
-            .get("motor_vehicle:conditional")
-            .map(|x| x.starts_with("no"))
-            .unwrap_or(false)
-            && tags.is("bus", "yes")
-        {
-            // Example: 3rd Ave in downtown Seattle
-            LaneType::Bus
         } else if tags.is("access", "no") || tags.is("highway", "construction") {
             LaneType::Construction
         } else {
@@ -170,6 +173,27 @@ pub fn get_lane_specs_ltr(tags: &Tags, driving_side: DrivingSide) -> Vec<LaneSpe
             }
         }
     }
+    // Peak-hour bus lanes
+    let fwd_conditional_bus_spec = if let Some(s) = tags
+        .get("bus:lanes:forward:conditional")
+        .or_else(|| tags.get("psv:lanes:forward:conditional"))
+    {
+        Some(s)
+    } else if oneway {
+        tags.get("bus:lanes:conditional")
+            .or_else(|| tags.get("psv:lanes:conditional"))
+    } else {
+        None
+    };
+    if let Some(spec) = fwd_conditional_bus_spec {
+        assign_conditional_bus_lanes(&mut fwd_side, spec);
+    }
+    if let Some(spec) = tags
+        .get("bus:lanes:backward:conditional")
+        .or_else(|| tags.get("psv:lanes:backward:conditional"))
+    {
+        assign_conditional_bus_lanes(&mut back_side, spec);
+    }
 
     if tags.is_any("cycleway", vec!["lane", "track"]) {
         fwd_side.push(fwd(LaneType::Biking));
@@ -207,11 +231,30 @@ pub fn get_lane_specs_ltr(tags: &Tags, driving_side: DrivingSide) -> Vec<LaneSpe
             || tags.is_any(osm::PARKING_BOTH, has_parking.clone());
         let parking_lane_back = tags.is_any(osm::PARKING_LEFT, has_parking.clone())
             || tags.is_any(osm::PARKING_BOTH, has_parking);
+        // Parking that's only allowed off-peak, like no_stopping @ (Mo-Fr 07:00-09:00)
+        let no_parking = |key: &str| {
+            tags.get(&format!("{}:conditional", key))
+                .and_then(|raw| conditional_windows(raw, |value| value.starts_with("no")))
+                .map(|windows| ConditionalRestriction {
+                    allow: EnumSet::new(),
+                    windows,
+                })
+        };
         if parking_lane_fwd {
-            fwd_side.push(fwd(LaneType::Parking));
+            let mut spec = fwd(LaneType::Parking);
+            spec.conditional =
+                no_parking(osm::PARKING_RIGHT).or_else(|| no_parking(osm::PARKING_BOTH));
+            spec.parking_rules = parking_rules(tags, "parking:right:")
+                .or_else(|| parking_rules(tags, "parking:both:"));
+            fwd_side.push(spec);
         }
         if parking_lane_back {
-            back_side.push(back(LaneType::Parking));
+            let mut spec = back(LaneType::Parking);
+            spec.conditional =
+                no_parking(osm::PARKING_LEFT).or_else(|| no_parking(osm::PARKING_BOTH));
+            spec.parking_rules = parking_rules(tags, "parking:left:")
+                .or_else(|| parking_rules(tags, "parking:both:"));
+            back_side.push(spec);
         }
     }
 
@@ -219,7 +262,8 @@ pub fn get_lane_specs_ltr(tags: &Tags, driving_side: DrivingSide) -> Vec<LaneSpe
         fwd_side.push(fwd(LaneType::Sidewalk));
         back_side.push(back(LaneType::Sidewalk));
     } else if tags.is(osm::SIDEWALK, "separate") {
-        // TODO Need to snap separate sidewalks to ways. Until then, just do this.
+        // convert_osm replaces this when it can match the separately mapped sidewalks. If it
+        // couldn't, just guess.
         fwd_side.push(fwd(LaneType::Sidewalk));
         if !back_side.is_empty() {
             back_side.push(back(LaneType::Sidewalk));
@@ -268,9 +312,334 @@ pub fn get_lane_specs_ltr(tags: &Tags, driving_side: DrivingSide) -> Vec<LaneSpe
         back_side.push(back(LaneType::Shoulder));
     }
 
+    // School streets and other roads closed to motor vehicles some of the time. Example: 3rd Ave
+    // in downtown Seattle.
+    if let Some(windows) = tags
+        .get("motor_vehicle:conditional")
+        .and_then(|raw| conditional_windows(raw, |value| value == "no"))
+    {
+        let mut allow = EnumSet::new();
+        allow.insert(PathConstraints::Bike);
+        if tags.is_any("bus", vec!["yes", "designated"])
+            || tags.is_any("psv", vec!["yes", "designated"])
+        {
+            allow.insert(PathConstraints::Bus);
+        }
+        for spec in fwd_side.iter_mut().chain(back_side.iter_mut()) {
+            if spec.lt == LaneType::Driving {
+                spec.conditional = Some(ConditionalRestriction {
+                    allow,
+                    windows: windows.clone(),
+                });
+            }
+        }
+    }
+    // Two-way roads that become one-way some of the time
+    if !oneway {
+        if let Some(windows) = tags
+            .get("oneway:conditional")
+            .and_then(|raw| conditional_windows(raw, |value| value == "yes"))
+        {
+            let mut allow = EnumSet::new();
+            if tags.is("oneway:bicycle", "no") {
+                allow.insert(PathConstraints::Bike);
+            }
+            for spec in back_side.iter_mut() {
+                if spec.lt == LaneType::Driving || spec.lt == LaneType::Bus {
+                    spec.conditional = Some(ConditionalRestriction {
+                        allow,
+                        windows: windows.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    // Turn lanes only matter at the end of the original OSM way, not every piece of it.
+    if tags.contains_key(osm::ENDPT_FWD) {
+        if let Some(spec) = tags
+            .get("turn:lanes:forward")
+            .or_else(|| tags.get("turn:lanes"))
+        {
+            assign_turn_lanes(&mut fwd_side, spec, driving_side);
+        }
+    }
+    if tags.contains_key(osm::ENDPT_BACK) {
+        if let Some(spec) = tags.get("turn:lanes:backward") {
+            assign_turn_lanes(&mut back_side, spec, driving_side);
+        }
+    }
+
     assemble_ltr(fwd_side, back_side, driving_side)
 }
 
+// turn:lanes lists every lane for motor vehicles from left to right, in the direction of travel.
+// If the number of lanes doesn't match up, the tag is ignored.
+fn assign_turn_lanes(side: &mut [LaneSpec], spec: &str, driving_side: DrivingSide) {
+    let mut parts: Vec<&str> = spec.split('|').collect();
+    // The side is ordered from the road center outwards.
+    if driving_side == DrivingSide::Left {
+        parts.reverse();
+    }
+    let lanes: Vec<&mut LaneSpec> = side
+        .iter_mut()
+        .filter(|spec| spec.lt == LaneType::Driving || spec.lt == LaneType::Bus)
+        .collect();
+    if lanes.len() != parts.len() {
+        return;
+    }
+    for (lane, part) in lanes.into_iter().zip(parts) {
+        lane.turn_restrictions = parse_turn_lane(part);
+    }
+}
+
+// Like bus:lanes, but only designated some of the time.
+fn assign_conditional_bus_lanes(side: &mut [LaneSpec], raw: &str) {
+    for (value, windows) in parse_conditional(raw) {
+        let parts: Vec<&str> = value.split('|').collect();
+        let lanes: Vec<&mut LaneSpec> = side
+            .iter_mut()
+            .filter(|spec| spec.lt != LaneType::SharedLeftTurn)
+            .collect();
+        if parts.len() != lanes.len() {
+            continue;
+        }
+        for (lane, part) in lanes.into_iter().zip(parts) {
+            if part.trim() == "designated" && lane.lt == LaneType::Driving {
+                let mut allow = EnumSet::new();
+                allow.insert(PathConstraints::Bus);
+                lane.conditional = Some(ConditionalRestriction {
+                    allow,
+                    windows: windows.clone(),
+                });
+            }
+        }
+    }
+}
+
+// Prices and time limits, from {prefix}charge like "2.50 USD/hour", {prefix}maxstay like
+// "2 hours", and optionally {prefix}fee:conditional like "yes @ (Mo-Sa 08:00-20:00)". Parking lots
+// use a blank prefix. None if there's no price or limit.
+pub fn parking_rules(tags: &Tags, prefix: &str) -> Option<ParkingRules> {
+    let cents_per_hour = tags
+        .get(&format!("{}charge", prefix))
+        .and_then(|x| parse_charge(x));
+    let max_stay = tags
+        .get(&format!("{}maxstay", prefix))
+        .and_then(|x| parse_maxstay(x));
+    if cents_per_hour.is_none() && max_stay.is_none() {
+        return None;
+    }
+    let windows = tags
+        .get(&format!("{}fee:conditional", prefix))
+        .and_then(|raw| conditional_windows(raw, |value| value == "yes"))
+        .unwrap_or_else(Vec::new);
+    Some(ParkingRules {
+        cents_per_hour: cents_per_hour.unwrap_or(0),
+        max_stay,
+        windows,
+    })
+}
+
+// Like "2.50 USD/hour", "1 EUR/h", or just "3". The currency is ignored. None for charges that
+// aren't per hour, like a flat daily rate.
+fn parse_charge(raw: &str) -> Option<usize> {
+    let mut parts = raw.splitn(2, '/');
+    let amount = parts.next().unwrap().trim();
+    if let Some(unit) = parts.next() {
+        if !["hour", "h", "hr"].contains(&unit.trim()) {
+            return None;
+        }
+    }
+    let number = amount.split_whitespace().next()?;
+    let dollars = number.parse::<f64>().ok()?;
+    if dollars < 0.0 {
+        return None;
+    }
+    Some((dollars * 100.0).round() as usize)
+}
+
+// Like "2 hours", "90 minutes", or "1 h"
+fn parse_maxstay(raw: &str) -> Option<Duration> {
+    let parts: Vec<&str> = raw.split_whitespace().collect();
+    if parts.len() != 2 {
+        return None;
+    }
+    let value = parts[0].parse::<f64>().ok()?;
+    match parts[1] {
+        "minute" | "minutes" | "min" => Some(Duration::f64_minutes(value)),
+        "hour" | "hours" | "h" => Some(Duration::f64_minutes(value * 60.0)),
+        "day" | "days" => Some(Duration::f64_minutes(value * 24.0 * 60.0)),
+        _ => None,
+    }
+}
+
+// All of the windows when the value matches
+fn conditional_windows<F: Fn(&str) -> bool>(raw: &str, matches: F) -> Option<Vec<TimeWindow>> {
+    let windows: Vec<TimeWindow> = parse_conditional(raw)
+        .into_iter()
+        .filter(|(value, _)| matches(value))
+        .flat_map(|(_, windows)| windows)
+        .collect();
+    if windows.is_empty() {
+        None
+    } else {
+        Some(windows)
+    }
+}
+
+// Parses the *:conditional syntax, like "no @ (Mo-Fr 07:00-09:00,16:00-18:00); yes @ wet". Only
+// conditions expressed as opening hours are understood; the rest are skipped. Since the
+// simulation models a weekday, rules that only apply on weekends are dropped.
+fn parse_conditional(raw: &str) -> Vec<(String, Vec<TimeWindow>)> {
+    let mut results = Vec::new();
+    for clause in split_outside_parens(raw, ';') {
+        let mut parts = clause.splitn(2, '@');
+        let value = parts.next().unwrap().trim();
+        let condition = if let Some(x) = parts.next() {
+            x.trim().trim_start_matches('(').trim_end_matches(')')
+        } else {
+            continue;
+        };
+        let mut windows = Vec::new();
+        let mut ok = true;
+        for rule in condition.split(';') {
+            if let Some(w) = parse_opening_hours_rule(rule) {
+                windows.extend(w);
+            } else {
+                ok = false;
+                break;
+            }
+        }
+        if ok && !windows.is_empty() {
+            results.push((value.to_string(), windows));
+        }
+    }
+    results
+}
+
+fn split_outside_parens(raw: &str, separator: char) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (idx, c) in raw.char_indices() {
+        if c == '(' {
+            depth += 1;
+        } else if c == ')' && depth > 0 {
+            depth -= 1;
+        } else if c == separator && depth == 0 {
+            pieces.push(&raw[start..idx]);
+            start = idx + c.len_utf8();
+        }
+    }
+    pieces.push(&raw[start..]);
+    pieces
+}
+
+// Something like "Mo-Fr 07:00-09:00,16:00-18:00". None if it isn't understood.
+fn parse_opening_hours_rule(rule: &str) -> Option<Vec<TimeWindow>> {
+    let mut weekdays = None;
+    let mut windows = Vec::new();
+    for token in rule.split_whitespace() {
+        if token.contains(':') {
+            for piece in token.split(',').filter(|x| !x.is_empty()) {
+                windows.push(TimeWindow::parse(piece)?);
+            }
+        } else if weekdays.is_none() {
+            weekdays = Some(includes_weekday(token)?);
+        } else {
+            return None;
+        }
+    }
+    match weekdays {
+        Some(false) => Some(Vec::new()),
+        Some(true) if windows.is_empty() => Some(vec![TimeWindow::parse("00:00-24:00").unwrap()]),
+        None if windows.is_empty() => None,
+        _ => Some(windows),
+    }
+}
+
+// Like "Mo-Fr" or "Sa,Su". None if it isn't understood.
+fn includes_weekday(days: &str) -> Option<bool> {
+    let day = |x: &str| {
+        ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]
+            .iter()
+            .position(|d| *d == x)
+    };
+    let mut weekday = false;
+    for piece in days.split(',').filter(|x| !x.is_empty()) {
+        let range: Vec<&str> = piece.split('-').collect();
+        let (from, to) = match range.len() {
+            1 => (day(range[0])?, day(range[0])?),
+            2 => (day(range[0])?, day(range[1])?),
+            _ => {
+                return None;
+            }
+        };
+        // Ranges like Sa-Mo wrap around
+        weekday |= from <= 4 || to <= 4 || to < from;
+    }
+    Some(weekday)
+}
+
+fn parse_turn_lane(part: &str) -> Option<BTreeSet<TurnType>> {
+    if is_unrestricted_turn_lane(part) {
+        return None;
+    }
+    let types: BTreeSet<TurnType> = part.split(';').filter_map(turn_types).flatten().collect();
+    // Don't strand the lane if nothing was recognized
+    if types.is_empty() {
+        None
+    } else {
+        Some(types)
+    }
+}
+
+fn is_unrestricted_turn_lane(part: &str) -> bool {
+    // TODO Probably the target lane should get marked as LaneType::Bus
+    part == "no" || part == "none" || part == "yes" || part == "psv" || part == "bus"
+}
+
+// The turns allowed by one value in a turn:lanes entry, or None if the value isn't recognized
+fn turn_types(value: &str) -> Option<Vec<TurnType>> {
+    match value {
+        "left" | "left\\left" => Some(vec![TurnType::Left]),
+        "right" => Some(vec![TurnType::Right]),
+        // TODO What is blank supposed to mean? From few observed cases, same as through
+        "through" | "" => Some(vec![TurnType::Straight]),
+        // TODO Check this more carefully
+        "slight_right" | "slight right" | "merge_to_right" | "sharp_right" => {
+            Some(vec![TurnType::Straight, TurnType::Right])
+        }
+        "slight_left" | "slight left" | "merge_to_left" | "sharp_left" => {
+            Some(vec![TurnType::Straight, TurnType::Left])
+        }
+        // TODO We need TurnType::UTurn. Until then, u-turns usually show up as left turns.
+        "reverse" => Some(vec![TurnType::Left]),
+        _ => None,
+    }
+}
+
+// Values in turn:lanes tags that get ignored, so map import can warn about them
+pub fn unknown_turn_lanes(tags: &Tags) -> Vec<String> {
+    let mut unknown = Vec::new();
+    for key in vec!["turn:lanes", "turn:lanes:forward", "turn:lanes:backward"] {
+        if let Some(spec) = tags.get(key) {
+            for part in spec.split('|') {
+                if is_unrestricted_turn_lane(part) {
+                    continue;
+                }
+                for value in part.split(';') {
+                    if turn_types(value).is_none() {
+                        unknown.push(value.to_string());
+                    }
+                }
+            }
+        }
+    }
+    unknown
+}
+
 fn assemble_ltr(
     mut fwd_side: Vec<LaneSpec>,
     mut back_side: Vec<LaneSpec>,
@@ -308,6 +677,28 @@ mod tests {
         }
     }
 
+    // - means no restrictions. Lowercase is just one type of turn, uppercase is that plus
+    // straight, and x is left and right.
+    fn turns_to_char(turns: &Option<BTreeSet<TurnType>>) -> &'static str {
+        let turns = if let Some(ref turns) = turns {
+            turns
+        } else {
+            return "-";
+        };
+        let left = turns.contains(&TurnType::Left);
+        let straight = turns.contains(&TurnType::Straight);
+        let right = turns.contains(&TurnType::Right);
+        match (left, straight, right) {
+            (true, false, false) => "l",
+            (false, true, false) => "s",
+            (false, false, true) => "r",
+            (true, true, false) => "L",
+            (false, true, true) => "R",
+            (true, false, true) => "x",
+            _ => "*",
+        }
+    }
+
     fn tags(kv: Vec<&str>) -> Tags {
         let mut tags = Tags::new(std::collections::BTreeMap::new());
         for pair in kv {
@@ -320,7 +711,7 @@ mod tests {
     #[test]
     fn test_osm_to_specs() {
         let mut ok = true;
-        for (url, input, driving_side, expected_lt, expected_dir) in vec![
+        for (url, input, driving_side, expected_lt, expected_dir, expected_turns) in vec![
             (
                 "https://www.openstreetmap.org/way/428294122",
                 vec![
@@ -332,6 +723,7 @@ mod tests {
                 DrivingSide::Right,
                 "sbdds",
                 "v^^^^",
+                "-----",
             ),
             (
                 "https://www.openstreetmap.org/way/8591383",
@@ -345,6 +737,7 @@ mod tests {
                 DrivingSide::Right,
                 "sbbds",
                 "vv^^^",
+                "-----",
             ),
             (
                 "https://www.openstreetmap.org/way/353690151",
@@ -358,6 +751,7 @@ mod tests {
                 DrivingSide::Right,
                 "spddddbbps",
                 "vvvv^^v^^^",
+                "----------",
             ),
             (
                 "https://www.openstreetmap.org/way/389654080",
@@ -373,6 +767,7 @@ mod tests {
                 DrivingSide::Right,
                 "spdCdbbs",
                 "vvv^^v^^",
+                "--------",
             ),
             (
                 "https://www.openstreetmap.org/way/369623526",
@@ -387,6 +782,7 @@ mod tests {
                 DrivingSide::Right,
                 "sbbdps",
                 "vv^^^^",
+                "------",
             ),
             (
                 "https://www.openstreetmap.org/way/534549104",
@@ -401,6 +797,7 @@ mod tests {
                 DrivingSide::Right,
                 "sddbbs",
                 "v^^v^^",
+                "------",
             ),
             (
                 "https://www.openstreetmap.org/way/777565028",
@@ -408,6 +805,7 @@ mod tests {
                 DrivingSide::Left,
                 "sdds",
                 "^^vv",
+                "----",
             ),
             (
                 "https://www.openstreetmap.org/way/224637155",
@@ -415,6 +813,77 @@ mod tests {
                 DrivingSide::Left,
                 "sdd",
                 "^^^",
+                "---",
+            ),
+            (
+                "made up: turn lanes on a one-way",
+                vec![
+                    "lanes=3",
+                    "oneway=yes",
+                    "sidewalk=both",
+                    "turn:lanes=left|none|through;slight_right",
+                    "abst:endpt_fwd=true",
+                ],
+                DrivingSide::Right,
+                "sddds",
+                "v^^^^",
+                "-l-R-",
+            ),
+            (
+                "made up: the number of turn lanes doesn't match",
+                vec![
+                    "lanes=3",
+                    "oneway=yes",
+                    "sidewalk=both",
+                    "turn:lanes=left|through",
+                    "abst:endpt_fwd=true",
+                ],
+                DrivingSide::Right,
+                "sddds",
+                "v^^^^",
+                "-----",
+            ),
+            (
+                "made up: turn lanes in the middle of a way",
+                vec![
+                    "lanes=3",
+                    "oneway=yes",
+                    "sidewalk=both",
+                    "turn:lanes=left|through|right",
+                ],
+                DrivingSide::Right,
+                "sddds",
+                "v^^^^",
+                "-----",
+            ),
+            (
+                "made up: turn lanes in both directions",
+                vec![
+                    "lanes=4",
+                    "sidewalk=both",
+                    "turn:lanes:forward=left|through",
+                    "turn:lanes:backward=through|right;bogus",
+                    "abst:endpt_fwd=true",
+                    "abst:endpt_back=true",
+                ],
+                DrivingSide::Right,
+                "sdddds",
+                "vvv^^^",
+                "-rsls-",
+            ),
+            (
+                "made up: turn lanes when driving on the left",
+                vec![
+                    "lanes=2",
+                    "oneway=yes",
+                    "sidewalk=left",
+                    "turn:lanes=left;through|reverse;right",
+                    "abst:endpt_fwd=true",
+                ],
+                DrivingSide::Left,
+                "sdd",
+                "^^^",
+                "-Lx",
             ),
         ] {
             let actual = get_lane_specs_ltr(&tags(input.clone()), driving_side);
@@ -428,7 +897,15 @@ mod tests {
                 .map(|s| if s.dir == Direction::Fwd { "^" } else { "v" })
                 .collect::<Vec<_>>()
                 .join("");
-            if actual_lt != expected_lt || actual_dir != expected_dir {
+            let actual_turns = actual
+                .iter()
+                .map(|s| turns_to_char(&s.turn_restrictions))
+                .collect::<Vec<_>>()
+                .join("");
+            if actual_lt != expected_lt
+                || actual_dir != expected_dir
+                || actual_turns != expected_turns
+            {
                 ok = false;
                 println!("For input (example from {}):", url);
                 for kv in input {
@@ -437,12 +914,83 @@ mod tests {
                 println!("Got:");
                 println!("    {}", actual_lt);
                 println!("    {}", actual_dir);
+                println!("    {}", actual_turns);
                 println!("Expected:");
                 println!("    {}", expected_lt);
                 println!("    {}", expected_dir);
+                println!("    {}", expected_turns);
                 println!("");
             }
         }
         assert!(ok);
     }
+
+    #[test]
+    fn test_unknown_turn_lanes() {
+        assert_eq!(
+            unknown_turn_lanes(&tags(vec![
+                "turn:lanes:forward=left|none|through;bogus",
+                "turn:lanes:backward=merge_to_left|sideways",
+            ])),
+            vec!["bogus".to_string(), "sideways".to_string()]
+        );
+        assert!(unknown_turn_lanes(&tags(vec!["turn:lanes=reverse|left\\left|"])).is_empty());
+    }
+
+    #[test]
+    fn test_parse_conditional() {
+        let describe = |raw: &str| {
+            parse_conditional(raw)
+                .into_iter()
+                .map(|(value, windows)| {
+                    format!(
+                        "{} {}",
+                        value,
+                        windows
+                            .into_iter()
+                            .map(|w| w.to_string())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            describe("no @ (Mo-Fr 07:00-09:00,16:00-18:00)"),
+            vec!["no 07:00-09:00,16:00-18:00"]
+        );
+        assert_eq!(
+            describe("no_stopping @ (Mo-Fr 07:00-09:00; Sa 10:00-12:00); yes @ wet"),
+            vec!["no_stopping 07:00-09:00"]
+        );
+        assert_eq!(describe("no @ (Sa-Su)"), Vec::<String>::new());
+        assert_eq!(
+            describe("no|designated @ (Mo-Fr)"),
+            vec!["no|designated 00:00-24:00"]
+        );
+    }
+
+    #[test]
+    fn test_parking_rules() {
+        let describe = |kv: Vec<&str>| {
+            parking_rules(&tags(kv), "parking:right:").map(|rules| rules.describe())
+        };
+        assert_eq!(
+            describe(vec!["parking:right:charge=2.50 USD/hour"]),
+            Some("$2.50/hour".to_string())
+        );
+        assert_eq!(
+            describe(vec![
+                "parking:right:charge=1 EUR/h",
+                "parking:right:maxstay=2 hours",
+                "parking:right:fee:conditional=yes @ (Mo-Sa 08:00-20:00)"
+            ]),
+            Some("$1.00/hour, 2h0m limit, 08:00-20:00".to_string())
+        );
+        assert_eq!(
+            describe(vec!["parking:right:maxstay=90 minutes"]),
+            Some("free, 1h30m limit".to_string())
+        );
+        assert_eq!(describe(vec!["parking:right:charge=5 USD/day"]), None);
+    }
 }
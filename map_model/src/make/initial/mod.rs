@@ -91,6 +91,9 @@ impl InitialMap {
 
             m.roads
                 .insert(*id, Road::new(*id, r, raw.config.driving_side));
+            for value in lane_specs::unknown_turn_lanes(&r.osm_tags) {
+                timer.warn(format!("{} has unknown turn restriction {}", id, value));
+            }
         }
 
         timer.start_iter("find each intersection polygon", m.intersections.len());
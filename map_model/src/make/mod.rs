@@ -1,4 +1,5 @@
 mod bridges;
+mod building_attributes;
 mod buildings;
 pub mod initial;
 mod parking_lots;
@@ -8,17 +9,20 @@ mod transit;
 pub mod turns;
 mod walking_turns;
 
-use crate::pathfind::Pathfinder;
-use crate::raw::{OriginalRoad, RawMap};
+use crate::pathfind::{GraphCache, Pathfinder};
+use crate::raw::{CrossingType, OriginalRoad, RawMap};
 use crate::{
     connectivity, osm, AccessRestrictions, Area, AreaID, ControlStopSign, ControlTrafficSignal,
     Direction, Intersection, IntersectionID, IntersectionType, Lane, LaneID, Map, MapEdits,
     Movement, PathConstraints, Position, Road, RoadID, Zone,
 };
 use abstutil::{Parallelism, Timer};
-use geom::{Bounds, Distance, FindClosest, HashablePt2D, Speed, EPSILON_DIST};
+use geom::{Bounds, Distance, FindClosest, HashablePt2D, PolyLine, Pt2D, Speed, EPSILON_DIST};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
+// Crossings mapped this close to the end of a trimmed road are at the intersection, not mid-block.
+const CROSSING_AT_INTERSECTION_DIST: Distance = Distance::const_meters(10.0);
+
 impl Map {
     pub fn create_from_raw(mut raw: RawMap, build_ch: bool, timer: &mut Timer) -> Map {
         // Better to defer this and see RawMaps with more debug info in map_editor
@@ -49,6 +53,7 @@ impl Map {
             config: raw.config.clone(),
             pathfinder: Pathfinder::Dijkstra,
             pathfinder_dirty: false,
+            dijkstra_graphs: GraphCache::new(),
             city_name: raw.city_name.clone(),
             name: raw.name.clone(),
             edits: MapEdits::new(),
@@ -101,13 +106,16 @@ impl Map {
                     .complicated_turn_restrictions
                     .iter()
                     .filter_map(|(via, to)| {
-                        if let (Some(via), Some(to)) =
-                            (road_id_mapping.get(via), road_id_mapping.get(to))
-                        {
-                            Some((*via, *to))
+                        let via_ids: Option<Vec<RoadID>> = via
+                            .iter()
+                            .map(|v| road_id_mapping.get(v).cloned())
+                            .collect();
+                        if let (Some(via_ids), Some(to)) = (via_ids, road_id_mapping.get(to)) {
+                            Some((via_ids, *to))
                         } else {
                             timer.warn(format!(
-                                "Complicated turn restriction from {} has invalid via {} or dst {}",
+                                "Complicated turn restriction from {} has invalid via {:?} or dst \
+                                 {}",
                                 r.id, via, to
                             ));
                             None
@@ -119,6 +127,21 @@ impl Map {
                 center_pts: r.trimmed_center_pts.clone(),
                 src_i: i1,
                 dst_i: i2,
+                elevation_profile: elevation_profile(
+                    &r.trimmed_center_pts,
+                    &raw.roads[&r.id].elevation_samples,
+                    map.intersections[i1.0].elevation,
+                    map.intersections[i2.0].elevation,
+                ),
+                crossings: crossings_along(
+                    &r.trimmed_center_pts,
+                    (
+                        raw.intersections[&r.src_i].point,
+                        raw.intersections[&r.dst_i].point,
+                    ),
+                    &raw.roads[&r.id].crossings,
+                ),
+                orig_crossings: Vec::new(),
                 speed_limit: Speed::ZERO,
                 zorder: if let Some(layer) = raw.roads[&r.id].osm_tags.get("layer") {
                     layer.parse::<isize>().unwrap()
@@ -129,6 +152,7 @@ impl Map {
             };
             road.speed_limit = road.speed_limit_from_osm();
             road.access_restrictions = road.access_restrictions_from_osm();
+            road.orig_crossings = road.crossings.clone();
 
             let mut total_back_width = Distance::ZERO;
             for lane in &r.lane_specs_ltr {
@@ -182,6 +206,9 @@ impl Map {
                     bus_stops: BTreeSet::new(),
                     driving_blackhole: false,
                     biking_blackhole: false,
+                    turn_restrictions: lane.turn_restrictions.clone(),
+                    conditional: lane.conditional.clone(),
+                    parking_rules: lane.parking_rules.clone(),
                 });
             }
             if road.get_name(None) == "???" {
@@ -286,6 +313,7 @@ impl Map {
         }
 
         bridges::find_bridges(&mut map.roads, &map.bounds, timer);
+        bridges::flatten_elevation(&mut map.roads);
 
         let mut stop_signs: BTreeMap<IntersectionID, ControlStopSign> = BTreeMap::new();
         let mut traffic_signals: BTreeMap<IntersectionID, ControlTrafficSignal> = BTreeMap::new();
@@ -416,3 +444,87 @@ fn match_points_to_lanes<F: Fn(&Lane) -> bool>(
         .flatten()
         .collect()
 }
+
+// Projects elevation samples onto a road's center line. Samples trimmed off the ends are dropped,
+// since the intersections' elevation is used there.
+fn elevation_profile(
+    center: &PolyLine,
+    samples: &[(Pt2D, Distance)],
+    start: Distance,
+    end: Distance,
+) -> Vec<(Distance, Distance)> {
+    let len = center.length();
+    let mut profile = Vec::new();
+    for (pt, elevation) in samples {
+        if let Some((away, dist)) = project_onto(center, *pt) {
+            if away < Distance::meters(20.0) && dist > Distance::ZERO && dist < len {
+                profile.push((dist, *elevation));
+            }
+        }
+    }
+    profile.sort_by_key(|(dist, _)| *dist);
+    profile.insert(0, (Distance::ZERO, start));
+    profile.push((len, end));
+    profile
+}
+
+// Projects crossings onto a road's center line. Crossings near either end, including ones trimmed
+// off, are placed exactly at that end, meaning they cross at the intersection. Anything else too
+// far from the road is dropped. `intersections` are the untrimmed ends of the road.
+fn crossings_along(
+    center: &PolyLine,
+    intersections: (Pt2D, Pt2D),
+    crossings: &[(Pt2D, CrossingType)],
+) -> Vec<(Distance, CrossingType)> {
+    let len = center.length();
+    let mut result: Vec<(Distance, CrossingType)> = Vec::new();
+    for (pt, crossing_type) in crossings {
+        let dist = match project_onto(center, *pt) {
+            Some((away, dist))
+                if away < Distance::meters(20.0)
+                    && dist > CROSSING_AT_INTERSECTION_DIST
+                    && dist < len - CROSSING_AT_INTERSECTION_DIST =>
+            {
+                dist
+            }
+            _ => {
+                let (end_dist, end, intersection) =
+                    if pt.dist_to(center.first_pt()) < pt.dist_to(center.last_pt()) {
+                        (Distance::ZERO, center.first_pt(), intersections.0)
+                    } else {
+                        (len, center.last_pt(), intersections.1)
+                    };
+                // The part of the road trimmed back for the intersection, plus a bit of slack
+                if pt.dist_to(end) > end.dist_to(intersection) + CROSSING_AT_INTERSECTION_DIST {
+                    continue;
+                }
+                end_dist
+            }
+        };
+        // The same intersection could have a node and a way mapped further apart than convert_osm
+        // noticed
+        if result.iter().any(|(d, _)| *d == dist) {
+            continue;
+        }
+        result.push((dist, *crossing_type));
+    }
+    result.sort_by_key(|(dist, _)| *dist);
+    result
+}
+
+// Returns (distance from the center line, distance along it) for the closest point on the line.
+pub(crate) fn project_onto(center: &PolyLine, pt: Pt2D) -> Option<(Distance, Distance)> {
+    let mut best: Option<(Distance, Distance)> = None;
+    let mut dist_so_far = Distance::ZERO;
+    for l in center.lines() {
+        let proj = l.project_pt(pt);
+        if let Some(dist) = l.dist_along_of_point(proj) {
+            let away = proj.dist_to(pt);
+            if best.map(|(d, _)| away < d).unwrap_or(true) {
+                best = Some((away, dist_so_far + dist));
+            }
+        }
+        dist_so_far += l.length();
+    }
+    best
+}
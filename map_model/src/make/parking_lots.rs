@@ -1,3 +1,5 @@
+use crate::make::buildings::charging_points;
+use crate::make::initial::lane_specs::parking_rules;
 use crate::make::match_points_to_lanes;
 use crate::raw::RawParkingLot;
 use crate::{
@@ -36,6 +38,8 @@ pub fn make_all_parking_lots(
     );
 
     let mut results = Vec::new();
+    // From the capacity tag, per result
+    let mut capacities: Vec<Option<usize>> = Vec::new();
     timer.start_iter("create parking lot driveways", center_per_lot.len());
     for (lot_center, orig) in center_per_lot.into_iter().zip(input.iter()) {
         timer.next();
@@ -75,6 +79,11 @@ pub fn make_all_parking_lots(
             }
             if let Some((driveway_line, driving_pos)) = driveway {
                 let id = ParkingLotID(results.len());
+                capacities.push(
+                    orig.osm_tags
+                        .get("capacity")
+                        .and_then(|x| x.parse::<usize>().ok()),
+                );
                 results.push(ParkingLot {
                     id,
                     polygon: orig.polygon.clone(),
@@ -82,6 +91,8 @@ pub fn make_all_parking_lots(
                     osm_id: orig.osm_id,
                     spots: Vec::new(),
                     extra_spots: 0,
+                    parking_rules: parking_rules(&orig.osm_tags, ""),
+                    charging_points: charging_points(&orig.osm_tags),
 
                     driveway_line,
                     driving_pos,
@@ -147,12 +158,16 @@ pub fn make_all_parking_lots(
     }
 
     timer.start_iter("generate parking lot spots", results.len());
-    for lot in results.iter_mut() {
+    for (lot, capacity) in results.iter_mut().zip(capacities) {
         timer.next();
         lot.spots = infer_spots(&lot.polygon, &lot.aisles);
 
         // Guess how many extra spots are available, that maybe aren't renderable.
-        if lot.spots.is_empty() {
+        if let Some(n) = capacity {
+            // Trust the tag, maybe from a GIS import, over the guesses.
+            lot.spots.truncate(n);
+            lot.extra_spots = n - lot.spots.len();
+        } else if lot.spots.is_empty() {
             // No parking aisles. Just guess based on the area. One spot per 30m^2 is a quick guess
             // from looking at examples with aisles.
             lot.extra_spots = (lot.polygon.area() / 30.0) as usize;
@@ -103,6 +103,7 @@ fn greedy_assignment(map: &Map, i: IntersectionID) -> ControlTrafficSignal {
 
     expand_all_stages(&mut ts);
 
+    ts.lengthen_stages_for_crosswalks();
     // This must succeed
     ts.validate().unwrap()
 }
@@ -121,6 +122,7 @@ fn degenerate(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         &mut ts,
         vec![vec![(vec![r1, r2], TurnType::Straight, PROTECTED)]],
     );
+    ts.lengthen_stages_for_crosswalks();
     ts.validate().ok()
 }
 
@@ -143,6 +145,7 @@ fn half_signal(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
     ped_stage.phase_type = PhaseType::Fixed(Duration::seconds(10.0));
 
     ts.stages = vec![vehicle_stage, ped_stage];
+    ts.lengthen_stages_for_crosswalks();
     ts.validate().ok()
 }
 
@@ -182,6 +185,7 @@ fn three_way(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
         ],
     );
 
+    ts.lengthen_stages_for_crosswalks();
     ts.validate().ok()
 }
 
@@ -216,6 +220,7 @@ fn four_way_four_stage(map: &Map, i: IntersectionID) -> Option<ControlTrafficSig
             vec![(vec![east, west], TurnType::Left, PROTECTED)],
         ],
     );
+    ts.lengthen_stages_for_crosswalks();
     ts.validate().ok()
 }
 
@@ -249,6 +254,7 @@ fn four_way_two_stage(map: &Map, i: IntersectionID) -> Option<ControlTrafficSign
             ],
         ],
     );
+    ts.lengthen_stages_for_crosswalks();
     ts.validate().ok()
 }
 
@@ -293,6 +299,7 @@ fn four_oneways(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal> {
             ],
         ],
     );
+    ts.lengthen_stages_for_crosswalks();
     ts.validate().ok()
 }
 
@@ -314,6 +321,7 @@ fn all_walk_all_yield(map: &Map, i: IntersectionID) -> ControlTrafficSignal {
     }
 
     ts.stages = vec![all_walk, all_yield];
+    ts.lengthen_stages_for_crosswalks();
     // This must succeed
     ts.validate().unwrap()
 }
@@ -344,6 +352,7 @@ fn stage_per_road(map: &Map, i: IntersectionID) -> Option<ControlTrafficSignal>
             ts.stages.push(stage);
         }
     }
+    ts.lengthen_stages_for_crosswalks();
     ts.validate().ok()
 }
 
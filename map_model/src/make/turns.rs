@@ -126,11 +126,8 @@ fn ensure_unique(turns: Vec<Turn>) -> Vec<Turn> {
 }
 
 fn is_turn_allowed(turn: &Turn, map: &Map) -> bool {
-    if let Some(mut types) = map
-        .get_l(turn.id.src)
-        .get_turn_restrictions(map.get_parent(turn.id.src))
-    {
-        types.any(|turn_type| turn_type == turn.turn_type)
+    if let Some(ref types) = map.get_l(turn.id.src).turn_restrictions {
+        types.contains(&turn.turn_type)
     } else {
         true
     }
@@ -1,3 +1,4 @@
+use crate::raw::CrossingType;
 use crate::{
     Direction, DrivingSide, Intersection, IntersectionID, Lane, LaneID, LaneType, Map, Road, Turn,
     TurnID, TurnType,
@@ -82,7 +83,9 @@ pub fn make_walking_turns(map: &Map, i: &Intersection, timer: &mut Timer) -> Vec
         if let Some(l1) = get_sidewalk(lanes, roads[idx1].incoming_lanes(i.id)) {
             // Make the crosswalk to the other side
             if let Some(l2) = get_sidewalk(lanes, roads[idx1].outgoing_lanes(i.id)) {
-                result.extend(make_crosswalks(i.id, l1, l2).into_iter().flatten());
+                if can_cross(i.id, roads[idx1]) {
+                    result.extend(make_crosswalks(i.id, l1, l2).into_iter().flatten());
+                }
             }
 
             // Find the shared corner
@@ -105,6 +108,8 @@ pub fn make_walking_turns(map: &Map, i: &Intersection, timer: &mut Timer) -> Vec
                         geom: geom.reversed(),
                     });
                 }
+            } else if !can_cross(i.id, wraparound_get(&roads, (idx1 as isize) + idx_offset)) {
+                // Don't cross the adjacent road at all
             } else if let Some(l2) = get_sidewalk(
                 lanes,
                 wraparound_get(&roads, (idx1 as isize) + idx_offset).incoming_lanes(i.id),
@@ -252,6 +257,12 @@ fn _new_make_walking_turns(
     result
 }
 
+// OSM often only has some of the crossings at an intersection mapped, so only skip the ones
+// explicitly tagged as not allowed.
+fn can_cross(i: IntersectionID, r: &Road) -> bool {
+    r.crossing_at(i) != Some(CrossingType::Forbidden)
+}
+
 fn make_crosswalks(i: IntersectionID, l1: &Lane, l2: &Lane) -> Option<Vec<Turn>> {
     let l1_pt = l1.endpoint(i);
     let l2_pt = l2.endpoint(i);
@@ -446,3 +457,63 @@ fn get_sidewalk<'a>(lanes: &'a Vec<Lane>, children: Vec<(LaneID, LaneType)>) ->
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::{OriginalRoad, RawMap};
+    use crate::{connectivity, osm, PathConstraints, RoadID};
+    use abstutil::Timer;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_mapped_crossings() {
+        // The middle of a 3x3 grid is a 4-way intersection at (300, 300)
+        let mut raw = RawMap::synthetic_grid(3, 3);
+        let west = OriginalRoad::new(6, (4, 5));
+        let east = OriginalRoad::new(8, (5, 6));
+        let north = OriginalRoad::new(4, (2, 5));
+        let south = OriginalRoad::new(9, (5, 8));
+        raw.roads
+            .get_mut(&west)
+            .unwrap()
+            .crossings
+            .push((Pt2D::new(295.0, 300.0), CrossingType::Marked));
+        raw.roads
+            .get_mut(&east)
+            .unwrap()
+            .crossings
+            .push((Pt2D::new(305.0, 300.0), CrossingType::Forbidden));
+        // Way off to the side of the road, and not near either end
+        raw.roads
+            .get_mut(&south)
+            .unwrap()
+            .crossings
+            .push((Pt2D::new(360.0, 350.0), CrossingType::Marked));
+        let map = Map::create_from_raw(raw, false, &mut Timer::throwaway());
+
+        let i = map.find_i_by_osm_id(osm::NodeID(5)).unwrap();
+        let road = |id: OriginalRoad| map.get_r(map.find_r_by_osm_id(id).unwrap());
+        assert_eq!(road(west).crossing_at(i), Some(CrossingType::Marked));
+        assert_eq!(road(east).crossing_at(i), Some(CrossingType::Forbidden));
+        assert!(road(south).crossings.is_empty());
+
+        // Mapping a crossing over one road doesn't stop people from crossing the unmapped ones,
+        // but crossing=no does.
+        let crossed: HashSet<RoadID> = map
+            .get_i(i)
+            .turns
+            .iter()
+            .filter(|t| map.get_t(**t).turn_type == TurnType::Crosswalk)
+            .map(|t| map.get_l(t.src).parent)
+            .collect();
+        let expected: HashSet<RoadID> = vec![road(west).id, road(north).id, road(south).id]
+            .into_iter()
+            .collect();
+        assert_eq!(crossed, expected);
+
+        // The sidewalks are all still connected, going around the other way
+        let (_, disconnected) = connectivity::find_scc(&map, PathConstraints::Pedestrian);
+        assert!(disconnected.is_empty());
+    }
+}
@@ -1,9 +1,10 @@
 use crate::raw::{OriginalRoad, RawMap};
 use crate::{
     osm, Area, AreaID, Building, BuildingID, BuildingType, BusRoute, BusRouteID, BusStop,
-    BusStopID, ControlStopSign, ControlTrafficSignal, Intersection, IntersectionID, Lane, LaneID,
-    LaneType, Map, MapEdits, MovementID, OffstreetParking, ParkingLot, ParkingLotID, Path,
-    PathConstraints, PathRequest, Pathfinder, Position, Road, RoadID, Turn, TurnID, TurnType, Zone,
+    BusStopID, ControlStopSign, ControlTrafficSignal, GraphCache, Intersection, IntersectionID,
+    Lane, LaneID, LaneType, Map, MapEdits, MidBlockCrossing, MovementID, OffstreetParking,
+    ParkingLot, ParkingLotID, Path, PathConstraints, PathRequest, PathStep, Pathfinder, Position,
+    Road, RoadID, Turn, TurnID, TurnType, Zone,
 };
 use abstutil::Timer;
 use geom::{Bounds, GPSBounds, Polygon, Pt2D, Ring, Time};
@@ -153,6 +154,7 @@ impl Map {
             },
             pathfinder: Pathfinder::Dijkstra,
             pathfinder_dirty: false,
+            dijkstra_graphs: GraphCache::new(),
             city_name: "blank city".to_string(),
             name: "blank".to_string(),
             edits: MapEdits::new(),
@@ -534,13 +536,63 @@ impl Map {
         assert!(!self.pathfinder_dirty);
         self.pathfinder.pathfind(req, self)
     }
-    pub fn pathfind_avoiding_zones(
+    pub fn pathfind_avoiding_lanes(
         &self,
         req: PathRequest,
         avoid: BTreeSet<LaneID>,
     ) -> Option<Path> {
         assert!(!self.pathfinder_dirty);
-        self.pathfinder.pathfind_avoiding_zones(req, avoid, self)
+        self.pathfinder.pathfind_avoiding_lanes(req, avoid, self)
+    }
+    pub fn pathfind_avoiding_turns(
+        &self,
+        req: PathRequest,
+        avoid: BTreeSet<TurnID>,
+    ) -> Option<Path> {
+        assert!(!self.pathfinder_dirty);
+        self.pathfinder.pathfind_avoiding_turns(req, avoid, self)
+    }
+    // Pedestrians can also cross roads partway along, at mid-block crossings or, when jaywalking,
+    // anywhere on a quiet street. `path` is the usual result for the request. Returns None if
+    // crossing doesn't shorten the walk.
+    pub fn pathfind_mid_block(
+        &self,
+        req: PathRequest,
+        path: &Path,
+        jaywalking: bool,
+    ) -> Option<(Path, Vec<(MidBlockCrossing, Path)>)> {
+        assert!(!self.pathfinder_dirty);
+        crate::pathfind::pathfind_mid_block(req, path, jaywalking, self)
+    }
+    // Also respects lanes that are restricted at this time of day. The usual pathfinding ignores
+    // these, so only when the normal path crosses one, fall back to something slower. Only the
+    // restrictions active at `time` are considered; if one starts or ends while the agent is on
+    // the way, the path won't know.
+    pub fn pathfind_at(&self, req: PathRequest, time: Time) -> Option<Path> {
+        let path = self.pathfind(req.clone())?;
+        let constraints = req.constraints;
+        if constraints == PathConstraints::Pedestrian
+            || path.get_steps().iter().all(|step| match step {
+                PathStep::Lane(l) => constraints.can_use_at(self.get_l(*l), self, time),
+                _ => true,
+            })
+        {
+            return Some(path);
+        }
+        // The start can't be avoided. Avoided lanes can still be entered, so the end is fine.
+        // Most requests around the same time avoid the same lanes, so the slower pathfinder
+        // reuses its graph for them.
+        let avoid = self
+            .all_lanes()
+            .iter()
+            .filter(|l| {
+                l.id != req.start.lane()
+                    && constraints.can_use(l, self)
+                    && !constraints.can_use_at(l, self, time)
+            })
+            .map(|l| l.id)
+            .collect();
+        self.pathfind_avoiding_lanes(req, avoid)
     }
 
     pub fn should_use_transit(
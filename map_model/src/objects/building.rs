@@ -37,6 +37,8 @@ pub struct Building {
     pub amenities: BTreeSet<(NamePerLanguage, String)>,
     pub bldg_type: BuildingType,
     pub parking: OffstreetParking,
+    // Public electric vehicle chargers
+    pub charging_points: usize,
 
     // The building's connection for pedestrians is immutable. For cars and bikes, it can change
     // based on map edits, so don't cache it.
@@ -58,16 +60,32 @@ pub enum OffstreetParking {
 pub enum BuildingType {
     // An estimated number of residents
     Residential(usize),
-    ResidentialCommercial(usize),
-    Commercial,
+    // Residents, jobs
+    ResidentialCommercial(usize, usize),
+    // An estimated number of jobs
+    Commercial(usize),
     Empty,
 }
 
 impl BuildingType {
     pub fn has_residents(&self) -> bool {
         match self {
-            BuildingType::Residential(_) | BuildingType::ResidentialCommercial(_) => true,
-            BuildingType::Commercial | BuildingType::Empty => false,
+            BuildingType::Residential(_) | BuildingType::ResidentialCommercial(_, _) => true,
+            BuildingType::Commercial(_) | BuildingType::Empty => false,
+        }
+    }
+
+    pub fn num_residents(&self) -> usize {
+        match self {
+            BuildingType::Residential(n) | BuildingType::ResidentialCommercial(n, _) => *n,
+            BuildingType::Commercial(_) | BuildingType::Empty => 0,
+        }
+    }
+
+    pub fn num_jobs(&self) -> usize {
+        match self {
+            BuildingType::Commercial(n) | BuildingType::ResidentialCommercial(_, n) => *n,
+            BuildingType::Residential(_) | BuildingType::Empty => 0,
         }
     }
 }
@@ -1,10 +1,8 @@
 use crate::pathfind;
-use crate::{
-    osm, BusStopID, DirectedRoadID, Direction, IntersectionID, Map, PathConstraints, Road, RoadID,
-    TurnType,
-};
+use crate::{BusStopID, DirectedRoadID, IntersectionID, Map, PathConstraints, RoadID, TurnType};
 use abstutil::{deserialize_usize, serialize_usize};
-use geom::{Distance, Line, PolyLine, Pt2D};
+use enumset::EnumSet;
+use geom::{Distance, Duration, Line, PolyLine, Pt2D, Time};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 use std::fmt;
@@ -119,6 +117,15 @@ pub struct Lane {
     // graph, because this is near a border.
     pub driving_blackhole: bool,
     pub biking_blackhole: bool,
+
+    // From turn:lanes. If present, vehicles may only make these types of turns from this lane at
+    // the end of the road.
+    pub turn_restrictions: Option<BTreeSet<TurnType>>,
+    // From the *:conditional tags. Peak-hour bus lanes, parking that's only allowed off-peak, and
+    // so on.
+    pub conditional: Option<ConditionalRestriction>,
+    // Prices and time limits for parking lanes
+    pub parking_rules: Option<ParkingRules>,
 }
 
 impl Lane {
@@ -226,53 +233,35 @@ impl Lane {
         }
     }
 
-    pub fn get_turn_restrictions<'a>(
-        &'a self,
-        road: &'a Road,
-    ) -> Option<impl Iterator<Item = TurnType> + 'a> {
-        if !self.is_driving() {
-            return None;
+    // Like Road::percent_grade, but in the direction of this lane.
+    pub fn percent_grade(&self, map: &Map) -> f64 {
+        let road = map.get_r(self.parent);
+        let grade = road.percent_grade(map);
+        if self.src_i == road.src_i {
+            grade
+        } else {
+            -grade
         }
+    }
 
-        let (dir, offset) = road.dir_and_offset(self.id);
-        let all = if dir == Direction::Fwd && road.osm_tags.contains_key(osm::ENDPT_FWD) {
-            road.osm_tags
-                .get("turn:lanes:forward")
-                .or_else(|| road.osm_tags.get("turn:lanes"))?
-        } else if dir == Direction::Back && road.osm_tags.contains_key(osm::ENDPT_BACK) {
-            road.osm_tags.get("turn:lanes:backward")?
-        } else {
-            return None;
-        };
-        let parts: Vec<&str> = all.split('|').collect();
-        // TODO Verify the number of lanes matches up
-        let part = parts.get(offset)?;
-        // TODO Probably the target lane should get marked as LaneType::Bus
-        if part == &"no" || part == &"none" || part == &"yes" || part == &"psv" || part == &"bus" {
-            return None;
+    // The grade going from dist1 to dist2 along this lane, following the road's elevation profile.
+    // dist1 may be past dist2, for somebody going against the lane's direction.
+    pub fn percent_grade_between(&self, map: &Map, dist1: Distance, dist2: Distance) -> f64 {
+        if dist1 == dist2 {
+            return self.percent_grade(map);
         }
-        Some(part.split(';').flat_map(|s| match s {
-            "left" | "left\\left" => vec![TurnType::Left],
-            "right" => vec![TurnType::Right],
-            // TODO What is blank supposed to mean? From few observed cases, same as through
-            "through" | "" => vec![TurnType::Straight],
-            // TODO Check this more carefully
-            "slight_right" | "slight right" | "merge_to_right" | "sharp_right" => {
-                vec![TurnType::Straight, TurnType::Right]
-            }
-            "slight_left" | "slight left" | "merge_to_left" | "sharp_left" => {
-                vec![TurnType::Straight, TurnType::Left]
-            }
-            "reverse" => {
-                // TODO We need TurnType::UTurn. Until then, u-turns usually show up as
-                // left turns.
-                vec![TurnType::Left]
-            }
-            s => {
-                println!("Unknown turn restriction {}", s);
-                vec![]
+        let road = map.get_r(self.parent);
+        // Lanes are offset from the road's center, so scale distances to the center line.
+        let to_center = |dist: Distance| {
+            let pct = (dist / self.length()).max(0.0).min(1.0);
+            if self.src_i == road.src_i {
+                road.center_pts.length() * pct
+            } else {
+                road.center_pts.length() * (1.0 - pct)
             }
-        }))
+        };
+        let rise = road.elevation_at(to_center(dist2)) - road.elevation_at(to_center(dist1));
+        (rise / (dist2 - dist1).abs()).max(-1.0).min(1.0)
     }
 
     pub fn get_max_cost(&self, constraints: PathConstraints, map: &Map) -> usize {
@@ -287,3 +276,303 @@ impl Lane {
             })
     }
 }
+
+// A restriction on a lane that only applies during some times of the day.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ConditionalRestriction {
+    // Who may still use the lane while the restriction is active. Parking lanes only allow
+    // parking then if this contains PathConstraints::Car.
+    pub allow: EnumSet<PathConstraints>,
+    pub windows: Vec<TimeWindow>,
+}
+
+impl ConditionalRestriction {
+    pub fn is_active(&self, time: Time) -> bool {
+        self.windows.iter().any(|w| w.contains(time))
+    }
+
+    pub fn describe_windows(&self) -> String {
+        self.windows
+            .iter()
+            .map(|w| w.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+// Staying past the time limit gets a ticket for this much
+pub const OVERSTAY_FINE_CENTS: usize = 4400;
+
+// What parking somewhere costs, and how long cars may stay. Outside of the windows, parking is free
+// and unlimited.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ParkingRules {
+    pub cents_per_hour: usize,
+    pub max_stay: Option<Duration>,
+    // If empty, the rules apply all day.
+    pub windows: Vec<TimeWindow>,
+}
+
+impl ParkingRules {
+    pub fn applies(&self, time: Time) -> bool {
+        self.windows.is_empty() || self.windows.iter().any(|w| w.contains(time))
+    }
+
+    // How much of a stay falls in the windows
+    pub fn charged_duration(&self, start: Time, end: Time) -> Duration {
+        if end <= start {
+            return Duration::ZERO;
+        }
+        if self.windows.is_empty() {
+            return end - start;
+        }
+
+        // Lay the windows out on every day the stay touches, in seconds since midnight of the
+        // first day, then add up how much of the stay they cover.
+        let day = Duration::hours(24).inner_seconds();
+        let (start, end) = (start.inner_seconds(), end.inner_seconds());
+        let first_day = (start / day).floor();
+        let mut intervals = Vec::new();
+        let mut day_start = first_day * day;
+        while day_start < end {
+            for w in &self.windows {
+                let (w1, w2) = (w.start.inner_seconds(), w.end.inner_seconds());
+                if w1 <= w2 {
+                    intervals.push((day_start + w1, day_start + w2));
+                } else {
+                    intervals.push((day_start, day_start + w2));
+                    intervals.push((day_start + w1, day_start + day));
+                }
+            }
+            day_start += day;
+        }
+        // Windows could overlap, so merge them before adding up
+        intervals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let mut total = 0.0;
+        let mut covered_until = start;
+        for (i1, i2) in intervals {
+            let i1 = i1.max(covered_until);
+            let i2 = i2.min(end);
+            if i2 > i1 {
+                total += i2 - i1;
+                covered_until = i2;
+            }
+        }
+        Duration::seconds(total)
+    }
+
+    // Includes the fine for overstaying the time limit
+    pub fn cost_cents(&self, start: Time, end: Time) -> usize {
+        let hours = self.charged_duration(start, end) / Duration::hours(1);
+        let mut cents = ((self.cents_per_hour as f64) * hours).round() as usize;
+        if self.overstayed(start, end) {
+            cents += OVERSTAY_FINE_CENTS;
+        }
+        cents
+    }
+
+    pub fn overstayed(&self, start: Time, end: Time) -> bool {
+        match self.max_stay {
+            Some(max) => self.charged_duration(start, end) > max,
+            None => false,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.cents_per_hour > 0 {
+            parts.push(format!("{}/hour", describe_cents(self.cents_per_hour)));
+        } else {
+            parts.push("free".to_string());
+        }
+        if let Some(max) = self.max_stay {
+            parts.push(format!("{} limit", max));
+        }
+        if !self.windows.is_empty() {
+            parts.push(
+                self.windows
+                    .iter()
+                    .map(|w| w.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+        parts.join(", ")
+    }
+}
+
+// Like "$2.50"
+pub fn describe_cents(cents: usize) -> String {
+    format!("${}.{:02}", cents / 100, cents % 100)
+}
+
+// The simulation doesn't distinguish days of the week, so this repeats every day.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct TimeWindow {
+    // Both since midnight. If end is before start, the window wraps around midnight.
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl TimeWindow {
+    pub fn contains(&self, time: Time) -> bool {
+        let t = (time - Time::START_OF_DAY) % Duration::hours(24);
+        if self.start <= self.end {
+            t >= self.start && t < self.end
+        } else {
+            t >= self.start || t < self.end
+        }
+    }
+
+    // Like "07:00-09:00"
+    pub fn parse(raw: &str) -> Option<TimeWindow> {
+        let parts: Vec<&str> = raw.trim().split('-').collect();
+        if parts.len() != 2 {
+            return None;
+        }
+        Some(TimeWindow {
+            start: parse_hh_mm(parts[0])?,
+            end: parse_hh_mm(parts[1])?,
+        })
+    }
+
+    // Comma-separated. Blank input means no windows.
+    pub fn parse_list(raw: &str) -> Option<Vec<TimeWindow>> {
+        raw.split(',')
+            .filter(|x| !x.trim().is_empty())
+            .map(TimeWindow::parse)
+            .collect()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", hh_mm(self.start), hh_mm(self.end))
+    }
+}
+
+fn parse_hh_mm(raw: &str) -> Option<Duration> {
+    let parts: Vec<&str> = raw.trim().split(':').collect();
+    if parts.len() != 2 {
+        return None;
+    }
+    let hours = parts[0].parse::<usize>().ok()?;
+    let mins = parts[1].parse::<usize>().ok()?;
+    if mins >= 60 || hours > 24 || (hours == 24 && mins > 0) {
+        return None;
+    }
+    Some(Duration::hours(hours) + Duration::minutes(mins))
+}
+
+fn hh_mm(d: Duration) -> String {
+    let mins = (d.inner_seconds() / 60.0).round() as usize;
+    format!("{:02}:{:02}", mins / 60, mins % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hours: usize, mins: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(hours) + Duration::minutes(mins)
+    }
+
+    fn rules(cents_per_hour: usize, max_stay: Option<Duration>, windows: &str) -> ParkingRules {
+        ParkingRules {
+            cents_per_hour,
+            max_stay,
+            windows: TimeWindow::parse_list(windows).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_time_window() {
+        let peak = TimeWindow::parse("07:00-09:00").unwrap();
+        assert!(!peak.contains(at(6, 59)));
+        assert!(peak.contains(at(7, 0)));
+        assert!(peak.contains(at(8, 59)));
+        // The end is exclusive
+        assert!(!peak.contains(at(9, 0)));
+        // Every day
+        assert!(peak.contains(at(24 + 8, 0)));
+
+        let overnight = TimeWindow::parse("22:00-02:00").unwrap();
+        assert!(overnight.contains(at(23, 0)));
+        assert!(overnight.contains(at(0, 0)));
+        assert!(overnight.contains(at(1, 59)));
+        assert!(!overnight.contains(at(2, 0)));
+        assert!(!overnight.contains(at(12, 0)));
+        assert!(!overnight.contains(at(21, 59)));
+        assert!(overnight.contains(at(24 + 1, 0)));
+    }
+
+    #[test]
+    fn test_charged_duration() {
+        let all_day = rules(100, None, "");
+        assert_eq!(
+            all_day.charged_duration(at(8, 0), at(10, 30)),
+            Duration::minutes(150)
+        );
+        assert_eq!(
+            all_day.charged_duration(at(10, 0), at(8, 0)),
+            Duration::ZERO
+        );
+
+        let daytime = rules(100, None, "09:00-17:00");
+        assert_eq!(
+            daytime.charged_duration(at(8, 0), at(10, 30)),
+            Duration::minutes(90)
+        );
+        assert_eq!(
+            daytime.charged_duration(at(17, 0), at(20, 0)),
+            Duration::ZERO
+        );
+        // Overnight, into the next day
+        assert_eq!(
+            daytime.charged_duration(at(16, 0), at(24 + 10, 0)),
+            Duration::hours(2)
+        );
+        // More than a whole day
+        assert_eq!(
+            daytime.charged_duration(at(12, 0), at(48 + 12, 0)),
+            Duration::hours(16)
+        );
+
+        // Windows can wrap around midnight
+        let nighttime = rules(100, None, "22:00-02:00");
+        assert_eq!(
+            nighttime.charged_duration(at(21, 0), at(24 + 3, 0)),
+            Duration::hours(4)
+        );
+        assert_eq!(
+            nighttime.charged_duration(at(1, 0), at(23, 0)),
+            Duration::hours(2)
+        );
+
+        // Overlapping windows aren't counted twice
+        let overlapping = rules(100, None, "08:00-12:00, 10:00-14:00");
+        assert_eq!(
+            overlapping.charged_duration(at(0, 0), at(24, 0)),
+            Duration::hours(6)
+        );
+    }
+
+    #[test]
+    fn test_cost_cents() {
+        let metered = rules(200, Some(Duration::hours(2)), "09:00-17:00");
+        assert_eq!(metered.cost_cents(at(9, 0), at(10, 30)), 300);
+        // Only the time in the window is charged, and counts against the limit
+        assert_eq!(metered.cost_cents(at(16, 0), at(19, 0)), 200);
+        assert!(!metered.overstayed(at(16, 0), at(19, 0)));
+        // Staying too long costs a ticket
+        assert!(metered.overstayed(at(9, 0), at(12, 0)));
+        assert_eq!(
+            metered.cost_cents(at(9, 0), at(12, 0)),
+            600 + OVERSTAY_FINE_CENTS
+        );
+
+        let free = rules(0, None, "");
+        assert_eq!(free.cost_cents(at(0, 0), at(48, 0)), 0);
+        assert!(!free.overstayed(at(0, 0), at(48, 0)));
+    }
+}
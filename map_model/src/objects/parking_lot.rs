@@ -1,4 +1,4 @@
-use crate::{osm, Position};
+use crate::{osm, ParkingRules, Position};
 use abstutil::{deserialize_usize, serialize_usize};
 use geom::{Angle, Line, PolyLine, Polygon, Pt2D};
 use serde::{Deserialize, Serialize};
@@ -39,6 +39,10 @@ pub struct ParkingLot {
     // If we can't render all spots (maybe a lot with no aisles or a multi-story garage), still
     // count the other spots.
     pub extra_spots: usize,
+    // From the charge, maxstay, and fee:conditional tags
+    pub parking_rules: Option<ParkingRules>,
+    // Spots with an electric vehicle charger
+    pub charging_points: usize,
 
     // Goes from the lot to the driving lane
     pub driveway_line: PolyLine,
@@ -1,4 +1,4 @@
-use crate::raw::{OriginalRoad, RestrictionType};
+use crate::raw::{CrossingType, OriginalRoad, RestrictionType};
 use crate::{
     osm, AccessRestrictions, BusStopID, IntersectionID, Lane, LaneID, LaneType, Map,
     PathConstraints, Zone,
@@ -93,8 +93,8 @@ pub struct Road {
     pub osm_tags: Tags,
     // self is 'from'
     pub turn_restrictions: Vec<(RestrictionType, RoadID)>,
-    // self is 'from'. (via, to). Only BanTurns.
-    pub complicated_turn_restrictions: Vec<(RoadID, RoadID)>,
+    // self is 'from'. (via, to), where via is a chain of one or more roads. Only BanTurns.
+    pub complicated_turn_restrictions: Vec<(Vec<RoadID>, RoadID)>,
     pub orig_id: OriginalRoad,
     pub speed_limit: Speed,
     pub access_restrictions: AccessRestrictions,
@@ -109,6 +109,15 @@ pub struct Road {
     pub center_pts: PolyLine,
     pub src_i: IntersectionID,
     pub dst_i: IntersectionID,
+    // (distance along center_pts, elevation), sorted by distance. Starts and ends at the
+    // elevation of src_i and dst_i.
+    pub elevation_profile: Vec<(Distance, Distance)>,
+    // Crossings mapped in OSM or added by edits, as (distance along center_pts, type), sorted by
+    // distance. Crossings at either intersection are at exactly 0 or the length of center_pts; the
+    // rest are mid-block.
+    pub crossings: Vec<(Distance, CrossingType)>,
+    // Just the crossings from OSM, before any edits
+    pub orig_crossings: Vec<(Distance, CrossingType)>,
 }
 
 impl Road {
@@ -300,6 +309,73 @@ impl Road {
         stops
     }
 
+    // The crossing mapped over this road at one of its intersections, if any
+    pub fn crossing_at(&self, i: IntersectionID) -> Option<CrossingType> {
+        let dist = if i == self.src_i {
+            Distance::ZERO
+        } else if i == self.dst_i {
+            self.center_pts.length()
+        } else {
+            return None;
+        };
+        self.crossings
+            .iter()
+            .find(|(d, _)| *d == dist)
+            .map(|(_, ct)| *ct)
+    }
+
+    pub fn mid_block_crossings(&self) -> Vec<(Distance, CrossingType)> {
+        let len = self.center_pts.length();
+        self.crossings
+            .iter()
+            .filter(|(d, ct)| *d != Distance::ZERO && *d != len && *ct != CrossingType::Forbidden)
+            .cloned()
+            .collect()
+    }
+
+    // Quiet streets, where people on foot cross wherever they like once there's a gap in traffic
+    pub fn can_jaywalk(&self) -> bool {
+        self.get_rank() == osm::RoadRank::Local
+            && self.speed_limit <= Speed::miles_per_hour(25.0)
+            && self
+                .lanes_ltr
+                .iter()
+                .filter(|(_, _, lt)| lt.is_for_moving_vehicles())
+                .count()
+                <= 2
+    }
+
+    pub fn elevation_at(&self, dist: Distance) -> Distance {
+        for pair in self.elevation_profile.windows(2) {
+            let ((dist1, e1), (dist2, e2)) = (pair[0], pair[1]);
+            if dist <= dist2 {
+                if dist2 == dist1 {
+                    return e2;
+                }
+                let pct = ((dist - dist1) / (dist2 - dist1)).max(0.0);
+                return e1 + (e2 - e1) * pct;
+            }
+        }
+        self.elevation_profile.last().unwrap().1
+    }
+
+    // Splits the road into pieces following the elevation profile. Returns (start distance, end
+    // distance, grade) for each, with grade in [-1.0, 1.0] like percent_grade.
+    pub fn grades(&self) -> Vec<(Distance, Distance, f64)> {
+        let mut result = Vec::new();
+        for pair in self.elevation_profile.windows(2) {
+            let ((dist1, e1), (dist2, e2)) = (pair[0], pair[1]);
+            if dist2 > dist1 {
+                result.push((
+                    dist1,
+                    dist2,
+                    ((e2 - e1) / (dist2 - dist1)).max(-1.0).min(1.0),
+                ));
+            }
+        }
+        result
+    }
+
     // Returns [-1.0, 1.0]. 0 is flat, positive is uphill, negative is downhill.
     // TODO Or do we care about the total up/down along the possibly long road?
     pub fn percent_grade(&self, map: &Map) -> f64 {
@@ -337,7 +413,21 @@ impl Road {
     }
 
     pub fn is_private(&self) -> bool {
-        self.access_restrictions != AccessRestrictions::new()
+        // Banning trucks from passing through doesn't make a road private. Pathfinding just steers
+        // trucks away from these roads.
+        let mut restrictions = self.access_restrictions.clone();
+        restrictions
+            .allow_through_traffic
+            .insert(PathConstraints::Truck);
+        restrictions != AccessRestrictions::new()
+    }
+
+    // Trucks can still make deliveries along the road, just not use it as a shortcut
+    pub fn bans_through_trucks(&self) -> bool {
+        !self
+            .access_restrictions
+            .allow_through_traffic
+            .contains(PathConstraints::Truck)
     }
 
     pub(crate) fn access_restrictions_from_osm(&self) -> AccessRestrictions {
@@ -349,6 +439,13 @@ impl Road {
                 allow |= PathConstraints::Bus;
             }
             allow
+        } else if self
+            .osm_tags
+            .is_any("hgv", vec!["no", "destination", "delivery"])
+        {
+            let mut allow = EnumSet::all();
+            allow.remove(PathConstraints::Truck);
+            allow
         } else {
             EnumSet::all()
         };
@@ -441,3 +538,102 @@ impl Road {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::RawMap;
+    use abstutil::Timer;
+    use geom::Pt2D;
+
+    fn close(d1: Distance, d2: Distance) -> bool {
+        (d1 - d2).abs() < Distance::meters(0.01)
+    }
+
+    #[test]
+    fn test_elevation_profile() {
+        // The top road of a 2x2 grid runs east from (100, 100) to (300, 100). It climbs from 10m to
+        // a plateau of 50m, then drops back down to 30m.
+        let mut raw = RawMap::synthetic_grid(2, 2);
+        let id = OriginalRoad::new(1, (1, 2));
+        raw.intersections
+            .get_mut(&osm::NodeID(1))
+            .unwrap()
+            .elevation = Distance::meters(10.0);
+        raw.intersections
+            .get_mut(&osm::NodeID(2))
+            .unwrap()
+            .elevation = Distance::meters(30.0);
+        raw.roads.get_mut(&id).unwrap().elevation_samples = vec![
+            (Pt2D::new(150.0, 100.0), Distance::meters(20.0)),
+            // Out of order, and one is way off to the side
+            (Pt2D::new(250.0, 100.0), Distance::meters(50.0)),
+            (Pt2D::new(200.0, 100.0), Distance::meters(50.0)),
+            (Pt2D::new(200.0, 200.0), Distance::meters(1000.0)),
+        ];
+        let map = Map::create_from_raw(raw, false, &mut Timer::throwaway());
+        let road = map.get_r(map.find_r_by_osm_id(id).unwrap());
+        let len = road.center_pts.length();
+
+        let profile = &road.elevation_profile;
+        assert_eq!(
+            profile.iter().map(|(_, e)| *e).collect::<Vec<_>>(),
+            vec![10.0, 20.0, 50.0, 50.0, 30.0]
+                .into_iter()
+                .map(Distance::meters)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(profile[0].0, Distance::ZERO);
+        assert_eq!(profile[4].0, len);
+        // The samples are 50m apart, wherever the road was trimmed
+        let (d1, d2, d3) = (profile[1].0, profile[2].0, profile[3].0);
+        assert!(close(d2 - d1, Distance::meters(50.0)));
+        assert!(close(d3 - d2, Distance::meters(50.0)));
+
+        // Interpolate between samples
+        assert!(close(
+            road.elevation_at(Distance::ZERO),
+            Distance::meters(10.0)
+        ));
+        assert!(close(road.elevation_at(d1), Distance::meters(20.0)));
+        assert!(close(
+            road.elevation_at(d1 + Distance::meters(10.0)),
+            Distance::meters(26.0)
+        ));
+        assert!(close(
+            road.elevation_at(d2 + Distance::meters(25.0)),
+            Distance::meters(50.0)
+        ));
+        assert!(close(road.elevation_at(len), Distance::meters(30.0)));
+        // Past the end, just use the last sample
+        assert!(close(
+            road.elevation_at(len + Distance::meters(5.0)),
+            Distance::meters(30.0)
+        ));
+
+        let grades = road.grades();
+        assert_eq!(grades.len(), 4);
+        assert_eq!((grades[0].0, grades[0].1), (Distance::ZERO, d1));
+        assert!((grades[0].2 - 10.0 / d1.inner_meters()).abs() < 0.001);
+        assert_eq!((grades[1].0, grades[1].1), (d1, d2));
+        assert!((grades[1].2 - 0.6).abs() < 0.001);
+        assert!(grades[2].2.abs() < 0.001);
+        assert_eq!(grades[3].1, len);
+        assert!((grades[3].2 + 20.0 / (len - d3).inner_meters()).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_grades_clamped() {
+        // A sample in the middle of the bottom road is absurdly high
+        let mut raw = RawMap::synthetic_grid(2, 2);
+        let id = OriginalRoad::new(4, (3, 4));
+        raw.roads.get_mut(&id).unwrap().elevation_samples =
+            vec![(Pt2D::new(200.0, 300.0), Distance::meters(1000.0))];
+        let map = Map::create_from_raw(raw, false, &mut Timer::throwaway());
+        let road = map.get_r(map.find_r_by_osm_id(id).unwrap());
+        let grades = road.grades();
+        assert_eq!(grades.len(), 2);
+        assert_eq!(grades[0].2, 1.0);
+        assert_eq!(grades[1].2, -1.0);
+    }
+}
@@ -5,11 +5,17 @@ use crate::{
     MovementID, TurnID, TurnPriority, TurnType,
 };
 use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap, Timer};
-use geom::Duration;
+use geom::{Duration, Speed};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryFrom;
 
+// Pedestrian clearance (the flashing don't walk) is timed for somebody walking this fast, per
+// MUTCD 4E.06. Slower people starting at the end of the walk interval won't make it.
+const PED_DESIGN_SPEED: Speed = Speed::const_meters_per_second(1.07);
+// After the walk indication starts, how long it shows before clearance begins
+const MIN_WALK_TIME: Duration = Duration::const_seconds(7.0);
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ControlTrafficSignal {
     pub id: IntersectionID,
@@ -117,6 +123,164 @@ impl ControlTrafficSignal {
         Ok(self)
     }
 
+    // Is every crosswalk green long enough to start walking, then finish crossing? Generated
+    // signals always are, but hand-mapped ones and older edits might not be, so only the editor
+    // insists on this.
+    pub fn check_crosswalk_timing(&self) -> Result<(), String> {
+        for (idx, m) in self.crosswalk_starts() {
+            let green = self.green_time(idx, m);
+            let needed = MIN_WALK_TIME + self.ped_clearance_time(m);
+            if green < needed {
+                return Err(format!(
+                    "Stage {} only gives pedestrians {} to cross, but they need at least {}",
+                    idx + 1,
+                    green,
+                    needed.round_up(Duration::seconds(1.0))
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // How long it takes to cross at PED_DESIGN_SPEED. Nobody should start crossing with less than
+    // this much green left.
+    pub fn ped_clearance_time(&self, m: MovementID) -> Duration {
+        self.movements[&m].geom.length() / PED_DESIGN_SPEED
+    }
+
+    // The total time a movement stays protected, starting with this stage and continuing through
+    // any following stages that also protect it.
+    pub fn green_time(&self, idx: usize, m: MovementID) -> Duration {
+        let mut total = Duration::ZERO;
+        for i in 0..self.stages.len() {
+            let stage = &self.stages[(idx + i) % self.stages.len()];
+            if !stage.protected_movements.contains(&m) {
+                break;
+            }
+            total += stage.phase_type.simple_duration();
+        }
+        total
+    }
+
+    // During this stage, with this much time left, how much longer does the walk indication show
+    // for a crosswalk? Zero during the flashing don't walk or if the crosswalk isn't green at all.
+    pub fn walk_time_left(&self, idx: usize, m: MovementID, time_left: Duration) -> Duration {
+        if !self.stages[idx].protected_movements.contains(&m) {
+            return Duration::ZERO;
+        }
+        let green_left = time_left + self.green_time((idx + 1) % self.stages.len(), m);
+        // Never turns red, so there's nothing to clear
+        if self
+            .stages
+            .iter()
+            .all(|s| s.protected_movements.contains(&m))
+        {
+            return green_left;
+        }
+
+        let mut start = idx;
+        while abstutil::wraparound_get(&self.stages, (start as isize) - 1)
+            .protected_movements
+            .contains(&m)
+        {
+            start = (start + self.stages.len() - 1) % self.stages.len();
+        }
+        let green = self.green_time(start, m);
+        // If the green is too short to fit the clearance time, the walk indication still shows
+        // for MIN_WALK_TIME, so people aren't stuck forever.
+        let walk = (green - self.ped_clearance_time(m)).max(MIN_WALK_TIME.min(green));
+        let elapsed = green - green_left;
+        if walk > elapsed {
+            walk - elapsed
+        } else {
+            Duration::ZERO
+        }
+    }
+
+    // Every stage where a crosswalk turns green, since it wasn't green in the previous stage
+    fn crosswalk_starts(&self) -> Vec<(usize, MovementID)> {
+        let mut results = Vec::new();
+        for (idx, stage) in self.stages.iter().enumerate() {
+            let prev = abstutil::wraparound_get(&self.stages, (idx as isize) - 1);
+            for m in &stage.protected_movements {
+                if !m.crosswalk {
+                    continue;
+                }
+                if !prev.protected_movements.contains(m) {
+                    results.push((idx, *m));
+                } else if idx == 0
+                    && self
+                        .stages
+                        .iter()
+                        .all(|s| s.protected_movements.contains(m))
+                {
+                    // Always green, so just count it once
+                    results.push((idx, *m));
+                }
+            }
+        }
+        results
+    }
+
+    // Stretches stages until every crosswalk is green for at least MIN_WALK_TIME plus its
+    // clearance time. The extra time goes to the last stage before the crosswalk turns red.
+    // Returns true if anything changed.
+    pub fn lengthen_stages_for_crosswalks(&mut self) -> bool {
+        let mut changed = false;
+        for (idx, m) in self.crosswalk_starts() {
+            let green = self.green_time(idx, m);
+            let needed = MIN_WALK_TIME + self.ped_clearance_time(m);
+            if green >= needed {
+                continue;
+            }
+            // Signal timing is stored in whole seconds.
+            let extra = (needed - green).round_up(Duration::seconds(1.0));
+            let mut last = idx;
+            while last + 1 - idx < self.stages.len()
+                && self.stages[(last + 1) % self.stages.len()]
+                    .protected_movements
+                    .contains(&m)
+            {
+                last += 1;
+            }
+            let stage = &mut self.stages[last % self.stages.len()];
+            stage.phase_type = match stage.phase_type {
+                PhaseType::Fixed(d) => PhaseType::Fixed(d + extra),
+                PhaseType::Adaptive(d) => PhaseType::Adaptive(d + extra),
+            };
+            changed = true;
+        }
+        changed
+    }
+
+    // Inserts a stage before this one, letting pedestrians start crossing a few seconds before
+    // vehicles get a green. Turning drivers then see people already in the crosswalk. Returns
+    // false if the stage has no crosswalks turning green or is only crosswalks.
+    pub fn add_leading_ped_interval(&mut self, idx: usize, duration: Duration) -> bool {
+        let crosswalks: Vec<MovementID> = self
+            .crosswalk_starts()
+            .into_iter()
+            .filter(|(i, _)| *i == idx)
+            .map(|(_, m)| m)
+            .collect();
+        let stage = &self.stages[idx];
+        if crosswalks.is_empty()
+            || stage
+                .protected_movements
+                .iter()
+                .chain(stage.yield_movements.iter())
+                .all(|m| m.crosswalk)
+        {
+            return false;
+        }
+
+        let mut lpi = Stage::new();
+        lpi.protected_movements.extend(crosswalks);
+        lpi.phase_type = PhaseType::Fixed(duration);
+        self.stages.insert(idx, lpi);
+        true
+    }
+
     // Returns true if this did anything
     pub fn convert_to_ped_scramble(&mut self) -> bool {
         let orig = self.clone();
@@ -388,3 +552,164 @@ fn find_r(id: seattle_traffic_signals::DirectedRoad, map: &Map) -> Option<Direct
         },
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::RawMap;
+    use crate::IntersectionType;
+
+    // The middle of a 3x3 grid is a 4-way intersection
+    fn setup() -> Map {
+        let mut raw = RawMap::synthetic_grid(3, 3);
+        raw.intersections
+            .get_mut(&osm::NodeID(5))
+            .unwrap()
+            .intersection_type = IntersectionType::TrafficSignal;
+        Map::create_from_raw(raw, false, &mut Timer::throwaway())
+    }
+
+    fn stage(protected: Vec<MovementID>, duration: Duration) -> Stage {
+        let mut stage = Stage::new();
+        stage.protected_movements.extend(protected);
+        stage.phase_type = PhaseType::Fixed(duration);
+        stage
+    }
+
+    fn duration(ts: &ControlTrafficSignal, idx: usize) -> Duration {
+        ts.stages[idx].phase_type.simple_duration()
+    }
+
+    // Returns the signal, three crosswalks, and a vehicle movement. The caller fills out stages.
+    fn signal(map: &Map) -> (ControlTrafficSignal, Vec<MovementID>, MovementID) {
+        let i = map.find_i_by_osm_id(osm::NodeID(5)).unwrap();
+        let ts = map.get_traffic_signal(i).clone();
+        let crosswalks: Vec<MovementID> = ts
+            .movements
+            .keys()
+            .filter(|m| m.crosswalk)
+            .cloned()
+            .collect();
+        assert!(crosswalks.len() >= 3);
+        let vehicle = *ts.movements.keys().find(|m| !m.crosswalk).unwrap();
+        (ts, crosswalks[0..3].to_vec(), vehicle)
+    }
+
+    #[test]
+    fn test_green_time_and_walk_time() {
+        let map = setup();
+        let (mut ts, crosswalks, vehicle) = signal(&map);
+        let (c, always, short) = (crosswalks[0], crosswalks[1], crosswalks[2]);
+        let clearance = ts.ped_clearance_time(c);
+        let s = Duration::seconds;
+        // c turns green in the last stage and stays green through the first
+        ts.stages = vec![
+            stage(vec![c, always], clearance),
+            stage(vec![always, short], s(5.0)),
+            stage(vec![c, always, vehicle], s(20.0)),
+        ];
+
+        assert_eq!(ts.green_time(2, c), s(20.0) + clearance);
+        assert_eq!(ts.green_time(0, c), clearance);
+        assert_eq!(ts.green_time(1, c), Duration::ZERO);
+        assert_eq!(ts.green_time(1, short), s(5.0));
+        let cycle = clearance + s(25.0);
+        for idx in 0..3 {
+            assert_eq!(ts.green_time(idx, always), cycle);
+        }
+
+        // The always green crosswalk only counts once
+        let mut starts = ts.crosswalk_starts();
+        starts.sort();
+        let mut expected = vec![(0, always), (1, short), (2, c)];
+        expected.sort();
+        assert_eq!(starts, expected);
+
+        // Walk for the 20s before the clearance, even after wrapping around
+        assert_eq!(ts.walk_time_left(2, c, s(20.0)), s(20.0));
+        assert_eq!(ts.walk_time_left(2, c, s(5.0)), s(5.0));
+        assert_eq!(ts.walk_time_left(0, c, clearance), Duration::ZERO);
+        assert_eq!(ts.walk_time_left(1, c, s(5.0)), Duration::ZERO);
+        // Never stops
+        assert_eq!(ts.walk_time_left(1, always, s(3.0)), s(3.0) + cycle);
+        // Too short for the clearance, but the walk indication still shows for the whole green
+        assert_eq!(ts.walk_time_left(1, short, s(5.0)), s(5.0));
+        assert_eq!(ts.walk_time_left(1, short, s(1.0)), s(1.0));
+    }
+
+    #[test]
+    fn test_lengthen_stages_for_crosswalks() {
+        let map = setup();
+        let (mut ts, crosswalks, vehicle) = signal(&map);
+        let (c, short) = (crosswalks[0], crosswalks[2]);
+        let s = Duration::seconds;
+        ts.stages = vec![
+            stage(vec![c], s(1.0)),
+            stage(vec![short], s(1.0)),
+            stage(vec![c, vehicle], s(1.0)),
+        ];
+        assert!(ts.check_crosswalk_timing().is_err());
+
+        assert!(ts.lengthen_stages_for_crosswalks());
+        assert!(ts.check_crosswalk_timing().is_ok());
+        // c's green wraps around, so the extra time goes to the first stage
+        assert_eq!(duration(&ts, 2), s(1.0));
+        for (m, idx, last) in vec![(c, 2, 0), (short, 1, 1)] {
+            let needed = MIN_WALK_TIME + ts.ped_clearance_time(m);
+            let green = ts.green_time(idx, m);
+            assert!(green >= needed);
+            assert!(green < needed + s(1.0));
+            // Whole seconds
+            assert_eq!(duration(&ts, last) % s(1.0), Duration::ZERO);
+        }
+
+        // Nothing left to do
+        let before = ts.clone();
+        assert!(!ts.lengthen_stages_for_crosswalks());
+        assert_eq!(ts, before);
+    }
+
+    #[test]
+    fn test_add_leading_ped_interval() {
+        let map = setup();
+        let (mut ts, crosswalks, vehicle) = signal(&map);
+        let (c, short) = (crosswalks[0], crosswalks[2]);
+        let s = Duration::seconds;
+        ts.stages = vec![
+            stage(vec![c, vehicle], s(30.0)),
+            stage(vec![short], s(30.0)),
+            stage(vec![c, vehicle], s(30.0)),
+        ];
+
+        // c continues from the last stage, so it doesn't start here
+        assert!(!ts.add_leading_ped_interval(0, s(3.0)));
+        // Only crosswalks
+        assert!(!ts.add_leading_ped_interval(1, s(3.0)));
+        assert_eq!(ts.stages.len(), 3);
+
+        assert!(ts.add_leading_ped_interval(2, s(3.0)));
+        assert_eq!(ts.stages.len(), 4);
+        assert_eq!(
+            ts.stages[2].protected_movements,
+            vec![c].into_iter().collect::<BTreeSet<_>>()
+        );
+        assert!(ts.stages[2].yield_movements.is_empty());
+        assert_eq!(duration(&ts, 2), s(3.0));
+        assert!(ts.stages[3].protected_movements.contains(&vehicle));
+        assert_eq!(ts.green_time(2, c), s(63.0));
+    }
+
+    #[test]
+    fn test_import_keeps_timing() {
+        let map = setup();
+        let (mut ts, _, _) = signal(&map);
+        // Too short for any crosswalk, like an old edit might be
+        for stage in &mut ts.stages {
+            stage.phase_type = PhaseType::Fixed(Duration::seconds(5.0));
+        }
+        assert!(ts.check_crosswalk_timing().is_err());
+
+        let imported = ControlTrafficSignal::import(ts.export(&map), ts.id, &map).unwrap();
+        assert_eq!(imported, ts);
+    }
+}
@@ -15,6 +15,7 @@ pub struct ContractionHierarchyPathfinder {
     bike_graph: VehiclePathfinder,
     bus_graph: VehiclePathfinder,
     train_graph: VehiclePathfinder,
+    truck_graph: VehiclePathfinder,
     walking_graph: SidewalkPathfinder,
     walking_with_transit_graph: SidewalkPathfinder,
 }
@@ -39,6 +40,10 @@ impl ContractionHierarchyPathfinder {
         let train_graph = VehiclePathfinder::new(map, PathConstraints::Train, None);
         timer.stop("prepare pathfinding for trains");
 
+        timer.start("prepare pathfinding for trucks");
+        let truck_graph = VehiclePathfinder::new(map, PathConstraints::Truck, Some(&car_graph));
+        timer.stop("prepare pathfinding for trucks");
+
         timer.start("prepare pathfinding for pedestrians");
         let walking_graph = SidewalkPathfinder::new(map, false, &bus_graph, &train_graph);
         timer.stop("prepare pathfinding for pedestrians");
@@ -53,6 +58,7 @@ impl ContractionHierarchyPathfinder {
             bike_graph,
             bus_graph,
             train_graph,
+            truck_graph,
             walking_graph,
             walking_with_transit_graph,
         }
@@ -83,7 +89,15 @@ impl ContractionHierarchyPathfinder {
                         }
                         return z1.pathfind(req, map);
                     }
-                } else {
+                } else if !z1
+                    .restrictions
+                    .allow_through_traffic
+                    .contains(req.constraints)
+                    || !z2
+                        .restrictions
+                        .allow_through_traffic
+                        .contains(req.constraints)
+                {
                     // TODO Handle paths going between two different zones
                     return None;
                 }
@@ -139,6 +153,7 @@ impl ContractionHierarchyPathfinder {
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Truck => self.truck_graph.pathfind(&req, map).map(|(p, _)| p),
         }
     }
 
@@ -221,6 +236,7 @@ impl ContractionHierarchyPathfinder {
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Truck => self.truck_graph.pathfind(&req, map).map(|(p, _)| p),
         }?;
         interior_path.append(main_path, map);
         Some(interior_path)
@@ -306,6 +322,7 @@ impl ContractionHierarchyPathfinder {
             PathConstraints::Bike => self.bike_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Bus => self.bus_graph.pathfind(&req, map).map(|(p, _)| p),
             PathConstraints::Train => self.train_graph.pathfind(&req, map).map(|(p, _)| p),
+            PathConstraints::Truck => self.truck_graph.pathfind(&req, map).map(|(p, _)| p),
         }?;
         main_path.append(interior_path, map);
         main_path.end_dist = orig_end_dist;
@@ -335,6 +352,10 @@ impl ContractionHierarchyPathfinder {
         self.bus_graph.apply_edits(map);
         timer.stop("apply edits to bus pathfinding");
 
+        timer.start("apply edits to truck pathfinding");
+        self.truck_graph.apply_edits(map);
+        timer.stop("apply edits to truck pathfinding");
+
         // Can't edit anything related to trains
 
         timer.start("apply edits to pedestrian pathfinding");
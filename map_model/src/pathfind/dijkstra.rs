@@ -4,10 +4,65 @@ use crate::pathfind::walking::{
 };
 use crate::{LaneID, Map, Path, PathConstraints, PathRequest, PathStep, TurnID};
 use petgraph::graphmap::DiGraphMap;
-use std::collections::BTreeSet;
+use std::cell::RefCell;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet};
+use thread_local::ThreadLocal;
 
-// TODO These should maybe keep the DiGraphMaps as state. It's cheap to recalculate it for edits.
+// Each graph covers the whole map, so don't hang onto too many.
+const MAX_CACHED_GRAPHS: usize = 8;
 
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum Avoid {
+    Lanes(BTreeSet<LaneID>),
+    Turns(BTreeSet<TurnID>),
+}
+
+// Callers avoiding lanes or turns usually ask about the same set many times in a row -- the lanes
+// closed during some time window, or the turns blocked by an incident -- so the graphs are reused
+// until the map is edited.
+pub struct GraphCache {
+    graphs: ThreadLocal<RefCell<BTreeMap<(PathConstraints, Avoid), DiGraphMap<LaneID, TurnID>>>>,
+}
+
+impl GraphCache {
+    pub fn new() -> GraphCache {
+        GraphCache {
+            graphs: ThreadLocal::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.graphs.clear();
+    }
+
+    fn pathfind(&self, req: PathRequest, avoid: Avoid, map: &Map) -> Option<Path> {
+        let mut graphs = self
+            .graphs
+            .get_or(|| RefCell::new(BTreeMap::new()))
+            .borrow_mut();
+        let key = (req.constraints, avoid);
+        if graphs.len() >= MAX_CACHED_GRAPHS && !graphs.contains_key(&key) {
+            graphs.clear();
+        }
+        let graph = match graphs.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let graph = make_graph(entry.key().0, &entry.key().1, map);
+                entry.insert(graph)
+            }
+        };
+        calc_path(graph, req, map)
+    }
+}
+
+impl Default for GraphCache {
+    fn default() -> GraphCache {
+        GraphCache::new()
+    }
+}
+
+// TODO This should maybe keep the DiGraphMaps as state too. It's cheap to recalculate it for edits.
 pub fn pathfind(req: PathRequest, map: &Map) -> Option<Path> {
     if req.constraints == PathConstraints::Pedestrian {
         if req.start.lane() == req.end.lane() {
@@ -27,30 +82,57 @@ pub fn pathfind(req: PathRequest, map: &Map) -> Option<Path> {
         }
     }
 
-    calc_path(graph, req, map)
+    calc_path(&graph, req, map)
 }
 
-pub fn pathfind_avoiding_zones(
+pub fn pathfind_avoiding_lanes(
     req: PathRequest,
     avoid: BTreeSet<LaneID>,
     map: &Map,
 ) -> Option<Path> {
-    assert_eq!(req.constraints, PathConstraints::Car);
+    assert_ne!(req.constraints, PathConstraints::Pedestrian);
+    map.dijkstra_graphs.pathfind(req, Avoid::Lanes(avoid), map)
+}
+
+pub fn pathfind_avoiding_turns(
+    req: PathRequest,
+    avoid: BTreeSet<TurnID>,
+    map: &Map,
+) -> Option<Path> {
+    assert_ne!(req.constraints, PathConstraints::Pedestrian);
+    map.dijkstra_graphs.pathfind(req, Avoid::Turns(avoid), map)
+}
+
+// Avoided lanes can still be entered, just not left, so they only wind up in a path as the end.
+fn make_graph(
+    constraints: PathConstraints,
+    avoid: &Avoid,
+    map: &Map,
+) -> DiGraphMap<LaneID, TurnID> {
     let mut graph: DiGraphMap<LaneID, TurnID> = DiGraphMap::new();
     for l in map.all_lanes() {
-        if req.constraints.can_use(l, map) && !avoid.contains(&l.id) {
-            for turn in map.get_turns_for(l.id, req.constraints) {
+        if let Avoid::Lanes(ref lanes) = avoid {
+            if lanes.contains(&l.id) {
+                continue;
+            }
+        }
+        if constraints.can_use(l, map) {
+            for turn in map.get_turns_for(l.id, constraints) {
+                if let Avoid::Turns(ref turns) = avoid {
+                    if turns.contains(&turn.id) {
+                        continue;
+                    }
+                }
                 graph.add_edge(turn.id.src, turn.id.dst, turn.id);
             }
         }
     }
-
-    calc_path(graph, req, map)
+    graph
 }
 
-fn calc_path(graph: DiGraphMap<LaneID, TurnID>, req: PathRequest, map: &Map) -> Option<Path> {
+fn calc_path(graph: &DiGraphMap<LaneID, TurnID>, req: PathRequest, map: &Map) -> Option<Path> {
     let (_, path) = petgraph::algo::astar(
-        &graph,
+        graph,
         req.start.lane(),
         |l| l == req.end.lane(),
         |(_, _, turn)| driving_cost(map.get_l(turn.src), map.get_t(*turn), req.constraints, map),
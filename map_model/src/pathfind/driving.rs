@@ -7,6 +7,9 @@ use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use thread_local::ThreadLocal;
 
+// How much more expensive it is for trucks to use roads that ban them from passing through
+const TRUCK_BAN_PENALTY: f64 = 10.0;
+
 #[derive(Serialize, Deserialize)]
 pub struct VehiclePathfinder {
     #[serde(serialize_with = "serialize_32", deserialize_with = "deserialize_32")]
@@ -164,13 +167,17 @@ fn make_input_graph(
     for l in map.all_lanes() {
         let from = nodes.get(Node::Lane(l.id));
         let mut any = false;
-        if constraints.can_use(l, map)
-            && map
-                .get_r(l.parent)
-                .access_restrictions
-                .allow_through_traffic
-                .contains(constraints)
-        {
+        let allow = &map
+            .get_r(l.parent)
+            .access_restrictions
+            .allow_through_traffic;
+        // Trucks may enter roads that only ban trucks; driving_cost discourages cutting through.
+        let allowed = if constraints == PathConstraints::Truck {
+            allow.contains(PathConstraints::Car)
+        } else {
+            allow.contains(constraints)
+        };
+        if constraints.can_use(l, map) && allowed {
             let indices = uber_turn_entrances.get(l.id);
             if indices.is_empty() {
                 for turn in map.get_turns_for(l.id, constraints) {
@@ -224,6 +231,18 @@ pub fn driving_cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map:
             let t2 = turn.geom.length() / map.get_parent(turn.id.dst).speed_limit;
             (t1 + t2).inner_seconds().round() as usize
         }
+        PathConstraints::Truck => {
+            // Like Car, but only use roads that ban trucks from passing through when the
+            // alternative is a huge detour, like to make a delivery there.
+            let t1 = lane.length() / map.get_r(lane.parent).speed_limit;
+            let t2 = turn.geom.length() / map.get_parent(turn.id.dst).speed_limit;
+            let ban_penalty = if map.get_r(lane.parent).bans_through_trucks() {
+                TRUCK_BAN_PENALTY
+            } else {
+                1.0
+            };
+            (ban_penalty * (t1 + t2)).inner_seconds().round() as usize
+        }
         PathConstraints::Bike => {
             // Speed limits don't matter, bikes are usually constrained by their own speed limit.
             let dist = lane.length() + turn.geom.length();
@@ -272,3 +291,68 @@ pub fn driving_cost(lane: &Lane, turn: &Turn, constraints: PathConstraints, map:
 
     base + extra_penalty
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::{OriginalRoad, RawMap};
+    use crate::{LaneType, Position};
+    use abstutil::Timer;
+    use geom::Distance;
+
+    #[test]
+    fn test_truck_bans() {
+        let mut raw = RawMap::synthetic_grid(3, 2);
+        // Trucks can't pass through along the bottom row of the grid. One road on the top row is
+        // private.
+        let banned = vec![OriginalRoad::new(1, (1, 2)), OriginalRoad::new(3, (2, 3))];
+        for id in &banned {
+            raw.roads.get_mut(id).unwrap().osm_tags.insert("hgv", "no");
+        }
+        let private = OriginalRoad::new(7, (5, 6));
+        raw.roads
+            .get_mut(&private)
+            .unwrap()
+            .osm_tags
+            .insert("access", "private");
+        let map = Map::create_from_raw(raw, true, &mut Timer::throwaway());
+
+        for id in &banned {
+            let r = map.get_r(map.find_r_by_osm_id(*id).unwrap());
+            assert!(r.bans_through_trucks());
+            // Nobody else is affected, so the road isn't part of a private zone
+            assert!(!r.is_private());
+            assert!(r.get_zone(&map).is_none());
+        }
+        let r = map.get_r(map.find_r_by_osm_id(private).unwrap());
+        assert!(r.is_private());
+        assert!(r.get_zone(&map).is_some());
+
+        let driving_lane = |id: OriginalRoad| {
+            map.get_r(map.find_r_by_osm_id(id).unwrap())
+                .lanes_ltr()
+                .into_iter()
+                .find(|(_, _, lt)| *lt == LaneType::Driving)
+                .unwrap()
+                .0
+        };
+
+        // Trucks pay much more than cars to use the road
+        let l = map.get_l(driving_lane(banned[0]));
+        let turn = map.get_turns_for(l.id, PathConstraints::Truck)[0];
+        assert!(
+            driving_cost(l, turn, PathConstraints::Truck, &map)
+                > 5 * driving_cost(l, turn, PathConstraints::Car, &map)
+        );
+
+        // But they can still reach somewhere along it, like to make a delivery
+        let start = driving_lane(OriginalRoad::new(5, (3, 6)));
+        assert!(map
+            .pathfind(PathRequest {
+                start: Position::new(start, Distance::meters(10.0)),
+                end: Position::new(l.id, l.length() / 2.0),
+                constraints: PathConstraints::Truck,
+            })
+            .is_some());
+    }
+}
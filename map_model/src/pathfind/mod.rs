@@ -7,15 +7,16 @@ pub mod uber_turns;
 mod walking;
 
 pub use self::ch::ContractionHierarchyPathfinder;
+pub(crate) use self::dijkstra::GraphCache;
 pub use self::driving::driving_cost;
-pub use self::walking::{walking_cost, WalkingNode};
+pub use self::walking::{pathfind_mid_block, walking_cost, MidBlockCrossing, WalkingNode};
 use crate::{
-    osm, BusRouteID, BusStopID, Lane, LaneID, LaneType, Map, Position, Traversable, TurnID,
+    osm, BusRouteID, BusStopID, Lane, LaneID, LaneType, Map, Position, RoadID, Traversable, TurnID,
     UberTurn,
 };
 use abstutil::Timer;
 use enumset::EnumSetType;
-use geom::{Distance, PolyLine, EPSILON_DIST};
+use geom::{Distance, PolyLine, Time, EPSILON_DIST};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeSet, VecDeque};
 use std::fmt;
@@ -405,6 +406,32 @@ impl Path {
         &self.steps
     }
 
+    // Keep the current step, but replace everything after it with another path to the same place.
+    // The other path has to start from the current step or the one right after it. Not for walking
+    // paths.
+    pub fn reroute(&mut self, other: Path, map: &Map) {
+        assert!(self.currently_inside_ut.is_none());
+        assert_eq!(self.last_step(), other.last_step());
+        let lanes_crossed = self.lanes_crossed_so_far();
+        let current = self.steps[0];
+
+        self.steps = other.steps;
+        if self.steps[0] != current {
+            self.steps.push_front(current);
+        }
+        // Progress so far stays the same; only the steps remaining on the new route count on top
+        // of it.
+        self.total_length = self.crossed_so_far;
+        self.total_lanes = lanes_crossed;
+        for step in &self.steps {
+            self.total_length += step.as_traversable().length(map);
+            if let PathStep::Lane(_) = step {
+                self.total_lanes += 1;
+            }
+        }
+        self.uber_turns = other.uber_turns;
+    }
+
     // Not for walking paths
     fn append(&mut self, other: Path, map: &Map) {
         assert!(self.currently_inside_ut.is_none());
@@ -435,6 +462,9 @@ pub enum PathConstraints {
     Bike,
     Bus,
     Train,
+    // Heavy goods vehicles. They use the same lanes as cars, but roads can ban them from passing
+    // through.
+    Truck,
 }
 
 impl PathConstraints {
@@ -445,6 +475,7 @@ impl PathConstraints {
             PathConstraints::Bike,
             PathConstraints::Bus,
             PathConstraints::Train,
+            PathConstraints::Truck,
         ]
     }
 
@@ -464,7 +495,7 @@ impl PathConstraints {
     pub fn can_use(self, l: &Lane, map: &Map) -> bool {
         match self {
             PathConstraints::Pedestrian => l.is_walkable(),
-            PathConstraints::Car => l.is_driving(),
+            PathConstraints::Car | PathConstraints::Truck => l.is_driving(),
             PathConstraints::Bike => {
                 if l.is_biking() {
                     true
@@ -483,6 +514,23 @@ impl PathConstraints {
         }
     }
 
+    // Like can_use, but also respects restrictions that only apply at some times of day. The
+    // pathfinding graphs are built without these.
+    pub fn can_use_at(self, l: &Lane, map: &Map, time: Time) -> bool {
+        if !self.can_use(l, map) {
+            return false;
+        }
+        match l.conditional {
+            Some(ref c) if c.is_active(time) => {
+                c.allow.contains(self)
+                    || (self == PathConstraints::Bike
+                        && c.allow.contains(PathConstraints::Bus)
+                        && map.config.bikes_can_use_bus_lanes)
+            }
+            _ => true,
+        }
+    }
+
     // Strict for bikes. If there are bike lanes, not allowed to use other lanes.
     pub(crate) fn filter_lanes(self, mut choices: Vec<LaneID>, map: &Map) -> Vec<LaneID> {
         choices.retain(|l| self.can_use(map.get_l(*l), map));
@@ -565,21 +613,20 @@ fn validate_continuity(map: &Map, steps: &Vec<PathStep>) {
 }
 
 fn validate_restrictions(map: &Map, steps: &Vec<PathStep>) {
-    for triple in steps.windows(5) {
-        if let (PathStep::Lane(l1), PathStep::Lane(l2), PathStep::Lane(l3)) =
-            (triple[0], triple[2], triple[4])
-        {
-            let from = map.get_parent(l1);
-            let via = map.get_l(l2).parent;
-            let to = map.get_l(l3).parent;
-
-            for (dont_via, dont_to) in &from.complicated_turn_restrictions {
-                if via == *dont_via && to == *dont_to {
-                    panic!(
-                        "Some path does illegal uber-turn: {} -> {} -> {}",
-                        l1, l2, l3
-                    );
-                }
+    let roads: Vec<RoadID> = steps
+        .iter()
+        .filter_map(|step| match step {
+            PathStep::Lane(l) | PathStep::ContraflowLane(l) => Some(map.get_l(*l).parent),
+            PathStep::Turn(_) => None,
+        })
+        .collect();
+    for (idx, from) in roads.iter().enumerate() {
+        for (via, to) in &map.get_r(*from).complicated_turn_restrictions {
+            let mut banned = vec![*from];
+            banned.extend(via.clone());
+            banned.push(*to);
+            if roads[idx..].starts_with(&banned) {
+                panic!("Some path does illegal uber-turn: {:?}", banned);
             }
         }
     }
@@ -601,13 +648,21 @@ impl Pathfinder {
             Pathfinder::CH(ref p) => p.pathfind(req, map),
         }
     }
-    pub fn pathfind_avoiding_zones(
+    pub fn pathfind_avoiding_lanes(
         &self,
         req: PathRequest,
         avoid: BTreeSet<LaneID>,
         map: &Map,
     ) -> Option<Path> {
-        dijkstra::pathfind_avoiding_zones(req, avoid, map)
+        dijkstra::pathfind_avoiding_lanes(req, avoid, map)
+    }
+    pub fn pathfind_avoiding_turns(
+        &self,
+        req: PathRequest,
+        avoid: BTreeSet<TurnID>,
+        map: &Map,
+    ) -> Option<Path> {
+        dijkstra::pathfind_avoiding_turns(req, avoid, map)
     }
 
     pub fn should_use_transit(
@@ -630,3 +685,173 @@ impl Pathfinder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::{OriginalRoad, RawMap};
+    use geom::Duration;
+
+    // The driving lane on a road heading away from the first intersection
+    fn driving_lane(map: &Map, id: OriginalRoad) -> LaneID {
+        map.get_r(map.find_r_by_osm_id(id).unwrap())
+            .all_lanes()
+            .into_iter()
+            .find(|l| {
+                let lane = map.get_l(*l);
+                lane.is_driving() && map.get_i(lane.src_i).orig_id == id.i1
+            })
+            .unwrap()
+    }
+
+    // The driving lane on a road heading towards the first intersection
+    fn driving_lane_back(map: &Map, id: OriginalRoad) -> LaneID {
+        map.get_r(map.find_r_by_osm_id(id).unwrap())
+            .all_lanes()
+            .into_iter()
+            .find(|l| {
+                let lane = map.get_l(*l);
+                lane.is_driving() && map.get_i(lane.dst_i).orig_id == id.i1
+            })
+            .unwrap()
+    }
+
+    // Whatever was already crossed, plus all of the steps left
+    fn check_totals(path: &Path, map: &Map) {
+        let mut remaining = Distance::ZERO;
+        let mut lanes = 0;
+        for step in &path.steps {
+            remaining += step.as_traversable().length(map);
+            if let PathStep::Lane(_) = step {
+                lanes += 1;
+            }
+        }
+        assert_eq!(path.total_length(), path.crossed_so_far() + remaining);
+        assert_eq!(path.total_lanes(), path.lanes_crossed_so_far() + lanes);
+    }
+
+    // Replace the path with one avoiding the next turn, from wherever it is now
+    fn detour(path: &mut Path, map: &Map) -> TurnID {
+        let avoid = path
+            .steps
+            .iter()
+            .skip(1)
+            .find_map(|step| match step {
+                PathStep::Turn(t) => Some(*t),
+                _ => None,
+            })
+            .unwrap();
+        let start = match path.current_step() {
+            PathStep::Lane(l) => l,
+            PathStep::Turn(t) => t.dst,
+            PathStep::ContraflowLane(_) => unreachable!(),
+        };
+        let other = map
+            .pathfind_avoiding_turns(
+                PathRequest {
+                    start: Position::start(start),
+                    end: Position::start(path.last_step().as_lane()),
+                    constraints: PathConstraints::Car,
+                },
+                vec![avoid].into_iter().collect(),
+            )
+            .unwrap();
+        path.reroute(other, map);
+        avoid
+    }
+
+    #[test]
+    fn test_reroute() {
+        let map = Map::create_from_raw(RawMap::synthetic_grid(3, 3), true, &mut Timer::throwaway());
+        // From a corner of the grid to a lane leaving the middle, which can be reached many ways
+        let start = driving_lane(&map, OriginalRoad::new(1, (1, 2)));
+        let end = driving_lane(&map, OriginalRoad::new(9, (5, 8)));
+        let mut path = map
+            .pathfind(PathRequest {
+                start: Position::start(start),
+                end: Position::end(end, &map),
+                constraints: PathConstraints::Car,
+            })
+            .unwrap();
+        let last_step = path.last_step();
+        check_totals(&path, &map);
+
+        // Get on the first turn, then detour around the turn after it
+        path.shift(&map);
+        let current = path.current_step();
+        assert!(matches!(current, PathStep::Turn(_)));
+        let avoided = detour(&mut path, &map);
+        assert_eq!(path.current_step(), current);
+        assert_eq!(path.last_step(), last_step);
+        assert!(!path.steps.contains(&PathStep::Turn(avoided)));
+        check_totals(&path, &map);
+
+        // Now from a lane
+        path.shift(&map);
+        let current = path.current_step();
+        assert!(matches!(current, PathStep::Lane(_)));
+        let avoided = detour(&mut path, &map);
+        assert_eq!(path.current_step(), current);
+        assert_eq!(path.last_step(), last_step);
+        assert!(!path.steps.contains(&PathStep::Turn(avoided)));
+        check_totals(&path, &map);
+
+        // Crossing all of it adds up
+        while path.isnt_last_step() {
+            path.shift(&map);
+        }
+        assert_eq!(
+            path.total_length(),
+            path.crossed_so_far() + last_step.as_traversable().length(&map)
+        );
+    }
+
+    #[test]
+    fn test_peak_hour_bus_lane() {
+        let mut raw = RawMap::synthetic_grid(3, 3);
+        let id = OriginalRoad::new(1, (1, 2));
+        raw.roads.get_mut(&id).unwrap().osm_tags.insert(
+            "bus:lanes:forward:conditional",
+            "designated @ (Mo-Fr 07:00-09:00)",
+        );
+        let map = Map::create_from_raw(raw, true, &mut Timer::throwaway());
+        let bus_lane = driving_lane(&map, id);
+        let peak = Time::START_OF_DAY + Duration::hours(8);
+        let off_peak = Time::START_OF_DAY + Duration::hours(10);
+
+        let lane = map.get_l(bus_lane);
+        assert!(lane.conditional.is_some());
+        assert!(!PathConstraints::Car.can_use_at(lane, &map, peak));
+        assert!(PathConstraints::Car.can_use_at(lane, &map, off_peak));
+        assert!(PathConstraints::Bus.can_use_at(lane, &map, peak));
+        assert!(PathConstraints::Bike.can_use_at(lane, &map, peak));
+        // The other direction isn't a bus lane
+        let other = driving_lane_back(&map, id);
+        assert!(PathConstraints::Car.can_use_at(map.get_l(other), &map, peak));
+
+        // Heading towards the corner, then along the bus lane and onwards. Without the bus lane,
+        // the car has to go around the block.
+        let req = PathRequest {
+            start: Position::start(driving_lane_back(&map, OriginalRoad::new(6, (4, 5)))),
+            end: Position::end(driving_lane(&map, OriginalRoad::new(3, (2, 3))), &map),
+            constraints: PathConstraints::Car,
+        };
+        let uses_bus_lane = |path: &Path| path.get_steps().contains(&PathStep::Lane(bus_lane));
+        let usual = map.pathfind_at(req.clone(), off_peak).unwrap();
+        assert!(uses_bus_lane(&usual));
+        let detour = map.pathfind_at(req.clone(), peak).unwrap();
+        assert!(!uses_bus_lane(&detour));
+        assert!(detour.total_length() > usual.total_length());
+        // Buses can take it
+        let bus = map
+            .pathfind_at(
+                PathRequest {
+                    constraints: PathConstraints::Bus,
+                    ..req
+                },
+                peak,
+            )
+            .unwrap();
+        assert!(uses_bus_lane(&bus));
+    }
+}
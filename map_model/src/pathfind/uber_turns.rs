@@ -1,4 +1,4 @@
-use crate::{DirectedRoadID, Direction, IntersectionID, LaneID, Map, TurnID};
+use crate::{DirectedRoadID, Direction, IntersectionID, LaneID, Map, RoadID, TurnID};
 use abstutil::MultiMap;
 use geom::{Angle, Distance, PolyLine, Pt2D};
 use petgraph::graphmap::UnGraphMap;
@@ -37,9 +37,11 @@ impl IntersectionCluster {
         let mut graph: UnGraphMap<IntersectionID, ()> = UnGraphMap::new();
         for from in map.all_roads() {
             for (via, _) in &from.complicated_turn_restrictions {
-                // Each of these tells us 2 intersections to group together
-                let r = map.get_r(*via);
-                graph.add_edge(r.src_i, r.dst_i, ());
+                // Each via road tells us 2 intersections to group together
+                for r in via {
+                    let r = map.get_r(*r);
+                    graph.add_edge(r.src_i, r.dst_i, ());
+                }
             }
         }
         for intersections in petgraph::algo::kosaraju_scc(&graph) {
@@ -108,27 +110,27 @@ impl IntersectionCluster {
             uber_turns.extend(flood(entrance, map, &exits));
         }
 
-        // Filter illegal paths
-        let mut all_restrictions = Vec::new();
+        // Filter illegal paths. Each restriction is the full sequence of roads (from, via..., to).
+        let mut all_restrictions: Vec<Vec<RoadID>> = Vec::new();
         for from in map.all_roads() {
             for (via, to) in &from.complicated_turn_restrictions {
-                all_restrictions.push((from.id, *via, *to));
+                let mut banned = vec![from.id];
+                banned.extend(via.clone());
+                banned.push(*to);
+                all_restrictions.push(banned);
             }
         }
 
         // Filter out the restricted ones!
         let mut illegal = Vec::new();
         uber_turns.retain(|ut| {
-            let mut ok = true;
-            for pair in ut.path.windows(2) {
-                let r1 = map.get_l(pair[0].src).parent;
-                let r2 = map.get_l(pair[0].dst).parent;
-                let r3 = map.get_l(pair[1].dst).parent;
-                if all_restrictions.contains(&(r1, r2, r3)) {
-                    ok = false;
-                    break;
-                }
+            let mut roads = vec![map.get_l(ut.path[0].src).parent];
+            for t in &ut.path {
+                roads.push(map.get_l(t.dst).parent);
             }
+            let ok = !all_restrictions
+                .iter()
+                .any(|banned| roads.windows(banned.len()).any(|w| w == &banned[..]));
             if ok {
                 true
             } else {
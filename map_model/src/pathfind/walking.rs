@@ -1,11 +1,12 @@
 use crate::pathfind::driving::VehiclePathfinder;
 use crate::pathfind::node_map::{deserialize_nodemap, NodeMap};
+use crate::raw::CrossingType;
 use crate::{
-    BusRoute, BusRouteID, BusStopID, IntersectionID, LaneID, Map, Path, PathConstraints,
-    PathRequest, PathStep, Position,
+    BusRoute, BusRouteID, BusStopID, Direction, IntersectionID, LaneID, Map, Path, PathConstraints,
+    PathRequest, PathStep, Position, Road, RoadID,
 };
 use fast_paths::{deserialize_32, serialize_32, FastGraph, InputGraph, PathCalculator};
-use geom::{Distance, Speed};
+use geom::{Distance, Line, Speed};
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
 use std::collections::HashSet;
@@ -474,3 +475,333 @@ pub fn one_step_walking_path(req: &PathRequest, map: &Map) -> Path {
         )
     }
 }
+
+// Crossing a road from one sidewalk to the other partway along, instead of at an intersection
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MidBlockCrossing {
+    pub from: Position,
+    pub to: Position,
+    // None when nothing's marked there at all, and people just cross when traffic allows
+    pub crossing_type: Option<CrossingType>,
+}
+
+impl MidBlockCrossing {
+    pub fn road(&self, map: &Map) -> RoadID {
+        map.get_l(self.from.lane()).parent
+    }
+
+    // from and to are on sidewalks on opposite sides of the road, so they never overlap
+    pub fn geom(&self, map: &Map) -> Line {
+        Line::must_new(self.from.pt(map), self.to.pt(map))
+    }
+
+    pub fn length(&self, map: &Map) -> Distance {
+        self.from.pt(map).dist_to(self.to.pt(map))
+    }
+
+    // The crossings mapped or added partway along a road, from one sidewalk to the other
+    pub fn all_on(road: &Road, map: &Map) -> Vec<MidBlockCrossing> {
+        match road
+            .lanes_ltr()
+            .into_iter()
+            .find(|(l, _, _)| map.get_l(*l).is_walkable())
+        {
+            Some((l, _, _)) => crossings_from(Position::new(l, Distance::ZERO), false, map),
+            None => Vec::new(),
+        }
+    }
+
+    fn reversed(&self) -> MidBlockCrossing {
+        MidBlockCrossing {
+            from: self.to,
+            to: self.from,
+            crossing_type: self.crossing_type,
+        }
+    }
+}
+
+// Every way to get from this spot to the sidewalk on the other side of the same road, without
+// walking to an intersection
+fn crossings_from(pos: Position, jaywalking: bool, map: &Map) -> Vec<MidBlockCrossing> {
+    let road = map.get_parent(pos.lane());
+    let other = match sidewalk_across(road, pos.lane(), map) {
+        Some(l) => l,
+        None => {
+            return Vec::new();
+        }
+    };
+
+    let mut result = Vec::new();
+    for (dist, crossing_type) in road.mid_block_crossings() {
+        result.push(MidBlockCrossing {
+            from: along_sidewalk(road, pos.lane(), dist, map),
+            to: along_sidewalk(road, other, dist, map),
+            crossing_type: Some(crossing_type),
+        });
+    }
+    if jaywalking && road.can_jaywalk() {
+        result.push(MidBlockCrossing {
+            from: pos,
+            to: pos.equiv_pos(other, map),
+            crossing_type: None,
+        });
+    }
+    result.retain(|c| c.from.pt(map) != c.to.pt(map));
+    result
+}
+
+fn sidewalk_across(road: &Road, l: LaneID, map: &Map) -> Option<LaneID> {
+    road.lanes_ltr()
+        .into_iter()
+        .map(|(id, _, _)| id)
+        .find(|id| *id != l && map.get_l(*id).is_walkable())
+}
+
+// Sidewalks run alongside the center line, so go the same fraction of the way along them.
+fn along_sidewalk(road: &Road, l: LaneID, dist: Distance, map: &Map) -> Position {
+    let len = map.get_l(l).length();
+    let pct = dist / road.center_pts.length();
+    if road.dir(l) == Direction::Fwd {
+        Position::new(l, len * pct)
+    } else {
+        Position::new(l, len * (1.0 - pct))
+    }
+}
+
+// Tries crossing the roads at the start and end of a walk partway along, instead of detouring to
+// an intersection. `baseline` is the usual path for the request. Returns None if crossing doesn't
+// make the walk any shorter. Otherwise, returns the path to walk first, then each crossing along
+// with the path to walk after it.
+//
+// Sidewalks run alongside each other, so only the crossing at the start landing closest to the
+// end, and the crossing at the end starting closest to the start, are worth trying. That's at most
+// 3 options and 5 extra pathfinding calls.
+pub fn pathfind_mid_block(
+    req: PathRequest,
+    baseline: &Path,
+    jaywalking: bool,
+    map: &Map,
+) -> Option<(Path, Vec<(MidBlockCrossing, Path)>)> {
+    let start = crossings_from(req.start, jaywalking, map)
+        .into_iter()
+        .min_by_key(|c| c.to.pt(map).dist_to(req.end.pt(map)));
+    let end = crossings_from(req.end, jaywalking, map)
+        .into_iter()
+        .map(|c| c.reversed())
+        .min_by_key(|c| c.from.pt(map).dist_to(req.start.pt(map)));
+
+    let mut options: Vec<Vec<MidBlockCrossing>> = Vec::new();
+    if let Some(ref s) = start {
+        options.push(vec![s.clone()]);
+    }
+    if let Some(ref e) = end {
+        options.push(vec![e.clone()]);
+    }
+    if let (Some(s), Some(e)) = (start, end) {
+        options.push(vec![s, e]);
+    }
+    if options.is_empty() {
+        return None;
+    }
+
+    let mut best_dist = walked_dist(baseline, req.start, req.end, map);
+    let mut best = None;
+    'options: for crossings in options {
+        let mut total = Distance::ZERO;
+        let mut paths = Vec::new();
+        let mut pos = req.start;
+        for end in crossings
+            .iter()
+            .map(|c| c.from)
+            .chain(std::iter::once(req.end))
+        {
+            let path = match map.pathfind(PathRequest {
+                start: pos,
+                end,
+                constraints: PathConstraints::Pedestrian,
+            }) {
+                Some(path) => path,
+                None => {
+                    continue 'options;
+                }
+            };
+            total += walked_dist(&path, pos, end, map);
+            paths.push(path);
+            if let Some(c) = crossings.get(paths.len() - 1) {
+                total += c.length(map);
+                pos = c.to;
+            }
+        }
+        if total < best_dist {
+            best_dist = total;
+            best = Some((crossings, paths));
+        }
+    }
+
+    let (crossings, mut paths) = best?;
+    let first = paths.remove(0);
+    Some((first, crossings.into_iter().zip(paths).collect()))
+}
+
+// Path::total_length counts the first and last lanes entirely
+fn walked_dist(path: &Path, start: Position, end: Position, map: &Map) -> Distance {
+    let steps = path.get_steps();
+    if steps.len() == 1 {
+        return if start.dist_along() < end.dist_along() {
+            end.dist_along() - start.dist_along()
+        } else {
+            start.dist_along() - end.dist_along()
+        };
+    }
+    let mut dist = path.total_length();
+    dist -= match steps[0] {
+        PathStep::ContraflowLane(l) => map.get_l(l).length() - start.dist_along(),
+        _ => start.dist_along(),
+    };
+    dist -= match steps[steps.len() - 1] {
+        PathStep::ContraflowLane(_) => end.dist_along(),
+        l => l.as_traversable().length(map) - end.dist_along(),
+    };
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::{OriginalRoad, RawMap};
+    use abstutil::Timer;
+    use geom::Pt2D;
+
+    // The middle row of a 3x3 grid, with a marked crossing halfway along the west block
+    fn setup() -> Map {
+        let mut raw = RawMap::synthetic_grid(3, 3);
+        raw.roads
+            .get_mut(&OriginalRoad::new(6, (4, 5)))
+            .unwrap()
+            .crossings
+            .push((Pt2D::new(200.0, 300.0), CrossingType::Marked));
+        Map::create_from_raw(raw, true, &mut Timer::throwaway())
+    }
+
+    fn sidewalks(map: &Map, id: OriginalRoad) -> (LaneID, LaneID) {
+        let sidewalks: Vec<LaneID> = map
+            .get_r(map.find_r_by_osm_id(id).unwrap())
+            .lanes_ltr()
+            .into_iter()
+            .filter(|(l, _, _)| map.get_l(*l).is_walkable())
+            .map(|(l, _, _)| l)
+            .collect();
+        assert_eq!(sidewalks.len(), 2);
+        (sidewalks[0], sidewalks[1])
+    }
+
+    fn halfway(l: LaneID, map: &Map) -> Position {
+        Position::new(l, map.get_l(l).length() / 2.0)
+    }
+
+    // Some distance along the sidewalk from the end touching an intersection
+    fn near(l: LaneID, i: IntersectionID, dist: Distance, map: &Map) -> Position {
+        let lane = map.get_l(l);
+        if lane.dst_i == i {
+            Position::new(l, lane.length() - dist)
+        } else {
+            assert_eq!(lane.src_i, i);
+            Position::new(l, dist)
+        }
+    }
+
+    fn req(start: Position, end: Position) -> PathRequest {
+        PathRequest {
+            start,
+            end,
+            constraints: PathConstraints::Pedestrian,
+        }
+    }
+
+    fn assert_close(d1: Distance, d2: Distance) {
+        assert!((d1 - d2).abs() < Distance::meters(0.1), "{} vs {}", d1, d2);
+    }
+
+    #[test]
+    fn test_walked_dist() {
+        let map = setup();
+        let (a, b) = sidewalks(&map, OriginalRoad::new(8, (5, 6)));
+
+        // Along one sidewalk, either way
+        let (pos1, pos2) = (
+            Position::new(a, Distance::meters(10.0)),
+            Position::new(a, Distance::meters(50.0)),
+        );
+        for (start, end) in vec![(pos1, pos2), (pos2, pos1)] {
+            let path = map.pathfind(req(start, end)).unwrap();
+            assert_close(walked_dist(&path, start, end, &map), Distance::meters(40.0));
+        }
+
+        // Around the corner to the other side. Starting or ending further from the intersection
+        // only adds that much.
+        let i = map.get_l(a).dst_i;
+        let walked = |start_dist: f64, end_dist: f64| {
+            let start = near(a, i, Distance::meters(start_dist), &map);
+            let end = near(b, i, Distance::meters(end_dist), &map);
+            let path = map.pathfind(req(start, end)).unwrap();
+            assert!(path.get_steps().len() > 1);
+            walked_dist(&path, start, end, &map)
+        };
+        let base = walked(10.0, 10.0);
+        assert_close(walked(30.0, 10.0), base + Distance::meters(20.0));
+        assert_close(walked(10.0, 30.0), base + Distance::meters(20.0));
+    }
+
+    #[test]
+    fn test_mapped_mid_block_crossing() {
+        let map = setup();
+        let (a, b) = sidewalks(&map, OriginalRoad::new(6, (4, 5)));
+
+        // Straight across the street
+        let r = req(halfway(a, &map), halfway(b, &map));
+        let baseline = map.pathfind(r.clone()).unwrap();
+        let (first, crossings) = pathfind_mid_block(r.clone(), &baseline, false, &map).unwrap();
+        assert_eq!(crossings.len(), 1);
+        let (crossing, second) = &crossings[0];
+        assert_eq!(crossing.crossing_type, Some(CrossingType::Marked));
+        assert_eq!(crossing.from.lane(), a);
+        assert_eq!(crossing.to.lane(), b);
+        assert_eq!(first.current_step().as_lane(), a);
+        assert_eq!(first.last_step().as_lane(), a);
+        assert_eq!(second.current_step().as_lane(), b);
+        assert_eq!(second.last_step().as_lane(), b);
+        let total = walked_dist(&first, r.start, crossing.from, &map)
+            + crossing.length(&map)
+            + walked_dist(second, crossing.to, r.end, &map);
+        assert!(total < walked_dist(&baseline, r.start, r.end, &map));
+
+        // Crossing over and back is never shorter than staying on the same side
+        let r = req(
+            Position::new(a, Distance::meters(10.0)),
+            Position::new(a, map.get_l(a).length() - Distance::meters(10.0)),
+        );
+        let baseline = map.pathfind(r.clone()).unwrap();
+        assert!(pathfind_mid_block(r, &baseline, false, &map).is_none());
+    }
+
+    #[test]
+    fn test_jaywalking() {
+        let map = setup();
+        // Nothing's mapped on the east block
+        let (a, b) = sidewalks(&map, OriginalRoad::new(8, (5, 6)));
+        let r = req(halfway(a, &map), halfway(b, &map));
+        let baseline = map.pathfind(r.clone()).unwrap();
+        assert!(pathfind_mid_block(r.clone(), &baseline, false, &map).is_none());
+
+        let (first, crossings) = pathfind_mid_block(r.clone(), &baseline, true, &map).unwrap();
+        assert_eq!(crossings.len(), 1);
+        let (crossing, second) = &crossings[0];
+        assert_eq!(crossing.crossing_type, None);
+        assert_eq!(crossing.from.lane(), a);
+        assert_eq!(crossing.to.lane(), b);
+        // Right about where they're standing
+        assert!(crossing.from.pt(&map).dist_to(r.start.pt(&map)) < Distance::meters(5.0));
+        assert_eq!(first.current_step().as_lane(), a);
+        assert_eq!(second.last_step().as_lane(), b);
+    }
+}
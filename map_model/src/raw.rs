@@ -3,7 +3,7 @@ use crate::{
     osm, AreaType, Direction, DrivingSide, IntersectionType, LaneType, MapConfig, NamePerLanguage,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Tags, Timer};
-use geom::{Circle, Distance, GPSBounds, PolyLine, Polygon, Pt2D};
+use geom::{Circle, Distance, GPSBounds, LonLat, PolyLine, Polygon, Pt2D};
 use petgraph::graphmap::DiGraphMap;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
@@ -98,6 +98,107 @@ impl RawMap {
         }
     }
 
+    // A tiny grid of two-way residential streets, with cols by rows intersections spaced 200m
+    // apart, and one building along each block. Meant for tests that need a real Map; callers can
+    // change the tags on roads and intersections before building.
+    pub fn synthetic_grid(cols: usize, rows: usize) -> RawMap {
+        let block = 200.0;
+        let margin = 100.0;
+        let width = block * (cols - 1) as f64 + 2.0 * margin;
+        let height = block * (rows - 1) as f64 + 2.0 * margin;
+
+        let mut map = RawMap::blank("test", "grid");
+        // Roughly 1m per 1/111,111 degrees of latitude, and a bit more longitude near Seattle.
+        // Pad a little, so every point is definitely inside.
+        let (lon, lat) = (-122.3, 47.6);
+        map.gps_bounds = GPSBounds::from(vec![
+            LonLat::new(lon, lat),
+            LonLat::new(
+                lon + 1.05 * width / 75_000.0,
+                lat + 1.05 * height / 111_111.0,
+            ),
+        ]);
+        map.boundary_polygon = Polygon::rectangle(width, height);
+
+        let node = |c: usize, r: usize| osm::NodeID((r * cols + c + 1) as i64);
+        let pt =
+            |c: usize, r: usize| Pt2D::new(margin + block * c as f64, margin + block * r as f64);
+        for r in 0..rows {
+            for c in 0..cols {
+                map.intersections.insert(
+                    node(c, r),
+                    RawIntersection {
+                        point: pt(c, r),
+                        intersection_type: IntersectionType::StopSign,
+                        elevation: Distance::ZERO,
+                    },
+                );
+            }
+        }
+
+        let mut edges = Vec::new();
+        for r in 0..rows {
+            for c in 0..cols {
+                if c + 1 < cols {
+                    edges.push(((c, r), (c + 1, r)));
+                }
+                if r + 1 < rows {
+                    edges.push(((c, r), (c, r + 1)));
+                }
+            }
+        }
+        for (idx, (from, to)) in edges.into_iter().enumerate() {
+            let mut osm_tags = Tags::new(BTreeMap::new());
+            osm_tags.insert(osm::HIGHWAY, "residential");
+            osm_tags.insert(osm::NAME, format!("Street {}", idx + 1));
+            osm_tags.insert(osm::SIDEWALK, "both");
+            osm_tags.insert("parking:lane:both", "parallel");
+            let (pt1, pt2) = (pt(from.0, from.1), pt(to.0, to.1));
+            let way = (idx + 1) as i64;
+            map.roads.insert(
+                OriginalRoad {
+                    osm_way_id: osm::WayID(way),
+                    i1: node(from.0, from.1),
+                    i2: node(to.0, to.1),
+                },
+                RawRoad {
+                    center_points: vec![pt1, pt2],
+                    osm_tags,
+                    turn_restrictions: Vec::new(),
+                    complicated_turn_restrictions: Vec::new(),
+                    elevation_samples: Vec::new(),
+                    crossings: Vec::new(),
+                },
+            );
+
+            // A building set back from the middle of the block, on the left side of the road
+            let mid = Pt2D::new((pt1.x() + pt2.x()) / 2.0, (pt1.y() + pt2.y()) / 2.0);
+            let center = if from.1 == to.1 {
+                mid.offset(0.0, 20.0)
+            } else {
+                mid.offset(20.0, 0.0)
+            };
+            let mut bldg_tags = Tags::new(BTreeMap::new());
+            bldg_tags.insert("building", "yes");
+            map.buildings.insert(
+                osm::OsmID::Way(osm::WayID(1000 + way)),
+                RawBuilding {
+                    polygon: Polygon::rectangle_centered(
+                        center,
+                        Distance::meters(10.0),
+                        Distance::meters(10.0),
+                    ),
+                    osm_tags: bldg_tags,
+                    public_garage_name: None,
+                    num_parking_spots: 0,
+                    amenities: BTreeSet::new(),
+                },
+            );
+        }
+
+        map
+    }
+
     // TODO Might be better to maintain this instead of doing a search everytime.
     pub fn roads_per_intersection(&self, i: osm::NodeID) -> Vec<OriginalRoad> {
         let mut results = Vec::new();
@@ -241,8 +342,13 @@ pub struct RawRoad {
     pub center_points: Vec<Pt2D>,
     pub osm_tags: Tags,
     pub turn_restrictions: Vec<(RestrictionType, OriginalRoad)>,
-    // (via, to). For turn restrictions where 'via' is an entire road. Only BanTurns.
-    pub complicated_turn_restrictions: Vec<(OriginalRoad, OriginalRoad)>,
+    // (via, to). For turn restrictions where 'via' is one or more entire roads, in order. Only
+    // BanTurns.
+    pub complicated_turn_restrictions: Vec<(Vec<OriginalRoad>, OriginalRoad)>,
+    // Elevation sampled along center_points. Empty if there's no elevation data.
+    pub elevation_samples: Vec<(Pt2D, Distance)>,
+    // Crossings mapped in OSM along (or at the ends of) center_points.
+    pub crossings: Vec<(Pt2D, CrossingType)>,
 }
 
 impl RawRoad {
@@ -353,6 +459,33 @@ impl RestrictionType {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CrossingType {
+    Signalized,
+    Marked,
+    Unmarked,
+    // crossing=no. People aren't supposed to cross here at all.
+    Forbidden,
+}
+
+impl CrossingType {
+    // Interprets the tags of a highway=crossing node or a footway=crossing way.
+    pub fn from_osm(tags: &Tags) -> CrossingType {
+        if tags.is("crossing", "no") {
+            CrossingType::Forbidden
+        } else if tags.is("crossing", "traffic_signals")
+            || tags.is("crossing:signals", "yes")
+            || tags.is(osm::HIGHWAY, "traffic_signals")
+        {
+            CrossingType::Signalized
+        } else if tags.is("crossing", "unmarked") || tags.is("crossing:markings", "no") {
+            CrossingType::Unmarked
+        } else {
+            CrossingType::Marked
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RawBusRoute {
     pub full_name: String,
@@ -0,0 +1,477 @@
+use crate::make::project_onto;
+use crate::raw::OriginalRoad;
+use crate::{
+    osm, BuildingID, BusRouteID, BusStopID, DirectedRoadID, Direction, IntersectionID, LaneID, Map,
+    Position, RoadID,
+};
+use geom::{Distance, FindClosest};
+use std::collections::{BTreeMap, BTreeSet};
+
+// When the OSM IDs don't line up, geometry in the new map must be within this distance of the old
+// to count as the same thing.
+const MAX_MOVE_DIST: Distance = Distance::const_meters(10.0);
+const SAMPLE_EVERY: Distance = Distance::const_meters(5.0);
+
+// Finds where things from an old version of a map are in a newer import of the same area. OSM IDs
+// are used when they still exist. When ways get split, merged, or redrawn, this falls back to
+// matching geometry.
+pub struct MapMatcher<'a> {
+    old: &'a Map,
+    new: &'a Map,
+
+    roads: BTreeMap<OriginalRoad, RoadID>,
+    intersections: BTreeMap<osm::NodeID, IntersectionID>,
+    buildings: BTreeMap<osm::OsmID, BuildingID>,
+    closest_road: FindClosest<RoadID>,
+    closest_intersection: FindClosest<IntersectionID>,
+    closest_building: FindClosest<BuildingID>,
+}
+
+impl<'a> MapMatcher<'a> {
+    pub fn new(old: &'a Map, new: &'a Map) -> MapMatcher<'a> {
+        let mut closest_road = FindClosest::new(new.get_bounds());
+        for r in new.all_roads() {
+            closest_road.add(r.id, r.center_pts.points());
+        }
+        let mut closest_intersection = FindClosest::new(new.get_bounds());
+        for i in new.all_intersections() {
+            closest_intersection.add(i.id, i.polygon.points());
+        }
+        let mut closest_building = FindClosest::new(new.get_bounds());
+        for b in new.all_buildings() {
+            closest_building.add(b.id, b.polygon.points());
+        }
+
+        MapMatcher {
+            old,
+            new,
+            roads: new.all_roads().iter().map(|r| (r.orig_id, r.id)).collect(),
+            intersections: new
+                .all_intersections()
+                .iter()
+                .map(|i| (i.orig_id, i.id))
+                .collect(),
+            buildings: new
+                .all_buildings()
+                .iter()
+                .map(|b| (b.orig_id, b.id))
+                .collect(),
+            closest_road,
+            closest_intersection,
+            closest_building,
+        }
+    }
+
+    pub fn old_map(&self) -> &Map {
+        self.old
+    }
+
+    pub fn new_map(&self) -> &Map {
+        self.new
+    }
+
+    // Returns the roads in the new map covering an old road, in order from the old road's src_i to
+    // dst_i. Each one is paired with true if it points the opposite direction.
+    pub fn road(&self, id: RoadID) -> Result<Vec<(RoadID, bool)>, String> {
+        let old_r = self.old.get_r(id);
+        if let Some(r) = self.roads.get(&old_r.orig_id) {
+            return Ok(vec![(*r, false)]);
+        }
+
+        // Every piece the old road got split into should be the closest road to some point along
+        // it. Near the ends, perpendicular roads show up too; the angle check gets rid of them.
+        let mut candidates = BTreeSet::new();
+        let mut dist = Distance::ZERO;
+        while dist <= old_r.center_pts.length() {
+            let (pt, _) = old_r.center_pts.must_dist_along(dist);
+            if let Some((r, _)) = self.closest_road.closest_pt(pt, MAX_MOVE_DIST) {
+                candidates.insert(r);
+            }
+            dist += SAMPLE_EVERY;
+        }
+
+        let mut matches = Vec::new();
+        for r in candidates {
+            let new_pl = &self.new.get_r(r).center_pts;
+            let (middle, angle) = new_pl.must_dist_along(new_pl.length() / 2.0);
+            let (away, along) = match project_onto(&old_r.center_pts, middle) {
+                Some(pair) => pair,
+                None => continue,
+            };
+            if away > MAX_MOVE_DIST {
+                continue;
+            }
+            let (_, old_angle) = old_r.center_pts.must_dist_along(along);
+            if angle.approx_eq(old_angle, 30.0) {
+                matches.push((along, r, false));
+            } else if angle.approx_eq(old_angle.opposite(), 30.0) {
+                matches.push((along, r, true));
+            }
+        }
+        if matches.is_empty() {
+            return Err(format!("{} doesn't match anything", old_r.orig_id));
+        }
+        matches.sort_by_key(|(along, _, _)| *along);
+        Ok(matches.into_iter().map(|(_, r, rev)| (r, rev)).collect())
+    }
+
+    pub fn orig_road(&self, id: OriginalRoad) -> Result<Vec<(RoadID, bool)>, String> {
+        self.road(self.old.find_r_by_osm_id(id)?)
+    }
+
+    // If the road got split, this is the piece at the beginning of the directed road.
+    pub fn directed_road(&self, dr: DirectedRoadID) -> Result<DirectedRoadID, String> {
+        let pieces = self.road(dr.id)?;
+        let (id, reversed) = if dr.dir == Direction::Fwd {
+            pieces[0]
+        } else {
+            pieces[pieces.len() - 1]
+        };
+        Ok(DirectedRoadID {
+            id,
+            dir: if reversed { dr.dir.opposite() } else { dr.dir },
+        })
+    }
+
+    pub fn intersection(&self, id: IntersectionID) -> Result<IntersectionID, String> {
+        self.orig_intersection(self.old.get_i(id).orig_id)
+    }
+
+    pub fn orig_intersection(&self, id: osm::NodeID) -> Result<IntersectionID, String> {
+        if let Some(i) = self.intersections.get(&id) {
+            return Ok(*i);
+        }
+        let center = self
+            .old
+            .get_i(self.old.find_i_by_osm_id(id)?)
+            .polygon
+            .center();
+        self.closest_intersection
+            .closest_pt(center, MAX_MOVE_DIST)
+            .map(|(i, _)| i)
+            .ok_or(format!("{} doesn't match anything", id))
+    }
+
+    pub fn building(&self, id: BuildingID) -> Result<BuildingID, String> {
+        let old_b = self.old.get_b(id);
+        if let Some(b) = self.buildings.get(&old_b.orig_id) {
+            return Ok(*b);
+        }
+        for (b, _, _) in self
+            .closest_building
+            .all_close_pts(old_b.label_center, MAX_MOVE_DIST)
+        {
+            if self.new.get_b(b).polygon.contains_pt(old_b.label_center) {
+                return Ok(b);
+            }
+        }
+        Err(format!("{} doesn't match anything", old_b.orig_id))
+    }
+
+    // The lane at the same index on the matching road, counting from the other side if the road
+    // got reversed. If the road got split, the piece closest to the position is used.
+    pub fn position(&self, pos: Position) -> Result<Position, String> {
+        let old_lane = self.old.get_l(pos.lane());
+        let old_r = self.old.get_r(old_lane.parent);
+        let idx = old_r
+            .lanes_ltr()
+            .into_iter()
+            .position(|(l, _, _)| l == old_lane.id)
+            .unwrap();
+        let pt = pos.pt(self.old);
+
+        let (new_r, reversed) = self
+            .road(old_r.id)?
+            .into_iter()
+            .min_by_key(|(r, _)| {
+                project_onto(&self.new.get_r(*r).center_pts, pt)
+                    .map(|(away, _)| away)
+                    .unwrap_or(Distance::meters(f64::MAX))
+            })
+            .unwrap();
+        let new_lanes = self.new.get_r(new_r).lanes_ltr();
+        if new_lanes.len() != old_r.lanes_ltr().len() {
+            return Err(format!(
+                "{} had {} lanes, but {} has {} now",
+                old_r.orig_id,
+                old_r.lanes_ltr().len(),
+                self.new.get_r(new_r).orig_id,
+                new_lanes.len()
+            ));
+        }
+        let (l, _, lt) = if reversed {
+            new_lanes[new_lanes.len() - 1 - idx]
+        } else {
+            new_lanes[idx]
+        };
+        if lt != old_lane.lane_type {
+            return Err(format!(
+                "{} is a {:?} lane now, not {:?}",
+                l, lt, old_lane.lane_type
+            ));
+        }
+        let new_lane = self.new.get_l(l);
+        let dist = project_onto(&new_lane.lane_center_pts, pt)
+            .map(|(_, along)| along)
+            .unwrap_or(Distance::ZERO)
+            .min(new_lane.length());
+        Ok(Position::new(l, dist))
+    }
+
+    // The lane ending at the same place an old lane did
+    pub fn lane(&self, id: LaneID) -> Result<LaneID, String> {
+        Ok(self.position(Position::end(id, self.old))?.lane())
+    }
+
+    pub fn bus_route(&self, id: BusRouteID) -> Result<BusRouteID, String> {
+        let osm_rel_id = self.old.get_br(id).osm_rel_id;
+        self.new
+            .find_br(osm_rel_id)
+            .ok_or(format!("{} doesn't exist anymore", osm_rel_id))
+    }
+
+    // The closest stop along the same route
+    pub fn bus_stop(&self, route: BusRouteID, stop: BusStopID) -> Result<BusStopID, String> {
+        let pt = self.old.get_bs(stop).sidewalk_pos.pt(self.old);
+        self.new
+            .get_br(self.bus_route(route)?)
+            .stops
+            .iter()
+            .map(|s| {
+                (
+                    *s,
+                    self.new.get_bs(*s).sidewalk_pos.pt(self.new).dist_to(pt),
+                )
+            })
+            .filter(|(_, dist)| *dist <= MAX_MOVE_DIST)
+            .min_by_key(|(_, dist)| *dist)
+            .map(|(s, _)| s)
+            .ok_or(format!(
+                "{} on {} doesn't match anything",
+                stop,
+                self.old.get_br(route).full_name
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::raw::{RawIntersection, RawMap};
+    use crate::{
+        EditCmd, EditIntersection, IntersectionType, LaneType, MapEdits, PermanentMapEdits,
+    };
+    use abstutil::Timer;
+    use geom::{Pt2D, Speed};
+
+    #[test]
+    fn test_split_and_reversed_roads() {
+        let old_raw = RawMap::synthetic_grid(3, 2);
+        let mut new_raw = RawMap::synthetic_grid(3, 2);
+
+        // Split the first road in the middle. OSM IDs of the pieces are new.
+        let mut road = new_raw.roads.remove(&OriginalRoad::new(1, (1, 2))).unwrap();
+        new_raw.intersections.insert(
+            osm::NodeID(100),
+            RawIntersection {
+                point: Pt2D::new(200.0, 100.0),
+                intersection_type: IntersectionType::StopSign,
+                elevation: Distance::ZERO,
+            },
+        );
+        road.center_points = vec![Pt2D::new(100.0, 100.0), Pt2D::new(200.0, 100.0)];
+        new_raw
+            .roads
+            .insert(OriginalRoad::new(101, (1, 100)), road.clone());
+        road.center_points = vec![Pt2D::new(200.0, 100.0), Pt2D::new(300.0, 100.0)];
+        new_raw.roads.insert(OriginalRoad::new(102, (100, 2)), road);
+
+        // Redraw the next road pointing the other way
+        let mut road = new_raw.roads.remove(&OriginalRoad::new(3, (2, 3))).unwrap();
+        road.center_points.reverse();
+        new_raw.roads.insert(OriginalRoad::new(103, (3, 2)), road);
+
+        // And give a building a new ID
+        let bldg = new_raw
+            .buildings
+            .remove(&osm::OsmID::Way(osm::WayID(1005)))
+            .unwrap();
+        new_raw
+            .buildings
+            .insert(osm::OsmID::Way(osm::WayID(2005)), bldg);
+
+        let old = Map::create_from_raw(old_raw, false, &mut Timer::throwaway());
+        let new = Map::create_from_raw(new_raw, false, &mut Timer::throwaway());
+        let matcher = MapMatcher::new(&old, &new);
+        let new_r = |id: OriginalRoad| new.find_r_by_osm_id(id).unwrap();
+
+        // Untouched things match by ID
+        let unchanged = OriginalRoad::new(2, (1, 4));
+        assert_eq!(
+            matcher.orig_road(unchanged).unwrap(),
+            vec![(new_r(unchanged), false)]
+        );
+        assert_eq!(
+            matcher.orig_intersection(osm::NodeID(2)).unwrap(),
+            new.find_i_by_osm_id(osm::NodeID(2)).unwrap()
+        );
+
+        // The split road maps to both pieces, in order
+        let split = OriginalRoad::new(1, (1, 2));
+        let (piece1, piece2) = (
+            new_r(OriginalRoad::new(101, (1, 100))),
+            new_r(OriginalRoad::new(102, (100, 2))),
+        );
+        assert_eq!(
+            matcher.orig_road(split).unwrap(),
+            vec![(piece1, false), (piece2, false)]
+        );
+
+        // A position near the end of the split road lands on the second piece, in the same kind
+        // of lane, about the same place
+        let old_lane = old
+            .get_r(old.find_r_by_osm_id(split).unwrap())
+            .lanes_ltr()
+            .into_iter()
+            .find(|(_, dir, lt)| *dir == Direction::Fwd && *lt == LaneType::Driving)
+            .unwrap()
+            .0;
+        let old_pos = Position::new(
+            old_lane,
+            old.get_l(old_lane).length() - Distance::meters(30.0),
+        );
+        let new_pos = matcher.position(old_pos).unwrap();
+        assert_eq!(new.get_l(new_pos.lane()).parent, piece2);
+        assert_eq!(new.get_l(new_pos.lane()).lane_type, LaneType::Driving);
+        assert!(new_pos.pt(&new).dist_to(old_pos.pt(&old)) < MAX_MOVE_DIST);
+
+        // The reversed road matches, flipping direction
+        let reversed = old.find_r_by_osm_id(OriginalRoad::new(3, (2, 3))).unwrap();
+        let new_reversed = new_r(OriginalRoad::new(103, (3, 2)));
+        assert_eq!(matcher.road(reversed).unwrap(), vec![(new_reversed, true)]);
+        assert_eq!(
+            matcher
+                .directed_road(DirectedRoadID {
+                    id: reversed,
+                    dir: Direction::Fwd,
+                })
+                .unwrap(),
+            DirectedRoadID {
+                id: new_reversed,
+                dir: Direction::Back,
+            }
+        );
+
+        // The building matches by geometry
+        let old_b = old
+            .find_b_by_osm_id(osm::OsmID::Way(osm::WayID(1005)))
+            .unwrap();
+        assert_eq!(
+            matcher.building(old_b).unwrap(),
+            new.find_b_by_osm_id(osm::OsmID::Way(osm::WayID(2005)))
+                .unwrap()
+        );
+    }
+
+    // The same grid, but every OSM ID is different, so nothing lines up by ID. One road is gone.
+    fn shuffled_grid(missing_way: i64) -> RawMap {
+        let mut raw = RawMap::synthetic_grid(3, 2);
+        let node = |id: osm::NodeID| osm::NodeID(20 - id.0);
+        raw.intersections = std::mem::take(&mut raw.intersections)
+            .into_iter()
+            .map(|(id, i)| (node(id), i))
+            .collect();
+        raw.roads = std::mem::take(&mut raw.roads)
+            .into_iter()
+            .filter(|(id, _)| id.osm_way_id != osm::WayID(missing_way))
+            .map(|(id, r)| {
+                (
+                    OriginalRoad {
+                        osm_way_id: osm::WayID(100 - id.osm_way_id.0),
+                        i1: node(id.i1),
+                        i2: node(id.i2),
+                    },
+                    r,
+                )
+            })
+            .collect();
+        raw.buildings = std::mem::take(&mut raw.buildings)
+            .into_iter()
+            .map(|(id, b)| match id {
+                osm::OsmID::Way(w) => (osm::OsmID::Way(osm::WayID(3000 - w.0)), b),
+                _ => (id, b),
+            })
+            .collect();
+        raw
+    }
+
+    #[test]
+    fn test_rebase_edits_onto_shuffled_ids() {
+        let mut timer = Timer::throwaway();
+        let old = Map::create_from_raw(RawMap::synthetic_grid(3, 2), false, &mut timer);
+        let new = Map::create_from_raw(shuffled_grid(4), false, &mut timer);
+        let matcher = MapMatcher::new(&old, &new);
+
+        let mut edits = MapEdits::new();
+        // Slow down the first road
+        let r1 = old.find_r_by_osm_id(OriginalRoad::new(1, (1, 2))).unwrap();
+        let mut slower = old.get_r_edit(r1);
+        slower.speed_limit = Speed::miles_per_hour(10.0);
+        edits.commands.push(EditCmd::ChangeRoad {
+            r: r1,
+            old: old.get_r_edit(r1),
+            new: slower,
+        });
+        // And let it through the intersection at the start without stopping
+        let i1 = old.find_i_by_osm_id(osm::NodeID(1)).unwrap();
+        let mut ss = old.get_stop_sign(i1).clone();
+        ss.roads.get_mut(&r1).unwrap().must_stop = false;
+        edits.commands.push(EditCmd::ChangeIntersection {
+            i: i1,
+            old: old.get_i_edit(i1),
+            new: EditIntersection::StopSign(ss),
+        });
+        // The road that's gone
+        let r4 = old.find_r_by_osm_id(OriginalRoad::new(4, (2, 5))).unwrap();
+        let mut slower = old.get_r_edit(r4);
+        slower.speed_limit = Speed::miles_per_hour(10.0);
+        edits.commands.push(EditCmd::ChangeRoad {
+            r: r4,
+            old: old.get_r_edit(r4),
+            new: slower,
+        });
+
+        let (perma, problems) = PermanentMapEdits::to_permanent(&edits, &old).rebase(&matcher);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("Dropping edit to"));
+        assert!(problems[0].contains("doesn't match anything"));
+
+        let rebased = PermanentMapEdits::from_permanent(perma, &new).unwrap();
+        assert_eq!(rebased.commands.len(), 2);
+        let new_r1 = new
+            .find_r_by_osm_id(OriginalRoad::new(99, (19, 18)))
+            .unwrap();
+        let new_i1 = new.find_i_by_osm_id(osm::NodeID(19)).unwrap();
+        // The IDs really did change
+        assert_ne!(r1, new_r1);
+        assert_ne!(i1, new_i1);
+        match &rebased.commands[0] {
+            EditCmd::ChangeRoad { r, new, .. } => {
+                assert_eq!(*r, new_r1);
+                assert_eq!(new.speed_limit, Speed::miles_per_hour(10.0));
+            }
+            _ => panic!("wrong command"),
+        }
+        match &rebased.commands[1] {
+            EditCmd::ChangeIntersection {
+                i,
+                new: EditIntersection::StopSign(ss),
+                ..
+            } => {
+                assert_eq!(*i, new_i1);
+                assert!(!ss.roads[&new_r1].must_stop);
+            }
+            _ => panic!("wrong command"),
+        }
+    }
+}
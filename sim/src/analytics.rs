@@ -1,4 +1,7 @@
-use crate::{AgentType, AlertLocation, CarID, Event, ParkingSpot, TripID, TripMode, TripPhaseType};
+use crate::{
+    AgentType, AlertLocation, CarID, ChargingStation, Emissions, Event, ParkingSpot, TripID,
+    TripMode, TripPhaseType,
+};
 use abstutil::Counter;
 use geom::{Distance, Duration, Time};
 use map_model::{
@@ -25,6 +28,12 @@ pub struct Analytics {
     // For each passenger boarding, how long did they wait at the stop?
     pub passengers_boarding: BTreeMap<BusStopID, Vec<(Time, BusRouteID, Duration)>>,
     pub passengers_alighting: BTreeMap<BusStopID, Vec<(Time, BusRouteID)>>,
+    // For each ride-hailing pickup, how long did the passenger wait?
+    pub ride_hail_waits: Vec<(Time, TripID, Duration)>,
+    // Every time a ride-hailing vehicle starts driving empty, how far does it go?
+    pub fleet_deadhead: Vec<(Time, CarID, Distance)>,
+    // Every delivery stop, and whether the vehicle double-parked
+    pub deliveries: Vec<(Time, CarID, BuildingID, bool)>,
 
     pub started_trips: BTreeMap<TripID, Time>,
     // TODO Hack: No TripMode means aborted
@@ -40,6 +49,19 @@ pub struct Analytics {
     // Per parking lane or lot, when does a spot become filled (true) or free (false)
     pub parking_lane_changes: BTreeMap<LaneID, Vec<(Time, bool)>>,
     pub parking_lot_changes: BTreeMap<ParkingLotID, Vec<(Time, bool)>>,
+    // Cents paid, and whether the car overstayed the time limit. Recorded when the car leaves, so
+    // cars still parked at the end of the simulation haven't paid yet.
+    pub parking_fees: Vec<(Time, TripID, usize, bool)>,
+    // How long and how far each driver searched for parking, recorded when they find a spot
+    pub parking_cruising: Vec<(Time, TripID, Duration, Distance)>,
+    // How long each electric vehicle waited for a free charger and how many kWh it got, recorded
+    // when it finishes charging or leaves the station
+    pub charging_sessions: Vec<(Time, ChargingStation, Duration, f64)>,
+
+    // Everything vehicles have emitted so far. Turns count towards their intersection.
+    pub road_emissions: BTreeMap<RoadID, Emissions>,
+    pub intersection_emissions: BTreeMap<IntersectionID, Emissions>,
+    pub trip_emissions: BTreeMap<TripID, Emissions>,
 
     pub(crate) alerts: Vec<(Time, AlertLocation, String)>,
 
@@ -59,12 +81,21 @@ impl Analytics {
             bus_arrivals: Vec::new(),
             passengers_boarding: BTreeMap::new(),
             passengers_alighting: BTreeMap::new(),
+            ride_hail_waits: Vec::new(),
+            fleet_deadhead: Vec::new(),
+            deliveries: Vec::new(),
             started_trips: BTreeMap::new(),
             finished_trips: Vec::new(),
             trip_log: Vec::new(),
             intersection_delays: BTreeMap::new(),
             parking_lane_changes: BTreeMap::new(),
             parking_lot_changes: BTreeMap::new(),
+            parking_fees: Vec::new(),
+            parking_cruising: Vec::new(),
+            charging_sessions: Vec::new(),
+            road_emissions: BTreeMap::new(),
+            intersection_emissions: BTreeMap::new(),
+            trip_emissions: BTreeMap::new(),
             alerts: Vec::new(),
             record_anything: true,
         }
@@ -147,6 +178,19 @@ impl Analytics {
                 .push((time, route));
         }
 
+        // Ride-hailing
+        if let Event::RideHailPickup(trip, _, _, waiting) = ev {
+            self.ride_hail_waits.push((time, trip, waiting));
+        }
+        if let Event::FleetVehicleDeadheading(car, dist) = ev {
+            self.fleet_deadhead.push((time, car, dist));
+        }
+
+        // Freight
+        if let Event::DeliveryMade(car, b, double_parked) = ev {
+            self.deliveries.push((time, car, b, double_parked));
+        }
+
         // Started trips
         if let Event::TripPhaseStarting(id, _, _, _) = ev {
             self.started_trips.entry(id).or_insert(time);
@@ -167,6 +211,27 @@ impl Analytics {
             self.finished_trips.push((time, id, None, Duration::ZERO));
         }
 
+        // Emissions
+        if let Event::VehicleEmissions(_, trip, on, emissions) = ev {
+            match on {
+                Traversable::Lane(l) => {
+                    *self
+                        .road_emissions
+                        .entry(map.get_l(l).parent)
+                        .or_insert(Emissions::ZERO) += emissions;
+                }
+                Traversable::Turn(t) => {
+                    *self
+                        .intersection_emissions
+                        .entry(t.parent)
+                        .or_insert(Emissions::ZERO) += emissions;
+                }
+            }
+            if let Some(trip) = trip {
+                *self.trip_emissions.entry(trip).or_insert(Emissions::ZERO) += emissions;
+            }
+        }
+
         // Intersection delays
         if let Event::IntersectionDelayMeasured(id, delay, agent) = ev {
             self.intersection_delays
@@ -203,6 +268,16 @@ impl Analytics {
             }
         }
 
+        if let Event::PaidForParking(trip, _, cents, overstayed) = ev {
+            self.parking_fees.push((time, trip, cents, overstayed));
+        }
+        if let Event::CruisedForParking(trip, dt, dist) = ev {
+            self.parking_cruising.push((time, trip, dt, dist));
+        }
+        if let Event::ChargingSession(_, station, waited, energy) = ev {
+            self.charging_sessions.push((time, station, waited, energy));
+        }
+
         // TODO Kinda hacky, but these all consume the event, so kinda bundle em.
         match ev {
             Event::TripPhaseStarting(id, _, maybe_req, phase_type) => {
@@ -283,6 +358,45 @@ impl Analytics {
         results
     }
 
+    // The network-wide effect of an incident starting at some time, compared to the finished trips
+    // of a baseline run without it. Only trips finished in both runs by now count, and only if
+    // they ended after the incident started in at least one. Returns the number of trips and how
+    // much longer they took in total; negative means faster.
+    pub fn incident_delay(
+        &self,
+        now: Time,
+        start: Time,
+        baseline: &[(Time, TripID, Option<TripMode>, Duration)],
+    ) -> (usize, Duration) {
+        let mut before = BTreeMap::new();
+        for (t, id, maybe_mode, dt) in baseline {
+            if *t > now {
+                break;
+            }
+            if maybe_mode.is_some() {
+                before.insert(*id, (*t, *dt));
+            }
+        }
+
+        let mut count = 0;
+        let mut total = Duration::ZERO;
+        for (t, id, maybe_mode, dt) in &self.finished_trips {
+            if *t > now {
+                break;
+            }
+            if maybe_mode.is_none() {
+                continue;
+            }
+            if let Some((t0, dt0)) = before.get(id) {
+                if *t >= start || *t0 >= start {
+                    count += 1;
+                    total += *dt - *dt0;
+                }
+            }
+        }
+        (count, total)
+    }
+
     // Find intersections where the cumulative sum of delay has changed. Negative means faster.
     pub fn compare_delay(&self, now: Time, before: &Analytics) -> Vec<(IntersectionID, Duration)> {
         let mut results = Vec::new();
@@ -629,3 +743,58 @@ impl Window {
         self.times.len()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(minutes: usize) -> Time {
+        Time::START_OF_DAY + Duration::minutes(minutes)
+    }
+
+    #[test]
+    fn test_incident_delay() {
+        let drive = Some(TripMode::Drive);
+        let baseline = vec![
+            // Done before the incident in both runs
+            (t(5), TripID(0), drive, Duration::minutes(5)),
+            // Done before the incident in the baseline, but the incident held it up
+            (t(9), TripID(1), drive, Duration::minutes(9)),
+            (t(15), TripID(2), drive, Duration::minutes(10)),
+            // Aborted in the baseline
+            (t(16), TripID(3), None, Duration::minutes(1)),
+            // Only finished in the baseline so far
+            (t(20), TripID(4), drive, Duration::minutes(10)),
+            // Finished after now in the baseline
+            (t(40), TripID(5), drive, Duration::minutes(30)),
+        ];
+
+        let mut analytics = Analytics::new();
+        analytics.finished_trips = vec![
+            (t(5), TripID(0), drive, Duration::minutes(5)),
+            (t(12), TripID(1), drive, Duration::minutes(12)),
+            // Rerouting got this one there faster
+            (t(14), TripID(2), drive, Duration::minutes(9)),
+            (t(18), TripID(3), drive, Duration::minutes(3)),
+            (t(25), TripID(5), drive, Duration::minutes(15)),
+            // Not done yet as of now
+            (t(35), TripID(6), drive, Duration::minutes(5)),
+        ];
+
+        // The incident started at 10 minutes
+        assert_eq!(
+            analytics.incident_delay(t(30), t(10), &baseline),
+            (2, Duration::minutes(2))
+        );
+        // Earlier, only the first held-up trip counts
+        assert_eq!(
+            analytics.incident_delay(t(13), t(10), &baseline),
+            (1, Duration::minutes(3))
+        );
+        // Nothing finished after an incident that hasn't started
+        assert_eq!(
+            analytics.incident_delay(t(30), t(60), &baseline),
+            (0, Duration::ZERO)
+        );
+    }
+}
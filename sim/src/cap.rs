@@ -100,7 +100,7 @@ impl CapSimState {
                 avoid_lanes.insert(*l);
             }
         }
-        map.pathfind_avoiding_zones(req.clone(), avoid_lanes)
+        map.pathfind_avoiding_lanes(req.clone(), avoid_lanes)
     }
 
     pub fn get_cap_counter(&self, l: LaneID) -> usize {
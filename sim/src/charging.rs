@@ -0,0 +1,540 @@
+use crate::{
+    CarID, Command, Event, ParkingSimState, ParkingSpot, Scheduler, Vehicle, VehicleSpec,
+    VehicleType,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Pt2D, Time};
+use map_model::{BuildingID, Map, ParkingLotID};
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+// Every charger is Level 2
+const CHARGER_POWER_KW: f64 = 11.0;
+// Drivers look for a charger when they'd arrive with less than this fraction of their battery
+const LOW_BATTERY: f64 = 0.2;
+// Straight-line distance underestimates how far somebody has to drive
+const DETOUR_FACTOR: f64 = 1.5;
+// How far from their destination drivers are willing to charge
+const MAX_WALK_FROM_CHARGER: Distance = Distance::const_meters(800.0);
+// Parking on the street this close to a station still reaches a charger
+const CHARGER_REACH: Distance = Distance::const_meters(100.0);
+// Every car already at a station makes it look this much farther away
+const QUEUE_PENALTY: Distance = Distance::const_meters(200.0);
+const KWH_PER_MJ: f64 = 1.0 / 3.6;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    // In kWh
+    pub capacity: f64,
+    // In kWh per km, on flat ground
+    pub consumption: f64,
+    // How full the battery is when the simulation starts, in kWh. ChargingSimState tracks it after
+    // that.
+    pub initial_charge: f64,
+}
+
+impl Battery {
+    pub fn random(rng: &mut XorShiftRng) -> Battery {
+        let capacity = rng.gen_range(40.0, 100.0);
+        Battery {
+            capacity,
+            consumption: rng.gen_range(0.15, 0.22),
+            initial_charge: capacity * rng.gen_range(0.15, 1.0),
+        }
+    }
+}
+
+// From amenity=charging_station in OSM
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChargingStation {
+    Building(BuildingID),
+    Lot(ParkingLotID),
+}
+
+impl ChargingStation {
+    pub fn pt(self, map: &Map) -> Pt2D {
+        match self {
+            ChargingStation::Building(b) => map.get_b(b).polygon.center(),
+            ChargingStation::Lot(pl) => map.get_pl(pl).polygon.center(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Station {
+    chargers: usize,
+    // When each car started charging, and how long it waited in the queue first
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    charging: BTreeMap<CarID, (Time, Duration)>,
+    // Cars parked at the station waiting for a free charger, and when they arrived
+    queue: VecDeque<(CarID, Time)>,
+}
+
+// Tracks the battery of every electric vehicle, and who's using or waiting for chargers. Cars
+// charge while parked at a station, so this just listens to parking events.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ChargingSimState {
+    // What fraction of private cars are electric
+    ev_share: f64,
+    // Each battery and how much energy it currently holds, in kWh
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    batteries: BTreeMap<CarID, (Battery, f64)>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    stations: BTreeMap<ChargingStation, Station>,
+    // Drivers who detoured to charge, and where they're going
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    heading_to: BTreeMap<CarID, ChargingStation>,
+    // Cars parked at a station, charging or waiting to
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    plugged_in: BTreeMap<CarID, ChargingStation>,
+
+    events: Vec<Event>,
+}
+
+impl ChargingSimState {
+    pub fn new(map: &Map, ev_share: f64) -> ChargingSimState {
+        let mut stations = BTreeMap::new();
+        for b in map.all_buildings() {
+            if b.charging_points > 0 {
+                stations.insert(
+                    ChargingStation::Building(b.id),
+                    Station::new(b.charging_points),
+                );
+            }
+        }
+        for pl in map.all_parking_lots() {
+            if pl.charging_points > 0 {
+                stations.insert(
+                    ChargingStation::Lot(pl.id),
+                    Station::new(pl.charging_points),
+                );
+            }
+        }
+        ChargingSimState {
+            ev_share,
+            batteries: BTreeMap::new(),
+            stations,
+            heading_to: BTreeMap::new(),
+            plugged_in: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    // Makes some of these cars electric. Doesn't touch the RNG when there aren't any EVs, so
+    // scenarios instantiate the same way as before.
+    pub fn electrify(&self, specs: &mut Vec<VehicleSpec>, rng: &mut XorShiftRng) {
+        if self.ev_share <= 0.0 {
+            return;
+        }
+        for spec in specs {
+            if spec.vehicle_type == VehicleType::Car && rng.gen_bool(self.ev_share.min(1.0)) {
+                spec.battery = Some(Battery::random(rng));
+            }
+        }
+    }
+
+    pub fn add_vehicles(&mut self, vehicles: &[Vehicle]) {
+        for v in vehicles {
+            if let Some(battery) = v.battery {
+                self.batteries
+                    .entry(v.id)
+                    .or_insert((battery, battery.initial_charge));
+            }
+        }
+    }
+
+    // If this driver is low on charge, pick somewhere near their destination to charge. Remembers
+    // the choice, so the car will plug in when it parks there.
+    pub fn maybe_detour(
+        &mut self,
+        car: CarID,
+        from: Pt2D,
+        to: BuildingID,
+        map: &Map,
+    ) -> Option<ChargingStation> {
+        let (battery, charge) = self.batteries.get(&car)?;
+        let goal = map.get_b(to).polygon.center();
+        let needed =
+            battery.consumption * DETOUR_FACTOR * from.dist_to(goal).inner_meters() / 1000.0;
+        if charge - needed >= battery.capacity * LOW_BATTERY {
+            return None;
+        }
+
+        let station = self
+            .stations
+            .iter()
+            .filter_map(|(id, s)| {
+                let dist = id.pt(map).dist_to(goal);
+                if dist > MAX_WALK_FROM_CHARGER {
+                    return None;
+                }
+                let busy = (s.charging.len() + s.queue.len()).saturating_sub(s.chargers);
+                Some((*id, dist + (busy as f64) * QUEUE_PENALTY))
+            })
+            .min_by_key(|(_, cost)| *cost)
+            .map(|(id, _)| id)?;
+        self.heading_to.insert(car, station);
+        Some(station)
+    }
+
+    // The car didn't wind up driving to the station after all
+    pub fn cancel_detour(&mut self, car: CarID) {
+        self.heading_to.remove(&car);
+    }
+
+    pub fn handle_event(
+        &mut self,
+        now: Time,
+        ev: &Event,
+        parking: &ParkingSimState,
+        map: &Map,
+        scheduler: &mut Scheduler,
+    ) {
+        match ev {
+            Event::VehicleEmissions(car, _, _, emissions) => {
+                // Running out of charge isn't modeled; an empty car keeps driving. Drivers plan
+                // ahead to charge, so this should be rare.
+                if let Some((_, charge)) = self.batteries.get_mut(car) {
+                    *charge = (*charge - emissions.energy * KWH_PER_MJ).max(0.0);
+                }
+            }
+            Event::CarReachedParkingSpot(car, spot) => {
+                let (battery, charge) = match self.batteries.get(car) {
+                    Some(pair) => *pair,
+                    None => {
+                        return;
+                    }
+                };
+                let heading_to = self.heading_to.remove(car);
+                if charge >= battery.capacity {
+                    return;
+                }
+                if let Some(station) = self.station_at(*spot, heading_to, parking, map) {
+                    self.plugged_in.insert(*car, station);
+                    self.stations
+                        .get_mut(&station)
+                        .unwrap()
+                        .queue
+                        .push_back((*car, now));
+                    self.start_charging(now, station, scheduler);
+                }
+            }
+            Event::CarLeftParkingSpot(car, _) => {
+                if let Some(station) = self.plugged_in.remove(car) {
+                    let s = self.stations.get_mut(&station).unwrap();
+                    if let Some((started, waited)) = s.charging.remove(car) {
+                        scheduler.cancel(Command::FinishCharging(*car));
+                        let added = self.add_charge(*car, now - started);
+                        self.events
+                            .push(Event::ChargingSession(*car, station, waited, added));
+                    } else {
+                        // Gave up waiting for a charger
+                        let idx = s.queue.iter().position(|(c, _)| c == car).unwrap();
+                        let (_, arrived) = s.queue.remove(idx).unwrap();
+                        self.events
+                            .push(Event::ChargingSession(*car, station, now - arrived, 0.0));
+                    }
+                    self.start_charging(now, station, scheduler);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The battery is full. Drivers aren't around to move their car, but the charger is free for
+    // the next person anyway.
+    pub fn finish_charging(&mut self, now: Time, car: CarID, scheduler: &mut Scheduler) {
+        let station = self.plugged_in.remove(&car).unwrap();
+        let (started, waited) = self
+            .stations
+            .get_mut(&station)
+            .unwrap()
+            .charging
+            .remove(&car)
+            .unwrap();
+        let added = self.add_charge(car, now - started);
+        self.events
+            .push(Event::ChargingSession(car, station, waited, added));
+        self.start_charging(now, station, scheduler);
+    }
+
+    // (current charge, capacity) in kWh
+    pub fn get_battery(&self, car: CarID, now: Time) -> Option<(f64, f64)> {
+        let (battery, mut charge) = *self.batteries.get(&car)?;
+        if let Some(station) = self.plugged_in.get(&car) {
+            if let Some((started, _)) = self.stations[station].charging.get(&car) {
+                charge += CHARGER_POWER_KW * (now - *started).inner_seconds() / 3600.0;
+            }
+        }
+        Some((charge.min(battery.capacity), battery.capacity))
+    }
+
+    // (chargers, cars charging, cars waiting)
+    pub fn get_station(&self, station: ChargingStation) -> Option<(usize, usize, usize)> {
+        let s = self.stations.get(&station)?;
+        Some((s.chargers, s.charging.len(), s.queue.len()))
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    fn station_at(
+        &self,
+        spot: ParkingSpot,
+        heading_to: Option<ChargingStation>,
+        parking: &ParkingSimState,
+        map: &Map,
+    ) -> Option<ChargingStation> {
+        let here = match spot {
+            ParkingSpot::Offstreet(b, _) => Some(ChargingStation::Building(b)),
+            ParkingSpot::Lot(pl, _) => Some(ChargingStation::Lot(pl)),
+            ParkingSpot::Onstreet(_, _) => None,
+        };
+        if let Some(station) = here {
+            if self.stations.contains_key(&station) {
+                return Some(station);
+            }
+        }
+        // The driver couldn't park right at the station, but maybe close enough
+        let station = heading_to?;
+        if parking
+            .spot_to_sidewalk_pos(spot, map)
+            .pt(map)
+            .dist_to(station.pt(map))
+            <= CHARGER_REACH
+        {
+            Some(station)
+        } else {
+            None
+        }
+    }
+
+    fn start_charging(&mut self, now: Time, station: ChargingStation, scheduler: &mut Scheduler) {
+        let s = self.stations.get_mut(&station).unwrap();
+        while s.charging.len() < s.chargers {
+            let (car, arrived) = if let Some(pair) = s.queue.pop_front() {
+                pair
+            } else {
+                break;
+            };
+            let (battery, charge) = self.batteries[&car];
+            let hours = (battery.capacity - charge).max(0.0) / CHARGER_POWER_KW;
+            s.charging.insert(car, (now, now - arrived));
+            scheduler.push(
+                now + Duration::seconds(hours * 3600.0),
+                Command::FinishCharging(car),
+            );
+        }
+    }
+
+    // Returns the kWh added
+    fn add_charge(&mut self, car: CarID, dt: Duration) -> f64 {
+        let (battery, charge) = self.batteries.get_mut(&car).unwrap();
+        let before = *charge;
+        *charge = (*charge + CHARGER_POWER_KW * dt.inner_seconds() / 3600.0).min(battery.capacity);
+        *charge - before
+    }
+}
+
+impl Station {
+    fn new(chargers: usize) -> Station {
+        Station {
+            chargers,
+            charging: BTreeMap::new(),
+            queue: VecDeque::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParkingSearch;
+    use abstutil::Timer;
+    use map_model::osm;
+    use map_model::raw::RawMap;
+
+    struct Harness {
+        map: Map,
+        charging: ChargingSimState,
+        parking: ParkingSimState,
+        scheduler: Scheduler,
+    }
+
+    impl Harness {
+        // Two buildings a block apart, each with one charger
+        fn new() -> Harness {
+            let mut raw = RawMap::synthetic_grid(3, 1);
+            for id in [1001, 1002].iter() {
+                raw.buildings
+                    .get_mut(&osm::OsmID::Way(osm::WayID(*id)))
+                    .unwrap()
+                    .osm_tags
+                    .insert("capacity:charging", "1");
+            }
+            let mut timer = Timer::throwaway();
+            let map = Map::create_from_raw(raw, false, &mut timer);
+            Harness {
+                charging: ChargingSimState::new(&map, 0.0),
+                parking: ParkingSimState::new(&map, ParkingSearch::omniscient(), &mut timer),
+                scheduler: Scheduler::new(),
+                map,
+            }
+        }
+
+        fn bldg(&self, id: i64) -> BuildingID {
+            self.map
+                .all_buildings()
+                .iter()
+                .find(|b| b.orig_id == osm::OsmID::Way(osm::WayID(id)))
+                .unwrap()
+                .id
+        }
+
+        // An EV that needs `kwh` to fill up
+        fn add_ev(&mut self, idx: usize, kwh: f64) -> CarID {
+            let id = CarID(idx, VehicleType::Car);
+            let mut spec = VehicleSpec::new(VehicleType::Car, Distance::meters(4.5), None);
+            spec.battery = Some(Battery {
+                capacity: 50.0,
+                consumption: 0.2,
+                initial_charge: 50.0 - kwh,
+            });
+            self.charging.add_vehicles(&[spec.make(id, None)]);
+            id
+        }
+
+        fn park(&mut self, now: Time, car: CarID, b: BuildingID) {
+            let ev = Event::CarReachedParkingSpot(car, ParkingSpot::Offstreet(b, 0));
+            self.charging
+                .handle_event(now, &ev, &self.parking, &self.map, &mut self.scheduler);
+        }
+
+        fn leave(&mut self, now: Time, car: CarID, b: BuildingID) {
+            let ev = Event::CarLeftParkingSpot(car, ParkingSpot::Offstreet(b, 0));
+            self.charging
+                .handle_event(now, &ev, &self.parking, &self.map, &mut self.scheduler);
+        }
+    }
+
+    fn hours(x: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(x)
+    }
+
+    #[test]
+    fn test_queue_for_chargers() {
+        let mut h = Harness::new();
+        let b = h.bldg(1001);
+        let station = ChargingStation::Building(b);
+        let cars: Vec<CarID> = (0..4).map(|i| h.add_ev(i, 11.0)).collect();
+        for car in &cars {
+            h.park(hours(0), *car, b);
+        }
+        // Only one charger, so the others wait in the order they showed up
+        assert_eq!(h.charging.get_station(station), Some((1, 1, 3)));
+
+        // Filling up 11kWh at 11kW takes an hour
+        assert_eq!(h.scheduler.peek_next_time(), Some(hours(1)));
+        assert_eq!(
+            h.scheduler.get_next(),
+            Some(Command::FinishCharging(cars[0]))
+        );
+        h.charging
+            .finish_charging(hours(1), cars[0], &mut h.scheduler);
+        assert_eq!(
+            h.charging.get_battery(cars[0], hours(1)),
+            Some((50.0, 50.0))
+        );
+        assert_eq!(h.charging.get_station(station), Some((1, 1, 2)));
+
+        // The last car gives up before getting a turn
+        h.leave(hours(1), cars[3], b);
+        assert_eq!(h.charging.get_station(station), Some((1, 1, 1)));
+
+        assert_eq!(h.scheduler.peek_next_time(), Some(hours(2)));
+        assert_eq!(
+            h.scheduler.get_next(),
+            Some(Command::FinishCharging(cars[1]))
+        );
+        h.charging
+            .finish_charging(hours(2), cars[1], &mut h.scheduler);
+        assert_eq!(
+            h.scheduler.get_next(),
+            Some(Command::FinishCharging(cars[2]))
+        );
+        assert_eq!(
+            h.charging.collect_events(),
+            vec![
+                Event::ChargingSession(cars[0], station, Duration::ZERO, 11.0),
+                Event::ChargingSession(cars[3], station, Duration::hours(1), 0.0),
+                Event::ChargingSession(cars[1], station, Duration::hours(1), 11.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_charge_carried_across_trips() {
+        let mut h = Harness::new();
+        let b = h.bldg(1001);
+        let car = h.add_ev(0, 22.0);
+
+        // Leave after charging halfway
+        h.park(hours(0), car, b);
+        assert_eq!(h.scheduler.peek_next_time(), Some(hours(2)));
+        h.leave(hours(1), car, b);
+        assert_eq!(h.charging.get_battery(car, hours(1)), Some((39.0, 50.0)));
+        // The charger doesn't finish anymore
+        assert_eq!(h.scheduler.get_next(), None);
+
+        // Coming back later, only the rest is left to charge
+        h.park(hours(5), car, b);
+        assert_eq!(h.scheduler.peek_next_time(), Some(hours(6)));
+    }
+
+    #[test]
+    fn test_detour_to_less_busy_station() {
+        let mut h = Harness::new();
+        let near = h.bldg(1002);
+        let far = h.bldg(1001);
+        let goal = h.map.get_b(near).polygon.center();
+
+        // Plenty of charge, no need to stop
+        let full = h.add_ev(0, 0.0);
+        assert_eq!(h.charging.maybe_detour(full, goal, near, &h.map), None);
+
+        let low = h.add_ev(1, 45.0);
+        assert_eq!(
+            h.charging.maybe_detour(low, goal, near, &h.map),
+            Some(ChargingStation::Building(near))
+        );
+        h.charging.cancel_detour(low);
+
+        // A line forms at the closest station
+        for i in 2..5 {
+            let car = h.add_ev(i, 10.0);
+            h.park(hours(0), car, near);
+        }
+        assert_eq!(
+            h.charging.maybe_detour(low, goal, near, &h.map),
+            Some(ChargingStation::Building(far))
+        );
+    }
+}
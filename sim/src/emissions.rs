@@ -0,0 +1,308 @@
+use crate::{Battery, VehicleType};
+use geom::{Distance, Duration};
+use serde::{Deserialize, Serialize};
+use std::ops;
+
+// Energy used and pollutants emitted. Only counts what comes out of the vehicle, so electric
+// trains are free of CO2 and NOx here.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Emissions {
+    // In megajoules
+    pub energy: f64,
+    // In grams
+    pub co2: f64,
+    pub nox: f64,
+}
+
+impl Emissions {
+    pub const ZERO: Emissions = Emissions {
+        energy: 0.0,
+        co2: 0.0,
+        nox: 0.0,
+    };
+
+    pub fn describe(&self) -> String {
+        format!(
+            "{:.1} MJ, {:.0}g CO2, {:.1}g NOx",
+            self.energy, self.co2, self.nox
+        )
+    }
+}
+
+impl ops::Add for Emissions {
+    type Output = Emissions;
+
+    fn add(self, other: Emissions) -> Emissions {
+        Emissions {
+            energy: self.energy + other.energy,
+            co2: self.co2 + other.co2,
+            nox: self.nox + other.nox,
+        }
+    }
+}
+
+impl ops::AddAssign for Emissions {
+    fn add_assign(&mut self, other: Emissions) {
+        *self = *self + other;
+    }
+}
+
+// A simple road-load model: rolling resistance, drag, climbing, and speeding up from rest all
+// take energy at the wheels, which costs more energy from the tank depending on efficiency. The
+// engine also burns some just to keep running, whether it's moving or idling.
+struct VehicleClass {
+    // In kg
+    mass: f64,
+    rolling_resistance: f64,
+    // Drag coefficient times frontal area, in m^2
+    drag_area: f64,
+    // Fraction of energy used that reaches the wheels
+    efficiency: f64,
+    // Energy burned just to keep running, in kW
+    idle_power: f64,
+    // Grams per megajoule of energy used
+    co2_per_mj: f64,
+    nox_per_mj: f64,
+}
+
+const GASOLINE_CO2_PER_MJ: f64 = 73.0;
+const DIESEL_CO2_PER_MJ: f64 = 74.0;
+const GRAVITY: f64 = 9.81;
+const AIR_DENSITY: f64 = 1.2;
+// Fraction of the energy from going downhill that regenerative braking recovers
+const REGEN_EFFICIENCY: f64 = 0.6;
+const MJ_PER_KWH: f64 = 3.6;
+
+fn vehicle_class(vehicle_type: VehicleType) -> Option<VehicleClass> {
+    match vehicle_type {
+        VehicleType::Car => Some(VehicleClass {
+            mass: 1500.0,
+            rolling_resistance: 0.012,
+            drag_area: 0.7,
+            efficiency: 0.2,
+            idle_power: 5.0,
+            co2_per_mj: GASOLINE_CO2_PER_MJ,
+            nox_per_mj: 0.03,
+        }),
+        VehicleType::Bus => Some(VehicleClass {
+            mass: 15000.0,
+            rolling_resistance: 0.008,
+            drag_area: 6.0,
+            efficiency: 0.3,
+            idle_power: 15.0,
+            co2_per_mj: DIESEL_CO2_PER_MJ,
+            nox_per_mj: 0.25,
+        }),
+        VehicleType::Train => Some(VehicleClass {
+            mass: 50000.0,
+            rolling_resistance: 0.002,
+            drag_area: 8.0,
+            efficiency: 0.85,
+            idle_power: 20.0,
+            co2_per_mj: 0.0,
+            nox_per_mj: 0.0,
+        }),
+        // People power
+        VehicleType::Bike => None,
+        VehicleType::Van => Some(VehicleClass {
+            mass: 3000.0,
+            rolling_resistance: 0.01,
+            drag_area: 1.8,
+            efficiency: 0.28,
+            idle_power: 6.0,
+            co2_per_mj: DIESEL_CO2_PER_MJ,
+            nox_per_mj: 0.15,
+        }),
+        VehicleType::Truck => Some(VehicleClass {
+            mass: 15000.0,
+            rolling_resistance: 0.007,
+            drag_area: 6.5,
+            efficiency: 0.35,
+            idle_power: 10.0,
+            co2_per_mj: DIESEL_CO2_PER_MJ,
+            nox_per_mj: 0.25,
+        }),
+    }
+}
+
+// Estimate what a vehicle emits while covering some distance over moving_time, on a road with
+// this grade (0.05 is a 5% incline), plus idle_time spent waiting for an intersection.
+// If the vehicle started from rest, it also had to speed up.
+pub(crate) fn estimate_emissions(
+    vehicle_type: VehicleType,
+    dist: Distance,
+    moving_time: Duration,
+    idle_time: Duration,
+    grade: f64,
+    from_rest: bool,
+) -> Emissions {
+    let class = if let Some(c) = vehicle_class(vehicle_type) {
+        c
+    } else {
+        return Emissions::ZERO;
+    };
+
+    let meters = dist.inner_meters().max(0.0);
+    let speed = if moving_time > Duration::ZERO {
+        meters / moving_time.inner_seconds()
+    } else {
+        0.0
+    };
+
+    // In joules. Going downhill helps, but brakes waste anything beyond what's needed to roll.
+    let mut at_wheels = (class.mass * GRAVITY * (class.rolling_resistance + grade)
+        + 0.5 * AIR_DENSITY * class.drag_area * speed * speed)
+        * meters;
+    if from_rest {
+        at_wheels += 0.5 * class.mass * speed * speed;
+    }
+    let joules = at_wheels.max(0.0) / class.efficiency
+        + 1000.0 * class.idle_power * (moving_time + idle_time).inner_seconds();
+
+    let energy = joules / 1_000_000.0;
+    Emissions {
+        energy,
+        co2: energy * class.co2_per_mj,
+        nox: energy * class.nox_per_mj,
+    }
+}
+
+// Electric vehicles draw on their battery instead, using its rated consumption on flat ground.
+// Climbing costs extra, and regenerative braking recovers some of that going back down. Idling
+// barely uses anything. Nothing comes out of the tailpipe.
+pub(crate) fn estimate_electric(
+    vehicle_type: VehicleType,
+    battery: &Battery,
+    dist: Distance,
+    grade: f64,
+) -> Emissions {
+    let meters = dist.inner_meters().max(0.0);
+    let mass = vehicle_class(vehicle_type).map(|c| c.mass).unwrap_or(0.0);
+    // In kWh
+    let mut climb = mass * GRAVITY * grade * meters / (MJ_PER_KWH * 1_000_000.0);
+    if climb < 0.0 {
+        climb *= REGEN_EFFICIENCY;
+    }
+    let kwh = (battery.consumption * meters / 1000.0 + climb).max(0.0);
+    Emissions {
+        energy: kwh * MJ_PER_KWH,
+        co2: 0.0,
+        nox: 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "Expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn test_idling() {
+        // Just keeping the engine running for a minute
+        let e = estimate_emissions(
+            VehicleType::Car,
+            Distance::ZERO,
+            Duration::ZERO,
+            Duration::minutes(1),
+            0.0,
+            false,
+        );
+        assert_close(e.energy, 0.3);
+        assert_close(e.co2, 0.3 * GASOLINE_CO2_PER_MJ);
+        assert_close(e.nox, 0.3 * 0.03);
+    }
+
+    #[test]
+    fn test_cruising() {
+        let cruise = |grade, from_rest| {
+            estimate_emissions(
+                VehicleType::Car,
+                Distance::meters(1000.0),
+                Duration::seconds(100.0),
+                Duration::ZERO,
+                grade,
+                from_rest,
+            )
+        };
+
+        // Rolling resistance and drag at 10m/s over 1km, plus running the engine for 100s
+        let flat = cruise(0.0, false);
+        let at_wheels =
+            1500.0 * GRAVITY * 0.012 * 1000.0 + 0.5 * AIR_DENSITY * 0.7 * 100.0 * 1000.0;
+        assert_close(flat.energy, (at_wheels / 0.2 + 500_000.0) / 1_000_000.0);
+
+        // Speeding up from rest costs the kinetic energy
+        assert_close(
+            cruise(0.0, true).energy - flat.energy,
+            0.5 * 1500.0 * 100.0 / 0.2 / 1_000_000.0,
+        );
+
+        // Climbing costs more
+        assert_close(
+            cruise(0.05, false).energy - flat.energy,
+            1500.0 * GRAVITY * 0.05 * 1000.0 / 0.2 / 1_000_000.0,
+        );
+
+        // Going steeply downhill, the brakes waste everything; only the engine running counts
+        assert_close(cruise(-0.5, false).energy, 0.5);
+    }
+
+    #[test]
+    fn test_no_tailpipe() {
+        let bike = estimate_emissions(
+            VehicleType::Bike,
+            Distance::meters(1000.0),
+            Duration::seconds(200.0),
+            Duration::seconds(30.0),
+            0.05,
+            true,
+        );
+        assert_eq!(bike, Emissions::ZERO);
+
+        let train = estimate_emissions(
+            VehicleType::Train,
+            Distance::meters(1000.0),
+            Duration::seconds(60.0),
+            Duration::ZERO,
+            0.0,
+            false,
+        );
+        assert!(train.energy > 0.0);
+        assert_eq!(train.co2, 0.0);
+        assert_eq!(train.nox, 0.0);
+    }
+
+    #[test]
+    fn test_electric() {
+        let battery = Battery {
+            capacity: 60.0,
+            consumption: 0.2,
+            initial_charge: 60.0,
+        };
+        let drive =
+            |grade| estimate_electric(VehicleType::Car, &battery, Distance::meters(1000.0), grade);
+
+        let flat = drive(0.0);
+        assert_close(flat.energy, 0.2 * MJ_PER_KWH);
+        assert_eq!(flat.co2, 0.0);
+
+        // Climbing 100m
+        let climb_kwh = 1500.0 * GRAVITY * 100.0 / (MJ_PER_KWH * 1_000_000.0);
+        assert_close(drive(0.1).energy, (0.2 + climb_kwh) * MJ_PER_KWH);
+        // Going down a gentle slope, regenerative braking recovers some of it
+        assert_close(
+            drive(-0.01).energy,
+            (0.2 - 0.1 * climb_kwh * REGEN_EFFICIENCY) * MJ_PER_KWH,
+        );
+        // But it can't recover more than the trip takes
+        assert_close(drive(-0.1).energy, 0.0);
+    }
+}
@@ -1,7 +1,8 @@
 use crate::{
-    AgentID, CarID, OffMapLocation, ParkingSpot, PedestrianID, PersonID, TripID, TripMode,
+    AgentID, CarID, ChargingStation, Emissions, OffMapLocation, ParkingSpot, PedestrianID,
+    PersonID, TripID, TripMode,
 };
-use geom::Duration;
+use geom::{Distance, Duration};
 use map_model::{
     BuildingID, BusRouteID, BusStopID, CompressedMovementID, IntersectionID, LaneID, Map, Path,
     PathRequest, Traversable,
@@ -15,12 +16,27 @@ use serde::{Deserialize, Serialize};
 pub enum Event {
     CarReachedParkingSpot(CarID, ParkingSpot),
     CarLeftParkingSpot(CarID, ParkingSpot),
+    // When a car leaves a spot with a price or time limit, the trip that parked it pays this many
+    // cents. True if the car stayed longer than allowed.
+    PaidForParking(TripID, ParkingSpot, usize, bool),
+    // How long and how far a driver searched before finding a spot
+    CruisedForParking(TripID, Duration, Distance),
+    // An electric vehicle finished charging or left the station. How long it waited for a free
+    // charger, and how many kWh it got.
+    ChargingSession(CarID, ChargingStation, Duration, f64),
 
     BusArrivedAtStop(CarID, BusRouteID, BusStopID),
     BusDepartedFromStop(CarID, BusRouteID, BusStopID),
     // How long waiting at the stop?
     PassengerBoardsTransit(PersonID, CarID, BusRouteID, BusStopID, Duration),
     PassengerAlightsTransit(PersonID, CarID, BusRouteID, BusStopID),
+    // How long did the passenger wait to be picked up?
+    RideHailPickup(TripID, PersonID, CarID, Duration),
+    RideHailDropoff(TripID, PersonID, CarID),
+    // A ride-hailing vehicle starts driving empty, either to a pickup or back to a depot
+    FleetVehicleDeadheading(CarID, Distance),
+    // A delivery vehicle reached a stop. True if it had to double-park in a travel lane.
+    DeliveryMade(CarID, BuildingID, bool),
 
     PersonEntersBuilding(PersonID, BuildingID),
     PersonLeavesBuilding(PersonID, BuildingID),
@@ -42,7 +58,12 @@ pub enum Event {
     // If the agent is a transit vehicle, then include a count of how many passengers are on
     // board.
     AgentEntersTraversable(AgentID, Traversable, Option<usize>),
+    // What a vehicle emitted while on something, recorded when it leaves. The trip is None for
+    // transit and freight.
+    VehicleEmissions(CarID, Option<TripID>, Traversable, Emissions),
     IntersectionDelayMeasured(CompressedMovementID, Duration, AgentID),
+    // How long an incident held somebody up
+    IncidentDelay(usize, AgentID, Duration),
 
     TripFinished {
         trip: TripID,
@@ -81,6 +102,10 @@ pub enum TripPhaseType {
     Finished,
     DelayedStart,
     Remote,
+    // Who's driving?
+    RidingAlong(PersonID),
+    WaitingForRide,
+    RidingFleetVehicle(CarID),
 }
 
 impl TripPhaseType {
@@ -98,6 +123,9 @@ impl TripPhaseType {
             TripPhaseType::Finished => "trip finished".to_string(),
             TripPhaseType::DelayedStart => "delayed by previous trip taking too long".to_string(),
             TripPhaseType::Remote => "remote trip outside the map boundaries".to_string(),
+            TripPhaseType::RidingAlong(p) => format!("riding along with {}", p),
+            TripPhaseType::WaitingForRide => "waiting for a ride-hailing vehicle".to_string(),
+            TripPhaseType::RidingFleetVehicle(c) => format!("riding in {}", c),
         }
     }
 }
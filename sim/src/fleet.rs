@@ -0,0 +1,430 @@
+use crate::sim::Ctx;
+use crate::{
+    AlertLocation, CarID, Command, CreateCar, Event, PersonID, Router, Scheduler, TripID,
+    TripManager, TripPhaseType, Vehicle, VehicleSpec, VehicleType, MAX_CAR_LENGTH,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Duration, Time};
+use map_model::{BuildingID, Map, Path, PathConstraints, PathRequest, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+// How long a ride-hailing vehicle idles at the curb for somebody to get in or out
+const TIME_TO_BOARD: Duration = Duration::const_seconds(30.0);
+
+#[derive(Serialize, Deserialize, Clone)]
+struct RideRequest {
+    trip: TripID,
+    person: PersonID,
+    requested_at: Time,
+    pickup: (BuildingID, Position),
+    dropoff: (BuildingID, Position),
+    // Calculated upfront, so we know the ride is possible before a vehicle is sent
+    path_to_dropoff: Path,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FleetVehicle {
+    vehicle: Vehicle,
+    // Where the vehicle waits off-map when it's not needed. When repositioning fails, the vehicle
+    // warps back here.
+    depot: Position,
+    state: FleetVehicleState,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum FleetVehicleState {
+    // Not on the map. Waiting at this depot.
+    Idle(Position),
+    DrivingToPickup(RideRequest),
+    Boarding(RideRequest),
+    DrivingToDropoff(RideRequest),
+    Alighting(Position),
+    // Driving empty to a depot, then becoming Idle there
+    Repositioning(Position),
+}
+
+// Manages a fleet of on-demand vehicles, like taxis or ride-hailing. Like TransitSimState, this
+// acts a bit like TripManager for the vehicles, which don't belong to anybody.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FleetSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, FleetVehicle>,
+    // Requests that no vehicle has been sent for yet, in the order they were made
+    queue: VecDeque<RideRequest>,
+    depots: Vec<Position>,
+
+    events: Vec<Event>,
+}
+
+impl FleetSimState {
+    pub fn new() -> FleetSimState {
+        FleetSimState {
+            vehicles: BTreeMap::new(),
+            queue: VecDeque::new(),
+            depots: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    // Spread the vehicles evenly over the driving lanes. The caller allocates the IDs.
+    pub fn create_vehicles(&mut self, ids: Vec<usize>, map: &Map) {
+        let lanes: Vec<_> = map
+            .all_lanes()
+            .iter()
+            .filter(|l| {
+                PathConstraints::Car.can_use(l, map)
+                    && l.length() > MAX_CAR_LENGTH * 2.0
+                    && map.get_parent(l.id).get_zone(map).is_none()
+            })
+            .map(|l| l.id)
+            .collect();
+        if lanes.is_empty() {
+            return;
+        }
+        let num = ids.len();
+        for (idx, id) in ids.into_iter().enumerate() {
+            // Vehicles appear with their back at the start of the lane
+            let depot = Position::new(lanes[idx * lanes.len() / num], MAX_CAR_LENGTH);
+            let vehicle = VehicleSpec::new(VehicleType::Car, MAX_CAR_LENGTH, None)
+                .make(CarID(id, VehicleType::Car), None);
+            self.depots.push(depot);
+            self.vehicles.insert(
+                vehicle.id,
+                FleetVehicle {
+                    vehicle,
+                    depot,
+                    state: FleetVehicleState::Idle(depot),
+                },
+            );
+        }
+        self.depots.sort();
+        self.depots.dedup();
+    }
+
+    pub fn request_ride(
+        &mut self,
+        now: Time,
+        trip: TripID,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) {
+        let (person, pickup, dropoff) = trips.ride_hail_request(trip);
+        let maybe_req = curbside(pickup, ctx.map).and_then(|start| {
+            let end = curbside(dropoff, ctx.map)?;
+            let path = fleet_path(start, end, ctx.map)?;
+            Some(RideRequest {
+                trip,
+                person,
+                requested_at: now,
+                pickup: (pickup, start),
+                dropoff: (dropoff, end),
+                path_to_dropoff: path,
+            })
+        });
+        if self.vehicles.is_empty() || maybe_req.is_none() {
+            self.events.push(Event::Alert(
+                AlertLocation::Person(person),
+                format!(
+                    "{} can't hail a ride from {} to {}, aborting {}",
+                    person, pickup, dropoff, trip
+                ),
+            ));
+            trips.abort_trip(now, trip, None, ctx);
+            return;
+        }
+        self.queue.push_back(maybe_req.unwrap());
+        self.dispatch(now, ctx.map, ctx.scheduler);
+    }
+
+    // Send idle vehicles to waiting requests, first-come first-serve. The closest idle vehicle
+    // that can actually reach the pickup is sent.
+    fn dispatch(&mut self, now: Time, map: &Map, scheduler: &mut Scheduler) {
+        while let Some(req) = self.queue.front() {
+            let mut idle: Vec<(CarID, Position)> = self
+                .vehicles
+                .values()
+                .filter_map(|v| match v.state {
+                    FleetVehicleState::Idle(pos) => Some((v.vehicle.id, pos)),
+                    _ => None,
+                })
+                .collect();
+            let goal = req.pickup.1.pt(map);
+            idle.sort_by_key(|(id, pos)| (pos.pt(map).dist_to(goal), *id));
+
+            let mut found = None;
+            for (id, pos) in idle {
+                if let Some(path) = fleet_path(pos, req.pickup.1, map) {
+                    found = Some((id, pos, path));
+                    break;
+                }
+            }
+            let (id, start, path) = if let Some(x) = found {
+                x
+            } else {
+                // Wait for a vehicle to free up
+                return;
+            };
+
+            let req = self.queue.pop_front().unwrap();
+            let end = req.pickup.1;
+            self.events
+                .push(Event::FleetVehicleDeadheading(id, path.total_length()));
+            let v = self.vehicles.get_mut(&id).unwrap();
+            v.state = FleetVehicleState::DrivingToPickup(req);
+            scheduler.push(
+                now,
+                Command::SpawnCar(
+                    CreateCar {
+                        vehicle: v.vehicle.clone(),
+                        router: Router::fleet_stop(id, path, end.dist_along()),
+                        req: PathRequest {
+                            start,
+                            end,
+                            constraints: PathConstraints::Car,
+                        },
+                        start_dist: start.dist_along(),
+                        maybe_parked_car: None,
+                        trip_and_person: None,
+                        maybe_route: None,
+                    },
+                    true,
+                ),
+            );
+        }
+    }
+
+    // If Some, the vehicle idles at the curb for that long. If None, the vehicle reached a depot
+    // and should vanish.
+    pub fn vehicle_arrived(
+        &mut self,
+        now: Time,
+        id: CarID,
+        trips: &mut TripManager,
+        ctx: &mut Ctx,
+    ) -> Option<Duration> {
+        let v = self.vehicles.get_mut(&id).unwrap();
+        match v.state.clone() {
+            FleetVehicleState::DrivingToPickup(req) => {
+                trips.ride_hail_pickup(req.trip, id);
+                self.events.push(Event::RideHailPickup(
+                    req.trip,
+                    req.person,
+                    id,
+                    now - req.requested_at,
+                ));
+                self.events.push(Event::TripPhaseStarting(
+                    req.trip,
+                    req.person,
+                    Some(PathRequest {
+                        start: req.pickup.1,
+                        end: req.dropoff.1,
+                        constraints: PathConstraints::Car,
+                    }),
+                    TripPhaseType::RidingFleetVehicle(id),
+                ));
+                v.state = FleetVehicleState::Boarding(req);
+                Some(TIME_TO_BOARD)
+            }
+            FleetVehicleState::DrivingToDropoff(req) => {
+                self.events
+                    .push(Event::RideHailDropoff(req.trip, req.person, id));
+                v.state = FleetVehicleState::Alighting(req.dropoff.1);
+                trips.ride_hail_dropoff(now, req.trip, req.dropoff.0, id, ctx);
+                Some(TIME_TO_BOARD)
+            }
+            FleetVehicleState::Repositioning(depot) => {
+                v.state = FleetVehicleState::Idle(depot);
+                // Somebody might've been waiting for this vehicle to free up. The car won't be
+                // deleted until after this, but spawning happens later.
+                self.dispatch(now, ctx.map, ctx.scheduler);
+                None
+            }
+            FleetVehicleState::Idle(_)
+            | FleetVehicleState::Boarding(_)
+            | FleetVehicleState::Alighting(_) => unreachable!(),
+        }
+    }
+
+    pub fn vehicle_departed(&mut self, id: CarID, map: &Map) -> Router {
+        let v = self.vehicles.get_mut(&id).unwrap();
+        match v.state.clone() {
+            FleetVehicleState::Boarding(req) => {
+                let end_dist = req.dropoff.1.dist_along();
+                let path = req.path_to_dropoff.clone();
+                v.state = FleetVehicleState::DrivingToDropoff(req);
+                Router::fleet_stop(id, path, end_dist)
+            }
+            FleetVehicleState::Alighting(pos) => {
+                // Head straight to the oldest waiting request, if we can reach it
+                if let Some(req) = self.queue.front() {
+                    if let Some(path) = fleet_path(pos, req.pickup.1, map) {
+                        let req = self.queue.pop_front().unwrap();
+                        let end_dist = req.pickup.1.dist_along();
+                        self.events
+                            .push(Event::FleetVehicleDeadheading(id, path.total_length()));
+                        v.state = FleetVehicleState::DrivingToPickup(req);
+                        return Router::fleet_stop(id, path, end_dist);
+                    }
+                }
+
+                // Otherwise return to the closest reachable depot
+                let here = pos.pt(map);
+                let mut depots = self.depots.clone();
+                depots.sort_by_key(|d| d.pt(map).dist_to(here));
+                for depot in depots {
+                    if let Some(path) = fleet_path(pos, depot, map) {
+                        self.events
+                            .push(Event::FleetVehicleDeadheading(id, path.total_length()));
+                        v.state = FleetVehicleState::Repositioning(depot);
+                        return Router::fleet_stop(id, path, depot.dist_along());
+                    }
+                }
+
+                // Stuck somewhere weird. Just drive off the end of this lane and warp home.
+                let lane = map.get_l(pos.lane());
+                v.state = FleetVehicleState::Repositioning(v.depot);
+                Router::fleet_stop(id, Path::one_step(lane.id, map), lane.length())
+            }
+            FleetVehicleState::Idle(_)
+            | FleetVehicleState::DrivingToPickup(_)
+            | FleetVehicleState::DrivingToDropoff(_)
+            | FleetVehicleState::Repositioning(_) => unreachable!(),
+        }
+    }
+
+    // (vehicles idling at a depot, total vehicles, requests waiting for a vehicle)
+    pub fn get_status(&self) -> (usize, usize, usize) {
+        let idle = self
+            .vehicles
+            .values()
+            .filter(|v| match v.state {
+                FleetVehicleState::Idle(_) => true,
+                _ => false,
+            })
+            .count();
+        (idle, self.vehicles.len(), self.queue.len())
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+}
+
+// Where vehicles stop to pick up or drop off somebody at a building
+fn curbside(b: BuildingID, map: &Map) -> Option<Position> {
+    map.get_b(b).driving_connection(map).map(|(pos, _)| pos)
+}
+
+// A vehicle can't reach something behind it on the same lane by a one-step path.
+fn fleet_path(start: Position, end: Position, map: &Map) -> Option<Path> {
+    if start.lane() == end.lane() && start.dist_along() >= end.dist_along() {
+        return None;
+    }
+    map.pathfind(PathRequest {
+        start,
+        end,
+        constraints: PathConstraints::Car,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AgentID, AgentType, IndividTrip, PersonSpec, PersonState, Scenario, Sim, SimOptions,
+        SpawnTrip, TripResult,
+    };
+    use abstutil::Timer;
+    use map_model::raw::RawMap;
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    // Everybody hails a ride between opposite corners of a small grid at the same time
+    fn setup(fleet_size: usize, num_people: usize) -> (Map, Sim, BuildingID) {
+        let mut timer = Timer::throwaway();
+        let map = Map::create_from_raw(RawMap::synthetic_grid(3, 3), false, &mut timer);
+        let mut opts = SimOptions::new("test");
+        opts.fleet_size = fleet_size;
+        let mut sim = Sim::new(&map, opts, &mut timer);
+
+        let from = BuildingID(0);
+        let to = BuildingID(map.all_buildings().len() - 1);
+        let mut scenario = Scenario::empty(&map, "ride_hail");
+        for idx in 0..num_people {
+            scenario.people.push(PersonSpec {
+                id: PersonID(idx),
+                orig_id: None,
+                trips: vec![IndividTrip::new(
+                    Time::START_OF_DAY + Duration::seconds(1.0),
+                    SpawnTrip::UsingRideHail(from, to),
+                )],
+            });
+        }
+        scenario.instantiate(
+            &mut sim,
+            &map,
+            &mut XorShiftRng::seed_from_u64(42),
+            &mut timer,
+        );
+        (map, sim, to)
+    }
+
+    fn step(sim: &mut Sim, map: &Map) {
+        sim.timed_step(
+            map,
+            Duration::seconds(5.0),
+            &mut None,
+            &mut Timer::throwaway(),
+        );
+    }
+
+    #[test]
+    fn test_dispatch_and_pickup() {
+        let (map, mut sim, dropoff) = setup(1, 1);
+        let trip = TripID(0);
+
+        let mut rode = false;
+        while !sim.is_done() && sim.time() < Time::START_OF_DAY + Duration::hours(1) {
+            step(&mut sim, &map);
+            if let TripResult::Ok(agent) = sim.trip_to_agent(trip) {
+                match agent {
+                    AgentID::RideHailPassenger(person, _) => {
+                        assert_eq!(person, PersonID(0));
+                        assert_eq!(sim.num_agents().get(AgentType::RideHailPassenger), 1);
+                        rode = true;
+                    }
+                    x => panic!("{} should be riding in a ride-hail, not {}", trip, x),
+                }
+            }
+        }
+
+        assert!(rode);
+        assert_eq!(sim.num_trips(), (1, 0));
+        assert_eq!(
+            sim.get_person(PersonID(0)).state,
+            PersonState::Inside(dropoff)
+        );
+        assert_eq!(sim.get_analytics().ride_hail_waits.len(), 1);
+    }
+
+    #[test]
+    fn test_wait_for_busy_vehicle() {
+        let (map, mut sim, _) = setup(1, 2);
+
+        // The only vehicle was sent to the first request; the second has to wait for it
+        step(&mut sim, &map);
+        assert_eq!(sim.get_fleet_status(), (0, 1, 1));
+
+        while !sim.is_done() && sim.time() < Time::START_OF_DAY + Duration::hours(1) {
+            step(&mut sim, &map);
+        }
+        assert_eq!(sim.num_trips(), (2, 0));
+        let waits = &sim.get_analytics().ride_hail_waits;
+        assert_eq!(waits.len(), 2);
+        assert!(waits[1].2 > waits[0].2);
+    }
+}
@@ -0,0 +1,437 @@
+use crate::sim::Ctx;
+use crate::{
+    AlertLocation, CarID, Command, CreateCar, DeliveryTour, Event, ParkedCar, ParkingSpot, Router,
+    Scheduler, TripEndpoint, Vehicle, SPAWN_DIST,
+};
+use abstutil::{deserialize_btreemap, serialize_btreemap};
+use geom::{Distance, Duration, Time};
+use map_model::{BuildingID, Map, Path, PathRequest, Position};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Don't use a loading zone too far from the building being delivered to
+const MAX_LOADING_ZONE_DIST: Distance = Distance::const_meters(50.0);
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Delivery {
+    vehicle: Vehicle,
+    tour: DeliveryTour,
+    // Index into the tour's stops. Once this is past the last stop, the vehicle is leaving.
+    next_stop: usize,
+    state: DeliveryState,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+enum DeliveryState {
+    NotStarted,
+    // Heading to the next stop, maybe with a loading zone reserved there
+    Driving(Position, Option<ParkingSpot>),
+    // Blocking a travel lane at this position
+    DoubleParked(Position),
+    // Off the road in a loading zone
+    Unloading(ParkingSpot),
+    Leaving,
+    Done,
+}
+
+// Manages delivery vans and trucks. Like FleetSimState, the vehicles don't belong to anybody.
+// Each one follows a fixed tour, stopping at a loading zone (a free parking spot near the
+// building) when it can, or double-parking and blocking the lane when it can't. Trucks are too
+// long for parking spots, so they always double-park.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FreightSimState {
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    vehicles: BTreeMap<CarID, Delivery>,
+
+    events: Vec<Event>,
+}
+
+impl FreightSimState {
+    pub fn new() -> FreightSimState {
+        FreightSimState {
+            vehicles: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    pub fn add_tour(&mut self, vehicle: Vehicle, tour: DeliveryTour, scheduler: &mut Scheduler) {
+        assert!(vehicle.vehicle_type.is_freight());
+        scheduler.push(tour.depart, Command::UpdateDelivery(vehicle.id));
+        self.vehicles.insert(
+            vehicle.id,
+            Delivery {
+                vehicle,
+                tour,
+                next_stop: 0,
+                state: DeliveryState::NotStarted,
+            },
+        );
+    }
+
+    // Either starts the tour or pulls out of a loading zone
+    pub fn update(&mut self, now: Time, id: CarID, ctx: &mut Ctx) {
+        let state = self.vehicles[&id].state.clone();
+        match state {
+            DeliveryState::NotStarted => {
+                let start = if let Some(pos) = self.start_pos(id, ctx.map) {
+                    pos
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Nil,
+                        format!("{} has nowhere to start its delivery tour", id),
+                    ));
+                    self.vehicles.get_mut(&id).unwrap().state = DeliveryState::Done;
+                    return;
+                };
+                let (router, req) = if let Some(x) = self.route_from(id, start, ctx) {
+                    x
+                } else {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Nil,
+                        format!("{} can't reach any stop on its delivery tour", id),
+                    ));
+                    self.vehicles.get_mut(&id).unwrap().state = DeliveryState::Done;
+                    return;
+                };
+                self.spawn(now, id, router, req, None, ctx);
+            }
+            DeliveryState::Unloading(spot) => {
+                let vehicle = self.vehicles[&id].vehicle.clone();
+                let start = ctx.parking.spot_to_driving_pos(spot, &vehicle, ctx.map);
+                let (router, req) = self.route_from_or_vanish(id, start, ctx);
+                // TODO Live map edits could remove the parking lane out from under us
+                let parked_car = ctx.parking.lookup_parked_car(id).cloned().unwrap();
+                self.spawn(now, id, router, req, Some(parked_car), ctx);
+            }
+            DeliveryState::Driving(_, _)
+            | DeliveryState::DoubleParked(_)
+            | DeliveryState::Leaving
+            | DeliveryState::Done => unreachable!(),
+        }
+    }
+
+    // If Some, the vehicle double-parks for that long. If None, the vehicle vanishes from the
+    // road, either into a loading zone or because the tour is over.
+    pub fn vehicle_arrived(&mut self, now: Time, id: CarID, ctx: &mut Ctx) -> Option<Duration> {
+        let delivery = self.vehicles.get_mut(&id).unwrap();
+        match delivery.state.clone() {
+            DeliveryState::Driving(pos, maybe_spot) => {
+                let b = delivery.tour.stops[delivery.next_stop];
+                delivery.next_stop += 1;
+                let dwell = delivery.tour.dwell_time;
+                self.events
+                    .push(Event::DeliveryMade(id, b, maybe_spot.is_none()));
+                if let Some(spot) = maybe_spot {
+                    ctx.parking.add_parked_car(
+                        ParkedCar {
+                            vehicle: delivery.vehicle.clone(),
+                            spot,
+                        },
+                        now,
+                        None,
+                    );
+                    delivery.state = DeliveryState::Unloading(spot);
+                    ctx.scheduler.push(now + dwell, Command::UpdateDelivery(id));
+                    None
+                } else {
+                    delivery.state = DeliveryState::DoubleParked(pos);
+                    Some(dwell)
+                }
+            }
+            DeliveryState::Leaving => {
+                delivery.state = DeliveryState::Done;
+                None
+            }
+            DeliveryState::NotStarted
+            | DeliveryState::DoubleParked(_)
+            | DeliveryState::Unloading(_)
+            | DeliveryState::Done => unreachable!(),
+        }
+    }
+
+    pub fn vehicle_departed(&mut self, id: CarID, ctx: &mut Ctx) -> Router {
+        let pos = match self.vehicles[&id].state {
+            DeliveryState::DoubleParked(pos) => pos,
+            _ => unreachable!(),
+        };
+        self.route_from_or_vanish(id, pos, ctx).0
+    }
+
+    // (vehicles on a tour right now, all delivery vehicles)
+    pub fn get_status(&self) -> (usize, usize) {
+        let active = self
+            .vehicles
+            .values()
+            .filter(|d| match d.state {
+                DeliveryState::NotStarted | DeliveryState::Done => false,
+                _ => true,
+            })
+            .count();
+        (active, self.vehicles.len())
+    }
+
+    pub fn collect_events(&mut self) -> Vec<Event> {
+        std::mem::replace(&mut self.events, Vec::new())
+    }
+
+    fn spawn(
+        &self,
+        now: Time,
+        id: CarID,
+        router: Router,
+        req: PathRequest,
+        maybe_parked_car: Option<ParkedCar>,
+        ctx: &mut Ctx,
+    ) {
+        ctx.scheduler.push(
+            now,
+            Command::SpawnCar(
+                CreateCar {
+                    vehicle: self.vehicles[&id].vehicle.clone(),
+                    router,
+                    start_dist: req.start.dist_along(),
+                    req,
+                    maybe_parked_car,
+                    trip_and_person: None,
+                    maybe_route: None,
+                },
+                true,
+            ),
+        );
+    }
+
+    fn start_pos(&self, id: CarID, map: &Map) -> Option<Position> {
+        let delivery = &self.vehicles[&id];
+        match delivery.tour.start {
+            TripEndpoint::Bldg(b) => curbside(b, &delivery.vehicle, map),
+            TripEndpoint::Border(i, _) => {
+                let dr = map.get_i(i).some_outgoing_road(map)?;
+                let lane = *dr
+                    .lanes(delivery.vehicle.vehicle_type.to_constraints(), map)
+                    .get(0)?;
+                Some(Position::new(lane, SPAWN_DIST))
+            }
+        }
+    }
+
+    // Heads to the next reachable stop, skipping any that can't be reached, then to the end of the
+    // tour. None if nothing is reachable.
+    fn route_from(
+        &mut self,
+        id: CarID,
+        start: Position,
+        ctx: &mut Ctx,
+    ) -> Option<(Router, PathRequest)> {
+        let delivery = self.vehicles.get_mut(&id).unwrap();
+        let constraints = delivery.vehicle.vehicle_type.to_constraints();
+        while delivery.next_stop < delivery.tour.stops.len() {
+            let b = delivery.tour.stops[delivery.next_stop];
+            let maybe_spot = find_loading_zone(b, &delivery.vehicle, ctx);
+            let maybe_end = if let Some(spot) = maybe_spot {
+                Some(
+                    ctx.parking
+                        .spot_to_driving_pos(spot, &delivery.vehicle, ctx.map),
+                )
+            } else {
+                curbside(b, &delivery.vehicle, ctx.map)
+            };
+            if let Some(end) = maybe_end {
+                let req = PathRequest {
+                    start,
+                    end,
+                    constraints,
+                };
+                if let Some(path) = freight_path(req.clone(), ctx.map) {
+                    if let Some(spot) = maybe_spot {
+                        ctx.parking.reserve_spot(spot);
+                    }
+                    delivery.state = DeliveryState::Driving(end, maybe_spot);
+                    return Some((Router::fleet_stop(id, path, end.dist_along()), req));
+                }
+            }
+            self.events.push(Event::Alert(
+                AlertLocation::Building(b),
+                format!("{} can't deliver to {}, skipping it", id, b),
+            ));
+            delivery.next_stop += 1;
+        }
+
+        delivery.state = DeliveryState::Leaving;
+        match delivery.tour.end {
+            TripEndpoint::Bldg(b) => {
+                let end = curbside(b, &delivery.vehicle, ctx.map)?;
+                let req = PathRequest {
+                    start,
+                    end,
+                    constraints,
+                };
+                let path = freight_path(req.clone(), ctx.map)?;
+                Some((Router::fleet_stop(id, path, end.dist_along()), req))
+            }
+            TripEndpoint::Border(i, _) => {
+                let dr = ctx.map.get_i(i).some_incoming_road(ctx.map)?;
+                let lane = *dr.lanes(constraints, ctx.map).get(0)?;
+                let req = PathRequest {
+                    start,
+                    end: Position::end(lane, ctx.map),
+                    constraints,
+                };
+                let path = freight_path(req.clone(), ctx.map)?;
+                Some((
+                    Router::end_at_border(id, path, ctx.map.get_l(lane).length(), i),
+                    req,
+                ))
+            }
+        }
+    }
+
+    // Vehicles already in the middle of a tour have to go somewhere. If nothing is reachable, just
+    // drive off the end of the current lane and vanish.
+    fn route_from_or_vanish(
+        &mut self,
+        id: CarID,
+        start: Position,
+        ctx: &mut Ctx,
+    ) -> (Router, PathRequest) {
+        if let Some(x) = self.route_from(id, start, ctx) {
+            return x;
+        }
+        self.events.push(Event::Alert(
+            AlertLocation::Nil,
+            format!("{} can't reach the end of its delivery tour", id),
+        ));
+        let delivery = self.vehicles.get_mut(&id).unwrap();
+        delivery.state = DeliveryState::Leaving;
+        let lane = ctx.map.get_l(start.lane());
+        (
+            Router::fleet_stop(id, Path::one_step(lane.id, ctx.map), lane.length()),
+            PathRequest {
+                start,
+                end: Position::end(lane.id, ctx.map),
+                constraints: delivery.vehicle.vehicle_type.to_constraints(),
+            },
+        )
+    }
+}
+
+// Where a vehicle double-parks to deliver to a building. The whole vehicle has to fit on the lane.
+fn curbside(b: BuildingID, vehicle: &Vehicle, map: &Map) -> Option<Position> {
+    let pos = map.get_b(b).driving_connection(map)?.0;
+    let lane = map.get_l(pos.lane());
+    if lane.length() <= vehicle.length {
+        return None;
+    }
+    Some(Position::new(
+        lane.id,
+        pos.dist_along().max(vehicle.length).min(lane.length()),
+    ))
+}
+
+// A free parking spot along the same driving lane, close to the building
+fn find_loading_zone(b: BuildingID, vehicle: &Vehicle, ctx: &Ctx) -> Option<ParkingSpot> {
+    if vehicle.length >= map_model::PARKING_SPOT_LENGTH {
+        return None;
+    }
+    let curb = curbside(b, vehicle, ctx.map)?;
+    let mut best: Option<(ParkingSpot, Distance)> = None;
+    for l in ctx.map.get_parent(curb.lane()).all_lanes() {
+        if !ctx.map.get_l(l).is_parking() {
+            continue;
+        }
+        for spot in ctx.parking.get_free_onstreet_spots(l) {
+            let pos = ctx.parking.spot_to_driving_pos(spot, vehicle, ctx.map);
+            if pos.lane() != curb.lane() {
+                continue;
+            }
+            let dist = if pos.dist_along() > curb.dist_along() {
+                pos.dist_along() - curb.dist_along()
+            } else {
+                curb.dist_along() - pos.dist_along()
+            };
+            if dist <= MAX_LOADING_ZONE_DIST && best.map(|(_, d)| dist < d).unwrap_or(true) {
+                best = Some((spot, dist));
+            }
+        }
+    }
+    best.map(|(spot, _)| spot)
+}
+
+// A vehicle can't reach something behind it on the same lane by a one-step path.
+fn freight_path(req: PathRequest, map: &Map) -> Option<Path> {
+    if req.start.lane() == req.end.lane() && req.start.dist_along() >= req.end.dist_along() {
+        return None;
+    }
+    map.pathfind(req)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GetDrawAgents, Scenario, Sim, SimOptions, VehicleType};
+    use abstutil::Timer;
+    use map_model::raw::RawMap;
+    use map_model::{osm, Traversable};
+    use rand::SeedableRng;
+    use rand_xorshift::XorShiftRng;
+
+    #[test]
+    fn test_tour_visits_stops_in_order() {
+        let mut timer = Timer::throwaway();
+        let map = Map::create_from_raw(RawMap::synthetic_grid(3, 3), true, &mut timer);
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut timer);
+        // Synthetic buildings sit next to the middle of each block
+        let bldg = |way: i64| {
+            map.all_buildings()
+                .iter()
+                .find(|b| b.orig_id == osm::OsmID::Way(osm::WayID(way)))
+                .unwrap()
+                .id
+        };
+        let depot = bldg(1001);
+        // Not the nearest-first order, which the tour should keep anyway
+        let stops = vec![bldg(1012), bldg(1003), bldg(1008)];
+
+        let mut scenario = Scenario::empty(&map, "deliveries");
+        scenario.deliveries.push(DeliveryTour {
+            depart: Time::START_OF_DAY + Duration::seconds(1.0),
+            vehicle_type: VehicleType::Van,
+            start: TripEndpoint::Bldg(depot),
+            stops: stops.clone(),
+            end: TripEndpoint::Bldg(depot),
+            dwell_time: Duration::minutes(1),
+        });
+        scenario.instantiate(
+            &mut sim,
+            &map,
+            &mut XorShiftRng::seed_from_u64(42),
+            &mut timer,
+        );
+
+        // Watch where the van is until the tour is over
+        let van = CarID(0, VehicleType::Van);
+        let mut last_seen = None;
+        while sim.time() < Time::START_OF_DAY + Duration::hours(2) {
+            sim.timed_step(&map, Duration::seconds(1.0), &mut None, &mut timer);
+            if let Some(car) = sim.get_draw_car(van, &map) {
+                last_seen = Some(car.on);
+            }
+            if last_seen.is_some() && sim.get_freight_status().0 == 0 {
+                break;
+            }
+        }
+        assert_eq!(sim.get_freight_status(), (0, 1));
+
+        let deliveries = &sim.get_analytics().deliveries;
+        assert!(deliveries.iter().all(|(_, car, _, _)| *car == van));
+        assert_eq!(
+            deliveries.iter().map(|(_, _, b, _)| *b).collect::<Vec<_>>(),
+            stops
+        );
+        // Back where it started
+        let depot_lane = map.get_b(depot).driving_connection(&map).unwrap().0.lane();
+        assert_eq!(last_seen, Some(Traversable::Lane(depot_lane)));
+    }
+}
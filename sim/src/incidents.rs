@@ -0,0 +1,179 @@
+use crate::{Command, Event, IntersectionSimState, Router, Scheduler};
+use geom::{Duration, Time};
+use map_model::{LaneID, Map, MovementID, TurnID, TurnType};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// A crash, a stalled vehicle, emergency roadwork -- something that blocks or slows down part of
+// the network for a while.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Incident {
+    pub start: Time,
+    pub duration: Duration,
+    pub location: IncidentLocation,
+    // What fraction of the usual flow still gets through, between 0 and 1. At 0, nothing gets
+    // through, and drivers heading that way look for another route.
+    pub capacity: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum IncidentLocation {
+    // Nobody can enter these lanes. Anybody already on them can leave.
+    Lanes(Vec<LaneID>),
+    Movement(MovementID),
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct IncidentReport {
+    // How many agents were held up getting past the incident, and how long they waited in total
+    pub agents_delayed: usize,
+    pub delay: Duration,
+    // Drivers who were already on their way when the incident started and found another route
+    pub rerouted: usize,
+    // And those with no other way to go, who have to wait it out
+    pub stuck: usize,
+}
+
+impl Incident {
+    pub fn describe(&self) -> String {
+        let what = if self.capacity <= 0.0 {
+            "closed".to_string()
+        } else {
+            format!("at {}% capacity", (self.capacity * 100.0).round())
+        };
+        format!(
+            "{} from {} to {}",
+            what,
+            self.start.ampm_tostring(),
+            (self.start + self.duration).ampm_tostring()
+        )
+    }
+
+    pub fn is_closure(&self) -> bool {
+        self.capacity <= 0.0
+    }
+
+    // Everything that leads into the incident
+    pub fn turns(&self, map: &Map) -> Vec<TurnID> {
+        match self.location {
+            IncidentLocation::Lanes(ref lanes) => lanes
+                .iter()
+                .flat_map(|l| map.get_turns_to_lane(*l))
+                .map(|t| t.id)
+                .collect(),
+            IncidentLocation::Movement(m) => map
+                .get_turns_in_intersection(m.parent)
+                .filter(|t| {
+                    (t.turn_type == TurnType::Crosswalk) == m.crosswalk
+                        && t.turn_type != TurnType::SharedSidewalkCorner
+                        && map.get_l(t.id.src).get_directed_parent(map) == m.from
+                        && map.get_l(t.id.dst).get_directed_parent(map) == m.to
+                })
+                .map(|t| t.id)
+                .collect(),
+        }
+    }
+}
+
+// Starts and ends incidents on schedule. IntersectionSimState does the actual blocking, since
+// everything has to ask it before starting a turn.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct IncidentSimState {
+    incidents: Vec<(Incident, IncidentReport)>,
+    active: BTreeSet<usize>,
+    // Turns closed by any active incident. Anybody setting out avoids these.
+    closed: BTreeSet<TurnID>,
+}
+
+impl IncidentSimState {
+    pub fn new() -> IncidentSimState {
+        IncidentSimState {
+            incidents: Vec::new(),
+            active: BTreeSet::new(),
+            closed: BTreeSet::new(),
+        }
+    }
+
+    // Incidents scheduled in the past start right away.
+    pub fn schedule(&mut self, incident: Incident, now: Time, scheduler: &mut Scheduler) -> usize {
+        let id = self.incidents.len();
+        scheduler.push(incident.start.max(now), Command::UpdateIncident(id));
+        self.incidents.push((incident, IncidentReport::default()));
+        id
+    }
+
+    // Starts or ends the incident. Returns true if it just closed something, so drivers headed
+    // that way need to reroute.
+    pub fn update(
+        &mut self,
+        id: usize,
+        now: Time,
+        intersections: &mut IntersectionSimState,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) -> bool {
+        let incident = &self.incidents[id].0;
+        let turns = incident.turns(map);
+        if self.active.insert(id) {
+            for t in turns {
+                intersections.restrict_turn(t, id, incident.capacity);
+            }
+            scheduler.push(now + incident.duration, Command::UpdateIncident(id));
+            let closure = incident.is_closure();
+            self.recalculate_closed(map);
+            closure
+        } else {
+            self.active.remove(&id);
+            for t in turns {
+                intersections.lift_restriction(t, id, now, scheduler, map);
+            }
+            self.recalculate_closed(map);
+            false
+        }
+    }
+
+    fn recalculate_closed(&mut self, map: &Map) {
+        self.closed.clear();
+        for id in &self.active {
+            let incident = &self.incidents[*id].0;
+            if incident.is_closure() {
+                self.closed.extend(incident.turns(map));
+            }
+        }
+    }
+
+    pub fn closed_turns(&self) -> &BTreeSet<TurnID> {
+        &self.closed
+    }
+
+    // If a vehicle is about to cross something closed, send it another way. Returns None if the
+    // path is fine, otherwise whether a way around was found.
+    pub fn avoid_closures(&self, router: &mut Router, map: &Map) -> Option<bool> {
+        if self.closed.is_empty() || !router.crosses_any(&self.closed) {
+            return None;
+        }
+        Some(router.reroute(&self.closed, map))
+    }
+
+    pub fn record_reroutes(&mut self, id: usize, rerouted: usize, stuck: usize) {
+        let report = &mut self.incidents[id].1;
+        report.rerouted += rerouted;
+        report.stuck += stuck;
+    }
+
+    pub fn handle_event(&mut self, ev: &Event) {
+        if let Event::IncidentDelay(id, _, dt) = ev {
+            let report = &mut self.incidents[*id].1;
+            report.agents_delayed += 1;
+            report.delay += *dt;
+        }
+    }
+
+    pub fn get_all(&self) -> &Vec<(Incident, IncidentReport)> {
+        &self.incidents
+    }
+
+    pub fn is_active(&self, id: usize) -> bool {
+        self.active.contains(&id)
+    }
+}
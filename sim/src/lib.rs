@@ -1,39 +1,57 @@
 mod analytics;
 mod cap;
+mod charging;
+mod emissions;
 mod events;
+mod fleet;
+mod freight;
+mod incidents;
 mod make;
 mod mechanics;
 mod pandemic;
 mod render;
+mod replay;
 mod router;
 mod scheduler;
 mod sim;
 mod transit;
 mod trips;
+mod weather;
 
 #[macro_use]
 extern crate log;
 
 pub use self::analytics::{Analytics, TripPhase};
 pub(crate) use self::cap::CapSimState;
+pub(crate) use self::charging::ChargingSimState;
+pub use self::charging::{Battery, ChargingStation};
+pub use self::emissions::Emissions;
 pub(crate) use self::events::Event;
 pub use self::events::{AlertLocation, TripPhaseType};
+pub(crate) use self::fleet::FleetSimState;
+pub(crate) use self::freight::FreightSimState;
+pub(crate) use self::incidents::IncidentSimState;
+pub use self::incidents::{Incident, IncidentLocation, IncidentReport};
 pub use self::make::{
-    BorderSpawnOverTime, ExternalPerson, ExternalTrip, IndividTrip, OffMapLocation,
-    OriginDestination, PersonSpec, Scenario, ScenarioGenerator, ScenarioModifier, SimFlags,
-    SpawnOverTime, SpawnTrip, TripSpawner, TripSpec,
+    BorderSpawnOverTime, DeliveryTour, DepartureProfile, ExternalPerson, ExternalTrip,
+    HouseholdSpec, IndividTrip, JointTrip, OffMapLocation, OriginDestination, PersonSpec, Scenario,
+    ScenarioDiff, ScenarioFilter, ScenarioGenerator, ScenarioModifier, SimFlags, SpawnOverTime,
+    SpawnTrip, TripChange, TripSpawner, TripSpec,
 };
 pub(crate) use self::mechanics::{
     DrivingSimState, IntersectionSimState, ParkingSimState, WalkingSimState,
 };
+pub use self::mechanics::{ParkingSearch, ParkingStrategy};
 pub(crate) use self::pandemic::PandemicModel;
+pub use self::replay::{ReplayCommand, ReplayEntry, ReplayLog};
 pub(crate) use self::router::{ActionAtEnd, Router};
-pub(crate) use self::scheduler::{Command, Scheduler};
+pub(crate) use self::scheduler::{Command, CommandType, Scheduler};
 pub use self::sim::{AgentProperties, AlertHandler, Sim, SimCallback, SimOptions};
 pub(crate) use self::transit::TransitSimState;
 pub use self::trips::{Person, PersonState, TripInfo, TripResult};
 pub use self::trips::{TripEndpoint, TripMode};
 pub(crate) use self::trips::{TripLeg, TripManager};
+pub use self::weather::{Precipitation, Weather};
 pub use crate::render::{
     CarStatus, DontDrawAgents, DrawCarInput, DrawPedCrowdInput, DrawPedestrianInput, GetDrawAgents,
     PedCrowdLocation, UnzoomedAgent,
@@ -41,8 +59,8 @@ pub use crate::render::{
 use abstutil::{deserialize_usize, serialize_usize};
 use geom::{Distance, Pt2D, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, DirectedRoadID, IntersectionID, LaneID, Map, ParkingLotID,
-    Path, PathConstraints, PathRequest, Position,
+    BuildingID, BusRouteID, BusStopID, DirectedRoadID, IntersectionID, LaneID, Map,
+    MidBlockCrossing, ParkingLotID, Path, PathConstraints, PathRequest, Position,
 };
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -55,6 +73,10 @@ pub const MAX_CAR_LENGTH: Distance = Distance::const_meters(6.5);
 // Note this is more than MAX_CAR_LENGTH
 pub const BUS_LENGTH: Distance = Distance::const_meters(12.5);
 pub const LIGHT_RAIL_LENGTH: Distance = Distance::const_meters(60.0);
+// Short enough to fit in a parking spot, so vans can use loading zones
+pub const DELIVERY_VAN_LENGTH: Distance = Distance::const_meters(7.0);
+// Box trucks are too long for any parking spot, so they always double-park
+pub const TRUCK_LENGTH: Distance = Distance::const_meters(15.0);
 
 // At all speeds (including at rest), cars must be at least this far apart, measured from front of
 // one car to the back of the other.
@@ -84,6 +106,8 @@ impl fmt::Display for CarID {
             VehicleType::Bus => write!(f, "Bus #{}", self.0),
             VehicleType::Train => write!(f, "Train #{}", self.0),
             VehicleType::Bike => write!(f, "Bike #{}", self.0),
+            VehicleType::Van => write!(f, "Van #{}", self.0),
+            VehicleType::Truck => write!(f, "Truck #{}", self.0),
         }
     }
 }
@@ -109,6 +133,7 @@ pub enum AgentID {
     Pedestrian(PedestrianID),
     // TODO Rename...
     BusPassenger(PersonID, CarID),
+    RideHailPassenger(PersonID, CarID),
 }
 
 impl AgentID {
@@ -126,9 +151,12 @@ impl AgentID {
                 VehicleType::Bike => AgentType::Bike,
                 VehicleType::Bus => AgentType::Bus,
                 VehicleType::Train => AgentType::Train,
+                // TODO Maybe freight deserves its own type
+                VehicleType::Van | VehicleType::Truck => AgentType::Car,
             },
             AgentID::Pedestrian(_) => AgentType::Pedestrian,
             AgentID::BusPassenger(_, _) => AgentType::TransitRider,
+            AgentID::RideHailPassenger(_, _) => AgentType::RideHailPassenger,
         }
     }
 }
@@ -139,6 +167,9 @@ impl fmt::Display for AgentID {
             AgentID::Car(id) => write!(f, "AgentID({})", id),
             AgentID::Pedestrian(id) => write!(f, "AgentID({})", id),
             AgentID::BusPassenger(person, bus) => write!(f, "AgentID({} on {})", person, bus),
+            AgentID::RideHailPassenger(person, car) => {
+                write!(f, "AgentID({} in {})", person, car)
+            }
         }
     }
 }
@@ -151,6 +182,7 @@ pub enum AgentType {
     Train,
     Pedestrian,
     TransitRider,
+    RideHailPassenger,
 }
 
 impl AgentType {
@@ -162,6 +194,7 @@ impl AgentType {
             AgentType::Train,
             AgentType::Pedestrian,
             AgentType::TransitRider,
+            AgentType::RideHailPassenger,
         ]
     }
 
@@ -173,6 +206,7 @@ impl AgentType {
             AgentType::Train => "Train",
             AgentType::Pedestrian => "Pedestrian",
             AgentType::TransitRider => "Transit rider",
+            AgentType::RideHailPassenger => "Ride-hail passenger",
         }
     }
 
@@ -184,6 +218,7 @@ impl AgentType {
             AgentType::Train => "trains",
             AgentType::Pedestrian => "pedestrians",
             AgentType::TransitRider => "transit riders",
+            AgentType::RideHailPassenger => "ride-hail passengers",
         }
     }
 
@@ -194,6 +229,7 @@ impl AgentType {
             AgentType::Bus | AgentType::Train => unreachable!(),
             AgentType::Pedestrian => "walking",
             AgentType::TransitRider => "riding transit",
+            AgentType::RideHailPassenger => "riding in a ride-hail",
         }
     }
 }
@@ -248,6 +284,9 @@ pub enum VehicleType {
     Bus,
     Train,
     Bike,
+    // Delivery vehicles, not owned by anybody
+    Van,
+    Truck,
 }
 
 impl fmt::Display for VehicleType {
@@ -257,6 +296,8 @@ impl fmt::Display for VehicleType {
             VehicleType::Bus => write!(f, "bus"),
             VehicleType::Train => write!(f, "train"),
             VehicleType::Bike => write!(f, "bike"),
+            VehicleType::Van => write!(f, "van"),
+            VehicleType::Truck => write!(f, "truck"),
         }
     }
 }
@@ -268,6 +309,9 @@ impl VehicleType {
             VehicleType::Bus => PathConstraints::Bus,
             VehicleType::Train => PathConstraints::Train,
             VehicleType::Bike => PathConstraints::Bike,
+            // Vans aren't heavy enough to be affected by truck bans
+            VehicleType::Van => PathConstraints::Car,
+            VehicleType::Truck => PathConstraints::Truck,
         }
     }
 
@@ -277,6 +321,27 @@ impl VehicleType {
             VehicleType::Bus => true,
             VehicleType::Train => true,
             VehicleType::Bike => false,
+            VehicleType::Van => false,
+            VehicleType::Truck => false,
+        }
+    }
+
+    pub(crate) fn is_freight(self) -> bool {
+        match self {
+            VehicleType::Van | VehicleType::Truck => true,
+            VehicleType::Car | VehicleType::Bus | VehicleType::Train | VehicleType::Bike => false,
+        }
+    }
+
+    // Comfortable (acceleration, braking), in meters per second squared
+    pub fn default_accel_limits(self) -> (f64, f64) {
+        match self {
+            VehicleType::Car => (2.5, 4.5),
+            VehicleType::Bus => (1.2, 3.0),
+            VehicleType::Train => (1.0, 1.3),
+            VehicleType::Bike => (1.0, 3.0),
+            VehicleType::Van => (2.0, 4.0),
+            VehicleType::Truck => (1.0, 3.5),
         }
     }
 }
@@ -288,6 +353,11 @@ pub struct Vehicle {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    // In meters per second squared. Only used when SimOptions::kinematics is enabled.
+    pub max_accel: f64,
+    pub max_decel: f64,
+    // Only for electric vehicles
+    pub battery: Option<Battery>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -295,9 +365,29 @@ pub struct VehicleSpec {
     pub vehicle_type: VehicleType,
     pub length: Distance,
     pub max_speed: Option<Speed>,
+    // In meters per second squared. Only used when SimOptions::kinematics is enabled.
+    pub max_accel: f64,
+    pub max_decel: f64,
+    pub battery: Option<Battery>,
 }
 
 impl VehicleSpec {
+    pub fn new(
+        vehicle_type: VehicleType,
+        length: Distance,
+        max_speed: Option<Speed>,
+    ) -> VehicleSpec {
+        let (max_accel, max_decel) = vehicle_type.default_accel_limits();
+        VehicleSpec {
+            vehicle_type,
+            length,
+            max_speed,
+            max_accel,
+            max_decel,
+            battery: None,
+        }
+    }
+
     pub fn make(self, id: CarID, owner: Option<PersonID>) -> Vehicle {
         assert_eq!(id.1, self.vehicle_type);
         Vehicle {
@@ -306,6 +396,9 @@ impl VehicleSpec {
             vehicle_type: self.vehicle_type,
             length: self.length,
             max_speed: self.max_speed,
+            max_accel: self.max_accel,
+            max_decel: self.max_decel,
+            battery: self.battery,
         }
     }
 }
@@ -356,9 +449,10 @@ impl DrivingGoal {
                     Some(Position::start(map.find_driving_lane_near_building(*b)))
                 }
                 PathConstraints::Bike => Some(map.get_b(*b).biking_connection(map)?.0),
-                PathConstraints::Bus | PathConstraints::Train | PathConstraints::Pedestrian => {
-                    unreachable!()
-                }
+                PathConstraints::Bus
+                | PathConstraints::Train
+                | PathConstraints::Truck
+                | PathConstraints::Pedestrian => unreachable!(),
             },
             DrivingGoal::Border(_, l, _) => Some(Position::end(*l, map)),
         }
@@ -588,6 +682,8 @@ pub struct CreatePedestrian {
     pub goal: SidewalkSpot,
     pub req: PathRequest,
     pub path: Path,
+    // Where to cross the street partway along, each followed by the path to walk after it
+    pub crossings: Vec<(MidBlockCrossing, Path)>,
     pub trip: TripID,
     pub person: PersonID,
 }
@@ -644,3 +740,80 @@ impl CreateCar {
         }
     }
 }
+
+// Grade is in [-1.0, 1.0], positive for uphill, like Lane::percent_grade. Returns the speed of a
+// cyclist who'd go `speed` on flat ground. Climbing costs about 8% of the flat speed for every 1%
+// of grade, down to a crawl; coasting downhill is a bit faster, but cyclists brake eventually.
+pub(crate) fn bike_speed_on_grade(speed: Speed, grade: f64) -> Speed {
+    let pct = grade * 100.0;
+    let factor = if pct > 0.0 {
+        (1.0 - 0.08 * pct).max(0.3)
+    } else {
+        (1.0 - 0.03 * pct).min(1.4)
+    };
+    speed * factor
+}
+
+// Tobler's hiking function (https://en.wikipedia.org/wiki/Tobler%27s_hiking_function), scaled so
+// that walking on flat ground is unchanged. The fastest pace is on a slight downhill.
+pub(crate) fn walking_speed_on_grade(speed: Speed, grade: f64) -> Speed {
+    speed * ((-3.5 * (grade + 0.05).abs()).exp() / (-3.5 * 0.05_f64).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the speed after adjusting 10m/s for the grade
+    fn adjust(f: fn(Speed, f64) -> Speed, grade: f64) -> f64 {
+        f(Speed::meters_per_second(10.0), grade).inner_meters_per_second()
+    }
+
+    fn close(x: f64, y: f64) -> bool {
+        (x - y).abs() < 0.01
+    }
+
+    #[test]
+    fn test_bike_speed_on_grade() {
+        assert!(close(adjust(bike_speed_on_grade, 0.0), 10.0));
+        assert!(close(adjust(bike_speed_on_grade, 0.05), 6.0));
+        // Steep climbs bottom out at a crawl
+        assert!(close(adjust(bike_speed_on_grade, 0.2), 3.0));
+        assert!(close(adjust(bike_speed_on_grade, 1.0), 3.0));
+        assert!(close(adjust(bike_speed_on_grade, -0.05), 11.5));
+        // Cyclists brake on steep descents
+        assert!(close(adjust(bike_speed_on_grade, -0.2), 14.0));
+        assert!(close(adjust(bike_speed_on_grade, -1.0), 14.0));
+
+        // Steeper is always slower
+        let mut last = f64::MAX;
+        for pct in -100..=100 {
+            let speed = adjust(bike_speed_on_grade, f64::from(pct) / 100.0);
+            assert!(speed <= last);
+            last = speed;
+        }
+    }
+
+    #[test]
+    fn test_walking_speed_on_grade() {
+        assert!(close(adjust(walking_speed_on_grade, 0.0), 10.0));
+        // The fastest pace is on a slight downhill
+        let fastest = adjust(walking_speed_on_grade, -0.05);
+        assert!(close(fastest, 10.0 * (3.5 * 0.05_f64).exp()));
+        for pct in -100..=100 {
+            assert!(adjust(walking_speed_on_grade, f64::from(pct) / 100.0) <= fastest + 0.01);
+        }
+        // Symmetric around that slight downhill
+        assert!(close(
+            adjust(walking_speed_on_grade, 0.1),
+            adjust(walking_speed_on_grade, -0.2)
+        ));
+        assert!(close(
+            adjust(walking_speed_on_grade, 0.1),
+            10.0 * (-3.5 * 0.1_f64).exp()
+        ));
+        // Walking up a 10% grade is about 30% slower
+        assert!(adjust(walking_speed_on_grade, 0.1) < 7.1);
+        assert!(adjust(walking_speed_on_grade, 0.1) > 7.0);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
-    IndividTrip, PersonID, PersonSpec, Scenario, ScenarioGenerator, SpawnTrip, TripEndpoint,
-    TripMode,
+    DepartureProfile, IndividTrip, PersonID, PersonSpec, Scenario, ScenarioGenerator, SpawnTrip,
+    TripEndpoint, TripMode,
 };
 use abstutil::{prettyprint_usize, Parallelism, Timer};
 use geom::{Distance, Duration, Time};
@@ -30,24 +30,20 @@ impl ScenarioGenerator {
                     }
                     num_bldg_residential += 1;
                 }
-                BuildingType::ResidentialCommercial(num_ppl) => {
+                BuildingType::ResidentialCommercial(num_ppl, num_jobs) => {
                     if num_ppl == 0 {
                         trace!("empty ResidentialCommercial");
                     }
                     for _ in 0..num_ppl {
                         residents.push(b.id);
-
-                        // TODO: currently we just assume work capacity is the same as residential
-                        // capacity, which is surely not true. How can we better estimate?
+                    }
+                    for _ in 0..num_jobs {
                         workers.push(b.id);
                     }
                     num_bldg_mixed_residential_commercial += 1;
                 }
-                BuildingType::Commercial => {
-                    // TODO: currently we just assume some constant for jobs per building.
-                    // A better metrics might be available parking or building size
-                    let building_cap = usize::pow(2, rng.gen_range(0, 7));
-                    for _ in 0..building_cap {
+                BuildingType::Commercial(num_jobs) => {
+                    for _ in 0..num_jobs {
                         workers.push(b.id);
                     }
                     num_bldg_commercial += 1;
@@ -245,33 +241,34 @@ fn create_prole(
         _ => TripMode::Drive,
     };
 
-    // TODO This will cause a single morning and afternoon rush. Outside of these times,
-    // it'll be really quiet. Probably want a normal distribution centered around these
-    // peak times, but with a long tail.
-    let mut depart_am = rand_time(
-        rng,
-        Time::START_OF_DAY + Duration::hours(7),
-        Time::START_OF_DAY + Duration::hours(10),
-    );
-    let mut depart_pm = rand_time(
-        rng,
-        Time::START_OF_DAY + Duration::hours(17),
-        Time::START_OF_DAY + Duration::hours(19),
-    );
-
-    if rng.gen_bool(0.1) {
+    let (morning, evening) = if rng.gen_bool(0.1) {
         // hacky hack to get some background traffic
-        depart_am = rand_time(
-            rng,
-            Time::START_OF_DAY + Duration::hours(0),
-            Time::START_OF_DAY + Duration::hours(12),
-        );
-        depart_pm = rand_time(
-            rng,
-            Time::START_OF_DAY + Duration::hours(12),
-            Time::START_OF_DAY + Duration::hours(24),
-        );
-    }
+        (
+            DepartureProfile::uniform(Time::START_OF_DAY, Time::START_OF_DAY + Duration::hours(12)),
+            DepartureProfile::uniform(
+                Time::START_OF_DAY + Duration::hours(12),
+                Time::START_OF_DAY + Duration::hours(24),
+            ),
+        )
+    } else {
+        // Peak around the morning and afternoon rush, with a tail on either side.
+        (
+            DepartureProfile::Normal {
+                mean: Time::START_OF_DAY + Duration::hours(8) + Duration::minutes(30),
+                std_dev: Duration::minutes(45),
+                start: Time::START_OF_DAY + Duration::hours(6),
+                stop: Time::START_OF_DAY + Duration::hours(11),
+            },
+            DepartureProfile::Normal {
+                mean: Time::START_OF_DAY + Duration::hours(17) + Duration::minutes(30),
+                std_dev: Duration::hours(1),
+                start: Time::START_OF_DAY + Duration::hours(15),
+                stop: Time::START_OF_DAY + Duration::hours(21),
+            },
+        )
+    };
+    let depart_am = morning.sample(rng);
+    let depart_pm = evening.sample(rng);
 
     let goto_work = SpawnTrip::new(home.clone(), work.clone(), mode, map)
         .ok_or("unable to spawn 'goto work' trip")?;
@@ -327,8 +324,3 @@ fn select_trip_mode(distance: Distance, rng: &mut XorShiftRng) -> TripMode {
     // Most of the time, just drive
     TripMode::Drive
 }
-
-fn rand_time(rng: &mut XorShiftRng, low: Time, high: Time) -> Time {
-    assert!(high > low);
-    Time::START_OF_DAY + Duration::seconds(rng.gen_range(low.inner_seconds(), high.inner_seconds()))
-}
@@ -0,0 +1,371 @@
+use geom::{Duration, Time};
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+use rand_distr::{Distribution, Normal, Triangular};
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+// Validation makes sure at least a third of a Normal profile falls inside its window, so running out
+// of redraws means something is very wrong.
+const MAX_REDRAWS: usize = 1000;
+
+// When do agents leave? Shared by everything that generates trips. Profiles read from a file are
+// validated, so sampling them can't fail.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(try_from = "UncheckedDepartureProfile")]
+pub enum DepartureProfile {
+    // Equally likely to leave any time in [start, stop)
+    Uniform {
+        start: Time,
+        stop: Time,
+    },
+    // Centered around a peak. Samples outside [start, stop] are redrawn, so the window has to be
+    // at least one standard deviation wide.
+    Normal {
+        mean: Time,
+        std_dev: Duration,
+        start: Time,
+        stop: Time,
+    },
+    // Ramps up linearly from start to the peak, then back down to stop.
+    Triangular {
+        start: Time,
+        peak: Time,
+        stop: Time,
+    },
+    // The relative number of departures during each hour, starting at midnight. Within one hour,
+    // departures are uniform.
+    Hourly(Vec<f64>),
+}
+
+impl DepartureProfile {
+    pub fn uniform(start: Time, stop: Time) -> DepartureProfile {
+        DepartureProfile::Uniform { start, stop }
+    }
+
+    // Panics if the profile isn't valid.
+    pub fn sample(&self, rng: &mut XorShiftRng) -> Time {
+        match self {
+            DepartureProfile::Uniform { start, stop } => rand_time(rng, *start, *stop),
+            DepartureProfile::Normal {
+                mean,
+                std_dev,
+                start,
+                stop,
+            } => {
+                let normal = Normal::new(mean.inner_seconds(), std_dev.inner_seconds())
+                    .unwrap_or_else(|_| panic!("Invalid profile {:?}", self));
+                for _ in 0..MAX_REDRAWS {
+                    let x = normal.sample(rng);
+                    if x >= start.inner_seconds() && x <= stop.inner_seconds() {
+                        return Time::START_OF_DAY + Duration::seconds(x);
+                    }
+                }
+                panic!("{:?} never sampled a time inside its window", self);
+            }
+            DepartureProfile::Triangular { start, peak, stop } => {
+                let triangular = Triangular::new(
+                    start.inner_seconds(),
+                    stop.inner_seconds(),
+                    peak.inner_seconds(),
+                )
+                .unwrap_or_else(|_| panic!("Invalid profile {:?}", self));
+                Time::START_OF_DAY + Duration::seconds(triangular.sample(rng))
+            }
+            DepartureProfile::Hourly(weights) => {
+                let hour = WeightedIndex::new(weights)
+                    .unwrap_or_else(|_| panic!("Invalid profile {:?}", self))
+                    .sample(rng);
+                let start = Time::START_OF_DAY + Duration::hours(hour);
+                rand_time(rng, start, start + Duration::hours(1))
+            }
+        }
+    }
+
+    // Departures never happen outside this range
+    pub fn bounds(&self) -> (Time, Time) {
+        match self {
+            DepartureProfile::Uniform { start, stop }
+            | DepartureProfile::Normal { start, stop, .. }
+            | DepartureProfile::Triangular { start, stop, .. } => (*start, *stop),
+            DepartureProfile::Hourly(weights) => {
+                let first = weights.iter().position(|w| *w > 0.0).unwrap_or(0);
+                let last = weights.iter().rposition(|w| *w > 0.0).unwrap_or(0);
+                (
+                    Time::START_OF_DAY + Duration::hours(first),
+                    Time::START_OF_DAY + Duration::hours(last + 1),
+                )
+            }
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            DepartureProfile::Uniform { start, stop } => {
+                if start >= stop {
+                    return Err(format!("{} isn't before {}", start, stop));
+                }
+            }
+            DepartureProfile::Normal {
+                mean,
+                std_dev,
+                start,
+                stop,
+            } => {
+                if start >= stop {
+                    return Err(format!("{} isn't before {}", start, stop));
+                }
+                if mean < start || mean > stop {
+                    return Err(format!("{} isn't between {} and {}", mean, start, stop));
+                }
+                if *std_dev <= Duration::ZERO {
+                    return Err(format!("standard deviation {} must be positive", std_dev));
+                }
+                if *stop - *start < *std_dev {
+                    return Err(format!(
+                        "{} to {} is narrower than the standard deviation {}; most departures \
+                         would fall outside it",
+                        start, stop, std_dev
+                    ));
+                }
+            }
+            DepartureProfile::Triangular { start, peak, stop } => {
+                if start >= stop {
+                    return Err(format!("{} isn't before {}", start, stop));
+                }
+                if peak < start || peak > stop {
+                    return Err(format!("{} isn't between {} and {}", peak, start, stop));
+                }
+            }
+            DepartureProfile::Hourly(weights) => {
+                if let Some(w) = weights.iter().find(|w| !w.is_finite()) {
+                    return Err(format!("hourly weights must be finite numbers, not {}", w));
+                }
+                if weights.iter().any(|w| *w < 0.0) {
+                    return Err("hourly weights can't be negative".to_string());
+                }
+                if !weights.iter().any(|w| *w > 0.0) {
+                    return Err("at least one hour needs some departures".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            DepartureProfile::Uniform { start, stop } => format!(
+                "uniformly between {} and {}",
+                start.ampm_tostring(),
+                stop.ampm_tostring()
+            ),
+            DepartureProfile::Normal {
+                mean,
+                std_dev,
+                start,
+                stop,
+            } => format!(
+                "around {} (standard deviation {}), between {} and {}",
+                mean.ampm_tostring(),
+                std_dev,
+                start.ampm_tostring(),
+                stop.ampm_tostring()
+            ),
+            DepartureProfile::Triangular { start, peak, stop } => format!(
+                "peaking at {}, between {} and {}",
+                peak.ampm_tostring(),
+                start.ampm_tostring(),
+                stop.ampm_tostring()
+            ),
+            DepartureProfile::Hourly(weights) => {
+                let (start, stop) = self.bounds();
+                format!(
+                    "following an hourly profile over {} hours, between {} and {}",
+                    weights.len(),
+                    start.ampm_tostring(),
+                    stop.ampm_tostring()
+                )
+            }
+        }
+    }
+}
+
+// The same shape as DepartureProfile, used to validate profiles as they're deserialized
+#[derive(Deserialize)]
+enum UncheckedDepartureProfile {
+    Uniform {
+        start: Time,
+        stop: Time,
+    },
+    Normal {
+        mean: Time,
+        std_dev: Duration,
+        start: Time,
+        stop: Time,
+    },
+    Triangular {
+        start: Time,
+        peak: Time,
+        stop: Time,
+    },
+    Hourly(Vec<f64>),
+}
+
+impl TryFrom<UncheckedDepartureProfile> for DepartureProfile {
+    type Error = String;
+
+    fn try_from(x: UncheckedDepartureProfile) -> Result<DepartureProfile, String> {
+        let profile = match x {
+            UncheckedDepartureProfile::Uniform { start, stop } => {
+                DepartureProfile::Uniform { start, stop }
+            }
+            UncheckedDepartureProfile::Normal {
+                mean,
+                std_dev,
+                start,
+                stop,
+            } => DepartureProfile::Normal {
+                mean,
+                std_dev,
+                start,
+                stop,
+            },
+            UncheckedDepartureProfile::Triangular { start, peak, stop } => {
+                DepartureProfile::Triangular { start, peak, stop }
+            }
+            UncheckedDepartureProfile::Hourly(weights) => DepartureProfile::Hourly(weights),
+        };
+        profile.validate()?;
+        Ok(profile)
+    }
+}
+
+fn rand_time(rng: &mut XorShiftRng, low: Time, high: Time) -> Time {
+    assert!(high > low);
+    Time::START_OF_DAY + Duration::seconds(rng.gen_range(low.inner_seconds(), high.inner_seconds()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn hour(x: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(x)
+    }
+
+    #[test]
+    fn test_samples_within_bounds() {
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        for profile in vec![
+            DepartureProfile::uniform(hour(7), hour(9)),
+            DepartureProfile::Normal {
+                mean: hour(8),
+                std_dev: Duration::minutes(30),
+                start: hour(7),
+                stop: hour(9),
+            },
+            DepartureProfile::Triangular {
+                start: hour(7),
+                peak: hour(8),
+                stop: hour(9),
+            },
+            DepartureProfile::Hourly(vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 3.0]),
+        ] {
+            assert_eq!(profile.validate(), Ok(()));
+            assert_eq!(profile.bounds(), (hour(7), hour(9)));
+            for _ in 0..100 {
+                let t = profile.sample(&mut rng);
+                assert!(t >= hour(7) && t <= hour(9), "{:?} gave {}", profile, t);
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalid_profiles() {
+        for profile in vec![
+            DepartureProfile::uniform(hour(9), hour(7)),
+            DepartureProfile::Normal {
+                mean: hour(8),
+                std_dev: Duration::ZERO,
+                start: hour(7),
+                stop: hour(9),
+            },
+            DepartureProfile::Normal {
+                mean: hour(10),
+                std_dev: Duration::minutes(30),
+                start: hour(7),
+                stop: hour(9),
+            },
+            DepartureProfile::Triangular {
+                start: hour(7),
+                peak: hour(6),
+                stop: hour(9),
+            },
+            DepartureProfile::Normal {
+                mean: hour(8),
+                std_dev: Duration::hours(3),
+                start: hour(7),
+                stop: hour(9),
+            },
+            DepartureProfile::Hourly(vec![0.0, 0.0]),
+            DepartureProfile::Hourly(vec![1.0, -1.0]),
+            DepartureProfile::Hourly(vec![1.0, std::f64::NAN]),
+            DepartureProfile::Hourly(vec![1.0, std::f64::INFINITY]),
+        ] {
+            assert!(
+                profile.validate().is_err(),
+                "{:?} should be invalid",
+                profile
+            );
+            // Deserializing catches the same problems
+            let json = abstutil::to_json(&profile);
+            assert!(abstutil::from_json::<DepartureProfile>(&json.into_bytes()).is_err());
+        }
+
+        let json = abstutil::to_json(&DepartureProfile::uniform(hour(7), hour(9)));
+        assert!(abstutil::from_json::<DepartureProfile>(&json.into_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(
+            DepartureProfile::Hourly(vec![1.0, std::f64::NAN]).validate(),
+            Err("hourly weights must be finite numbers, not NaN".to_string())
+        );
+        assert_eq!(
+            DepartureProfile::Hourly(vec![1.0, -1.0]).validate(),
+            Err("hourly weights can't be negative".to_string())
+        );
+        assert!(DepartureProfile::Normal {
+            mean: hour(8),
+            std_dev: Duration::hours(3),
+            start: hour(7),
+            stop: hour(9),
+        }
+        .validate()
+        .unwrap_err()
+        .contains("narrower than the standard deviation"));
+    }
+
+    #[test]
+    fn test_normal_keeps_spread_near_edge() {
+        // The peak is right at the start of a window only one standard deviation wide. Most draws
+        // fall outside, but the samples still spread out instead of piling up at the mean.
+        let profile = DepartureProfile::Normal {
+            mean: hour(7),
+            std_dev: Duration::hours(1),
+            start: hour(7),
+            stop: hour(8),
+        };
+        assert_eq!(profile.validate(), Ok(()));
+        let mut rng = XorShiftRng::from_seed([42; 16]);
+        let samples: Vec<Time> = (0..100).map(|_| profile.sample(&mut rng)).collect();
+        assert!(samples.iter().all(|t| *t >= hour(7) && *t <= hour(8)));
+        assert!(samples.iter().filter(|t| **t == hour(7)).count() <= 1);
+        assert!(samples
+            .iter()
+            .any(|t| *t >= hour(7) + Duration::minutes(30)));
+    }
+}
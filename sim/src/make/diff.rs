@@ -0,0 +1,190 @@
+use crate::{IndividTrip, PersonID, PersonSpec, Scenario, TripEndpoint, TripMode};
+use abstutil::{prettyprint_usize, Counter};
+use geom::Time;
+use map_model::Map;
+use std::collections::BTreeMap;
+
+// A summary of what changed between two versions of a scenario, meant to be read by people
+// reviewing a change. People are matched up by PersonID, and trips by their index in the person's
+// schedule.
+pub struct ScenarioDiff {
+    pub people_added: Vec<PersonID>,
+    pub people_removed: Vec<PersonID>,
+    // (person, trip index) for trips that only exist in one of the two scenarios
+    pub trips_added: Vec<(PersonID, usize)>,
+    pub trips_removed: Vec<(PersonID, usize)>,
+    pub trips_changed: Vec<TripChange>,
+    // (mode, hour of departure, count before, count after). Only includes entries that differ.
+    pub trips_per_mode_and_hour: Vec<((TripMode, usize), usize, usize)>,
+}
+
+// Each field is filled out with (before, after) only if that property changed.
+pub struct TripChange {
+    pub person: PersonID,
+    pub trip_idx: usize,
+    pub mode: Option<(TripMode, TripMode)>,
+    pub depart: Option<(Time, Time)>,
+    pub start: Option<(TripEndpoint, TripEndpoint)>,
+    pub end: Option<(TripEndpoint, TripEndpoint)>,
+}
+
+impl ScenarioDiff {
+    pub fn new(before: &Scenario, after: &Scenario, map: &Map) -> ScenarioDiff {
+        let mut diff = ScenarioDiff {
+            people_added: Vec::new(),
+            people_removed: Vec::new(),
+            trips_added: Vec::new(),
+            trips_removed: Vec::new(),
+            trips_changed: Vec::new(),
+            trips_per_mode_and_hour: Vec::new(),
+        };
+
+        let before_people: BTreeMap<PersonID, &PersonSpec> =
+            before.people.iter().map(|p| (p.id, p)).collect();
+        let after_people: BTreeMap<PersonID, &PersonSpec> =
+            after.people.iter().map(|p| (p.id, p)).collect();
+
+        for (id, p1) in &before_people {
+            let p2 = if let Some(p) = after_people.get(id) {
+                p
+            } else {
+                diff.people_removed.push(*id);
+                continue;
+            };
+            for (idx, (t1, t2)) in p1.trips.iter().zip(p2.trips.iter()).enumerate() {
+                if let Some(change) = TripChange::new(*id, idx, t1, t2, map) {
+                    diff.trips_changed.push(change);
+                }
+            }
+            for idx in p2.trips.len()..p1.trips.len() {
+                diff.trips_removed.push((*id, idx));
+            }
+            for idx in p1.trips.len()..p2.trips.len() {
+                diff.trips_added.push((*id, idx));
+            }
+        }
+        for id in after_people.keys() {
+            if !before_people.contains_key(id) {
+                diff.people_added.push(*id);
+            }
+        }
+
+        diff.trips_per_mode_and_hour = count_trips(before)
+            .compare(count_trips(after))
+            .into_iter()
+            .filter(|(_, cnt1, cnt2)| cnt1 != cnt2)
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.people_added.is_empty()
+            && self.people_removed.is_empty()
+            && self.trips_added.is_empty()
+            && self.trips_removed.is_empty()
+            && self.trips_changed.is_empty()
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!(
+                "{} people added, {} people removed",
+                prettyprint_usize(self.people_added.len()),
+                prettyprint_usize(self.people_removed.len())
+            ),
+            format!(
+                "{} trips added, {} trips removed, {} trips changed",
+                prettyprint_usize(self.trips_added.len()),
+                prettyprint_usize(self.trips_removed.len()),
+                prettyprint_usize(self.trips_changed.len())
+            ),
+        ];
+        for id in &self.people_added {
+            lines.push(format!("+ {}", id));
+        }
+        for id in &self.people_removed {
+            lines.push(format!("- {}", id));
+        }
+        for (id, idx) in &self.trips_added {
+            lines.push(format!("+ {}, trip #{}", id, idx));
+        }
+        for (id, idx) in &self.trips_removed {
+            lines.push(format!("- {}, trip #{}", id, idx));
+        }
+        for change in &self.trips_changed {
+            lines.push(format!("~ {}, trip #{}", change.person, change.trip_idx));
+            if let Some((m1, m2)) = change.mode {
+                lines.push(format!("    mode: {:?} -> {:?}", m1, m2));
+            }
+            if let Some((t1, t2)) = change.depart {
+                lines.push(format!("    departure: {} -> {}", t1, t2));
+            }
+            if let Some((ref e1, ref e2)) = change.start {
+                lines.push(format!("    start: {:?} -> {:?}", e1, e2));
+            }
+            if let Some((ref e1, ref e2)) = change.end {
+                lines.push(format!("    end: {:?} -> {:?}", e1, e2));
+            }
+        }
+        if !self.trips_per_mode_and_hour.is_empty() {
+            lines.push("Trips by mode and departure hour:".to_string());
+            for ((mode, hour), cnt1, cnt2) in &self.trips_per_mode_and_hour {
+                lines.push(format!(
+                    "    {:?} during hour {}: {} -> {}",
+                    mode,
+                    hour,
+                    prettyprint_usize(*cnt1),
+                    prettyprint_usize(*cnt2)
+                ));
+            }
+        }
+        lines
+    }
+}
+
+impl TripChange {
+    fn new(
+        person: PersonID,
+        trip_idx: usize,
+        t1: &IndividTrip,
+        t2: &IndividTrip,
+        map: &Map,
+    ) -> Option<TripChange> {
+        let change = TripChange {
+            person,
+            trip_idx,
+            mode: changed(t1.trip.mode(), t2.trip.mode()),
+            depart: changed(t1.depart, t2.depart),
+            start: changed(t1.trip.start(map), t2.trip.start(map)),
+            end: changed(t1.trip.end(map), t2.trip.end(map)),
+        };
+        if change.mode.is_none()
+            && change.depart.is_none()
+            && change.start.is_none()
+            && change.end.is_none()
+        {
+            None
+        } else {
+            Some(change)
+        }
+    }
+}
+
+fn changed<T: PartialEq>(before: T, after: T) -> Option<(T, T)> {
+    if before == after {
+        None
+    } else {
+        Some((before, after))
+    }
+}
+
+fn count_trips(s: &Scenario) -> Counter<(TripMode, usize)> {
+    let mut cnt = Counter::new();
+    for p in &s.people {
+        for trip in &p.trips {
+            cnt.inc((trip.trip.mode(), trip.depart.get_parts().0));
+        }
+    }
+    cnt
+}
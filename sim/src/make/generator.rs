@@ -1,4 +1,7 @@
-use crate::{DrivingGoal, IndividTrip, PersonID, PersonSpec, Scenario, SidewalkSpot, SpawnTrip};
+use crate::{
+    DepartureProfile, DrivingGoal, IndividTrip, PersonID, PersonSpec, Scenario, SidewalkSpot,
+    SpawnTrip,
+};
 use abstutil::Timer;
 use geom::{Duration, Time};
 use map_model::{BuildingID, DirectedRoadID, Map, PathConstraints};
@@ -24,9 +27,7 @@ pub struct ScenarioGenerator {
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct SpawnOverTime {
     pub num_agents: usize,
-    // TODO use https://docs.rs/rand/0.5.5/rand/distributions/struct.Normal.html
-    pub start_time: Time,
-    pub stop_time: Time,
+    pub departure: DepartureProfile,
     pub goal: OriginDestination,
     pub percent_driving: f64,
     pub percent_biking: f64,
@@ -39,9 +40,7 @@ pub struct BorderSpawnOverTime {
     pub num_cars: usize,
     pub num_bikes: usize,
     pub percent_use_transit: f64,
-    // TODO use https://docs.rs/rand/0.5.5/rand/distributions/struct.Normal.html
-    pub start_time: Time,
-    pub stop_time: Time,
+    pub departure: DepartureProfile,
     pub start_from_border: DirectedRoadID,
     pub goal: OriginDestination,
 }
@@ -94,8 +93,10 @@ impl ScenarioGenerator {
             only_seed_buses: None,
             spawn_over_time: vec![SpawnOverTime {
                 num_agents: 100,
-                start_time: Time::START_OF_DAY,
-                stop_time: Time::START_OF_DAY + Duration::seconds(5.0),
+                departure: DepartureProfile::uniform(
+                    Time::START_OF_DAY,
+                    Time::START_OF_DAY + Duration::seconds(5.0),
+                ),
                 goal: OriginDestination::Anywhere,
                 percent_driving: 0.5,
                 percent_biking: 0.5,
@@ -110,8 +111,10 @@ impl ScenarioGenerator {
                     num_peds: 10,
                     num_cars: 10,
                     num_bikes: 10,
-                    start_time: Time::START_OF_DAY,
-                    stop_time: Time::START_OF_DAY + Duration::seconds(5.0),
+                    departure: DepartureProfile::uniform(
+                        Time::START_OF_DAY,
+                        Time::START_OF_DAY + Duration::seconds(5.0),
+                    ),
                     start_from_border: i.some_outgoing_road(map).unwrap(),
                     goal: OriginDestination::Anywhere,
                     percent_use_transit: 0.5,
@@ -121,8 +124,10 @@ impl ScenarioGenerator {
         for i in map.all_outgoing_borders() {
             s.spawn_over_time.push(SpawnOverTime {
                 num_agents: 10,
-                start_time: Time::START_OF_DAY,
-                stop_time: Time::START_OF_DAY + Duration::seconds(5.0),
+                departure: DepartureProfile::uniform(
+                    Time::START_OF_DAY,
+                    Time::START_OF_DAY + Duration::seconds(5.0),
+                ),
                 goal: OriginDestination::EndOfRoad(i.some_incoming_road(map).unwrap()),
                 percent_driving: 0.5,
                 percent_biking: 0.5,
@@ -148,8 +153,10 @@ impl ScenarioGenerator {
             only_seed_buses: Some(BTreeSet::new()),
             spawn_over_time: vec![SpawnOverTime {
                 num_agents: num_agents,
-                start_time: Time::START_OF_DAY,
-                stop_time: Time::START_OF_DAY + Duration::seconds(5.0),
+                departure: DepartureProfile::uniform(
+                    Time::START_OF_DAY,
+                    Time::START_OF_DAY + Duration::seconds(5.0),
+                ),
                 goal: OriginDestination::Anywhere,
                 percent_driving: 0.5,
                 percent_biking: 0.5,
@@ -168,7 +175,7 @@ impl SpawnOverTime {
         map: &Map,
         timer: &mut Timer,
     ) {
-        let depart = rand_time(rng, self.start_time, self.stop_time);
+        let depart = self.departure.sample(rng);
         // Note that it's fine for agents to start/end at the same building. Later we might
         // want a better assignment of people per household, or workers per office building.
         let from_bldg = map.all_buildings().choose(rng).unwrap().id;
@@ -273,7 +280,7 @@ impl BorderSpawnOverTime {
         };
 
         for _ in 0..self.num_peds {
-            let depart = rand_time(rng, self.start_time, self.stop_time);
+            let depart = self.departure.sample(rng);
             let id = PersonID(scenario.people.len());
             if let Some(goal) = self.goal.pick_walking_goal(map, rng, timer) {
                 if rng.gen_bool(self.percent_use_transit) {
@@ -322,7 +329,7 @@ impl BorderSpawnOverTime {
         timer: &mut Timer,
     ) {
         for _ in 0..num {
-            let depart = rand_time(rng, self.start_time, self.stop_time);
+            let depart = self.departure.sample(rng);
             if let Some(goal) = self.goal.pick_driving_goal(constraints, map, rng, timer) {
                 let id = PersonID(scenario.people.len());
                 scenario.people.push(PersonSpec {
@@ -398,8 +405,3 @@ impl OriginDestination {
         }
     }
 }
-
-fn rand_time(rng: &mut XorShiftRng, low: Time, high: Time) -> Time {
-    assert!(high > low);
-    Time::START_OF_DAY + Duration::seconds(rng.gen_range(low.inner_seconds(), high.inner_seconds()))
-}
@@ -1,5 +1,9 @@
-use crate::{AlertHandler, Scenario, ScenarioModifier, Sim, SimOptions};
-use abstutil::CmdArgs;
+use crate::{
+    AlertHandler, Incident, ParkingSearch, ParkingStrategy, Precipitation, Scenario,
+    ScenarioModifier, Sim, SimOptions, Weather,
+};
+use abstutil::{CmdArgs, Timer};
+use geom::Duration;
 use map_model::{Map, MapEdits};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
@@ -11,6 +15,9 @@ const RNG_SEED: u8 = 42;
 pub struct SimFlags {
     pub load: String,
     pub modifiers: Vec<ScenarioModifier>,
+    // Scheduled when the simulation starts
+    #[serde(default)]
+    pub incidents: Vec<Incident>,
     #[serde(skip_deserializing)]
     pub rng_seed: u8,
     #[serde(skip_deserializing)]
@@ -28,6 +35,10 @@ impl SimFlags {
                 .optional_free()
                 .unwrap_or_else(|| abstutil::path_map("montlake")),
             modifiers: Vec::new(),
+            incidents: args
+                .optional("--incidents")
+                .map(|path| abstutil::read_json(path, &mut Timer::throwaway()))
+                .unwrap_or_else(Vec::new),
             rng_seed,
             opts: SimOptions {
                 run_name: args
@@ -54,6 +65,51 @@ impl SimFlags {
                     .unwrap_or(AlertHandler::Print),
                 pathfinding_upfront: args.enabled("--pathfinding_upfront"),
                 live_map_edits: args.enabled("--live_map_edits"),
+                fleet_size: args
+                    .optional_parse("--fleet_size", |s| s.parse())
+                    .unwrap_or(0),
+                parking_search: ParkingSearch {
+                    strategy: args
+                        .optional("--parking_search")
+                        .map(|x| match x.as_ref() {
+                            "omniscient" => ParkingStrategy::Omniscient,
+                            "circle_block" => ParkingStrategy::CircleBlock,
+                            "expand_radius" => ParkingStrategy::ExpandRadius,
+                            "nearest_garage" => ParkingStrategy::NearestGarage,
+                            _ => panic!(
+                                "Bad --parking_search={}. Must be \
+                                 omniscient|circle_block|expand_radius|nearest_garage",
+                                x
+                            ),
+                        })
+                        .unwrap_or(ParkingStrategy::Omniscient),
+                    give_up_after: args
+                        .optional_parse("--parking_give_up_after", Duration::parse)
+                        .unwrap_or(Duration::minutes(10)),
+                },
+                kinematics: args.enabled("--kinematics"),
+                ev_share: args
+                    .optional_parse("--ev_share", |s| s.parse())
+                    .unwrap_or(0.0),
+                jaywalking: args.enabled("--jaywalking"),
+                pass_bikes: args.enabled("--pass_bikes"),
+                weather: Weather {
+                    precipitation: args
+                        .optional("--weather")
+                        .map(|x| match x.as_ref() {
+                            "dry" => Precipitation::Dry,
+                            "rain" => Precipitation::Rain,
+                            "snow" => Precipitation::Snow,
+                            _ => panic!("Bad --weather={}. Must be dry|rain|snow", x),
+                        })
+                        .unwrap_or(Precipitation::Dry),
+                    daylight: args.optional("--daylight").map(|x| match x.as_ref() {
+                        "summer" => Weather::summer_daylight(),
+                        "winter" => Weather::winter_daylight(),
+                        _ => panic!("Bad --daylight={}. Must be summer|winter", x),
+                    }),
+                },
+                record_replay: args.enabled("--record_replay"),
             },
         }
     }
@@ -67,6 +123,7 @@ impl SimFlags {
         SimFlags {
             load: abstutil::path_map(map),
             modifiers: Vec::new(),
+            incidents: Vec::new(),
             rng_seed: RNG_SEED,
             opts: SimOptions::new(run_name),
         }
@@ -123,6 +180,9 @@ impl SimFlags {
             }
             let mut sim = Sim::new(&map, opts, timer);
             scenario.instantiate(&mut sim, &map, &mut rng, timer);
+            for incident in &self.incidents {
+                sim.schedule_incident(incident.clone());
+            }
 
             (map, sim, rng)
         } else if self.load.starts_with(&abstutil::path_all_raw_maps())
@@ -134,7 +194,10 @@ impl SimFlags {
             let map = Map::new(self.load.clone(), timer);
 
             timer.start("create sim");
-            let sim = Sim::new(&map, opts, timer);
+            let mut sim = Sim::new(&map, opts, timer);
+            for incident in &self.incidents {
+                sim.schedule_incident(incident.clone());
+            }
             timer.stop("create sim");
 
             (map, sim, rng)
@@ -1,16 +1,24 @@
 mod activity_model;
+mod departure;
+mod diff;
 mod external;
 mod generator;
 mod load;
 mod modifier;
+mod rebase;
 mod scenario;
 mod spawner;
 
+pub use self::departure::DepartureProfile;
+pub use self::diff::{ScenarioDiff, TripChange};
 pub use self::external::{ExternalPerson, ExternalTrip};
 pub use self::generator::{
     BorderSpawnOverTime, OriginDestination, ScenarioGenerator, SpawnOverTime,
 };
 pub use self::load::SimFlags;
 pub use self::modifier::ScenarioModifier;
-pub use self::scenario::{IndividTrip, OffMapLocation, PersonSpec, Scenario, SpawnTrip};
+pub use self::scenario::{
+    DeliveryTour, HouseholdSpec, IndividTrip, JointTrip, OffMapLocation, PersonSpec, Scenario,
+    ScenarioFilter, SpawnTrip,
+};
 pub use self::spawner::{TripSpawner, TripSpec};
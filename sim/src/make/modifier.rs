@@ -1,4 +1,4 @@
-use crate::{IndividTrip, PersonID, Scenario, SpawnTrip, TripMode};
+use crate::{DeliveryTour, IndividTrip, PersonID, Scenario, SpawnTrip, TripMode};
 use abstutil::Timer;
 use geom::{Duration, Time};
 use map_model::Map;
@@ -19,6 +19,8 @@ pub enum ScenarioModifier {
     },
     // Scenario name
     AddExtraTrips(String),
+    LimitCarsPerHousehold(usize),
+    AddDeliveryTours(usize),
 }
 
 impl ScenarioModifier {
@@ -70,6 +72,20 @@ impl ScenarioModifier {
                 }
                 s
             }
+            ScenarioModifier::LimitCarsPerHousehold(n) => {
+                for h in &mut s.households {
+                    h.num_cars = h.num_cars.min(*n);
+                }
+                s
+            }
+            ScenarioModifier::AddDeliveryTours(n) => {
+                for _ in 0..*n {
+                    if let Some(tour) = DeliveryTour::random(map, rng) {
+                        s.deliveries.push(tour);
+                    }
+                }
+                s
+            }
         }
     }
 
@@ -93,6 +109,10 @@ impl ScenarioModifier {
                 to_mode.verb()
             ),
             ScenarioModifier::AddExtraTrips(name) => format!("Add extra trips from {}", name),
+            ScenarioModifier::LimitCarsPerHousehold(n) => {
+                format!("limit every household to {} shared cars", n)
+            }
+            ScenarioModifier::AddDeliveryTours(n) => format!("add {} delivery tours", n),
         }
     }
 }
@@ -0,0 +1,353 @@
+use crate::{
+    DeliveryTour, DrivingGoal, PersonID, Scenario, SidewalkPOI, SidewalkSpot, SpawnTrip,
+    TripEndpoint,
+};
+use map_model::MapMatcher;
+use std::collections::BTreeMap;
+
+impl Scenario {
+    // Moves a scenario to a newer import of the same map. People with a trip that can't be carried
+    // over are dropped, along with their households. Everything dropped is described in the
+    // returned list.
+    pub fn rebase(mut self, matcher: &MapMatcher) -> (Scenario, Vec<String>) {
+        let mut problems = Vec::new();
+
+        let mut people = Vec::new();
+        for mut person in self.people {
+            match person
+                .trips
+                .iter()
+                .enumerate()
+                .map(|(idx, trip)| {
+                    trip.trip
+                        .rebase(matcher)
+                        .map_err(|err| format!("trip {}: {}", idx, err))
+                })
+                .collect::<Result<Vec<SpawnTrip>, String>>()
+            {
+                Ok(trips) => {
+                    for (trip, rebased) in person.trips.iter_mut().zip(trips) {
+                        trip.trip = rebased;
+                    }
+                    people.push(person);
+                }
+                Err(err) => {
+                    problems.push(format!("Dropping {} because of {}", person.id, err));
+                }
+            }
+        }
+        self.people = people;
+
+        // Fix up IDs
+        let mut new_ids: BTreeMap<PersonID, PersonID> = BTreeMap::new();
+        for (idx, person) in self.people.iter_mut().enumerate() {
+            new_ids.insert(person.id, PersonID(idx));
+            person.id = PersonID(idx);
+        }
+        let mut households = Vec::new();
+        for mut h in self.households {
+            // Households that lost somebody don't make sense anymore
+            if !h.members.iter().all(|id| new_ids.contains_key(id)) {
+                problems.push(format!(
+                    "Dropping household at {} because some members were dropped",
+                    h.home
+                ));
+                continue;
+            }
+            match matcher.building(h.home) {
+                Ok(b) => {
+                    h.home = b;
+                    h.renumber(|id| new_ids[&id]);
+                    households.push(h);
+                }
+                Err(err) => {
+                    problems.push(format!("Dropping household at {}: {}", h.home, err));
+                }
+            }
+        }
+        self.households = households;
+
+        let mut deliveries = Vec::new();
+        for tour in self.deliveries {
+            let depart = tour.depart;
+            match tour.rebase(matcher) {
+                Ok(tour) => deliveries.push(tour),
+                Err(err) => {
+                    problems.push(format!("Dropping delivery leaving at {}: {}", depart, err));
+                }
+            }
+        }
+        self.deliveries = deliveries;
+
+        self.map_name = matcher.new_map().get_name().to_string();
+        (self, problems)
+    }
+}
+
+impl DeliveryTour {
+    fn rebase(mut self, matcher: &MapMatcher) -> Result<DeliveryTour, String> {
+        self.start = self.start.rebase(matcher)?;
+        self.end = self.end.rebase(matcher)?;
+        for b in &mut self.stops {
+            *b = matcher.building(*b)?;
+        }
+        Ok(self)
+    }
+}
+
+impl SpawnTrip {
+    fn rebase(&self, matcher: &MapMatcher) -> Result<SpawnTrip, String> {
+        Ok(match self {
+            SpawnTrip::VehicleAppearing {
+                start,
+                goal,
+                is_bike,
+            } => SpawnTrip::VehicleAppearing {
+                start: matcher.position(*start)?,
+                goal: goal.rebase(matcher)?,
+                is_bike: *is_bike,
+            },
+            SpawnTrip::FromBorder {
+                dr,
+                goal,
+                is_bike,
+                origin,
+            } => SpawnTrip::FromBorder {
+                dr: matcher.directed_road(*dr)?,
+                goal: goal.rebase(matcher)?,
+                is_bike: *is_bike,
+                origin: origin.clone(),
+            },
+            SpawnTrip::UsingParkedCar(b, goal) => {
+                SpawnTrip::UsingParkedCar(matcher.building(*b)?, goal.rebase(matcher)?)
+            }
+            SpawnTrip::UsingBike(b, goal) => {
+                SpawnTrip::UsingBike(matcher.building(*b)?, goal.rebase(matcher)?)
+            }
+            SpawnTrip::JustWalking(start, goal) => {
+                SpawnTrip::JustWalking(start.rebase(true, matcher)?, goal.rebase(false, matcher)?)
+            }
+            SpawnTrip::UsingTransit(start, goal, route, stop1, maybe_stop2) => {
+                SpawnTrip::UsingTransit(
+                    start.rebase(true, matcher)?,
+                    goal.rebase(false, matcher)?,
+                    matcher.bus_route(*route)?,
+                    matcher.bus_stop(*route, *stop1)?,
+                    match maybe_stop2 {
+                        Some(stop2) => Some(matcher.bus_stop(*route, *stop2)?),
+                        None => None,
+                    },
+                )
+            }
+            SpawnTrip::UsingRideHail(b1, b2) => {
+                SpawnTrip::UsingRideHail(matcher.building(*b1)?, matcher.building(*b2)?)
+            }
+            SpawnTrip::Remote { .. } => self.clone(),
+        })
+    }
+}
+
+impl DrivingGoal {
+    fn rebase(&self, matcher: &MapMatcher) -> Result<DrivingGoal, String> {
+        match self {
+            DrivingGoal::ParkNear(b) => Ok(DrivingGoal::ParkNear(matcher.building(*b)?)),
+            DrivingGoal::Border(i, l, destination) => {
+                let i = matcher.intersection(*i)?;
+                let l = matcher.lane(*l)?;
+                if matcher.new_map().get_l(l).dst_i != i {
+                    return Err(format!("{} doesn't lead to {} anymore", l, i));
+                }
+                Ok(DrivingGoal::Border(i, l, destination.clone()))
+            }
+        }
+    }
+}
+
+impl SidewalkSpot {
+    fn rebase(&self, start: bool, matcher: &MapMatcher) -> Result<SidewalkSpot, String> {
+        let map = matcher.new_map();
+        match self.connection {
+            SidewalkPOI::Building(b) => Ok(SidewalkSpot::building(matcher.building(b)?, map)),
+            SidewalkPOI::BusStop(stop) => {
+                let route = matcher
+                    .old_map()
+                    .all_bus_routes()
+                    .iter()
+                    .find(|r| r.stops.contains(&stop))
+                    .ok_or(format!("no route serves {}", stop))?;
+                Ok(SidewalkSpot::bus_stop(
+                    matcher.bus_stop(route.id, stop)?,
+                    map,
+                ))
+            }
+            SidewalkPOI::Border(i, ref off_map) => {
+                let i = matcher.intersection(i)?;
+                if start {
+                    SidewalkSpot::start_at_border(i, off_map.clone(), map)
+                } else {
+                    SidewalkSpot::end_at_border(i, off_map.clone(), map)
+                }
+                .ok_or(format!("{} has no sidewalks anymore", i))
+            }
+            SidewalkPOI::SuddenlyAppear => {
+                let pos = matcher.position(self.sidewalk_pos)?;
+                Ok(SidewalkSpot::suddenly_appear(
+                    pos.lane(),
+                    pos.dist_along(),
+                    map,
+                ))
+            }
+            SidewalkPOI::ParkingSpot(_)
+            | SidewalkPOI::DeferredParkingSpot
+            | SidewalkPOI::BikeRack(_) => Err(format!("can't rebase {:?}", self.connection)),
+        }
+    }
+}
+
+impl TripEndpoint {
+    fn rebase(&self, matcher: &MapMatcher) -> Result<TripEndpoint, String> {
+        match self {
+            TripEndpoint::Bldg(b) => Ok(TripEndpoint::Bldg(matcher.building(*b)?)),
+            TripEndpoint::Border(i, off_map) => Ok(TripEndpoint::Border(
+                matcher.intersection(*i)?,
+                off_map.clone(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HouseholdSpec, IndividTrip, PersonSpec, VehicleType};
+    use abstutil::Timer;
+    use geom::{Duration, Time};
+    use map_model::raw::RawMap;
+    use map_model::{osm, BuildingID, Map};
+
+    fn way(id: i64) -> osm::OsmID {
+        osm::OsmID::Way(osm::WayID(id))
+    }
+
+    fn drive(from: BuildingID, to: BuildingID) -> IndividTrip {
+        IndividTrip::new(
+            Time::START_OF_DAY + Duration::hours(8),
+            SpawnTrip::UsingParkedCar(from, DrivingGoal::ParkNear(to)),
+        )
+    }
+
+    #[test]
+    fn test_rebase_scenario_onto_shuffled_ids() {
+        let mut timer = Timer::throwaway();
+        let old_raw = RawMap::synthetic_grid(3, 2);
+        // Every building gets a new OSM ID, in the opposite order, and one is gone
+        let mut new_raw = RawMap::synthetic_grid(3, 2);
+        new_raw.buildings = std::mem::take(&mut new_raw.buildings)
+            .into_iter()
+            .filter(|(id, _)| *id != way(1007))
+            .map(|(id, b)| match id {
+                osm::OsmID::Way(w) => (way(4000 - w.0), b),
+                _ => (id, b),
+            })
+            .collect();
+        let old = Map::create_from_raw(old_raw, false, &mut timer);
+        let new = Map::create_from_raw(new_raw, false, &mut timer);
+        let old_b = |id: i64| old.find_b_by_osm_id(way(id)).unwrap();
+        let new_b = |id: i64| new.find_b_by_osm_id(way(4000 - id)).unwrap();
+        // The IDs really did change
+        assert_ne!(old_b(1001), new_b(1001));
+
+        let mut scenario = Scenario::empty(&old, "rebase");
+        scenario.people = vec![
+            PersonSpec {
+                id: PersonID(0),
+                orig_id: None,
+                trips: vec![drive(old_b(1001), old_b(1003))],
+            },
+            // Going somewhere that's gone
+            PersonSpec {
+                id: PersonID(1),
+                orig_id: None,
+                trips: vec![drive(old_b(1001), old_b(1007))],
+            },
+            PersonSpec {
+                id: PersonID(2),
+                orig_id: None,
+                trips: vec![IndividTrip::new(
+                    Time::START_OF_DAY + Duration::hours(9),
+                    SpawnTrip::JustWalking(
+                        SidewalkSpot::building(old_b(1002), &old),
+                        SidewalkSpot::building(old_b(1003), &old),
+                    ),
+                )],
+            },
+        ];
+        scenario.households = vec![
+            HouseholdSpec {
+                home: old_b(1001),
+                members: vec![PersonID(0), PersonID(2)],
+                num_cars: 1,
+                joint_trips: Vec::new(),
+            },
+            HouseholdSpec {
+                home: old_b(1001),
+                members: vec![PersonID(1)],
+                num_cars: 1,
+                joint_trips: Vec::new(),
+            },
+        ];
+        let tour = |stops: Vec<BuildingID>| DeliveryTour {
+            depart: Time::START_OF_DAY + Duration::hours(6),
+            vehicle_type: VehicleType::Van,
+            start: TripEndpoint::Bldg(old_b(1004)),
+            stops,
+            end: TripEndpoint::Bldg(old_b(1004)),
+            dwell_time: Duration::minutes(5),
+        };
+        scenario.deliveries = vec![
+            tour(vec![old_b(1003)]),
+            tour(vec![old_b(1003), old_b(1007)]),
+        ];
+
+        let matcher = MapMatcher::new(&old, &new);
+        let (scenario, problems) = scenario.rebase(&matcher);
+
+        // The person, their household, and the tour involving the missing building are dropped
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].starts_with("Dropping"));
+        assert!(problems[0].contains("doesn't match anything"));
+        assert!(problems[1].contains("because some members were dropped"));
+        assert!(problems[2].starts_with("Dropping delivery"));
+
+        // Everybody else refers to the new buildings, and people are renumbered
+        assert_eq!(scenario.people.len(), 2);
+        assert_eq!(scenario.people[0].id, PersonID(0));
+        assert!(matches!(
+            scenario.people[0].trips[0].trip,
+            SpawnTrip::UsingParkedCar(b1, DrivingGoal::ParkNear(b2))
+                if b1 == new_b(1001) && b2 == new_b(1003)
+        ));
+        assert_eq!(scenario.people[1].id, PersonID(1));
+        match scenario.people[1].trips[0].trip {
+            SpawnTrip::JustWalking(ref start, ref goal) => {
+                assert_eq!(start.connection, SidewalkPOI::Building(new_b(1002)));
+                assert_eq!(goal.sidewalk_pos, new.get_b(new_b(1003)).sidewalk_pos);
+            }
+            _ => panic!("wrong trip"),
+        }
+
+        assert_eq!(scenario.households.len(), 1);
+        assert_eq!(scenario.households[0].home, new_b(1001));
+        assert_eq!(
+            scenario.households[0].members,
+            vec![PersonID(0), PersonID(1)]
+        );
+
+        assert_eq!(scenario.deliveries.len(), 1);
+        assert_eq!(scenario.deliveries[0].stops, vec![new_b(1003)]);
+        assert!(matches!(
+            scenario.deliveries[0].start,
+            TripEndpoint::Bldg(b) if b == new_b(1004)
+        ));
+    }
+}
@@ -1,13 +1,13 @@
 use crate::{
-    CarID, DrivingGoal, OrigPersonID, ParkingSpot, PersonID, SidewalkPOI, SidewalkSpot, Sim,
-    TripEndpoint, TripMode, TripSpec, Vehicle, VehicleSpec, VehicleType, BIKE_LENGTH,
-    MAX_CAR_LENGTH, MIN_CAR_LENGTH, SPAWN_DIST,
+    AlertLocation, CarID, DepartureProfile, DrivingGoal, OrigPersonID, ParkingSpot, PersonID,
+    SidewalkPOI, SidewalkSpot, Sim, TripEndpoint, TripMode, TripSpec, Vehicle, VehicleSpec,
+    VehicleType, BIKE_LENGTH, MAX_CAR_LENGTH, MIN_CAR_LENGTH, SPAWN_DIST,
 };
 use abstutil::{prettyprint_usize, Counter, Timer};
-use geom::{Distance, Duration, LonLat, Speed, Time};
+use geom::{Distance, Duration, LonLat, Polygon, Pt2D, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, DirectedRoadID, Map, OffstreetParking, PathConstraints,
-    Position, RoadID,
+    BuildingID, BuildingType, BusRouteID, BusStopID, DirectedRoadID, Map, OffstreetParking,
+    PathConstraints, Position, RoadID,
 };
 use rand::seq::SliceRandom;
 use rand::{Rng, SeedableRng};
@@ -15,6 +15,10 @@ use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
+// Members of a household driving between the same places within this long of each other are
+// assumed to be traveling together.
+const JOINT_TRIP_WINDOW: Duration = Duration::const_seconds(300.0);
+
 // How to start a simulation.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Scenario {
@@ -22,6 +26,8 @@ pub struct Scenario {
     pub map_name: String,
 
     pub people: Vec<PersonSpec>,
+    pub households: Vec<HouseholdSpec>,
+    pub deliveries: Vec<DeliveryTour>,
     // None means seed all buses. Otherwise the route name must be present here.
     pub only_seed_buses: Option<BTreeSet<String>>,
 }
@@ -34,6 +40,53 @@ pub struct PersonSpec {
     pub trips: Vec<IndividTrip>,
 }
 
+// People living in the same building, sharing some cars and sometimes traveling together.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct HouseholdSpec {
+    pub home: BuildingID,
+    pub members: Vec<PersonID>,
+    // These cars start parked at home. A member can drive one if it's parked where their trip
+    // starts and nobody else has taken it. Members who can't get a car take transit or walk
+    // instead.
+    pub num_cars: usize,
+    pub joint_trips: Vec<JointTrip>,
+}
+
+// One member drives and everybody else rides along, getting dropped off at the same destination.
+// Each person is paired with the index of the trip in their schedule. The passengers' trips
+// must start and end in the same place as the driver's, and can't leave after the driver does.
+// Passengers who are ready early wait for the driver.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct JointTrip {
+    pub driver: (PersonID, usize),
+    pub passengers: Vec<(PersonID, usize)>,
+}
+
+// A delivery van or truck visiting a few buildings in order. Nobody rides along; the vehicle just
+// appears at the start, spends some time at each stop, and disappears at the end.
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct DeliveryTour {
+    pub depart: Time,
+    // Van or Truck
+    pub vehicle_type: VehicleType,
+    pub start: TripEndpoint,
+    pub stops: Vec<BuildingID>,
+    pub end: TripEndpoint,
+    // How long to spend loading or unloading at each stop
+    pub dwell_time: Duration,
+}
+
+// How a household affects one member's trip
+#[derive(Clone, Debug)]
+enum HouseholdTrip {
+    // Index into the household's cars
+    SharedCar(usize),
+    // Wanted to drive, but none of the household's cars were available
+    NoCarAvailable,
+    // The driver and the index of their trip
+    RideAlong(PersonID, usize),
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct IndividTrip {
     pub depart: Time,
@@ -79,6 +132,8 @@ pub enum SpawnTrip {
         BusStopID,
         Option<BusStopID>,
     ),
+    // Only between buildings
+    UsingRideHail(BuildingID, BuildingID),
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
         from: OffMapLocation,
@@ -88,6 +143,16 @@ pub enum SpawnTrip {
     },
 }
 
+// Which trips to keep when taking a subset of a scenario. Each filter that's set has to match.
+#[derive(Clone, Debug)]
+pub struct ScenarioFilter {
+    // Inclusive of both ends
+    pub departure: Option<(Time, Time)>,
+    // Both endpoints of the trip must be inside this area
+    pub area: Option<Polygon>,
+    pub modes: Option<BTreeSet<TripMode>>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct OffMapLocation {
     pub parcel_id: usize,
@@ -114,37 +179,121 @@ impl Scenario {
             }
         }
 
+        // A broken household shouldn't ruin everything else. Its members just travel on their own.
+        let mut households: Vec<&HouseholdSpec> = Vec::new();
+        for h in &self.households {
+            match h.check(&self.people, map) {
+                Ok(()) => {
+                    households.push(h);
+                }
+                Err(err) => {
+                    sim.alert(
+                        AlertLocation::Building(h.home),
+                        format!("Ignoring a household: {}", err),
+                    );
+                }
+            }
+        }
+        let mut household_of: BTreeMap<PersonID, usize> = BTreeMap::new();
+        for (idx, h) in households.iter().enumerate() {
+            for id in &h.members {
+                household_of.insert(*id, idx);
+            }
+        }
+        let household_plan = self.plan_households(&households);
+        // The first member of each household to be created owns the shared cars.
+        let mut household_cars: Vec<Option<Vec<Vehicle>>> = vec![None; households.len()];
+
         timer.start_iter("trips for People", self.people.len());
         let mut spawner = sim.make_spawner();
         let mut parked_cars: Vec<(Vehicle, BuildingID)> = Vec::new();
         for p in &self.people {
             timer.next();
 
+            // Households plan trips together, so leave them alone.
+            let adjusted = if household_of.contains_key(&p.id) {
+                None
+            } else {
+                sim.adjust_for_weather(p, map, rng)
+            };
+            let p = adjusted.as_ref().unwrap_or(p);
+
             if let Err(err) = p.check_schedule(map) {
                 panic!("{}", err);
             }
 
-            let (vehicle_specs, cars_initially_parked_at, vehicle_foreach_trip) =
-                p.get_vehicles(rng);
+            let skip_trips: BTreeSet<usize> = household_plan
+                .range((p.id, 0)..=(p.id, usize::MAX))
+                .map(|((_, idx), _)| *idx)
+                .collect();
+            let (mut vehicle_specs, cars_initially_parked_at, vehicle_foreach_trip) =
+                p.get_vehicles(rng, &skip_trips);
+            // Shared cars come after the person's own vehicles.
+            let num_own_vehicles = vehicle_specs.len();
+            let maybe_household = household_of.get(&p.id).cloned();
+            if let Some(h) = maybe_household {
+                if household_cars[h].is_none() {
+                    for _ in 0..households[h].num_cars {
+                        vehicle_specs.push(Scenario::rand_car(rng));
+                    }
+                }
+            }
+            sim.electrify(&mut vehicle_specs, rng);
+            sim.vary_bike_speeds(&mut vehicle_specs, rng);
             sim.new_person(
                 p.id,
                 p.orig_id,
                 Scenario::rand_ped_speed(rng),
                 vehicle_specs,
             );
+            if let Some(h) = maybe_household {
+                if let Some(ref cars) = household_cars[h] {
+                    sim.share_vehicles(p.id, cars.clone());
+                } else {
+                    let cars = sim.get_person(p.id).vehicles[num_own_vehicles..].to_vec();
+                    for car in &cars {
+                        parked_cars.push((car.clone(), households[h].home));
+                    }
+                    household_cars[h] = Some(cars);
+                }
+            }
             let person = sim.get_person(p.id);
             for (idx, b) in cars_initially_parked_at {
                 parked_cars.push((person.vehicles[idx].clone(), b));
             }
-            for (t, maybe_idx) in p.trips.iter().zip(vehicle_foreach_trip) {
+            for (trip_idx, (t, maybe_idx)) in p.trips.iter().zip(vehicle_foreach_trip).enumerate() {
                 // The RNG call might change over edits for picking the spawning lane from a border
                 // with multiple choices for a vehicle type.
                 let mut tmp_rng = abstutil::fork_rng(rng);
-                let spec = t.trip.clone().to_trip_spec(
-                    maybe_idx.map(|idx| person.vehicles[idx].id),
-                    &mut tmp_rng,
-                    map,
-                );
+                let spec = match household_plan.get(&(p.id, trip_idx)) {
+                    None => t.trip.clone().to_trip_spec(
+                        maybe_idx.map(|idx| person.vehicles[idx].id),
+                        &mut tmp_rng,
+                        map,
+                    ),
+                    Some(HouseholdTrip::SharedCar(car)) => t.trip.clone().to_trip_spec(
+                        Some(person.vehicles[num_own_vehicles + car].id),
+                        &mut tmp_rng,
+                        map,
+                    ),
+                    // check() made sure this works
+                    Some(HouseholdTrip::NoCarAvailable) => {
+                        SpawnTrip::new(t.trip.start(map), t.trip.end(map), TripMode::Transit, map)
+                            .unwrap()
+                            .to_trip_spec(None, &mut tmp_rng, map)
+                    }
+                    Some(HouseholdTrip::RideAlong(driver, driver_trip_idx)) => {
+                        TripSpec::RideAlong {
+                            driver: *driver,
+                            driver_trip_idx: *driver_trip_idx,
+                            start_bldg: match t.trip.start(map) {
+                                TripEndpoint::Bldg(b) => b,
+                                TripEndpoint::Border(_, _) => unreachable!(),
+                            },
+                            goal: t.trip.end(map),
+                        }
+                    }
+                };
                 spawner.schedule_trip(
                     person,
                     t.depart,
@@ -161,6 +310,13 @@ impl Scenario {
         parked_cars.shuffle(rng);
         seed_parked_cars(parked_cars, sim, map, rng, timer);
 
+        for tour in &self.deliveries {
+            if let Err(err) = tour.check(map) {
+                panic!("{}", err);
+            }
+            sim.add_delivery_tour(tour.clone());
+        }
+
         sim.flush_spawner(spawner, map, timer);
         timer.stop(format!("Instantiating {}", self.scenario_name));
     }
@@ -177,17 +333,15 @@ impl Scenario {
             scenario_name: name.to_string(),
             map_name: map.get_name().to_string(),
             people: Vec::new(),
+            households: Vec::new(),
+            deliveries: Vec::new(),
             only_seed_buses: Some(BTreeSet::new()),
         }
     }
 
     pub fn rand_car(rng: &mut XorShiftRng) -> VehicleSpec {
         let length = Scenario::rand_dist(rng, MIN_CAR_LENGTH, MAX_CAR_LENGTH);
-        VehicleSpec {
-            vehicle_type: VehicleType::Car,
-            length,
-            max_speed: None,
-        }
+        VehicleSpec::new(VehicleType::Car, length, None)
     }
 
     pub fn rand_bike(rng: &mut XorShiftRng) -> VehicleSpec {
@@ -196,11 +350,18 @@ impl Scenario {
             Speed::miles_per_hour(8.0),
             Speed::miles_per_hour(10.0),
         ));
-        VehicleSpec {
-            vehicle_type: VehicleType::Bike,
-            length: BIKE_LENGTH,
-            max_speed,
-        }
+        VehicleSpec::new(VehicleType::Bike, BIKE_LENGTH, max_speed)
+    }
+
+    // Cyclists ride at very different speeds. Most are cautious, some are confident, and a few
+    // are fast.
+    pub(crate) fn rand_cyclist_speed(rng: &mut XorShiftRng) -> Speed {
+        let (low, high) = match rng.gen_range(0.0, 1.0) {
+            x if x < 0.55 => (6.0, 10.0),
+            x if x < 0.9 => (10.0, 14.0),
+            _ => (14.0, 20.0),
+        };
+        Scenario::rand_speed(rng, Speed::miles_per_hour(low), Speed::miles_per_hour(high))
     }
 
     pub fn rand_dist(rng: &mut XorShiftRng, low: Distance, high: Distance) -> Distance {
@@ -227,36 +388,546 @@ impl Scenario {
         let mut per_bldg = Counter::new();
         // Pass in a dummy RNG
         let mut rng = XorShiftRng::from_seed([0; 16]);
+        let household_plan = self.plan_households();
         for p in &self.people {
-            let (_, cars_initially_parked_at, _) = p.get_vehicles(&mut rng);
+            let skip_trips: BTreeSet<usize> = household_plan
+                .range((p.id, 0)..=(p.id, usize::MAX))
+                .map(|((_, idx), _)| *idx)
+                .collect();
+            let (_, cars_initially_parked_at, _) = p.get_vehicles(&mut rng, &skip_trips);
             for (_, b) in cars_initially_parked_at {
                 per_bldg.inc(b);
             }
         }
+        for h in &self.households {
+            per_bldg.add(h.home, h.num_cars);
+        }
         per_bldg
     }
 
     pub fn remove_weird_schedules(mut self, map: &Map) -> Scenario {
         let orig = self.people.len();
-        self.people
-            .retain(|person| match person.check_schedule(map) {
-                Ok(()) => true,
-                Err(err) => {
-                    println!("{}", err);
-                    false
-                }
-            });
+        let removed = self.split_off_weird_schedules(map);
+        for (_, err) in &removed {
+            println!("{}", err);
+        }
         println!(
             "{} of {} people have nonsense schedules",
-            prettyprint_usize(orig - self.people.len()),
+            prettyprint_usize(removed.len()),
             prettyprint_usize(orig)
         );
+        self
+    }
+
+    // Removes people whose schedule doesn't make sense, and their households, then renumbers
+    // everybody else. Returns the people removed, with their old IDs, and what's wrong.
+    fn split_off_weird_schedules(&mut self, map: &Map) -> Vec<(PersonSpec, String)> {
+        let mut removed = Vec::new();
+        let mut people = Vec::new();
+        for person in self.people.drain(..) {
+            match person.check_schedule(map) {
+                Ok(()) => people.push(person),
+                Err(err) => removed.push((person, err)),
+            }
+        }
+        self.people = people;
         // Fix up IDs
+        let mut new_ids: BTreeMap<PersonID, PersonID> = BTreeMap::new();
         for (idx, person) in self.people.iter_mut().enumerate() {
+            new_ids.insert(person.id, PersonID(idx));
             person.id = PersonID(idx);
         }
+        // Households that lost somebody don't make sense anymore
+        self.households
+            .retain(|h| h.members.iter().all(|id| new_ids.contains_key(id)));
+        for h in &mut self.households {
+            h.renumber(|id| new_ids[&id]);
+        }
+        removed
+    }
+
+    // Shifts everybody's day so their first trip departs following the profile, preserving the
+    // gaps between each person's trips.
+    pub fn reschedule(&mut self, profile: &DepartureProfile, rng: &mut XorShiftRng) {
+        for person in &mut self.people {
+            if person.trips.is_empty() {
+                continue;
+            }
+            let orig_start = person.trips[0].depart;
+            let new_start = profile.sample(rng);
+            for trip in &mut person.trips {
+                trip.depart = new_start + (trip.depart - orig_start);
+                trip.modified = true;
+            }
+        }
+    }
+
+    // Appends all people from the other scenario, renumbering them after the existing people.
+    pub fn merge(mut self, other: Scenario) -> Scenario {
+        assert_eq!(self.map_name, other.map_name);
+        self.scenario_name = format!("{} + {}", self.scenario_name, other.scenario_name);
+        let mut new_ids: BTreeMap<PersonID, PersonID> = BTreeMap::new();
+        for mut p in other.people {
+            let id = PersonID(self.people.len());
+            new_ids.insert(p.id, id);
+            p.id = id;
+            self.people.push(p);
+        }
+        for mut h in other.households {
+            h.renumber(|id| new_ids[&id]);
+            self.households.push(h);
+        }
+        self.deliveries.extend(other.deliveries);
+        self.only_seed_buses = match (self.only_seed_buses, other.only_seed_buses) {
+            (Some(mut routes1), Some(routes2)) => {
+                routes1.extend(routes2);
+                Some(routes1)
+            }
+            // None means all buses
+            _ => None,
+        };
         self
     }
+
+    // Only keeps trips matching the filter. Removing trips from the middle of somebody's day
+    // could leave them warping between places, so those people are dropped entirely. They're
+    // returned, with their old IDs, along with what's wrong with their schedule.
+    pub fn subset(
+        mut self,
+        filter: &ScenarioFilter,
+        map: &Map,
+    ) -> (Scenario, Vec<(PersonSpec, String)>) {
+        // Joint trips refer to trips by index, and removing trips shifts those around. Keep it
+        // simple and just drop households.
+        self.households.clear();
+        self.deliveries
+            .retain(|tour| filter.matches_delivery(tour, map));
+        for person in &mut self.people {
+            person.trips.retain(|trip| filter.matches(trip, map));
+        }
+        self.people.retain(|person| !person.trips.is_empty());
+        let removed = self.split_off_weird_schedules(map);
+        (self, removed)
+    }
+
+    // Groups people whose day starts in the same building into households. People with the same
+    // original household ID are kept together. Each household shares as many cars as its members
+    // would've parked at home individually. When members drive between the same places around
+    // the same time, the last to leave drives everybody, so nobody's left waiting for a car
+    // that's already gone.
+    pub fn group_into_households(&mut self, map: &Map) {
+        let mut groups: BTreeMap<(BuildingID, Option<usize>), Vec<PersonID>> = BTreeMap::new();
+        for p in &self.people {
+            if let Some(trip) = p.trips.get(0) {
+                if let TripEndpoint::Bldg(b) = trip.trip.start(map) {
+                    groups
+                        .entry((b, p.orig_id.map(|id| id.0)))
+                        .or_insert_with(Vec::new)
+                        .push(p.id);
+                }
+            }
+        }
+
+        // Pass in a dummy RNG
+        let mut rng = XorShiftRng::from_seed([0; 16]);
+        self.households.clear();
+        for ((home, _), members) in groups {
+            // Living alone doesn't change anything
+            if members.len() < 2 {
+                continue;
+            }
+
+            // Every car trip from the household, in order of departure
+            let mut car_trips: Vec<(Time, PersonID, usize, TripEndpoint, TripEndpoint)> =
+                Vec::new();
+            for id in &members {
+                for (idx, trip) in self.people[id.0].trips.iter().enumerate() {
+                    if let SpawnTrip::UsingParkedCar(_, _) = trip.trip {
+                        car_trips.push((
+                            trip.depart,
+                            *id,
+                            idx,
+                            trip.trip.start(map),
+                            trip.trip.end(map),
+                        ));
+                    }
+                }
+            }
+            car_trips.sort_by_key(|(depart, id, idx, _, _)| (*depart, *id, *idx));
+
+            let mut joint_trips = Vec::new();
+            // Everybody in some joint trip, including drivers
+            let mut grouped: BTreeSet<(PersonID, usize)> = BTreeSet::new();
+            let mut passengers: BTreeSet<(PersonID, usize)> = BTreeSet::new();
+            for (i, (depart, id, idx, start, end)) in car_trips.iter().enumerate() {
+                if grouped.contains(&(*id, *idx)) {
+                    continue;
+                }
+                // In order of departure
+                let mut group: Vec<(PersonID, usize)> = vec![(*id, *idx)];
+                for (depart2, id2, idx2, start2, end2) in &car_trips[i + 1..] {
+                    if *depart2 - *depart > JOINT_TRIP_WINDOW {
+                        break;
+                    }
+                    if start2 == start
+                        && end2 == end
+                        && !grouped.contains(&(*id2, *idx2))
+                        && group.iter().all(|(p, _)| p != id2)
+                    {
+                        group.push((*id2, *idx2));
+                    }
+                }
+                if group.len() > 1 {
+                    grouped.extend(group.iter().cloned());
+                    let driver = group.pop().unwrap();
+                    passengers.extend(group.iter().cloned());
+                    joint_trips.push(JointTrip {
+                        driver,
+                        passengers: group,
+                    });
+                }
+            }
+
+            let mut num_cars = 0;
+            for id in &members {
+                let skip_trips: BTreeSet<usize> = passengers
+                    .range((*id, 0)..=(*id, usize::MAX))
+                    .map(|(_, idx)| *idx)
+                    .collect();
+                let (_, cars_initially_parked_at, _) =
+                    self.people[id.0].get_vehicles(&mut rng, &skip_trips);
+                num_cars += cars_initially_parked_at
+                    .into_iter()
+                    .filter(|(_, b)| *b == home)
+                    .count();
+            }
+
+            self.households.push(HouseholdSpec {
+                home,
+                members,
+                num_cars,
+                joint_trips,
+            });
+        }
+    }
+
+    fn plan_households(
+        &self,
+        households: &[&HouseholdSpec],
+    ) -> BTreeMap<(PersonID, usize), HouseholdTrip> {
+        let mut plan = BTreeMap::new();
+        for h in households {
+            plan.extend(h.plan(&self.people));
+        }
+        plan
+    }
+}
+
+impl DeliveryTour {
+    // Visits a few commercial buildings, nearest first. Tours come from and leave through borders
+    // when the map has them; otherwise they start and end at a commercial building acting as a
+    // depot.
+    pub fn random(map: &Map, rng: &mut XorShiftRng) -> Option<DeliveryTour> {
+        let commercial: Vec<BuildingID> = map
+            .all_buildings()
+            .iter()
+            .filter(|b| match b.bldg_type {
+                BuildingType::Commercial(_) | BuildingType::ResidentialCommercial(_, _) => {
+                    b.driving_connection(map).is_some()
+                }
+                BuildingType::Residential(_) | BuildingType::Empty => false,
+            })
+            .map(|b| b.id)
+            .collect();
+        let depot = *commercial.choose(rng)?;
+
+        let vehicle_type = if rng.gen_bool(0.25) {
+            VehicleType::Truck
+        } else {
+            VehicleType::Van
+        };
+        let start = map
+            .all_incoming_borders()
+            .choose(rng)
+            .map(|i| TripEndpoint::Border(i.id, None))
+            .unwrap_or(TripEndpoint::Bldg(depot));
+        let end = map
+            .all_outgoing_borders()
+            .choose(rng)
+            .map(|i| TripEndpoint::Border(i.id, None))
+            .unwrap_or(TripEndpoint::Bldg(depot));
+
+        let num_stops = rng.gen_range(2, 8);
+        let mut remaining: Vec<BuildingID> = commercial
+            .choose_multiple(rng, num_stops)
+            .cloned()
+            .collect();
+        let mut pt = match start {
+            TripEndpoint::Bldg(b) => map.get_b(b).polygon.center(),
+            TripEndpoint::Border(i, _) => map.get_i(i).polygon.center(),
+        };
+        let mut stops = Vec::new();
+        while !remaining.is_empty() {
+            let (idx, _) = remaining
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, b)| map.get_b(**b).polygon.center().dist_to(pt))
+                .unwrap();
+            let b = remaining.remove(idx);
+            pt = map.get_b(b).polygon.center();
+            stops.push(b);
+        }
+
+        Some(DeliveryTour {
+            depart: Time::START_OF_DAY
+                + Duration::seconds(rng.gen_range(6.0 * 3600.0, 14.0 * 3600.0)),
+            vehicle_type,
+            start,
+            stops,
+            end,
+            dwell_time: Duration::seconds(rng.gen_range(180.0, 900.0)),
+        })
+    }
+
+    fn check(&self, map: &Map) -> Result<(), String> {
+        if !self.vehicle_type.is_freight() {
+            return Err(format!(
+                "Delivery tour leaving at {} uses a {}, not a van or truck",
+                self.depart, self.vehicle_type
+            ));
+        }
+        if self.stops.is_empty() {
+            return Err(format!(
+                "Delivery tour leaving at {} has no stops",
+                self.depart
+            ));
+        }
+        if let TripEndpoint::Border(i, _) = self.start {
+            if !map.get_i(i).is_incoming_border() {
+                return Err(format!(
+                    "Delivery tour leaving at {} can't start at {}",
+                    self.depart, i
+                ));
+            }
+        }
+        if let TripEndpoint::Border(i, _) = self.end {
+            if !map.get_i(i).is_outgoing_border() {
+                return Err(format!(
+                    "Delivery tour leaving at {} can't end at {}",
+                    self.depart, i
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HouseholdSpec {
+    fn check(&self, people: &[PersonSpec], map: &Map) -> Result<(), String> {
+        for id in &self.members {
+            if id.0 >= people.len() {
+                return Err(format!(
+                    "Household at {} has unknown member {}",
+                    self.home, id
+                ));
+            }
+        }
+        for jt in &self.joint_trips {
+            let driver = self.lookup(people, jt.driver)?;
+            for (id, idx) in &jt.passengers {
+                if *id == jt.driver.0 {
+                    return Err(format!("{} can't ride along with themselves", id));
+                }
+                let trip = self.lookup(people, (*id, *idx))?;
+                if let TripEndpoint::Border(_, _) = trip.trip.start(map) {
+                    return Err(format!(
+                        "{}'s trip #{} has to start in a building to ride along",
+                        id, idx
+                    ));
+                }
+                if trip.trip.start(map) != driver.trip.start(map)
+                    || trip.trip.end(map) != driver.trip.end(map)
+                {
+                    return Err(format!(
+                        "{}'s trip #{} doesn't go between the same places as {}'s trip #{}",
+                        id, idx, jt.driver.0, jt.driver.1
+                    ));
+                }
+                if trip.depart > driver.depart {
+                    return Err(format!(
+                        "{}'s trip #{} leaves after {}'s trip #{}, so the car would be gone",
+                        id, idx, jt.driver.0, jt.driver.1
+                    ));
+                }
+            }
+        }
+        // Anybody who can't get a car needs some other way to get around
+        for id in &self.members {
+            for (idx, trip) in people[id.0].trips.iter().enumerate() {
+                if let SpawnTrip::UsingParkedCar(_, _) = trip.trip {
+                    if SpawnTrip::new(
+                        trip.trip.start(map),
+                        trip.trip.end(map),
+                        TripMode::Transit,
+                        map,
+                    )
+                    .is_none()
+                    {
+                        return Err(format!(
+                            "{}'s trip #{} has no alternative if no car is available",
+                            id, idx
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn lookup<'a>(
+        &self,
+        people: &'a [PersonSpec],
+        (id, idx): (PersonID, usize),
+    ) -> Result<&'a IndividTrip, String> {
+        if !self.members.contains(&id) {
+            return Err(format!("{} isn't in the household at {}", id, self.home));
+        }
+        people[id.0]
+            .trips
+            .get(idx)
+            .ok_or_else(|| format!("{} doesn't have a trip #{}", id, idx))
+    }
+
+    // Figures out who drives which car for every member's car trip. Cars are handed out in order
+    // of departure, so the same trips always get the same cars.
+    fn plan(&self, people: &[PersonSpec]) -> BTreeMap<(PersonID, usize), HouseholdTrip> {
+        let is_car_trip = |(id, idx): (PersonID, usize)| match people[id.0].trips[idx].trip {
+            SpawnTrip::UsingParkedCar(_, _) => true,
+            _ => false,
+        };
+
+        // Passengers don't need their own car
+        let mut passengers: BTreeSet<(PersonID, usize)> = BTreeSet::new();
+        for jt in &self.joint_trips {
+            if is_car_trip(jt.driver) {
+                passengers.extend(jt.passengers.iter().cloned());
+            }
+        }
+
+        let mut car_trips = Vec::new();
+        for id in &self.members {
+            for (idx, trip) in people[id.0].trips.iter().enumerate() {
+                if let SpawnTrip::UsingParkedCar(b, ref goal) = trip.trip {
+                    if !passengers.contains(&(*id, idx)) {
+                        car_trips.push((trip.depart, *id, idx, b, goal));
+                    }
+                }
+            }
+        }
+        car_trips.sort_by_key(|(depart, id, idx, _, _)| (*depart, *id, *idx));
+
+        let mut plan = BTreeMap::new();
+        // Where is each car parked? None means off-map.
+        let mut car_locations: Vec<Option<BuildingID>> = vec![Some(self.home); self.num_cars];
+        for (_, id, idx, b, goal) in car_trips {
+            if let Some(car) = car_locations.iter().position(|at| *at == Some(b)) {
+                car_locations[car] = match goal {
+                    DrivingGoal::ParkNear(b) => Some(*b),
+                    DrivingGoal::Border(_, _, _) => None,
+                };
+                plan.insert((id, idx), HouseholdTrip::SharedCar(car));
+            } else {
+                plan.insert((id, idx), HouseholdTrip::NoCarAvailable);
+            }
+        }
+
+        for jt in &self.joint_trips {
+            if !is_car_trip(jt.driver) {
+                continue;
+            }
+            let has_car = matches!(plan.get(&jt.driver), Some(HouseholdTrip::SharedCar(_)));
+            for passenger in &jt.passengers {
+                if has_car {
+                    plan.insert(
+                        *passenger,
+                        HouseholdTrip::RideAlong(jt.driver.0, jt.driver.1),
+                    );
+                } else if is_car_trip(*passenger) {
+                    plan.insert(*passenger, HouseholdTrip::NoCarAvailable);
+                }
+            }
+        }
+        plan
+    }
+
+    pub(crate) fn renumber<F: Fn(PersonID) -> PersonID>(&mut self, f: F) {
+        for id in &mut self.members {
+            *id = f(*id);
+        }
+        for jt in &mut self.joint_trips {
+            jt.driver.0 = f(jt.driver.0);
+            for (id, _) in &mut jt.passengers {
+                *id = f(*id);
+            }
+        }
+    }
+}
+
+impl ScenarioFilter {
+    pub fn matches(&self, trip: &IndividTrip, map: &Map) -> bool {
+        if let Some((t1, t2)) = self.departure {
+            if trip.depart < t1 || trip.depart > t2 {
+                return false;
+            }
+        }
+        if let Some(ref modes) = self.modes {
+            if !modes.contains(&trip.trip.mode()) {
+                return false;
+            }
+        }
+        if let Some(ref area) = self.area {
+            for endpt in &[trip.trip.start(map), trip.trip.end(map)] {
+                let pt = match endpt {
+                    TripEndpoint::Bldg(b) => map.get_b(*b).polygon.center(),
+                    TripEndpoint::Border(i, _) => map.get_i(*i).polygon.center(),
+                };
+                if !area.contains_pt(pt) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // Deliveries aren't any TripMode, so they're dropped when filtering by mode. Every stop has to
+    // be inside the area.
+    pub fn matches_delivery(&self, tour: &DeliveryTour, map: &Map) -> bool {
+        if let Some((t1, t2)) = self.departure {
+            if tour.depart < t1 || tour.depart > t2 {
+                return false;
+            }
+        }
+        if self.modes.is_some() {
+            return false;
+        }
+        if let Some(ref area) = self.area {
+            let mut pts: Vec<Pt2D> = tour
+                .stops
+                .iter()
+                .map(|b| map.get_b(*b).polygon.center())
+                .collect();
+            for endpt in &[&tour.start, &tour.end] {
+                pts.push(match endpt {
+                    TripEndpoint::Bldg(b) => map.get_b(*b).polygon.center(),
+                    TripEndpoint::Border(i, _) => map.get_i(*i).polygon.center(),
+                });
+            }
+            if pts.into_iter().any(|pt| !area.contains_pt(pt)) {
+                return false;
+            }
+        }
+        true
+    }
 }
 
 fn seed_parked_cars(
@@ -424,6 +1095,9 @@ impl SpawnTrip {
                     maybe_stop2,
                 }
             }
+            SpawnTrip::UsingRideHail(start_bldg, goal) => {
+                TripSpec::UsingRideHail { start_bldg, goal }
+            }
             SpawnTrip::Remote {
                 from,
                 to,
@@ -459,6 +1133,7 @@ impl SpawnTrip {
             SpawnTrip::UsingBike(_, _) => TripMode::Bike,
             SpawnTrip::JustWalking(_, _) => TripMode::Walk,
             SpawnTrip::UsingTransit(_, _, _, _, _) => TripMode::Transit,
+            SpawnTrip::UsingRideHail(_, _) => TripMode::RideHail,
             // TODO Uh...
             SpawnTrip::Remote { .. } => TripMode::Drive,
         }
@@ -474,6 +1149,7 @@ impl SpawnTrip {
             }
             SpawnTrip::UsingParkedCar(b, _) => TripEndpoint::Bldg(*b),
             SpawnTrip::UsingBike(b, _) => TripEndpoint::Bldg(*b),
+            SpawnTrip::UsingRideHail(b, _) => TripEndpoint::Bldg(*b),
             SpawnTrip::JustWalking(ref spot, _) | SpawnTrip::UsingTransit(ref spot, _, _, _, _) => {
                 match spot.connection {
                     SidewalkPOI::Building(b) => TripEndpoint::Bldg(b),
@@ -507,6 +1183,7 @@ impl SpawnTrip {
                     _ => unreachable!(),
                 }
             }
+            SpawnTrip::UsingRideHail(_, b) => TripEndpoint::Bldg(*b),
             // Pick an arbitrary border
             SpawnTrip::Remote { ref to, .. } => {
                 TripEndpoint::Border(map.all_incoming_borders()[0].id, Some(to.clone()))
@@ -559,6 +1236,14 @@ impl SpawnTrip {
                     SpawnTrip::JustWalking(start, goal)
                 }
             }
+            TripMode::RideHail => match (from, to) {
+                (TripEndpoint::Bldg(b1), TripEndpoint::Bldg(b2)) => {
+                    SpawnTrip::UsingRideHail(b1, b2)
+                }
+                _ => {
+                    return None;
+                }
+            },
         })
     }
 }
@@ -606,9 +1291,11 @@ impl PersonSpec {
         Ok(())
     }
 
+    // Trips in skip_trips are handled by the person's household and don't need a vehicle.
     fn get_vehicles(
         &self,
         rng: &mut XorShiftRng,
+        skip_trips: &BTreeSet<usize>,
     ) -> (
         Vec<VehicleSpec>,
         Vec<(usize, BuildingID)>,
@@ -623,7 +1310,11 @@ impl PersonSpec {
         let mut car_locations: Vec<(usize, Option<BuildingID>)> = Vec::new();
 
         // TODO If the trip is cancelled, this should be affected...
-        for trip in &self.trips {
+        for (idx, trip) in self.trips.iter().enumerate() {
+            if skip_trips.contains(&idx) {
+                vehicle_foreach_trip.push(None);
+                continue;
+            }
             let use_for_trip = match trip.trip {
                 SpawnTrip::VehicleAppearing {
                     is_bike, ref goal, ..
@@ -703,7 +1394,7 @@ impl PersonSpec {
                     bike_idx
                 }
                 SpawnTrip::JustWalking(_, _) | SpawnTrip::UsingTransit(_, _, _, _, _) => None,
-                SpawnTrip::Remote { .. } => None,
+                SpawnTrip::UsingRideHail(_, _) | SpawnTrip::Remote { .. } => None,
             };
             vehicle_foreach_trip.push(use_for_trip);
         }
@@ -726,3 +1417,302 @@ impl PersonSpec {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ScenarioDiff, SimOptions};
+    use map_model::raw::RawMap;
+
+    fn at(hours: usize, minutes: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(hours) + Duration::minutes(minutes)
+    }
+
+    // Drives to work and back
+    fn commuter(
+        id: usize,
+        household: Option<usize>,
+        (home, work): (BuildingID, BuildingID),
+        leave: Time,
+        come_back: Time,
+    ) -> PersonSpec {
+        PersonSpec {
+            id: PersonID(id),
+            orig_id: household.map(|h| OrigPersonID(h, id)),
+            trips: vec![
+                IndividTrip::new(
+                    leave,
+                    SpawnTrip::UsingParkedCar(home, DrivingGoal::ParkNear(work)),
+                ),
+                IndividTrip::new(
+                    come_back,
+                    SpawnTrip::UsingParkedCar(work, DrivingGoal::ParkNear(home)),
+                ),
+            ],
+        }
+    }
+
+    fn setup() -> (Map, (BuildingID, BuildingID)) {
+        let map =
+            Map::create_from_raw(RawMap::synthetic_grid(3, 3), false, &mut Timer::throwaway());
+        let places = (BuildingID(0), BuildingID(map.all_buildings().len() - 1));
+        (map, places)
+    }
+
+    #[test]
+    fn test_group_into_households() {
+        let (map, places) = setup();
+        let mut scenario = Scenario::empty(&map, "households");
+        scenario.people = vec![
+            commuter(0, Some(1), places, at(8, 0), at(17, 0)),
+            // Leaves a little later in the morning, but not in the evening
+            commuter(1, Some(1), places, at(8, 3), at(17, 10)),
+            // Lives in the same place, but in a different household
+            commuter(2, Some(2), places, at(8, 0), at(17, 0)),
+        ];
+        scenario.group_into_households(&map);
+
+        // Living alone doesn't make a household
+        assert_eq!(scenario.households.len(), 1);
+        let h = &scenario.households[0];
+        assert_eq!(h.home, places.0);
+        assert_eq!(h.members, vec![PersonID(0), PersonID(1)]);
+        // The last to leave drives
+        assert_eq!(h.joint_trips.len(), 1);
+        assert_eq!(h.joint_trips[0].driver, (PersonID(1), 0));
+        assert_eq!(h.joint_trips[0].passengers, vec![(PersonID(0), 0)]);
+        // Only one car is needed in the morning
+        assert_eq!(h.num_cars, 1);
+
+        // The household's one car is shared. Whoever leaves work first takes it home.
+        let plan = scenario.plan_households(&[h]);
+        assert!(matches!(
+            plan[&(PersonID(0), 0)],
+            HouseholdTrip::RideAlong(PersonID(1), 0)
+        ));
+        assert!(matches!(
+            plan[&(PersonID(1), 0)],
+            HouseholdTrip::SharedCar(0)
+        ));
+        assert!(matches!(
+            plan[&(PersonID(0), 1)],
+            HouseholdTrip::SharedCar(0)
+        ));
+        assert!(matches!(
+            plan[&(PersonID(1), 1)],
+            HouseholdTrip::NoCarAvailable
+        ));
+    }
+
+    #[test]
+    fn test_joint_trip_window() {
+        let (map, places) = setup();
+        let mut scenario = Scenario::empty(&map, "households");
+        scenario.people = vec![
+            commuter(0, Some(1), places, at(8, 0), at(17, 0)),
+            commuter(1, Some(1), places, at(8, 6), at(17, 0)),
+        ];
+        scenario.group_into_households(&map);
+
+        // Too far apart in the morning, so both need a car
+        let h = &scenario.households[0];
+        assert_eq!(h.joint_trips.len(), 1);
+        assert_eq!(h.joint_trips[0].driver, (PersonID(1), 1));
+        assert_eq!(h.joint_trips[0].passengers, vec![(PersonID(0), 1)]);
+        assert_eq!(h.num_cars, 2);
+        let plan = scenario.plan_households(&[h]);
+        assert!(matches!(
+            plan[&(PersonID(0), 0)],
+            HouseholdTrip::SharedCar(_)
+        ));
+        assert!(matches!(
+            plan[&(PersonID(1), 0)],
+            HouseholdTrip::SharedCar(_)
+        ));
+    }
+
+    #[test]
+    fn test_broken_household() {
+        let (map, places) = setup();
+        let mut scenario = Scenario::empty(&map, "households");
+        scenario.people = vec![
+            commuter(0, None, places, at(8, 5), at(17, 0)),
+            commuter(1, None, places, at(8, 0), at(17, 0)),
+        ];
+        // The passenger would leave after the driver's gone
+        scenario.households = vec![HouseholdSpec {
+            home: places.0,
+            members: vec![PersonID(0), PersonID(1)],
+            num_cars: 1,
+            joint_trips: vec![JointTrip {
+                driver: (PersonID(1), 0),
+                passengers: vec![(PersonID(0), 0)],
+            }],
+        }];
+
+        let mut timer = Timer::throwaway();
+        let mut sim = Sim::new(&map, SimOptions::new("test"), &mut timer);
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        scenario.instantiate(&mut sim, &map, &mut rng, &mut timer);
+
+        // Everybody still travels on their own
+        let alerts = sim.clear_alerts();
+        assert_eq!(alerts.len(), 1);
+        assert!(alerts[0].2.contains("so the car would be gone"));
+        for id in [PersonID(0), PersonID(1)].iter() {
+            assert_eq!(sim.get_person(*id).vehicles.len(), 1);
+        }
+    }
+
+    fn walk(from: BuildingID, to: BuildingID, depart: Time, map: &Map) -> IndividTrip {
+        IndividTrip::new(
+            depart,
+            SpawnTrip::JustWalking(
+                SidewalkSpot::building(from, map),
+                SidewalkSpot::building(to, map),
+            ),
+        )
+    }
+
+    #[test]
+    fn test_diff() {
+        let (map, places) = setup();
+        let mut before = Scenario::empty(&map, "before");
+        before.people = vec![
+            commuter(0, None, places, at(8, 0), at(17, 0)),
+            commuter(1, None, places, at(8, 0), at(17, 0)),
+        ];
+        assert!(ScenarioDiff::new(&before, &before, &map).is_empty());
+
+        let mut after = before.clone();
+        // Leaves later, and walks back to work in the evening
+        after.people[0].trips[0].depart = at(9, 30);
+        after.people[0]
+            .trips
+            .push(walk(places.0, places.1, at(19, 0), &map));
+        // Somebody else replaces the second person
+        after.people[1] = commuter(2, None, places, at(8, 0), at(17, 0));
+
+        let diff = ScenarioDiff::new(&before, &after, &map);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.people_added, vec![PersonID(2)]);
+        assert_eq!(diff.people_removed, vec![PersonID(1)]);
+        assert_eq!(diff.trips_added, vec![(PersonID(0), 2)]);
+        assert!(diff.trips_removed.is_empty());
+        assert_eq!(diff.trips_changed.len(), 1);
+        let change = &diff.trips_changed[0];
+        assert_eq!((change.person, change.trip_idx), (PersonID(0), 0));
+        assert_eq!(change.depart, Some((at(8, 0), at(9, 30))));
+        assert!(change.mode.is_none() && change.start.is_none() && change.end.is_none());
+
+        // Only the counts that changed
+        assert_eq!(diff.trips_per_mode_and_hour.len(), 3);
+        for entry in &[
+            ((TripMode::Drive, 8), 2, 1),
+            ((TripMode::Drive, 9), 0, 1),
+            ((TripMode::Walk, 19), 0, 1),
+        ] {
+            assert!(diff.trips_per_mode_and_hour.contains(entry));
+        }
+        assert_eq!(diff.describe()[0], "1 people added, 1 people removed");
+    }
+
+    #[test]
+    fn test_merge() {
+        let (map, places) = setup();
+        let mut first = Scenario::empty(&map, "first");
+        first.people = vec![commuter(0, None, places, at(8, 0), at(17, 0))];
+        first.only_seed_buses = Some(vec!["A".to_string()].into_iter().collect());
+        let mut second = Scenario::empty(&map, "second");
+        second.people = vec![
+            commuter(0, None, places, at(8, 0), at(17, 0)),
+            commuter(1, None, places, at(8, 3), at(17, 0)),
+        ];
+        second.households = vec![HouseholdSpec {
+            home: places.0,
+            members: vec![PersonID(0), PersonID(1)],
+            num_cars: 1,
+            joint_trips: vec![JointTrip {
+                driver: (PersonID(1), 0),
+                passengers: vec![(PersonID(0), 0)],
+            }],
+        }];
+        second.only_seed_buses = Some(vec!["B".to_string()].into_iter().collect());
+
+        let merged = first.clone().merge(second.clone());
+        assert_eq!(merged.scenario_name, "first + second");
+        assert_eq!(
+            merged.people.iter().map(|p| p.id).collect::<Vec<_>>(),
+            vec![PersonID(0), PersonID(1), PersonID(2)]
+        );
+        // The household follows its members
+        let h = &merged.households[0];
+        assert_eq!(h.members, vec![PersonID(1), PersonID(2)]);
+        assert_eq!(h.joint_trips[0].driver, (PersonID(2), 0));
+        assert_eq!(h.joint_trips[0].passengers, vec![(PersonID(1), 0)]);
+        assert_eq!(
+            merged.only_seed_buses,
+            Some(vec!["A".to_string(), "B".to_string()].into_iter().collect())
+        );
+
+        // Compared to the first scenario, only the second's people are new
+        let diff = ScenarioDiff::new(&first, &merged, &map);
+        assert_eq!(diff.people_added, vec![PersonID(1), PersonID(2)]);
+        assert!(diff.people_removed.is_empty());
+        assert!(diff.trips_added.is_empty() && diff.trips_changed.is_empty());
+
+        // Seeding every bus wins
+        first.only_seed_buses = None;
+        assert_eq!(first.merge(second).only_seed_buses, None);
+    }
+
+    #[test]
+    fn test_subset() {
+        let (map, places) = setup();
+        let shop = BuildingID(1);
+        let mut scenario = Scenario::empty(&map, "subset");
+        // Walks from work to a shop at lunch, and drives home from there
+        let mut errands = commuter(0, Some(1), places, at(8, 0), at(17, 0));
+        errands.trips[1] = IndividTrip::new(
+            at(17, 0),
+            SpawnTrip::UsingParkedCar(shop, DrivingGoal::ParkNear(places.0)),
+        );
+        errands
+            .trips
+            .insert(1, walk(places.1, shop, at(12, 0), &map));
+        scenario.people = vec![errands, commuter(1, Some(2), places, at(8, 0), at(17, 0))];
+        scenario.households = vec![HouseholdSpec {
+            home: places.0,
+            members: vec![PersonID(0), PersonID(1)],
+            num_cars: 2,
+            joint_trips: Vec::new(),
+        }];
+
+        // Keeping everything only drops households
+        let everything = ScenarioFilter {
+            departure: None,
+            area: None,
+            modes: None,
+        };
+        let (all, removed) = scenario.clone().subset(&everything, &map);
+        assert!(removed.is_empty());
+        assert!(all.households.is_empty());
+        assert!(ScenarioDiff::new(&scenario, &all, &map).is_empty());
+
+        // Without the walk, the first person warps from work to the shop
+        let driving = ScenarioFilter {
+            departure: None,
+            area: None,
+            modes: Some(vec![TripMode::Drive].into_iter().collect()),
+        };
+        let (subset, removed) = scenario.subset(&driving, &map);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].0.id, PersonID(0));
+        assert!(removed[0].1.contains("warps between some trips"));
+        // The other person is renumbered
+        assert_eq!(subset.people.len(), 1);
+        assert_eq!(subset.people[0].id, PersonID(0));
+        assert_eq!(subset.people[0].orig_id, Some(OrigPersonID(2, 1)));
+        assert_eq!(subset.people[0].trips.len(), 2);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::{
     CarID, Command, DrivingGoal, OffMapLocation, Person, PersonID, Scheduler, SidewalkSpot,
-    TripEndpoint, TripLeg, TripManager, TripMode, VehicleType,
+    TripEndpoint, TripID, TripLeg, TripManager, TripMode, VehicleType,
 };
 use abstutil::{Parallelism, Timer};
 use geom::{Duration, Time};
@@ -8,6 +8,7 @@ use map_model::{
     BuildingID, BusRouteID, BusStopID, IntersectionID, Map, PathConstraints, PathRequest, Position,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 // TODO Some of these fields are unused now that we separately pass TripEndpoint
 #[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
@@ -52,6 +53,10 @@ pub enum TripSpec {
         stop1: BusStopID,
         maybe_stop2: Option<BusStopID>,
     },
+    UsingRideHail {
+        start_bldg: BuildingID,
+        goal: BuildingID,
+    },
     // Completely off-map trip. Don't really simulate much of it.
     Remote {
         from: OffMapLocation,
@@ -59,6 +64,14 @@ pub enum TripSpec {
         trip_time: Duration,
         mode: TripMode,
     },
+    // Ride along with somebody else for one of their trips, specified by index in their schedule.
+    // The goal must match the driver's.
+    RideAlong {
+        driver: PersonID,
+        driver_trip_idx: usize,
+        start_bldg: BuildingID,
+        goal: TripEndpoint,
+    },
 }
 
 // This structure is created temporarily by a Scenario or to interactively spawn agents.
@@ -176,7 +189,9 @@ impl TripSpawner {
                 }
             }
             TripSpec::UsingTransit { .. } => {}
+            TripSpec::UsingRideHail { .. } => {}
             TripSpec::Remote { .. } => {}
+            TripSpec::RideAlong { .. } => {}
         };
 
         self.trips
@@ -216,6 +231,22 @@ impl TripSpawner {
             abstutil::stop_profiler();
         }
 
+        // Passengers need to know their driver's trip, which might not be created yet. Trips are
+        // numbered in order, so figure out the IDs upfront.
+        let mut trip_ids: BTreeMap<(PersonID, usize), TripID> = BTreeMap::new();
+        {
+            let (finished, unfinished) = trips.num_trips();
+            let mut trips_per_person: BTreeMap<PersonID, usize> = BTreeMap::new();
+            for (idx, ((p, _, _, _, _, _), _, _)) in paths.iter().enumerate() {
+                let n = trips_per_person
+                    .entry(*p)
+                    .or_insert_with(|| trips.get_person(*p).unwrap().trips.len());
+                trip_ids.insert((*p, *n), TripID(finished + unfinished + idx));
+                *n += 1;
+            }
+        }
+        let mut passengers: Vec<(TripID, TripID)> = Vec::new();
+
         timer.start_iter("spawn trips", paths.len());
         for ((p, start_time, spec, trip_start, cancelled, modified), maybe_req, maybe_path) in paths
         {
@@ -351,6 +382,15 @@ impl TripSpawner {
                         map,
                     )
                 }
+                TripSpec::UsingRideHail { goal, .. } => trips.new_trip(
+                    person.id,
+                    start_time,
+                    trip_start,
+                    TripMode::RideHail,
+                    modified,
+                    vec![TripLeg::RideHail(goal)],
+                    map,
+                ),
                 TripSpec::Remote { to, mode, .. } => trips.new_trip(
                     person.id,
                     start_time,
@@ -360,6 +400,25 @@ impl TripSpawner {
                     vec![TripLeg::Remote(to)],
                     map,
                 ),
+                TripSpec::RideAlong {
+                    driver,
+                    driver_trip_idx,
+                    goal,
+                    ..
+                } => {
+                    let driver_trip = trip_ids[&(driver, driver_trip_idx)];
+                    let trip = trips.new_trip(
+                        person.id,
+                        start_time,
+                        trip_start,
+                        TripMode::Drive,
+                        modified,
+                        vec![TripLeg::RideAlong(driver_trip, goal)],
+                        map,
+                    );
+                    passengers.push((driver_trip, trip));
+                    trip
+                }
             };
 
             if cancelled {
@@ -371,6 +430,10 @@ impl TripSpawner {
                 );
             }
         }
+
+        for (driver, passenger) in passengers {
+            trips.add_passenger(driver, passenger);
+        }
     }
 }
 
@@ -412,7 +475,10 @@ impl TripSpec {
                 end: SidewalkSpot::bus_stop(*stop1, map).sidewalk_pos,
                 constraints: PathConstraints::Pedestrian,
             }),
+            // The vehicle is picked when the ride is requested
+            TripSpec::UsingRideHail { .. } => None,
             TripSpec::Remote { .. } => None,
+            TripSpec::RideAlong { .. } => None,
         }
     }
 }
@@ -1,8 +1,10 @@
+use crate::emissions::{estimate_electric, estimate_emissions};
 use crate::{
-    CarStatus, DistanceInterval, DrawCarInput, ParkingSpot, PersonID, Router, TimeInterval,
-    TransitSimState, TripID, Vehicle, VehicleType,
+    bike_speed_on_grade, CarStatus, DistanceInterval, DrawCarInput, Emissions,
+    IntersectionSimState, ParkingSpot, PersonID, Router, TimeInterval, TransitSimState, TripID,
+    Vehicle, VehicleType, Weather,
 };
-use geom::{Distance, Duration, PolyLine, Time};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
 use map_model::{Direction, Map, Traversable};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
@@ -21,11 +23,26 @@ pub struct Car {
     // In reverse order -- most recently left is first. The sum length of these must be >=
     // vehicle.length.
     pub last_steps: VecDeque<Traversable>,
+
+    // Accelerate and brake, instead of instantly changing speed. From SimOptions::kinematics.
+    pub kinematics: bool,
+    // With kinematics, how fast the car was going when it last finished crossing something
+    pub last_speed: Speed,
+    // For estimating emissions: when the car started its current step, how far along it started,
+    // and whether it started from rest
+    pub step_started: (Time, Distance, bool),
 }
 
 impl Car {
     // Assumes the current head of the path is the thing to cross.
-    pub fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> CarState {
+    pub fn crossing_state(
+        &self,
+        start_dist: Distance,
+        start_time: Time,
+        map: &Map,
+        intersections: &IntersectionSimState,
+        weather: &Weather,
+    ) -> CarState {
         let dist_int = DistanceInterval::new_driving(
             start_dist,
             if self.router.last_step() {
@@ -34,7 +51,7 @@ impl Car {
                 self.router.head().length(map)
             },
         );
-        self.crossing_state_with_end_dist(dist_int, start_time, map)
+        self.crossing_state_with_end_dist(dist_int, start_time, map, intersections, weather)
     }
 
     pub fn crossing_state_with_end_dist(
@@ -42,14 +59,152 @@ impl Car {
         dist_int: DistanceInterval,
         start_time: Time,
         map: &Map,
+        intersections: &IntersectionSimState,
+        weather: &Weather,
     ) -> CarState {
         let on = self.router.head();
+        let speed = self.cruising_speed_along(on, &dist_int, start_time, map, weather);
+        if !self.kinematics {
+            let dt = (dist_int.end - dist_int.start) / speed;
+            return CarState::Crossing(
+                TimeInterval::new(start_time, start_time + dt),
+                dist_int,
+                None,
+            );
+        }
+
+        let dist = dist_int.end - dist_int.start;
+        let start_speed = self.current_speed(start_time);
+        let profile = |end_speed| {
+            SpeedProfile::new(
+                dist,
+                start_speed,
+                speed,
+                end_speed,
+                self.vehicle.max_accel,
+                self.vehicle.max_decel,
+            )
+        };
+        // Only brake when reaching the end of something, not when the crossing is cut short
+        let end_speed = if self.router.last_step() {
+            if dist_int.end == self.router.get_end_dist() {
+                Speed::ZERO
+            } else {
+                speed
+            }
+        } else if dist_int.end < on.length(map) {
+            speed
+        } else {
+            match self.router.next() {
+                Traversable::Turn(t) => {
+                    let arrival = start_time + profile(speed).duration();
+                    if intersections.expect_to_roll_through(t, arrival, map) {
+                        speed
+                    } else {
+                        Speed::ZERO
+                    }
+                }
+                next => speed.min(next.speed_limit(map)),
+            }
+        };
+        let profile = profile(end_speed);
+        CarState::Crossing(
+            TimeInterval::new(start_time, start_time + profile.duration()),
+            dist_int,
+            Some(profile),
+        )
+    }
+
+    // How fast this vehicle goes along something when nothing's in the way. Bad weather and
+    // darkness slow everybody down, and make vehicles take longer to get through turns.
+    pub fn cruising_speed(
+        &self,
+        on: Traversable,
+        now: Time,
+        map: &Map,
+        weather: &Weather,
+    ) -> Speed {
+        self.cruising_speed_along(
+            on,
+            &DistanceInterval::new_driving(Distance::ZERO, on.length(map)),
+            now,
+            map,
+            weather,
+        )
+    }
+
+    // Like cruising_speed, but only considering part of the lane or turn. Bikes care about how
+    // steep that part is.
+    fn cruising_speed_along(
+        &self,
+        on: Traversable,
+        dist_int: &DistanceInterval,
+        now: Time,
+        map: &Map,
+        weather: &Weather,
+    ) -> Speed {
         let mut speed = on.speed_limit(map);
         if let Some(s) = self.vehicle.max_speed {
             speed = speed.min(s);
         }
-        let dt = (dist_int.end - dist_int.start) / speed;
-        CarState::Crossing(TimeInterval::new(start_time, start_time + dt), dist_int)
+        if self.vehicle.vehicle_type == VehicleType::Bike {
+            if let Traversable::Lane(l) = on {
+                let grade = map
+                    .get_l(l)
+                    .percent_grade_between(map, dist_int.start, dist_int.end);
+                speed = bike_speed_on_grade(speed, grade).min(on.speed_limit(map));
+            }
+        }
+        speed = speed * weather.vehicle_speed_factor(self.vehicle.vehicle_type, now);
+        if let Traversable::Turn(_) = on {
+            speed = speed * (1.0 / weather.headway_factor(now));
+        }
+        speed
+    }
+
+    // Estimate what the car emitted on its current step, now that it's reached dist. idle is how
+    // long the car waited at the end of the step for the intersection to let it through.
+    pub fn emissions_on_current_step(
+        &self,
+        dist: Distance,
+        idle: Duration,
+        now: Time,
+        map: &Map,
+    ) -> Emissions {
+        let (start_time, start_dist, from_rest) = self.step_started;
+        let total = now - start_time;
+        let idle = idle.max(Duration::ZERO).min(total);
+        let grade = match self.router.head() {
+            Traversable::Lane(l) => map.get_l(l).percent_grade_between(map, start_dist, dist),
+            Traversable::Turn(_) => 0.0,
+        };
+        if let Some(ref battery) = self.vehicle.battery {
+            return estimate_electric(self.vehicle.vehicle_type, battery, dist - start_dist, grade);
+        }
+        estimate_emissions(
+            self.vehicle.vehicle_type,
+            dist - start_dist,
+            total - idle,
+            idle,
+            grade,
+            from_rest,
+        )
+    }
+
+    // With kinematics, how fast the car is going right now
+    pub fn current_speed(&self, now: Time) -> Speed {
+        match self.state {
+            CarState::Crossing(ref time_int, _, Some(ref profile)) => {
+                profile.speed_at(now - time_int.start)
+            }
+            // If the car didn't actually have to wait, it hasn't slowed down.
+            CarState::Queued { blocked_since } | CarState::WaitingToAdvance { blocked_since }
+                if blocked_since == now =>
+            {
+                self.last_speed
+            }
+            _ => Speed::ZERO,
+        }
     }
 
     pub fn get_draw_car(
@@ -210,7 +365,7 @@ impl Car {
             status: match self.state {
                 CarState::Queued { .. } => CarStatus::Moving,
                 CarState::WaitingToAdvance { .. } => CarStatus::Moving,
-                CarState::Crossing(_, _) => CarStatus::Moving,
+                CarState::Crossing(_, _, _) => CarStatus::Moving,
                 // Eh they're technically moving, but this is a bit easier to spot
                 CarState::Unparking(_, _, _) => CarStatus::Parked,
                 CarState::Parking(_, _, _) => CarStatus::Parked,
@@ -244,7 +399,8 @@ impl Car {
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum CarState {
-    Crossing(TimeInterval, DistanceInterval),
+    // The profile is only used with kinematics; otherwise the car crosses at a constant speed.
+    Crossing(TimeInterval, DistanceInterval, Option<SpeedProfile>),
     Queued { blocked_since: Time },
     WaitingToAdvance { blocked_since: Time },
     // Where's the front of the car while this is happening?
@@ -256,7 +412,7 @@ pub enum CarState {
 impl CarState {
     pub fn get_end_time(&self) -> Time {
         match self {
-            CarState::Crossing(ref time_int, _) => time_int.end,
+            CarState::Crossing(ref time_int, _, _) => time_int.end,
             CarState::Queued { .. } => unreachable!(),
             CarState::WaitingToAdvance { .. } => unreachable!(),
             CarState::Unparking(_, _, ref time_int) => time_int.end,
@@ -265,3 +421,255 @@ impl CarState {
         }
     }
 }
+
+// How a car's speed changes while it crosses something, with kinematics: accelerate from
+// start_speed to peak_speed, hold it, then brake to end_speed right at the end.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpeedProfile {
+    start_speed: Speed,
+    peak_speed: Speed,
+    end_speed: Speed,
+    // In meters per second squared
+    accel: f64,
+    decel: f64,
+    accel_time: Duration,
+    cruise_time: Duration,
+    decel_time: Duration,
+}
+
+impl SpeedProfile {
+    fn new(
+        dist: Distance,
+        start_speed: Speed,
+        max_speed: Speed,
+        end_speed: Speed,
+        accel: f64,
+        decel: f64,
+    ) -> SpeedProfile {
+        let d = dist.inner_meters();
+        let max = max_speed.inner_meters_per_second();
+        // If the speed limit just dropped, assume the car already slowed down.
+        let v0 = start_speed.inner_meters_per_second().min(max);
+        let mut v1 = end_speed.inner_meters_per_second().min(max);
+        let mut decel = decel;
+
+        let peak =
+            if (max * max - v0 * v0) / (2.0 * accel) + (max * max - v1 * v1) / (2.0 * decel) <= d {
+                max
+            } else {
+                // Not enough room to reach the max speed. Find where the acceleration and braking
+                // curves meet.
+                let peak = ((2.0 * accel * decel * d + decel * v0 * v0 + accel * v1 * v1)
+                    / (accel + decel))
+                    .sqrt();
+                if peak < v1 {
+                    // Not enough room to even speed up to the end speed
+                    v1 = (v0 * v0 + 2.0 * accel * d).sqrt();
+                    v1
+                } else if peak < v0 {
+                    // Not enough room to slow down comfortably, so brake harder
+                    if d > 0.0 {
+                        decel = (v0 * v0 - v1 * v1) / (2.0 * d);
+                    } else {
+                        v1 = v0;
+                    }
+                    v0
+                } else {
+                    peak
+                }
+            };
+
+        let accel_dist = (peak * peak - v0 * v0) / (2.0 * accel);
+        let decel_dist = (peak * peak - v1 * v1) / (2.0 * decel);
+        let cruise_dist = (d - accel_dist - decel_dist).max(0.0);
+        SpeedProfile {
+            start_speed: Speed::meters_per_second(v0),
+            peak_speed: Speed::meters_per_second(peak),
+            end_speed: Speed::meters_per_second(v1),
+            accel,
+            decel,
+            accel_time: Duration::seconds((peak - v0) / accel),
+            cruise_time: if peak > 0.0 {
+                Duration::seconds(cruise_dist / peak)
+            } else {
+                Duration::ZERO
+            },
+            decel_time: Duration::seconds((peak - v1) / decel),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.accel_time + self.cruise_time + self.decel_time
+    }
+
+    // Distance covered since the start
+    pub fn dist_at(&self, elapsed: Duration) -> Distance {
+        let v0 = self.start_speed.inner_meters_per_second();
+        let peak = self.peak_speed.inner_meters_per_second();
+        let ta = self.accel_time.inner_seconds();
+        let tc = self.cruise_time.inner_seconds();
+        let t = elapsed
+            .inner_seconds()
+            .max(0.0)
+            .min(self.duration().inner_seconds());
+
+        let accel_dist = v0 * ta + 0.5 * self.accel * ta * ta;
+        Distance::meters(if t <= ta {
+            v0 * t + 0.5 * self.accel * t * t
+        } else if t <= ta + tc {
+            accel_dist + peak * (t - ta)
+        } else {
+            let s = t - ta - tc;
+            accel_dist + peak * tc + peak * s - 0.5 * self.decel * s * s
+        })
+    }
+
+    pub fn speed_at(&self, elapsed: Duration) -> Speed {
+        let v0 = self.start_speed.inner_meters_per_second();
+        let peak = self.peak_speed.inner_meters_per_second();
+        let ta = self.accel_time.inner_seconds();
+        let tc = self.cruise_time.inner_seconds();
+        let t = elapsed.inner_seconds().max(0.0);
+
+        if t >= self.duration().inner_seconds() {
+            self.end_speed
+        } else if t <= ta {
+            Speed::meters_per_second(v0 + self.accel * t)
+        } else if t <= ta + tc {
+            self.peak_speed
+        } else {
+            Speed::meters_per_second((peak - self.decel * (t - ta - tc)).max(0.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mps(x: f64) -> Speed {
+        Speed::meters_per_second(x)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.001,
+            "Expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    // Samples the whole profile, checking the car never backs up, speeds, or overshoots.
+    fn check_consistent(profile: &SpeedProfile, dist: Distance, max_speed: Speed) {
+        assert_close(
+            profile.dist_at(profile.duration()).inner_meters(),
+            dist.inner_meters(),
+        );
+        let mut last = Distance::ZERO;
+        for i in 0..=100 {
+            let t = profile.duration() * (i as f64 / 100.0);
+            let d = profile.dist_at(t);
+            assert!(d >= last);
+            assert!(d <= dist + Distance::meters(0.001));
+            assert!(profile.speed_at(t) <= max_speed + mps(0.001));
+            last = d;
+        }
+    }
+
+    #[test]
+    fn test_reach_cruising_speed_from_rest() {
+        let dist = Distance::meters(1000.0);
+        let profile = SpeedProfile::new(dist, Speed::ZERO, mps(10.0), Speed::ZERO, 2.0, 3.0);
+        // 5s to speed up over 25m, 10/3s to slow down over 50/3m, and cruise the rest
+        let expected = 5.0 + (1000.0 - 25.0 - 50.0 / 3.0) / 10.0 + 10.0 / 3.0;
+        assert_close(profile.duration().inner_seconds(), expected);
+
+        assert_eq!(profile.dist_at(Duration::ZERO), Distance::ZERO);
+        assert_close(profile.dist_at(Duration::seconds(2.0)).inner_meters(), 4.0);
+        assert_close(profile.dist_at(Duration::seconds(5.0)).inner_meters(), 25.0);
+        assert_close(
+            profile.dist_at(Duration::seconds(15.0)).inner_meters(),
+            125.0,
+        );
+
+        assert_eq!(profile.speed_at(Duration::ZERO), Speed::ZERO);
+        assert_close(
+            profile
+                .speed_at(Duration::seconds(2.0))
+                .inner_meters_per_second(),
+            4.0,
+        );
+        assert_close(
+            profile
+                .speed_at(Duration::seconds(50.0))
+                .inner_meters_per_second(),
+            10.0,
+        );
+        assert_eq!(profile.speed_at(profile.duration()), Speed::ZERO);
+        check_consistent(&profile, dist, mps(10.0));
+    }
+
+    #[test]
+    fn test_never_reach_cruising_speed() {
+        // Speeding up and slowing down both take 100m at this speed limit
+        let dist = Distance::meters(10.0);
+        let profile = SpeedProfile::new(dist, Speed::ZERO, mps(20.0), Speed::ZERO, 2.0, 2.0);
+        // Half the distance to speed up, half to slow down
+        let peak = 20.0_f64.sqrt();
+        assert_close(profile.duration().inner_seconds(), peak);
+        assert_close(
+            profile
+                .speed_at(Duration::seconds(peak / 2.0))
+                .inner_meters_per_second(),
+            peak,
+        );
+        assert_close(
+            profile
+                .dist_at(Duration::seconds(peak / 2.0))
+                .inner_meters(),
+            5.0,
+        );
+        check_consistent(&profile, dist, mps(peak));
+    }
+
+    #[test]
+    fn test_short_lane() {
+        // Not enough room to reach the end speed; just accelerate the whole way
+        let dist = Distance::meters(4.0);
+        let profile = SpeedProfile::new(dist, Speed::ZERO, mps(20.0), mps(20.0), 2.0, 2.0);
+        assert_close(profile.duration().inner_seconds(), 2.0);
+        assert_close(
+            profile
+                .speed_at(profile.duration())
+                .inner_meters_per_second(),
+            4.0,
+        );
+        check_consistent(&profile, dist, mps(4.0));
+
+        // Not enough room to stop comfortably; brake harder
+        let dist = Distance::meters(5.0);
+        let profile = SpeedProfile::new(dist, mps(10.0), mps(20.0), Speed::ZERO, 2.0, 2.0);
+        assert_close(profile.duration().inner_seconds(), 1.0);
+        assert_eq!(profile.speed_at(profile.duration()), Speed::ZERO);
+        check_consistent(&profile, dist, mps(10.0));
+
+        // Nothing to cross at all
+        let profile = SpeedProfile::new(Distance::ZERO, mps(5.0), mps(10.0), Speed::ZERO, 2.0, 2.0);
+        assert_eq!(profile.duration(), Duration::ZERO);
+        assert_eq!(profile.dist_at(Duration::seconds(1.0)), Distance::ZERO);
+    }
+
+    #[test]
+    fn test_speed_limit_drops() {
+        // Already going faster than the new limit, so start out at the limit
+        let dist = Distance::meters(100.0);
+        let profile = SpeedProfile::new(dist, mps(20.0), mps(10.0), mps(10.0), 2.0, 2.0);
+        assert_close(profile.duration().inner_seconds(), 10.0);
+        assert_close(
+            profile.speed_at(Duration::ZERO).inner_meters_per_second(),
+            10.0,
+        );
+        check_consistent(&profile, dist, mps(10.0));
+    }
+}
@@ -3,13 +3,15 @@ use crate::mechanics::Queue;
 use crate::sim::Ctx;
 use crate::{
     ActionAtEnd, AgentID, AgentProperties, CarID, Command, CreateCar, DistanceInterval,
-    DrawCarInput, Event, IntersectionSimState, ParkedCar, ParkingSimState, ParkingSpot, PersonID,
-    Scheduler, TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent, Vehicle,
-    WalkingSimState, FOLLOWING_DISTANCE,
+    DrawCarInput, Event, FleetSimState, FreightSimState, IntersectionSimState, ParkedCar,
+    ParkingSimState, ParkingSpot, PersonID, Scenario, Scheduler, TimeInterval, TransitSimState,
+    TripID, TripManager, UnzoomedAgent, Vehicle, VehicleSpec, VehicleType, WalkingSimState,
+    Weather, FOLLOWING_DISTANCE,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap};
-use geom::{Distance, Duration, PolyLine, Time};
-use map_model::{LaneID, Map, Path, PathStep, Traversable};
+use geom::{Distance, Duration, PolyLine, Speed, Time};
+use map_model::{LaneID, LaneType, Map, Path, PathStep, Position, Traversable, TurnID};
+use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
 
@@ -22,6 +24,8 @@ const TIME_TO_WAIT_AT_STOP: Duration = Duration::const_seconds(10.0);
 // TODO Do something else.
 pub(crate) const BLIND_RETRY_TO_CREEP_FORWARDS: Duration = Duration::const_seconds(0.1);
 pub(crate) const BLIND_RETRY_TO_REACH_END_DIST: Duration = Duration::const_seconds(5.0);
+// While stuck behind a moving bike, how often to look for a chance to pass again
+const RETRY_PASSING_BIKE: Duration = Duration::const_seconds(1.0);
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DrivingSimState {
@@ -39,16 +43,29 @@ pub struct DrivingSimState {
 
     recalc_lanechanging: bool,
     handle_uber_turns: bool,
+    kinematics: bool,
+    pass_bikes: bool,
+    weather: Weather,
 }
 
 impl DrivingSimState {
-    pub fn new(map: &Map, recalc_lanechanging: bool, handle_uber_turns: bool) -> DrivingSimState {
+    pub fn new(
+        map: &Map,
+        recalc_lanechanging: bool,
+        handle_uber_turns: bool,
+        kinematics: bool,
+        pass_bikes: bool,
+        weather: Weather,
+    ) -> DrivingSimState {
         let mut sim = DrivingSimState {
             cars: BTreeMap::new(),
             queues: BTreeMap::new(),
             events: Vec::new(),
             recalc_lanechanging,
             handle_uber_turns,
+            kinematics,
+            pass_bikes,
+            weather,
         };
 
         for l in map.all_lanes() {
@@ -98,6 +115,9 @@ impl DrivingSimState {
                 started_at: now,
                 total_blocked_time: Duration::ZERO,
                 trip_and_person: params.trip_and_person,
+                kinematics: self.kinematics,
+                last_speed: Speed::ZERO,
+                step_started: (now, params.start_dist, true),
             };
             if let Some(p) = params.maybe_parked_car {
                 let delay = match p.spot {
@@ -118,6 +138,7 @@ impl DrivingSimState {
                         params.start_dist,
                         &car.vehicle,
                         parking,
+                        now,
                         map,
                         car.trip_and_person,
                         &mut self.events,
@@ -146,7 +167,8 @@ impl DrivingSimState {
                     }
                 }
 
-                car.state = car.crossing_state(params.start_dist, now, map);
+                car.state =
+                    car.crossing_state(params.start_dist, now, map, intersections, &self.weather);
             }
             scheduler.push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
             {
@@ -169,6 +191,8 @@ impl DrivingSimState {
         ctx: &mut Ctx,
         trips: &mut TripManager,
         transit: &mut TransitSimState,
+        fleet: &mut FleetSimState,
+        freight: &mut FreightSimState,
         walking: &mut WalkingSimState,
     ) {
         // State transitions for this car:
@@ -176,7 +200,7 @@ impl DrivingSimState {
         // Crossing -> Queued or WaitingToAdvance
         // Unparking -> Crossing
         // IdlingAtStop -> Crossing
-        // Queued -> last step handling (Parking or done)
+        // Queued -> last step handling (Parking or done), or try passing a bike again
         // WaitingToAdvance -> try to advance to the next step of the path
         // Parking -> done
         //
@@ -201,12 +225,26 @@ impl DrivingSimState {
         };
 
         if !need_distances {
+            // Still stuck behind a bike; see if we can get around them now.
+            if let CarState::Queued { .. } = self.cars[&id].state {
+                self.maybe_pass_bike(id, now, ctx);
+                return;
+            }
+
             // We need to mutate two different cars in one case. To avoid fighting the borrow
             // checker, temporarily move one of them out of the BTreeMap.
             let mut car = self.cars.remove(&id).unwrap();
             // Responsibility of update_car to manage scheduling stuff!
-            need_distances = self.update_car_without_distances(&mut car, now, ctx, transit);
+            need_distances =
+                self.update_car_without_distances(&mut car, now, ctx, transit, fleet, freight);
             self.cars.insert(id, car);
+
+            // Stuck behind somebody. If it's a slow bike, maybe get around them.
+            if !need_distances && self.pass_bikes {
+                if let CarState::Queued { .. } = self.cars[&id].state {
+                    self.maybe_pass_bike(id, now, ctx);
+                }
+            }
         }
 
         if need_distances {
@@ -222,9 +260,9 @@ impl DrivingSimState {
             // checker, temporarily move one of them out of the BTreeMap.
             let mut car = self.cars.remove(&id).unwrap();
             // Responsibility of update_car_with_distances to manage scheduling stuff!
-            if self
-                .update_car_with_distances(&mut car, &dists, idx, now, ctx, trips, transit, walking)
-            {
+            if self.update_car_with_distances(
+                &mut car, &dists, idx, now, ctx, trips, transit, fleet, freight, walking,
+            ) {
                 self.cars.insert(id, car);
             } else {
                 self.delete_car_internal(&mut car, dists, idx, now, ctx);
@@ -241,9 +279,12 @@ impl DrivingSimState {
         now: Time,
         ctx: &mut Ctx,
         transit: &mut TransitSimState,
+        fleet: &mut FleetSimState,
+        freight: &mut FreightSimState,
     ) -> bool {
         match car.state {
-            CarState::Crossing(_, _) => {
+            CarState::Crossing(_, _, _) => {
+                car.last_speed = car.current_speed(now);
                 car.state = CarState::Queued { blocked_since: now };
                 if car.router.last_step() {
                     // Immediately run update_car_with_distances.
@@ -256,6 +297,8 @@ impl DrivingSimState {
                     if self.recalc_lanechanging {
                         car.router.opportunistically_lanechange(
                             &self.queues,
+                            ctx.intersections,
+                            now,
                             ctx.map,
                             self.handle_uber_turns,
                         );
@@ -273,20 +316,31 @@ impl DrivingSimState {
                         front,
                         &car.vehicle,
                         ctx.parking,
+                        now,
                         ctx.map,
                         car.trip_and_person,
                         &mut self.events,
                     );
                 }
-                car.state = car.crossing_state(front, now, ctx.map);
+                car.state =
+                    car.crossing_state(front, now, ctx.map, ctx.intersections, &self.weather);
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                // Don't count the time spent pulling out of the spot as driving.
+                car.step_started.0 = now;
             }
             CarState::IdlingAtStop(dist, _) => {
-                car.router = transit.bus_departed_from_stop(car.vehicle.id, ctx.map);
+                car.router = if car.vehicle.vehicle_type.is_transit() {
+                    transit.bus_departed_from_stop(car.vehicle.id, ctx.map)
+                } else if car.vehicle.vehicle_type.is_freight() {
+                    freight.vehicle_departed(car.vehicle.id, ctx)
+                } else {
+                    fleet.vehicle_departed(car.vehicle.id, ctx.map)
+                };
                 self.events
                     .push(Event::PathAmended(car.router.get_path().clone()));
-                car.state = car.crossing_state(dist, now, ctx.map);
+                car.state =
+                    car.crossing_state(dist, now, ctx.map, ctx.intersections, &self.weather);
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
 
@@ -310,6 +364,8 @@ impl DrivingSimState {
                                     dist - car.vehicle.length - FOLLOWING_DISTANCE,
                                     now,
                                     ctx.map,
+                                    ctx.intersections,
+                                    &self.weather,
                                 );
                                 ctx.scheduler.update(
                                     follower.state.get_end_time(),
@@ -321,7 +377,7 @@ impl DrivingSimState {
                         // They weren't blocked. Note that there's no way the Crossing state could
                         // jump forwards here; the leader is still in front
                         // of them.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::IdlingAtStop(_, _) => {}
@@ -335,24 +391,24 @@ impl DrivingSimState {
                 let goto = car.router.next();
                 assert!(from != goto);
 
-                if let Traversable::Turn(t) = goto {
-                    let mut speed = goto.speed_limit(ctx.map);
-                    if let Some(s) = car.vehicle.max_speed {
-                        speed = speed.min(s);
-                    }
-                    if !ctx.intersections.maybe_start_turn(
+                // For emissions, the car idled for as long as the intersection kept it waiting
+                let idle = if let Traversable::Turn(t) = goto {
+                    match ctx.intersections.maybe_start_turn(
                         AgentID::Car(car.vehicle.id),
                         t,
-                        speed,
+                        car.cruising_speed(goto, now, ctx.map, &self.weather),
                         now,
                         ctx.map,
                         ctx.scheduler,
                         Some((&car, &self.cars, &mut self.queues)),
                     ) {
+                        Some(delay) => delay,
                         // Don't schedule a retry here.
-                        return false;
+                        None => return false,
                     }
-                }
+                } else {
+                    Duration::ZERO
+                };
 
                 {
                     let mut queue = self.queues.get_mut(&from).unwrap();
@@ -363,15 +419,30 @@ impl DrivingSimState {
                 // We do NOT need to update the follower. If they were Queued, they'll remain that
                 // way, until laggy_head is None.
 
+                car.total_blocked_time += now - blocked_since;
+                self.events.push(Event::VehicleEmissions(
+                    car.vehicle.id,
+                    car.trip_and_person.map(|(t, _)| t),
+                    from,
+                    car.emissions_on_current_step(from.length(ctx.map), idle, now, ctx.map),
+                ));
+                car.step_started = (now, Distance::ZERO, now > blocked_since);
+
                 let last_step = car.router.advance(
                     &car.vehicle,
                     ctx.parking,
+                    now,
                     ctx.map,
                     car.trip_and_person,
                     &mut self.events,
                 );
-                car.total_blocked_time += now - blocked_since;
-                car.state = car.crossing_state(Distance::ZERO, now, ctx.map);
+                car.state = car.crossing_state(
+                    Distance::ZERO,
+                    now,
+                    ctx.map,
+                    ctx.intersections,
+                    &self.weather,
+                );
                 ctx.scheduler
                     .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                 self.events.push(Event::AgentEntersTraversable(
@@ -399,6 +470,8 @@ impl DrivingSimState {
                         ),
                         now,
                         ctx.map,
+                        ctx.intersections,
+                        &self.weather,
                     )
                     .get_end_time(),
                     Command::UpdateLaggyHead(car.vehicle.id),
@@ -425,12 +498,14 @@ impl DrivingSimState {
         ctx: &mut Ctx,
         trips: &mut TripManager,
         transit: &mut TransitSimState,
+        fleet: &mut FleetSimState,
+        freight: &mut FreightSimState,
         walking: &mut WalkingSimState,
     ) -> bool {
         let our_dist = dists[idx].1;
 
         match car.state {
-            CarState::Crossing(_, _)
+            CarState::Crossing(_, _, _)
             | CarState::Unparking(_, _, _)
             | CarState::IdlingAtStop(_, _)
             | CarState::WaitingToAdvance { .. } => unreachable!(),
@@ -439,6 +514,7 @@ impl DrivingSimState {
                     our_dist,
                     &car.vehicle,
                     ctx.parking,
+                    now,
                     ctx.map,
                     car.trip_and_person,
                     &mut self.events,
@@ -483,13 +559,24 @@ impl DrivingSimState {
                         // behind, see the spot free, and start parking too. This can
                         // happen with multiple lanes and certain vehicle lengths.
                         ctx.parking.reserve_spot(spot);
+                        if let (Some((trip, _)), Some((time, dist))) =
+                            (car.trip_and_person, car.router.parking_search(now))
+                        {
+                            self.events.push(Event::CruisedForParking(trip, time, dist));
+                        }
                         ctx.scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         true
                     }
                     Some(ActionAtEnd::GotoLaneEnd) => {
                         car.total_blocked_time += now - blocked_since;
-                        car.state = car.crossing_state(our_dist, now, ctx.map);
+                        car.state = car.crossing_state(
+                            our_dist,
+                            now,
+                            ctx.map,
+                            ctx.intersections,
+                            &self.weather,
+                        );
                         ctx.scheduler
                             .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
                         true
@@ -521,6 +608,26 @@ impl DrivingSimState {
                             false
                         }
                     }
+                    Some(ActionAtEnd::FleetAtStop) => {
+                        car.total_blocked_time += now - blocked_since;
+                        let maybe_delay = if car.vehicle.vehicle_type.is_freight() {
+                            freight.vehicle_arrived(now, car.vehicle.id, ctx)
+                        } else {
+                            fleet.vehicle_arrived(now, car.vehicle.id, trips, ctx)
+                        };
+                        if let Some(delay) = maybe_delay {
+                            car.state = CarState::IdlingAtStop(
+                                our_dist,
+                                TimeInterval::new(now, now + delay),
+                            );
+                            ctx.scheduler
+                                .push(car.state.get_end_time(), Command::UpdateCar(car.vehicle.id));
+                            true
+                        } else {
+                            // Back at a depot, in a loading zone, or done with a delivery tour
+                            false
+                        }
+                    }
                     None => {
                         ctx.scheduler.push(
                             now + BLIND_RETRY_TO_REACH_END_DIST,
@@ -532,7 +639,7 @@ impl DrivingSimState {
                         /*
                         // If this car wasn't blocked at all, when would it reach its goal?
                         let ideal_end_time = match car.crossing_state(our_dist, now, map) {
-                            CarState::Crossing(time_int, _) => time_int.end,
+                            CarState::Crossing(time_int, _, _) => time_int.end,
                             _ => unreachable!(),
                         };
                         if ideal_end_time == now {
@@ -551,10 +658,14 @@ impl DrivingSimState {
                 }
             }
             CarState::Parking(_, spot, _) => {
-                ctx.parking.add_parked_car(ParkedCar {
-                    vehicle: car.vehicle.clone(),
-                    spot,
-                });
+                ctx.parking.add_parked_car(
+                    ParkedCar {
+                        vehicle: car.vehicle.clone(),
+                        spot,
+                    },
+                    now,
+                    car.trip_and_person.map(|(t, _)| t),
+                );
                 trips.car_reached_parking_spot(
                     now,
                     car.vehicle.id,
@@ -599,6 +710,12 @@ impl DrivingSimState {
         now: Time,
         ctx: &mut Ctx,
     ) {
+        self.events.push(Event::VehicleEmissions(
+            car.vehicle.id,
+            car.trip_and_person.map(|(t, _)| t),
+            car.router.head(),
+            car.emissions_on_current_step(dists[idx].1, Duration::ZERO, now, ctx.map),
+        ));
         {
             let queue = self.queues.get_mut(&car.router.head()).unwrap();
             assert_eq!(queue.cars.remove(idx).unwrap(), car.vehicle.id);
@@ -632,17 +749,29 @@ impl DrivingSimState {
                 CarState::Queued { blocked_since } => {
                     // Prevent them from jumping forwards.
                     follower.total_blocked_time += now - blocked_since;
-                    follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        ctx.map,
+                        ctx.intersections,
+                        &self.weather,
+                    );
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
                     );
                 }
-                CarState::Crossing(_, _) => {
+                CarState::Crossing(_, _, _) => {
                     // If the follower was still Crossing, they might not've been blocked
                     // by leader yet. In that case, recalculating their Crossing state is a
                     // no-op.
-                    follower.state = follower.crossing_state(follower_dist, now, ctx.map);
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        ctx.map,
+                        ctx.intersections,
+                        &self.weather,
+                    );
                     ctx.scheduler.update(
                         follower.state.get_end_time(),
                         Command::UpdateCar(follower_id),
@@ -657,6 +786,195 @@ impl DrivingSimState {
         }
     }
 
+    // Once vehicles can pass them, cyclists ride at their own pace. Otherwise, leave the speeds
+    // and the RNG alone, so existing runs don't change.
+    pub(crate) fn vary_bike_speeds(&self, specs: &mut Vec<VehicleSpec>, rng: &mut XorShiftRng) {
+        if !self.pass_bikes {
+            return;
+        }
+        for spec in specs {
+            if spec.vehicle_type == VehicleType::Bike {
+                spec.max_speed = Some(Scenario::rand_cyclist_speed(rng));
+            }
+        }
+    }
+
+    fn retry_passing_bike(&self, id: CarID, now: Time, ctx: &mut Ctx) {
+        // A queued car on its last step gets woken up to finish the trip instead
+        if !self.cars[&id].router.last_step() {
+            ctx.scheduler
+                .update(now + RETRY_PASSING_BIKE, Command::UpdateCar(id));
+        }
+    }
+
+    // A vehicle stuck behind a slower bike overtakes it, if there's room in front of the bike and
+    // either another lane going the same way or a gap in oncoming traffic. Bikes pass each other
+    // within the lane, including in bike lanes. Passing happens all at once; the passer jumps from
+    // right behind the bike to right in front of it. If something's in the way, try again a little
+    // later, as long as the bike keeps moving.
+    //
+    // Bikes don't filter forwards past cars queued in a shared lane. Cars and bikes in one lane
+    // form a single queue, so that'd need bikes to leave it entirely.
+    fn maybe_pass_bike(&mut self, id: CarID, now: Time, ctx: &mut Ctx) {
+        let map = ctx.map;
+        let on = self.cars[&id].router.head();
+        let lane = match on {
+            Traversable::Lane(l) => map.get_l(l),
+            Traversable::Turn(_) => {
+                return;
+            }
+        };
+        match lane.lane_type {
+            LaneType::Driving => {}
+            LaneType::Biking if id.1 == VehicleType::Bike => {}
+            _ => {
+                return;
+            }
+        }
+        let dists = self.queues[&on].get_car_positions(now, &self.cars, &self.queues);
+        let idx = dists.iter().position(|(c, _)| *c == id).unwrap();
+        if idx == 0 {
+            return;
+        }
+        let (bike_id, bike_dist) = dists[idx - 1];
+        if bike_id.1 != VehicleType::Bike {
+            return;
+        }
+        let car = &self.cars[&id];
+        let bike = &self.cars[&bike_id];
+        // Don't pass a bike stopped for something else
+        if !matches!(bike.state, CarState::Crossing(_, _, _)) {
+            return;
+        }
+        let our_speed = car.cruising_speed(on, now, map, &self.weather);
+        let bike_speed = bike.cruising_speed(on, now, map, &self.weather);
+        if our_speed <= bike_speed {
+            return;
+        }
+
+        // Is there room in front of the bike?
+        let our_dist = dists[idx].1;
+        let new_dist = bike_dist + car.vehicle.length + FOLLOWING_DISTANCE;
+        let bound = if idx >= 2 {
+            let (leader, leader_dist) = dists[idx - 2];
+            leader_dist - self.cars[&leader].vehicle.length - FOLLOWING_DISTANCE
+        } else if self.queues[&on].laggy_head.is_some() {
+            self.retry_passing_bike(id, now, ctx);
+            return;
+        } else {
+            lane.length()
+        };
+        // How long it'd realistically take to get around them, and how far we'd go meanwhile
+        let time_to_pass = (new_dist - our_dist) / (our_speed - bike_speed);
+        let far_dist = our_dist + our_speed * time_to_pass;
+        // There's only less room later
+        if far_dist > lane.length() {
+            return;
+        }
+        if new_dist >= bound {
+            self.retry_passing_bike(id, now, ctx);
+            return;
+        }
+
+        if car.vehicle.vehicle_type != VehicleType::Bike {
+            let road = map.get_r(lane.parent);
+            let here = Position::new(lane.id, our_dist);
+            let there = Position::new(lane.id, far_dist);
+            let mut same_way = Vec::new();
+            let mut oncoming = Vec::new();
+            for (l, dir, lt) in road.lanes_ltr() {
+                if l == lane.id || lt != LaneType::Driving {
+                    continue;
+                }
+                if dir == road.dir(lane.id) {
+                    same_way.push(l);
+                } else {
+                    oncoming.push(l);
+                }
+            }
+
+            // Nobody in the way in the next lane over?
+            let clear_same_way = same_way.into_iter().any(|l| {
+                !self.vehicle_approaching(
+                    now,
+                    l,
+                    there.equiv_pos(l, map).dist_along(),
+                    far_dist - our_dist + car.vehicle.length + FOLLOWING_DISTANCE,
+                )
+            });
+            // Otherwise, can we pull into oncoming traffic and back before anybody gets close?
+            let clear_oncoming = !oncoming.is_empty()
+                && oncoming.iter().all(|l| {
+                    let start = here.equiv_pos(*l, map).dist_along();
+                    let end = there.equiv_pos(*l, map).dist_along();
+                    let their_speed = map.get_parent(*l).speed_limit;
+                    !self.vehicle_approaching(
+                        now,
+                        *l,
+                        start,
+                        start - end + their_speed * time_to_pass,
+                    )
+                });
+            if !clear_same_way && !clear_oncoming {
+                self.retry_passing_bike(id, now, ctx);
+                return;
+            }
+        }
+
+        {
+            let queue = self.queues.get_mut(&on).unwrap();
+            assert_eq!(queue.cars.remove(idx), Some(id));
+            queue.cars.insert(idx - 1, id);
+        }
+        let mut car = self.cars.remove(&id).unwrap();
+        if let CarState::Queued { blocked_since } = car.state {
+            car.total_blocked_time += now - blocked_since;
+        }
+        car.state = car.crossing_state(new_dist, now, map, ctx.intersections, &self.weather);
+        ctx.scheduler
+            .update(car.state.get_end_time(), Command::UpdateCar(id));
+        self.cars.insert(id, car);
+
+        // Our follower is now behind the bike instead. Don't let them suddenly jump forwards.
+        if idx != dists.len() - 1 {
+            let (follower_id, follower_dist) = dists[idx + 1];
+            let follower = self.cars.get_mut(&follower_id).unwrap();
+            match follower.state {
+                CarState::Queued { blocked_since } => {
+                    follower.total_blocked_time += now - blocked_since;
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        map,
+                        ctx.intersections,
+                        &self.weather,
+                    );
+                    ctx.scheduler.update(
+                        follower.state.get_end_time(),
+                        Command::UpdateCar(follower_id),
+                    );
+                }
+                CarState::Crossing(_, _, _) => {
+                    follower.state = follower.crossing_state(
+                        follower_dist,
+                        now,
+                        map,
+                        ctx.intersections,
+                        &self.weather,
+                    );
+                    ctx.scheduler.update(
+                        follower.state.get_end_time(),
+                        Command::UpdateCar(follower_id),
+                    );
+                }
+                CarState::Unparking(_, _, _)
+                | CarState::Parking(_, _, _)
+                | CarState::IdlingAtStop(_, _) => {}
+                CarState::WaitingToAdvance { .. } => unreachable!(),
+            }
+        }
+    }
+
     pub fn update_laggy_head(&mut self, id: CarID, now: Time, ctx: &mut Ctx) {
         let currently_on = self.cars[&id].router.head();
         let current_dists =
@@ -704,6 +1022,8 @@ impl DrivingSimState {
                     ),
                     now,
                     ctx.map,
+                    ctx.intersections,
+                    &self.weather,
                 )
                 .get_end_time();
             // Sometimes due to rounding, retry_at will be exactly time, but we really need to
@@ -769,19 +1089,22 @@ impl DrivingSimState {
                                 if self.recalc_lanechanging {
                                     follower.router.opportunistically_lanechange(
                                         &self.queues,
+                                        ctx.intersections,
+                                        now,
                                         ctx.map,
                                         self.handle_uber_turns,
                                     );
                                 }
+                                // Might replace a retry at passing a bike
                                 ctx.scheduler
-                                    .push(now, Command::UpdateCar(follower.vehicle.id));
+                                    .update(now, Command::UpdateCar(follower.vehicle.id));
                             }
                         }
                         CarState::WaitingToAdvance { .. } => unreachable!(),
                         // They weren't blocked. Note that there's no way the Crossing state
                         // could jump forwards here; the leader
                         // vanished from the end of the traversable.
-                        CarState::Crossing(_, _)
+                        CarState::Crossing(_, _, _)
                         | CarState::Unparking(_, _, _)
                         | CarState::Parking(_, _, _)
                         | CarState::IdlingAtStop(_, _) => {}
@@ -1020,6 +1343,25 @@ impl DrivingSimState {
         self.queues[&Traversable::Lane(l)].target_lane_penalty()
     }
 
+    // Is any vehicle on this lane less than some distance away from a point, or still passing it?
+    pub fn vehicle_approaching(
+        &self,
+        now: Time,
+        l: LaneID,
+        dist: Distance,
+        within: Distance,
+    ) -> bool {
+        match self.queues.get(&Traversable::Lane(l)) {
+            Some(q) => q
+                .get_car_positions(now, &self.cars, &self.queues)
+                .into_iter()
+                .any(|(id, front)| {
+                    front >= dist - within && front - self.cars[&id].vehicle.length <= dist
+                }),
+            None => false,
+        }
+    }
+
     pub fn find_trips_to_edited_parking(
         &self,
         spots: BTreeSet<ParkingSpot>,
@@ -1035,4 +1377,240 @@ impl DrivingSimState {
         }
         affected
     }
+
+    // An incident just closed some turns. Send everybody headed through them another way. Returns
+    // how many found a way around, and how many have to wait it out.
+    pub fn reroute_around(
+        &mut self,
+        avoid: &BTreeSet<TurnID>,
+        now: Time,
+        ctx: &mut Ctx,
+    ) -> (usize, usize) {
+        let mut rerouted = 0;
+        let mut stuck = 0;
+        for car in self.cars.values_mut() {
+            if !car.router.crosses_any(avoid) {
+                continue;
+            }
+            let old_next = car.router.maybe_next();
+            if !car.router.reroute(avoid, ctx.map) {
+                stuck += 1;
+                continue;
+            }
+            rerouted += 1;
+            self.events
+                .push(Event::PathAmended(car.router.get_path().clone()));
+
+            // Somebody already waiting at the intersection has to ask for a different turn now
+            if let CarState::WaitingToAdvance { .. } = car.state {
+                if let Some(Traversable::Turn(t)) = old_next {
+                    if car.router.maybe_next() != old_next {
+                        ctx.intersections
+                            .cancel_request(AgentID::Car(car.vehicle.id), t);
+                        ctx.scheduler
+                            .update(now, Command::UpdateCar(car.vehicle.id));
+                    }
+                }
+            }
+        }
+        (rerouted, stuck)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DrivingGoal, GetDrawAgents, Sim, SimOptions, TripEndpoint, TripSpec, BIKE_LENGTH};
+    use abstutil::Timer;
+    use map_model::raw::{OriginalRoad, RawMap};
+    use map_model::{osm, BuildingID};
+    use rand::{Rng, SeedableRng};
+
+    // A cyclist riding at 2m/s starts down the first block of a 3x3 grid, then a faster vehicle
+    // appears behind them. If `oncoming`, a line of slow cars comes the other way while the faster
+    // one first catches up. True if the faster one leaves the block first.
+    fn passed(pass_bikes: bool, passer: VehicleSpec, bike_lane: bool, oncoming: bool) -> bool {
+        let mut timer = Timer::throwaway();
+        let mut raw = RawMap::synthetic_grid(3, 3);
+        let block = OriginalRoad::new(1, (1, 2));
+        if bike_lane {
+            raw.roads
+                .get_mut(&block)
+                .unwrap()
+                .osm_tags
+                .insert("cycleway", "lane");
+        }
+        let map = Map::create_from_raw(raw, true, &mut timer);
+        let mut opts = SimOptions::new("test");
+        opts.pass_bikes = pass_bikes;
+        let mut sim = Sim::new(&map, opts, &mut timer);
+
+        let lane_type = if bike_lane {
+            LaneType::Biking
+        } else {
+            LaneType::Driving
+        };
+        let lane = map
+            .get_r(map.find_r_by_osm_id(block).unwrap())
+            .all_lanes()
+            .into_iter()
+            .map(|l| map.get_l(l))
+            .find(|l| l.lane_type == lane_type && map.get_i(l.src_i).orig_id == block.i1)
+            .unwrap();
+        // Keep going along the same row afterwards
+        let goal: BuildingID = map
+            .all_buildings()
+            .iter()
+            .find(|b| b.orig_id == osm::OsmID::Way(osm::WayID(1003)))
+            .unwrap()
+            .id;
+
+        let slow_bike = VehicleSpec::new(
+            VehicleType::Bike,
+            BIKE_LENGTH,
+            Some(Speed::meters_per_second(2.0)),
+        );
+        let mut spawner = sim.make_spawner();
+        let mut ids = Vec::new();
+        for (spec, start_time) in vec![(slow_bike, 1.0), (passer, 6.0)] {
+            let person = sim.random_person(Speed::meters_per_second(1.0), vec![spec]);
+            let id = person.vehicles[0].id;
+            spawner.schedule_trip(
+                person,
+                Time::START_OF_DAY + Duration::seconds(start_time),
+                TripSpec::VehicleAppearing {
+                    start_pos: Position::new(lane.id, Distance::meters(10.0)),
+                    goal: DrivingGoal::ParkNear(goal),
+                    use_vehicle: id,
+                    retry_if_no_room: false,
+                    origin: None,
+                },
+                TripEndpoint::Border(lane.src_i, None),
+                false,
+                false,
+                &map,
+            );
+            ids.push(id);
+        }
+        if oncoming {
+            let other_way = map
+                .get_r(lane.parent)
+                .all_lanes()
+                .into_iter()
+                .map(|l| map.get_l(l))
+                .find(|l| {
+                    l.lane_type == LaneType::Driving && map.get_i(l.src_i).orig_id == block.i2
+                })
+                .unwrap();
+            // Heading back along the first column
+            let goal: BuildingID = map
+                .all_buildings()
+                .iter()
+                .find(|b| b.orig_id == osm::OsmID::Way(osm::WayID(1002)))
+                .unwrap()
+                .id;
+            let slow_car = VehicleSpec::new(
+                VehicleType::Car,
+                Distance::meters(4.5),
+                Some(Speed::meters_per_second(2.0)),
+            );
+            // Spaced out so that there's always somebody coming while the faster one catches up
+            for i in 0..8 {
+                let person =
+                    sim.random_person(Speed::meters_per_second(1.0), vec![slow_car.clone()]);
+                let id = person.vehicles[0].id;
+                spawner.schedule_trip(
+                    person,
+                    Time::START_OF_DAY + Duration::seconds(1.0),
+                    TripSpec::VehicleAppearing {
+                        start_pos: Position::new(
+                            other_way.id,
+                            other_way.length() - Distance::meters(60.0 + 15.0 * (i as f64)),
+                        ),
+                        goal: DrivingGoal::ParkNear(goal),
+                        use_vehicle: id,
+                        retry_if_no_room: false,
+                        origin: None,
+                    },
+                    TripEndpoint::Border(other_way.src_i, None),
+                    false,
+                    false,
+                    &map,
+                );
+            }
+        }
+        sim.flush_spawner(spawner, &map, &mut timer);
+
+        let mut seen = BTreeSet::new();
+        let mut left = Vec::new();
+        while left.len() < 2 && sim.time() < Time::START_OF_DAY + Duration::minutes(5) {
+            sim.timed_step(&map, Duration::seconds(1.0), &mut None, &mut timer);
+            for id in &ids {
+                let on_block =
+                    sim.get_draw_car(*id, &map).map(|c| c.on) == Some(Traversable::Lane(lane.id));
+                if on_block {
+                    seen.insert(*id);
+                } else if seen.contains(id) && !left.contains(id) {
+                    left.push(*id);
+                }
+            }
+        }
+        assert_eq!(left.len(), 2);
+        left[0] == ids[1]
+    }
+
+    fn car() -> VehicleSpec {
+        VehicleSpec::new(VehicleType::Car, Distance::meters(4.5), None)
+    }
+
+    fn fast_bike() -> VehicleSpec {
+        VehicleSpec::new(
+            VehicleType::Bike,
+            BIKE_LENGTH,
+            Some(Speed::meters_per_second(8.0)),
+        )
+    }
+
+    #[test]
+    fn test_car_passes_bike() {
+        // Nothing's coming the other way
+        assert!(passed(true, car(), false, false));
+        // Otherwise, stuck behind them
+        assert!(!passed(false, car(), false, false));
+    }
+
+    #[test]
+    fn test_bikes_pass_in_bike_lane() {
+        assert!(passed(true, fast_bike(), true, false));
+        assert!(!passed(false, fast_bike(), true, false));
+    }
+
+    #[test]
+    fn test_pass_bike_once_oncoming_traffic_clears() {
+        // The first chance to pass is blocked, but the car keeps looking
+        assert!(passed(true, car(), false, true));
+    }
+
+    #[test]
+    fn test_bike_speeds_only_vary_when_passing() {
+        let map =
+            Map::create_from_raw(RawMap::synthetic_grid(2, 1), false, &mut Timer::throwaway());
+        for pass_bikes in vec![false, true] {
+            let state =
+                DrivingSimState::new(&map, false, false, false, pass_bikes, Weather::clear());
+            let mut rng = XorShiftRng::seed_from_u64(42);
+            let mut specs = vec![Scenario::rand_bike(&mut rng), car()];
+            let before = specs.clone();
+            let mut rng_before = rng.clone();
+            state.vary_bike_speeds(&mut specs, &mut rng);
+            if pass_bikes {
+                assert_ne!(specs[0].max_speed, before[0].max_speed);
+                assert_eq!(specs[1], before[1]);
+            } else {
+                assert_eq!(specs, before);
+                // Nothing was drawn
+                assert_eq!(rng.gen::<u64>(), rng_before.gen::<u64>());
+            }
+        }
+    }
 }
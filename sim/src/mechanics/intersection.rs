@@ -1,7 +1,10 @@
 use crate::mechanics::car::Car;
 use crate::mechanics::Queue;
-use crate::{AgentID, AlertLocation, CarID, Command, Event, Scheduler, Speed};
-use abstutil::{deserialize_btreemap, retain_btreeset, serialize_btreemap};
+use crate::{AgentID, AlertLocation, CarID, Command, Event, PedestrianID, Scheduler, Speed};
+use abstutil::{
+    deserialize_btreemap, deserialize_multimap, retain_btreeset, serialize_btreemap,
+    serialize_multimap, MultiMap,
+};
 use geom::{Duration, Time};
 use map_model::{
     ControlStopSign, ControlTrafficSignal, IntersectionID, LaneID, Map, PhaseType, RoadID,
@@ -12,6 +15,8 @@ use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 const WAIT_AT_STOP_SIGN: Duration = Duration::const_seconds(0.5);
 const WAIT_BEFORE_YIELD_AT_TRAFFIC_SIGNAL: Duration = Duration::const_seconds(0.2);
+// At full capacity, how often somebody can squeeze past an incident
+const INCIDENT_HEADWAY: Duration = Duration::const_seconds(2.0);
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct IntersectionSimState {
@@ -23,6 +28,26 @@ pub struct IntersectionSimState {
     // (x, y) means x is blocked by y. It's a many-to-many relationship. TODO Better data
     // structure.
     blocked_by: BTreeSet<(CarID, CarID)>,
+    // Turns restricted by incidents. Incidents can overlap; the one letting the fewest through
+    // applies.
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    restricted: BTreeMap<TurnID, Vec<Restriction>>,
+    // Agents held up by an incident, which one, and since when
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    held: BTreeMap<Request, (usize, Time)>,
+    // Pedestrians crossing these lanes partway along. Vehicles yield by not entering until they're
+    // across.
+    #[serde(
+        serialize_with = "serialize_multimap",
+        deserialize_with = "deserialize_multimap"
+    )]
+    mid_block: MultiMap<LaneID, PedestrianID>,
     events: Vec<Event>,
 }
 
@@ -50,6 +75,14 @@ struct SignalState {
     stage_ends_at: Time,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+struct Restriction {
+    incident: usize,
+    capacity: f64,
+    // When somebody last got through
+    last_entry: Option<Time>,
+}
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Debug)]
 struct Request {
     agent: AgentID,
@@ -72,6 +105,9 @@ impl IntersectionSimState {
             break_turn_conflict_cycles,
             handle_uber_turns,
             blocked_by: BTreeSet::new(),
+            restricted: BTreeMap::new(),
+            held: BTreeMap::new(),
+            mid_block: MultiMap::new(),
             events: Vec::new(),
         };
         for i in map.all_intersections() {
@@ -99,6 +135,30 @@ impl IntersectionSimState {
             .any(|req| req.turn.dst == lane)
     }
 
+    // With kinematics, a car about to cross a lane decides whether to brake for the end of it.
+    // This is just a guess made ahead of time: the light might be red when they arrive, somebody
+    // might be waiting to turn from the same lane, or the turn might need a full stop.
+    pub fn expect_to_roll_through(&self, turn: TurnID, arrival: Time, map: &Map) -> bool {
+        let state = &self.state[&turn.parent];
+        if state.waiting.keys().any(|req| req.turn.src == turn.src) {
+            return false;
+        }
+        if self.use_freeform_policy_everywhere {
+            return true;
+        }
+        if let Some(ref signal) = map.maybe_get_traffic_signal(turn.parent) {
+            let signal_state = state.signal.as_ref().unwrap();
+            arrival < signal_state.stage_ends_at
+                && signal.stages[signal_state.current_stage].get_priority_of_turn(turn, signal)
+                    == TurnPriority::Protected
+        } else if let Some(ref sign) = map.maybe_get_stop_sign(turn.parent) {
+            sign.get_priority(turn, map) == TurnPriority::Protected
+        } else {
+            // Border intersections have neither a signal nor a stop sign. Braking is the safe guess.
+            false
+        }
+    }
+
     pub fn turn_finished(
         &mut self,
         now: Time,
@@ -124,6 +184,7 @@ impl IntersectionSimState {
     pub fn cancel_request(&mut self, agent: AgentID, turn: TurnID) {
         let state = self.state.get_mut(&turn.parent).unwrap();
         state.waiting.remove(&Request { agent, turn });
+        self.held.remove(&Request { agent, turn });
         if self.break_turn_conflict_cycles {
             if let AgentID::Car(car) = agent {
                 retain_btreeset(&mut self.blocked_by, |(c1, c2)| *c1 != car && *c2 != car);
@@ -147,6 +208,90 @@ impl IntersectionSimState {
         retain_btreeset(&mut self.blocked_by, |(c1, c2)| *c1 != car && *c2 != car);
     }
 
+    pub fn restrict_turn(&mut self, turn: TurnID, incident: usize, capacity: f64) {
+        self.restricted
+            .entry(turn)
+            .or_insert_with(Vec::new)
+            .push(Restriction {
+                incident,
+                capacity,
+                last_entry: None,
+            });
+    }
+
+    pub fn lift_restriction(
+        &mut self,
+        turn: TurnID,
+        incident: usize,
+        now: Time,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        if let Some(list) = self.restricted.get_mut(&turn) {
+            if !list.iter().any(|r| r.incident == incident) {
+                return;
+            }
+            list.retain(|r| r.incident != incident);
+            if list.is_empty() {
+                self.restricted.remove(&turn);
+            }
+        } else {
+            return;
+        }
+
+        // Whatever else they wait for after this isn't because of the incident. If another
+        // incident still holds them up, they'll be held again when they wake up.
+        let mut released = Vec::new();
+        for (req, (id, since)) in &self.held {
+            if req.turn == turn && *id == incident {
+                released.push(req.clone());
+                self.events
+                    .push(Event::IncidentDelay(incident, req.agent, now - *since));
+            }
+        }
+        for req in released {
+            self.held.remove(&req);
+        }
+        self.wakeup_waiting(now, turn.parent, scheduler, map);
+    }
+
+    // Drivers don't change lanes into a closed turn.
+    pub fn is_closed(&self, turn: TurnID) -> bool {
+        self.get_restriction(turn)
+            .map(|r| r.capacity <= 0.0)
+            .unwrap_or(false)
+    }
+
+    // The incident letting the fewest through this turn
+    fn get_restriction(&self, turn: TurnID) -> Option<&Restriction> {
+        self.restricted
+            .get(&turn)?
+            .iter()
+            .min_by(|r1, r2| r1.capacity.partial_cmp(&r2.capacity).unwrap())
+    }
+
+    pub fn start_mid_block_crossing(&mut self, ped: PedestrianID, lanes: Vec<LaneID>) {
+        for l in lanes {
+            self.mid_block.insert(l, ped);
+        }
+    }
+
+    pub fn finish_mid_block_crossing(
+        &mut self,
+        ped: PedestrianID,
+        lanes: Vec<LaneID>,
+        now: Time,
+        scheduler: &mut Scheduler,
+        map: &Map,
+    ) {
+        for l in lanes {
+            self.mid_block.remove(l, ped);
+            if self.mid_block.get(l).is_empty() {
+                self.wakeup_waiting(now, map.get_l(l).src_i, scheduler, map);
+            }
+        }
+    }
+
     fn wakeup_waiting(&self, now: Time, i: IntersectionID, scheduler: &mut Scheduler, map: &Map) {
         /*if i == IntersectionID(64) {
             println!("at {}: wakeup_waiting -----------------", now);
@@ -265,12 +410,13 @@ impl IntersectionSimState {
     }
 
     // For cars: The head car calls this when they're at the end of the lane WaitingToAdvance. If
-    // this returns true, then the head car MUST actually start this turn.
+    // this returns something, then the head car MUST actually start this turn.
     // For peds: Likewise -- only called when the ped is at the start of the turn. They must
-    // actually do the turn if this returns true.
+    // actually do the turn if this returns something.
     //
-    // If this returns false, the agent should NOT retry. IntersectionSimState will schedule a
-    // retry event at some point.
+    // If this returns None, the agent should NOT retry. IntersectionSimState will schedule a
+    // retry event at some point. Otherwise, it's how long the agent waited for the turn, the same
+    // delay that IntersectionDelayMeasured reports at signals.
     pub fn maybe_start_turn(
         &mut self,
         agent: AgentID,
@@ -284,7 +430,7 @@ impl IntersectionSimState {
             &BTreeMap<CarID, Car>,
             &mut BTreeMap<Traversable, Queue>,
         )>,
-    ) -> bool {
+    ) -> Option<Duration> {
         let req = Request { agent, turn };
         self.state
             .get_mut(&turn.parent)
@@ -293,6 +439,32 @@ impl IntersectionSimState {
             .entry(req.clone())
             .or_insert(now);
 
+        if let Some(r) = self.get_restriction(turn) {
+            let held = if r.capacity <= 0.0 {
+                // lift_restriction wakes everybody up
+                true
+            } else if let Some(t) = r
+                .last_entry
+                .map(|t| t + INCIDENT_HEADWAY / r.capacity)
+                .filter(|t| *t > now)
+            {
+                scheduler.update(t, Command::update_agent(agent));
+                true
+            } else {
+                false
+            };
+            if held {
+                let incident = r.incident;
+                self.held.entry(req).or_insert((incident, now));
+                return None;
+            }
+        }
+
+        // finish_mid_block_crossing wakes everybody up
+        if !self.mid_block.get(turn.dst).is_empty() {
+            return None;
+        }
+
         let shared_sidewalk_corner =
             map.get_t(req.turn).turn_type == TurnType::SharedSidewalkCorner;
 
@@ -333,7 +505,7 @@ impl IntersectionSimState {
             unreachable!()
         };
         if !allowed {
-            return false;
+            return None;
         }
 
         // Lock the entire uber-turn.
@@ -346,7 +518,7 @@ impl IntersectionSimState {
                 for t in &ut.path {
                     let req = Request { agent, turn: *t };
                     if !self.handle_accepted_conflicts(&req, map, readonly_pair) {
-                        return false;
+                        return None;
                     }
                 }
                 // If the way is clear, make sure it stays that way.
@@ -398,7 +570,7 @@ impl IntersectionSimState {
                     }
                 }
 
-                return false;
+                return None;
             }
         }
 
@@ -416,6 +588,13 @@ impl IntersectionSimState {
                 ));
             }
         }
+        for r in self.restricted.get_mut(&turn).into_iter().flatten() {
+            r.last_entry = Some(now);
+        }
+        if let Some((incident, since)) = self.held.remove(&req) {
+            self.events
+                .push(Event::IncidentDelay(incident, agent, now - since));
+        }
         state.accepted.insert(req);
         if self.break_turn_conflict_cycles {
             if let AgentID::Car(car) = agent {
@@ -423,7 +602,7 @@ impl IntersectionSimState {
             }
         }
 
-        true
+        Some(delay)
     }
 
     pub fn debug(&self, id: IntersectionID, map: &Map) {
@@ -650,6 +829,15 @@ impl IntersectionSimState {
         // Priority vehicles getting scheduled first just requires a little tweak in
         // update_intersection.
 
+        // Pedestrians only start crossing while the walk indication shows. The rest of the green
+        // is clearance time for anybody already crossing, timed for the design walking speed, not
+        // this pedestrian's.
+        if turn.turn_type == TurnType::Crosswalk {
+            let m = signal.turn_to_movement(req.turn);
+            return signal.walk_time_left(signal_state.current_stage, m, remaining_stage_time)
+                > Duration::ZERO;
+        }
+
         // TODO Make sure we can optimistically finish this turn before an approaching
         // higher-priority vehicle wants to begin.
 
@@ -818,3 +1006,52 @@ fn allow_block_the_box(osm_node_id: i64) -> bool {
         || osm_node_id == 53209840
         || osm_node_id == 4249361353
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abstutil::Timer;
+    use map_model::raw::RawMap;
+
+    #[test]
+    fn test_overlapping_incidents() {
+        let map =
+            Map::create_from_raw(RawMap::synthetic_grid(3, 3), false, &mut Timer::throwaway());
+        let mut scheduler = Scheduler::new();
+        let mut intersections =
+            IntersectionSimState::new(&map, &mut scheduler, false, false, false, false);
+        let turn = map
+            .all_turns()
+            .values()
+            .find(|t| map.get_l(t.id.src).is_driving())
+            .unwrap()
+            .id;
+        let now = Time::START_OF_DAY;
+        let capacity = |i: &IntersectionSimState| i.get_restriction(turn).map(|r| r.capacity);
+
+        // A crash slows things down, then the lane is closed for cleanup
+        intersections.restrict_turn(turn, 0, 0.5);
+        intersections.restrict_turn(turn, 1, 0.0);
+        assert!(intersections.is_closed(turn));
+
+        // The crash ending doesn't reopen the lane
+        intersections.lift_restriction(turn, 0, now, &mut scheduler, &map);
+        assert!(intersections.is_closed(turn));
+        intersections.lift_restriction(turn, 1, now, &mut scheduler, &map);
+        assert!(!intersections.is_closed(turn));
+        assert_eq!(capacity(&intersections), None);
+        assert!(intersections.restricted.is_empty());
+
+        // The other way around, the crash still slows things down after the cleanup ends
+        intersections.restrict_turn(turn, 2, 0.5);
+        intersections.restrict_turn(turn, 3, 0.0);
+        intersections.lift_restriction(turn, 3, now, &mut scheduler, &map);
+        assert!(!intersections.is_closed(turn));
+        assert_eq!(capacity(&intersections), Some(0.5));
+        // Lifting it twice does nothing
+        intersections.lift_restriction(turn, 3, now, &mut scheduler, &map);
+        assert_eq!(capacity(&intersections), Some(0.5));
+        intersections.lift_restriction(turn, 2, now, &mut scheduler, &map);
+        assert_eq!(capacity(&intersections), None);
+    }
+}
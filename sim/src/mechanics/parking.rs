@@ -1,16 +1,60 @@
-use crate::{CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID, Vehicle};
+use crate::{
+    CarID, CarStatus, DrawCarInput, Event, ParkedCar, ParkingSpot, PersonID, TripID, Vehicle,
+};
 use abstutil::{
     deserialize_btreemap, deserialize_multimap, serialize_btreemap, serialize_multimap, MultiMap,
     Timer,
 };
-use geom::{Distance, PolyLine, Pt2D};
+use geom::{Distance, Duration, PolyLine, Pt2D, Time};
 use map_model::{
-    BuildingID, Lane, LaneID, LaneType, Map, OffstreetParking, ParkingLotID, PathConstraints,
-    PathStep, Position, Traversable, TurnID,
+    BuildingID, ConditionalRestriction, Lane, LaneID, LaneType, Map, OffstreetParking,
+    ParkingLotID, ParkingRules, PathConstraints, PathStep, Position, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
 
+// When looking for parking, drivers will go this much farther to save a dollar per hour...
+const DETOUR_PER_DOLLAR_PER_HOUR: Distance = Distance::const_meters(200.0);
+// ... but no farther than this in total, no matter how expensive the closest spot is.
+const MAX_DETOUR_FOR_PRICE: Distance = Distance::const_meters(1000.0);
+// With ParkingStrategy::ExpandRadius, drivers start by checking lanes this close to their
+// destination, then widen the circle every minute they spend searching.
+const INITIAL_SEARCH_RADIUS: Distance = Distance::const_meters(150.0);
+const SEARCH_RADIUS_PER_MINUTE: Distance = Distance::const_meters(100.0);
+
+// How drivers look for a free spot when the lane they're on is full
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum ParkingStrategy {
+    // Drivers magically know about every free spot, no matter how far away it is.
+    Omniscient,
+    // Drivers only see spots on lanes they pass. They keep turning towards the destination,
+    // preferring lanes they haven't checked yet.
+    CircleBlock,
+    // Drivers only see spots on lanes they pass. They sweep outwards, checking lanes within a
+    // radius of the destination that grows the longer they search.
+    ExpandRadius,
+    // Drivers head straight for the closest lot or public garage, and only circle the block if
+    // there's no space in any of them.
+    NearestGarage,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ParkingSearch {
+    pub strategy: ParkingStrategy,
+    // After cruising this long, drivers give up on a free spot nearby and head to the closest lot
+    // or garage with space, even if it's far away or expensive. Doesn't matter for Omniscient.
+    pub give_up_after: Duration,
+}
+
+impl ParkingSearch {
+    pub fn omniscient() -> ParkingSearch {
+        ParkingSearch {
+            strategy: ParkingStrategy::Omniscient,
+            give_up_after: Duration::minutes(10),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ParkingSimState {
     #[serde(
@@ -24,6 +68,12 @@ pub struct ParkingSimState {
     )]
     occupants: BTreeMap<ParkingSpot, CarID>,
     reserved_spots: BTreeSet<ParkingSpot>,
+    // When each car parked, and the trip that parked it, to charge for the stay when it leaves
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    stays: BTreeMap<CarID, (Time, Option<TripID>)>,
 
     // On-street
     onstreet_lanes: BTreeMap<LaneID, ParkingLane>,
@@ -50,18 +100,25 @@ pub struct ParkingSimState {
         deserialize_with = "deserialize_multimap"
     )]
     driving_to_lots: MultiMap<LaneID, ParkingLotID>,
+    #[serde(
+        serialize_with = "serialize_btreemap",
+        deserialize_with = "deserialize_btreemap"
+    )]
+    lot_rules: BTreeMap<ParkingLotID, ParkingRules>,
 
+    search: ParkingSearch,
     events: Vec<Event>,
 }
 
 impl ParkingSimState {
     // Counterintuitive: any spots located in blackholes are just not represented here. If somebody
     // tries to drive from a blackholed spot, they couldn't reach most places.
-    pub fn new(map: &Map, timer: &mut Timer) -> ParkingSimState {
+    pub fn new(map: &Map, search: ParkingSearch, timer: &mut Timer) -> ParkingSimState {
         let mut sim = ParkingSimState {
             parked_cars: BTreeMap::new(),
             occupants: BTreeMap::new(),
             reserved_spots: BTreeSet::new(),
+            stays: BTreeMap::new(),
 
             onstreet_lanes: BTreeMap::new(),
             driving_to_parking_lanes: MultiMap::new(),
@@ -69,7 +126,9 @@ impl ParkingSimState {
             driving_to_offstreet: MultiMap::new(),
             num_spots_per_lot: BTreeMap::new(),
             driving_to_lots: MultiMap::new(),
+            lot_rules: BTreeMap::new(),
 
+            search,
             events: Vec::new(),
         };
         for l in map.all_lanes() {
@@ -94,6 +153,9 @@ impl ParkingSimState {
             if !map.get_l(pl.driving_pos.lane()).driving_blackhole {
                 sim.num_spots_per_lot.insert(pl.id, pl.capacity());
                 sim.driving_to_lots.insert(pl.driving_pos.lane(), pl.id);
+                if let Some(ref rules) = pl.parking_rules {
+                    sim.lot_rules.insert(pl.id, rules.clone());
+                }
             }
         }
         sim
@@ -102,7 +164,7 @@ impl ParkingSimState {
     // Returns any cars that got very abruptly evicted from existence
     pub fn handle_live_edits(&mut self, map: &Map, timer: &mut Timer) -> Vec<ParkedCar> {
         let (filled_before, _) = self.get_all_parking_spots();
-        let new = ParkingSimState::new(map, timer);
+        let new = ParkingSimState::new(map, self.search, timer);
         let (_, avail_after) = new.get_all_parking_spots();
         let avail_after: BTreeSet<ParkingSpot> = avail_after.into_iter().collect();
 
@@ -113,6 +175,7 @@ impl ParkingSimState {
         self.driving_to_offstreet = new.driving_to_offstreet;
         self.num_spots_per_lot = new.num_spots_per_lot;
         self.driving_to_lots = new.driving_to_lots;
+        self.lot_rules = new.lot_rules;
 
         // For every spot filled or reserved before, make sure that same spot still exists. If not,
         // evict that car.
@@ -120,6 +183,7 @@ impl ParkingSimState {
         for spot in filled_before {
             if !avail_after.contains(&spot) {
                 let car = self.occupants.remove(&spot).unwrap();
+                self.stays.remove(&car);
                 evicted.push(self.parked_cars.remove(&car).unwrap());
             }
         }
@@ -186,7 +250,7 @@ impl ParkingSimState {
         }
     }
 
-    pub fn remove_parked_car(&mut self, p: ParkedCar) {
+    pub fn remove_parked_car(&mut self, p: ParkedCar, now: Time) {
         self.parked_cars
             .remove(&p.vehicle.id)
             .expect("remove_parked_car missing from parked_cars");
@@ -195,11 +259,26 @@ impl ParkingSimState {
             .expect("remove_parked_car missing from occupants");
         self.events
             .push(Event::CarLeftParkingSpot(p.vehicle.id, p.spot));
+
+        // The trip that parked the car pays for the whole stay. Cars that were already parked
+        // when the simulation started don't belong to a trip, so they park for free.
+        if let Some((since, Some(trip))) = self.stays.remove(&p.vehicle.id) {
+            if let Some((cents, overstayed)) = self
+                .get_parking_rules(p.spot)
+                .map(|rules| (rules.cost_cents(since, now), rules.overstayed(since, now)))
+            {
+                if cents > 0 || overstayed {
+                    self.events
+                        .push(Event::PaidForParking(trip, p.spot, cents, overstayed));
+                }
+            }
+        }
     }
 
-    pub fn add_parked_car(&mut self, p: ParkedCar) {
+    pub fn add_parked_car(&mut self, p: ParkedCar, now: Time, trip: Option<TripID>) {
         self.events
             .push(Event::CarReachedParkingSpot(p.vehicle.id, p.spot));
+        self.stays.insert(p.vehicle.id, (now, trip));
 
         assert!(self.reserved_spots.remove(&p.spot));
 
@@ -320,11 +399,15 @@ impl ParkingSimState {
         // Either the building where a seeded car starts or the target of a trip. For filtering
         // private spots.
         target: BuildingID,
+        now: Time,
         map: &Map,
     ) -> Vec<(ParkingSpot, Position)> {
         let mut candidates = Vec::new();
 
         for l in self.driving_to_parking_lanes.get(driving_pos.lane()) {
+            if !self.onstreet_lanes[l].allowed_at(now) {
+                continue;
+            }
             for spot in self.onstreet_lanes[l].spots() {
                 if self.is_free(spot)
                     && driving_pos.dist_along()
@@ -400,6 +483,25 @@ impl ParkingSimState {
         }
     }
 
+    // Prices and time limits. Garages are always free.
+    pub fn get_parking_rules(&self, spot: ParkingSpot) -> Option<&ParkingRules> {
+        match spot {
+            ParkingSpot::Onstreet(l, _) => self.onstreet_lanes[&l].rules.as_ref(),
+            ParkingSpot::Offstreet(_, _) => None,
+            ParkingSpot::Lot(pl, _) => self.lot_rules.get(&pl),
+        }
+    }
+
+    // How much farther a driver would go to avoid paying for this spot
+    pub fn price_penalty(&self, spot: ParkingSpot, now: Time) -> Distance {
+        match self.get_parking_rules(spot) {
+            Some(rules) if rules.applies(now) => (DETOUR_PER_DOLLAR_PER_HOUR
+                * (rules.cents_per_hour as f64 / 100.0))
+                .min(MAX_DETOUR_FOR_PRICE),
+            _ => Distance::ZERO,
+        }
+    }
+
     pub fn get_owner_of_car(&self, id: CarID) -> Option<PersonID> {
         self.parked_cars.get(&id).and_then(|p| p.vehicle.owner)
     }
@@ -436,49 +538,71 @@ impl ParkingSimState {
         (filled, available)
     }
 
-    // Unrealistically assumes the driver has knowledge of currently free parking spots, even if
-    // they're far away. Since they don't reserve the spot in advance, somebody else can still beat
-    // them there, producing some nice, realistic churn if there's too much contention.
+    pub fn search_options(&self) -> ParkingSearch {
+        self.search
+    }
+
+    // Assumes the driver has knowledge of currently free parking spots, even if they're far away.
+    // That's unrealistic for on-street parking (see ParkingStrategy), but fine for lots and
+    // garages that advertise how many spots are left; offstreet_only restricts the search to
+    // those. Since they don't reserve the spot in advance, somebody else can still beat them
+    // there, producing some nice, realistic churn if there's too much contention. Drivers will go
+    // a bit farther for cheaper parking; see price_penalty.
     // The first PathStep is the turn after start, NOT PathStep::Lane(start).
     pub fn path_to_free_parking_spot(
         &self,
         start: LaneID,
         vehicle: &Vehicle,
         target: BuildingID,
+        now: Time,
         map: &Map,
+        offstreet_only: bool,
     ) -> Option<(Vec<PathStep>, ParkingSpot, Position)> {
         let mut backrefs: HashMap<LaneID, TurnID> = HashMap::new();
-        // Don't travel far.
+        // Search outwards from the start. Once some spot is found, only keep going while a cheaper
+        // one could still turn up. Since the price penalty is capped, that's never more than
+        // MAX_DETOUR_FOR_PRICE past the closest free spot.
         // This is a max-heap, so negate all distances. Tie breaker is lane ID, arbitrary but
         // deterministic.
         let mut queue: BinaryHeap<(Distance, LaneID)> = BinaryHeap::new();
         queue.push((Distance::ZERO, start));
 
+        // The cheapest spot found so far. Keep searching until nothing closer could beat it.
+        let mut best: Option<(Distance, LaneID, ParkingSpot, Position)> = None;
+
         while !queue.is_empty() {
             let (dist_so_far, current) = queue.pop().unwrap();
+            if let Some((cost, _, _, _)) = best {
+                if -dist_so_far >= cost {
+                    break;
+                }
+            }
             // If the current lane has a spot open, we wouldn't be asking. This can happen if a spot
             // opens up on the 'start' lane, but behind the car.
             if current != start {
-                // Pick the closest to the start of the lane, since that's closest to where we came
-                // from
-                if let Some((spot, pos)) = self
-                    .get_all_free_spots(Position::start(current), vehicle, target, map)
+                // Prefer spots closest to the start of the lane, since that's closest to where we
+                // came from
+                if let Some((cost, spot, pos)) = self
+                    .get_all_free_spots(Position::start(current), vehicle, target, now, map)
                     .into_iter()
-                    .min_by_key(|(_, pos)| pos.dist_along())
+                    .filter(|(spot, _)| match spot {
+                        ParkingSpot::Onstreet(_, _) => !offstreet_only,
+                        ParkingSpot::Offstreet(_, _) | ParkingSpot::Lot(_, _) => true,
+                    })
+                    .map(|(spot, pos)| {
+                        (
+                            -dist_so_far + pos.dist_along() + self.price_penalty(spot, now),
+                            spot,
+                            pos,
+                        )
+                    })
+                    .min_by_key(|(cost, _, _)| *cost)
                 {
-                    let mut steps = vec![PathStep::Lane(current)];
-                    let mut current = current;
-                    loop {
-                        if current == start {
-                            // Don't include PathStep::Lane(start)
-                            steps.pop();
-                            steps.reverse();
-                            return Some((steps, spot, pos));
-                        }
-                        let turn = backrefs[&current];
-                        steps.push(PathStep::Turn(turn));
-                        steps.push(PathStep::Lane(turn.src));
-                        current = turn.src;
+                    if best
+                        .map(|(best_cost, _, _, _)| cost < best_cost)
+                        .unwrap_or(true)
+                    {
+                        best = Some((cost, current, spot, pos));
                     }
                 }
             }
@@ -492,22 +616,67 @@ impl ParkingSimState {
             }
         }
 
-        None
+        let (_, mut current, spot, pos) = best?;
+        let mut steps = vec![PathStep::Lane(current)];
+        loop {
+            if current == start {
+                // Don't include PathStep::Lane(start)
+                steps.pop();
+                steps.reverse();
+                return Some((steps, spot, pos));
+            }
+            let turn = backrefs[&current];
+            steps.push(PathStep::Turn(turn));
+            steps.push(PathStep::Lane(turn.src));
+            current = turn.src;
+        }
+    }
+
+    // When a driver without perfect knowledge finds the lane they're on full, pick the next lane to
+    // check, based on the ParkingStrategy. visited has an entry for every time the driver checked
+    // a lane. Returns None at dead-ends.
+    pub fn next_lane_to_search(
+        &self,
+        current: LaneID,
+        target: BuildingID,
+        visited: &[LaneID],
+        searching_for: Duration,
+        map: &Map,
+    ) -> Option<TurnID> {
+        let goal = map.get_b(target).label_center;
+        let radius = INITIAL_SEARCH_RADIUS
+            + SEARCH_RADIUS_PER_MINUTE * (searching_for / Duration::minutes(1));
+        map.get_turns_for(current, PathConstraints::Car)
+            .into_iter()
+            .filter(|turn| !map.get_l(turn.id.dst).driving_blackhole)
+            .min_by_key(|turn| {
+                let l = turn.id.dst;
+                let times_visited = visited.iter().filter(|x| **x == l).count();
+                // Drivers can see if a street has any parking at all, even if it's full.
+                let no_parking = self.driving_to_parking_lanes.get(l).is_empty()
+                    && self.driving_to_offstreet.get(l).is_empty()
+                    && self.driving_to_lots.get(l).is_empty();
+                let dist = map.get_l(l).lane_center_pts.middle().dist_to(goal);
+                match self.search.strategy {
+                    // Sweep the farthest lanes still inside the radius
+                    ParkingStrategy::ExpandRadius => {
+                        (dist > radius, times_visited, no_parking, -dist, l)
+                    }
+                    _ => (false, times_visited, no_parking, dist, l),
+                }
+            })
+            .map(|turn| turn.id)
     }
 
     pub fn collect_events(&mut self) -> Vec<Event> {
         std::mem::replace(&mut self.events, Vec::new())
     }
 
+    // Delivery vehicles in loading zones don't belong to anybody, so they're skipped
     pub fn all_parked_car_positions(&self, map: &Map) -> Vec<(Position, PersonID)> {
         self.parked_cars
             .values()
-            .map(|p| {
-                (
-                    self.spot_to_sidewalk_pos(p.spot, map),
-                    p.vehicle.owner.unwrap(),
-                )
-            })
+            .filter_map(|p| Some((self.spot_to_sidewalk_pos(p.spot, map), p.vehicle.owner?)))
             .collect()
     }
 }
@@ -519,6 +688,8 @@ struct ParkingLane {
     sidewalk: LaneID,
     // The front of the parking spot (farthest along the lane)
     spot_dist_along: Vec<Distance>,
+    conditional: Option<ConditionalRestriction>,
+    rules: Option<ParkingRules>,
 }
 
 impl ParkingLane {
@@ -554,9 +725,19 @@ impl ParkingLane {
             spot_dist_along: (0..lane.number_parking_spots())
                 .map(|idx| map_model::PARKING_SPOT_LENGTH * (2.0 + idx as f64))
                 .collect(),
+            conditional: lane.conditional.clone(),
+            rules: lane.parking_rules.clone(),
         })
     }
 
+    // Cars already parked can stay, but nobody new can park here while this is false.
+    fn allowed_at(&self, now: Time) -> bool {
+        match self.conditional {
+            Some(ref c) => !c.is_active(now) || c.allow.contains(PathConstraints::Car),
+            None => true,
+        }
+    }
+
     fn dist_along_for_car(&self, spot_idx: usize, vehicle: &Vehicle) -> Distance {
         // Find the offset to center this particular car in the parking spot
         self.spot_dist_along[spot_idx] - (map_model::PARKING_SPOT_LENGTH - vehicle.length) / 2.0
@@ -570,3 +751,41 @@ impl ParkingLane {
         spots
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{VehicleSpec, VehicleType, MAX_CAR_LENGTH};
+    use map_model::raw::{OriginalRoad, RawMap};
+
+    #[test]
+    fn test_parking_only_off_peak() {
+        let mut raw = RawMap::synthetic_grid(3, 3);
+        let id = OriginalRoad::new(1, (1, 2));
+        raw.roads.get_mut(&id).unwrap().osm_tags.insert(
+            "parking:lane:both:conditional",
+            "no_stopping @ (Mo-Fr 07:00-09:00)",
+        );
+        let mut timer = Timer::throwaway();
+        let map = Map::create_from_raw(raw, false, &mut timer);
+        let parking = ParkingSimState::new(&map, ParkingSearch::omniscient(), &mut timer);
+        let lane = map
+            .get_r(map.find_r_by_osm_id(id).unwrap())
+            .all_lanes()
+            .into_iter()
+            .find(|l| map.get_l(*l).is_driving())
+            .unwrap();
+        let vehicle = VehicleSpec::new(VehicleType::Car, MAX_CAR_LENGTH, None)
+            .make(CarID(0, VehicleType::Car), None);
+
+        let onstreet_spots = |now: Time| {
+            parking
+                .get_all_free_spots(Position::start(lane), &vehicle, BuildingID(0), now, &map)
+                .into_iter()
+                .filter(|(spot, _)| matches!(spot, ParkingSpot::Onstreet(_, _)))
+                .count()
+        };
+        assert_eq!(onstreet_spots(Time::START_OF_DAY + Duration::hours(8)), 0);
+        assert!(onstreet_spots(Time::START_OF_DAY + Duration::hours(10)) > 0);
+    }
+}
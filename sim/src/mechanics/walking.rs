@@ -1,21 +1,30 @@
 use crate::sim::Ctx;
 use crate::{
-    AgentID, AgentProperties, Command, CreatePedestrian, DistanceInterval, DrawPedCrowdInput,
-    DrawPedestrianInput, Event, IntersectionSimState, ParkedCar, ParkingSpot, PedCrowdLocation,
-    PedestrianID, PersonID, Scheduler, SidewalkPOI, SidewalkSpot, TimeInterval, TransitSimState,
-    TripID, TripManager, UnzoomedAgent,
+    walking_speed_on_grade, AgentID, AgentProperties, Command, CreatePedestrian, DistanceInterval,
+    DrawPedCrowdInput, DrawPedestrianInput, DrivingSimState, Event, IntersectionSimState,
+    ParkedCar, ParkingSpot, PedCrowdLocation, PedestrianID, PersonID, Scheduler, SidewalkPOI,
+    SidewalkSpot, TimeInterval, TransitSimState, TripID, TripManager, UnzoomedAgent, Weather,
 };
 use abstutil::{deserialize_multimap, serialize_multimap, MultiMap};
 use geom::{Distance, Duration, Line, PolyLine, Speed, Time};
+use map_model::raw::CrossingType;
 use map_model::{
-    BuildingID, BusRouteID, DrivingSide, Map, ParkingLotID, Path, PathStep, Traversable,
-    SIDEWALK_THICKNESS,
+    BuildingID, BusRouteID, DrivingSide, LaneID, Map, MidBlockCrossing, ParkingLotID, Path,
+    PathStep, Traversable, SIDEWALK_THICKNESS,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
 const TIME_TO_START_BIKING: Duration = Duration::const_seconds(30.0);
 const TIME_TO_FINISH_BIKING: Duration = Duration::const_seconds(45.0);
+// Slack on top of the time to cross mid-block, so nobody's still in the road when a vehicle passes
+const TIME_TO_STOP: Duration = Duration::const_seconds(2.0);
+// Nothing tells a pedestrian when a gap in traffic opens up, so they keep looking
+const LOOK_FOR_GAP_EVERY: Duration = Duration::const_seconds(1.0);
+// Signals at mid-block crossings aren't coordinated with anything else. They give a walk signal at
+// the start of every cycle, counting from midnight.
+const MID_BLOCK_SIGNAL_CYCLE: Duration = Duration::const_seconds(90.0);
+const MID_BLOCK_WALK_TIME: Duration = Duration::const_seconds(15.0);
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct WalkingSimState {
@@ -27,14 +36,17 @@ pub struct WalkingSimState {
     )]
     peds_per_traversable: MultiMap<Traversable, PedestrianID>,
     events: Vec<Event>,
+
+    weather: Weather,
 }
 
 impl WalkingSimState {
-    pub fn new() -> WalkingSimState {
+    pub fn new(weather: Weather) -> WalkingSimState {
         WalkingSimState {
             peds: BTreeMap::new(),
             peds_per_traversable: MultiMap::new(),
             events: Vec::new(),
+            weather,
         }
     }
 
@@ -45,10 +57,18 @@ impl WalkingSimState {
         map: &Map,
         scheduler: &mut Scheduler,
     ) {
+        let path = params.path;
+        let crossings: VecDeque<(MidBlockCrossing, Path)> = params.crossings.into_iter().collect();
+
         let start_lane = params.start.sidewalk_pos.lane();
-        assert_eq!(params.path.current_step().as_lane(), start_lane);
+        assert_eq!(path.current_step().as_lane(), start_lane);
         assert_eq!(
-            params.path.last_step().as_lane(),
+            crossings
+                .back()
+                .map(|(_, p)| p)
+                .unwrap_or(&path)
+                .last_step()
+                .as_lane(),
             params.goal.sidewalk_pos.lane()
         );
 
@@ -62,10 +82,11 @@ impl WalkingSimState {
                     Time::START_OF_DAY + Duration::seconds(1.0),
                 ),
             ),
-            speed: params.speed,
+            speed: params.speed * self.weather.walking_speed_factor(),
             total_blocked_time: Duration::ZERO,
             started_at: now,
-            path: params.path,
+            path,
+            crossings,
             goal: params.goal,
             trip: params.trip,
             person: params.person,
@@ -120,9 +141,13 @@ impl WalkingSimState {
         ctx: &mut Ctx,
         trips: &mut TripManager,
         transit: &mut TransitSimState,
+        driving: &DrivingSimState,
     ) {
         let mut ped = self.peds.get_mut(&id).unwrap();
         match ped.state {
+            PedState::Crossing(_, _) if ped.path.is_last_step() && !ped.crossings.is_empty() => {
+                ped.maybe_cross_mid_block(now, driving, ctx);
+            }
             PedState::Crossing(ref dist_int, _) => {
                 if ped.path.is_last_step() {
                     match ped.goal.connection {
@@ -225,6 +250,32 @@ impl WalkingSimState {
                     }
                 }
             }
+            PedState::WaitingToCross(_) => {
+                ped.maybe_cross_mid_block(now, driving, ctx);
+            }
+            PedState::CrossingMidBlock(_) => {
+                let (crossing, path) = ped.crossings.pop_front().unwrap();
+                ctx.intersections.finish_mid_block_crossing(
+                    ped.id,
+                    lanes_crossed(&crossing, ctx.map),
+                    now,
+                    ctx.scheduler,
+                    ctx.map,
+                );
+                self.peds_per_traversable
+                    .remove(ped.path.current_step().as_traversable(), ped.id);
+                ped.path = path;
+                ped.state = ped.crossing_state(crossing.to.dist_along(), now, ctx.map);
+                self.peds_per_traversable
+                    .insert(ped.path.current_step().as_traversable(), ped.id);
+                self.events.push(Event::AgentEntersTraversable(
+                    AgentID::Pedestrian(ped.id),
+                    ped.path.current_step().as_traversable(),
+                    None,
+                ));
+                ctx.scheduler
+                    .push(ped.state.get_end_time(), Command::UpdatePed(ped.id));
+            }
             PedState::WaitingToTurn(_, blocked_since) => {
                 if ped.maybe_transition(
                     now,
@@ -299,11 +350,20 @@ impl WalkingSimState {
         };
     }
 
-    pub fn delete_ped(&mut self, id: PedestrianID, scheduler: &mut Scheduler) {
+    pub fn delete_ped(&mut self, id: PedestrianID, now: Time, ctx: &mut Ctx) {
         let ped = self.peds.remove(&id).unwrap();
         self.peds_per_traversable
             .remove(ped.path.current_step().as_traversable(), id);
-        scheduler.cancel(Command::UpdatePed(id));
+        if let PedState::CrossingMidBlock(_) = ped.state {
+            ctx.intersections.finish_mid_block_crossing(
+                id,
+                lanes_crossed(&ped.crossings[0].0, ctx.map),
+                now,
+                ctx.scheduler,
+                ctx.map,
+            );
+        }
+        ctx.scheduler.cancel(Command::UpdatePed(id));
     }
 
     pub fn debug_ped(&self, id: PedestrianID) {
@@ -318,6 +378,7 @@ impl WalkingSimState {
         let p = &self.peds[&id];
         let time_spent_waiting = match p.state {
             PedState::WaitingToTurn(_, blocked_since)
+            | PedState::WaitingToCross(blocked_since)
             | PedState::WaitingForBus(_, blocked_since) => now - blocked_since,
             _ => Duration::ZERO,
         };
@@ -387,6 +448,8 @@ impl WalkingSimState {
         let mut backwards: Vec<(PedestrianID, Distance)> = Vec::new();
         let mut bldg_driveway: MultiMap<BuildingID, (PedestrianID, Distance)> = MultiMap::new();
         let mut lot_driveway: MultiMap<ParkingLotID, (PedestrianID, Distance)> = MultiMap::new();
+        // Out in the road, away from everybody else
+        let mut crossing_road: Vec<PedestrianID> = Vec::new();
 
         for id in self.peds_per_traversable.get(on) {
             let ped = &self.peds[id];
@@ -429,11 +492,17 @@ impl WalkingSimState {
                     // The backwards half of the sidewalk is closer to the road.
                     backwards.push((*id, dist));
                 }
+                PedState::WaitingToCross(_) | PedState::CrossingMidBlock(_) => {
+                    crossing_road.push(*id);
+                }
             }
         }
 
         let mut crowds: Vec<DrawPedCrowdInput> = Vec::new();
-        let mut loners: Vec<DrawPedestrianInput> = Vec::new();
+        let mut loners: Vec<DrawPedestrianInput> = crossing_road
+            .into_iter()
+            .map(|id| self.peds[&id].get_draw_ped(now, map))
+            .collect();
 
         // For each group, sort by distance along. Attempt to bundle into intervals.
         for (mut group, location, on_len) in vec![
@@ -515,6 +584,8 @@ struct Pedestrian {
     started_at: Time,
 
     path: Path,
+    // After finishing path, cross the road partway along, then walk the next path
+    crossings: VecDeque<(MidBlockCrossing, Path)>,
     goal: SidewalkSpot,
     trip: TripID,
     person: PersonID,
@@ -523,7 +594,10 @@ struct Pedestrian {
 impl Pedestrian {
     fn crossing_state(&self, start_dist: Distance, start_time: Time, map: &Map) -> PedState {
         let end_dist = if self.path.is_last_step() {
-            self.goal.sidewalk_pos.dist_along()
+            match self.crossings.front() {
+                Some((crossing, _)) => crossing.from.dist_along(),
+                None => self.goal.sidewalk_pos.dist_along(),
+            }
         } else {
             // TODO PathStep should have a end_dist... or end_pos
             match self.path.current_step() {
@@ -533,7 +607,16 @@ impl Pedestrian {
             }
         };
         let dist_int = DistanceInterval::new_walking(start_dist, end_dist);
-        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / self.speed);
+        // Contraflow intervals run backwards, so the grade is already in the direction of travel.
+        let speed = match self.path.current_step() {
+            PathStep::Lane(l) | PathStep::ContraflowLane(l) => walking_speed_on_grade(
+                self.speed,
+                map.get_l(l)
+                    .percent_grade_between(map, dist_int.start, dist_int.end),
+            ),
+            PathStep::Turn(_) => self.speed,
+        };
+        let time_int = TimeInterval::new(start_time, start_time + dist_int.length() / speed);
         PedState::Crossing(dist_int, time_int)
     }
 
@@ -550,6 +633,9 @@ impl Pedestrian {
             PedState::StartingToBike(ref spot, _, _) => spot.sidewalk_pos.dist_along(),
             PedState::FinishingBiking(ref spot, _, _) => spot.sidewalk_pos.dist_along(),
             PedState::WaitingForBus(_, _) => self.goal.sidewalk_pos.dist_along(),
+            PedState::WaitingToCross(_) | PedState::CrossingMidBlock(_) => {
+                self.crossings[0].0.from.dist_along()
+            }
         }
     }
 
@@ -637,6 +723,18 @@ impl Pedestrian {
                     .unwrap_or(line.pt1()),
                 line.angle(),
             ),
+            PedState::WaitingToCross(_) => {
+                let line = self.crossings[0].0.geom(map);
+                (line.pt1(), line.angle())
+            }
+            PedState::CrossingMidBlock(ref time_int) => {
+                let line = self.crossings[0].0.geom(map);
+                (
+                    line.percent_along(time_int.percent(now))
+                        .unwrap_or(line.pt1()),
+                    line.angle(),
+                )
+            }
             PedState::WaitingForBus(_, _) => {
                 let (pt, angle) = self.goal.sidewalk_pos.pt_and_angle(map);
                 // Stand on the far side of the sidewalk (by the bus stop), facing the road
@@ -661,6 +759,51 @@ impl Pedestrian {
         }
     }
 
+    // Starts crossing the road partway along when traffic allows. Otherwise waits and looks again
+    // shortly.
+    fn maybe_cross_mid_block(&mut self, now: Time, driving: &DrivingSimState, ctx: &mut Ctx) {
+        let blocked_since = match self.state {
+            PedState::WaitingToCross(since) => since,
+            _ => now,
+        };
+        let crossing = &self.crossings[0].0;
+        if crossing.crossing_type == Some(CrossingType::Signalized) {
+            let into_cycle = (now - Time::START_OF_DAY) % MID_BLOCK_SIGNAL_CYCLE;
+            if into_cycle >= MID_BLOCK_WALK_TIME {
+                self.state = PedState::WaitingToCross(blocked_since);
+                ctx.scheduler.push(
+                    now + (MID_BLOCK_SIGNAL_CYCLE - into_cycle),
+                    Command::UpdatePed(self.id),
+                );
+                return;
+            }
+        }
+
+        // Vehicles about to enter the lanes wait at the turn until we're across, but the ones
+        // already on them don't stop partway along, even at a marked crosswalk. So wait for a gap
+        // long enough to get across.
+        let time_to_cross = crossing.length(ctx.map) / self.speed;
+        let gap = time_to_cross + TIME_TO_STOP;
+        let lanes = lanes_crossed(crossing, ctx.map);
+        let clear = lanes.iter().all(|l| {
+            let dist = crossing.from.equiv_pos(*l, ctx.map).dist_along();
+            let speed_limit = ctx.map.get_parent(*l).speed_limit;
+            !driving.vehicle_approaching(now, *l, dist, speed_limit * gap)
+        });
+
+        if clear {
+            self.total_blocked_time += now - blocked_since;
+            ctx.intersections.start_mid_block_crossing(self.id, lanes);
+            self.state = PedState::CrossingMidBlock(TimeInterval::new(now, now + time_to_cross));
+            ctx.scheduler
+                .push(self.state.get_end_time(), Command::UpdatePed(self.id));
+        } else {
+            self.state = PedState::WaitingToCross(blocked_since);
+            ctx.scheduler
+                .push(now + LOOK_FOR_GAP_EVERY, Command::UpdatePed(self.id));
+        }
+    }
+
     // True if we successfully continued to the next step of our path
     fn maybe_transition(
         &mut self,
@@ -672,15 +815,18 @@ impl Pedestrian {
         scheduler: &mut Scheduler,
     ) -> bool {
         if let PathStep::Turn(t) = self.path.next_step() {
-            if !intersections.maybe_start_turn(
-                AgentID::Pedestrian(self.id),
-                t,
-                self.speed,
-                now,
-                map,
-                scheduler,
-                None,
-            ) {
+            if intersections
+                .maybe_start_turn(
+                    AgentID::Pedestrian(self.id),
+                    t,
+                    self.speed,
+                    now,
+                    map,
+                    scheduler,
+                    None,
+                )
+                .is_none()
+            {
                 return false;
             }
         }
@@ -715,6 +861,9 @@ enum PedState {
     StartingToBike(SidewalkSpot, Line, TimeInterval),
     FinishingBiking(SidewalkSpot, Line, TimeInterval),
     WaitingForBus(BusRouteID, Time),
+    // At the start of the next mid-block crossing. The Time is blocked_since.
+    WaitingToCross(Time),
+    CrossingMidBlock(TimeInterval),
 }
 
 impl PedState {
@@ -729,10 +878,22 @@ impl PedState {
             PedState::StartingToBike(_, _, ref time_int) => time_int.end,
             PedState::FinishingBiking(_, _, ref time_int) => time_int.end,
             PedState::WaitingForBus(_, _) => unreachable!(),
+            PedState::WaitingToCross(_) => unreachable!(),
+            PedState::CrossingMidBlock(ref time_int) => time_int.end,
         }
     }
 }
 
+// Vehicles using these lanes have to watch out for somebody crossing
+fn lanes_crossed(crossing: &MidBlockCrossing, map: &Map) -> Vec<LaneID> {
+    map.get_r(crossing.road(map))
+        .lanes_ltr()
+        .into_iter()
+        .filter(|(_, _, lt)| lt.is_for_moving_vehicles())
+        .map(|(l, _, _)| l)
+        .collect()
+}
+
 // The crowds returned here may have low/high values extending up to radius past the real geometry.
 fn find_crowds(
     input: Vec<(PedestrianID, Distance)>,
@@ -0,0 +1,343 @@
+use crate::{AgentID, Command, CommandType, Event};
+use geom::{Pt2D, Time};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+// How many matching commands to show before the first difference
+const CONTEXT: usize = 5;
+// A full day in a big city dispatches many millions of commands, so only remember what happened
+// after the most recent ones.
+const RECENT_ENTRIES: usize = 10_000;
+// Every kind of Command. The record of each command just stores an index into this.
+const KINDS: [&str; 15] = [
+    "SpawnCar",
+    "SpawnPed",
+    "StartTrip",
+    "UpdateCar",
+    "UpdateLaggyHead",
+    "UpdatePed",
+    "UpdateIntersection",
+    "Callback",
+    "Pandemic",
+    "FinishRemoteTrip",
+    "RequestRide",
+    "UpdateDelivery",
+    "FinishCharging",
+    "UpdateIncident",
+    "StartBus",
+];
+
+// An opt-in record of every Command the scheduler dispatches, for tracking down nondeterminism.
+// Two runs of the same scenario with the same flags should produce identical logs; the first
+// hash that differs is where they diverged.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayLog {
+    // One per command dispatched. Each covers that command and everything before it.
+    pub hashes: Vec<u64>,
+    // Also one per command dispatched
+    pub commands: Vec<ReplayCommand>,
+    // What happened after only the last RECENT_ENTRIES commands
+    pub recent: VecDeque<ReplayEntry>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayCommand {
+    pub time: Time,
+    // Into KINDS
+    kind: u8,
+    pub cmd: CommandType,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    // Into hashes
+    pub idx: usize,
+    // How many events handling the command produced
+    pub num_events: usize,
+    // Where the agent the command was about wound up, if it's still around
+    pub agent_pt: Option<Pt2D>,
+}
+
+impl ReplayLog {
+    pub(crate) fn new() -> ReplayLog {
+        ReplayLog {
+            hashes: Vec::new(),
+            commands: Vec::new(),
+            recent: VecDeque::new(),
+        }
+    }
+
+    // Which agent a command is about, if any
+    pub(crate) fn agent(cmd: &CommandType) -> Option<AgentID> {
+        match cmd {
+            CommandType::Car(c)
+            | CommandType::CarLaggyHead(c)
+            | CommandType::Delivery(c)
+            | CommandType::Charging(c) => Some(AgentID::Car(*c)),
+            CommandType::Ped(p) => Some(AgentID::Pedestrian(*p)),
+            _ => None,
+        }
+    }
+
+    // CommandType lumps some commands together, like spawning and updating a car. Recording
+    // which one it really was tells them apart.
+    pub(crate) fn kind(cmd: &Command) -> &'static str {
+        match cmd {
+            Command::SpawnCar(_, _) => "SpawnCar",
+            Command::SpawnPed(_) => "SpawnPed",
+            Command::StartTrip(_, _, _, _) => "StartTrip",
+            Command::UpdateCar(_) => "UpdateCar",
+            Command::UpdateLaggyHead(_) => "UpdateLaggyHead",
+            Command::UpdatePed(_) => "UpdatePed",
+            Command::UpdateIntersection(_) => "UpdateIntersection",
+            Command::Callback(_) => "Callback",
+            Command::Pandemic(_) => "Pandemic",
+            Command::FinishRemoteTrip(_) => "FinishRemoteTrip",
+            Command::RequestRide(_) => "RequestRide",
+            Command::UpdateDelivery(_) => "UpdateDelivery",
+            Command::FinishCharging(_) => "FinishCharging",
+            Command::UpdateIncident(_) => "UpdateIncident",
+            Command::StartBus(_, _) => "StartBus",
+        }
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        time: Time,
+        kind: &'static str,
+        cmd: CommandType,
+        events: &[Event],
+        agent_pt: Option<Pt2D>,
+        num_trips: (usize, usize),
+    ) {
+        // Use our own hash instead of std's, which may change between Rust versions, so logs from
+        // two different builds can be compared. Debug output of floats is exact.
+        let mut hash = self.hashes.last().cloned().unwrap_or(FNV_OFFSET_BASIS);
+        for byte in format!(
+            "{:?} {} {:?} {:?} {:?} {:?}",
+            time, kind, cmd, events, agent_pt, num_trips
+        )
+        .bytes()
+        {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+
+        if self.recent.len() == RECENT_ENTRIES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(ReplayEntry {
+            idx: self.hashes.len(),
+            num_events: events.len(),
+            agent_pt,
+        });
+        self.hashes.push(hash);
+        self.commands.push(ReplayCommand {
+            time,
+            kind: KINDS.iter().position(|k| *k == kind).unwrap() as u8,
+            cmd,
+        });
+    }
+
+    fn get(&self, idx: usize) -> Option<&ReplayEntry> {
+        let first = self.recent.front()?.idx;
+        if idx < first {
+            return None;
+        }
+        self.recent.get(idx - first)
+    }
+
+    fn describe(&self, idx: usize) -> String {
+        let c = &self.commands[idx];
+        let mut x = format!("{}: {} {:?}", c.time, KINDS[c.kind as usize], c.cmd);
+        if let Some(e) = self.get(idx) {
+            x = format!("{} produced {} events", x, e.num_events);
+            if let Some(pt) = e.agent_pt {
+                x = format!("{}, agent at {}", x, pt);
+            }
+        }
+        x
+    }
+
+    // Describes where two logs first disagree, or returns None if they match.
+    pub fn find_divergence(&self, other: &ReplayLog) -> Option<Vec<String>> {
+        let idx = match self
+            .hashes
+            .iter()
+            .zip(other.hashes.iter())
+            .position(|(h1, h2)| h1 != h2)
+        {
+            Some(idx) => idx,
+            None => {
+                let (n1, n2) = (self.hashes.len(), other.hashes.len());
+                if n1 == n2 {
+                    return None;
+                }
+                return Some(vec![format!(
+                    "Both logs match for {} commands, then the {} log stops, while the other \
+                     has {} more",
+                    n1.min(n2),
+                    if n1 < n2 { "first" } else { "second" },
+                    n1.max(n2) - n1.min(n2)
+                )]);
+            }
+        };
+
+        let mut lines = vec![format!("Both logs match for {} commands", idx)];
+        if idx > 0 {
+            lines.push("Leading up to the difference:".to_string());
+            for i in idx.saturating_sub(CONTEXT)..idx {
+                lines.push(format!("  {}", self.describe(i)));
+            }
+        }
+        let (c1, c2) = (&self.commands[idx], &other.commands[idx]);
+        if c1.time == c2.time && c1.kind == c2.kind && c1.cmd == c2.cmd {
+            lines.push("The same command had different results:".to_string());
+        } else {
+            lines.push("Different commands were dispatched:".to_string());
+        }
+        lines.push(format!("  first:  {}", self.describe(idx)));
+        lines.push(format!("  second: {}", other.describe(idx)));
+        if self.get(idx).is_none() || other.get(idx).is_none() {
+            lines.push(format!(
+                "Logs only keep what happened after the last {} commands. Save the logs sooner \
+                 to see more.",
+                RECENT_ENTRIES
+            ));
+        }
+        Some(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CarID, VehicleType};
+    use geom::Duration;
+    use map_model::IntersectionID;
+
+    // Spawns a car, then alternates between updating it and an intersection
+    fn log(num_cmds: usize, car_at: Pt2D) -> ReplayLog {
+        let car = CarID(0, VehicleType::Car);
+        let mut log = ReplayLog::new();
+        for idx in 0..num_cmds {
+            let time = Time::START_OF_DAY + Duration::seconds(idx as f64);
+            let (kind, cmd) = if idx == 0 {
+                ("SpawnCar", CommandType::Car(car))
+            } else if idx % 2 == 1 {
+                ("UpdateCar", CommandType::Car(car))
+            } else {
+                (
+                    "UpdateIntersection",
+                    CommandType::Intersection(IntersectionID(0)),
+                )
+            };
+            log.record(time, kind, cmd, &[], Some(car_at), (0, 1));
+        }
+        log
+    }
+
+    #[test]
+    fn test_equal_logs() {
+        let pt = Pt2D::new(1.0, 2.0);
+        assert!(log(10, pt).find_divergence(&log(10, pt)).is_none());
+        assert!(ReplayLog::new()
+            .find_divergence(&ReplayLog::new())
+            .is_none());
+    }
+
+    #[test]
+    fn test_mid_log_difference() {
+        let pt = Pt2D::new(1.0, 2.0);
+        let first = log(10, pt);
+
+        // The car wound up somewhere else after the 4th command
+        let mut second = log(3, pt);
+        second.record(
+            Time::START_OF_DAY + Duration::seconds(3.0),
+            "UpdateCar",
+            CommandType::Car(CarID(0, VehicleType::Car)),
+            &[],
+            Some(Pt2D::new(5.0, 2.0)),
+            (0, 1),
+        );
+        let lines = first.find_divergence(&second).unwrap();
+        assert_eq!(lines[0], "Both logs match for 3 commands");
+        assert_eq!(lines[1], "Leading up to the difference:");
+        assert!(lines[2].contains("SpawnCar"));
+        assert_eq!(lines[5], "The same command had different results:");
+
+        // The car was spawned again, instead of updated
+        let mut second = log(1, pt);
+        second.record(
+            Time::START_OF_DAY + Duration::seconds(1.0),
+            "SpawnCar",
+            CommandType::Car(CarID(0, VehicleType::Car)),
+            &[],
+            Some(pt),
+            (0, 1),
+        );
+        let lines = first.find_divergence(&second).unwrap();
+        assert_eq!(lines[0], "Both logs match for 1 commands");
+        assert_eq!(lines[3], "Different commands were dispatched:");
+    }
+
+    #[test]
+    fn test_truncated_log() {
+        let pt = Pt2D::new(1.0, 2.0);
+        let lines = log(10, pt).find_divergence(&log(7, pt)).unwrap();
+        assert_eq!(
+            lines,
+            vec![
+                "Both logs match for 7 commands, then the second log stops, while the other has \
+                 3 more"
+                    .to_string()
+            ]
+        );
+        let lines = log(0, pt).find_divergence(&log(2, pt)).unwrap();
+        assert!(lines[0].contains("the first log stops"));
+    }
+
+    #[test]
+    fn test_only_recent_details_kept() {
+        let first = log(RECENT_ENTRIES + 10, Pt2D::new(1.0, 2.0));
+        assert_eq!(first.hashes.len(), RECENT_ENTRIES + 10);
+        assert_eq!(first.recent.len(), RECENT_ENTRIES);
+        assert_eq!(first.recent[0].idx, 10);
+
+        // Every hash differs. What happened after the first command is gone, but not the command
+        // itself.
+        let second = log(RECENT_ENTRIES + 10, Pt2D::new(3.0, 4.0));
+        let lines = first.find_divergence(&second).unwrap();
+        assert_eq!(lines[0], "Both logs match for 0 commands");
+        assert_eq!(lines[1], "The same command had different results:");
+        assert!(lines[2].ends_with("SpawnCar Car(CarID(0, Car))"));
+        assert!(lines[4].starts_with("Logs only keep what happened"));
+    }
+
+    #[test]
+    fn test_old_commands_kept() {
+        let pt = Pt2D::new(1.0, 2.0);
+        let first = log(RECENT_ENTRIES + 10, pt);
+
+        // The car was spawned again early on, then everything else lined up
+        let car = CommandType::Car(CarID(0, VehicleType::Car));
+        let mut second = log(3, pt);
+        for idx in 3..RECENT_ENTRIES + 10 {
+            let kind = if idx == 3 { "SpawnCar" } else { "UpdateCar" };
+            let time = Time::START_OF_DAY + Duration::seconds(idx as f64);
+            second.record(time, kind, car.clone(), &[], Some(pt), (0, 1));
+        }
+        let lines = first.find_divergence(&second).unwrap();
+        assert_eq!(lines[0], "Both logs match for 3 commands");
+        assert_eq!(lines[1], "Leading up to the difference:");
+        assert!(lines[2].contains("SpawnCar"));
+        assert!(lines[4].contains("UpdateIntersection"));
+        assert_eq!(lines[5], "Different commands were dispatched:");
+        assert!(lines[6].contains("UpdateCar"));
+        assert!(lines[7].contains("SpawnCar"));
+        assert!(lines[8].starts_with("Logs only keep what happened"));
+    }
+}
@@ -1,15 +1,15 @@
 use crate::mechanics::Queue;
 use crate::{
-    CarID, Event, ParkingSimState, ParkingSpot, PersonID, SidewalkSpot, TripID, TripPhaseType,
-    Vehicle, VehicleType,
+    CarID, Event, IntersectionSimState, ParkingSimState, ParkingSpot, ParkingStrategy, PersonID,
+    SidewalkSpot, TripID, TripPhaseType, Vehicle, VehicleType,
 };
-use geom::Distance;
+use geom::{Distance, Duration, Time};
 use map_model::{
     BuildingID, IntersectionID, LaneID, Map, Path, PathConstraints, PathRequest, PathStep,
     Position, Traversable, TurnID,
 };
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Router {
@@ -26,6 +26,7 @@ pub enum ActionAtEnd {
     GotoLaneEnd,
     StopBiking(SidewalkSpot),
     BusAtStop,
+    FleetAtStop,
     GiveUpOnParking,
 }
 
@@ -37,7 +38,12 @@ enum Goal {
         spot: Option<(ParkingSpot, Distance)>,
         // No parking available at all!
         stuck_end_dist: Option<Distance>,
-        started_looking: bool,
+        // When the driver first reached the end of their original path and started looking
+        search_started: Option<Time>,
+        // Extra distance driven while looking for a spot
+        cruised: Distance,
+        // Every lane the driver checked for free spots, with repeats
+        visited: Vec<LaneID>,
     },
     EndAtBorder {
         end_dist: Distance,
@@ -49,6 +55,11 @@ enum Goal {
     FollowBusRoute {
         end_dist: Distance,
     },
+    // Ride-hailing vehicles picking up, dropping off, or returning to a depot. Also delivery
+    // vehicles stopping somewhere.
+    FleetStop {
+        end_dist: Distance,
+    },
 }
 
 impl Router {
@@ -83,7 +94,31 @@ impl Router {
                 target: bldg,
                 spot: None,
                 stuck_end_dist: None,
-                started_looking: false,
+                search_started: None,
+                cruised: Distance::ZERO,
+                visited: Vec::new(),
+            },
+            owner,
+        }
+    }
+
+    // Like park_near, but heads for one particular spot first
+    pub fn park_at_spot(
+        owner: CarID,
+        path: Path,
+        bldg: BuildingID,
+        spot: ParkingSpot,
+        dist: Distance,
+    ) -> Router {
+        Router {
+            path,
+            goal: Goal::ParkNearBuilding {
+                target: bldg,
+                spot: Some((spot, dist)),
+                stuck_end_dist: None,
+                search_started: None,
+                cruised: Distance::ZERO,
+                visited: Vec::new(),
             },
             owner,
         }
@@ -105,6 +140,14 @@ impl Router {
         }
     }
 
+    pub fn fleet_stop(owner: CarID, path: Path, end_dist: Distance) -> Router {
+        Router {
+            path,
+            goal: Goal::FleetStop { end_dist },
+            owner,
+        }
+    }
+
     pub fn head(&self) -> Traversable {
         self.path.current_step().as_traversable()
     }
@@ -137,6 +180,7 @@ impl Router {
             } => stuck_end_dist.unwrap_or_else(|| spot.unwrap().1),
             Goal::BikeThenStop { ref goal } => goal.sidewalk_pos.dist_along(),
             Goal::FollowBusRoute { end_dist } => end_dist,
+            Goal::FleetStop { end_dist } => end_dist,
         }
     }
 
@@ -149,6 +193,7 @@ impl Router {
         &mut self,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
+        now: Time,
         map: &Map,
         trip_and_person: Option<(TripID, PersonID)>,
         events: &mut Vec<Event>,
@@ -160,6 +205,7 @@ impl Router {
                 Distance::ZERO,
                 vehicle,
                 parking,
+                now,
                 map,
                 trip_and_person,
                 events,
@@ -187,6 +233,7 @@ impl Router {
         front: Distance,
         vehicle: &Vehicle,
         parking: &ParkingSimState,
+        now: Time,
         map: &Map,
         // TODO Not so nice to plumb all of this here
         trip_and_person: Option<(TripID, PersonID)>,
@@ -204,7 +251,9 @@ impl Router {
                 ref mut spot,
                 ref mut stuck_end_dist,
                 target,
-                ref mut started_looking,
+                ref mut search_started,
+                ref mut cruised,
+                ref mut visited,
             } => {
                 if let Some(d) = stuck_end_dist {
                     if *d == front {
@@ -219,33 +268,38 @@ impl Router {
                     None => true,
                 };
                 if need_new_spot {
-                    *started_looking = true;
+                    if search_started.is_none() {
+                        *search_started = Some(now);
+                    }
                     let current_lane = self.path.current_step().as_lane();
+                    visited.push(current_lane);
                     let candidates = parking.get_all_free_spots(
                         Position::new(current_lane, front),
                         vehicle,
                         target,
+                        now,
                         map,
                     );
                     let best =
                         if let Some((driving_pos, _)) = map.get_b(target).driving_connection(map) {
                             if driving_pos.lane() == current_lane {
                                 let target_dist = driving_pos.dist_along();
-                                // Closest to the building
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| (pos.dist_along() - target_dist).abs())
+                                // Closest to the building, unless a cheaper spot is a bit farther
+                                candidates.into_iter().min_by_key(|(spot, pos)| {
+                                    (pos.dist_along() - target_dist).abs()
+                                        + parking.price_penalty(*spot, now)
+                                })
                             } else {
                                 // Closest to the road endpoint, I guess
-                                candidates
-                                    .into_iter()
-                                    .min_by_key(|(_, pos)| pos.dist_along())
+                                candidates.into_iter().min_by_key(|(spot, pos)| {
+                                    pos.dist_along() + parking.price_penalty(*spot, now)
+                                })
                             }
                         } else {
                             // Closest to the road endpoint, I guess
-                            candidates
-                                .into_iter()
-                                .min_by_key(|(_, pos)| pos.dist_along())
+                            candidates.into_iter().min_by_key(|(spot, pos)| {
+                                pos.dist_along() + parking.price_penalty(*spot, now)
+                            })
                         };
                     if let Some((new_spot, new_pos)) = best {
                         if let Some((t, p)) = trip_and_person {
@@ -264,11 +318,65 @@ impl Router {
                         assert!(new_pos.dist_along() >= front);
                         *spot = Some((new_spot, new_pos.dist_along()));
                     } else {
-                        if let Some((new_path_steps, new_spot, new_pos)) =
-                            parking.path_to_free_parking_spot(current_lane, vehicle, target, map)
+                        let search = parking.search_options();
+                        let searching_for = now - search_started.unwrap();
+                        let gave_up = searching_for >= search.give_up_after;
+                        // Only the omniscient driver knows about free on-street spots elsewhere.
+                        // Everybody else can find a lot or garage, if they're heading for one
+                        // anyway or have given up cruising. If there's no lot or garage with room
+                        // either, drivers who've given up stop looking, so they don't cruise
+                        // forever.
+                        let known_spot = match search.strategy {
+                            ParkingStrategy::Omniscient => parking.path_to_free_parking_spot(
+                                current_lane,
+                                vehicle,
+                                target,
+                                now,
+                                map,
+                                false,
+                            ),
+                            ParkingStrategy::NearestGarage => parking.path_to_free_parking_spot(
+                                current_lane,
+                                vehicle,
+                                target,
+                                now,
+                                map,
+                                true,
+                            ),
+                            ParkingStrategy::CircleBlock | ParkingStrategy::ExpandRadius => {
+                                if gave_up {
+                                    parking.path_to_free_parking_spot(
+                                        current_lane,
+                                        vehicle,
+                                        target,
+                                        now,
+                                        map,
+                                        true,
+                                    )
+                                } else {
+                                    None
+                                }
+                            }
+                        };
+                        let next_lane = if known_spot.is_none()
+                            && search.strategy != ParkingStrategy::Omniscient
+                            && !gave_up
                         {
+                            parking.next_lane_to_search(
+                                current_lane,
+                                target,
+                                visited,
+                                searching_for,
+                                map,
+                            )
+                        } else {
+                            None
+                        };
+
+                        if let Some((new_path_steps, new_spot, new_pos)) = known_spot {
                             assert!(!new_path_steps.is_empty());
                             for step in new_path_steps {
+                                *cruised += step.as_traversable().length(map);
                                 self.path.add(step, map);
                             }
                             *spot = Some((new_spot, new_pos.dist_along()));
@@ -286,6 +394,26 @@ impl Router {
                                     TripPhaseType::Parking,
                                 ));
                             }
+                        } else if let Some(turn) = next_lane {
+                            // Cruise one more lane, then look again once we get there.
+                            *cruised +=
+                                map.get_t(turn).geom.length() + map.get_l(turn.dst).length();
+                            self.path.add(PathStep::Turn(turn), map);
+                            self.path.add(PathStep::Lane(turn.dst), map);
+                            *spot = None;
+                            events.push(Event::PathAmended(self.path.clone()));
+                            if let Some((t, p)) = trip_and_person {
+                                events.push(Event::TripPhaseStarting(
+                                    t,
+                                    p,
+                                    Some(PathRequest {
+                                        start: Position::new(current_lane, front),
+                                        end: Position::end(turn.dst, map),
+                                        constraints: PathConstraints::Car,
+                                    }),
+                                    TripPhaseType::Parking,
+                                ));
+                            }
                         } else {
                             println!(
                                 "WARNING: {} can't find parking on {} or anywhere reachable from \
@@ -318,12 +446,21 @@ impl Router {
                     None
                 }
             }
+            Goal::FleetStop { end_dist } => {
+                if end_dist == front {
+                    Some(ActionAtEnd::FleetAtStop)
+                } else {
+                    None
+                }
+            }
         }
     }
 
     pub fn opportunistically_lanechange(
         &mut self,
         queues: &BTreeMap<Traversable, Queue>,
+        intersections: &IntersectionSimState,
+        now: Time,
         map: &Map,
         handle_uber_turns: bool,
     ) {
@@ -355,7 +492,7 @@ impl Router {
         let (_, turn1, best_lane, turn2) = parent
             .lanes_ltr()
             .into_iter()
-            .filter(|(l, d, _)| dir == *d && constraints.can_use(map.get_l(*l), map))
+            .filter(|(l, d, _)| dir == *d && constraints.can_use_at(map.get_l(*l), map, now))
             .filter_map(|(l, _, _)| {
                 let t1 = TurnID {
                     parent: current_turn.parent,
@@ -369,6 +506,10 @@ impl Router {
                         src: l,
                         dst: next_lane,
                     }) {
+                        // Don't swerve into something closed by an incident
+                        if intersections.is_closed(turn1.id) || intersections.is_closed(turn2.id) {
+                            return None;
+                        }
                         Some((turn1, l, turn2))
                     } else {
                         None
@@ -421,15 +562,62 @@ impl Router {
         std::mem::replace(&mut self.path, path)
     }
 
+    // Is the vehicle still headed through any of these turns?
+    pub fn crosses_any(&self, turns: &BTreeSet<TurnID>) -> bool {
+        self.path.get_steps().iter().skip(1).any(|step| match step {
+            PathStep::Turn(t) => turns.contains(t),
+            _ => false,
+        })
+    }
+
+    // Find a way around some turns from wherever the vehicle is now, still ending up in the same
+    // place. If there's no way around, keep the current path.
+    pub fn reroute(&mut self, avoid: &BTreeSet<TurnID>, map: &Map) -> bool {
+        if self.path.currently_inside_ut().is_some() {
+            return false;
+        }
+        let start = match self.head() {
+            Traversable::Lane(l) => l,
+            Traversable::Turn(t) => t.dst,
+        };
+        let end = self.path.last_step().as_lane();
+        // The path loops back around to this lane; starting over from here would skip that.
+        if start == end {
+            return false;
+        }
+        let req = PathRequest {
+            start: Position::new(start, Distance::ZERO),
+            end: Position::new(end, Distance::ZERO),
+            constraints: self.owner.1.to_constraints(),
+        };
+        if let Some(path) = map.pathfind_avoiding_turns(req, avoid.clone()) {
+            self.path.reroute(path, map);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn is_parking(&self) -> bool {
         match self.goal {
-            Goal::ParkNearBuilding {
-                started_looking, ..
-            } => started_looking,
+            Goal::ParkNearBuilding { search_started, .. } => search_started.is_some(),
             _ => false,
         }
     }
 
+    // How long and how far the driver has been looking for parking. None if they didn't have to
+    // drive anywhere extra.
+    pub fn parking_search(&self, now: Time) -> Option<(Duration, Distance)> {
+        match self.goal {
+            Goal::ParkNearBuilding {
+                search_started: Some(t),
+                cruised,
+                ..
+            } if cruised > Distance::ZERO => Some((now - t, cruised)),
+            _ => None,
+        }
+    }
+
     pub fn get_parking_spot_goal(&self) -> Option<&ParkingSpot> {
         match self.goal {
             Goal::ParkNearBuilding { ref spot, .. } => spot.as_ref().map(|(s, _)| s),
@@ -437,3 +625,188 @@ impl Router {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParkingSearch, VehicleSpec, MAX_CAR_LENGTH};
+    use abstutil::Timer;
+    use map_model::raw::RawMap;
+
+    fn setup(give_up_after: Duration) -> (Map, ParkingSimState, Router, Vehicle) {
+        let mut timer = Timer::throwaway();
+        let map = Map::create_from_raw(RawMap::synthetic_grid(3, 3), false, &mut timer);
+        let mut parking = ParkingSimState::new(
+            &map,
+            ParkingSearch {
+                strategy: ParkingStrategy::CircleBlock,
+                give_up_after,
+            },
+            &mut timer,
+        );
+
+        let start = map.all_lanes().iter().find(|l| l.is_driving()).unwrap().id;
+        let path = map
+            .pathfind(PathRequest {
+                start: Position::start(start),
+                end: Position::end(start, &map),
+                constraints: PathConstraints::Car,
+            })
+            .unwrap();
+        let id = CarID(0, VehicleType::Car);
+        let vehicle = VehicleSpec::new(VehicleType::Car, MAX_CAR_LENGTH, None).make(id, None);
+        let router = Router::park_near(id, path, BuildingID(0));
+        (map, parking, router, vehicle)
+    }
+
+    // Take every spot on the grid, except maybe one
+    fn fill_parking(parking: &mut ParkingSimState, keep_free: Option<ParkingSpot>) {
+        for spot in parking.get_all_parking_spots().1 {
+            if Some(spot) != keep_free {
+                parking.reserve_spot(spot);
+            }
+        }
+    }
+
+    fn handle_end(
+        router: &mut Router,
+        vehicle: &Vehicle,
+        parking: &ParkingSimState,
+        now: Time,
+        map: &Map,
+    ) -> Option<ActionAtEnd> {
+        let front = map.get_l(router.head().as_lane()).length();
+        router.maybe_handle_end(front, vehicle, parking, now, map, None, &mut Vec::new())
+    }
+
+    #[test]
+    fn test_give_up_when_parking_is_full() {
+        let (map, mut parking, mut router, vehicle) = setup(Duration::minutes(1));
+        fill_parking(&mut parking, None);
+        let start = Time::START_OF_DAY;
+
+        // Before giving up, cruise one more lane
+        match handle_end(&mut router, &vehicle, &parking, start, &map) {
+            Some(ActionAtEnd::GotoLaneEnd) => {}
+            x => panic!("Expected to keep cruising, got {:?}", x),
+        }
+        assert_eq!(router.path.get_steps().len(), 3);
+        router.path.shift(&map);
+        router.path.shift(&map);
+
+        // After giving up, there's no lot or garage, and no free spot anywhere. Don't cruise
+        // forever; give up on parking at the end of this lane.
+        let later = start + Duration::minutes(2);
+        match handle_end(&mut router, &vehicle, &parking, later, &map) {
+            Some(ActionAtEnd::GotoLaneEnd) => {}
+            x => panic!("Expected to head for the end of the lane, got {:?}", x),
+        }
+        assert_eq!(router.path.get_steps().len(), 1);
+        match handle_end(&mut router, &vehicle, &parking, later, &map) {
+            Some(ActionAtEnd::GiveUpOnParking) => {}
+            x => panic!("Expected to give up on parking, got {:?}", x),
+        }
+
+        let (time, dist) = router.parking_search(later).unwrap();
+        assert_eq!(time, Duration::minutes(2));
+        assert!(dist > Distance::ZERO);
+    }
+
+    // Leave one spot free, along the next lane the driver would search
+    fn free_spot_on_next_lane(
+        map: &Map,
+        parking: &mut ParkingSimState,
+        router: &Router,
+    ) -> (LaneID, ParkingSpot) {
+        let current = router.head().as_lane();
+        let next = parking
+            .next_lane_to_search(current, BuildingID(0), &[current], Duration::ZERO, map)
+            .unwrap()
+            .dst;
+        let free_spot = parking
+            .get_all_parking_spots()
+            .1
+            .into_iter()
+            .find(|spot| match spot {
+                ParkingSpot::Onstreet(l, _) => {
+                    map.get_parent(*l).parking_to_driving(*l, map) == Some(next)
+                }
+                _ => false,
+            })
+            .unwrap();
+        fill_parking(parking, Some(free_spot));
+        (next, free_spot)
+    }
+
+    #[test]
+    fn test_cruise_to_unvisited_free_spot() {
+        let (map, mut parking, mut router, vehicle) = setup(Duration::hours(1));
+        let (next, free_spot) = free_spot_on_next_lane(&map, &mut parking, &router);
+        let start = Time::START_OF_DAY;
+
+        // The driver doesn't know about the spot yet, so they just cruise onto the next lane
+        match handle_end(&mut router, &vehicle, &parking, start, &map) {
+            Some(ActionAtEnd::GotoLaneEnd) => {}
+            x => panic!("Expected to keep cruising, got {:?}", x),
+        }
+        assert_eq!(router.get_parking_spot_goal(), None);
+        assert_eq!(router.path.get_steps().len(), 3);
+        router.path.shift(&map);
+        router.path.shift(&map);
+        assert_eq!(router.head().as_lane(), next);
+
+        // Once they're there, they spot it
+        let later = start + Duration::minutes(1);
+        router.maybe_handle_end(
+            Distance::ZERO,
+            &vehicle,
+            &parking,
+            later,
+            &map,
+            None,
+            &mut Vec::new(),
+        );
+        assert_eq!(router.get_parking_spot_goal(), Some(&free_spot));
+        assert_eq!(router.path.get_steps().len(), 1);
+    }
+
+    #[test]
+    fn test_no_omniscience_after_giving_up() {
+        let (map, mut parking, mut router, vehicle) = setup(Duration::ZERO);
+        free_spot_on_next_lane(&map, &mut parking, &router);
+        let start = Time::START_OF_DAY;
+
+        // Having given up, there's no lot or garage to head for. The driver can't know about the
+        // free spot just around the corner, so they stop looking.
+        match handle_end(&mut router, &vehicle, &parking, start, &map) {
+            Some(ActionAtEnd::GotoLaneEnd) => {}
+            x => panic!("Expected to head for the end of the lane, got {:?}", x),
+        }
+        assert_eq!(router.get_parking_spot_goal(), None);
+        assert_eq!(router.path.get_steps().len(), 1);
+        match handle_end(&mut router, &vehicle, &parking, start, &map) {
+            Some(ActionAtEnd::GiveUpOnParking) => {}
+            x => panic!("Expected to give up on parking, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_no_cruising_for_spot_on_same_lane() {
+        let (map, parking, mut router, vehicle) = setup(Duration::ZERO);
+        let lane = router.head().as_lane();
+        router.maybe_handle_end(
+            Distance::ZERO,
+            &vehicle,
+            &parking,
+            Time::START_OF_DAY,
+            &map,
+            None,
+            &mut Vec::new(),
+        );
+        assert!(router.get_parking_spot_goal().is_some());
+        assert_eq!(router.path.get_steps().len(), 1);
+        assert_eq!(router.head().as_lane(), lane);
+        // Nothing to report
+        assert_eq!(router.parking_search(Time::START_OF_DAY), None);
+    }
+}
@@ -22,6 +22,14 @@ pub enum Command {
     Callback(Duration),
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    // Somebody is waiting for a ride-hailing vehicle
+    RequestRide(TripID),
+    // Start a delivery tour or leave a loading zone
+    UpdateDelivery(CarID),
+    // An electric vehicle's battery is full
+    FinishCharging(CarID),
+    // Start or end an incident
+    UpdateIncident(usize),
     // The Time is redundant, just used to dedupe commands
     StartBus(BusRouteID, Time),
 }
@@ -31,7 +39,7 @@ impl Command {
         match id {
             AgentID::Car(c) => Command::UpdateCar(c),
             AgentID::Pedestrian(p) => Command::UpdatePed(p),
-            AgentID::BusPassenger(_, _) => unreachable!(),
+            AgentID::BusPassenger(_, _) | AgentID::RideHailPassenger(_, _) => unreachable!(),
         }
     }
 
@@ -47,6 +55,10 @@ impl Command {
             Command::Callback(_) => CommandType::Callback,
             Command::Pandemic(ref p) => CommandType::Pandemic(p.clone()),
             Command::FinishRemoteTrip(t) => CommandType::FinishRemoteTrip(*t),
+            Command::RequestRide(t) => CommandType::RequestRide(*t),
+            Command::UpdateDelivery(c) => CommandType::Delivery(*c),
+            Command::FinishCharging(c) => CommandType::Charging(*c),
+            Command::UpdateIncident(id) => CommandType::Incident(*id),
             Command::StartBus(r, t) => CommandType::StartBus(*r, *t),
         }
     }
@@ -64,6 +76,10 @@ pub enum CommandType {
     Callback,
     Pandemic(pandemic::Cmd),
     FinishRemoteTrip(TripID),
+    RequestRide(TripID),
+    Delivery(CarID),
+    Charging(CarID),
+    Incident(usize),
     StartBus(BusRouteID, Time),
 }
 
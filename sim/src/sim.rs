@@ -1,12 +1,14 @@
 use crate::analytics::Window;
 use crate::{
-    AgentID, AgentType, AlertLocation, Analytics, CapSimState, CarID, Command, CreateCar,
-    DrawCarInput, DrawPedCrowdInput, DrawPedestrianInput, DrivingSimState, Event, GetDrawAgents,
-    IntersectionSimState, OrigPersonID, PandemicModel, ParkedCar, ParkingSimState, ParkingSpot,
-    PedestrianID, Person, PersonID, PersonState, Router, Scenario, Scheduler, SidewalkPOI,
+    AgentID, AgentType, AlertLocation, Analytics, CapSimState, CarID, ChargingSimState,
+    ChargingStation, Command, CommandType, CreateCar, DeliveryTour, DrawCarInput,
+    DrawPedCrowdInput, DrawPedestrianInput, DrivingSimState, Event, FleetSimState, FreightSimState,
+    GetDrawAgents, Incident, IncidentReport, IncidentSimState, IntersectionSimState, OrigPersonID,
+    PandemicModel, ParkedCar, ParkingSearch, ParkingSimState, ParkingSpot, PedestrianID, Person,
+    PersonID, PersonSpec, PersonState, ReplayLog, Router, Scenario, Scheduler, SidewalkPOI,
     SidewalkSpot, TransitSimState, TripID, TripInfo, TripManager, TripPhaseType, TripResult,
-    TripSpawner, UnzoomedAgent, Vehicle, VehicleSpec, VehicleType, WalkingSimState, BUS_LENGTH,
-    LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH, SPAWN_DIST,
+    TripSpawner, UnzoomedAgent, Vehicle, VehicleSpec, VehicleType, WalkingSimState, Weather,
+    BUS_LENGTH, DELIVERY_VAN_LENGTH, LIGHT_RAIL_LENGTH, MIN_CAR_LENGTH, SPAWN_DIST, TRUCK_LENGTH,
 };
 use abstutil::{prettyprint_usize, serialized_size_bytes, Counter, Parallelism, Timer};
 use geom::{Distance, Duration, PolyLine, Pt2D, Speed, Time};
@@ -30,10 +32,17 @@ pub struct Sim {
     walking: WalkingSimState,
     intersections: IntersectionSimState,
     transit: TransitSimState,
+    fleet: FleetSimState,
+    freight: FreightSimState,
     cap: CapSimState,
+    charging: ChargingSimState,
+    incidents: IncidentSimState,
+    weather: Weather,
     trips: TripManager,
     #[serde(skip_serializing, skip_deserializing)]
     pandemic: Option<PandemicModel>,
+    #[serde(skip_serializing, skip_deserializing)]
+    replay: Option<ReplayLog>,
     scheduler: Scheduler,
     time: Time,
 
@@ -58,6 +67,7 @@ pub struct Ctx<'a> {
     pub parking: &'a mut ParkingSimState,
     pub intersections: &'a mut IntersectionSimState,
     pub cap: &'a mut CapSimState,
+    pub charging: &'a mut ChargingSimState,
     pub scheduler: &'a mut Scheduler,
     pub map: &'a Map,
 }
@@ -74,6 +84,22 @@ pub struct SimOptions {
     pub alerts: AlertHandler,
     pub pathfinding_upfront: bool,
     pub live_map_edits: bool,
+    // How many ride-hailing vehicles are available
+    pub fleet_size: usize,
+    pub parking_search: ParkingSearch,
+    // Vehicles accelerate and brake using VehicleSpec limits, instead of instantly changing speed
+    pub kinematics: bool,
+    // What fraction of private cars are electric
+    pub ev_share: f64,
+    // Pedestrians cross quiet streets wherever they like, once there's a gap in traffic
+    pub jaywalking: bool,
+    // Vehicles overtake slower bikes sharing their lane, when there's room to get around them, and
+    // cyclists ride at a wider range of speeds
+    pub pass_bikes: bool,
+    // Rain, snow, and darkness slow everybody down and put some people off biking
+    pub weather: Weather,
+    // Log every command dispatched, to compare runs that should be identical
+    pub record_replay: bool,
 }
 
 impl std::default::Default for SimOptions {
@@ -111,6 +137,14 @@ impl SimOptions {
             alerts: AlertHandler::Print,
             pathfinding_upfront: false,
             live_map_edits: false,
+            fleet_size: 0,
+            parking_search: ParkingSearch::omniscient(),
+            kinematics: false,
+            ev_share: 0.0,
+            jaywalking: false,
+            pass_bikes: false,
+            weather: Weather::clear(),
+            record_replay: false,
         }
     }
 }
@@ -119,10 +153,23 @@ impl SimOptions {
 impl Sim {
     pub fn new(map: &Map, opts: SimOptions, timer: &mut Timer) -> Sim {
         let mut scheduler = Scheduler::new();
+        let mut trips = TripManager::new(opts.pathfinding_upfront, opts.jaywalking);
+        let mut fleet = FleetSimState::new();
+        if opts.fleet_size > 0 {
+            let ids = (0..opts.fleet_size).map(|_| trips.new_car_id()).collect();
+            fleet.create_vehicles(ids, map);
+        }
         Sim {
-            driving: DrivingSimState::new(map, opts.recalc_lanechanging, opts.handle_uber_turns),
-            parking: ParkingSimState::new(map, timer),
-            walking: WalkingSimState::new(),
+            driving: DrivingSimState::new(
+                map,
+                opts.recalc_lanechanging,
+                opts.handle_uber_turns,
+                opts.kinematics,
+                opts.pass_bikes,
+                opts.weather,
+            ),
+            parking: ParkingSimState::new(map, opts.parking_search, timer),
+            walking: WalkingSimState::new(opts.weather),
             intersections: IntersectionSimState::new(
                 map,
                 &mut scheduler,
@@ -132,13 +179,23 @@ impl Sim {
                 opts.handle_uber_turns,
             ),
             transit: TransitSimState::new(map),
+            fleet,
+            freight: FreightSimState::new(),
             cap: CapSimState::new(map),
-            trips: TripManager::new(opts.pathfinding_upfront),
+            charging: ChargingSimState::new(map, opts.ev_share),
+            incidents: IncidentSimState::new(),
+            weather: opts.weather,
+            trips,
             pandemic: if let Some(rng) = opts.enable_pandemic_model {
                 Some(PandemicModel::new(rng))
             } else {
                 None
             },
+            replay: if opts.record_replay {
+                Some(ReplayLog::new())
+            } else {
+                None
+            },
             scheduler,
             time: Time::START_OF_DAY,
 
@@ -163,7 +220,7 @@ impl Sim {
             m.initialize(self.trips.get_all_people(), &mut self.scheduler);
         }
 
-        self.dispatch_events(Vec::new(), map);
+        self.dispatch_events(Vec::new(), None, map);
     }
 
     pub fn get_free_onstreet_spots(&self, l: LaneID) -> Vec<ParkingSpot> {
@@ -183,19 +240,44 @@ impl Sim {
         self.parking.get_all_parking_spots()
     }
 
+    // For electric vehicles, (current charge, capacity) in kWh
+    pub fn get_battery(&self, car: CarID) -> Option<(f64, f64)> {
+        self.charging.get_battery(car, self.time)
+    }
+
+    // (chargers, cars charging, cars waiting)
+    pub fn get_charging_station(&self, station: ChargingStation) -> Option<(usize, usize, usize)> {
+        self.charging.get_station(station)
+    }
+
+    // Returns the incident's ID. Incidents scheduled in the past start right away.
+    pub fn schedule_incident(&mut self, incident: Incident) -> usize {
+        self.incidents
+            .schedule(incident, self.time, &mut self.scheduler)
+    }
+
+    pub fn get_weather(&self) -> Weather {
+        self.weather
+    }
+
+    // Indexed by ID. Also says whether each incident is happening right now.
+    pub fn get_incidents(&self) -> Vec<(&Incident, bool, &IncidentReport)> {
+        self.incidents
+            .get_all()
+            .iter()
+            .enumerate()
+            .map(|(id, (incident, report))| (incident, self.incidents.is_active(id), report))
+            .collect()
+    }
+
     // Also returns the start distance of the building. TODO Do that in the Path properly.
     pub fn walking_path_to_nearest_parking_spot(
         &self,
         map: &Map,
         b: BuildingID,
     ) -> Option<(Path, Distance)> {
-        let vehicle = Vehicle {
-            id: CarID(0, VehicleType::Car),
-            owner: None,
-            vehicle_type: VehicleType::Car,
-            length: MIN_CAR_LENGTH,
-            max_speed: None,
-        };
+        let vehicle = VehicleSpec::new(VehicleType::Car, MIN_CAR_LENGTH, None)
+            .make(CarID(0, VehicleType::Car), None);
         let driving_lane = map.find_driving_lane_near_building(b);
 
         // Anything on the current lane? TODO Should find the closest one to the sidewalk, but
@@ -203,14 +285,19 @@ impl Sim {
         // TODO Refactor the logic in router
         let spot = if let Some((spot, _)) = self
             .parking
-            .get_all_free_spots(Position::start(driving_lane), &vehicle, b, map)
+            .get_all_free_spots(Position::start(driving_lane), &vehicle, b, self.time, map)
             .get(0)
         {
             spot.clone()
         } else {
-            let (_, spot, _) =
-                self.parking
-                    .path_to_free_parking_spot(driving_lane, &vehicle, b, map)?;
+            let (_, spot, _) = self.parking.path_to_free_parking_spot(
+                driving_lane,
+                &vehicle,
+                b,
+                self.time,
+                map,
+                false,
+            )?;
             spot
         };
 
@@ -233,13 +320,57 @@ impl Sim {
         vehicle_specs: Vec<VehicleSpec>,
     ) {
         self.trips.new_person(p, orig_id, ped_speed, vehicle_specs);
+        self.charging
+            .add_vehicles(&self.trips.get_person(p).unwrap().vehicles);
+    }
+    pub(crate) fn electrify(&self, vehicle_specs: &mut Vec<VehicleSpec>, rng: &mut XorShiftRng) {
+        self.charging.electrify(vehicle_specs, rng);
+    }
+    pub(crate) fn vary_bike_speeds(
+        &self,
+        vehicle_specs: &mut Vec<VehicleSpec>,
+        rng: &mut XorShiftRng,
+    ) {
+        self.driving.vary_bike_speeds(vehicle_specs, rng);
+    }
+
+    pub(crate) fn adjust_for_weather(
+        &self,
+        person: &PersonSpec,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Option<PersonSpec> {
+        self.weather.adjust_person(person, map, rng)
+    }
+    pub(crate) fn share_vehicles(&mut self, p: PersonID, vehicles: Vec<Vehicle>) {
+        self.trips.share_vehicles(p, vehicles);
     }
     pub fn random_person(&mut self, ped_speed: Speed, vehicle_specs: Vec<VehicleSpec>) -> &Person {
         self.trips.random_person(ped_speed, vehicle_specs)
     }
     pub(crate) fn seed_parked_car(&mut self, vehicle: Vehicle, spot: ParkingSpot) {
         self.parking.reserve_spot(spot);
-        self.parking.add_parked_car(ParkedCar { vehicle, spot });
+        self.parking
+            .add_parked_car(ParkedCar { vehicle, spot }, self.time, None);
+    }
+
+    pub(crate) fn add_delivery_tour(&mut self, tour: DeliveryTour) {
+        let length = match tour.vehicle_type {
+            VehicleType::Van => DELIVERY_VAN_LENGTH,
+            VehicleType::Truck => TRUCK_LENGTH,
+            _ => unreachable!(),
+        };
+        let vehicle = VehicleSpec::new(
+            tour.vehicle_type,
+            length,
+            Some(if tour.vehicle_type == VehicleType::Truck {
+                Speed::miles_per_hour(55.0)
+            } else {
+                Speed::miles_per_hour(65.0)
+            }),
+        )
+        .make(CarID(self.trips.new_car_id(), tour.vehicle_type), None);
+        self.freight.add_tour(vehicle, tour, &mut self.scheduler);
     }
 
     pub(crate) fn seed_bus_route(&mut self, route: &BusRoute) {
@@ -259,12 +390,8 @@ impl Sim {
             PathConstraints::Train => (VehicleType::Train, LIGHT_RAIL_LENGTH),
             _ => unreachable!(),
         };
-        let vehicle = VehicleSpec {
-            vehicle_type,
-            length,
-            max_speed: None,
-        }
-        .make(CarID(self.trips.new_car_id(), vehicle_type), None);
+        let vehicle = VehicleSpec::new(vehicle_type, length, None)
+            .make(CarID(self.trips.new_car_id(), vehicle_type), None);
         let start_lane = map.get_l(path.current_step().as_lane());
         let start_dist = if map.get_i(start_lane.src_i).is_incoming_border() {
             SPAWN_DIST
@@ -414,11 +541,17 @@ impl Sim {
         self.time = time;
         let mut events = Vec::new();
         let mut halt = false;
+        let replay_cmd = if self.replay.is_some() {
+            Some((ReplayLog::kind(&cmd), cmd.to_type()))
+        } else {
+            None
+        };
 
         let mut ctx = Ctx {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             cap: &mut self.cap,
+            charging: &mut self.charging,
             scheduler: &mut self.scheduler,
             map,
         };
@@ -428,7 +561,9 @@ impl Sim {
                 self.trips
                     .start_trip(self.time, id, trip_spec, maybe_req, maybe_path, &mut ctx);
             }
-            Command::SpawnCar(create_car, retry_if_no_room) => {
+            Command::SpawnCar(mut create_car, retry_if_no_room) => {
+                // Drivers setting out know to avoid anything closed
+                self.incidents.avoid_closures(&mut create_car.router, map);
                 if self.driving.start_car_on_lane(
                     self.time,
                     create_car.clone(),
@@ -459,7 +594,7 @@ impl Sim {
                                 b,
                             ));
                         }
-                        self.parking.remove_parked_car(parked_car);
+                        self.parking.remove_parked_car(parked_car, self.time);
                     }
                     if let Some(route) = create_car.maybe_route {
                         self.transit.bus_created(create_car.vehicle.id, route);
@@ -484,6 +619,7 @@ impl Sim {
                         parking: &mut self.parking,
                         intersections: &mut self.intersections,
                         cap: &mut self.cap,
+                        charging: &mut self.charging,
                         scheduler: &mut self.scheduler,
                         map,
                     };
@@ -535,6 +671,8 @@ impl Sim {
                     &mut ctx,
                     &mut self.trips,
                     &mut self.transit,
+                    &mut self.fleet,
+                    &mut self.freight,
                     &mut self.walking,
                 );
             }
@@ -548,6 +686,7 @@ impl Sim {
                     &mut ctx,
                     &mut self.trips,
                     &mut self.transit,
+                    &self.driving,
                 );
             }
             Command::UpdateIntersection(i) => {
@@ -570,25 +709,71 @@ impl Sim {
             Command::FinishRemoteTrip(trip) => {
                 self.trips.remote_trip_finished(self.time, trip, &mut ctx);
             }
+            Command::RequestRide(trip) => {
+                self.fleet
+                    .request_ride(self.time, trip, &mut self.trips, &mut ctx);
+            }
+            Command::UpdateDelivery(car) => {
+                self.freight.update(self.time, car, &mut ctx);
+            }
+            Command::FinishCharging(car) => {
+                self.charging
+                    .finish_charging(self.time, car, &mut self.scheduler);
+            }
+            Command::UpdateIncident(id) => {
+                if self
+                    .incidents
+                    .update(id, self.time, ctx.intersections, ctx.scheduler, map)
+                {
+                    let (rerouted, stuck) = self.driving.reroute_around(
+                        self.incidents.closed_turns(),
+                        self.time,
+                        &mut ctx,
+                    );
+                    self.incidents.record_reroutes(id, rerouted, stuck);
+                }
+            }
             Command::StartBus(r, _) => {
                 self.start_bus(map.get_br(r), map);
             }
         }
 
         // Record events at precisely the time they occur.
-        self.dispatch_events(events, map);
+        self.dispatch_events(events, replay_cmd, map);
 
         halt
     }
 
-    fn dispatch_events(&mut self, mut events: Vec<Event>, map: &Map) {
+    fn dispatch_events(
+        &mut self,
+        mut events: Vec<Event>,
+        replay_cmd: Option<(&'static str, CommandType)>,
+        map: &Map,
+    ) {
         events.extend(self.trips.collect_events());
         events.extend(self.transit.collect_events());
+        events.extend(self.fleet.collect_events());
+        events.extend(self.freight.collect_events());
         events.extend(self.driving.collect_events());
         events.extend(self.walking.collect_events());
         events.extend(self.intersections.collect_events());
         events.extend(self.parking.collect_events());
+        for ev in &events {
+            self.charging
+                .handle_event(self.time, ev, &self.parking, map, &mut self.scheduler);
+        }
+        events.extend(self.charging.collect_events());
+        if let Some((kind, cmd)) = replay_cmd {
+            let agent_pt =
+                ReplayLog::agent(&cmd).and_then(|id| self.canonical_pt_for_agent(id, map));
+            let num_trips = self.num_trips();
+            self.replay
+                .as_mut()
+                .unwrap()
+                .record(self.time, kind, cmd, &events, agent_pt, num_trips);
+        }
         for ev in events {
+            self.incidents.handle_event(&ev);
             if let Some(ref mut m) = self.pandemic {
                 m.handle_event(self.time, &ev, &mut self.scheduler);
             }
@@ -693,6 +878,9 @@ impl Sim {
         if let Some(path) = self.find_previous_savestate(self.time) {
             println!("Debug from {}", path);
         }
+        if let Some(path) = self.save_replay_log() {
+            println!("Replay log so far in {}", path);
+        }
     }
 }
 
@@ -750,6 +938,7 @@ impl Sim {
                     (self.time() - last_sim_time) / dt_real,
                     self.scheduler.describe_stats()
                 );
+                self.save_replay_log();
                 break;
             }
 
@@ -802,6 +991,14 @@ impl Sim {
                 "- transit: {} bytes",
                 prettyprint_usize(serialized_size_bytes(&self.transit))
             );
+            println!(
+                "- fleet: {} bytes",
+                prettyprint_usize(serialized_size_bytes(&self.fleet))
+            );
+            println!(
+                "- freight: {} bytes",
+                prettyprint_usize(serialized_size_bytes(&self.freight))
+            );
             println!(
                 "- cap: {} bytes",
                 prettyprint_usize(serialized_size_bytes(&self.cap))
@@ -824,6 +1021,14 @@ impl Sim {
         path
     }
 
+    // With --record_replay, writes every command dispatched so far. Returns the path.
+    pub fn save_replay_log(&self) -> Option<String> {
+        let log = self.replay.as_ref()?;
+        let path = abstutil::path_replay(&self.map_name, &self.edits_name, &self.run_name);
+        abstutil::write_binary(path.clone(), log);
+        Some(path)
+    }
+
     pub fn find_previous_savestate(&self, base_time: Time) -> Option<String> {
         abstutil::find_prev_file(self.save_path(base_time))
     }
@@ -867,6 +1072,7 @@ impl Sim {
             parking: &mut self.parking,
             intersections: &mut self.intersections,
             cap: &mut self.cap,
+            charging: &mut self.charging,
             scheduler: &mut self.scheduler,
             map,
         };
@@ -878,10 +1084,10 @@ impl Sim {
                         .abort_trip(self.time, trip, Some(vehicle), &mut ctx);
                 }
                 AgentID::Pedestrian(ped) => {
-                    self.walking.delete_ped(ped, ctx.scheduler);
+                    self.walking.delete_ped(ped, self.time, &mut ctx);
                     self.trips.abort_trip(self.time, trip, None, &mut ctx);
                 }
-                AgentID::BusPassenger(_, _) => unreachable!(),
+                AgentID::BusPassenger(_, _) | AgentID::RideHailPassenger(_, _) => unreachable!(),
             }
         }
     }
@@ -982,7 +1188,7 @@ impl Sim {
             AgentID::Pedestrian(id) => self.walking.agent_properties(id, self.time),
             AgentID::Car(id) => self.driving.agent_properties(id, self.time),
             // TODO Harder to measure some of this stuff
-            AgentID::BusPassenger(_, _) => AgentProperties {
+            AgentID::BusPassenger(_, _) | AgentID::RideHailPassenger(_, _) => AgentProperties {
                 total_time: Duration::ZERO,
                 waiting_here: Duration::ZERO,
                 total_waiting: Duration::ZERO,
@@ -994,6 +1200,16 @@ impl Sim {
         }
     }
 
+    // (vehicles idling at a depot, total vehicles, ride requests waiting for a vehicle)
+    pub fn get_fleet_status(&self) -> (usize, usize, usize) {
+        self.fleet.get_status()
+    }
+
+    // (delivery vehicles on a tour right now, all delivery vehicles)
+    pub fn get_freight_status(&self) -> (usize, usize) {
+        self.freight.get_status()
+    }
+
     pub fn num_transit_passengers(&self, car: CarID) -> usize {
         self.transit.get_passengers(car).len()
     }
@@ -1085,6 +1301,8 @@ impl Sim {
             VehicleType::Bike,
             VehicleType::Bus,
             VehicleType::Train,
+            VehicleType::Van,
+            VehicleType::Truck,
         ] {
             let id = CarID(idx, *vt);
             if self.driving.does_car_exist(id) {
@@ -1092,10 +1310,12 @@ impl Sim {
             }
         }
 
-        let id = CarID(idx, VehicleType::Car);
-        // Only cars can be parked.
-        if self.parking.lookup_parked_car(id).is_some() {
-            return Some(id);
+        // Only cars and vans in loading zones can be parked.
+        for vt in &[VehicleType::Car, VehicleType::Van] {
+            let id = CarID(idx, *vt);
+            if self.parking.lookup_parked_car(id).is_some() {
+                return Some(id);
+            }
         }
 
         None
@@ -1105,7 +1325,7 @@ impl Sim {
         match id {
             AgentID::Car(car) => self.driving.get_path(car),
             AgentID::Pedestrian(ped) => self.walking.get_path(ped),
-            AgentID::BusPassenger(_, _) => None,
+            AgentID::BusPassenger(_, _) | AgentID::RideHailPassenger(_, _) => None,
         }
     }
     pub fn get_all_driving_paths(&self) -> Vec<&Path> {
@@ -1121,7 +1341,7 @@ impl Sim {
         match id {
             AgentID::Car(car) => self.driving.trace_route(self.time, car, map, dist_ahead),
             AgentID::Pedestrian(ped) => self.walking.trace_route(self.time, ped, map, dist_ahead),
-            AgentID::BusPassenger(_, _) => None,
+            AgentID::BusPassenger(_, _) | AgentID::RideHailPassenger(_, _) => None,
         }
     }
 
@@ -1152,7 +1372,9 @@ impl Sim {
                 .canonical_pt(id, map)
                 .or_else(|| Some(self.get_draw_car(id, map)?.body.last_pt())),
             AgentID::Pedestrian(id) => Some(self.get_draw_ped(id, map)?.pos),
-            AgentID::BusPassenger(_, bus) => Some(self.get_draw_car(bus, map)?.body.last_pt()),
+            AgentID::BusPassenger(_, car) | AgentID::RideHailPassenger(_, car) => {
+                Some(self.get_draw_car(car, map)?.body.last_pt())
+            }
         }
     }
 
@@ -1297,6 +1519,7 @@ impl Sim {
                 parking: &mut self.parking,
                 intersections: &mut self.intersections,
                 cap: &mut self.cap,
+                charging: &mut self.charging,
                 scheduler: &mut self.scheduler,
                 map,
             };
@@ -1312,6 +1535,11 @@ impl Sim {
     pub fn clear_alerts(&mut self) -> Vec<(Time, AlertLocation, String)> {
         std::mem::replace(&mut self.analytics.alerts, Vec::new())
     }
+
+    // For problems that don't come from an event, like a broken scenario
+    pub(crate) fn alert(&mut self, loc: AlertLocation, msg: String) {
+        self.analytics.alerts.push((self.time, loc, msg));
+    }
 }
 
 // Callbacks
@@ -1,20 +1,23 @@
 use crate::sim::Ctx;
 use crate::{
-    AgentID, AgentType, AlertLocation, CarID, Command, CreateCar, CreatePedestrian, DrivingGoal,
-    Event, IndividTrip, OffMapLocation, OrigPersonID, ParkedCar, ParkingSpot, PedestrianID,
-    PersonID, PersonSpec, Scenario, Scheduler, SidewalkPOI, SidewalkSpot, SpawnTrip,
-    TransitSimState, TripID, TripPhaseType, TripSpec, Vehicle, VehicleSpec, VehicleType,
-    WalkingSimState,
+    AgentID, AgentType, AlertLocation, CarID, ChargingStation, Command, CreateCar,
+    CreatePedestrian, DrivingGoal, Event, IndividTrip, OffMapLocation, OrigPersonID, ParkedCar,
+    ParkingSpot, PedestrianID, PersonID, PersonSpec, Router, Scenario, Scheduler, SidewalkPOI,
+    SidewalkSpot, SpawnTrip, TransitSimState, TripID, TripPhaseType, TripSpec, Vehicle,
+    VehicleSpec, VehicleType, WalkingSimState,
 };
 use abstutil::{deserialize_btreemap, serialize_btreemap, Counter};
 use geom::{Duration, Speed, Time};
 use map_model::{
-    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, Path, PathConstraints, PathRequest,
-    Position,
+    BuildingID, BusRouteID, BusStopID, IntersectionID, Map, MidBlockCrossing, Path,
+    PathConstraints, PathRequest, Position,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, VecDeque};
 
+// When a household's shared car isn't back yet, check again this often.
+const SHARED_CAR_RETRY: Duration = Duration::const_seconds(60.0);
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TripManager {
     trips: Vec<Trip>,
@@ -27,6 +30,8 @@ pub struct TripManager {
     active_trip_mode: BTreeMap<AgentID, TripID>,
     unfinished_trips: usize,
     pub pathfinding_upfront: bool,
+    // Cross quiet streets anywhere, not just at intersections and mid-block crossings
+    jaywalking: bool,
 
     car_id_counter: usize,
 
@@ -34,7 +39,7 @@ pub struct TripManager {
 }
 
 impl TripManager {
-    pub fn new(pathfinding_upfront: bool) -> TripManager {
+    pub fn new(pathfinding_upfront: bool, jaywalking: bool) -> TripManager {
         TripManager {
             trips: Vec::new(),
             people: Vec::new(),
@@ -43,6 +48,7 @@ impl TripManager {
             car_id_counter: 0,
             events: Vec::new(),
             pathfinding_upfront,
+            jaywalking,
         }
     }
 
@@ -73,8 +79,22 @@ impl TripManager {
             vehicles,
             delayed_trips: Vec::new(),
             on_bus: None,
+            in_ride_hail: None,
         });
     }
+    // Let somebody else use vehicles that another person owns.
+    pub fn share_vehicles(&mut self, id: PersonID, vehicles: Vec<Vehicle>) {
+        self.people[id.0].vehicles.extend(vehicles);
+    }
+    // The passenger's trip must have a RideAlong leg for the driver's trip. The driver's trip may
+    // be created after the passenger's, so this is done separately.
+    pub fn add_passenger(&mut self, driver: TripID, passenger: TripID) {
+        assert_eq!(
+            self.trips[driver.0].info.end,
+            self.trips[passenger.0].info.end
+        );
+        self.trips[driver.0].passengers.push(passenger);
+    }
     pub fn random_person(&mut self, ped_speed: Speed, vehicle_specs: Vec<VehicleSpec>) -> &Person {
         let id = PersonID(self.people.len());
         self.new_person(id, None, ped_speed, vehicle_specs);
@@ -119,6 +139,8 @@ impl TripManager {
                 // TODO No way to plumb OffMapLocation here
                 TripEndpoint::Border(map.get_l(map.get_br(*r).end_border.unwrap()).dst_i, None)
             }
+            Some(TripLeg::RideAlong(_, ref end)) => end.clone(),
+            Some(TripLeg::RideHail(b)) => TripEndpoint::Bldg(*b),
             _ => unreachable!(),
         };
         let trip = Trip {
@@ -138,6 +160,7 @@ impl TripManager {
             aborted: false,
             cancelled: false,
             legs: VecDeque::from(legs),
+            passengers: Vec::new(),
         };
         self.unfinished_trips += 1;
         let person = &mut self.people[trip.person.0];
@@ -211,9 +234,10 @@ impl TripManager {
                         blocked_time: trip.total_blocked_time,
                     });
                     let person = trip.person;
+                    let trip_id = trip.id;
                     self.people[person.0].state = PersonState::Inside(b1);
                     self.events.push(Event::PersonEntersBuilding(person, b1));
-                    self.person_finished_trip(now, person, ctx);
+                    self.person_finished_trip(now, person, trip_id, ctx);
                     return;
                 }
                 _ => {}
@@ -225,6 +249,7 @@ impl TripManager {
             now,
             SidewalkSpot::parking_spot(spot, ctx.map, ctx.parking),
             &self.people[trip.person.0],
+            self.jaywalking,
             ctx.map,
             ctx.scheduler,
             &mut self.events,
@@ -251,7 +276,7 @@ impl TripManager {
 
         trip.assert_walking_leg(SidewalkSpot::deferred_parking_spot());
         let parked_car = ctx.parking.get_car_at_spot(spot).unwrap().clone();
-        let drive_to = match trip.legs[0] {
+        let mut drive_to = match trip.legs[0] {
             TripLeg::Drive(c, ref to) => {
                 assert_eq!(c, parked_car.vehicle.id);
                 to.clone()
@@ -274,14 +299,47 @@ impl TripManager {
                 start = Position::new(start.lane(), start.dist_along() + parked_car.vehicle.length);
             }
         }
-        let end = drive_to.goal_pos(PathConstraints::Car, ctx.map).unwrap();
+
+        // An electric vehicle low on charge might head for a charging station near the
+        // destination instead. The person walks the rest of the way.
+        let car = parked_car.vehicle.id;
+        let mut charge_at_lot = None;
+        if let DrivingGoal::ParkNear(b) = drive_to {
+            match ctx
+                .charging
+                .maybe_detour(car, start.pt(ctx.map), b, ctx.map)
+            {
+                Some(ChargingStation::Building(station)) => {
+                    drive_to = DrivingGoal::ParkNear(station);
+                    trip.legs[0] = TripLeg::Drive(car, drive_to.clone());
+                }
+                Some(ChargingStation::Lot(pl)) => {
+                    // Cars can't wait for a spot inside the lot, so only go if there's room
+                    if let Some(spot) = ctx.parking.get_free_lot_spots(pl).into_iter().next() {
+                        let pos =
+                            ctx.parking
+                                .spot_to_driving_pos(spot, &parked_car.vehicle, ctx.map);
+                        charge_at_lot = Some((b, spot, pos));
+                    } else {
+                        ctx.charging.cancel_detour(car);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let end = if let Some((_, _, pos)) = charge_at_lot {
+            pos
+        } else {
+            drive_to.goal_pos(PathConstraints::Car, ctx.map).unwrap()
+        };
         let req = PathRequest {
             start,
             end,
             constraints: PathConstraints::Car,
         };
 
-        match ctx.map.pathfind(req.clone()).and_then(|path| {
+        match ctx.map.pathfind_at(req.clone(), now).and_then(|path| {
             ctx.cap.validate_path(
                 &req,
                 path,
@@ -292,7 +350,11 @@ impl TripManager {
             )
         }) {
             Some(path) => {
-                let router = drive_to.make_router(parked_car.vehicle.id, path, ctx.map);
+                let router = if let Some((b, spot, pos)) = charge_at_lot {
+                    Router::park_at_spot(car, path, b, spot, pos.dist_along())
+                } else {
+                    drive_to.make_router(car, path, ctx.map)
+                };
                 ctx.scheduler.push(
                     now,
                     Command::SpawnCar(
@@ -317,8 +379,9 @@ impl TripManager {
                         trip.id, start, end
                     ),
                 ));
+                ctx.charging.cancel_detour(car);
                 // Move the car to the destination...
-                ctx.parking.remove_parked_car(parked_car.clone());
+                ctx.parking.remove_parked_car(parked_car.clone(), now);
                 let trip = trip.id;
                 self.abort_trip(now, trip, Some(parked_car.vehicle), ctx);
             }
@@ -371,7 +434,7 @@ impl TripManager {
         };
         if let Some(router) = ctx
             .map
-            .pathfind(req.clone())
+            .pathfind_at(req.clone(), now)
             .map(|path| drive_to.make_router(bike, path, ctx.map))
         {
             ctx.scheduler.push(
@@ -429,6 +492,7 @@ impl TripManager {
             now,
             bike_rack,
             &self.people[trip.person.0],
+            self.jaywalking,
             map,
             scheduler,
             &mut self.events,
@@ -464,9 +528,10 @@ impl TripManager {
             blocked_time: trip.total_blocked_time,
         });
         let person = trip.person;
+        let trip_id = trip.id;
         self.people[person.0].state = PersonState::Inside(bldg);
         self.events.push(Event::PersonEntersBuilding(person, bldg));
-        self.person_finished_trip(now, person, ctx);
+        self.person_finished_trip(now, person, trip_id, ctx);
     }
 
     // If no route is returned, the pedestrian boarded a bus immediately.
@@ -565,6 +630,7 @@ impl TripManager {
             now,
             start,
             &self.people[trip.person.0],
+            self.jaywalking,
             ctx.map,
             ctx.scheduler,
             &mut self.events,
@@ -573,6 +639,62 @@ impl TripManager {
         }
     }
 
+    pub fn ride_hail_request(&self, id: TripID) -> (PersonID, BuildingID, BuildingID) {
+        let trip = &self.trips[id.0];
+        let pickup = match trip.info.start {
+            TripEndpoint::Bldg(b) => b,
+            TripEndpoint::Border(_, _) => unreachable!(),
+        };
+        let dropoff = match trip.legs[0] {
+            TripLeg::RideHail(b) => b,
+            _ => unreachable!(),
+        };
+        (trip.person, pickup, dropoff)
+    }
+
+    pub fn ride_hail_pickup(&mut self, id: TripID, car: CarID) {
+        let person = self.trips[id.0].person;
+        self.active_trip_mode
+            .insert(AgentID::RideHailPassenger(person, car), id);
+        self.people[person.0].in_ride_hail = Some(car);
+    }
+
+    pub fn ride_hail_dropoff(
+        &mut self,
+        now: Time,
+        id: TripID,
+        dropoff: BuildingID,
+        car: CarID,
+        ctx: &mut Ctx,
+    ) {
+        let person = self.trips[id.0].person;
+        assert_eq!(
+            self.active_trip_mode
+                .remove(&AgentID::RideHailPassenger(person, car)),
+            Some(id)
+        );
+        self.people[person.0].in_ride_hail.take().unwrap();
+
+        let trip = &mut self.trips[id.0];
+        match trip.legs.pop_front() {
+            Some(TripLeg::RideHail(b)) => assert_eq!(b, dropoff),
+            _ => unreachable!(),
+        }
+        assert!(trip.legs.is_empty());
+        trip.finished_at = Some(now);
+        self.unfinished_trips -= 1;
+        self.events.push(Event::TripFinished {
+            trip: trip.id,
+            mode: trip.info.mode,
+            total_time: now - trip.info.departure,
+            blocked_time: trip.total_blocked_time,
+        });
+        self.people[person.0].state = PersonState::Inside(dropoff);
+        self.events
+            .push(Event::PersonEntersBuilding(person, dropoff));
+        self.person_finished_trip(now, person, id, ctx);
+    }
+
     pub fn ped_reached_border(
         &mut self,
         now: Time,
@@ -606,6 +728,7 @@ impl TripManager {
             blocked_time: trip.total_blocked_time,
         });
         let person = trip.person;
+        let trip_id = trip.id;
         if let TripEndpoint::Border(_, ref loc) = trip.info.end {
             self.events.push(Event::PersonLeavesMap(
                 person,
@@ -615,7 +738,7 @@ impl TripManager {
             ));
         }
         self.people[person.0].state = PersonState::OffMap;
-        self.person_finished_trip(now, person, ctx);
+        self.person_finished_trip(now, person, trip_id, ctx);
     }
 
     pub fn transit_rider_reached_border(
@@ -643,6 +766,7 @@ impl TripManager {
             blocked_time: trip.total_blocked_time,
         });
         let person = trip.person;
+        let trip_id = trip.id;
         if let TripEndpoint::Border(i, ref loc) = trip.info.end {
             self.events
                 .push(Event::PersonLeavesMap(person, Some(agent), i, loc.clone()));
@@ -650,7 +774,7 @@ impl TripManager {
             unreachable!()
         }
         self.people[person.0].state = PersonState::OffMap;
-        self.person_finished_trip(now, person, ctx);
+        self.person_finished_trip(now, person, trip_id, ctx);
     }
 
     pub fn car_or_bike_reached_border(
@@ -682,6 +806,7 @@ impl TripManager {
             blocked_time: trip.total_blocked_time,
         });
         let person = trip.person;
+        let trip_id = trip.id;
         self.people[person.0].state = PersonState::OffMap;
         if let TripEndpoint::Border(_, ref loc) = trip.info.end {
             self.events.push(Event::PersonLeavesMap(
@@ -691,7 +816,7 @@ impl TripManager {
                 loc.clone(),
             ));
         }
-        self.person_finished_trip(now, person, ctx);
+        self.person_finished_trip(now, person, trip_id, ctx);
     }
 
     pub fn remote_trip_finished(&mut self, now: Time, id: TripID, ctx: &mut Ctx) {
@@ -715,7 +840,7 @@ impl TripManager {
         self.events
             .push(Event::PersonEntersRemoteBuilding(person, to));
         self.people[person.0].state = PersonState::OffMap;
-        self.person_finished_trip(now, person, ctx);
+        self.person_finished_trip(now, person, id, ctx);
     }
 
     // Different than aborting a trip. Don't warp any vehicles or change where the person is.
@@ -766,13 +891,26 @@ impl TripManager {
                     let driving_lane = ctx.map.find_driving_lane_near_building(b);
                     if let Some(spot) = ctx
                         .parking
-                        .get_all_free_spots(Position::start(driving_lane), &vehicle, b, ctx.map)
+                        .get_all_free_spots(
+                            Position::start(driving_lane),
+                            &vehicle,
+                            b,
+                            now,
+                            ctx.map,
+                        )
                         // TODO Could pick something closer, but meh, aborted trips are bugs anyway
                         .get(0)
                         .map(|(spot, _)| spot.clone())
                         .or_else(|| {
                             ctx.parking
-                                .path_to_free_parking_spot(driving_lane, &vehicle, b, ctx.map)
+                                .path_to_free_parking_spot(
+                                    driving_lane,
+                                    &vehicle,
+                                    b,
+                                    now,
+                                    ctx.map,
+                                    false,
+                                )
                                 .map(|(_, spot, _)| spot)
                         })
                     {
@@ -784,7 +922,8 @@ impl TripManager {
                             ),
                         ));
                         ctx.parking.reserve_spot(spot);
-                        ctx.parking.add_parked_car(ParkedCar { vehicle, spot });
+                        ctx.parking
+                            .add_parked_car(ParkedCar { vehicle, spot }, now, None);
                     } else {
                         self.events.push(Event::Alert(
                             AlertLocation::Person(person),
@@ -806,7 +945,7 @@ impl TripManager {
             }
         }
 
-        self.person_finished_trip(now, person, ctx);
+        self.person_finished_trip(now, person, id, ctx);
     }
 
     pub fn active_agents(&self) -> Vec<AgentID> {
@@ -849,6 +988,16 @@ impl TripManager {
             TripLeg::Remote(_) => {
                 return TripResult::RemoteTrip;
             }
+            TripLeg::RideAlong(driver, _) => {
+                return self.trip_to_agent(*driver);
+            }
+            // Still waiting for pickup, or already riding
+            TripLeg::RideHail(_) => match person.in_ride_hail {
+                Some(car) => AgentID::RideHailPassenger(person.id, car),
+                None => {
+                    return TripResult::ModeChange;
+                }
+            },
         };
         if self.active_trip_mode.get(&a) == Some(&id) {
             TripResult::Ok(a)
@@ -945,7 +1094,9 @@ impl TripManager {
         self.trips[id.0].person
     }
 
-    fn person_finished_trip(&mut self, now: Time, person: PersonID, ctx: &mut Ctx) {
+    fn person_finished_trip(&mut self, now: Time, person: PersonID, trip: TripID, ctx: &mut Ctx) {
+        self.drop_off_passengers(now, trip, ctx);
+
         let person = &mut self.people[person.0];
         if person.delayed_trips.is_empty() {
             return;
@@ -963,6 +1114,50 @@ impl TripManager {
         self.start_trip(now, trip, spec, maybe_req, maybe_path, ctx);
     }
 
+    // Anybody riding along with this trip arrives at the same place.
+    fn drop_off_passengers(&mut self, now: Time, driver_trip: TripID, ctx: &mut Ctx) {
+        let driver_aborted = self.trips[driver_trip.0].aborted;
+        for id in self.trips[driver_trip.0].passengers.clone() {
+            let trip = &mut self.trips[id.0];
+            // If the passenger's previous trip is running late, they're left behind. When they
+            // eventually start, they'll notice the driver is gone.
+            if !trip.started || trip.finished_at.is_some() || trip.aborted || trip.cancelled {
+                continue;
+            }
+            if driver_aborted {
+                self.abort_trip(now, id, None, ctx);
+                continue;
+            }
+
+            match trip.legs.pop_front() {
+                Some(TripLeg::RideAlong(t, _)) => assert_eq!(t, driver_trip),
+                _ => unreachable!(),
+            }
+            assert!(trip.legs.is_empty());
+            trip.finished_at = Some(now);
+            self.unfinished_trips -= 1;
+            self.events.push(Event::TripFinished {
+                trip: trip.id,
+                mode: trip.info.mode,
+                total_time: now - trip.info.departure,
+                blocked_time: trip.total_blocked_time,
+            });
+            let person = trip.person;
+            match trip.info.end {
+                TripEndpoint::Bldg(b) => {
+                    self.people[person.0].state = PersonState::Inside(b);
+                    self.events.push(Event::PersonEntersBuilding(person, b));
+                }
+                TripEndpoint::Border(i, ref loc) => {
+                    self.people[person.0].state = PersonState::OffMap;
+                    self.events
+                        .push(Event::PersonLeavesMap(person, None, i, loc.clone()));
+                }
+            }
+            self.person_finished_trip(now, person, id, ctx);
+        }
+    }
+
     pub fn start_trip(
         &mut self,
         now: Time,
@@ -975,7 +1170,7 @@ impl TripManager {
         assert!(!self.trips[trip.0].cancelled);
         assert!(!self.trips[trip.0].aborted);
         if !self.pathfinding_upfront && maybe_path.is_none() && maybe_req.is_some() {
-            maybe_path = ctx.map.pathfind(maybe_req.clone().unwrap());
+            maybe_path = ctx.map.pathfind_at(maybe_req.clone().unwrap(), now);
         }
 
         let person = &mut self.people[self.trips[trip.0].person.0];
@@ -1091,6 +1286,7 @@ impl TripManager {
                         constraints: PathConstraints::Pedestrian,
                     };
                     if let Some(path) = ctx.map.pathfind(req.clone()) {
+                        let (path, crossings) = plan_walk(&req, path, self.jaywalking, ctx.map);
                         ctx.scheduler.push(
                             now,
                             Command::SpawnPed(CreatePedestrian {
@@ -1099,6 +1295,7 @@ impl TripManager {
                                 start,
                                 goal: walking_goal,
                                 path,
+                                crossings,
                                 req,
                                 trip,
                                 person: person.id,
@@ -1110,9 +1307,18 @@ impl TripManager {
                             format!("UsingParkedCar trip couldn't find the walking path {}", req),
                         ));
                         // Move the car to the destination
-                        ctx.parking.remove_parked_car(parked_car.clone());
+                        ctx.parking.remove_parked_car(parked_car.clone(), now);
                         self.abort_trip(now, trip, Some(parked_car.vehicle), ctx);
                     }
+                } else if self.active_trip_mode.contains_key(&AgentID::Car(car)) {
+                    // Somebody else in the household is still driving the shared car. Wait for
+                    // them to bring it back.
+                    person.state = PersonState::Inside(start_bldg);
+                    self.trips[trip.0].started = false;
+                    ctx.scheduler.push(
+                        now + SHARED_CAR_RETRY,
+                        Command::StartTrip(trip, spec, maybe_req, maybe_path),
+                    );
                 } else {
                     // This should only happen when a driving trip has been aborted and there was
                     // absolutely no room to warp the car.
@@ -1159,6 +1365,7 @@ impl TripManager {
 
                 let req = maybe_req.unwrap();
                 if let Some(path) = maybe_path {
+                    let (path, crossings) = plan_walk(&req, path, self.jaywalking, ctx.map);
                     ctx.scheduler.push(
                         now,
                         Command::SpawnPed(CreatePedestrian {
@@ -1167,6 +1374,7 @@ impl TripManager {
                             start,
                             goal,
                             path,
+                            crossings,
                             req,
                             trip,
                             person: person.id,
@@ -1187,6 +1395,7 @@ impl TripManager {
                 if let Some(walk_to) = SidewalkSpot::bike_rack(start, ctx.map) {
                     let req = maybe_req.unwrap();
                     if let Some(path) = maybe_path {
+                        let (path, crossings) = plan_walk(&req, path, self.jaywalking, ctx.map);
                         ctx.scheduler.push(
                             now,
                             Command::SpawnPed(CreatePedestrian {
@@ -1195,6 +1404,7 @@ impl TripManager {
                                 start: SidewalkSpot::building(start, ctx.map),
                                 goal: walk_to,
                                 path,
+                                crossings,
                                 req,
                                 trip,
                                 person: person.id,
@@ -1251,6 +1461,7 @@ impl TripManager {
                 let walk_to = SidewalkSpot::bus_stop(stop1, ctx.map);
                 let req = maybe_req.unwrap();
                 if let Some(path) = maybe_path {
+                    let (path, crossings) = plan_walk(&req, path, self.jaywalking, ctx.map);
                     ctx.scheduler.push(
                         now,
                         Command::SpawnPed(CreatePedestrian {
@@ -1259,6 +1470,7 @@ impl TripManager {
                             start,
                             goal: walk_to,
                             path,
+                            crossings,
                             req,
                             trip,
                             person: person.id,
@@ -1272,6 +1484,58 @@ impl TripManager {
                     self.abort_trip(now, trip, None, ctx);
                 }
             }
+            TripSpec::RideAlong { start_bldg, .. } => {
+                let driver_trip = match self.trips[trip.0].legs[0] {
+                    TripLeg::RideAlong(t, _) => t,
+                    _ => unreachable!(),
+                };
+                // The driver doesn't wait around. If an earlier trip went wrong and the passenger
+                // isn't at the pickup, or the driver is already done, the passenger misses out.
+                let driver = &self.trips[driver_trip.0];
+                let problem = if person.state != PersonState::Inside(start_bldg) {
+                    Some(format!("isn't at {} to get picked up", start_bldg))
+                } else if driver.finished_at.is_some() || driver.aborted || driver.cancelled {
+                    Some("the ride is already over".to_string())
+                } else {
+                    None
+                };
+                if let Some(problem) = problem {
+                    self.events.push(Event::Alert(
+                        AlertLocation::Person(person.id),
+                        format!(
+                            "{} was supposed to ride along with {}, but {}",
+                            person.id, driver_trip, problem
+                        ),
+                    ));
+                    self.abort_trip(now, trip, None, ctx);
+                    return;
+                }
+
+                person.state = PersonState::Trip(trip);
+                let person = person.id;
+                self.events
+                    .push(Event::PersonLeavesBuilding(person, start_bldg));
+                self.events.push(Event::TripPhaseStarting(
+                    trip,
+                    person,
+                    None,
+                    TripPhaseType::RidingAlong(self.trips[driver_trip.0].person),
+                ));
+            }
+            TripSpec::UsingRideHail { start_bldg, .. } => {
+                assert_eq!(person.state, PersonState::Inside(start_bldg));
+                person.state = PersonState::Trip(trip);
+                // Wait at the curb for the vehicle to show up
+                self.events
+                    .push(Event::PersonLeavesBuilding(person.id, start_bldg));
+                self.events.push(Event::TripPhaseStarting(
+                    trip,
+                    person.id,
+                    None,
+                    TripPhaseType::WaitingForRide,
+                ));
+                ctx.scheduler.push(now, Command::RequestRide(trip));
+            }
             TripSpec::Remote {
                 trip_time, from, ..
             } => {
@@ -1304,6 +1568,9 @@ impl TripManager {
                         TripMode::Walk => AgentType::Pedestrian,
                         TripMode::Bike => AgentType::Bike,
                         TripMode::Drive => AgentType::Car,
+                        // Ride-hailing only works between buildings today, but if somebody
+                        // could hail a ride from a border, they'd show up already in the vehicle
+                        TripMode::RideHail => AgentType::RideHailPassenger,
                         // TODO Not true for long. People will be able to spawn at borders already
                         // on a bus.
                         TripMode::Transit => AgentType::Pedestrian,
@@ -1357,6 +1624,8 @@ struct Trip {
     cancelled: bool,
     legs: VecDeque<TripLeg>,
     person: PersonID,
+    // Other people riding along in the same vehicle
+    passengers: Vec<TripID>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -1379,6 +1648,7 @@ impl Trip {
         now: Time,
         start: SidewalkSpot,
         person: &Person,
+        jaywalking: bool,
         map: &Map,
         scheduler: &mut Scheduler,
         events: &mut Vec<Event>,
@@ -1405,6 +1675,7 @@ impl Trip {
             ));
             return false;
         };
+        let (path, crossings) = plan_walk(&req, path, jaywalking, map);
 
         scheduler.push(
             now,
@@ -1414,6 +1685,7 @@ impl Trip {
                 start,
                 goal: walk_to,
                 path,
+                crossings,
                 req,
                 trip: self.id,
                 person: self.person,
@@ -1442,6 +1714,10 @@ pub enum TripLeg {
     // Maybe get off at a stop, maybe ride off-map
     RideBus(BusRouteID, Option<BusStopID>),
     Remote(OffMapLocation),
+    // Riding in somebody else's vehicle for their entire trip, winding up at the same place
+    RideAlong(TripID, TripEndpoint),
+    // Wait for a ride-hailing vehicle, then get dropped off at this building
+    RideHail(BuildingID),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, PartialOrd, Ord)]
@@ -1450,6 +1726,7 @@ pub enum TripMode {
     Bike,
     Transit,
     Drive,
+    RideHail,
 }
 
 impl TripMode {
@@ -1459,6 +1736,7 @@ impl TripMode {
             TripMode::Bike,
             TripMode::Transit,
             TripMode::Drive,
+            TripMode::RideHail,
         ]
     }
 
@@ -1468,6 +1746,7 @@ impl TripMode {
             TripMode::Bike => "bike",
             TripMode::Transit => "use transit",
             TripMode::Drive => "drive",
+            TripMode::RideHail => "hail a ride",
         }
     }
 
@@ -1478,6 +1757,7 @@ impl TripMode {
             TripMode::Bike => "biking",
             TripMode::Transit => "using transit",
             TripMode::Drive => "driving",
+            TripMode::RideHail => "riding in a ride-hail",
         }
     }
 
@@ -1487,6 +1767,7 @@ impl TripMode {
             TripMode::Bike => "Bike",
             TripMode::Transit => "Bus",
             TripMode::Drive => "Car",
+            TripMode::RideHail => "Ride-hail",
         }
     }
 
@@ -1496,7 +1777,7 @@ impl TripMode {
             TripMode::Bike => PathConstraints::Bike,
             // TODO WRONG
             TripMode::Transit => PathConstraints::Bus,
-            TripMode::Drive => PathConstraints::Car,
+            TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
         }
     }
 
@@ -1506,7 +1787,7 @@ impl TripMode {
             PathConstraints::Bike => TripMode::Bike,
             // TODO The bijection breaks down... transit rider vs train vs bus...
             PathConstraints::Bus | PathConstraints::Train => TripMode::Transit,
-            PathConstraints::Car => TripMode::Drive,
+            PathConstraints::Car | PathConstraints::Truck => TripMode::Drive,
         }
     }
 }
@@ -1529,7 +1810,7 @@ impl TripEndpoint {
             end: pos(to, mode, false, map)?,
             constraints: match mode {
                 TripMode::Walk | TripMode::Transit => PathConstraints::Pedestrian,
-                TripMode::Drive => PathConstraints::Car,
+                TripMode::Drive | TripMode::RideHail => PathConstraints::Car,
                 TripMode::Bike => PathConstraints::Bike,
             },
         })
@@ -1546,6 +1827,8 @@ fn pos(endpt: TripEndpoint, mode: TripMode, from: bool, map: &Map) -> Option<Pos
                     .goal_pos(PathConstraints::Car, map)
                     .unwrap(),
             ),
+            // Picked up and dropped off at the curb
+            TripMode::RideHail => map.get_b(b).driving_connection(map).map(|(pos, _)| pos),
         },
         TripEndpoint::Border(i, _) => match mode {
             TripMode::Walk | TripMode::Transit => if from {
@@ -1554,7 +1837,7 @@ fn pos(endpt: TripEndpoint, mode: TripMode, from: bool, map: &Map) -> Option<Pos
                 SidewalkSpot::end_at_border(i, None, map)
             }
             .map(|spot| spot.sidewalk_pos),
-            TripMode::Bike | TripMode::Drive => (if from {
+            TripMode::Bike | TripMode::Drive | TripMode::RideHail => (if from {
                 map.get_i(i).some_outgoing_road(map)
             } else {
                 map.get_i(i).some_incoming_road(map)
@@ -1575,6 +1858,20 @@ fn pos(endpt: TripEndpoint, mode: TripMode, from: bool, map: &Map) -> Option<Pos
     }
 }
 
+// Decides up front whether to cross the roads at either end of a walk partway along. Only does any
+// extra pathfinding when there's a mid-block crossing or jaywalking is allowed.
+fn plan_walk(
+    req: &PathRequest,
+    path: Path,
+    jaywalking: bool,
+    map: &Map,
+) -> (Path, Vec<(MidBlockCrossing, Path)>) {
+    match map.pathfind_mid_block(req.clone(), &path, jaywalking) {
+        Some((first, crossings)) => (first, crossings),
+        None => (path, Vec::new()),
+    }
+}
+
 pub enum TripResult<T> {
     Ok(T),
     ModeChange,
@@ -1623,6 +1920,7 @@ pub struct Person {
 
     delayed_trips: Vec<(TripID, TripSpec, Option<PathRequest>, Option<Path>)>,
     on_bus: Option<CarID>,
+    in_ride_hail: Option<CarID>,
 }
 
 impl Person {
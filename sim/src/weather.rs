@@ -0,0 +1,296 @@
+use crate::{PersonSpec, SpawnTrip, TripMode, VehicleType};
+use geom::{Duration, Time};
+use map_model::Map;
+use rand::Rng;
+use rand_xorshift::XorShiftRng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Precipitation {
+    Dry,
+    Rain,
+    Snow,
+}
+
+// What the day is like for an entire run. Bad weather and darkness slow everybody down, make
+// vehicles leave intersections more slowly, and put some people off biking.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Weather {
+    pub precipitation: Precipitation,
+    // Sunrise and sunset, as a time of day. Every day of the run is the same. None means darkness
+    // isn't modeled.
+    pub daylight: Option<(Duration, Duration)>,
+}
+
+impl Weather {
+    // Nothing affects anybody
+    pub fn clear() -> Weather {
+        Weather {
+            precipitation: Precipitation::Dry,
+            daylight: None,
+        }
+    }
+
+    // Seattle's longest day
+    pub fn summer_daylight() -> (Duration, Duration) {
+        (
+            Duration::hours(5) + Duration::minutes(11),
+            Duration::hours(21) + Duration::minutes(10),
+        )
+    }
+
+    // Seattle's shortest day
+    pub fn winter_daylight() -> (Duration, Duration) {
+        (
+            Duration::hours(7) + Duration::minutes(55),
+            Duration::hours(16) + Duration::minutes(20),
+        )
+    }
+
+    pub fn is_clear(&self) -> bool {
+        self.precipitation == Precipitation::Dry && self.daylight.is_none()
+    }
+
+    pub fn is_dark(&self, now: Time) -> bool {
+        match self.daylight {
+            Some((sunrise, sunset)) => {
+                let time_of_day = (now - Time::START_OF_DAY) % Duration::hours(24);
+                time_of_day < sunrise || time_of_day >= sunset
+            }
+            None => false,
+        }
+    }
+
+    pub fn describe(&self, now: Time) -> String {
+        let sky = match self.precipitation {
+            Precipitation::Dry => "dry",
+            Precipitation::Rain => "raining",
+            Precipitation::Snow => "snowing",
+        };
+        match self.daylight {
+            Some((sunrise, sunset)) => format!(
+                "{}, {} (sunrise {}, sunset {})",
+                sky,
+                if self.is_dark(now) { "dark" } else { "light" },
+                (Time::START_OF_DAY + sunrise).ampm_tostring(),
+                (Time::START_OF_DAY + sunset).ampm_tostring()
+            ),
+            None => sky.to_string(),
+        }
+    }
+
+    // Multiplies how fast a vehicle goes when nothing's in its way. Roughly from the Highway
+    // Capacity Manual's adjustments for adverse weather; cyclists slow down more.
+    pub fn vehicle_speed_factor(&self, vehicle_type: VehicleType, now: Time) -> f64 {
+        let (car, bike) = match self.precipitation {
+            Precipitation::Dry => (1.0, 1.0),
+            Precipitation::Rain => (0.92, 0.85),
+            Precipitation::Snow => (0.8, 0.6),
+        };
+        let factor = if vehicle_type == VehicleType::Bike {
+            bike
+        } else {
+            car
+        };
+        if self.is_dark(now) {
+            factor * 0.95
+        } else {
+            factor
+        }
+    }
+
+    // Multiplies how fast somebody walks
+    pub fn walking_speed_factor(&self) -> f64 {
+        match self.precipitation {
+            Precipitation::Dry => 1.0,
+            Precipitation::Rain => 0.95,
+            Precipitation::Snow => 0.85,
+        }
+    }
+
+    // Multiplies the time between vehicles leaving a queue at an intersection. Vehicles take turns
+    // this much slower.
+    pub fn headway_factor(&self, now: Time) -> f64 {
+        let factor = match self.precipitation {
+            Precipitation::Dry => 1.0,
+            Precipitation::Rain => 1.1,
+            Precipitation::Snow => 1.25,
+        };
+        if self.is_dark(now) {
+            factor * 1.05
+        } else {
+            factor
+        }
+    }
+
+    // Some people who'd bike today stay home instead, and others take transit for some trips.
+    // Returns None when nothing changes. Doesn't touch the RNG when the weather can't matter, so
+    // scenarios instantiate the same way as before.
+    pub(crate) fn adjust_person(
+        &self,
+        person: &PersonSpec,
+        map: &Map,
+        rng: &mut XorShiftRng,
+    ) -> Option<PersonSpec> {
+        if self.is_clear() || !person.trips.iter().any(|t| t.trip.mode() == TripMode::Bike) {
+            return None;
+        }
+
+        let mut person = person.clone();
+        let stay_home = match self.precipitation {
+            Precipitation::Dry => 0.0,
+            Precipitation::Rain => 0.05,
+            Precipitation::Snow => 0.15,
+        };
+        if rng.gen_bool(stay_home) {
+            // Like ScenarioModifier::CancelPeople, cancel everything, so nobody's left stranded
+            // halfway through their day.
+            for trip in &mut person.trips {
+                trip.modified = true;
+                trip.cancelled = true;
+            }
+            return Some(person);
+        }
+
+        for trip in &mut person.trips {
+            if trip.trip.mode() != TripMode::Bike {
+                continue;
+            }
+            let mut switch = match self.precipitation {
+                Precipitation::Dry => 0.0,
+                Precipitation::Rain => 0.25,
+                Precipitation::Snow => 0.5,
+            };
+            if self.is_dark(trip.depart) {
+                switch += 0.1;
+            }
+            if !rng.gen_bool(switch) {
+                continue;
+            }
+            // If transit doesn't help, this winds up walking.
+            if let Some(new) = SpawnTrip::new(
+                trip.trip.start(map),
+                trip.trip.end(map),
+                TripMode::Transit,
+                map,
+            ) {
+                trip.trip = new;
+                trip.modified = true;
+            }
+        }
+        Some(person)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DrivingGoal, IndividTrip, PersonID, SidewalkSpot};
+    use abstutil::Timer;
+    use map_model::raw::RawMap;
+    use map_model::BuildingID;
+    use rand::SeedableRng;
+
+    fn at(hours: usize, minutes: usize) -> Time {
+        Time::START_OF_DAY + Duration::hours(hours) + Duration::minutes(minutes)
+    }
+
+    fn rain_in_winter() -> Weather {
+        Weather {
+            precipitation: Precipitation::Rain,
+            daylight: Some(Weather::winter_daylight()),
+        }
+    }
+
+    fn approx_eq(x: f64, y: f64) -> bool {
+        (x - y).abs() < 1e-9
+    }
+
+    #[test]
+    fn test_is_dark() {
+        let weather = rain_in_winter();
+        assert!(weather.is_dark(at(3, 0)));
+        assert!(!weather.is_dark(at(7, 55)));
+        assert!(!weather.is_dark(at(12, 0)));
+        assert!(weather.is_dark(at(16, 20)));
+        assert!(weather.is_dark(at(23, 59)));
+        // Every day of the run is the same
+        assert!(weather.is_dark(at(24, 0)));
+        assert!(weather.is_dark(at(27, 0)));
+        assert!(!weather.is_dark(at(36, 0)));
+        assert!(weather.is_dark(at(24 + 16, 20)));
+
+        assert!(!Weather::clear().is_dark(at(3, 0)));
+    }
+
+    #[test]
+    fn test_factors() {
+        let clear = Weather::clear();
+        for vehicle_type in vec![VehicleType::Car, VehicleType::Bike] {
+            assert_eq!(clear.vehicle_speed_factor(vehicle_type, at(3, 0)), 1.0);
+        }
+        assert_eq!(clear.walking_speed_factor(), 1.0);
+        assert_eq!(clear.headway_factor(at(3, 0)), 1.0);
+
+        let rain = rain_in_winter();
+        assert!(approx_eq(
+            rain.vehicle_speed_factor(VehicleType::Car, at(12, 0)),
+            0.92
+        ));
+        assert!(approx_eq(
+            rain.vehicle_speed_factor(VehicleType::Bike, at(12, 0)),
+            0.85
+        ));
+        assert!(approx_eq(rain.headway_factor(at(12, 0)), 1.1));
+        // Darkness makes it a bit worse
+        assert!(approx_eq(
+            rain.vehicle_speed_factor(VehicleType::Car, at(20, 0)),
+            0.92 * 0.95
+        ));
+        assert!(approx_eq(rain.headway_factor(at(20, 0)), 1.1 * 1.05));
+        // But doesn't affect walking
+        assert!(approx_eq(rain.walking_speed_factor(), 0.95));
+    }
+
+    #[test]
+    fn test_adjust_person() {
+        let map =
+            Map::create_from_raw(RawMap::synthetic_grid(2, 2), false, &mut Timer::throwaway());
+        let (from, to) = (BuildingID(0), BuildingID(map.all_buildings().len() - 1));
+        let person = |trip| PersonSpec {
+            id: PersonID(0),
+            orig_id: None,
+            trips: vec![IndividTrip::new(at(20, 0), trip)],
+        };
+        let biker = person(SpawnTrip::UsingBike(from, DrivingGoal::ParkNear(to)));
+        let walker = person(SpawnTrip::JustWalking(
+            SidewalkSpot::building(from, &map),
+            SidewalkSpot::building(to, &map),
+        ));
+        let snow = Weather {
+            precipitation: Precipitation::Snow,
+            daylight: None,
+        };
+
+        // Nothing changes, and the RNG isn't touched, so scenarios instantiate like before
+        for (weather, person) in vec![(Weather::clear(), &biker), (snow, &walker)] {
+            let mut rng = XorShiftRng::seed_from_u64(42);
+            let mut rng_before = rng.clone();
+            assert!(weather.adjust_person(person, &map, &mut rng).is_none());
+            assert_eq!(rng.gen::<u64>(), rng_before.gen::<u64>());
+        }
+
+        // In the snow, plenty of people give up on biking
+        let mut rng = XorShiftRng::seed_from_u64(42);
+        let changed = (0..100)
+            .filter(|_| {
+                snow.adjust_person(&biker, &map, &mut rng)
+                    .unwrap()
+                    .trips
+                    .iter()
+                    .any(|t| t.modified)
+            })
+            .count();
+        assert!(changed > 0 && changed < 100);
+    }
+}
@@ -190,6 +190,7 @@ impl Manifest {
                 || path.contains("system/proposals")
                 || path.contains("system/synthetic_maps")
                 || path.contains("/polygons/")
+                || path.ends_with("/config.json")
             {
                 continue;
             }